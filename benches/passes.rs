@@ -0,0 +1,114 @@
+// benches/passes.rs
+//
+// Compiler throughput on generated inputs that stress different passes:
+//
+//   classes    many classes with fields, methods and operators
+//   imports    a chain of files, each importing the next
+//   operators  long chains of overloaded operators and method calls
+//
+// Each input is measured for lexing, parsing, rewriting (the pipeline up to
+// emission, so it includes lexing and parsing) and full compilation.
+//
+//   cargo bench --bench passes
+//   cargo bench --bench passes -- operators
+
+use std::fmt::Write;
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use z_lang::syntax::{parse, tokenize};
+use z_lang::{Compiler, Session};
+
+const CLASSES: usize = 200;
+const IMPORT_DEPTH: usize = 40;
+const EXPRESSIONS: usize = 300;
+
+fn classes() -> String {
+    let mut src = String::from("#include <stdio.h>\n");
+    for i in 0..CLASSES {
+        writeln!(src, "class c{i} {{
+  int x;
+  int y;
+  double weight;
+  int sum(){{ return self.x + self.y; }}
+  void scale(int k){{ self.x = self.x * k; self.y = self.y * k; }}
+  c{i} operator+(c{i} o){{ c{i} r; r.x = self.x + o.x; r.y = self.y + o.y; return r; }}
+  int operator==(c{i} o){{ return self.x == o.x && self.y == o.y; }}
+}}").unwrap();
+    }
+    src.push_str("int main(){\n");
+    for i in 0..CLASSES {
+        writeln!(src, "  c{i} a{i}; a{i}.x = {i}; a{i}.y = 1; c{i} b{i} = a{i} + a{i}; if (b{i} == a{i}) return b{i}.sum();").unwrap();
+    }
+    src.push_str("  return 0;\n}\n");
+    src
+}
+
+// Writes the chain to a scratch directory and returns the root source
+fn imports() -> String {
+    let dir = std::env::temp_dir().join("tarnish_bench_imports");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = |i: usize| -> PathBuf { dir.join(format!("m{i}.z")) };
+    for i in 0..IMPORT_DEPTH {
+        let mut src = String::new();
+        if i + 1 < IMPORT_DEPTH {
+            writeln!(src, "#import <{}>", file(i + 1).display()).unwrap();
+        }
+        writeln!(src, "namespace m{i} {{\n  class item {{ int v; int get(){{ return self.v; }} }}\n  int twice(int v){{ return v * 2; }}\n}}").unwrap();
+        std::fs::write(file(i), src).unwrap();
+    }
+    format!("#import <{}>\nint main(){{ m0::item it; it.v = 1; return it.get() + m{}::twice(2); }}\n", file(0).display(), IMPORT_DEPTH - 1)
+}
+
+fn operators() -> String {
+    let mut src = String::from("class v {
+  double x;
+  double y;
+  v operator+(v o){ v r; r.x = self.x + o.x; r.y = self.y + o.y; return r; }
+  v operator-(v o){ v r; r.x = self.x - o.x; r.y = self.y - o.y; return r; }
+  v operator*(v o){ v r; r.x = self.x * o.x; r.y = self.y * o.y; return r; }
+  int operator<(v o){ return self.x < o.x; }
+  double len(){ return self.x * self.x + self.y * self.y; }
+}
+int main(){
+  v a; v b; v c; v d;
+  double total = 0;
+");
+    for i in 0..EXPRESSIONS {
+        writeln!(src, "  v e{i} = a + b * c - d * a + b;").unwrap();
+        writeln!(src, "  if (e{i} < a + b) total += (e{i} - c * d).len();").unwrap();
+    }
+    src.push_str("  return total > 0;\n}\n");
+    src
+}
+
+fn bench_passes(c: &mut Criterion) {
+    let inputs = [("classes", classes()), ("imports", imports()), ("operators", operators())];
+    let compiler = Compiler::new();
+    let rewriter = Compiler::builder().without_pass("export-api").without_pass("emit").build();
+
+    for (name, src) in &inputs {
+        let mut session = Session::default();
+        compiler.compile_in(&mut session, "main.z", src);
+        let errors: Vec<String> = session.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert!(errors.is_empty(), "the {} input doesn't compile:\n{}", name, errors.join("\n"));
+    }
+
+    let mut group = c.benchmark_group("passes");
+    for (name, src) in &inputs {
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::new("lex", name), src, |b, src| b.iter(|| tokenize(black_box(src))));
+        group.bench_with_input(BenchmarkId::new("parse", name), src, |b, src| b.iter(|| parse(black_box(src), "main")));
+        group.bench_with_input(BenchmarkId::new("rewrite", name), src, |b, src| {
+            b.iter(|| rewriter.compile_in(&mut Session::default(), "main.z", black_box(src)))
+        });
+        group.bench_with_input(BenchmarkId::new("compile", name), src, |b, src| {
+            b.iter(|| compiler.compile_in(&mut Session::default(), "main.z", black_box(src)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_passes);
+criterion_main!(benches);
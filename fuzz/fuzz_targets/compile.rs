@@ -0,0 +1,18 @@
+// fuzz/fuzz_targets/compile.rs
+//
+// `cargo fuzz run compile`: no pass panics on any input, however broken;
+// a program with errors is reported, never an internal compiler error.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use z_lang::{Compiler, Options, Session};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else { return };
+    let mut session = Session::default();
+    Compiler::builder().options(Options { entry_point: false, ..Options::default() }).build().compile_in(&mut session, "main.z", source);
+    if let Some(ice) = session.ice {
+        panic!("{}", ice.report(None));
+    }
+});
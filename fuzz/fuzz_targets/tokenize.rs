@@ -0,0 +1,30 @@
+// fuzz/fuzz_targets/tokenize.rs
+//
+// `cargo fuzz run tokenize`: the lexer never panics, its spans follow each
+// other and cover everything but whitespace, and printed tokens lex to the
+// same kinds of tokens. tests/lexer.rs checks the same on every test run.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use z_lang::syntax::{detokenize, tokenize, tokenize_spanned, Token};
+
+// The tokens of `source` but line breaks, which printing adds and drops,
+// without their text
+fn kinds(source: &str) -> Vec<std::mem::Discriminant<Token>> {
+    tokenize(source).iter().filter(|t| !matches!(t, Token::Newline)).map(std::mem::discriminant).collect()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else { return };
+    let mut at = 0;
+    for (token, span) in tokenize_spanned(input) {
+        assert!(span.start >= at && span.start <= span.end && span.end <= input.len(), "{:?} at {:?}", token, span);
+        assert!(input[at..span.start].chars().all(|c| c.is_whitespace() && c != '\n'), "{:?} skipped", &input[at..span.start]);
+        at = span.end;
+    }
+    assert_eq!(at, input.len());
+
+    let printed = detokenize(&tokenize(input));
+    assert_eq!(kinds(&printed), kinds(input), "printed as {:?}", printed);
+});
@@ -0,0 +1,551 @@
+// src/api.rs
+//
+// The public C surface of compiled files: what a library built from them
+// exports, and the header that describes it to C and C++ code.
+
+use std::fmt::Write;
+
+use crate::ast::{declarator, derives, has_attribute, Attribute, Class, Global, Module, Namespace, OperatorOverload, StructLayout, Variable};
+use crate::compiler::{Context, Pass};
+use crate::links::{self, Link};
+use crate::lints::Warning;
+use crate::parser::{function_definitions, parse_params, type_text};
+use crate::registry::SymbolRegistry;
+use crate::tokenizer::{detokenize, tokenize, Token};
+use crate::visit::{walk_namespace, Visit};
+
+/// One exported C declaration, with every name already mangled
+#[derive(Debug, Clone, PartialEq)]
+pub enum Declaration {
+    /// `#include` line the exported types depend on, e.g. `#include <stdint.h>`
+    Include(String),
+    Struct { name: String, fields: Vec<Variable>, layout: StructLayout, kind: StructKind },
+    Function { name: String, return_type: String, params: Vec<Variable> },
+    Global { name: String, type_: String },
+    /// An `@inline` function, defined `static inline` in the header itself.
+    /// `definition` is the C definition without its linkage.
+    Inline { name: String, return_type: String, params: Vec<Variable>, definition: String },
+}
+
+/// What a `Declaration::Struct` declares
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructKind {
+    Struct,
+    Union,
+    /// A `@tagged` union built with `--union-checks`: a struct of the tag
+    /// and the union
+    CheckedUnion,
+}
+
+impl StructKind {
+    fn of(class: &Class) -> Self {
+        match (class.union, class.checked) {
+            (true, true) => StructKind::CheckedUnion,
+            (true, false) => StructKind::Union,
+            _ => StructKind::Struct,
+        }
+    }
+}
+
+/// Collects the public API of each file into `Session::api`. Imports run
+/// their own pipeline first, so dependencies come before their users.
+pub(crate) struct ExportApi;
+
+impl Pass for ExportApi {
+    fn name(&self) -> &str {
+        "export-api"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let mut collector = ApiCollector::default();
+        collector.visit_module(&cx.module);
+        for declaration in collector.declarations {
+            if !cx.session.api.contains(&declaration) {
+                cx.session.api.push(declaration);
+            }
+        }
+        for name in collector.exports {
+            if !cx.session.exports.contains(&name) {
+                cx.session.exports.push(name);
+            }
+        }
+        // Only libraries have a header to leave them out of
+        if !cx.options().entry_point {
+            for (name, attributes) in collector.hidden_inline {
+                let message = format!("`@inline` method `{}` of an rc class is not exported; the class's fields are not visible outside this file", name);
+                cx.lint(Warning::new("unexported_inline", message).within(&attributes));
+            }
+        }
+    }
+}
+
+/// Public declarations of a lowered module. Anonymous namespaces and
+/// `static` functions are left out.
+pub fn declarations(module: &Module) -> Vec<Declaration> {
+    let mut collector = ApiCollector::default();
+    collector.visit_module(module);
+    collector.declarations
+}
+
+#[derive(Default)]
+struct ApiCollector {
+    declarations: Vec<Declaration>,
+    /// `@inline` members of rc classes, which a header can't define, with
+    /// the class's attributes and then their own
+    hidden_inline: Vec<(String, Vec<Attribute>)>,
+    /// Names of the `@export` functions
+    exports: Vec<String>,
+}
+
+impl ApiCollector {
+    fn push_function(&mut self, name: String, return_type: String, params: Vec<Variable>, inline: Option<String>) {
+        self.declarations.push(match inline {
+            Some(definition) => Declaration::Inline { name, return_type, params, definition },
+            None => Declaration::Function { name, return_type, params },
+        });
+    }
+}
+
+impl Visit for ApiCollector {
+    fn visit_namespace(&mut self, namespace: &Namespace) {
+        if !namespace.is_anonymous() {
+            walk_namespace(self, namespace);
+        }
+    }
+
+    fn visit_class(&mut self, class: &Class) {
+        if class.file_private {
+            return;
+        }
+        let name = class.full_name();
+        let receiver = Variable { name: "self".to_string(), type_: name.clone() };
+        if class.rc {
+            // The fields live behind the handle; C code goes through the helpers
+            let handle = Variable { name: "ptr".to_string(), type_: "void*".to_string() };
+            self.declarations.push(Declaration::Struct { name: name.clone(), fields: vec![handle], layout: StructLayout::default(), kind: StructKind::Struct });
+            let slot = Variable { name: "slot".to_string(), type_: format!("{}*", name) };
+            let value = Variable { name: "value".to_string(), type_: name.clone() };
+            for (helper, return_type, params) in [
+                ("new", name.clone(), vec![]),
+                ("retain", name.clone(), vec![receiver.clone()]),
+                ("release", "void".to_string(), vec![receiver.clone()]),
+                ("set", "void".to_string(), vec![slot, value]),
+            ] {
+                self.declarations.push(Declaration::Function { name: format!("{}_{}", name, helper), return_type, params });
+            }
+        } else {
+            self.declarations.push(Declaration::Struct { name: name.clone(), fields: class.variables.clone(), layout: class.layout(), kind: StructKind::of(class) });
+        }
+        if derives(&class.attributes).iter().any(|d| d == "Pack") {
+            let out = Variable { name: "out".to_string(), type_: "unsigned char*".to_string() };
+            let slot = Variable { name: "self".to_string(), type_: format!("{}*", name) };
+            let input = Variable { name: "in".to_string(), type_: "const unsigned char*".to_string() };
+            self.declarations.push(Declaration::Include("#include <stddef.h>".to_string()));
+            self.push_function(format!("{}_pack", name), "size_t".to_string(), vec![receiver.clone(), out], None);
+            self.push_function(format!("{}_unpack", name), "size_t".to_string(), vec![slot, input], None);
+        }
+        let members = class.functions.iter()
+            .map(|f| (format!("{}_{}", name, f.name), &f.return_type, &f.params, &f.attributes, f.to_string()))
+            .chain(class.operators.iter()
+                .map(|o| (o.full_name(), &o.return_type, &o.params, &o.attributes, o.to_string())));
+        for (member, return_type, params, attributes, definition) in members {
+            let inline = has_attribute(attributes, "inline");
+            if inline && class.rc {
+                self.hidden_inline.push((member, class.attributes.iter().chain(attributes).cloned().collect()));
+                continue;
+            }
+            let params = std::iter::once(receiver.clone()).chain(params.iter().cloned()).collect();
+            let definition = inline.then(|| detokenize(&tokenize(&definition)).trim().to_string());
+            self.push_function(member, return_type.clone(), params, definition);
+        }
+    }
+
+    // Free operators; the ones in classes are declared with their class
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        let inline = has_attribute(&operator.attributes, "inline")
+            .then(|| detokenize(&tokenize(&operator.to_string())).trim().to_string());
+        self.push_function(operator.full_name(), operator.return_type.clone(), operator.params.clone(), inline);
+    }
+
+    fn visit_global(&mut self, global: &Global) {
+        if !global.file_private {
+            self.declarations.push(Declaration::Global { name: global.full_name(), type_: global.type_.clone() });
+        }
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        for line in include_lines(tokens) {
+            self.declarations.push(Declaration::Include(line));
+        }
+        for definition in function_definitions(tokens) {
+            if definition.return_type.iter().any(|t| *t == Token::Identifier("static".to_string())) {
+                continue;
+            }
+            let inline = has_attribute(&definition.attributes, "inline").then(|| {
+                let end = definition.body_end(tokens).unwrap_or(tokens.len() - 1);
+                detokenize(&tokens[definition.start..=end]).trim().to_string()
+            });
+            if has_attribute(&definition.attributes, "export") {
+                self.exports.push(definition.name(tokens).to_string());
+            }
+            self.push_function(
+                definition.name(tokens).to_string(),
+                type_text(&definition.return_type),
+                parse_params(&definition.params),
+                inline,
+            );
+        }
+    }
+}
+
+// `#include` directives, one per line
+fn include_lines(tokens: &[Token]) -> Vec<String> {
+    tokens
+        .split(|t| matches!(t, Token::Newline))
+        .filter(|line| {
+            matches!(line, [Token::Symbol(hash), Token::Identifier(include), ..] if hash == "#" && include == "include")
+        })
+        .map(|line| detokenize(line).trim().to_string())
+        .collect()
+}
+
+fn c_params(params: &[Variable]) -> String {
+    if params.is_empty() {
+        return "void".to_string();
+    }
+    params.iter().map(Variable::param).collect::<Vec<_>>().join(", ")
+}
+
+// `#include` lines first, then every declaration of `api`
+fn c_declarations(api: &[Declaration]) -> (String, String) {
+    let mut includes = String::new();
+    let mut declarations = String::new();
+    for declaration in api {
+        match declaration {
+            Declaration::Include(line) => writeln!(includes, "{}", line).unwrap(),
+            Declaration::Struct { name, fields, layout, kind } => {
+                let fields: String = fields.iter().map(|f| format!(" {}", f)).collect();
+                let attribute = layout.c_attribute();
+                match kind {
+                    StructKind::Struct => writeln!(declarations, "typedef struct{} {{{} }} {};", attribute, fields, name),
+                    StructKind::Union => writeln!(declarations, "typedef union{} {} {{{} }} {};", attribute, name, fields, name),
+                    StructKind::CheckedUnion => {
+                        writeln!(declarations, "typedef struct{} {} {{ int __z_tag; union {{{} }}; }} {};", attribute, name, fields, name)
+                    }
+                }
+                .unwrap();
+            }
+            Declaration::Function { name, return_type, params } => {
+                writeln!(declarations, "{} {}({});", return_type, name, c_params(params)).unwrap();
+            }
+            Declaration::Global { name, type_ } => {
+                writeln!(declarations, "extern {};", declarator(type_, name)).unwrap();
+            }
+            // The prototype lets `.zi` importers see the function
+            Declaration::Inline { name, return_type, params, definition } => {
+                writeln!(declarations, "static inline {} {}({});", return_type, name, c_params(params)).unwrap();
+                writeln!(declarations, "static inline {}", definition).unwrap();
+            }
+        }
+    }
+    (includes, declarations)
+}
+
+/// A C header declaring `api`, guarded by `guard` and usable from C++
+pub fn c_header(guard: &str, api: &[Declaration]) -> String {
+    let (includes, declarations) = c_declarations(api);
+    let mut out = String::new();
+    writeln!(out, "#ifndef {}\n#define {}\n", guard, guard).unwrap();
+    if !includes.is_empty() {
+        writeln!(out, "{}", includes).unwrap();
+    }
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    out.push_str(&declarations);
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n");
+    writeln!(out, "#endif").unwrap();
+    out
+}
+
+/// What a shared library's header declares: the types of `api` and the
+/// functions named in `exports`, the `@export` ones
+pub fn exported(api: &[Declaration], exports: &[String]) -> Vec<Declaration> {
+    api.iter()
+        .filter(|declaration| match declaration {
+            Declaration::Include(_) | Declaration::Struct { .. } => true,
+            Declaration::Function { name, .. } | Declaration::Inline { name, .. } => exports.contains(name),
+            Declaration::Global { .. } => false,
+        })
+        .cloned()
+        .collect()
+}
+
+/// A `.zi` interface file for `api`: `#class Name mangled_name` lines that
+/// register the classes with importers, followed by the C declarations.
+/// `classes` are the classes known to the session, `Session::known_classes`.
+pub fn interface(api: &[Declaration], classes: &SymbolRegistry, links: &[Link]) -> String {
+    let mut out = String::from("// Interface file generated by the Tarnish compiler. Do not edit.\n");
+    for declaration in api {
+        if let Declaration::Struct { name, .. } = declaration {
+            for class in classes.iter().filter(|class| class.c_name == *name) {
+                writeln!(out, "#class {} {}", class.name, name).unwrap();
+            }
+        }
+    }
+    // The implementation's libraries, which its users link with
+    for link in links {
+        writeln!(out, "{}", link).unwrap();
+    }
+    let (includes, declarations) = c_declarations(api);
+    out.push_str(&includes);
+    out.push_str(&declarations);
+    out
+}
+
+/// Splits a `.zi` file into its class registrations, its `#link`s and its
+/// C declarations
+pub fn parse_interface(source: &str) -> (Vec<(String, String)>, Vec<Link>, String) {
+    let mut classes = Vec::new();
+    let mut links = Vec::new();
+    let mut c_code = String::new();
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("#class") => {
+                if let (Some(class), Some(mangled)) = (words.next(), words.next()) {
+                    classes.push((class.to_string(), mangled.to_string()));
+                }
+                continue;
+            }
+            Some(word) if word == "#link" || word.starts_with("@link") => {
+                links.extend(links::collect(&tokenize(line)).1);
+                continue;
+            }
+            _ => {}
+        }
+        c_code.push_str(line);
+        c_code.push('\n');
+    }
+    (classes, links, c_code)
+}
+
+/// A Rust module declaring `api` with `#[repr(C)]` structs and an
+/// `extern "C"` block. With `link`, the block links that static library.
+pub fn rust_bindings(api: &[Declaration], link: Option<&str>) -> String {
+    let mut out = String::new();
+    out.push_str("// Generated by the Tarnish compiler. Do not edit.\n\n");
+    out.push_str("#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals, dead_code)]\n\n");
+    out.push_str("use std::os::raw::*;\n\n");
+
+    for declaration in api {
+        if let Declaration::Struct { name, fields, layout, kind } = declaration {
+            let repr = match layout {
+                StructLayout { packed: true, .. } => "C, packed".to_string(),
+                StructLayout { align: Some(align), .. } => format!("C, align({})", align),
+                _ => "C".to_string(),
+            };
+            // Rust unions can't derive `Debug`
+            let (keyword, derive, members) = match kind {
+                StructKind::Struct => ("struct", "Debug, Clone, Copy", name.clone()),
+                StructKind::Union => ("union", "Clone, Copy", name.clone()),
+                StructKind::CheckedUnion => ("union", "Clone, Copy", format!("{}__members", name)),
+            };
+            writeln!(out, "#[repr({})]\n#[derive({})]\npub {} {} {{", repr, derive, keyword, members).unwrap();
+            for field in fields {
+                writeln!(out, "    pub {}: {},", rust_ident(&field.name), rust_type(&field.type_)).unwrap();
+            }
+            out.push_str("}\n\n");
+            if *kind == StructKind::CheckedUnion {
+                writeln!(out, "#[repr({})]\n#[derive(Clone, Copy)]\npub struct {} {{\n    pub __z_tag: c_int,\n    pub members: {},\n}}\n", repr, name, members).unwrap();
+            }
+        }
+    }
+
+    if let Some(library) = link {
+        writeln!(out, "#[link(name = \"{}\", kind = \"static\")]", library).unwrap();
+    }
+    // `unsafe extern` is required from the 2024 edition on
+    out.push_str("unsafe extern \"C\" {\n");
+    for declaration in api {
+        match declaration {
+            Declaration::Function { name, return_type, params } => {
+                let params: Vec<String> = params.iter()
+                    .map(|p| if p.is_variadic() { p.type_.clone() } else { format!("{}: {}", rust_ident(&p.name), rust_type(&p.type_)) })
+                    .collect();
+                let ret = match return_type.as_str() {
+                    "void" => String::new(),
+                    other => format!(" -> {}", rust_type(other)),
+                };
+                writeln!(out, "    pub fn {}({}){};", name, params.join(", "), ret).unwrap();
+            }
+            Declaration::Global { name, type_ } => {
+                let mutability = if type_.starts_with("const ") { "" } else { "mut " };
+                writeln!(out, "    pub static {}{}: {};", mutability, name, rust_type(type_.trim_start_matches("const "))).unwrap();
+            }
+            // `static inline` functions have no symbol to link against
+            Declaration::Inline { .. } => {}
+            _ => {}
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Rust spelling of a C type as written in the source, e.g. `const char*` -> `*const c_char`
+fn rust_type(c_type: &str) -> String {
+    let c_type = c_type.trim();
+    // `float[3][4]` -> `[[f32; 4]; 3]`
+    if let Some((element, rest)) = c_type.split_once('[') {
+        if let Some((size, inner)) = rest.split_once(']') {
+            return format!("[{}; {}]", rust_type(&format!("{}{}", element, inner)), size.trim());
+        }
+    }
+    if let Some(pointee) = c_type.strip_suffix('*') {
+        let pointee = pointee.trim();
+        // `const` before the base type qualifies the innermost pointee only
+        return match pointee.strip_prefix("const ") {
+            Some(inner) if !inner.ends_with('*') => format!("*const {}", rust_pointee(inner)),
+            _ => format!("*mut {}", rust_pointee(pointee)),
+        };
+    }
+    let c_type = c_type.trim_start_matches("const ").trim();
+    match c_type {
+        "void" => "()",
+        "char" => "c_char",
+        "signed char" => "c_schar",
+        "unsigned char" => "c_uchar",
+        "short" => "c_short",
+        "unsigned short" => "c_ushort",
+        "int" | "signed" | "signed int" => "c_int",
+        "unsigned" | "unsigned int" => "c_uint",
+        "long" => "c_long",
+        "unsigned long" => "c_ulong",
+        "long long" => "c_longlong",
+        "unsigned long long" => "c_ulonglong",
+        "float" => "f32",
+        "double" => "f64",
+        "bool" | "_Bool" => "bool",
+        "int8_t" => "i8",
+        "int16_t" => "i16",
+        "int32_t" => "i32",
+        "int64_t" => "i64",
+        "uint8_t" => "u8",
+        "uint16_t" => "u16",
+        "uint32_t" => "u32",
+        "uint64_t" => "u64",
+        "size_t" | "uintptr_t" => "usize",
+        "ssize_t" | "intptr_t" | "ptrdiff_t" => "isize",
+        // Exported structs keep their C names
+        other => other,
+    }.to_string()
+}
+
+fn rust_pointee(c_type: &str) -> String {
+    if c_type.trim().trim_start_matches("const ") == "void" {
+        return "c_void".to_string();
+    }
+    rust_type(c_type)
+}
+
+// C names that are Rust keywords get a trailing underscore, e.g. `self_`
+fn rust_ident(name: &str) -> String {
+    match name {
+        "self" | "type" | "match" | "ref" | "fn" | "mod" | "move" | "impl" | "trait" | "loop" | "use"
+        | "pub" | "mut" | "let" | "in" | "where" | "crate" | "super" | "as" | "dyn" | "box" | "async"
+        | "await" | "yield" | "priv" | "final" | "override" | "macro" | "unsafe" | "abstract" => format!("{}_", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Include-guard macro for a header file name, e.g. `geometry.h` -> `GEOMETRY_H`
+pub fn header_guard(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compiler, Options, Session};
+
+    fn library_api(src: &str) -> Vec<Declaration> {
+        let mut session = Session::default();
+        let compiler = Compiler::builder().options(Options { entry_point: false, ..Options::default() }).build();
+        compiler.compile_in(&mut session, "lib.z", src);
+        session.api
+    }
+
+    #[test]
+    fn test_exports_classes_functions_and_globals() {
+        let api = library_api("#include <stdint.h>\nnamespace geo {\nint64_t count = 0;\nclass Point { int x; int y; int sum() { return self.x + self.y; } }\nint origin_x() { return 0; }\n}\nnamespace { int hidden() { return 1; } }\nstatic int helper() { return 2; }");
+        assert_eq!(api[0], Declaration::Include("#include <stdint.h>".to_string()));
+        assert!(api.contains(&Declaration::Global { name: "geo_count".to_string(), type_: "int64_t".to_string() }));
+        assert!(api.iter().any(|d| matches!(d, Declaration::Struct { name, fields, .. } if name == "geo_Point" && fields.len() == 2)));
+        assert!(api.iter().any(|d| matches!(d, Declaration::Function { name, params, .. } if name == "geo_Point_sum" && params[0].type_ == "geo_Point")));
+        assert!(api.iter().any(|d| matches!(d, Declaration::Function { name, .. } if name == "geo_origin_x")));
+        assert!(!api.iter().any(|d| matches!(d, Declaration::Function { name, .. } if name == "hidden" || name == "helper")));
+    }
+
+    #[test]
+    fn test_c_header() {
+        let header = c_header("GEO_H", &library_api("namespace geo { const int dims = 2; double scale(double x) { return x * 2; } }"));
+        assert!(header.starts_with("#ifndef GEO_H\n#define GEO_H\n"));
+        assert!(header.contains("extern \"C\" {"));
+        assert!(header.contains("extern const int geo_dims;"));
+        assert!(header.contains("double geo_scale(double x);"));
+    }
+
+    #[test]
+    fn test_pack_functions_are_exported() {
+        let header = c_header("MSG_H", &library_api("@derive(Pack) class Msg { int id; }"));
+        assert!(header.contains("#include <stddef.h>"));
+        assert!(header.contains("size_t Msg_pack(Msg self, unsigned char* out);"));
+        assert!(header.contains("size_t Msg_unpack(Msg* self, const unsigned char* in);"));
+    }
+
+    #[test]
+    fn test_inline_functions_are_defined_in_the_header() {
+        let api = library_api("class Point { int x; @inline int get_x() { return self.x; } }\n@inline int twice(int v) { return v * 2; }");
+        let header = c_header("GEO_H", &api);
+        assert!(header.contains("static inline int Point_get_x(Point self);\nstatic inline int Point_get_x(Point self) { return self.x; }"));
+        assert!(header.contains("static inline int twice(int v);\nstatic inline int twice(int v) { return v * 2; }"));
+        assert!(!rust_bindings(&api, None).contains("twice"));
+    }
+
+    #[test]
+    fn test_rust_bindings() {
+        let api = library_api("#include <stdint.h>\nnamespace geo {\nclass Point { int x; int y; int sum() { return self.x + self.y; } }\nuint64_t made = 0;\nconst char* name(void* data, const char** out) { return \"geo\"; }\n}");
+        let bindings = rust_bindings(&api, Some("geo"));
+        assert!(bindings.contains("#[repr(C)]\n#[derive(Debug, Clone, Copy)]\npub struct geo_Point {\n    pub x: c_int,\n    pub y: c_int,\n}"));
+        assert!(bindings.contains("#[link(name = \"geo\", kind = \"static\")]\nunsafe extern \"C\" {"));
+        assert!(bindings.contains("pub fn geo_Point_sum(self_: geo_Point) -> c_int;"));
+        assert!(bindings.contains("pub static mut geo_made: u64;"));
+        assert!(bindings.contains("pub fn geo_name(data: *mut c_void, out: *mut *const c_char) -> *const c_char;"));
+
+        let api = library_api("@packed class Header { char kind; int length; }\n@align(8) class Slot { int v; }");
+        let bindings = rust_bindings(&api, None);
+        assert!(bindings.contains("#[repr(C, packed)]\n#[derive(Debug, Clone, Copy)]\npub struct Header {"));
+        assert!(bindings.contains("#[repr(C, align(8))]\n#[derive(Debug, Clone, Copy)]\npub struct Slot {"));
+        assert!(c_header("HDR", &api).contains("typedef struct __attribute__((packed)) { char kind; int length; } Header;"));
+
+        let api = library_api("union Word { int i; float f; }");
+        assert!(rust_bindings(&api, None).contains("#[repr(C)]\n#[derive(Clone, Copy)]\npub union Word {\n    pub i: c_int,\n    pub f: f32,\n}"));
+        assert!(c_header("WORD", &api).contains("typedef union Word { int i; float f; } Word;"));
+    }
+
+    #[test]
+    fn test_interface_round_trip() {
+        let mut session = Session::default();
+        let compiler = Compiler::builder().options(Options { entry_point: false, ..Options::default() }).build();
+        compiler.compile_in(&mut session, "geo.z", "namespace geo { class Point { int x; int sum() { return self.x; } } }");
+        let zi = interface(&session.api, &session.known_classes, &[Link::Library("m".to_string()), Link::PkgConfig("sdl2".to_string())]);
+        assert!(zi.contains("#class Point geo_Point\n"));
+        assert!(zi.contains("typedef struct { int x; } geo_Point;\nint geo_Point_sum(geo_Point self);\n"));
+
+        let (classes, links, c_code) = parse_interface(&zi);
+        assert_eq!(classes, vec![("Point".to_string(), "geo_Point".to_string())]);
+        assert_eq!(links, vec![Link::Library("m".to_string()), Link::PkgConfig("sdl2".to_string())]);
+        assert!(!c_code.contains("#class"));
+    }
+}
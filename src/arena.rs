@@ -0,0 +1,162 @@
+// src/arena.rs
+//
+// `@arena` functions: an rc value that a call returns but nothing stores,
+// such as the inner `a + b` of `a + b + c` on an rc class, is kept in an
+// arena that the function frees when it returns. Without one these
+// temporaries are never released. Runs after lowering, when operator
+// chains have become nested calls.
+
+use std::collections::HashMap;
+
+use crate::parser::{code, is_symbol, matching_close, statement_end};
+use crate::tokenizer::Token;
+
+/// The arena type and its helpers, emitted once per file that uses them
+pub const RUNTIME: &str = "#ifndef __Z_ARENA
+#define __Z_ARENA
+#include <stdlib.h>
+typedef struct { void* ptr; void (*release)(void*); } __z_arena_entry;
+typedef struct { __z_arena_entry* entries; size_t len; size_t cap; } __z_arena;
+static inline void __z_arena_keep(__z_arena* arena, void* ptr, void (*release)(void*)) {
+  if (arena->len == arena->cap) {
+    arena->cap = arena->cap ? arena->cap * 2 : 8;
+    arena->entries = realloc(arena->entries, arena->cap * sizeof(__z_arena_entry));
+    if (!arena->entries) abort();
+  }
+  arena->entries[arena->len++] = (__z_arena_entry){ ptr, release };
+}
+static inline void __z_arena_free(__z_arena* arena) {
+  while (arena->len > 0) {
+    arena->len--;
+    arena->entries[arena->len].release(arena->entries[arena->len].ptr);
+  }
+  free(arena->entries);
+  arena->entries = NULL;
+  arena->cap = 0;
+}
+#define __z_arena_temp(arena, type, release, value) ({ type __z_tmp = (value); __z_arena_keep((arena), __z_tmp.ptr, release); __z_tmp; })
+#endif
+";
+
+// Calls whose parentheses don't pass values on to a function
+const NOT_CALLS: &[&str] = &["if", "while", "for", "switch", "return", "sizeof", "__typeof__", "typeof", "_Alignof"];
+
+/// Gives a lowered function body its arena. `temporaries` maps the C name
+/// of every function returning an rc value to that value's class.
+pub fn rewrite_body(body: &[Token], returns_void: bool, temporaries: &HashMap<String, String>) -> Vec<Token> {
+    let kept = keep_temporaries(body, None, temporaries);
+    let mut out = code("__z_arena __z_arena = { 0 };");
+    out.extend(free_on_return(&kept, returns_void));
+    out.extend(code("__z_arena_free(&__z_arena);"));
+    out
+}
+
+// Wraps every rc-returning call that is an argument of another call;
+// `callee` is the call whose arguments `tokens` are
+fn keep_temporaries(tokens: &[Token], callee: Option<&str>, temporaries: &HashMap<String, String>) -> Vec<Token> {
+    let mut out = Vec::new();
+    // Open parentheses in `tokens`: the function each one calls, if any
+    let mut calls: Vec<Option<String>> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Identifier(name) if is_symbol(tokens.get(i + 1), "(") => {
+                if let (Some(class), Some(close)) = (temporaries.get(name), matching_close(tokens, i + 1)) {
+                    let mut call = vec![tokens[i].clone(), tokens[i + 1].clone()];
+                    call.extend(keep_temporaries(&tokens[i + 2..close], Some(name), temporaries));
+                    call.push(tokens[close].clone());
+
+                    let enclosing = match calls.last() {
+                        Some(call) => call.as_deref(),
+                        None => callee,
+                    };
+                    let argument = is_argument_start(previous_code(tokens, i));
+                    // `X_set(&x, f())` stores the value it is given
+                    if argument && enclosing.is_some_and(|c| !c.ends_with("_set")) {
+                        out.extend(code(&format!("__z_arena_temp(&__z_arena, {class}, {class}_release_ptr,")));
+                        out.extend(call);
+                        out.push(Token::Symbol(")".to_string()));
+                    } else {
+                        out.extend(call);
+                    }
+                    i = close + 1;
+                    continue;
+                }
+            }
+            Token::Symbol(s) if s == "(" => {
+                let called = match previous_code(tokens, i) {
+                    Some(Token::Identifier(name)) if !NOT_CALLS.contains(&name.as_str()) => Some(name.clone()),
+                    _ => None,
+                };
+                calls.push(called);
+            }
+            Token::Symbol(s) if s == ")" => {
+                calls.pop();
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+// `return e;` -> `{ __auto_type ret = e; free; return ret; }`
+fn free_on_return(tokens: &[Token], returns_void: bool) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let (Token::Identifier(word), Some(end)) = (&tokens[i], statement_end(tokens, i)) {
+            if word == "return" {
+                let value = &tokens[i + 1..end];
+                let value_is_empty = value.iter().all(|t| matches!(t, Token::Newline | Token::Comment(_)));
+                out.extend(code("{"));
+                if value_is_empty || returns_void {
+                    out.extend(value.iter().cloned());
+                    if !value_is_empty {
+                        out.push(Token::Symbol(";".to_string()));
+                    }
+                    out.extend(code("__z_arena_free(&__z_arena); return; }"));
+                } else {
+                    out.extend(code("__auto_type __z_arena_ret ="));
+                    out.extend(value.iter().cloned());
+                    out.extend(code("; __z_arena_free(&__z_arena); return __z_arena_ret; }"));
+                }
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+fn is_argument_start(previous: Option<&Token>) -> bool {
+    previous.is_none() || is_symbol(previous, "(") || is_symbol(previous, ",")
+}
+
+fn previous_code(tokens: &[Token], i: usize) -> Option<&Token> {
+    tokens[..i].iter().rev().find(|t| !matches!(t, Token::Newline | Token::Comment(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::detokenize;
+
+    #[test]
+    fn test_unstored_temporaries_are_kept() {
+        let temporaries = HashMap::from([
+            ("Big_operator_add".to_string(), "Big".to_string()),
+            ("Big_new".to_string(), "Big".to_string()),
+        ]);
+        let body = code("Big s = Big_operator_add(Big_operator_add(a, b), c);\nBig_set(&s, Big_new());\nif (s.ptr) return s;");
+        let output = detokenize(&rewrite_body(&body, false, &temporaries));
+        assert!(output.starts_with("__z_arena __z_arena = { 0 };"));
+        assert!(output.contains("Big s = Big_operator_add(__z_arena_temp(&__z_arena, Big, Big_release_ptr, Big_operator_add(a, b)), c);"));
+        assert!(output.contains("Big_set(&s, Big_new());"));
+        assert!(output.contains("if (s.ptr) { __auto_type __z_arena_ret = s; __z_arena_free(&__z_arena); return __z_arena_ret; }"));
+        assert!(output.ends_with("__z_arena_free(&__z_arena);"));
+    }
+}
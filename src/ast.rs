@@ -0,0 +1,678 @@
+// src/ast.rs
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::consteval;
+use crate::operators;
+use crate::pack;
+use crate::reflect;
+use crate::tokenizer::{detokenize, tokenize, Token};
+
+/// A parsed source file.
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub items: Vec<Item>,
+}
+
+impl Module {
+    /// Appends C source as a raw item
+    pub fn push_c(&mut self, code: &str) {
+        let tokens: Vec<Token> = tokenize(code).into_iter().filter(|t| !matches!(t, Token::Eof)).collect();
+        self.items.push(Item::Raw(tokens));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Class(Class),
+    Interface(Interface),
+    Namespace(Namespace),
+    Global(Global),
+    Import(Import),
+    /// An operator overload written outside of its class
+    Operator(OperatorOverload),
+    /// A namespace alias or re-exported member; it only adds a name
+    Export(Export),
+    /// Plain C the front end does not model, passed through as tokens
+    Raw(Vec<Token>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Namespace {
+    /// Empty for `namespace { ... }`
+    pub name: String,
+    /// `inline namespace`: members are mangled as if declared in the parent
+    pub inline: bool,
+    /// Mangling prefix of the members, e.g. `std_env`; `None` at file scope
+    pub prefix: Option<String>,
+    /// Path the members are named by from outside, e.g. `math::linear`;
+    /// `None` at file scope and in anonymous namespaces
+    pub path: Option<String>,
+    pub items: Vec<Item>,
+}
+
+impl Namespace {
+    /// Anonymous namespaces hold file-private declarations
+    pub fn is_anonymous(&self) -> bool {
+        self.name.is_empty()
+    }
+}
+
+/// Variable declared at namespace scope, emitted as a mangled C global
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub namespace: Option<String>,
+    pub type_: String,
+    pub name: String,
+    /// Tokens after `=`, if the global is initialized
+    pub init: Option<Vec<Token>>,
+    /// Declared in an anonymous namespace, emitted `static`
+    pub file_private: bool,
+}
+
+impl Global {
+    /// Name of the generated C global, e.g. `cfg_verbosity`
+    pub fn full_name(&self) -> String {
+        mangle(&self.namespace, &self.name)
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.file_private {
+            write!(f, "static ")?;
+        }
+        match &self.init {
+            Some(init) => writeln!(f, "{} = {};", declarator(&self.type_, &self.full_name()), join_body(init)),
+            None => writeln!(f, "{};", declarator(&self.type_, &self.full_name())),
+        }
+    }
+}
+
+/// `#import <path>`
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    /// Generated C for the imported file, filled in by import resolution
+    pub expansion: Option<String>,
+}
+
+impl Import {
+    /// `stdio.h` for `#import <c:stdio.h>`
+    pub fn c_header(&self) -> Option<&str> {
+        self.path.strip_prefix("c:")
+    }
+}
+
+/// `export namespace math::linear as linalg;` or `export shapes::Circle;`
+#[derive(Debug, Clone)]
+pub struct Export {
+    /// What is exported, e.g. `math::linear`
+    pub path: String,
+    /// The name it gets, by default the last segment of `path`
+    pub alias: String,
+    /// A whole namespace rather than one member
+    pub namespace: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub variables: Vec<Variable>,
+    pub functions: Vec<Function>,
+    pub operators: Vec<OperatorOverload>,
+    /// Declared in an anonymous namespace: methods are emitted `static`
+    /// and the class is not visible to importers
+    pub file_private: bool,
+    /// `rc class`: values are reference-counted handles to a heap block
+    pub rc: bool,
+    /// `union`: the fields share their storage
+    pub union: bool,
+    /// A `@tagged` union compiled with `--union-checks`: a hidden tag
+    /// records the member last written and reads of another member abort
+    pub checked: bool,
+    /// Fields holding rc values, released with the block: (field, mangled
+    /// class). Filled in by the rc pass
+    pub rc_fields: Vec<(String, String)>,
+    /// `@name` attributes written before the class, e.g. `@packed`
+    pub attributes: Vec<Attribute>,
+    /// Field declarations that aren't `Type name;`, as written. They are
+    /// left out of the struct
+    pub unsupported_fields: Vec<String>,
+}
+
+impl Class {
+    /// Name of the generated C struct, e.g. `math_Vector`
+    pub fn full_name(&self) -> String {
+        mangle(&self.namespace, &self.name)
+    }
+
+    pub fn layout(&self) -> StructLayout {
+        StructLayout::of(&self.attributes)
+    }
+}
+
+/// How a class's struct is laid out: `@packed` drops the padding between
+/// fields, `@align(N)` raises the struct's alignment to N bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StructLayout {
+    pub packed: bool,
+    pub align: Option<u64>,
+}
+
+impl StructLayout {
+    pub fn of(attributes: &[Attribute]) -> Self {
+        StructLayout {
+            packed: has_attribute(attributes, "packed"),
+            align: attributes.iter().find(|a| a.name == "align").and_then(|a| alignment(&a.args).ok()),
+        }
+    }
+
+    /// The gcc attribute after `struct`, e.g. ` __attribute__((packed))`;
+    /// empty for C's own layout
+    pub fn c_attribute(&self) -> String {
+        let mut attributes = Vec::new();
+        if self.packed {
+            attributes.push("packed".to_string());
+        }
+        if let Some(align) = self.align {
+            attributes.push(format!("aligned({})", align));
+        }
+        if attributes.is_empty() {
+            return String::new();
+        }
+        format!(" __attribute__(({}))", attributes.join(", "))
+    }
+}
+
+/// The alignment `@align(args)` asks for: a power of two
+pub fn alignment(args: &[Token]) -> Result<u64, String> {
+    if args.iter().all(|t| matches!(t, Token::Newline | Token::Comment(_))) {
+        return Err("needs an alignment, e.g. `@align(16)`".to_string());
+    }
+    match consteval::eval(args, &HashMap::new()) {
+        Ok(align) if align > 0 && (align as u64).is_power_of_two() => Ok(align as u64),
+        Ok(align) => Err(format!("must be a power of two, not {}", align)),
+        Err(_) => Err(format!("must be an integer constant, not `{}`", detokenize(args).trim())),
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rc {
+            return self.fmt_rc(f);
+        }
+        let name = self.full_name();
+        let attribute = self.layout().c_attribute();
+        // Unions keep their tag, so C code can still write `union Name`
+        if self.checked {
+            write!(f, "typedef struct{attribute} {name} {{ int __z_tag; union {{ ")?;
+        } else if self.union {
+            write!(f, "typedef union{attribute} {name} {{ ")?;
+        } else {
+            write!(f, "typedef struct{attribute} {{ ")?;
+        }
+
+        for var in &self.variables {
+            write!(f, "{}", var)?;
+        }
+
+        if self.checked {
+            write!(f, " }};")?;
+        }
+        writeln!(f, " }} {};", name)?;
+        // Before the methods, which may call them
+        if derives(&self.attributes).iter().any(|d| d == "Pack") {
+            write!(f, "{}", pack::functions(self, self.linkage()))?;
+        }
+        self.fmt_members(f)?;
+        self.fmt_typeinfo(f)
+    }
+}
+
+impl Class {
+    fn linkage(&self) -> &'static str {
+        if self.file_private { "static " } else { "" }
+    }
+
+    fn fmt_members(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let linkage = |attributes: &[Attribute]| {
+            if has_attribute(attributes, "inline") { INLINE_LINKAGE } else { self.linkage() }
+        };
+        // Prototypes first, so methods can call each other in any order
+        for func in &self.functions {
+            writeln!(f, "{}{};", linkage(&func.attributes), func.signature())?;
+        }
+        for op in &self.operators {
+            writeln!(f, "{}{};", linkage(&op.attributes), op.signature())?;
+        }
+
+        for func in &self.functions {
+            write!(f, "{}{}", linkage(&func.attributes), func)?;
+        }
+
+        for op in &self.operators {
+            write!(f, "{}{}", linkage(&op.attributes), op)?;
+        }
+        Ok(())
+    }
+
+    // A handle struct pointing at the fields and the reference count, then
+    // `new`, `retain`, `release` and `set`
+    fn fmt_rc(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.full_name();
+        let linkage = self.linkage();
+        writeln!(f, "#include <stdlib.h>")?;
+        writeln!(f, "typedef struct {name}__data {name}__data;")?;
+        writeln!(f, "typedef struct {{ {name}__data* ptr; }} {name};")?;
+        write!(f, "struct{} {name}__data {{ int __z_refs; ", self.layout().c_attribute())?;
+        for var in &self.variables {
+            write!(f, "{}", var)?;
+        }
+        writeln!(f, " }};")?;
+        writeln!(f, "{linkage}{name} {name}_new(void);")?;
+        writeln!(f, "{linkage}{name} {name}_retain({name} self);")?;
+        writeln!(f, "{linkage}void {name}_release({name} self);")?;
+        writeln!(f, "{linkage}void {name}_set({name}* slot, {name} value);")?;
+        self.fmt_members(f)?;
+
+        writeln!(f)?;
+        writeln!(f, "{linkage}{name} {name}_new(void){{ {name} self = {{ calloc(1, sizeof({name}__data)) }}; if (!self.ptr) abort(); self.ptr->__z_refs = 1; return self; }}")?;
+        writeln!(f, "{linkage}{name} {name}_retain({name} self){{ if (self.ptr) self.ptr->__z_refs++; return self; }}")?;
+        write!(f, "{linkage}void {name}_release({name} self){{ if (!self.ptr || --self.ptr->__z_refs > 0) return; ")?;
+        if self.functions.iter().any(|func| func.name == "drop" && func.params.is_empty()) {
+            write!(f, "{name}_drop(self); ")?;
+        }
+        for (field, class) in &self.rc_fields {
+            write!(f, "{class}_release(self.ptr->{field}); ")?;
+        }
+        writeln!(f, "free(self.ptr); }}")?;
+        writeln!(f, "{linkage}void {name}_set({name}* slot, {name} value){{ {name} old = *slot; *slot = value; {name}_release(old); }}")?;
+        // For `@arena` functions, which keep temporaries by their pointer
+        writeln!(f, "{linkage}void {name}_release_ptr(void* ptr){{ {name}_release(({name}){{ ptr }}); }}")?;
+        self.fmt_typeinfo(f)
+    }
+
+    // The tables `typeinfo(Name)` points at, for `@reflect` classes
+    fn fmt_typeinfo(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !has_attribute(&self.attributes, "reflect") {
+            return Ok(());
+        }
+        writeln!(f)?;
+        write!(f, "{}", reflect::tables(self, self.linkage()))
+    }
+}
+
+/// `interface Name { Ret method(Params); ... }`: a value that refers to
+/// any class with those methods, see `dynamic`
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub methods: Vec<InterfaceMethod>,
+    /// Declared in an anonymous namespace, not visible to importers
+    pub file_private: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceMethod {
+    pub name: String,
+    pub return_type: String,
+    pub params: Vec<Variable>,
+}
+
+impl Interface {
+    /// Name of the generated C struct, e.g. `geo_Shape`
+    pub fn full_name(&self) -> String {
+        mangle(&self.namespace, &self.name)
+    }
+}
+
+// The vtable and the `{ data, vt }` pair, then a function per method that
+// calls through the vtable, so `s.area()` lowers like a class method call
+impl fmt::Display for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.full_name();
+        write!(f, "typedef struct {{ const __z_type* type; ")?;
+        for method in &self.methods {
+            let params: String = method.params.iter().map(|p| format!(", {}", p.param())).collect();
+            write!(f, "{} (*{})(void* self{}); ", method.return_type, method.name, params)?;
+        }
+        writeln!(f, "}} {name}__vtable;")?;
+        writeln!(f, "typedef struct {{ void* data; const {name}__vtable* vt; }} {name};")?;
+        for method in &self.methods {
+            let params: String = method.params.iter().map(|p| format!(", {}", p.param())).collect();
+            let args: String = method.params.iter().map(|p| format!(", {}", p.name)).collect();
+            let call = format!("self.vt->{}(self.data{})", method.name, args);
+            let body = if method.return_type == "void" { format!("{};", call) } else { format!("return {};", call) };
+            writeln!(f, "{INLINE_LINKAGE}{} {name}_{}({name} self{params}){{ {body} }}", method.return_type, method.name)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub type_: String,
+}
+
+impl Variable {
+    /// The `...` ending the parameters of a variadic function, kept as a
+    /// parameter of type `...` with no name
+    pub fn is_variadic(&self) -> bool {
+        self.type_ == "..."
+    }
+
+    /// `type name` as written in a parameter list
+    pub fn param(&self) -> String {
+        if self.is_variadic() {
+            return self.type_.clone();
+        }
+        format!("{} {}", self.type_, self.name)
+    }
+
+    /// `type name` as C declares it, see `declarator`
+    pub fn declarator(&self) -> String {
+        declarator(&self.type_, &self.name)
+    }
+}
+
+/// `type name` as C declares it, with an array's sizes after the name:
+/// `float data[9]` of a `float[9]`
+pub fn declarator(type_: &str, name: &str) -> String {
+    match type_.split_once('[') {
+        Some((element, dimensions)) => format!("{} {}[{}", element, name, dimensions),
+        None => format!("{} {}", type_, name),
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};", self.declarator())
+    }
+}
+
+/// `@name` or `@name(args)` written before a declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<Token>,
+}
+
+/// Attributes the compiler understands
+pub const ATTRIBUTES: &[&str] = &["arena", "inline", "packed", "align", "c_layout", "tagged", "reflect", "derive", "allow", "deny", "export", "no_mangle", "test"];
+
+/// The attributes of `ATTRIBUTES` that apply to both classes and functions
+pub const SHARED_ATTRIBUTES: &[&str] = &["allow", "deny"];
+
+/// The attributes of `ATTRIBUTES` that apply to classes; the rest apply to functions
+pub const CLASS_ATTRIBUTES: &[&str] = &["packed", "align", "c_layout", "tagged", "reflect", "derive"];
+
+/// The attributes of `ATTRIBUTES` that only free functions take; a method's
+/// C name always has its class's in it, and a test is called with nothing
+pub const FREE_FUNCTION_ATTRIBUTES: &[&str] = &["export", "no_mangle", "test"];
+
+/// Linkage of `@inline` functions, which gcc can then inline at -O1 and up
+pub const INLINE_LINKAGE: &str = "static inline ";
+
+pub fn has_attribute(attributes: &[Attribute], name: &str) -> bool {
+    attributes.iter().any(|a| a.name == name)
+}
+
+/// Whether a function keeps its own name as its C symbol, even inside a
+/// namespace: `@no_mangle`, and `@export`, which implies it
+pub fn keeps_name(attributes: &[Attribute]) -> bool {
+    has_attribute(attributes, "no_mangle") || has_attribute(attributes, "export")
+}
+
+/// What `@export` adds before a function so a shared library built from it
+/// exports the symbol: the library is compiled with hidden visibility, and
+/// Windows DLLs export nothing unless asked
+pub fn export_linkage(os: &str) -> &'static str {
+    if os == "windows" { "__declspec(dllexport) " } else { "__attribute__((visibility(\"default\"))) " }
+}
+
+/// What `@derive(...)` attributes ask to generate, e.g. `["Pack"]`
+pub fn derives(attributes: &[Attribute]) -> Vec<String> {
+    attributes.iter()
+        .filter(|a| a.name == "derive")
+        .flat_map(|a| a.args.iter())
+        .filter_map(|t| match t {
+            Token::Identifier(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub class_name: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub return_type: String,
+    pub params: Vec<Variable>,
+    pub body_tokens: Vec<Token>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Function {
+    /// `ret Class_name(Class self, params)`, the C declaration without a body
+    pub fn signature(&self) -> String {
+        let full_class_name = mangle(&self.namespace, &self.class_name);
+
+        let params = if self.params.is_empty() {
+            String::new()
+        } else {
+            ",".to_owned() + &join_params(&self.params)
+        };
+
+        format!("{} {}_{}({} self{})", self.return_type, full_class_name, self.name, full_class_name, params)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{{{}}}", self.signature(), join_body(&self.body_tokens))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OperatorOverload {
+    pub class_name: String,
+    pub namespace: Option<String>,
+    pub operator: String,
+    pub return_type: String,
+    pub params: Vec<Variable>,
+    pub body_tokens: Vec<Token>,
+    pub attributes: Vec<Attribute>,
+    /// Written outside of the class, e.g. `V operator+(V a, V b)`: the left
+    /// operand is the first parameter rather than `self`. Name resolution
+    /// points `class_name` and `namespace` at the left operand's class
+    pub free: bool,
+    /// The right operand's type, mangled, when the class overloads the
+    /// operator more than once, e.g. `float` of `V_operator_mul_float`; see
+    /// `overloads`
+    pub overload: Option<String>,
+}
+
+impl OperatorOverload {
+    /// Name of the generated C function, e.g. `math_Vector_operator_add`
+    pub fn full_name(&self) -> String {
+        let name = format!("{}_operator_{}", mangle(&self.namespace, &self.class_name), self.c_name());
+        match &self.overload {
+            Some(overload) => format!("{}_{}", name, overload),
+            None => name,
+        }
+    }
+
+    /// The type of the right operand as written, e.g. `float` of
+    /// `operator*(float k)`; none for a unary operator
+    pub fn right_type(&self) -> Option<&str> {
+        let right = if self.free { self.params.get(1) } else { self.params.first() };
+        right.map(|param| param.type_.as_str())
+    }
+
+    /// Suffix of the generated function, e.g. `add` for `Vector_operator_add`
+    pub fn c_name(&self) -> &'static str {
+        operators::find(&self.operator).map_or("unknown_op", |op| op.name)
+    }
+}
+
+impl OperatorOverload {
+    /// `ret Class_operator_name(Class self, params)`, without a body
+    pub fn signature(&self) -> String {
+        if self.free {
+            return format!("{} {}({})", self.return_type, self.full_name(), join_params(&self.params));
+        }
+        let full_class_name = mangle(&self.namespace, &self.class_name);
+
+        let params = if self.params.is_empty() {
+            String::new()
+        } else {
+            ", ".to_owned() + &join_params(&self.params)
+        };
+
+        format!("{} {}({} self{})", self.return_type, self.full_name(), full_class_name, params)
+    }
+}
+
+impl fmt::Display for OperatorOverload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{{{}}}", self.signature(), join_body(&self.body_tokens))
+    }
+}
+
+/// A statement in a function body. Only the control flow the compiler
+/// checks is structured; everything else stays as tokens.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Tokens(Vec<Token>),
+    Loop(Loop),
+    Switch(Switch),
+}
+
+#[derive(Debug, Clone)]
+pub struct Loop {
+    /// `outer` in `outer: for (...)`, the target of `break outer;`
+    pub label: Option<String>,
+    pub kind: LoopKind,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LoopKind {
+    While { condition: Vec<Token> },
+    DoWhile { condition: Vec<Token> },
+    /// `for (init; condition; step)`, any part may be empty
+    For { init: Vec<Token>, condition: Vec<Token>, step: Vec<Token> },
+    /// `for (T x : iterable)`, over a class value with `begin()` and `end()`
+    ForEach { binding: Vec<Token>, iterable: Vec<Token> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Switch {
+    pub scrutinee: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+/// Turns statements back into tokens. Loop and switch bodies are always braced.
+pub fn flatten(stmts: &[Stmt]) -> Vec<Token> {
+    let mut out = Vec::new();
+    for stmt in stmts {
+        flatten_into(stmt, &mut out);
+    }
+    out
+}
+
+fn flatten_into(stmt: &Stmt, out: &mut Vec<Token>) {
+    let symbol = |s: &str| Token::Symbol(s.to_string());
+    let keyword = |s: &str| Token::Identifier(s.to_string());
+    let braced = |out: &mut Vec<Token>, body: &[Stmt]| {
+        out.push(symbol("{"));
+        for stmt in body {
+            flatten_into(stmt, out);
+        }
+        out.push(symbol("}"));
+    };
+
+    match stmt {
+        Stmt::Tokens(tokens) => out.extend(tokens.iter().cloned()),
+        // Lowering replaces labels with gotos; until then they round-trip
+        Stmt::Loop(Loop { label: Some(label), kind, body }) => {
+            out.extend([keyword(label), symbol(":")]);
+            flatten_into(&Stmt::Loop(Loop { label: None, kind: kind.clone(), body: body.clone() }), out);
+        }
+        Stmt::Loop(Loop { kind: LoopKind::While { condition }, body, .. }) => {
+            out.extend([keyword("while"), symbol("(")]);
+            out.extend(condition.iter().cloned());
+            out.push(symbol(")"));
+            braced(out, body);
+        }
+        Stmt::Loop(Loop { kind: LoopKind::DoWhile { condition }, body, .. }) => {
+            out.push(keyword("do"));
+            braced(out, body);
+            out.extend([keyword("while"), symbol("(")]);
+            out.extend(condition.iter().cloned());
+            out.extend([symbol(")"), symbol(";")]);
+        }
+        Stmt::Loop(Loop { kind: LoopKind::For { init, condition, step }, body, .. }) => {
+            out.extend([keyword("for"), symbol("(")]);
+            out.extend(init.iter().cloned());
+            out.push(symbol(";"));
+            out.extend(condition.iter().cloned());
+            out.push(symbol(";"));
+            out.extend(step.iter().cloned());
+            out.push(symbol(")"));
+            braced(out, body);
+        }
+        Stmt::Loop(Loop { kind: LoopKind::ForEach { binding, iterable }, body, .. }) => {
+            out.extend([keyword("for"), symbol("(")]);
+            out.extend(binding.iter().cloned());
+            out.push(symbol(":"));
+            out.extend(iterable.iter().cloned());
+            out.push(symbol(")"));
+            braced(out, body);
+        }
+        Stmt::Switch(switch) => {
+            out.extend([keyword("switch"), symbol("(")]);
+            out.extend(switch.scrutinee.iter().cloned());
+            out.push(symbol(")"));
+            braced(out, &switch.body);
+        }
+    }
+}
+
+pub(crate) fn mangle(namespace: &Option<String>, name: &str) -> String {
+    match namespace {
+        Some(ns) => format!("{}_{}", ns, name),
+        None => name.to_string(),
+    }
+}
+
+fn join_params(params: &[Variable]) -> String {
+    params
+        .iter()
+        .map(Variable::param)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn join_body(tokens: &[Token]) -> String {
+    let token_strings: Vec<String> = tokens.iter().map(|t| {
+        match t {
+            Token::Identifier(s)
+            | Token::Number(s)
+            | Token::StringLit(s)
+            | Token::CharLit(s)
+            | Token::Symbol(s)
+            | Token::Comment(s) => s.clone(),
+            Token::Newline => "\n".to_string(),
+            Token::Eof => "".to_string(),
+        }
+    }).collect();
+
+    token_strings.join(" ")
+}
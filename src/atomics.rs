@@ -0,0 +1,187 @@
+// src/atomics.rs
+//
+// Atomics: `Atomic<T> name` is a C11 `T _Atomic`, and its methods are the
+// `<stdatomic.h>` operations, e.g. `hits.fetch_add(1)` is
+// `atomic_fetch_add(&hits, 1)`. Every operation is sequentially consistent.
+// Like slices this runs on the token stream before parsing, so only atomics
+// declared in the same file get methods; the others can still be passed to
+// the `atomic_*` functions directly.
+
+use std::collections::HashSet;
+
+use crate::parser::{code, is_symbol};
+use crate::tokenizer::{generated, Token};
+
+const RUNTIME: &str = "#include <stdatomic.h>\n";
+
+/// Methods of an atomic and the `<stdatomic.h>` function each one calls
+const METHODS: &[(&str, &str)] = &[
+    ("load", "atomic_load"),
+    ("store", "atomic_store"),
+    ("exchange", "atomic_exchange"),
+    ("fetch_add", "atomic_fetch_add"),
+    ("fetch_sub", "atomic_fetch_sub"),
+    ("fetch_and", "atomic_fetch_and"),
+    ("fetch_or", "atomic_fetch_or"),
+    ("fetch_xor", "atomic_fetch_xor"),
+    ("compare_exchange", "atomic_compare_exchange_strong"),
+    ("compare_exchange_weak", "atomic_compare_exchange_weak"),
+];
+
+/// Rewrites `Atomic<T>` declarations and the method calls on them, and
+/// returns the errors for methods atomics don't have
+pub fn rewrite(tokens: &[Token]) -> (Vec<Token>, Vec<String>) {
+    let (names, pointers) = atomic_names(tokens);
+    if names.is_empty() {
+        return (tokens.to_vec(), Vec::new());
+    }
+
+    let mut out: Vec<Token> = Vec::new();
+    let mut errors = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        // `Atomic < T >` -> `T _Atomic`
+        if is_identifier(tokens.get(i), "Atomic") && is_symbol(tokens.get(i + 1), "<") {
+            if let Some(close) = (i + 2..tokens.len()).find(|&j| is_symbol(tokens.get(j), ">")) {
+                out.extend_from_slice(&tokens[i + 2..close]);
+                out.push(Token::Identifier("_Atomic".to_string()));
+                i = close + 1;
+                continue;
+            }
+        }
+
+        // `x.method(` or `p->method(` -> `atomic_method(&x, ` or `atomic_method(p, `
+        if let (Some(Token::Identifier(name)), Some(Token::Symbol(access)), Some(Token::Identifier(method))) = (tokens.get(i), tokens.get(i + 1), tokens.get(i + 2)) {
+            let pointer = access == "->" && pointers.contains(name);
+            if names.contains(name) && (access == "." || pointer) && is_symbol(tokens.get(i + 3), "(") {
+                let Some(&(_, function)) = METHODS.iter().find(|(m, _)| m == method) else {
+                    errors.push(format!(
+                        "`{}` is atomic and has no method `{}`; use {}",
+                        name,
+                        method,
+                        METHODS.iter().map(|(m, _)| format!("`{}`", m)).collect::<Vec<_>>().join(", ")
+                    ));
+                    out.push(tokens[i].clone());
+                    i += 1;
+                    continue;
+                };
+                let start = receiver_start(&out);
+                let mut receiver: Vec<Token> = out.drain(start..).collect();
+                receiver.push(Token::Identifier(name.clone()));
+                out.extend(code(&format!("{}(", function)));
+                if !pointer {
+                    out.push(Token::Symbol("&".to_string()));
+                }
+                out.extend(receiver);
+                if !is_symbol(tokens.get(i + 4), ")") {
+                    out.push(Token::Symbol(",".to_string()));
+                }
+                i += 4;
+                continue;
+            }
+        }
+
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out.splice(0..0, generated(RUNTIME, 1));
+    (out, errors)
+}
+
+// Names declared as `Atomic<T> name` anywhere in the file, and the ones of
+// those declared as `Atomic<T>* name`
+fn atomic_names(tokens: &[Token]) -> (HashSet<String>, HashSet<String>) {
+    let mut names = HashSet::new();
+    let mut pointers = HashSet::new();
+    for i in 0..tokens.len() {
+        if !is_identifier(tokens.get(i), "Atomic") || !is_symbol(tokens.get(i + 1), "<") {
+            continue;
+        }
+        let Some(close) = (i + 2..tokens.len()).find(|&j| is_symbol(tokens.get(j), ">")) else {
+            continue;
+        };
+        let mut j = close + 1;
+        while is_symbol(tokens.get(j), "*") {
+            j += 1;
+        }
+        if let Some(Token::Identifier(name)) = tokens.get(j) {
+            names.insert(name.clone());
+            if j > close + 1 {
+                pointers.insert(name.clone());
+            }
+        }
+    }
+    (names, pointers)
+}
+
+// Start of the `a.`, `a->`, `a[i].` or `a.b.` path in front of an atomic
+// member, if any
+fn receiver_start(out: &[Token]) -> usize {
+    let mut start = out.len();
+    while let Some(Token::Symbol(access)) = start.checked_sub(1).and_then(|i| out.get(i)) {
+        if access != "." && access != "->" {
+            break;
+        }
+        let mut operand = start - 1;
+        while is_symbol(operand.checked_sub(1).and_then(|i| out.get(i)), "]") {
+            match matching_open(out, operand - 1) {
+                Some(open) => operand = open,
+                None => return start,
+            }
+        }
+        match operand.checked_sub(1).and_then(|i| out.get(i)) {
+            Some(Token::Identifier(_)) => start = operand - 1,
+            _ => break,
+        }
+    }
+    start
+}
+
+// Index of the `[` opening the `]` at `close`
+fn matching_open(tokens: &[Token], close: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..=close).rev() {
+        match &tokens[i] {
+            Token::Symbol(s) if s == "]" => depth += 1,
+            Token::Symbol(s) if s == "[" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_identifier(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Identifier(w)) if w == word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_atomics_become_stdatomic_calls() {
+        let tokens = tokenize("Atomic<int> hits;\nvoid f(Atomic<long>* total, Counter c) {\n  hits.fetch_add(1);\n  total->store(hits.load());\n  c.slots[i].hits.compare_exchange(&old, 2);\n}");
+        let (tokens, errors) = rewrite(&tokens);
+        let output = detokenize(&tokens);
+        assert!(errors.is_empty());
+        assert!(output.contains("#include <stdatomic.h>"));
+        assert!(output.contains("int _Atomic hits;"));
+        assert!(output.contains("long _Atomic *total"));
+        assert!(output.contains("atomic_fetch_add(&hits, 1);"));
+        assert!(output.contains("atomic_store(total, atomic_load(&hits));"));
+        assert!(output.contains("atomic_compare_exchange_strong(&c.slots[i].hits, &old, 2);"));
+    }
+
+    #[test]
+    fn test_unknown_atomic_method() {
+        let (_, errors) = rewrite(&tokenize("Atomic<int> hits;\nvoid f() { hits.increment(); }"));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("`hits` is atomic and has no method `increment`; use `load`, `store`"));
+    }
+}
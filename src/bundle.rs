@@ -0,0 +1,157 @@
+// src/bundle.rs
+//
+// `--bundle`: the program as one C file that builds wherever there is a C
+// compiler, without the Z toolchain or the project's files. The generated
+// C already holds the standard library and the imported files the program
+// uses; the bundle also copies in the C headers the project includes
+// itself (`#include "vec.h"`, or one found with `-I`), so the only
+// includes left are those of the C library and the system.
+//
+// Headers are copied in where they are included, as the preprocessor would
+// do, so their include guards keep working; one with `#pragma once` is
+// copied the first time only. A header of the C library that the
+// generated C includes again outside of any `#if` is left out after the
+// first time.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Headers of the C library and of the systems the standard library
+/// supports, which every C compiler there has
+const SYSTEM_HEADERS: &[&str] = &[
+    "assert.h", "complex.h", "ctype.h", "errno.h", "fenv.h", "float.h", "inttypes.h", "iso646.h", "limits.h", "locale.h", "math.h",
+    "setjmp.h", "signal.h", "stdalign.h", "stdarg.h", "stdatomic.h", "stdbit.h", "stdbool.h", "stdckdint.h", "stddef.h", "stdint.h",
+    "stdio.h", "stdlib.h", "stdnoreturn.h", "string.h", "tgmath.h", "threads.h", "time.h", "uchar.h", "wchar.h", "wctype.h",
+    // POSIX
+    "dirent.h", "dlfcn.h", "execinfo.h", "fcntl.h", "netdb.h", "poll.h", "pthread.h", "sched.h", "semaphore.h", "strings.h", "termios.h", "unistd.h",
+    // Windows
+    "direct.h", "io.h", "process.h", "windows.h", "winsock2.h", "ws2tcpip.h",
+];
+
+const SYSTEM_DIRS: &[&str] = &["sys/", "netinet/", "arpa/"];
+
+/// One C file and what it still includes from outside
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub c_code: String,
+    /// Included headers that are neither the C library's nor found to be
+    /// copied in, e.g. `<SDL2/SDL.h>`
+    pub external: Vec<String>,
+}
+
+/// `c_code` with the headers it includes from `dir`, the directory of the
+/// program, and from `include_dirs` copied in
+pub fn bundle(c_code: &str, dir: &Path, include_dirs: &[PathBuf]) -> Bundle {
+    let mut bundler = Bundler { include_dirs, out: String::new(), external: Vec::new(), system: Vec::new(), once: Vec::new(), open: Vec::new(), depth: 0 };
+    bundler.add(c_code, dir);
+    Bundle { c_code: bundler.out, external: bundler.external }
+}
+
+fn is_system_header(header: &str) -> bool {
+    SYSTEM_HEADERS.contains(&header) || SYSTEM_DIRS.iter().any(|dir| header.starts_with(dir))
+}
+
+struct Bundler<'a> {
+    include_dirs: &'a [PathBuf],
+    out: String,
+    external: Vec<String>,
+    /// C library headers included outside of any `#if` so far
+    system: Vec<String>,
+    /// Headers with `#pragma once` copied in so far
+    once: Vec<PathBuf>,
+    /// The headers being copied in, to stop at one including itself
+    open: Vec<PathBuf>,
+    /// How many `#if`s the current line is in
+    depth: usize,
+}
+
+impl Bundler<'_> {
+    fn add(&mut self, text: &str, dir: &Path) {
+        for line in text.lines() {
+            let directive = line.trim_start().strip_prefix('#').map(str::trim_start);
+            match directive.and_then(|d| d.split_whitespace().next()) {
+                Some("if" | "ifdef" | "ifndef") => self.depth += 1,
+                Some("endif") => self.depth = self.depth.saturating_sub(1),
+                Some("pragma") if directive.is_some_and(|d| d.split_whitespace().nth(1) == Some("once")) => continue,
+                Some("include") => {
+                    if let Some((header, quoted)) = included(directive.unwrap_or_default()) {
+                        if self.include(header, quoted, dir) {
+                            continue;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+    }
+
+    // Copies in or drops the include of `header`; false keeps the line
+    fn include(&mut self, header: &str, quoted: bool, dir: &Path) -> bool {
+        let local = quoted.then(|| dir.join(header));
+        let found = local.into_iter().chain(self.include_dirs.iter().map(|d| d.join(header))).find(|path| path.is_file());
+        match found {
+            Some(path) => {
+                let path = path.canonicalize().unwrap_or(path);
+                let Ok(text) = fs::read_to_string(&path) else { return false };
+                if self.open.contains(&path) || self.once.contains(&path) {
+                    return true;
+                }
+                if text.lines().any(|l| l.trim_start().strip_prefix('#').is_some_and(|d| d.split_whitespace().eq(["pragma", "once"]))) {
+                    self.once.push(path.clone());
+                }
+                self.out.push_str(&format!("/* {} */\n", header));
+                self.open.push(path.clone());
+                self.add(&text, path.parent().unwrap_or(dir));
+                self.open.pop();
+                true
+            }
+            None if is_system_header(header) => {
+                if self.depth > 0 {
+                    return false;
+                }
+                let seen = self.system.iter().any(|h| h == header);
+                if !seen {
+                    self.system.push(header.to_string());
+                }
+                seen
+            }
+            None => {
+                let shown = if quoted { format!("\"{}\"", header) } else { format!("<{}>", header) };
+                if !self.external.contains(&shown) {
+                    self.external.push(shown);
+                }
+                false
+            }
+        }
+    }
+}
+
+// The header of `include "x.h"` or `include <x.h>`, and whether it is quoted
+fn included(directive: &str) -> Option<(&str, bool)> {
+    let rest = directive.strip_prefix("include")?.trim();
+    if let Some(rest) = rest.strip_prefix('"') {
+        return rest.split_once('"').map(|(header, _)| (header, true));
+    }
+    rest.strip_prefix('<')?.split_once('>').map(|(header, _)| (header, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_headers_are_copied_in() {
+        let dir = std::env::temp_dir().join(format!("tarnish_bundle_{}", std::process::id()));
+        fs::create_dir_all(dir.join("include")).unwrap();
+        fs::write(dir.join("vec.h"), "#pragma once\n#include <stdio.h>\n#include \"scalar.h\"\ntypedef struct { scalar x; } vec;\n").unwrap();
+        fs::write(dir.join("include/scalar.h"), "#ifndef SCALAR_H\n#define SCALAR_H\n#include <stdint.h>\ntypedef int32_t scalar;\n#endif\n").unwrap();
+
+        let c_code = "#include <stdio.h>\n#include \"vec.h\"\n#include \"vec.h\"\n#include <SDL2/SDL.h>\nint main() { return 0; }\n";
+        let bundle = bundle(c_code, &dir, &[dir.join("include")]);
+        assert_eq!(bundle.c_code, "#include <stdio.h>\n/* vec.h */\n/* scalar.h */\n#ifndef SCALAR_H\n#define SCALAR_H\n#include <stdint.h>\ntypedef int32_t scalar;\n#endif\ntypedef struct { scalar x; } vec;\n#include <SDL2/SDL.h>\nint main() { return 0; }\n");
+        assert_eq!(bundle.external, vec!["<SDL2/SDL.h>"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
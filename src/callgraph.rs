@@ -0,0 +1,189 @@
+// src/callgraph.rs
+//
+// The call graph of the generated C functions, by C name, across every file
+// of a compile. It is built from the lowered modules, so method calls are
+// already `Class_method(...)` calls. Strongly connected components give the
+// recursive functions: `@inline` on one of them can't be honored by gcc, and
+// `--emit callgraph` writes the graph as GraphViz DOT with them highlighted.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{has_attribute, Module};
+use crate::parser::function_definitions;
+use crate::tokenizer::Token;
+use crate::unused;
+
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// Names called by each function defined so far; callees are filtered
+    /// to defined functions when the graph is read
+    calls: BTreeMap<String, BTreeSet<String>>,
+    /// File that defines each function
+    files: BTreeMap<String, String>,
+    /// Functions emitted `inline`
+    inline: BTreeSet<String>,
+}
+
+impl CallGraph {
+    /// Adds the functions `module`, compiled from `path`, defines
+    pub fn add(&mut self, path: &str, module: &Module) {
+        for tokens in unused::code(&module.items) {
+            for definition in function_definitions(&tokens) {
+                let Some(end) = definition.body_end(&tokens) else { continue };
+                let name = definition.name(&tokens).to_string();
+                let callees = calls(&tokens[definition.name_index + 1..end]);
+                // Free `@inline` functions only become `static inline` when emitted
+                let written = definition.return_type.iter().any(|t| matches!(t, Token::Identifier(w) if w == "inline"));
+                if written || has_attribute(&definition.attributes, "inline") {
+                    self.inline.insert(name.clone());
+                }
+                self.files.insert(name.clone(), path.to_string());
+                self.calls.entry(name).or_default().extend(callees);
+            }
+        }
+    }
+
+    pub fn contains(&self, function: &str) -> bool {
+        self.calls.contains_key(function)
+    }
+
+    /// Path of the file that defines `function`
+    pub fn file(&self, function: &str) -> Option<&String> {
+        self.files.get(function)
+    }
+
+    pub fn is_inline(&self, function: &str) -> bool {
+        self.inline.contains(function)
+    }
+
+    /// Defined functions `function` calls
+    pub fn callees(&self, function: &str) -> Vec<&str> {
+        let Some(callees) = self.calls.get(function) else { return Vec::new() };
+        callees.iter().filter(|c| self.contains(c)).map(String::as_str).collect()
+    }
+
+    /// Groups of functions that call each other, directly or through the
+    /// others of the group, in name order. A function that calls itself is
+    /// a group of one
+    pub fn recursion(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan { graph: self, index: BTreeMap::new(), low: BTreeMap::new(), stack: Vec::new(), on_stack: BTreeSet::new(), groups: Vec::new() };
+        for function in self.calls.keys() {
+            if !tarjan.index.contains_key(function.as_str()) {
+                tarjan.visit(function);
+            }
+        }
+        let mut groups: Vec<Vec<String>> = tarjan
+            .groups
+            .into_iter()
+            .filter(|group| group.len() > 1 || self.callees(group[0]).contains(&group[0]))
+            .map(|group| {
+                let mut names: Vec<String> = group.into_iter().map(str::to_string).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    /// The graph as GraphViz DOT, a cluster per file, recursive functions in red
+    pub fn to_dot(&self) -> String {
+        let recursive: BTreeSet<String> = self.recursion().into_iter().flatten().collect();
+        let mut by_file: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (function, file) in &self.files {
+            by_file.entry(file).or_default().push(function);
+        }
+        let mut out = String::from("digraph calls {\n  node [shape=box, fontname=\"monospace\"];\n");
+        for (i, (file, functions)) in by_file.iter().enumerate() {
+            out.push_str(&format!("  subgraph cluster_{} {{\n    label={:?};\n", i, file));
+            for function in functions {
+                let style = if recursive.contains(*function) { ", color=red" } else { "" };
+                out.push_str(&format!("    {:?} [label={:?}{}];\n", function, function, style));
+            }
+            out.push_str("  }\n");
+        }
+        for function in self.calls.keys() {
+            for callee in self.callees(function) {
+                out.push_str(&format!("  {:?} -> {:?};\n", function, callee));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+// Names called in `tokens`, member calls through function pointers left out
+fn calls(tokens: &[Token]) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for i in 0..tokens.len() {
+        let Token::Identifier(name) = &tokens[i] else { continue };
+        let called = matches!(tokens.get(i + 1), Some(Token::Symbol(s)) if s == "(");
+        let member = matches!(i.checked_sub(1).map(|p| &tokens[p]), Some(Token::Symbol(s)) if s == "." || s == "->");
+        if called && !member {
+            names.insert(name.clone());
+        }
+    }
+    names
+}
+
+// Tarjan's strongly connected components
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: BTreeMap<&'a str, usize>,
+    low: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    groups: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, function: &'a str) {
+        let index = self.index.len();
+        self.index.insert(function, index);
+        self.low.insert(function, index);
+        self.stack.push(function);
+        self.on_stack.insert(function);
+        for callee in self.graph.callees(function) {
+            if !self.index.contains_key(callee) {
+                self.visit(callee);
+                let low = self.low[function].min(self.low[callee]);
+                self.low.insert(function, low);
+            } else if self.on_stack.contains(callee) {
+                let low = self.low[function].min(self.index[callee]);
+                self.low.insert(function, low);
+            }
+        }
+        if self.low[function] == index {
+            let mut group = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                group.push(member);
+                if member == function {
+                    break;
+                }
+            }
+            self.groups.push(group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_recursion_is_found() {
+        let tokens = tokenize("int fact(int n) { return n ? n * fact(n - 1) : 1; }\nint even(int n) { return n == 0 || odd(n - 1); }\nint odd(int n) { return n != 0 && even(n - 1); }\nstatic inline int twice(int n) { return n * 2; }\nint main() { printf(\"%d\", fact(3)); return even(twice(2)); }");
+        let mut graph = CallGraph::default();
+        graph.add("main.z", &parse_module(&tokens, "main.z"));
+        assert_eq!(graph.recursion(), vec![vec!["even".to_string(), "odd".to_string()], vec!["fact".to_string()]]);
+        assert_eq!(graph.callees("main"), vec!["even", "fact", "twice"]);
+        assert!(graph.is_inline("twice"));
+        let dot = graph.to_dot();
+        assert!(dot.contains("subgraph cluster_0 {\n    label=\"main.z\";"));
+        assert!(dot.contains("    \"fact\" [label=\"fact\", color=red];"));
+        assert!(dot.contains("  \"main\" -> \"twice\";"));
+    }
+}
@@ -0,0 +1,267 @@
+// src/cfg.rs
+//
+// Conditional compilation. A declaration, statement or block written after
+// `@cfg(predicate)` is only compiled when the predicate holds for the build:
+//
+//     @cfg(os = "windows")          target operating system: linux, macos, windows, ...
+//     @cfg(family = "unix")         unix or windows
+//     @cfg(arch = "x86_64")         target architecture
+//     @cfg(feature = "gui")         a feature enabled for the build
+//     @cfg(not(p)), @cfg(all(p, q, ...)), @cfg(any(p, q, ...))
+//
+// Excluded code is removed right after lexing, so no later pass registers
+// the names it declares: another file can't use them and they don't reach
+// the exported API. Its line breaks are kept to keep line numbers right.
+
+use std::collections::BTreeSet;
+
+use crate::parser::{matching_close, parse_attribute};
+use crate::tokenizer::Token;
+
+/// What `@cfg` predicates are checked against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub os: String,
+    pub family: String,
+    pub arch: String,
+    pub features: BTreeSet<String>,
+}
+
+impl Cfg {
+    /// The machine the compiler runs on, with no features
+    pub fn host() -> Cfg {
+        Cfg {
+            os: std::env::consts::OS.to_string(),
+            family: std::env::consts::FAMILY.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            features: BTreeSet::new(),
+        }
+    }
+
+    /// The target's name, architecture then operating system, e.g.
+    /// `x86_64-linux`
+    pub fn target(&self) -> String {
+        format!("{}-{}", self.arch, self.os)
+    }
+}
+
+impl Default for Cfg {
+    fn default() -> Self {
+        Cfg::host()
+    }
+}
+
+const KEYS: &[&str] = &["os", "family", "arch", "feature"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Is(String, String),
+    Not(Box<Predicate>),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+}
+
+impl Predicate {
+    fn holds(&self, cfg: &Cfg) -> bool {
+        match self {
+            Predicate::Is(key, value) => match key.as_str() {
+                "os" => cfg.os == *value,
+                "family" => cfg.family == *value,
+                "arch" => cfg.arch == *value,
+                _ => cfg.features.contains(value),
+            },
+            Predicate::Not(inner) => !inner.holds(cfg),
+            Predicate::All(all) => all.iter().all(|p| p.holds(cfg)),
+            Predicate::Any(any) => any.iter().any(|p| p.holds(cfg)),
+        }
+    }
+}
+
+/// `tokens` with the code `cfg` excludes removed and every `@cfg` dropped,
+/// and the errors in the predicates
+pub fn apply(tokens: &[Token], cfg: &Cfg) -> (Vec<Token>, Vec<String>) {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut errors = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some((attribute, next)) = parse_attribute(tokens, i).filter(|(a, _)| a.name == "cfg") else {
+            out.push(tokens[i].clone());
+            i += 1;
+            continue;
+        };
+        let code: Vec<&Token> = attribute.args.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        let holds = match parse_predicate(&code) {
+            Ok(predicate) => predicate.holds(cfg),
+            Err(e) => {
+                errors.push(format!("in `@cfg`: {}", e));
+                true
+            }
+        };
+        // The attribute's own line breaks stay either way
+        out.extend(tokens[i..next].iter().filter(|t| **t == Token::Newline).cloned());
+        if holds {
+            i = next;
+            continue;
+        }
+        let end = declaration_end(tokens, next);
+        out.extend(tokens[next..end].iter().filter(|t| **t == Token::Newline).cloned());
+        i = end;
+    }
+    (out, errors)
+}
+
+fn parse_predicate(tokens: &[&Token]) -> Result<Predicate, String> {
+    let (predicate, rest) = predicate(tokens)?;
+    match rest.first() {
+        None => Ok(predicate),
+        Some(token) => Err(format!("unexpected `{}`", text(token))),
+    }
+}
+
+// A predicate at the start of `tokens` and what follows it
+fn predicate<'t>(tokens: &'t [&'t Token]) -> Result<(Predicate, &'t [&'t Token]), String> {
+    let Some(Token::Identifier(word)) = tokens.first() else {
+        return Err(format!("expected a predicate such as `os = \"linux\"`, found {}", tokens.first().map_or("nothing".to_string(), |t| format!("`{}`", text(t)))));
+    };
+    match (word.as_str(), tokens.get(1)) {
+        ("not" | "all" | "any", Some(Token::Symbol(open))) if open == "(" => {
+            let mut rest = &tokens[2..];
+            let mut inner = Vec::new();
+            loop {
+                if let Some(Token::Symbol(close)) = rest.first() {
+                    if close == ")" {
+                        rest = &rest[1..];
+                        break;
+                    }
+                }
+                let (p, after) = predicate(rest)?;
+                inner.push(p);
+                rest = match after.first() {
+                    Some(Token::Symbol(s)) if s == "," => &after[1..],
+                    Some(Token::Symbol(s)) if s == ")" => after,
+                    _ => return Err(format!("expected `,` or `)` in `{}(...)`", word)),
+                };
+            }
+            let predicate = match word.as_str() {
+                "not" if inner.len() == 1 => Predicate::Not(Box::new(inner.remove(0))),
+                "not" => return Err("`not` takes one predicate".to_string()),
+                "all" => Predicate::All(inner),
+                _ => Predicate::Any(inner),
+            };
+            Ok((predicate, rest))
+        }
+        (key, Some(Token::Symbol(eq))) if eq == "=" => {
+            if !KEYS.contains(&key) {
+                return Err(format!("unknown key `{}`, expected `os`, `family`, `arch` or `feature`", key));
+            }
+            let Some(Token::StringLit(value)) = tokens.get(2) else { return Err(format!("`{} =` takes a string", key)) };
+            Ok((Predicate::Is(key.to_string(), value.trim_matches('"').to_string()), &tokens[3..]))
+        }
+        _ => Err(format!("expected `{} = \"...\"`", word)),
+    }
+}
+
+fn text(token: &Token) -> &str {
+    match token {
+        Token::Identifier(s) | Token::Number(s) | Token::StringLit(s) | Token::CharLit(s) | Token::Symbol(s) | Token::Comment(s) => s,
+        Token::Newline => "\\n",
+        Token::Eof => "end of file",
+    }
+}
+
+// End (exclusive) of the declaration, statement or block starting at
+// `start`: a directive ends with its line; a class, interface, namespace or
+// function with its closing brace; anything else with its `;`
+fn declaration_end(tokens: &[Token], start: usize) -> usize {
+    let mut i = start;
+    loop {
+        match tokens.get(i) {
+            Some(Token::Newline | Token::Comment(_)) => i += 1,
+            Some(Token::Symbol(at)) if at == "@" => match parse_attribute(tokens, i) {
+                Some((_, next)) => i = next,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    if matches!(tokens.get(i), Some(Token::Symbol(s)) if s == "#") {
+        return (i..tokens.len()).find(|&j| tokens[j] == Token::Newline).unwrap_or(tokens.len());
+    }
+    let first = match tokens.get(i) {
+        Some(Token::Identifier(word)) => word.as_str(),
+        _ => "",
+    };
+    let braced_item = matches!(first, "class" | "rc" | "interface" | "namespace" | "inline");
+    let mut previous: Option<&Token> = None;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Symbol(s) if s == ";" => return i + 1,
+            Token::Symbol(s) if s == "(" || s == "[" => {
+                let Some(close) = matching_close(tokens, i) else { return tokens.len() };
+                previous = Some(&tokens[close]);
+                i = close + 1;
+                continue;
+            }
+            Token::Symbol(s) if s == "{" => {
+                let Some(close) = matching_close(tokens, i) else { return tokens.len() };
+                let body = matches!(previous, Some(Token::Symbol(p)) if p == ")");
+                let statement = previous.is_none() || matches!(previous, Some(Token::Identifier(w)) if w == "else" || w == "do");
+                let next = tokens[close + 1..].iter().position(|t| !matches!(t, Token::Newline | Token::Comment(_))).map(|p| close + 1 + p);
+                let semicolon = next.filter(|&n| matches!(&tokens[n], Token::Symbol(s) if s == ";"));
+                if let Some(semicolon) = semicolon {
+                    return semicolon + 1;
+                }
+                // `if { } else { }` takes its `else` along
+                if let Some(n) = next.filter(|&n| matches!(&tokens[n], Token::Identifier(w) if w == "else")) {
+                    previous = Some(&tokens[n]);
+                    i = n + 1;
+                    continue;
+                }
+                // `do { } while (x);` and `struct { } name;` go on to their `;`
+                let declarator = match next.map(|n| (&tokens[n], tokens.get(n + 1))) {
+                    Some((Token::Symbol(s), _)) => s == "*",
+                    Some((Token::Identifier(_), Some(Token::Symbol(s)))) => matches!(s.as_str(), ";" | "," | "=" | "["),
+                    _ => false,
+                };
+                let aggregate = matches!(first, "struct" | "union" | "enum" | "typedef");
+                let continues = first == "do" || (declarator && aggregate);
+                if (body || statement || braced_item || aggregate) && !continues {
+                    return close + 1;
+                }
+                previous = Some(&tokens[close]);
+                i = close + 1;
+                continue;
+            }
+            Token::Newline | Token::Comment(_) => {}
+            token => previous = Some(token),
+        }
+        i += 1;
+    }
+    tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    fn linux() -> Cfg {
+        Cfg { os: "linux".to_string(), family: "unix".to_string(), arch: "x86_64".to_string(), features: ["gui".to_string()].into() }
+    }
+
+    #[test]
+    fn test_excluded_code_is_removed() {
+        let source = "@cfg(os = \"windows\") #import <c:windows.h>\n@cfg(family = \"unix\") int a = 1;\n@cfg(not(feature = \"gui\"))\nclass Console { int x; }\n@cfg(any(os = \"macos\", all(arch = \"x86_64\", feature = \"gui\"))) void draw() { }\n@cfg(os = \"windows\") union U { int a; }\n@cfg(os = \"windows\") struct S { int a; } s, *p;\nint main() {\n  @cfg(os = \"windows\") { Sleep(1); }\n  @cfg(os = \"windows\") if (x) { y(); } else if (w) { z(); } else { v(); }\n  @cfg(os = \"windows\") do { w(); } while (x);\n  return 0;\n}";
+        let (tokens, errors) = apply(&tokenize(source), &linux());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(detokenize(&tokens), "\nint a = 1;\n\n\nvoid draw() { }\n\n\nint main() {\n\n\n\nreturn 0;\n}");
+    }
+
+    #[test]
+    fn test_predicate_errors() {
+        let errors = |source: &str| apply(&tokenize(source), &linux()).1;
+        assert_eq!(errors("@cfg(target = \"x\") int a;"), vec!["in `@cfg`: unknown key `target`, expected `os`, `family`, `arch` or `feature`"]);
+        assert_eq!(errors("@cfg(not(os = \"a\", os = \"b\")) int a;"), vec!["in `@cfg`: `not` takes one predicate"]);
+        assert_eq!(errors("@cfg(os) int a;"), vec!["in `@cfg`: expected `os = \"...\"`"]);
+    }
+}
@@ -0,0 +1,200 @@
+// src/cheader.rs
+//
+// `#import <c:header.h>` support: locating a C header and scanning it for
+// function prototypes so calls into C can be checked before gcc runs. The
+// scanner is deliberately shallow: it reads the one header (not what that
+// header includes) and skips anything that is not a plain prototype.
+
+use std::path::{Path, PathBuf};
+
+use crate::tokenizer::{detokenize, tokenize, Token};
+
+/// A prototype found in a C header
+#[derive(Debug, Clone, PartialEq)]
+pub struct CFunction {
+    pub name: String,
+    pub return_type: String,
+    /// Parameter declarations as written, without the trailing `...`
+    pub params: Vec<String>,
+    pub variadic: bool,
+}
+
+impl CFunction {
+    /// Whether a call with `count` arguments matches the prototype
+    pub fn accepts(&self, count: usize) -> bool {
+        if self.variadic { count >= self.params.len() } else { count == self.params.len() }
+    }
+}
+
+const SYSTEM_INCLUDE_DIRS: &[&str] = &["/usr/local/include", "/usr/include", "/usr/include/x86_64-linux-gnu"];
+
+/// Finds `header` relative to the working directory, in `C_INCLUDE_PATH`,
+/// or in the usual system include directories
+pub fn find(header: &str) -> Option<PathBuf> {
+    let path = Path::new(header);
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    let from_env = std::env::var("C_INCLUDE_PATH").unwrap_or_default();
+    from_env
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .chain(SYSTEM_INCLUDE_DIRS.iter().copied())
+        .map(|dir| Path::new(dir).join(header))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Every top-level function prototype in C source
+pub fn scan(source: &str) -> Vec<CFunction> {
+    let tokens = strip_preprocessor(&tokenize(source));
+    let mut functions = Vec::new();
+    let mut statement: Vec<Token> = Vec::new();
+    let mut brace_level = 0;
+
+    for token in tokens {
+        match &token {
+            Token::Symbol(s) if s == "{" => brace_level += 1,
+            Token::Symbol(s) if s == "}" => {
+                brace_level -= 1;
+                if brace_level == 0 {
+                    // Function bodies and struct definitions end a statement
+                    statement.clear();
+                }
+            }
+            Token::Symbol(s) if s == ";" && brace_level == 0 => {
+                functions.extend(parse_prototype(&statement));
+                statement.clear();
+            }
+            _ if brace_level == 0 => statement.push(token),
+            _ => {}
+        }
+    }
+    functions
+}
+
+// Drops comments, newlines and preprocessor lines (with `\` continuations)
+fn strip_preprocessor(tokens: &[Token]) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut in_directive = false;
+    let mut line_start = true;
+    let mut previous: Option<&Token> = None;
+
+    for token in tokens {
+        match token {
+            Token::Newline => {
+                let continued = matches!(previous, Some(Token::Symbol(s)) if s == "\\");
+                if !continued {
+                    in_directive = false;
+                }
+                line_start = true;
+            }
+            Token::Comment(_) | Token::Eof => {}
+            Token::Symbol(s) if s == "#" && line_start => {
+                in_directive = true;
+                line_start = false;
+            }
+            _ => {
+                line_start = false;
+                if !in_directive {
+                    out.push(token.clone());
+                }
+            }
+        }
+        previous = Some(token);
+    }
+    out
+}
+
+// `[extern] type name (params) [attributes]`
+fn parse_prototype(statement: &[Token]) -> Option<CFunction> {
+    let open = statement.iter().position(|t| matches!(t, Token::Symbol(s) if s == "("))?;
+    if open < 2 {
+        return None;
+    }
+    let Token::Identifier(name) = &statement[open - 1] else { return None };
+    if name.starts_with("__attribute") || matches!(statement.first(), Some(Token::Identifier(s)) if s == "typedef") {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut close = None;
+    for (i, token) in statement.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" => depth += 1,
+            Token::Symbol(s) if s == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let inner = &statement[open + 1..close?];
+
+    let mut params = Vec::new();
+    let mut variadic = false;
+    for param in split_top_level(inner) {
+        if param.is_empty() {
+            continue;
+        }
+        if matches!(param, [Token::Symbol(s)] if s == "...") {
+            variadic = true;
+            continue;
+        }
+        params.push(detokenize(param).trim().to_string());
+    }
+    if params == ["void"] {
+        params.clear();
+    }
+
+    let return_type: Vec<Token> = statement[..open - 1]
+        .iter()
+        .filter(|t| !matches!(t, Token::Identifier(s) if s == "extern"))
+        .cloned()
+        .collect();
+    Some(CFunction {
+        name: name.clone(),
+        return_type: detokenize(&return_type).trim().to_string(),
+        params,
+        variadic,
+    })
+}
+
+// Splits on commas outside of nested parentheses
+fn split_top_level(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" => depth += 1,
+            Token::Symbol(s) if s == ")" => depth -= 1,
+            Token::Symbol(s) if s == "," && depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_prototypes() {
+        let functions = scan("#ifndef X_H\n#define X_H 1 \\\n  + 2\n/* doc */\nextern int printf (const char *__restrict __format, ...);\nextern FILE *fopen (const char *__filename, const char *__modes) __wur;\nint getchar (void);\ntypedef int (*cmp_fn)(const void *, const void *);\nstruct point { int x; };\nstatic inline int twice(int x) { return x * 2; }\n#endif");
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["printf", "fopen", "getchar"]);
+        assert!(functions[0].variadic && functions[0].accepts(3) && !functions[0].accepts(0));
+        assert_eq!(functions[1].params.len(), 2);
+        assert_eq!(functions[1].return_type, "FILE *");
+        assert!(functions[2].accepts(0));
+    }
+}
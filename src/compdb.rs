@@ -0,0 +1,77 @@
+// src/compdb.rs
+//
+// `--emit compile-commands`: a compilation database, `compile_commands.json`
+// next to the project, in the format clang's tools read. It says how the
+// generated C is compiled, with the compiler, include directories, defines
+// and optimization flags of the build, so clangd, clang-tidy and other
+// analyzers see `target/main.c` the way the build does.
+//
+// The database names the files of the finished build, e.g. `target/main.c`
+// rather than the scratch file a build writes first.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// How one C file is compiled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The working directory of the command, which relative paths in it
+    /// are relative to
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    /// The compiler and its arguments
+    pub arguments: Vec<String>,
+    pub output: Option<PathBuf>,
+}
+
+/// The database of `entries` as JSON
+pub fn to_json(entries: &[Entry]) -> String {
+    let mut json = String::from("[\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let arguments: Vec<String> = entry.arguments.iter().map(|a| quote(a)).collect();
+        json.push_str("  {\n");
+        writeln!(json, "    \"directory\": {},", quote(&entry.directory.display().to_string())).unwrap();
+        writeln!(json, "    \"file\": {},", quote(&entry.file.display().to_string())).unwrap();
+        if let Some(output) = &entry.output {
+            writeln!(json, "    \"output\": {},", quote(&output.display().to_string())).unwrap();
+        }
+        writeln!(json, "    \"arguments\": [{}]", arguments.join(", ")).unwrap();
+        json.push_str(if i + 1 < entries.len() { "  },\n" } else { "  }\n" });
+    }
+    json.push_str("]\n");
+    json
+}
+
+// A JSON string
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_is_json() {
+        let entry = Entry {
+            directory: PathBuf::from("/home/me/app"),
+            file: PathBuf::from("/home/me/app/target/main.c"),
+            arguments: ["gcc", "target/main.c", "-DNAME=\"app\"", "-o", "target/main"].map(str::to_string).to_vec(),
+            output: Some(PathBuf::from("target/main")),
+        };
+        assert_eq!(to_json(&[entry]), "[\n  {\n    \"directory\": \"/home/me/app\",\n    \"file\": \"/home/me/app/target/main.c\",\n    \"output\": \"target/main\",\n    \"arguments\": [\"gcc\", \"target/main.c\", \"-DNAME=\\\"app\\\"\", \"-o\", \"target/main\"]\n  }\n]\n");
+        assert_eq!(to_json(&[]), "[\n]\n");
+    }
+}
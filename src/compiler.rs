@@ -0,0 +1,506 @@
+// src/compiler.rs
+//
+// The compiler is an ordered list of named passes run over a shared
+// per-file `Context`. Embedders can insert their own passes around the
+// built-in ones through `CompilerBuilder`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::api::{Declaration, ExportApi};
+use crate::ast::{Interface, Module, Variable};
+use crate::callgraph::CallGraph;
+use crate::cfg::Cfg;
+use crate::cheader::CFunction;
+use crate::crash;
+use crate::diagnostics::Diagnostic;
+use crate::dynamic;
+use crate::edition::Edition;
+use crate::graph::ProjectGraph;
+use crate::ice::Ice;
+use crate::limits::Limits;
+use crate::links::Link;
+use crate::lints::{self, Level, Levels, Warning};
+use crate::namespaces::NamespaceTable;
+use crate::overloads::Overload;
+use crate::passes::{Arenas, Atomics, Calls, CfgAttributes, Panics, ConstEval, ConstGenerics, EmbedFiles, EntryPoint, Emit, Lex, LinkDirectives, LintLevels, Lower, Parse, Ranges, ResolveImports, OverflowChecks, RefCounting, ResolveNames, Slices, StringMatch, Temporaries, Tests, TypeCheck, UnionChecks, Unused};
+use crate::plugin::{CodegenPlugin, RunPlugins};
+use crate::pretty::Printer;
+use crate::registry::SymbolRegistry;
+use crate::timing::{measure, PassTiming};
+use crate::tokenizer::Token;
+use crate::DEBUG;
+
+/// A single step of the pipeline.
+pub trait Pass {
+    /// Unique name used for ordering and timing, e.g. `"lower"`
+    fn name(&self) -> &str;
+
+    fn run(&self, cx: &mut Context<'_>);
+}
+
+/// State shared by every file compiled in one invocation, including imports.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Every class and interface seen so far, by name
+    pub known_classes: SymbolRegistry,
+    /// Cost of each pass run, in execution order
+    pub timings: Vec<PassTiming>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// How many imports deep the file being compiled is; 0 for the root file
+    pub import_depth: usize,
+    /// Paths already imported; each file is only expanded the first time
+    pub imported: HashSet<String>,
+    /// Public declarations of every file compiled so far, dependencies first
+    pub api: Vec<Declaration>,
+    /// Prototypes from `#import <c:...>` headers, by function name
+    pub c_functions: HashMap<String, CFunction>,
+    /// Whether an `#import <c:...>` header was found in the working
+    /// directory, which the C compiler then has to search too
+    pub local_headers: bool,
+    /// Mangled names of classes that define `operator bool`
+    pub truthy_classes: HashSet<String>,
+    /// Every interface seen so far, by mangled name
+    pub interfaces: HashMap<String, Interface>,
+    /// Method names of every class, by mangled class name
+    pub methods: HashMap<String, Vec<String>>,
+    /// Mangled class each method returns, by the method's C name, for
+    /// lowering chained calls such as `v.scale(2).length()`
+    pub method_returns: HashMap<String, String>,
+    /// Mangled type each free function returns, by name, for choosing the
+    /// overload `v * g()` calls
+    pub function_returns: HashMap<String, String>,
+    /// Overloads of every binary operator so far, by the C name it has when
+    /// overloaded once, e.g. `V_operator_mul`; see `overloads`
+    pub operator_overloads: HashMap<String, Vec<Overload>>,
+    /// The `@test` functions of every file compiled so far, in a test build
+    pub tests: Vec<String>,
+    /// Enumerators of every enum seen so far, by tag and typedef name
+    pub enums: HashMap<String, Vec<String>>,
+    /// Fields of every `rc class`, by mangled class name
+    pub rc_classes: HashMap<String, Vec<Variable>>,
+    /// Mangled names of the `@reflect` classes, which `typeinfo` accepts
+    pub reflected: HashSet<String>,
+    /// Mangled names of the classes with `@derive(Pack)`
+    pub packable: HashSet<String>,
+    /// Member names read by every file so far, see `unused`
+    pub field_reads: HashSet<String>,
+    /// (file, class, field) of the fields whose reads are checked once the
+    /// root file has been seen, with the level of `unused` there
+    pub declared_fields: Vec<(String, String, String, Level)>,
+    /// C names each file compiled so far defines, by path
+    pub provides: HashMap<String, HashSet<String>>,
+    /// Calls between the functions of every file compiled so far
+    pub call_graph: CallGraph,
+    /// Imports and class outlines of every file compiled so far
+    pub graph: ProjectGraph,
+    /// C definition of every class and global emitted so far, by mangled
+    /// name, so a file reached through several import paths defines each once
+    pub emitted: HashMap<String, String>,
+    /// Members of every named namespace, whichever files declared them
+    pub namespaces: NamespaceTable,
+    /// Libraries, linker flags and pkg-config packages from the `#link`s of
+    /// every file, in the order they were seen
+    pub links: Vec<Link>,
+    /// C names of the `@export` functions of every file, which a shared
+    /// library's header declares
+    pub exports: Vec<String>,
+    /// What the debugger pretty-printers show of every class so far
+    pub printers: Vec<Printer>,
+    /// Z name and file of the functions of every file so far, by C name,
+    /// for crash reports and `lint --native`
+    pub symbols: HashMap<String, crash::Symbol>,
+    /// Text of files to compile instead of what is on disk, by path, e.g.
+    /// an editor's unsaved buffers
+    pub sources: HashMap<String, String>,
+    /// Tokens of files already lexed, by path; lexing takes them as they are
+    pub lexed: HashMap<String, Vec<Token>>,
+    /// Bytes of C the imports have spliced in so far
+    pub expanded: u64,
+    /// Hash of the source of every file compiled so far, `.zi` interfaces
+    /// too, by path, see `provenance`
+    pub source_hashes: BTreeMap<String, u64>,
+    /// The pass that panicked, which ended the compilation; see `ice`
+    pub ice: Option<Ice>,
+}
+
+impl Session {
+    /// Adds what a `#link` asks for, once
+    pub fn link(&mut self, link: Link) {
+        if !self.links.contains(&link) {
+            self.links.push(link);
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+}
+
+/// Settings that change what the pipeline produces
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Require a `main` in the root file and wrap it in the C entry point
+    pub entry_point: bool,
+    /// Check every slice index at runtime and abort with file:line when out of range
+    pub bounds_check: bool,
+    /// Abort with file:line when `+`, `-` or `*` on integer variables overflows
+    pub overflow_checks: bool,
+    /// Track the member last written to `@tagged` unions and abort with
+    /// file:line when another one is read
+    pub union_checks: bool,
+    /// Record every function entered so an uncaught panic prints a call trace
+    pub panic_trace: bool,
+    /// Install signal handlers that print the functions on the stack when
+    /// the program crashes
+    pub crash_report: bool,
+    /// Copy the lines no pass changed from the source as written instead of
+    /// re-spacing them
+    pub preserve_whitespace: bool,
+    /// Carry the source's comments into the generated C; they are dropped
+    /// otherwise
+    pub keep_comments: bool,
+    /// Edition of the program's files; the standard library always uses
+    /// the latest
+    pub edition: Edition,
+    /// Target and features `@cfg` attributes are checked against
+    pub cfg: Cfg,
+    /// The modules of `stdlib::OPTIONAL` the enabled features provide;
+    /// `None` provides them all
+    pub std_modules: Option<BTreeSet<String>>,
+    /// Where `build.z` put the files it generated, which imports and
+    /// `#embed` also look in
+    pub generated: Option<PathBuf>,
+    /// The directory holding an installed `std/`, whose modules are read
+    /// instead of the bundled ones
+    pub std_dir: Option<PathBuf>,
+    /// How much the compilation may take in, see `limits`
+    pub limits: Limits,
+    /// Build the `@test` functions and a `main` that runs them, see
+    /// `testing`; other builds leave them out
+    pub test: bool,
+    /// Levels of the lints set in `[lints]`, see `lints`
+    pub lints: Levels,
+    /// Report code at file scope the compiler doesn't recognize instead of
+    /// passing it to the C compiler, see `strict`
+    pub strict: bool,
+}
+
+impl Options {
+    /// The file `path` names: in the working directory, or else among the
+    /// generated files when it is there
+    pub fn locate(&self, path: &str) -> PathBuf {
+        match &self.generated {
+            Some(dir) if !Path::new(path).exists() && dir.join(path).exists() => dir.join(path),
+            _ => PathBuf::from(path),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { entry_point: true, bounds_check: false, overflow_checks: false, union_checks: false, panic_trace: false, crash_report: false, preserve_whitespace: false, keep_comments: false, edition: Edition::LATEST, cfg: Cfg::host(), std_modules: None, generated: None, std_dir: None, limits: Limits::default(), test: false, lints: Levels::default(), strict: false }
+    }
+}
+
+/// Per-file state threaded through the passes.
+pub struct Context<'a> {
+    compiler: &'a Compiler,
+    pub session: &'a mut Session,
+    /// Path of the file being compiled, as written in the import or on the command line
+    pub path: String,
+    pub source: String,
+    pub tokens: Vec<Token>,
+    pub module: Module,
+    /// Classes only visible inside this file (anonymous namespaces)
+    pub local_classes: HashMap<String, String>,
+    /// Class-typed variables visible to lowering
+    pub variables: Vec<Variable>,
+    /// Variables of numbers and pointers, whose types pick operator overloads
+    pub scalars: Vec<Variable>,
+    /// Vtables and type IDs the lowered code refers to
+    pub dynamic: dynamic::Uses,
+    /// Levels of the lints in this file, items aside
+    pub lints: Levels,
+    pub output: String,
+}
+
+impl<'a> Context<'a> {
+    pub fn options(&self) -> &'a Options {
+        &self.compiler.options
+    }
+
+    /// True for the file compilation started from, false for imports
+    pub fn is_root(&self) -> bool {
+        self.session.import_depth == 0
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        let diagnostic = Diagnostic::error(&self.path, message);
+        self.session.diagnostics.push(diagnostic);
+    }
+
+    pub fn warning(&mut self, message: impl Into<String>) {
+        let diagnostic = Diagnostic::warning(&self.path, message);
+        self.session.diagnostics.push(diagnostic);
+    }
+
+    /// Reports `warning` at the level its lint is at where it was found
+    pub fn lint(&mut self, warning: Warning) {
+        let level = self.lints.within(&warning.attributes).level(warning.lint);
+        if let Some(diagnostic) = lints::diagnostic(&self.path, warning.lint, level, warning.message) {
+            self.session.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Every class visible from this file: the session's plus file-private ones
+    pub fn class_names(&self) -> HashMap<String, String> {
+        let mut names = self.session.known_classes.c_names();
+        names.extend(self.local_classes.iter().map(|(k, v)| (k.clone(), v.clone())));
+        names
+    }
+
+    /// Codegen plugins registered on the compiler
+    pub fn plugins(&self) -> &'a [Box<dyn CodegenPlugin>] {
+        &self.compiler.plugins
+    }
+
+    /// Runs the full pipeline on an imported file, sharing this session
+    pub fn compile_import(&mut self, path: &str, src: &str) -> String {
+        self.session.import_depth += 1;
+        let output = self.compiler.compile_in(self.session, path, src);
+        self.session.import_depth -= 1;
+        output
+    }
+}
+
+pub struct Compiler {
+    passes: Vec<Box<dyn Pass>>,
+    plugins: Vec<Box<dyn CodegenPlugin>>,
+    options: Options,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> CompilerBuilder {
+        CompilerBuilder::new()
+    }
+
+    /// Names of the passes in execution order
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn compile(&self, src: &str) -> String {
+        self.compile_in(&mut Session::default(), "<input>", src)
+    }
+
+    pub fn compile_in(&self, session: &mut Session, path: &str, src: &str) -> String {
+        if DEBUG {println!("DEBUG: Starting compilation with {} known classes", session.known_classes.len());}
+
+        let mut cx = Context {
+            compiler: self,
+            session,
+            path: path.to_string(),
+            source: src.to_string(),
+            tokens: Vec::new(),
+            module: Module::default(),
+            local_classes: HashMap::new(),
+            variables: Vec::new(),
+            scalars: Vec::new(),
+            dynamic: dynamic::Uses::default(),
+            lints: self.options.lints.clone(),
+            output: String::new(),
+        };
+
+        for pass in &self.passes {
+            let path = cx.path.clone();
+            let nested_start = cx.session.timings.len();
+            let ran = panic::catch_unwind(AssertUnwindSafe(|| measure(&path, pass.name(), || pass.run(&mut cx))));
+            let ((), mut timing) = match ran {
+                Ok(ran) => ran,
+                Err(payload) => {
+                    cx.session.ice = Some(Ice::new(pass.name(), &cx.path, &cx.source, payload.as_ref()));
+                    return String::new();
+                }
+            };
+
+            // Imported files record their own passes; only count this file's share
+            for nested in &cx.session.timings[nested_start..] {
+                timing.duration = timing.duration.saturating_sub(nested.duration);
+                timing.allocated = timing.allocated.saturating_sub(nested.allocated);
+            }
+
+            if DEBUG {println!("DEBUG: pass {} took {:?}", pass.name(), timing.duration);}
+            cx.session.timings.push(timing);
+            // An import's pass panicked; the importer stops too
+            if cx.session.ice.is_some() {
+                return String::new();
+            }
+        }
+
+        cx.output
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CompilerBuilder {
+    passes: Vec<Box<dyn Pass>>,
+    plugins: Vec<Box<dyn CodegenPlugin>>,
+    options: Options,
+}
+
+impl CompilerBuilder {
+    /// Starts from the default pipeline
+    pub fn new() -> Self {
+        CompilerBuilder {
+            passes: vec![
+                Box::new(Lex),
+                Box::new(LintLevels),
+                Box::new(CfgAttributes),
+                Box::new(LinkDirectives),
+                Box::new(EmbedFiles),
+                Box::new(ConstGenerics),
+                Box::new(ConstEval),
+                Box::new(Slices),
+                Box::new(Ranges),
+                Box::new(StringMatch),
+                Box::new(Atomics),
+                Box::new(Panics),
+                Box::new(OverflowChecks),
+                Box::new(UnionChecks),
+                Box::new(Parse),
+                Box::new(ResolveImports),
+                Box::new(Tests),
+                Box::new(ResolveNames),
+                Box::new(TypeCheck),
+                Box::new(RefCounting),
+                Box::new(Lower),
+                Box::new(Temporaries),
+                Box::new(Unused),
+                Box::new(Calls),
+                Box::new(Arenas),
+                Box::new(EntryPoint),
+                Box::new(RunPlugins),
+                Box::new(ExportApi),
+                Box::new(Emit),
+            ],
+            plugins: Vec::new(),
+            options: Options::default(),
+        }
+    }
+
+    /// Inserts `pass` right before the pass called `name`, or at the end if there is none
+    pub fn pass_before(mut self, name: &str, pass: impl Pass + 'static) -> Self {
+        let index = self.position(name).unwrap_or(self.passes.len());
+        self.passes.insert(index, Box::new(pass));
+        self
+    }
+
+    /// Inserts `pass` right after the pass called `name`, or at the end if there is none
+    pub fn pass_after(mut self, name: &str, pass: impl Pass + 'static) -> Self {
+        let index = self.position(name).map_or(self.passes.len(), |i| i + 1);
+        self.passes.insert(index, Box::new(pass));
+        self
+    }
+
+    /// Drops the pass called `name`
+    pub fn without_pass(mut self, name: &str) -> Self {
+        self.passes.retain(|p| p.name() != name);
+        self
+    }
+
+    /// Registers a codegen plugin, run after lowering and before emission
+    pub fn plugin(mut self, plugin: impl CodegenPlugin + 'static) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Compiler {
+        Compiler {
+            passes: self.passes,
+            plugins: self.plugins,
+            options: self.options,
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|p| p.name() == name)
+    }
+}
+
+impl Default for CompilerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Banner;
+
+    impl Pass for Banner {
+        fn name(&self) -> &str {
+            "banner"
+        }
+
+        fn run(&self, cx: &mut Context<'_>) {
+            cx.output.insert_str(0, "/* generated */\n");
+        }
+    }
+
+    #[test]
+    fn test_default_pipeline_order() {
+        let compiler = Compiler::new();
+        assert_eq!(
+            compiler.pass_names(),
+            vec!["lex", "lint-levels", "cfg", "links", "embed", "const-generics", "const-eval", "slices", "ranges", "string-match", "atomics", "panics", "overflow-checks", "union-checks", "parse", "resolve-imports", "tests", "resolve-names", "type-check", "rc", "lower", "temporaries", "unused", "call-graph", "arena", "entry-point", "codegen-plugins", "export-api", "emit"]
+        );
+    }
+
+    #[test]
+    fn test_custom_pass_runs_after_emit() {
+        let compiler = Compiler::builder().pass_after("emit", Banner).build();
+        let output = compiler.compile("int x;");
+        assert!(output.starts_with("/* generated */\n"));
+    }
+
+    #[test]
+    fn test_timings_recorded_per_pass() {
+        let mut session = Session::default();
+        Compiler::new().compile_in(&mut session, "main.z", "int x;");
+        assert_eq!(session.timings.len(), 29);
+        assert_eq!(session.timings[0].pass, "lex");
+        assert_eq!(session.timings[0].module, "main.z");
+    }
+
+    #[test]
+    fn test_builtin_types_are_not_rewritten() {
+        let output = Compiler::new().compile("int main() { int a = 1; int b = a + 2; return b; }");
+        assert!(output.contains("a + 2"));
+    }
+
+    #[test]
+    fn test_operator_on_class_variable_is_lowered() {
+        let output = Compiler::new().compile(
+            "class V { int x; V operator+(V other) { return self; } }\nint main() { V a; V b; V c = a + b; }",
+        );
+        assert!(output.contains("V_operator_add(a, b)"));
+    }
+}
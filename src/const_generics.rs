@@ -0,0 +1,368 @@
+// src/const_generics.rs
+//
+// Classes parameterized by integer constants:
+//
+//     class Matrix<const N: int, const M: int> { float data[N*M]; }
+//
+//     Matrix<3, 3> identity;
+//
+// Such a class is a template, not a class. Each distinct list of arguments
+// it is used with makes one class of its own, named after the values,
+// `Matrix_3_3`, with every parameter in its body replaced by the value
+// given for it; `const-eval` then computes `data[3*3]` like any other size
+// at file scope. The instantiations take the template's place in the file,
+// in the order they are first used, each after the ones its body uses; a
+// template used by another one must come first, as a class would.
+//
+// Arguments are integer constant expressions over literals, `#define`s
+// and file-scope `const` integers, e.g. `Matrix<ROWS, ROWS * 2>`; one the
+// compiler can't compute is an error rather than a class gcc would have to
+// make sense of. Inside its body the template's name, bare or with
+// arguments, names an instantiation too: `Matrix` is the class itself and
+// `Matrix<M, N>` its transpose. A template is instantiated by the file that
+// declares it.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::consteval::{self, is_integer_word};
+use crate::parser::{is_symbol, matching_close};
+use crate::tokenizer::{detokenize, Token};
+
+// Instantiations of instantiations deeper than this are taken for endless,
+// e.g. `List<N + 1>` inside `List`
+const MAX_DEPTH: usize = 64;
+
+struct Template {
+    params: Vec<String>,
+    /// From the first word before `class`, e.g. `rc` or `@packed`, to the
+    /// name
+    head: Vec<Token>,
+    /// `{ ... }`
+    body: Vec<Token>,
+}
+
+struct Instantiator<'a> {
+    templates: &'a HashMap<String, Template>,
+    constants: HashMap<String, i64>,
+    /// Each template and argument list used so far
+    made: HashSet<(String, Vec<i64>)>,
+    /// The classes made of each template, each after those it uses
+    classes: HashMap<String, Vec<Token>>,
+    errors: Vec<String>,
+}
+
+/// Replaces each class template of `tokens` with its instantiations and
+/// each use with the instantiation's name; returns the rewritten tokens and
+/// the errors found
+pub fn instantiate(tokens: &[Token]) -> (Vec<Token>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut templates = HashMap::new();
+    // Where each template was, and its name
+    let mut spans: Vec<(usize, usize, String)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if matches!(&tokens[i], Token::Identifier(word) if word == "class") {
+            if let Some((name, template, start, end)) = template(tokens, i, &mut errors) {
+                templates.insert(name.clone(), template);
+                spans.push((start, end, name));
+                i = end + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if templates.is_empty() {
+        return (tokens.to_vec(), errors);
+    }
+
+    let mut instantiator = Instantiator { templates: &templates, constants: consteval::constants(tokens), made: HashSet::new(), classes: HashMap::new(), errors };
+    let mut out = Vec::new();
+    let mut from = 0;
+    for (start, end, name) in &spans {
+        out.extend(instantiator.uses(&tokens[from..*start], None, 0));
+        out.push(Token::Identifier(format!("\u{0}{}", name)));
+        from = end + 1;
+    }
+    out.extend(instantiator.uses(&tokens[from..], None, 0));
+
+    // Each template's placeholder becomes the classes made of it
+    let mut classes = instantiator.classes;
+    let out = out
+        .into_iter()
+        .flat_map(|token| match &token {
+            Token::Identifier(word) if word.starts_with('\u{0}') => classes.remove(&word[1..]).unwrap_or_default(),
+            _ => vec![token],
+        })
+        .collect();
+    (out, instantiator.errors)
+}
+
+// The template whose `class` is at `class`: its name, what it is, and the
+// span of tokens it covers
+fn template(tokens: &[Token], class: usize, errors: &mut Vec<String>) -> Option<(String, Template, usize, usize)> {
+    let name_index = next_code(tokens, class + 1)?;
+    let Token::Identifier(name) = &tokens[name_index] else { return None };
+    let open = next_code(tokens, name_index + 1)?;
+    if !is_symbol(tokens.get(open), "<") || !matches!(tokens.get(next_code(tokens, open + 1)?), Some(Token::Identifier(word)) if word == "const") {
+        return None;
+    }
+    let close = (open..tokens.len()).find(|&k| is_symbol(tokens.get(k), ">"))?;
+    let brace = next_code(tokens, close + 1)?;
+    if !is_symbol(tokens.get(brace), "{") {
+        return None;
+    }
+    let end = matching_close(tokens, brace)?;
+
+    let mut params = Vec::new();
+    for param in split_commas(&tokens[open + 1..close]) {
+        let code: Vec<&Token> = param.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        match code.as_slice() {
+            [Token::Identifier(keyword), Token::Identifier(param), Token::Symbol(colon), type_ @ ..] if keyword == "const" && colon == ":" && !type_.is_empty() => {
+                let words: Vec<&str> = type_.iter().map(|t| match t {
+                    Token::Identifier(word) => word.as_str(),
+                    _ => "",
+                }).collect();
+                if !words.iter().all(|word| is_integer_word(word)) {
+                    errors.push(format!("the parameter `{}` of `{}` must have an integer type, not `{}`", param, name, words.join(" ")));
+                }
+                if params.contains(param) {
+                    errors.push(format!("`{}` has two parameters named `{}`", name, param));
+                }
+                params.push(param.clone());
+            }
+            _ => {
+                errors.push(format!("a parameter of `{}` must be written `const NAME: type`", name));
+                return None;
+            }
+        }
+    }
+
+    let line = tokens[..class].iter().rposition(|t| matches!(t, Token::Newline)).map_or(0, |p| p + 1);
+    let start = attributes_above(tokens, line);
+    let head = tokens[start..=name_index].iter().filter(|t| !matches!(t, Token::Comment(_))).cloned().collect();
+    let template = Template { params, head, body: tokens[brace..=end].to_vec() };
+    Some((name.clone(), template, start, end))
+}
+
+// Where the `@attribute` lines right above `line` start, or `line`
+fn attributes_above(tokens: &[Token], line: usize) -> usize {
+    let mut start = line;
+    while start > 0 {
+        let above = tokens[..start - 1].iter().rposition(|t| matches!(t, Token::Newline)).map_or(0, |p| p + 1);
+        if !is_symbol(tokens.get(above), "@") {
+            break;
+        }
+        start = above;
+    }
+    start
+}
+
+impl Instantiator<'_> {
+    // `tokens` with every use of a template replaced by its instantiation;
+    // `inside` is the template and arguments of the class being made, if
+    // the tokens are its body
+    fn uses(&mut self, tokens: &[Token], inside: Option<(&str, &[i64])>, depth: usize) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let Token::Identifier(name) = &tokens[i] else {
+                out.push(tokens[i].clone());
+                i += 1;
+                continue;
+            };
+            let Some(template) = self.templates.get(name) else {
+                out.push(tokens[i].clone());
+                i += 1;
+                continue;
+            };
+            let open = next_code(tokens, i + 1).filter(|&k| is_symbol(tokens.get(k), "<"));
+            let close = open.and_then(|open| angle_close(tokens, open));
+            let at = i;
+            let values = match (open, close) {
+                (Some(open), Some(close)) => {
+                    let values = self.arguments(name, template, &tokens[open + 1..close]);
+                    i = close + 1;
+                    match values {
+                        Some(values) => values,
+                        None => {
+                            out.extend(tokens[at..=close].iter().cloned());
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    i += 1;
+                    match inside {
+                        Some((own, values)) if own == name => values.to_vec(),
+                        _ => {
+                            self.errors.push(format!("`{}` needs its arguments, e.g. `{}<{}>`", name, name, vec!["1"; template.params.len()].join(", ")));
+                            out.push(Token::Identifier(name.clone()));
+                            continue;
+                        }
+                    }
+                }
+            };
+            out.push(Token::Identifier(self.instantiation(name, values, depth)));
+        }
+        out
+    }
+
+    // The values of the arguments `tokens` given to `name`, or `None` after
+    // reporting why there are none
+    fn arguments(&mut self, name: &str, template: &Template, tokens: &[Token]) -> Option<Vec<i64>> {
+        let arguments = split_commas(tokens);
+        let written = || detokenize(tokens);
+        if arguments.len() != template.params.len() {
+            let plural = if template.params.len() == 1 { "" } else { "s" };
+            self.errors.push(format!("`{}` takes {} argument{} but `{}<{}>` gives {}", name, template.params.len(), plural, name, written(), arguments.len()));
+            return None;
+        }
+        let mut values = Vec::new();
+        for (argument, param) in arguments.iter().zip(&template.params) {
+            match consteval::eval(argument, &self.constants) {
+                Ok(value) => values.push(value),
+                Err(consteval::EvalError::Invalid(message)) => {
+                    self.errors.push(format!("`{}` of `{}<{}>`: {}", param, name, written(), message));
+                    return None;
+                }
+                Err(consteval::EvalError::NotConstant) => {
+                    self.errors.push(format!(
+                        "`{}` of `{}<{}>` must be a constant: a literal, a `#define` or a file-scope `const` integer",
+                        param, name, written()
+                    ));
+                    return None;
+                }
+            }
+        }
+        Some(values)
+    }
+
+    // The name of `name` instantiated with `values`, made the first time
+    fn instantiation(&mut self, name: &str, values: Vec<i64>, depth: usize) -> String {
+        let mangled = mangle(name, &values);
+        if self.made.insert((name.to_string(), values.clone())) {
+            self.class(name, &values, depth + 1);
+        }
+        mangled
+    }
+
+    // Makes the class of `name` with `values`
+    fn class(&mut self, name: &str, values: &[i64], depth: usize) {
+        let template = &self.templates[name];
+        let mangled = mangle(name, values);
+        if depth > MAX_DEPTH {
+            self.errors.push(format!("instantiating `{}` goes more than {} templates deep; does `{}` use itself with other arguments?", mangled, MAX_DEPTH, name));
+            return;
+        }
+        let mut body = Vec::new();
+        for token in &template.body {
+            match token {
+                Token::Identifier(word) => match template.params.iter().position(|p| p == word) {
+                    Some(k) if values[k] < 0 => body.extend([Token::Symbol("(".to_string()), Token::Symbol("-".to_string()), Token::Number(values[k].unsigned_abs().to_string()), Token::Symbol(")".to_string())]),
+                    Some(k) => body.push(Token::Number(values[k].to_string())),
+                    None => body.push(token.clone()),
+                },
+                _ => body.push(token.clone()),
+            }
+        }
+        let body = self.uses(&body, Some((name, values)), depth);
+        let mut class = template.head.clone();
+        class.pop();
+        class.push(Token::Identifier(mangled));
+        class.extend(body);
+        class.push(Token::Newline);
+        self.classes.entry(name.to_string()).or_default().extend(class);
+    }
+}
+
+/// The name of the class `name` makes with `values`, e.g. `Matrix_3_3`; a
+/// negative value is written with `m`, `Offset_m1`
+pub fn mangle(name: &str, values: &[i64]) -> String {
+    let mut mangled = name.to_string();
+    for value in values {
+        if *value < 0 {
+            mangled.push_str(&format!("_m{}", value.unsigned_abs()));
+        } else {
+            mangled.push_str(&format!("_{}", value));
+        }
+    }
+    mangled
+}
+
+// The `>` closing the arguments opened at `open`; one inside parentheses
+// is part of an argument, `Buffer<(A > B ? A : B)>`
+fn angle_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (k, token) in tokens.iter().enumerate().skip(open + 1) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" => depth = depth.checked_sub(1)?,
+            Token::Symbol(s) if s == ">" && depth == 0 => return Some(k),
+            Token::Symbol(s) if (s == ";" || s == "{" || s == "}") && depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut from = 0;
+    for (k, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" => depth -= 1,
+            Token::Symbol(s) if s == "," && depth == 0 => {
+                parts.push(&tokens[from..k]);
+                from = k + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[from..]);
+    parts
+}
+
+fn next_code(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&k| !matches!(tokens[k], Token::Newline | Token::Comment(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::code;
+
+    fn instantiated(source: &str) -> (String, Vec<String>) {
+        let (tokens, errors) = instantiate(&code(source));
+        (detokenize(&tokens), errors)
+    }
+
+    #[test]
+    fn test_each_argument_list_makes_a_class() {
+        let (out, errors) = instantiated(
+            "#define ROWS 3\nclass Matrix<const N: int, const M: int> { float data[N*M]; Matrix<M, N> transpose(); int rows(){ return N; } }\nMatrix<ROWS, 3> a; Matrix<2, ROWS> b;",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            out,
+            detokenize(&code(
+                "#define ROWS 3\nclass Matrix_3_3 { float data[3*3]; Matrix_3_3 transpose(); int rows(){ return 3; } }\nclass Matrix_3_2 { float data[3*2]; Matrix_2_3 transpose(); int rows(){ return 3; } }\nclass Matrix_2_3 { float data[2*3]; Matrix_3_2 transpose(); int rows(){ return 2; } }\n\nMatrix_3_3 a; Matrix_2_3 b;"
+            ))
+        );
+        assert_eq!(mangle("Offset", &[-1, 2]), "Offset_m1_2");
+
+        let (_, errors) = instantiated("class V<const N: int> { float x[N]; }\nint f(int n){ V<n> v; V<1, 2> w; V u; }");
+        assert_eq!(
+            errors,
+            vec![
+                "`N` of `V<n>` must be a constant: a literal, a `#define` or a file-scope `const` integer",
+                "`V` takes 1 argument but `V<1, 2>` gives 2",
+                "`V` needs its arguments, e.g. `V<1>`",
+            ]
+        );
+        let (_, errors) = instantiated("class List<const N: int> { List<N + 1> next; }\nList<0> l;");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("instantiating `List_64` goes more than 64 templates deep"), "{:?}", errors);
+    }
+}
@@ -0,0 +1,797 @@
+// src/consteval.rs
+//
+// Integer constant expressions. Array sizes outside of function bodies and
+// explicit enumerator values are computed by the compiler, so
+// `int buf[SIZE * 2];` works at file scope with `const int SIZE = 16;` and
+// a size or value that can't be right (a division by zero, a negative size,
+// an enumerator that doesn't fit in an `int`) is reported before gcc runs.
+//
+// Names come from `#define NAME <expr>`, file-scope `const` integers and
+// enumerators, in source order. `sizeof(T)` and `alignof(T)` are known for
+// primitives, pointers, arrays and classes whose fields are. An expression
+// using anything else (a call, a variable, a macro from a header) isn't
+// constant to the compiler and is left for gcc.
+
+use std::collections::HashMap;
+
+use crate::tokenizer::{is_macro_space, tokenize, Token};
+
+/// Why an expression has no value
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// It uses something only gcc knows, e.g. `sizeof` or an unknown name
+    NotConstant,
+    /// It is constant but invalid, e.g. `1 / 0`
+    Invalid(String),
+}
+
+/// Size and alignment of a type, in bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub size: i64,
+    pub align: i64,
+}
+
+/// Evaluates an integer constant expression, looking names up in `constants`
+pub fn eval(tokens: &[Token], constants: &HashMap<String, i64>) -> Result<i64, EvalError> {
+    evaluate(tokens, constants, &|_: &str| None)
+}
+
+// `eval`, with `classes` giving the layout of a class by its name as written
+fn evaluate(tokens: &[Token], constants: &HashMap<String, i64>, classes: &dyn Fn(&str) -> Option<Layout>) -> Result<i64, EvalError> {
+    let tokens: Vec<&Token> = tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    let mut parser = Parser { tokens: &tokens, position: 0, constants, classes };
+    let value = parser.conditional()?;
+    if parser.position < tokens.len() {
+        return Err(EvalError::NotConstant);
+    }
+    Ok(value)
+}
+
+/// Computes array sizes and enumerator values; returns the rewritten tokens
+/// and the errors found
+pub fn rewrite(tokens: &[Token]) -> (Vec<Token>, Vec<String>) {
+    let mut rewriter = Rewriter { constants: HashMap::new(), layouts: HashMap::new(), errors: Vec::new(), braces: Vec::new() };
+    let out = rewriter.rewrite(tokens);
+    (out, rewriter.errors)
+}
+
+/// The `#define`s and file-scope `const` integers of `tokens` with a
+/// constant value, as they stand at the end of the file
+pub fn constants(tokens: &[Token]) -> HashMap<String, i64> {
+    let mut rewriter = Rewriter { constants: HashMap::new(), layouts: HashMap::new(), errors: Vec::new(), braces: Vec::new() };
+    rewriter.rewrite(tokens);
+    rewriter.constants
+}
+
+struct Rewriter {
+    constants: HashMap<String, i64>,
+    // Classes by their qualified name, e.g. `geo::Point`
+    layouts: HashMap<String, Layout>,
+    errors: Vec<String>,
+    braces: Vec<Brace>,
+}
+
+// What an open `{` belongs to
+enum Brace {
+    /// A function body, or anything inside one
+    Body,
+    Namespace(String),
+    Other,
+}
+
+impl Rewriter {
+    fn in_function(&self) -> bool {
+        matches!(self.braces.last(), Some(Brace::Body))
+    }
+
+    fn eval(&self, tokens: &[Token]) -> Result<i64, EvalError> {
+        evaluate(tokens, &self.constants, &|name| self.class_layout(name))
+    }
+
+    // A class named from the current namespace: `Point` inside `geo` is `geo::Point`
+    fn class_layout(&self, name: &str) -> Option<Layout> {
+        let namespaces: Vec<&str> = self.braces.iter().filter_map(|b| match b { Brace::Namespace(n) => Some(n.as_str()), _ => None }).collect();
+        (0..=namespaces.len()).rev().find_map(|depth| {
+            let mut qualified: Vec<&str> = namespaces[..depth].to_vec();
+            qualified.push(name);
+            self.layouts.get(&qualified.join("::")).copied()
+        })
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        let mut parts: Vec<&str> = self.braces.iter().filter_map(|b| match b { Brace::Namespace(n) => Some(n.as_str()), _ => None }).collect();
+        parts.push(name);
+        parts.join("::")
+    }
+
+    fn rewrite(&mut self, tokens: &[Token]) -> Vec<Token> {
+        let mut out: Vec<Token> = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                Token::Symbol(s) if s == "#" && at_line_start(&out) => self.directive(tokens, i),
+                Token::Symbol(s) if s == "{" => {
+                    let code: Vec<&Token> = out.iter().rev().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).take(2).collect();
+                    let brace = match code.as_slice() {
+                        _ if self.in_function() => Brace::Body,
+                        [Token::Symbol(p), ..] if p == ")" => Brace::Body,
+                        [Token::Identifier(name), Token::Identifier(keyword)] if keyword == "namespace" => Brace::Namespace(name.clone()),
+                        _ => Brace::Other,
+                    };
+                    self.braces.push(brace);
+                }
+                Token::Symbol(s) if s == "}" => {
+                    self.braces.pop();
+                }
+                Token::Identifier(word) if word == "const" && !self.in_function() => self.const_declaration(tokens, i),
+                Token::Identifier(word) if (word == "class" || word == "union") && !self.in_function() => self.class(tokens, i, &out),
+                Token::Identifier(word) if word == "enum" => {
+                    if let Some(end) = self.enumeration(tokens, i, &mut out) {
+                        i = end;
+                        continue;
+                    }
+                }
+                Token::Symbol(s) if s == "[" && !self.in_function() && is_declarator(&out) => {
+                    if let Some(close) = matching_bracket(tokens, i) {
+                        out.push(tokens[i].clone());
+                        let size = &tokens[i + 1..close];
+                        out.extend(self.array_size(size, &out));
+                        out.push(tokens[close].clone());
+                        i = close + 1;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            out.push(tokens[i].clone());
+            i += 1;
+        }
+        // What's left for gcc: C11 spells `alignof` as `_Alignof`
+        for k in 0..out.len() {
+            if matches!(&out[k], Token::Identifier(word) if word == "alignof") && matches!(out.get(k + 1), Some(Token::Symbol(s)) if s == "(") {
+                out[k] = Token::Identifier("_Alignof".to_string());
+            }
+        }
+        out
+    }
+
+    // `#define NAME expr` and `#undef NAME`
+    fn directive(&mut self, tokens: &[Token], hash: usize) {
+        let end = (hash..tokens.len()).find(|&k| matches!(tokens[k], Token::Newline)).unwrap_or(tokens.len());
+        match &tokens[hash + 1..end] {
+            [Token::Identifier(directive), Token::Identifier(name), value @ ..] if directive == "define" => {
+                // `#define F(x) ...` takes arguments, unlike `#define F (x)`
+                let value = match value.split_first() {
+                    Some((space, value)) if is_macro_space(space) => value,
+                    Some((Token::Symbol(s), _)) if s == "(" => {
+                        self.constants.remove(name);
+                        return;
+                    }
+                    _ => value,
+                };
+                match self.eval(value) {
+                    Ok(v) if !value.is_empty() => {
+                        self.constants.insert(name.clone(), v);
+                    }
+                    _ => {
+                        self.constants.remove(name);
+                    }
+                }
+            }
+            [Token::Identifier(directive), Token::Identifier(name), ..] if directive == "undef" => {
+                self.constants.remove(name);
+            }
+            _ => {}
+        }
+    }
+
+    // `const int NAME = expr;` outside of functions
+    fn const_declaration(&mut self, tokens: &[Token], start: usize) {
+        let end = (start..tokens.len()).find(|&k| matches!(&tokens[k], Token::Symbol(s) if s == ";" || s == "{")).unwrap_or(tokens.len());
+        let code: Vec<&Token> = tokens[start + 1..end].iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        let Some(assign) = code.iter().position(|t| matches!(t, Token::Symbol(s) if s == "=")) else { return };
+        let [type_ @ .., Token::Identifier(name)] = &code[..assign] else { return };
+        if type_.is_empty() || !type_.iter().all(|t| matches!(t, Token::Identifier(word) if is_integer_word(word))) {
+            return;
+        }
+        let value: Vec<Token> = code[assign + 1..].iter().map(|t| (*t).clone()).collect();
+        match self.eval(&value) {
+            Ok(v) => {
+                self.constants.insert(name.clone(), v);
+            }
+            Err(EvalError::Invalid(message)) => self.errors.push(format!("`{}`: {}", name, message)),
+            Err(EvalError::NotConstant) => {}
+        }
+    }
+
+    // `[rc] class Name { ... }` or `union Name { ... }`: records the layout
+    // of the class's struct
+    fn class(&mut self, tokens: &[Token], start: usize, out: &[Token]) {
+        let Some(name_index) = next_code(tokens, start + 1) else { return };
+        let Token::Identifier(name) = &tokens[name_index] else { return };
+        let Some(open) = next_code(tokens, name_index + 1).filter(|&k| matches!(&tokens[k], Token::Symbol(s) if s == "{")) else { return };
+        let qualified = self.qualified(name);
+        // An rc class is a handle to its heap block
+        if matches!(last_code(out), Some(Token::Identifier(word)) if word == "rc") {
+            self.layouts.insert(qualified, Layout { size: 8, align: 8 });
+            return;
+        }
+        let union = matches!(&tokens[start], Token::Identifier(word) if word == "union");
+        let (mut before, mut packed, mut align) = (out, false, None);
+        while let Some((name, args, start)) = attribute_before(before) {
+            match name.as_str() {
+                "packed" => packed = true,
+                "align" => align = Some(self.eval(args)),
+                // `--union-checks` adds a tag; leave it to gcc
+                "tagged" => {
+                    self.layouts.remove(&qualified);
+                    return;
+                }
+                _ => {}
+            }
+            before = &before[..start];
+        }
+        let Some(close) = matching_bracket(tokens, open) else { return };
+        let align = match align {
+            Some(Ok(align)) if align > 0 && align.count_ones() == 1 => Some(align),
+            // The type checker reports it
+            Some(_) => {
+                self.layouts.remove(&qualified);
+                return;
+            }
+            None => None,
+        };
+        match self.struct_layout(&tokens[open + 1..close], union, packed, align) {
+            Some(layout) => self.layouts.insert(qualified, layout),
+            None => self.layouts.remove(&qualified),
+        };
+    }
+
+    // The fields of a class body laid out in order, as C lays out a struct,
+    // or on top of each other for a union
+    fn struct_layout(&self, body: &[Token], union: bool, packed: bool, align: Option<i64>) -> Option<Layout> {
+        let mut fields: Vec<Vec<Token>> = Vec::new();
+        let mut field = Vec::new();
+        let mut k = 0;
+        while k < body.len() {
+            match &body[k] {
+                // A method: its body and everything before it
+                Token::Symbol(s) if s == "{" => {
+                    k = matching_bracket(body, k)?;
+                    field.clear();
+                }
+                Token::Symbol(s) if s == ";" => fields.push(std::mem::take(&mut field)),
+                Token::Newline | Token::Comment(_) => {}
+                token => field.push(token.clone()),
+            }
+            k += 1;
+        }
+        let mut layout = Layout { size: 0, align: 1 };
+        for field in fields.iter().filter(|f| !f.iter().any(|t| matches!(t, Token::Symbol(s) if s == "("))) {
+            // Only `Type name;` fields make it into the struct; leave anything else to gcc
+            let simple = field.iter().all(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "*"));
+            let (Some(Token::Identifier(_)), true) = (field.last(), simple && field.len() > 1) else { return None };
+            let member = type_layout(&field[..field.len() - 1], &self.constants, &|name| self.class_layout(name)).ok()?;
+            // `@packed` fields follow each other without padding
+            let member_align = if packed { 1 } else { member.align };
+            layout.size = if union { layout.size.max(member.size) } else { round_up(layout.size, member_align) + member.size };
+            layout.align = layout.align.max(member_align);
+        }
+        layout.align = layout.align.max(align.unwrap_or(1));
+        layout.size = round_up(layout.size, layout.align);
+        Some(layout)
+    }
+
+    // `enum [tag] { A, B = expr, ... }`: records every enumerator and writes
+    // the explicit values out computed. Returns the index after the `}`.
+    fn enumeration(&mut self, tokens: &[Token], start: usize, out: &mut Vec<Token>) -> Option<usize> {
+        let open = (start + 1..tokens.len()).find(|&k| !matches!(&tokens[k], Token::Newline | Token::Comment(_) | Token::Identifier(_)))?;
+        if !matches!(&tokens[open], Token::Symbol(s) if s == "{") {
+            return None;
+        }
+        let close = matching_bracket(tokens, open)?;
+        out.extend(tokens[start..=open].iter().cloned());
+
+        // The implicit value of the next enumerator, unless it depends on one only gcc knows
+        let mut next = Some(0i64);
+        let entries = split_commas(&tokens[open + 1..close]);
+        for (index, entry) in entries.iter().enumerate() {
+            let code: Vec<usize> = (0..entry.len()).filter(|&k| !matches!(entry[k], Token::Newline | Token::Comment(_))).collect();
+            let Some(Token::Identifier(name)) = code.first().map(|&k| &entry[k]) else {
+                out.extend(entry.iter().cloned());
+                continue;
+            };
+            let assign = code.iter().position(|&k| matches!(&entry[k], Token::Symbol(s) if s == "="));
+            let value = match assign {
+                Some(position) => {
+                    let expression = &entry[code[position] + 1..];
+                    out.extend(entry[..=code[position]].iter().cloned());
+                    match self.eval(expression) {
+                        Ok(v) => {
+                            out.extend(leading_space(expression));
+                            out.extend(literal(v));
+                            out.extend(trailing_trivia(expression));
+                            Some(v)
+                        }
+                        Err(error) => {
+                            if let EvalError::Invalid(message) = error {
+                                self.errors.push(format!("enumerator `{}`: {}", name, message));
+                            }
+                            out.extend(expression.iter().cloned());
+                            None
+                        }
+                    }
+                }
+                None => {
+                    out.extend(entry.iter().cloned());
+                    next
+                }
+            };
+            match value {
+                Some(v) if i32::try_from(v).is_err() => {
+                    self.errors.push(format!("enumerator `{}` is {}, which does not fit in an `int`", name, v));
+                    self.constants.remove(name);
+                    next = None;
+                }
+                Some(v) => {
+                    self.constants.insert(name.clone(), v);
+                    next = Some(v + 1);
+                }
+                None => {
+                    self.constants.remove(name);
+                    next = None;
+                }
+            }
+            if index + 1 < entries.len() {
+                out.push(Token::Symbol(",".to_string()));
+            }
+        }
+        out.push(tokens[close].clone());
+        Some(close + 1)
+    }
+
+    // The size between `[` and `]` of an array declarator, computed
+    fn array_size(&mut self, size: &[Token], out: &[Token]) -> Vec<Token> {
+        let code: Vec<&Token> = size.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        if code.is_empty() || matches!(code.as_slice(), [Token::Number(_)]) {
+            return size.to_vec();
+        }
+        let name = match last_code(out.strip_suffix(&[Token::Symbol("[".to_string())]).unwrap_or(out)) {
+            Some(Token::Identifier(name)) => name.clone(),
+            _ => "array".to_string(),
+        };
+        match self.eval(size) {
+            Ok(v) if v < 0 => {
+                self.errors.push(format!("array `{}` has a negative size ({})", name, v));
+                size.to_vec()
+            }
+            Ok(v) => literal(v),
+            Err(EvalError::Invalid(message)) => {
+                self.errors.push(format!("size of array `{}`: {}", name, message));
+                size.to_vec()
+            }
+            Err(EvalError::NotConstant) => size.to_vec(),
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [&'a Token],
+    position: usize,
+    constants: &'a HashMap<String, i64>,
+    classes: &'a dyn Fn(&str) -> Option<Layout>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if self.peek() == Some(symbol) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    // `c ? a : b`
+    fn conditional(&mut self) -> Result<i64, EvalError> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return Err(EvalError::NotConstant);
+        }
+        let otherwise = self.conditional()?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    // Binary operators by C precedence, loosest first
+    fn binary(&mut self, level: usize) -> Result<i64, EvalError> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek().filter(|op| LEVELS[level].contains(op)).map(str::to_string) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = apply(&op, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, EvalError> {
+        for op in ["-", "+", "~", "!"] {
+            if self.eat(op) {
+                let value = self.unary()?;
+                return match op {
+                    "-" => value.checked_neg().ok_or_else(|| overflow("-", value, 0)),
+                    "+" => Ok(value),
+                    "~" => Ok(!value),
+                    _ => Ok((value == 0) as i64),
+                };
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, EvalError> {
+        if self.eat("(") {
+            let value = self.conditional()?;
+            return if self.eat(")") { Ok(value) } else { Err(EvalError::NotConstant) };
+        }
+        if let Some(Token::Identifier(query)) = self.tokens.get(self.position) {
+            if matches!(query.as_str(), "sizeof" | "alignof" | "_Alignof") {
+                return self.type_query(query == "sizeof");
+            }
+        }
+        let value = match self.tokens.get(self.position) {
+            Some(Token::Number(number)) => parse_number(number)?,
+            Some(Token::CharLit(literal)) => parse_char(literal).ok_or(EvalError::NotConstant)?,
+            Some(Token::Identifier(name)) => *self.constants.get(name).ok_or(EvalError::NotConstant)?,
+            _ => return Err(EvalError::NotConstant),
+        };
+        self.position += 1;
+        // `10u`, `1UL`: the tokenizer splits off the suffix
+        if matches!(self.tokens.get(self.position), Some(Token::Identifier(suffix)) if is_integer_suffix(suffix)) {
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    // `sizeof(T)` or `alignof(T)` of a type whose layout is known;
+    // `sizeof x` and `sizeof(x)` of a variable are left to gcc
+    fn type_query(&mut self, size: bool) -> Result<i64, EvalError> {
+        self.position += 1;
+        if !self.eat("(") {
+            return Err(EvalError::NotConstant);
+        }
+        let start = self.position;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.tokens.get(self.position) {
+                Some(Token::Symbol(s)) if s == "(" => depth += 1,
+                Some(Token::Symbol(s)) if s == ")" => depth -= 1,
+                Some(_) => {}
+                None => return Err(EvalError::NotConstant),
+            }
+            self.position += 1;
+        }
+        let type_: Vec<Token> = self.tokens[start..self.position - 1].iter().map(|t| (*t).clone()).collect();
+        let layout = type_layout(&type_, self.constants, self.classes)?;
+        Ok(if size { layout.size } else { layout.align })
+    }
+}
+
+/// The layout of a type as gcc lays it out on 64-bit Linux and macOS:
+/// primitives, pointers, arrays and classes known to `classes`
+fn type_layout(type_: &[Token], constants: &HashMap<String, i64>, classes: &dyn Fn(&str) -> Option<Layout>) -> Result<Layout, EvalError> {
+    let code: Vec<&Token> = type_.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    // `T[N]` is N `T`s, so `T[2][3]` is 3 `T[2]`s
+    if matches!(code.last(), Some(Token::Symbol(s)) if s == "]") {
+        let open = code.iter().rposition(|t| matches!(t, Token::Symbol(s) if s == "[")).ok_or(EvalError::NotConstant)?;
+        let element: Vec<Token> = code[..open].iter().map(|t| (*t).clone()).collect();
+        let count: Vec<Token> = code[open + 1..code.len() - 1].iter().map(|t| (*t).clone()).collect();
+        let count = evaluate(&count, constants, classes)?;
+        let layout = type_layout(&element, constants, classes)?;
+        let size = layout.size.checked_mul(count).ok_or_else(|| EvalError::Invalid("array size overflows".to_string()))?;
+        return Ok(Layout { size, align: layout.align });
+    }
+    if code.iter().any(|t| matches!(t, Token::Symbol(s) if s == "*")) {
+        return Ok(Layout { size: 8, align: 8 });
+    }
+    let words: Vec<&str> = code
+        .iter()
+        .filter_map(|t| match t {
+            Token::Identifier(word) if !matches!(word.as_str(), "const" | "volatile" | "static") => Some(word.as_str()),
+            _ => None,
+        })
+        .collect();
+    if let Some(size) = primitive_size(&words) {
+        return Ok(Layout { size, align: size });
+    }
+    match words.as_slice() {
+        ["enum", _] => Ok(Layout { size: 4, align: 4 }),
+        ["struct", name] => classes(name).ok_or(EvalError::NotConstant),
+        _ if code.iter().all(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "::")) => {
+            classes(&words.join("::")).ok_or(EvalError::NotConstant)
+        }
+        _ => Err(EvalError::NotConstant),
+    }
+}
+
+fn primitive_size(words: &[&str]) -> Option<i64> {
+    let size = match words {
+        [] => return None,
+        ["bool" | "_Bool" | "int8_t" | "uint8_t"] => 1,
+        ["int16_t" | "uint16_t"] => 2,
+        ["float" | "int32_t" | "uint32_t"] => 4,
+        ["double" | "size_t" | "ssize_t" | "ptrdiff_t" | "intptr_t" | "uintptr_t" | "int64_t" | "uint64_t"] => 8,
+        ["long", "double"] => 16,
+        _ if words.iter().all(|w| matches!(*w, "signed" | "unsigned" | "char" | "short" | "int" | "long")) => {
+            if words.contains(&"char") {
+                1
+            } else if words.contains(&"short") {
+                2
+            } else if words.contains(&"long") {
+                8
+            } else {
+                4
+            }
+        }
+        _ => return None,
+    };
+    Some(size)
+}
+
+fn apply(op: &str, a: i64, b: i64) -> Result<i64, EvalError> {
+    let checked = match op {
+        "+" => a.checked_add(b),
+        "-" => a.checked_sub(b),
+        "*" => a.checked_mul(b),
+        "/" | "%" if b == 0 => return Err(EvalError::Invalid("division by zero".to_string())),
+        "/" => a.checked_div(b),
+        "%" => a.checked_rem(b),
+        "<<" | ">>" if !(0..64).contains(&b) => {
+            return Err(EvalError::Invalid(format!("shift by {} is out of range", b)));
+        }
+        "<<" => a.checked_shl(b as u32).filter(|v| v >> b == a),
+        ">>" => Some(a >> b),
+        "&" => Some(a & b),
+        "|" => Some(a | b),
+        "^" => Some(a ^ b),
+        "==" => Some((a == b) as i64),
+        "!=" => Some((a != b) as i64),
+        "<" => Some((a < b) as i64),
+        ">" => Some((a > b) as i64),
+        "<=" => Some((a <= b) as i64),
+        ">=" => Some((a >= b) as i64),
+        "&&" => Some((a != 0 && b != 0) as i64),
+        _ => Some((a != 0 || b != 0) as i64),
+    };
+    checked.ok_or_else(|| overflow(op, a, b))
+}
+
+fn overflow(op: &str, a: i64, b: i64) -> EvalError {
+    if op == "-" && b == 0 {
+        return EvalError::Invalid(format!("`-{}` overflows", a));
+    }
+    EvalError::Invalid(format!("`{} {} {}` overflows", a, op, b))
+}
+
+fn parse_number(literal: &str) -> Result<i64, EvalError> {
+    // `u` and `l` suffixes don't change the value
+    let number = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    let parsed = if let Some(hex) = number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else if number.contains(['.', 'e', 'E', 'f', 'F']) {
+        return Err(EvalError::NotConstant);
+    } else if number.len() > 1 && number.starts_with('0') {
+        i64::from_str_radix(&number[1..], 8)
+    } else {
+        number.parse()
+    };
+    parsed.map_err(|_| EvalError::Invalid(format!("`{}` is too large", literal)))
+}
+
+fn parse_char(literal: &str) -> Option<i64> {
+    let inner = literal.strip_prefix('\'')?.strip_suffix('\'')?;
+    let value = match inner.as_bytes() {
+        [c] => *c,
+        [b'\\', b'n'] => b'\n',
+        [b'\\', b't'] => b'\t',
+        [b'\\', b'r'] => b'\r',
+        [b'\\', b'0'] => 0,
+        [b'\\', c @ (b'\\' | b'\'' | b'"')] => *c,
+        _ => return None,
+    };
+    Some(value as i64)
+}
+
+fn is_integer_suffix(word: &str) -> bool {
+    matches!(word.to_ascii_lowercase().as_str(), "u" | "l" | "ul" | "lu" | "ll" | "ull" | "llu")
+}
+
+pub(crate) fn is_integer_word(word: &str) -> bool {
+    matches!(
+        word,
+        "static" | "unsigned" | "signed" | "char" | "short" | "int" | "long" | "size_t" | "int8_t" | "int16_t" | "int32_t" | "int64_t"
+            | "uint8_t" | "uint16_t" | "uint32_t" | "uint64_t"
+    )
+}
+
+// Whether a `[` following `out` starts an array declarator: `T name[`,
+// `T *name[` or a further dimension `T name[N][`
+fn is_declarator(out: &[Token]) -> bool {
+    let code: Vec<&Token> = out.iter().rev().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).take(2).collect();
+    match code.as_slice() {
+        [Token::Symbol(close), ..] if close == "]" => {
+            // Only a further dimension if the brackets before were a declarator too
+            matching_open(out, "[", "]").is_some_and(|open| is_declarator(&out[..open]))
+        }
+        [Token::Identifier(_), Token::Identifier(before)] => !matches!(before.as_str(), "return" | "case" | "sizeof" | "else" | "goto" | "do"),
+        [Token::Identifier(_), Token::Symbol(star)] if star == "*" => {
+            // `T *name[` but not `a * b[`: the `*` follows a type
+            let before: Vec<&Token> = out.iter().rev().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).skip(2).take(2).collect();
+            matches!(before.as_slice(), [Token::Identifier(word), ..] if !matches!(word.as_str(), "return" | "case"))
+                || matches!(before.as_slice(), [Token::Symbol(star), ..] if star == "*")
+        }
+        _ => false,
+    }
+}
+
+// The bracket opening the one `tokens` ends with
+fn matching_open(tokens: &[Token], open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().rev() {
+        match token {
+            Token::Symbol(s) if s == close => depth += 1,
+            Token::Symbol(s) if s == open => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The `@name` or `@name(args)` attribute that `tokens` end with: its name,
+// its arguments and where it starts
+fn attribute_before(tokens: &[Token]) -> Option<(String, &[Token], usize)> {
+    let end = tokens.iter().rposition(|t| !matches!(t, Token::Newline | Token::Comment(_)))?;
+    let (name_index, args) = match &tokens[end] {
+        Token::Symbol(s) if s == ")" => {
+            let open = matching_open(&tokens[..=end], "(", ")")?;
+            (open.checked_sub(1)?, &tokens[open + 1..end])
+        }
+        _ => (end, &tokens[end..end]),
+    };
+    match (&tokens[name_index], name_index.checked_sub(1).map(|k| &tokens[k])) {
+        (Token::Identifier(name), Some(Token::Symbol(at))) if at == "@" => Some((name.clone(), args, name_index - 1)),
+        _ => None,
+    }
+}
+
+fn round_up(value: i64, align: i64) -> i64 {
+    (value + align - 1) / align * align
+}
+
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            Token::Symbol(s) if s == "," && depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+fn matching_bracket(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn next_code(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&i| !matches!(tokens[i], Token::Newline | Token::Comment(_)))
+}
+
+fn last_code(tokens: &[Token]) -> Option<&Token> {
+    tokens.iter().rev().find(|t| !matches!(t, Token::Newline | Token::Comment(_)))
+}
+
+fn at_line_start(out: &[Token]) -> bool {
+    matches!(out.last(), None | Some(Token::Newline))
+}
+
+fn leading_space(tokens: &[Token]) -> Vec<Token> {
+    tokens.iter().take_while(|t| matches!(t, Token::Newline | Token::Comment(_))).cloned().collect()
+}
+
+fn trailing_trivia(tokens: &[Token]) -> Vec<Token> {
+    let code_end = tokens.iter().rposition(|t| !matches!(t, Token::Newline | Token::Comment(_))).map_or(0, |p| p + 1);
+    tokens[code_end..].to_vec()
+}
+
+fn literal(value: i64) -> Vec<Token> {
+    tokenize(&value.to_string()).into_iter().filter(|t| !matches!(t, Token::Eof)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::detokenize;
+
+    fn value(source: &str) -> Result<i64, EvalError> {
+        let constants = HashMap::from([("N".to_string(), 4)]);
+        eval(&tokenize(source).into_iter().filter(|t| !matches!(t, Token::Eof)).collect::<Vec<_>>(), &constants)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(value("N * 2 + 1"), Ok(9));
+        assert_eq!(value("1 << 4 | 1 << 0"), Ok(17));
+        assert_eq!(value("(N > 2 ? 0x10 : 010) - 'a' % 10u"), Ok(9));
+        assert_eq!(value("-N / 3 + !0 + ~0"), Ok(-1));
+        assert_eq!(value("N / (N - 4)"), Err(EvalError::Invalid("division by zero".to_string())));
+        assert_eq!(value("1 << 64"), Err(EvalError::Invalid("shift by 64 is out of range".to_string())));
+        assert_eq!(value("sizeof(int) * N + alignof(char*)"), Ok(24));
+        assert_eq!(value("sizeof(x) * N"), Err(EvalError::NotConstant));
+        assert_eq!(value("M + 1"), Err(EvalError::NotConstant));
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let source = "#define SIZE 8\nconst int ROWS = SIZE / 2;\nint grid[ROWS][SIZE * 2];\nenum flags { A = 1 << 0, B = 1 << 1, C, D = A | B };\nint f(int n) { int local[n + 1]; return grid[0][n * 2]; }\nint bad[ROWS - 5];";
+        let (tokens, errors) = rewrite(&tokenize(source));
+        let output = detokenize(&tokens);
+        assert!(output.contains("int grid[4][16];"));
+        assert!(output.contains("enum flags { A = 1, B = 2, C, D = 3 };"));
+        assert!(output.contains("int local[n + 1]; return grid[0][n * 2];"));
+        assert_eq!(errors, vec!["array `bad` has a negative size (-1)"]);
+    }
+
+    #[test]
+    fn test_layouts() {
+        let source = "class Pair { int a; int b; }\nnamespace geo { class Point { short x; char c; } }\nclass V { double x; char tag; Pair p; V* next; int len(){ return 0; } }\nrc class Node { int v; }\nclass Odd { int xs[4]; }\nchar a[sizeof(V)];\nchar b[alignof(geo::Point)];\nchar c[sizeof(Node) + sizeof(long double[2][3])];\nchar d[sizeof(Odd)];\nchar e[alignof(Odd)];";
+        let (tokens, errors) = rewrite(&tokenize(source));
+        let output = detokenize(&tokens);
+        assert!(errors.is_empty());
+        assert!(output.contains("char a[32];\nchar b[2];\nchar c[104];"));
+        // The parser keeps only `Type name;` fields, so gcc sizes `Odd`
+        assert!(output.contains("char d[sizeof(Odd)];\nchar e[_Alignof(Odd)];"));
+    }
+}
@@ -0,0 +1,216 @@
+// src/crash.rs
+//
+// `--crash-report`: a program that crashes says where instead of dying
+// with a bare "Segmentation fault". The generated C installs handlers for
+// the fatal signals before `main` runs; a crash prints what happened and
+// the functions on the stack by their Z names, innermost first:
+//
+//     crash: segmentation fault (invalid memory access)
+//       in geo::Polygon::area (geo.z)
+//       in report (main.z)
+//       in main (main.z)
+//
+// The addresses of the stack are looked up in a table of every function of
+// the program, written at the end of the generated C where all of them are
+// defined. An address belongs to the function starting closest below it.
+// The handler runs on a stack of its own, so a stack overflow is reported
+// too, and then the signal kills the program as it would have without it.
+//
+// Backtraces need `<execinfo.h>` (glibc, macOS and the BSDs) or Windows;
+// elsewhere only the kind of crash is printed.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ast::{Item, Module};
+use crate::parser::function_definitions;
+use crate::tokenizer::Token;
+
+/// What a crash report says of a C function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    /// As written in Z, e.g. `geo::Point::area`
+    pub name: String,
+    pub file: String,
+}
+
+/// The Z names of the functions `module` defines, by C name. Functions
+/// the compiler generates keep their C names
+pub fn names(module: &Module) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    names_in(&module.items, None, None, &mut names);
+    names
+}
+
+fn names_in(items: &[Item], path: Option<&str>, prefix: Option<&str>, names: &mut HashMap<String, String>) {
+    let qualified = |name: &str| match path {
+        Some(path) => format!("{}::{}", path, name),
+        None => name.to_string(),
+    };
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => {
+                let path = namespace.path.as_deref().or(path);
+                names_in(&namespace.items, path, namespace.prefix.as_deref(), names);
+            }
+            Item::Class(class) => {
+                let class_name = qualified(&class.name);
+                for function in &class.functions {
+                    names.insert(format!("{}_{}", class.full_name(), function.name), format!("{}::{}", class_name, function.name));
+                }
+                for operator in &class.operators {
+                    names.insert(operator.full_name(), format!("{}::operator{}", class_name, operator.operator));
+                }
+            }
+            Item::Raw(tokens) => {
+                for definition in function_definitions(tokens) {
+                    let c_name = definition.name(tokens);
+                    // Lowered already: `area` of `geo` is `geo_area` here
+                    let name = prefix.and_then(|p| c_name.strip_prefix(p)).and_then(|rest| rest.strip_prefix('_')).unwrap_or(c_name);
+                    // The program's `main`, which the generated one calls
+                    let name = if c_name == "__z_main" { "main" } else { name };
+                    names.insert(c_name.to_string(), qualified(name));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The symbol table of the functions defined in `tokens`, the whole
+/// program, and the handlers that print crash reports with it
+pub fn runtime(tokens: &[Token], symbols: &HashMap<String, Symbol>) -> String {
+    let mut table = String::new();
+    let mut seen = Vec::new();
+    let definitions = function_definitions(tokens);
+    let wrapped = definitions.iter().any(|d| d.name(tokens) == "__z_main");
+    for definition in definitions {
+        let c_name = definition.name(tokens);
+        if definition.body_end(tokens).is_none() || seen.contains(&c_name) {
+            continue;
+        }
+        seen.push(c_name);
+        // The generated `main` and the runtime's helpers, e.g. `__z_panic`,
+        // are left out like the handlers
+        if (c_name == "main" && wrapped) || (c_name.starts_with("__z_") && c_name != "__z_main") {
+            writeln!(table, "  {{ (void (*)(void)){}, NULL, NULL }},", c_name).unwrap();
+            continue;
+        }
+        let (name, file) = match symbols.get(c_name) {
+            Some(symbol) => (symbol.name.as_str(), symbol.file.as_str()),
+            None => (c_name, ""),
+        };
+        writeln!(table, "  {{ (void (*)(void)){}, {:?}, {:?} }},", c_name, name, file).unwrap();
+    }
+    RUNTIME.replace("__Z_SYMBOLS\n", &table)
+}
+
+// `name` NULL marks the handlers' own functions, left out of reports
+const RUNTIME: &str = r#"
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#if defined(_WIN32)
+#include <windows.h>
+#define __Z_BACKTRACE(frames, size) ((int)CaptureStackBackTrace(0, (size), (frames), NULL))
+#elif defined(__has_include)
+#if __has_include(<execinfo.h>)
+#include <execinfo.h>
+#define __Z_BACKTRACE(frames, size) backtrace((frames), (size))
+#endif
+#endif
+typedef struct { void (*address)(void); const char* name; const char* file; } __z_symbol;
+static void __z_crash(int number);
+static void __z_install_crash_handlers(void);
+static const __z_symbol __z_symbols[] = {
+__Z_SYMBOLS
+  { (void (*)(void))__z_crash, NULL, NULL },
+  { (void (*)(void))__z_install_crash_handlers, NULL, NULL },
+};
+// The function starting closest below `address`, if it is near enough to
+// be in the program rather than in a library
+static const __z_symbol* __z_symbol_at(uintptr_t address) {
+  const __z_symbol* found = NULL;
+  for (size_t i = 0; i < sizeof __z_symbols / sizeof __z_symbols[0]; i++) {
+    uintptr_t start = (uintptr_t)__z_symbols[i].address;
+    if (start <= address && (found == NULL || start > (uintptr_t)found->address)) found = &__z_symbols[i];
+  }
+  return found != NULL && address - (uintptr_t)found->address < 65536 ? found : NULL;
+}
+static void __z_crash(int number) {
+  const char* what = number == SIGSEGV ? "segmentation fault (invalid memory access)"
+    : number == SIGFPE ? "arithmetic error (e.g. integer division by zero)"
+    : number == SIGILL ? "illegal instruction"
+    : number == SIGABRT ? "aborted"
+    : "bus error (misaligned or unmapped memory)";
+  fflush(stdout);
+  fprintf(stderr, "crash: %s\n", what);
+#ifdef __Z_BACKTRACE
+  void* frames[64];
+  int count = __Z_BACKTRACE(frames, 64);
+  int printed = 0;
+  for (int i = 0; i < count; i++) {
+    // A return address is just after its call, which may end a function
+    const __z_symbol* symbol = __z_symbol_at((uintptr_t)frames[i] - 1);
+    if (symbol != NULL && symbol->name == NULL) continue;
+    // The signal trampoline, before the function that crashed
+    if (symbol == NULL && printed == 0) continue;
+    if (symbol != NULL) fprintf(stderr, "  in %s (%s)\n", symbol->name, symbol->file[0] ? symbol->file : "generated");
+    else fprintf(stderr, "  in ?? (%p)\n", frames[i]);
+    printed++;
+    // What called `main` is the C runtime's
+    if (symbol != NULL && strcmp(symbol->name, "main") == 0) break;
+  }
+#endif
+  fflush(stderr);
+  signal(number, SIG_DFL);
+  raise(number);
+}
+__attribute__((constructor)) static void __z_install_crash_handlers(void) {
+  int signals[] = { SIGSEGV, SIGFPE, SIGILL, SIGABRT,
+#ifdef SIGBUS
+    SIGBUS,
+#endif
+  };
+#if defined(_WIN32)
+  for (size_t i = 0; i < sizeof signals / sizeof signals[0]; i++) signal(signals[i], __z_crash);
+#else
+  // Its own stack, so a stack overflow can be reported
+  static char stack[65536];
+  stack_t alternate;
+  memset(&alternate, 0, sizeof alternate);
+  alternate.ss_sp = stack;
+  alternate.ss_size = sizeof stack;
+  sigaltstack(&alternate, NULL);
+  struct sigaction action;
+  memset(&action, 0, sizeof action);
+  action.sa_handler = __z_crash;
+  action.sa_flags = SA_ONSTACK;
+  sigemptyset(&action.sa_mask);
+  for (size_t i = 0; i < sizeof signals / sizeof signals[0]; i++) sigaction(signals[i], &action, NULL);
+#endif
+#ifdef __Z_BACKTRACE
+  // The first backtrace loads what it needs, which a crashed program can't
+  void* frame[1];
+  (void)__Z_BACKTRACE(frame, 1);
+#endif
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_symbols_have_z_names() {
+        let options = crate::Options { crash_report: true, ..crate::Options::default() };
+        let c_code = crate::Compiler::builder().options(options).build().compile("namespace geo {\n  class Point {\n    int x;\n    int get(){ return self.x; }\n  }\n  int twice(int n){ return n * 2; }\n}\nint main(){ return geo::twice(1); }");
+        assert!(c_code.contains("{ (void (*)(void))geo_Point_get, \"geo::Point::get\", \"<input>\" },"), "{}", c_code);
+        assert!(c_code.contains("{ (void (*)(void))geo_twice, \"geo::twice\", \"<input>\" },"), "{}", c_code);
+
+        let runtime = runtime(&tokenize("int twice(int n){ return n * 2; }\nint helper(void);\nstatic int helper(void){ return 0; }"), &HashMap::new());
+        assert!(runtime.contains("static const __z_symbol __z_symbols[] = {\n  { (void (*)(void))twice, \"twice\", \"\" },\n  { (void (*)(void))helper, \"helper\", \"\" },\n  { (void (*)(void))__z_crash, NULL, NULL },"), "{}", runtime);
+    }
+}
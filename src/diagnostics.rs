@@ -0,0 +1,80 @@
+// src/diagnostics.rs
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while compiling, reported instead of letting gcc fail later
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// File the problem was found in
+    pub file: String,
+    /// The lint of a warning, or of an error it was denied into, see `lints`
+    pub lint: Option<&'static str>,
+}
+
+impl Diagnostic {
+    pub fn error(file: &str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, message: message.into(), file: file.to_string(), lint: None }
+    }
+
+    pub fn warning(file: &str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, message: message.into(), file: file.to_string(), lint: None }
+    }
+
+    pub fn with_lint(mut self, lint: &'static str) -> Self {
+        self.lint = Some(lint);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.message)?;
+        if let Some(lint) = self.lint {
+            write!(f, " [{}]", lint)?;
+        }
+        write!(f, "\n  --> {}", self.file)
+    }
+}
+
+/// The line closing a build's diagnostics, e.g. `2 errors, 1 warning
+/// emitted`; none when there are none
+pub fn summary<'a>(diagnostics: impl IntoIterator<Item = &'a Diagnostic>) -> Option<String> {
+    let (mut errors, mut warnings) = (0, 0);
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Error => errors += 1,
+            Severity::Warning => warnings += 1,
+        }
+    }
+    let count = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    (errors + warnings > 0).then(|| format!("{}, {} emitted", count(errors, "error"), count(warnings, "warning")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_counts_errors_and_warnings() {
+        assert_eq!(summary(&[]), None);
+        let diagnostics = [Diagnostic::error("a.z", "x"), Diagnostic::warning("a.z", "y"), Diagnostic::error("b.z", "z")];
+        assert_eq!(summary(&diagnostics).as_deref(), Some("2 errors, 1 warning emitted"));
+        assert_eq!(summary(&diagnostics[1..2]).as_deref(), Some("0 errors, 1 warning emitted"));
+    }
+}
@@ -0,0 +1,196 @@
+// src/dynamic.rs
+//
+// Dynamic values: an `interface` value is a `{ data, vt }` pair of a pointer
+// to a class value and that class's vtable for the interface, so values of
+// different classes can share an array. `any` is the interface without
+// methods. `Shape s = &circle;` or `Shape(&circle)` makes one, and
+// `s as? Circle` gets the `Circle*` back, or NULL when `s` refers to another
+// class, by comparing the type IDs the vtables point at.
+
+use std::collections::BTreeSet;
+
+use crate::ast::Interface;
+use crate::parser::is_symbol;
+use crate::tokenizer::Token;
+
+/// The type ID struct and `any`, shared by every file
+pub fn runtime() -> String {
+    format!("#ifndef __Z_DYNAMIC\n#define __Z_DYNAMIC\ntypedef struct {{ const char* name; }} __z_type;\n{}#endif\n", any())
+}
+
+/// The built-in interface every class converts to
+pub fn any() -> Interface {
+    Interface { name: "any".to_string(), namespace: None, methods: Vec::new(), file_private: false }
+}
+
+/// What a file's lowered code refers to, for the emitter to define once the
+/// classes and interfaces involved are
+#[derive(Debug, Default)]
+pub struct Uses {
+    /// (class, interface) conversions, both mangled
+    pub vtables: BTreeSet<(String, String)>,
+    /// Mangled classes whose type ID is compared against
+    pub type_ids: BTreeSet<String>,
+}
+
+impl Uses {
+    pub fn is_empty(&self) -> bool {
+        self.vtables.is_empty() && self.type_ids.is_empty()
+    }
+}
+
+pub fn type_id_name(class: &str) -> String {
+    format!("{}__type", class)
+}
+
+pub fn vtable_name(class: &str, interface: &str) -> String {
+    format!("{}__{}__vtable", class, interface)
+}
+
+/// The type ID of `class`. It is weak so separately compiled files that
+/// each define it agree on one address
+pub fn type_id(class: &str) -> String {
+    format!("__attribute__((weak)) const __z_type {} = {{ \"{}\" }};\n", type_id_name(class), class)
+}
+
+/// The vtable of `class` for `interface`: a function per method that calls
+/// the class's method on `*data`
+pub fn vtable(class: &str, interface: &Interface) -> String {
+    let iface = interface.full_name();
+    let mut out = String::new();
+    let mut entries = vec![format!("&{}", type_id_name(class))];
+    for method in &interface.methods {
+        let thunk = format!("{}__{}_{}", class, iface, method.name);
+        let params: String = method.params.iter().map(|p| format!(", {}", p.param())).collect();
+        let args: String = method.params.iter().map(|p| format!(", {}", p.name)).collect();
+        let call = format!("{}_{}(*({}*)self{})", class, method.name, class, args);
+        let body = if method.return_type == "void" { format!("{};", call) } else { format!("return {};", call) };
+        out.push_str(&format!("static {} {}(void* self{}){{ {} }}\n", method.return_type, thunk, params, body));
+        entries.push(thunk);
+    }
+    out.push_str(&format!("static const {}__vtable {} = {{ {} }};\n", iface, vtable_name(class, &iface), entries.join(", ")));
+    out
+}
+
+/// A conversion to an interface value found in code
+#[derive(Debug, PartialEq)]
+pub struct Conversion {
+    /// The tokens replaced by the interface value
+    pub start: usize,
+    pub end: usize,
+    pub interface: String,
+    /// `&c` or `p`, as written
+    pub operand: Vec<Token>,
+    /// Written `I(operand)` rather than initializing an `I`
+    pub explicit: bool,
+}
+
+/// `I name = operand;` and `I(operand)`, where `I` is one of `interfaces`
+pub fn conversions(tokens: &[Token], interfaces: &dyn Fn(&str) -> bool) -> Vec<Conversion> {
+    let mut found = Vec::new();
+    for i in 0..tokens.len() {
+        let Token::Identifier(interface) = &tokens[i] else { continue };
+        if !interfaces(interface) || is_symbol(i.checked_sub(1).and_then(|p| tokens.get(p)), ".") {
+            continue;
+        }
+        // `I name = operand` up to the `;` or `,`
+        if let (Some(Token::Identifier(_)), true) = (tokens.get(i + 1), is_symbol(tokens.get(i + 2), "=")) {
+            let end = (i + 3..tokens.len())
+                .find(|&j| is_symbol(tokens.get(j), ";") || is_symbol(tokens.get(j), ","))
+                .unwrap_or(tokens.len());
+            let operand = significant(&tokens[i + 3..end]);
+            if !operand.is_empty() {
+                found.push(Conversion { start: i + 3, end, interface: interface.clone(), operand, explicit: false });
+            }
+            continue;
+        }
+        // `I(operand)`
+        if is_symbol(tokens.get(i + 1), "(") {
+            let Some(close) = crate::parser::matching_close(tokens, i + 1) else { continue };
+            found.push(Conversion { start: i, end: close + 1, interface: interface.clone(), operand: significant(&tokens[i + 2..close]), explicit: true });
+        }
+    }
+    found
+}
+
+/// A downcast `operand as? Class` found in code
+#[derive(Debug, PartialEq)]
+pub struct Downcast {
+    pub start: usize,
+    pub end: usize,
+    pub operand: Vec<Token>,
+    /// The class as written, e.g. `geo::Circle`
+    pub class: String,
+}
+
+/// Every `x as? Class`, where `x` is a name, optionally indexed or a member
+pub fn downcasts(tokens: &[Token]) -> Vec<Downcast> {
+    let mut found = Vec::new();
+    for i in 0..tokens.len() {
+        if !matches!(&tokens[i], Token::Identifier(word) if word == "as") || !is_symbol(tokens.get(i + 1), "?") {
+            continue;
+        }
+        let start = operand_start(tokens, i);
+        if start == i {
+            continue;
+        }
+        let mut end = i + 2;
+        let mut class = String::new();
+        while let Some(Token::Identifier(segment)) = tokens.get(end) {
+            class.push_str(segment);
+            end += 1;
+            if !is_symbol(tokens.get(end), "::") {
+                break;
+            }
+            class.push_str("::");
+            end += 1;
+        }
+        found.push(Downcast { start, end, operand: significant(&tokens[start..i]), class });
+    }
+    found
+}
+
+// Start of the `a`, `a[i]`, `a.b` or `a->b[i]` before `end`
+fn operand_start(tokens: &[Token], end: usize) -> usize {
+    let mut start = end;
+    loop {
+        while is_symbol(start.checked_sub(1).and_then(|i| tokens.get(i)), "]") {
+            match (0..start - 1).rev().find(|&open| crate::parser::matching_close(tokens, open) == Some(start - 1)) {
+                Some(open) => start = open,
+                None => return end,
+            }
+        }
+        match start.checked_sub(1).and_then(|i| tokens.get(i)) {
+            Some(Token::Identifier(_)) => start -= 1,
+            _ => return end,
+        }
+        match start.checked_sub(1).and_then(|i| tokens.get(i)) {
+            Some(Token::Symbol(access)) if access == "." || access == "->" => start -= 1,
+            _ => return start,
+        }
+    }
+}
+
+fn significant(tokens: &[Token]) -> Vec<Token> {
+    tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_conversions_and_downcasts_are_found() {
+        let tokens = tokenize("Shape s = &c;\nitems[0] = Shape(p);\nCircle* c2 = items[i] as? geo::Circle;");
+        let is_interface = |name: &str| name == "Shape";
+        let found = conversions(&tokens, &is_interface);
+        assert_eq!(found.len(), 2);
+        assert_eq!(detokenize(&found[0].operand), "&c");
+        assert_eq!(detokenize(&found[1].operand), "p");
+        let casts = downcasts(&tokens);
+        assert_eq!(casts.len(), 1);
+        assert_eq!(detokenize(&casts[0].operand), "items[i]");
+        assert_eq!(casts[0].class, "geo::Circle");
+    }
+}
@@ -0,0 +1,110 @@
+// src/edition.rs
+//
+// Language editions. Each keyword Z adds on top of C belongs to the edition
+// that introduced it, and a file only has the keywords of its own edition
+// and older ones. In an older edition a newer keyword is a plain name: the
+// lexer escapes it so no pass takes it for syntax, and emission writes it
+// back as it was. A program keeps compiling when a later edition claims one
+// of its names; it moves to the new edition when it is ready to.
+
+use std::fmt;
+
+use crate::tokenizer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Edition {
+    /// Classes, unions, interfaces, namespaces and operator overloading
+    E2024,
+    /// `rc` classes, string `match`, `try`/`catch`/`panic`, `typeinfo` and
+    /// `export`
+    E2025,
+}
+
+/// Each keyword and the edition that introduced it
+const KEYWORDS: &[(&str, Edition)] = &[
+    ("class", Edition::E2024),
+    ("union", Edition::E2024),
+    ("interface", Edition::E2024),
+    ("namespace", Edition::E2024),
+    ("operator", Edition::E2024),
+    ("rc", Edition::E2025),
+    ("match", Edition::E2025),
+    ("try", Edition::E2025),
+    ("catch", Edition::E2025),
+    ("panic", Edition::E2025),
+    ("typeinfo", Edition::E2025),
+    ("export", Edition::E2025),
+];
+
+/// Prefix of a name that is a keyword only in a later edition
+const ESCAPE: &str = "__z_raw_";
+
+impl Edition {
+    pub const ALL: &'static [Edition] = &[Edition::E2024, Edition::E2025];
+    pub const LATEST: Edition = Edition::E2025;
+
+    pub fn parse(name: &str) -> Option<Edition> {
+        Edition::ALL.iter().copied().find(|e| e.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Edition::E2024 => "2024",
+            Edition::E2025 => "2025",
+        }
+    }
+
+    /// Whether `word` is a keyword in this edition
+    pub fn is_keyword(self, word: &str) -> bool {
+        KEYWORDS.iter().any(|&(keyword, since)| keyword == word && since <= self)
+    }
+
+    /// Escapes the names in `tokens` that only later editions reserve
+    pub fn escape(self, tokens: &mut [Token]) {
+        for token in tokens {
+            if let Token::Identifier(word) = token {
+                if !self.is_keyword(word) && KEYWORDS.iter().any(|&(keyword, _)| keyword == word) {
+                    *word = format!("{}{}", ESCAPE, word);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Writes escaped names back as they were, also inside names built from
+/// them (e.g. `Parser___z_raw_match` -> `Parser_match`)
+pub fn unescape(tokens: &mut [Token]) {
+    for token in tokens {
+        if let Token::Identifier(word) = token {
+            if word.contains(ESCAPE) {
+                *word = word.replace(ESCAPE, "");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_later_keywords_are_names() {
+        assert_eq!(Edition::parse("2024"), Some(Edition::E2024));
+        assert_eq!(Edition::parse("2023"), None);
+        assert!(Edition::E2024.is_keyword("class") && !Edition::E2024.is_keyword("match"));
+        let mut tokens = tokenize("int match = try(class);");
+        Edition::E2024.escape(&mut tokens);
+        assert_eq!(tokens[1], Token::Identifier("__z_raw_match".to_string()));
+        assert_eq!(tokens[3], Token::Identifier("__z_raw_try".to_string()));
+        assert_eq!(tokens[5], Token::Identifier("class".to_string()));
+        unescape(&mut tokens);
+        assert_eq!(tokens, tokenize("int match = try(class);"));
+    }
+}
@@ -0,0 +1,105 @@
+// src/embed.rs
+//
+// `#embed "logo.png" as LOGO` reads the file when the program is compiled
+// and defines its bytes as a C array:
+//
+//     const unsigned char LOGO[] = "\211PNG\r\n..."; enum { LOGO_LEN = 1234 };
+//
+// The bytes are written as one string literal, which costs a compiler far
+// less than an initializer of a number per byte, and which ends with a 0
+// byte that `LOGO_LEN` doesn't count, so an embedded text file is also a C
+// string. The length is `logo_len` for a name that isn't in capitals. Files
+// are found like imports: from the directory the compiler runs in, or among
+// the files `build.z` generated. `#embed` without `as` is C23's and is left
+// to the C compiler.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::tokenizer::Token;
+
+/// `tokens` with each `#embed ... as NAME` replaced by its definitions,
+/// and the errors. `locate` finds the file a path names
+pub fn expand(tokens: &[Token], locate: impl Fn(&str) -> PathBuf) -> (Vec<Token>, Vec<String>) {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut errors = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let line_start = i == 0 || tokens[i - 1] == Token::Newline;
+        let directive = line_start && matches!(&tokens[i], Token::Symbol(s) if s == "#") && matches!(tokens.get(i + 1), Some(Token::Identifier(w)) if w == "embed");
+        let end = if directive { (i..tokens.len()).find(|&j| tokens[j] == Token::Newline).unwrap_or(tokens.len()) } else { i };
+        let args: Vec<&Token> = tokens[(i + 2).min(end)..end].iter().filter(|t| !matches!(t, Token::Comment(_) | Token::Eof)).collect();
+        if !args.iter().any(|t| matches!(t, Token::Identifier(w) if w == "as")) {
+            out.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+        match args.as_slice() {
+            [Token::StringLit(path), Token::Identifier(as_), Token::Identifier(name)] if as_ == "as" => match fs::read(locate(path.trim_matches('"'))) {
+                Ok(bytes) => out.extend(definitions(name, &bytes)),
+                Err(e) => errors.push(format!("cannot embed {}: {}", path, e)),
+            },
+            _ => errors.push("expected `#embed \"file\" as NAME`".to_string()),
+        }
+        i = end;
+    }
+    (out, errors)
+}
+
+// `const unsigned char NAME[] = "..."; enum { NAME_LEN = n };`
+fn definitions(name: &str, bytes: &[u8]) -> Vec<Token> {
+    let len = if name.chars().any(|c| c.is_lowercase()) { format!("{}_len", name) } else { format!("{}_LEN", name) };
+    let symbol = |s: &str| Token::Symbol(s.to_string());
+    let identifier = |s: &str| Token::Identifier(s.to_string());
+    vec![
+        identifier("const"), identifier("unsigned"), identifier("char"), identifier(name), symbol("["), symbol("]"), symbol("="),
+        Token::StringLit(literal(bytes)), symbol(";"),
+        identifier("enum"), symbol("{"), identifier(&len), symbol("="), Token::Number(bytes.len().to_string()), symbol("}"), symbol(";"),
+    ]
+}
+
+// A C string literal of `bytes`. Octal escapes have at most three digits,
+// so unlike `\x` they can't run into a digit that follows
+fn literal(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2 + 2);
+    out.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            b'\n' => out.push_str("\\n"),
+            // `?` could start a trigraph
+            b' '..=b'~' if byte != b'?' => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_files_are_embedded() {
+        let path = std::env::temp_dir().join(format!("tarnish_embed_{}.bin", std::process::id()));
+        fs::write(&path, b"a\"\\?\n\x00\x89\x07z").unwrap();
+        let source = format!("#embed \"{}\" as BLOB\n#embed \"{}\" as icon // small\n#embed \"data.bin\"\nint main() {{ return BLOB_LEN; }}", path.display(), path.display());
+        let (tokens, errors) = expand(&tokenize(&source), |path| PathBuf::from(path));
+        fs::remove_file(&path).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let code = detokenize(&tokens);
+        let lines: Vec<&str> = code.lines().collect();
+        assert_eq!(lines[0], "const unsigned char BLOB[] = \"a\\\"\\\\\\077\\n\\000\\211\\007z\"; enum { BLOB_LEN = 9 };");
+        assert!(lines[1].ends_with("enum { icon_len = 9 };"), "{}", lines[1]);
+        assert_eq!(lines.len(), 4);
+
+        let (_, errors) = expand(&tokenize("#embed \"missing.png\" as LOGO\n#embed as LOGO"), |path| PathBuf::from(path));
+        assert!(errors[0].starts_with("cannot embed \"missing.png\": "), "{:?}", errors);
+        assert_eq!(errors[1], "expected `#embed \"file\" as NAME`");
+    }
+}
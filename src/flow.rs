@@ -0,0 +1,365 @@
+// src/flow.rs
+//
+// Return-path checking. A function body is parsed into a tree of statements,
+// just deep enough to tell which ones can finish normally: a `return`, a
+// call to a function that never returns, an `if` whose branches both can't,
+// an infinite loop nothing breaks out of and a `switch` with a `default`
+// whose cases all end that way. A non-void function whose body can finish
+// is an error, and a statement that can only be reached by jumping over a
+// `return`, `break`, `continue` or `goto` gets a warning. C compilers only
+// warn about the first at some optimization levels, and the warning points
+// at the generated C.
+
+use crate::parser::matching_close;
+use crate::tokenizer::Token;
+
+/// Calls that never come back. `panic` has become `__z_panic` by the time
+/// bodies are checked
+const NORETURN: &[&str] = &["__z_panic", "abort", "exit", "_Exit", "quick_exit", "longjmp", "__builtin_unreachable", "__builtin_trap"];
+
+#[derive(Debug)]
+enum Statement {
+    /// `return`, `goto` or a call in `NORETURN`: leaves the function
+    Leave(&'static str),
+    Break,
+    Continue,
+    /// A label, `case` or `default` (true), which jumps can reach
+    Label(bool),
+    /// `#if*`, `#else`/`#elif` or `#endif`; only one branch is compiled
+    Conditional(Directive),
+    Block(Vec<Statement>),
+    If(Box<Statement>, Option<Box<Statement>>),
+    /// `while`, `for` or `do`; `infinite` when the condition is missing or
+    /// always true, `once` when the body always runs
+    Loop { body: Box<Statement>, infinite: bool, once: bool },
+    Switch(Box<Statement>),
+    /// Anything else
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Directive {
+    Open,
+    Branch,
+    Close,
+    Other,
+}
+
+/// Checks that `body`, the tokens between the braces of `function`, returns
+/// a value on every path when `return_type` isn't void, and that no code
+/// follows an unconditional jump. Returns the errors and the warnings.
+pub fn check_body(function: &str, return_type: &str, body: &[Token]) -> (Vec<String>, Vec<String>) {
+    let code: Vec<Token> = body.iter().filter(|t| !matches!(t, Token::Comment(_))).cloned().collect();
+    let statements = Parser { tokens: &code }.statements(0, code.len());
+    let mut warnings = Vec::new();
+    let finishes = finishes_list(&statements, function, &mut warnings);
+    let mut errors = Vec::new();
+    // The entry point adds a `return 0;` to a `main` that can finish, as C
+    // does for its own
+    if finishes && !is_void(return_type) && function != "main" {
+        errors.push(format!("`{}` returns `{}` but can reach the end of its body without a `return`", function, return_type.trim()));
+    }
+    (errors, warnings)
+}
+
+/// Whether control can reach the end of `body`, the tokens between the
+/// braces of `function`
+pub fn reaches_end(function: &str, body: &[Token]) -> bool {
+    let code: Vec<Token> = body.iter().filter(|t| !matches!(t, Token::Comment(_))).cloned().collect();
+    let statements = Parser { tokens: &code }.statements(0, code.len());
+    finishes_list(&statements, function, &mut Vec::new())
+}
+
+// `void` and `static inline void`, but not `void*`
+fn is_void(return_type: &str) -> bool {
+    let words: Vec<&str> = return_type.split_whitespace().filter(|w| !matches!(*w, "static" | "inline" | "extern" | "_Noreturn")).collect();
+    words.is_empty() || words == ["void"]
+}
+
+// Whether control can get past the end of `statements`, warning once about
+// code after a jump
+fn finishes_list(statements: &[Statement], function: &str, warnings: &mut Vec<String>) -> bool {
+    let mut reachable = true;
+    let mut jumped: Option<&'static str> = None;
+    let mut warned = false;
+    // For each open `#if`: whether its start was reachable, and whether the
+    // end of any branch so far is
+    let mut conditionals: Vec<(bool, bool)> = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Label(_) => {
+                reachable = true;
+                continue;
+            }
+            Statement::Conditional(directive) => {
+                match directive {
+                    Directive::Open => conditionals.push((reachable, false)),
+                    Directive::Branch => {
+                        if let Some((start, any)) = conditionals.last_mut() {
+                            *any |= reachable;
+                            reachable = *start;
+                        }
+                    }
+                    Directive::Close => {
+                        if let Some((_, any)) = conditionals.pop() {
+                            reachable |= any;
+                        }
+                    }
+                    Directive::Other => {}
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if let (false, Some(jump), false) = (reachable, jumped, warned) {
+            warnings.push(format!("code after the `{}` in `{}` is never run", jump, function));
+            warned = true;
+        }
+        if !finishes(statement, function, warnings) && reachable {
+            reachable = false;
+            jumped = match statement {
+                Statement::Leave(word) => Some(word),
+                Statement::Break => Some("break"),
+                Statement::Continue => Some("continue"),
+                _ => None,
+            };
+        }
+    }
+    reachable
+}
+
+fn finishes(statement: &Statement, function: &str, warnings: &mut Vec<String>) -> bool {
+    match statement {
+        Statement::Leave(_) | Statement::Break | Statement::Continue => false,
+        Statement::Label(_) | Statement::Conditional(_) | Statement::Other => true,
+        Statement::Block(statements) => finishes_list(statements, function, warnings),
+        Statement::If(then, otherwise) => {
+            let then = finishes(then, function, warnings);
+            let otherwise = otherwise.as_ref().is_none_or(|s| finishes(s, function, warnings));
+            then || otherwise
+        }
+        Statement::Loop { body, infinite, once } => {
+            let body_finishes = finishes(body, function, warnings);
+            // A `do` body that can't finish never gets to the condition
+            let runs_forever = *infinite || (*once && !body_finishes && !continues(body));
+            breaks(body) || !runs_forever
+        }
+        Statement::Switch(body) => {
+            let body_finishes = finishes(body, function, warnings);
+            let has_default = matches!(&**body, Statement::Block(statements) if statements.iter().any(|s| matches!(s, Statement::Label(true))));
+            body_finishes || breaks(body) || !has_default
+        }
+    }
+}
+
+// A `break` that leaves the loop or switch whose body is `statement`
+fn breaks(statement: &Statement) -> bool {
+    match statement {
+        Statement::Break => true,
+        Statement::Block(statements) => statements.iter().any(breaks),
+        Statement::If(then, otherwise) => breaks(then) || otherwise.as_deref().is_some_and(breaks),
+        _ => false,
+    }
+}
+
+// A `continue` that goes back to the condition of the loop whose body is
+// `statement`
+fn continues(statement: &Statement) -> bool {
+    match statement {
+        Statement::Continue => true,
+        Statement::Block(statements) => statements.iter().any(continues),
+        Statement::If(then, otherwise) => continues(then) || otherwise.as_deref().is_some_and(continues),
+        Statement::Switch(body) => continues(body),
+        _ => false,
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+}
+
+impl Parser<'_> {
+    fn statements(&self, mut i: usize, end: usize) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        while let Some(start) = self.skip_newlines(i, end) {
+            if self.tokens[start] == Token::Symbol(";".to_string()) {
+                i = start + 1;
+                continue;
+            }
+            let (statement, next) = self.statement(start, end);
+            statements.push(statement);
+            i = next.max(start + 1);
+        }
+        statements
+    }
+
+    fn skip_newlines(&self, i: usize, end: usize) -> Option<usize> {
+        (i..end).find(|&j| self.tokens[j] != Token::Newline)
+    }
+
+    // The statement starting at `i` and the index after it
+    fn statement(&self, i: usize, end: usize) -> (Statement, usize) {
+        let tokens = self.tokens;
+        let word = match &tokens[i] {
+            Token::Identifier(word) => word.as_str(),
+            Token::Symbol(s) if s == "{" => {
+                let close = matching_close(tokens, i).unwrap_or(end).min(end);
+                return (Statement::Block(self.statements(i + 1, close)), close + 1);
+            }
+            Token::Symbol(s) if s == "#" => {
+                let line_end = (i..end).find(|&j| tokens[j] == Token::Newline).unwrap_or(end);
+                let directive = match tokens.get(i + 1) {
+                    Some(Token::Identifier(d)) if d.starts_with("if") => Directive::Open,
+                    Some(Token::Identifier(d)) if d == "else" || d == "elif" => Directive::Branch,
+                    Some(Token::Identifier(d)) if d == "endif" => Directive::Close,
+                    _ => Directive::Other,
+                };
+                return (Statement::Conditional(directive), line_end);
+            }
+            _ => "",
+        };
+        let next_is = |j: usize, symbol: &str| matches!(self.skip_newlines(j, end).map(|k| &tokens[k]), Some(Token::Symbol(s)) if s == symbol);
+        match word {
+            "if" | "while" | "for" | "switch" if next_is(i + 1, "(") => {
+                let open = self.skip_newlines(i + 1, end).unwrap();
+                let close = matching_close(tokens, open).unwrap_or(end).min(end);
+                let Some(body_start) = self.skip_newlines(close + 1, end) else { return (Statement::Other, end) };
+                let (body, mut next) = self.statement(body_start, end);
+                let body = Box::new(body);
+                let condition = &tokens[open + 1..close];
+                let statement = match word {
+                    "if" => {
+                        let mut otherwise = None;
+                        if let Some(e) = self.skip_newlines(next, end).filter(|&e| matches!(&tokens[e], Token::Identifier(w) if w == "else")) {
+                            if let Some(s) = self.skip_newlines(e + 1, end) {
+                                let (statement, after) = self.statement(s, end);
+                                otherwise = Some(Box::new(statement));
+                                next = after;
+                            }
+                        }
+                        Statement::If(body, otherwise)
+                    }
+                    "while" => Statement::Loop { body, infinite: always_true(condition), once: false },
+                    "for" => {
+                        let parts: Vec<&[Token]> = condition.split(|t| *t == Token::Symbol(";".to_string())).collect();
+                        let infinite = parts.len() == 3 && (significant(parts[1]).is_empty() || always_true(parts[1]));
+                        Statement::Loop { body, infinite, once: false }
+                    }
+                    _ => Statement::Switch(body),
+                };
+                (statement, next)
+            }
+            "do" => {
+                let Some(body_start) = self.skip_newlines(i + 1, end) else { return (Statement::Other, end) };
+                let (body, next) = self.statement(body_start, end);
+                // `while (condition);`
+                let mut infinite = false;
+                let mut after = next;
+                if let Some(w) = self.skip_newlines(next, end).filter(|&w| matches!(&tokens[w], Token::Identifier(x) if x == "while")) {
+                    if let Some(open) = self.skip_newlines(w + 1, end) {
+                        let close = matching_close(tokens, open).unwrap_or(end).min(end);
+                        infinite = always_true(&tokens[open + 1..close]);
+                        after = self.statement_end(close + 1, end);
+                    }
+                }
+                (Statement::Loop { body: Box::new(body), infinite, once: true }, after)
+            }
+            "else" => (Statement::Other, i + 1),
+            "case" | "default" => {
+                let colon = (i..end).find(|&j| tokens[j] == Token::Symbol(":".to_string())).unwrap_or(end);
+                (Statement::Label(word == "default"), colon + 1)
+            }
+            _ if !word.is_empty() && next_is(i + 1, ":") => {
+                let colon = self.skip_newlines(i + 1, end).unwrap();
+                (Statement::Label(false), colon + 1)
+            }
+            "return" | "goto" | "break" | "continue" => {
+                let statement = match word {
+                    "return" => Statement::Leave("return"),
+                    "goto" => Statement::Leave("goto"),
+                    "break" => Statement::Break,
+                    _ => Statement::Continue,
+                };
+                (statement, self.statement_end(i, end))
+            }
+            _ => {
+                let next = self.statement_end(i, end);
+                let noreturn = NORETURN.contains(&word) && next_is(i + 1, "(");
+                if !noreturn {
+                    return (Statement::Other, next);
+                }
+                // Named as written in messages
+                let call = match word {
+                    "__z_panic" => "panic",
+                    _ => NORETURN.iter().find(|w| **w == word).unwrap(),
+                };
+                (Statement::Leave(call), next)
+            }
+        }
+    }
+
+    // The index after the `;` ending the statement at `i`
+    fn statement_end(&self, i: usize, end: usize) -> usize {
+        let mut depth = 0i32;
+        for j in i..end {
+            match &self.tokens[j] {
+                Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+                Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+                Token::Symbol(s) if s == ";" && depth <= 0 => return j + 1,
+                _ => {}
+            }
+        }
+        end
+    }
+}
+
+// `1`, `true` or nothing else
+fn always_true(condition: &[Token]) -> bool {
+    matches!(significant(condition).as_slice(), [Token::Number(n)] if n == "1") || matches!(significant(condition).as_slice(), [Token::Identifier(w)] if w == "true")
+}
+
+fn significant(tokens: &[Token]) -> Vec<Token> {
+    tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    fn check(return_type: &str, body: &str) -> (Vec<String>, Vec<String>) {
+        let tokens: Vec<Token> = tokenize(body).into_iter().filter(|t| *t != Token::Eof).collect();
+        check_body("f", return_type, &tokens)
+    }
+
+    #[test]
+    fn test_missing_returns() {
+        let missing = vec!["`f` returns `int` but can reach the end of its body without a `return`".to_string()];
+        assert_eq!(check("int", "if (x) return 1;").0, missing);
+        assert_eq!(check("int", "while (x) { return 1; }").0, missing);
+        assert_eq!(check("int", "switch (x) { case 1: return 1; }").0, missing);
+        assert_eq!(check("int", "for (;;) { if (x) break; }").0, missing);
+        assert!(check("int", "if (x) return 1;\nelse if (y) return 2;\nelse { abort(); }").0.is_empty());
+        assert!(check("int", "switch (x) { case 1: return 1; default: return 2; }").0.is_empty());
+        assert!(check("int", "while (1) { if (x) return 1; }").0.is_empty());
+        assert!(check("int", "do { return 1; } while (x);").0.is_empty());
+        assert!(check("static void", "if (x) return;").0.is_empty());
+        assert!(check("void*", "return 0;").0.is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let (errors, warnings) = check("int", "return 1;\nx++;\ny++;");
+        assert!(errors.is_empty());
+        assert_eq!(warnings, vec!["code after the `return` in `f` is never run"]);
+        let (_, warnings) = check("void", "while (x) { if (y) { continue; } break; x++; }");
+        assert_eq!(warnings, vec!["code after the `break` in `f` is never run"]);
+        // Labels, cases and the other branch of `#ifdef` are reachable
+        let (_, warnings) = check("int", "goto out;\nout:\nreturn 1;");
+        assert!(warnings.is_empty());
+        let (_, warnings) = check("int", "switch (x) { case 1: return 1; case 2: return 2; default: break; }\nreturn 0;");
+        assert!(warnings.is_empty());
+        let (_, warnings) = check("int", "#ifdef _WIN32\nreturn 1;\n#else\nreturn 2;\n#endif");
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+// src/format.rs
+//
+// Compile-time checking of printf-style calls. When the format argument of
+// `printf`, `fprintf`, `sprintf`, `snprintf` or `dprintf` is a string
+// literal, its conversions say how many arguments follow and what each one
+// must be. Arguments are only typed when that is obvious from the tokens
+// (literals, `&x`, `NULL` and variables with a declared type); anything else
+// is accepted, gcc's `-Wformat` still sees the generated C.
+
+use std::collections::HashMap;
+
+use crate::ast::Variable;
+use crate::tokenizer::Token;
+
+/// What an argument is, as far as a format cares
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Integer,
+    Float,
+    /// `char*` and string literals
+    String,
+    Pointer,
+    /// A value of a Z class, by its declared name
+    Class(String),
+}
+
+impl Arg {
+    fn describe(&self) -> String {
+        match self {
+            Arg::Integer => "an integer".to_string(),
+            Arg::Float => "a floating-point number".to_string(),
+            Arg::String => "a string".to_string(),
+            Arg::Pointer => "a pointer".to_string(),
+            Arg::Class(name) => format!("a `{}`", name),
+        }
+    }
+}
+
+/// What a conversion expects, e.g. `%d` an integer
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expect {
+    Integer,
+    Float,
+    String,
+    Pointer,
+}
+
+impl Expect {
+    fn accepts(self, arg: &Arg) -> bool {
+        match self {
+            Expect::Integer => *arg == Arg::Integer,
+            Expect::Float => *arg == Arg::Float,
+            // A `char buf[]` or a typedef'd pointer may well be a string
+            Expect::String => matches!(arg, Arg::String | Arg::Pointer),
+            Expect::Pointer => matches!(arg, Arg::String | Arg::Pointer),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Expect::Integer => "an integer",
+            Expect::Float => "a floating-point number",
+            Expect::String => "a string",
+            Expect::Pointer => "a pointer",
+        }
+    }
+}
+
+const INTEGER_TYPES: &[&str] = &[
+    "char", "short", "int", "long", "signed", "unsigned", "bool", "_Bool", "size_t", "ssize_t", "ptrdiff_t", "intptr_t",
+    "uintptr_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+];
+
+/// Position of the format among the arguments of a printf-family function
+pub fn format_position(function: &str) -> Option<usize> {
+    match function {
+        "printf" => Some(0),
+        "fprintf" | "sprintf" | "dprintf" => Some(1),
+        "snprintf" => Some(2),
+        _ => None,
+    }
+}
+
+/// Checks the arguments of a call to `function` against its format.
+/// `args` are the call's arguments; `type_of` gives what an argument is
+/// when it can tell. Returns the errors and warnings found.
+pub fn check_call(function: &str, args: &[&[Token]], type_of: impl Fn(&[Token]) -> Option<Arg>) -> (Vec<String>, Vec<String>) {
+    let (mut errors, mut warnings) = (Vec::new(), Vec::new());
+    let Some(position) = format_position(function) else { return (errors, warnings) };
+    let Some(format) = args.get(position).and_then(|arg| literal_text(arg)) else { return (errors, warnings) };
+    let expected = match conversions(&format) {
+        Ok(expected) => expected,
+        Err(message) => {
+            errors.push(format!("`{}` format: {}", function, message));
+            return (errors, warnings);
+        }
+    };
+
+    let given = &args[position + 1..];
+    for (index, ((conversion, expect), arg)) in expected.iter().zip(given).enumerate() {
+        let Some(arg) = type_of(arg) else { continue };
+        if !expect.accepts(&arg) {
+            errors.push(format!(
+                "`{}` format `{}` expects {} but argument {} is {}",
+                function,
+                conversion,
+                expect.describe(),
+                position + index + 2,
+                arg.describe()
+            ));
+        }
+    }
+    if given.len() < expected.len() {
+        errors.push(format!(
+            "`{}` format uses {} argument{} but {} {} given",
+            function,
+            expected.len(),
+            if expected.len() == 1 { "" } else { "s" },
+            given.len(),
+            if given.len() == 1 { "was" } else { "were" }
+        ));
+    } else if given.len() > expected.len() {
+        warnings.push(format!(
+            "`{}` is given {} argument{} after its format but the format uses {}",
+            function,
+            given.len(),
+            if given.len() == 1 { "" } else { "s" },
+            expected.len()
+        ));
+    }
+    (errors, warnings)
+}
+
+/// What an argument is from its tokens, looking names up in `variables`
+/// (latest declaration wins) and `class_names`
+pub fn argument_type(arg: &[Token], variables: &[Variable], class_names: &HashMap<String, String>) -> Option<Arg> {
+    let arg: Vec<&Token> = arg.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    match arg.as_slice() {
+        [] => None,
+        tokens if tokens.iter().all(|t| matches!(t, Token::StringLit(_))) => Some(Arg::String),
+        [Token::Number(n)] if is_float_literal(n) => Some(Arg::Float),
+        [Token::Number(_)] | [Token::CharLit(_)] => Some(Arg::Integer),
+        [Token::Symbol(sign), Token::Number(n)] if sign == "-" || sign == "+" => {
+            Some(if is_float_literal(n) { Arg::Float } else { Arg::Integer })
+        }
+        [Token::Symbol(amp), Token::Identifier(_)] if amp == "&" => Some(Arg::Pointer),
+        [Token::Identifier(word)] if word == "NULL" => Some(Arg::Pointer),
+        [Token::Identifier(word)] if word == "true" || word == "false" => Some(Arg::Integer),
+        [Token::Identifier(name)] => {
+            let variable = variables.iter().rev().find(|v| v.name == *name)?;
+            type_category(&variable.type_, class_names)
+        }
+        _ => None,
+    }
+}
+
+fn type_category(type_: &str, class_names: &HashMap<String, String>) -> Option<Arg> {
+    let type_ = type_.trim();
+    if let Some(pointee) = type_.strip_suffix('*') {
+        let pointee = pointee.trim().trim_start_matches("const ").trim();
+        return Some(if pointee == "char" { Arg::String } else { Arg::Pointer });
+    }
+    let words: Vec<&str> = type_.split_whitespace().filter(|w| *w != "const").collect();
+    if words.is_empty() {
+        None
+    } else if words.iter().all(|w| INTEGER_TYPES.contains(w)) {
+        Some(Arg::Integer)
+    } else if words.iter().all(|w| matches!(*w, "float" | "double" | "long")) {
+        Some(Arg::Float)
+    } else if words.len() == 1 && class_names.contains_key(words[0]) {
+        Some(Arg::Class(words[0].to_string()))
+    } else {
+        None
+    }
+}
+
+fn is_float_literal(number: &str) -> bool {
+    !number.starts_with("0x") && !number.starts_with("0X") && number.contains(['.', 'e', 'E'])
+}
+
+// The text of an argument made of adjacent string literals, without quotes
+fn literal_text(arg: &[Token]) -> Option<String> {
+    let mut text = String::new();
+    let mut any = false;
+    for token in arg {
+        match token {
+            Token::StringLit(literal) if literal.len() >= 2 && literal.ends_with('"') => {
+                text.push_str(&literal[1..literal.len() - 1]);
+                any = true;
+            }
+            Token::Newline | Token::Comment(_) => {}
+            _ => return None,
+        }
+    }
+    any.then_some(text)
+}
+
+// Each conversion of `format` that takes an argument, as written, with
+// what it expects. `*` widths and precisions take an int of their own.
+fn conversions(format: &str) -> Result<Vec<(String, Expect)>, String> {
+    let mut out = Vec::new();
+    let mut chars = format.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        let mut end = start + 1;
+        let mut next = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+            let item = chars.next();
+            if let Some((i, c)) = item {
+                end = i + c.len_utf8();
+            }
+            item.map(|(_, c)| c)
+        };
+        let mut c = next(&mut chars);
+        // flags, width and precision
+        while let Some(flag) = c.filter(|c| "-+ #0'123456789.*".contains(*c)) {
+            if flag == '*' {
+                out.push(("*".to_string(), Expect::Integer));
+            }
+            c = next(&mut chars);
+        }
+        // length modifiers
+        while c.is_some_and(|c| "hljztL".contains(c)) {
+            c = next(&mut chars);
+        }
+        let spec = format[start..end].to_string();
+        let expect = match c {
+            Some('%') if spec == "%%" => continue,
+            Some('m') => continue,
+            Some('d' | 'i' | 'u' | 'o' | 'x' | 'X' | 'c') => Expect::Integer,
+            Some('f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A') => Expect::Float,
+            Some('s') => Expect::String,
+            Some('p' | 'n') => Expect::Pointer,
+            Some(_) => return Err(format!("unknown conversion `{}`", spec)),
+            None => return Err(format!("incomplete conversion `{}` at the end", spec)),
+        };
+        out.push((spec, expect));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    fn check(source: &str, variables: &[Variable]) -> (Vec<String>, Vec<String>) {
+        let tokens: Vec<Token> = tokenize(source).into_iter().filter(|t| !matches!(t, Token::Eof)).collect();
+        let args: Vec<&[Token]> = tokens.split(|t| matches!(t, Token::Symbol(s) if s == ",")).collect();
+        let class_names = HashMap::from([("V".to_string(), "V".to_string())]);
+        check_call("printf", &args, |arg| argument_type(arg, variables, &class_names))
+    }
+
+    #[test]
+    fn test_conversions() {
+        let specs: Vec<String> = conversions("%-5d|%%|%.*s|%lld|%8.3lf|%p").unwrap().into_iter().map(|(s, _)| s).collect();
+        assert_eq!(specs, vec!["%-5d", "*", "%.*s", "%lld", "%8.3lf", "%p"]);
+        assert_eq!(conversions("100%").unwrap_err(), "incomplete conversion `%` at the end");
+        assert_eq!(conversions("%y").unwrap_err(), "unknown conversion `%y`");
+    }
+
+    #[test]
+    fn test_check_call() {
+        let variables = vec![
+            Variable { name: "n".to_string(), type_: "unsigned long".to_string() },
+            Variable { name: "name".to_string(), type_: "char*".to_string() },
+            Variable { name: "v".to_string(), type_: "V".to_string() },
+        ];
+        assert_eq!(check("\"%lu %s %c %.2f\\n\", n, name, 'x', 1.5", &variables), (vec![], vec![]));
+        assert_eq!(check("\"%d %s\", f(), g()", &variables), (vec![], vec![]));
+        let (errors, _) = check("\"%d %s\\n\", name, v", &variables);
+        assert_eq!(errors, vec![
+            "`printf` format `%d` expects an integer but argument 2 is a string",
+            "`printf` format `%s` expects a string but argument 3 is a `V`",
+        ]);
+        let (errors, _) = check("\"%d and %d\", 1", &variables);
+        assert_eq!(errors, vec!["`printf` format uses 2 arguments but 1 was given"]);
+        let (_, warnings) = check("\"done\" \"\\n\", n", &variables);
+        assert_eq!(warnings, vec!["`printf` is given 1 argument after its format but the format uses 0"]);
+    }
+}
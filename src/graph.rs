@@ -0,0 +1,175 @@
+// src/graph.rs
+//
+// The structure of a whole program for `--emit graph`: which file imports
+// which, and an outline of every class. Both are written as GraphViz DOT.
+// Z has no inheritance, so the class diagram links a class to the classes
+// its fields hold and, with a dashed arrow, to each interface whose methods
+// it has all of.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::ast::{Class, Interface, Variable};
+
+#[derive(Debug, Default)]
+pub struct ProjectGraph {
+    /// (importing file, imported path)
+    imports: BTreeSet<(String, String)>,
+    /// Every class seen so far, by mangled name
+    classes: BTreeMap<String, Outline>,
+}
+
+/// What the class diagram shows of a class
+#[derive(Debug, Clone)]
+struct Outline {
+    kind: &'static str,
+    fields: Vec<Variable>,
+    /// `ret name(params)` of each method and operator
+    methods: Vec<String>,
+    method_names: BTreeSet<String>,
+    /// Mangled class each field holds, directly or through a pointer
+    holds: Vec<(String, String)>,
+}
+
+impl ProjectGraph {
+    pub fn add_import(&mut self, from: &str, to: &str) {
+        self.imports.insert((from.to_string(), to.to_string()));
+    }
+
+    /// Adds `class`; `class_names` resolves the types of its fields
+    pub fn add_class(&mut self, class: &Class, class_names: &HashMap<String, String>) {
+        let kind = if class.union {
+            "union"
+        } else if class.rc {
+            "rc class"
+        } else {
+            "class"
+        };
+        let mut methods: Vec<String> = class.functions.iter().map(|f| format!("{} {}({})", f.return_type, f.name, params(&f.params))).collect();
+        methods.extend(class.operators.iter().map(|o| format!("{} operator{}({})", o.return_type, o.operator, params(&o.params))));
+        let holds = class
+            .variables
+            .iter()
+            .filter_map(|field| {
+                let base = field.type_.trim_end_matches('*').trim().trim_start_matches("const ");
+                let mangled = class_names.get(base).cloned().or_else(|| base.contains("::").then(|| base.replace("::", "_")))?;
+                Some((field.name.clone(), mangled))
+            })
+            .collect();
+        let outline = Outline {
+            kind,
+            fields: class.variables.clone(),
+            methods,
+            method_names: class.functions.iter().map(|f| f.name.clone()).collect(),
+            holds,
+        };
+        self.classes.insert(class.full_name(), outline);
+    }
+
+    /// Files as boxes, C headers as grey ellipses, precompiled `.zi`
+    /// interfaces as notes
+    pub fn imports_dot(&self) -> String {
+        let mut files: BTreeSet<&str> = BTreeSet::new();
+        for (from, to) in &self.imports {
+            files.insert(from);
+            files.insert(to);
+        }
+        let mut out = String::from("digraph imports {\n  node [shape=box, fontname=\"monospace\"];\n");
+        for file in files {
+            let node = match file.strip_prefix("c:") {
+                Some(header) => format!("label={:?}, shape=ellipse, color=grey", format!("<{}>", header)),
+                None if file.ends_with(".zi") => format!("label={:?}, shape=note", file),
+                None => format!("label={:?}", file),
+            };
+            out.push_str(&format!("  {:?} [{}];\n", file, node));
+        }
+        for (from, to) in &self.imports {
+            out.push_str(&format!("  {:?} -> {:?};\n", from, to));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// A record per class with its fields and methods, and the interfaces
+    /// of `interfaces` that classes satisfy
+    pub fn classes_dot(&self, interfaces: &HashMap<String, Interface>) -> String {
+        let mut out = String::from("digraph classes {\n  node [shape=record, fontname=\"monospace\"];\n");
+        for (name, outline) in &self.classes {
+            let fields: String = outline.fields.iter().map(|f| format!("{}\\l", escape(&f.param()))).collect();
+            let methods: String = outline.methods.iter().map(|m| format!("{}\\l", escape(m))).collect();
+            let title = if outline.kind == "class" { escape(name) } else { format!("{} {}", outline.kind, escape(name)) };
+            out.push_str(&format!("  {:?} [label=\"{{{}|{}|{}}}\"];\n", name, title, fields, methods));
+        }
+        let interfaces: BTreeMap<&String, &Interface> = interfaces.iter().filter(|(_, i)| !i.methods.is_empty()).collect();
+        for (name, interface) in &interfaces {
+            let methods: String = interface.methods.iter().map(|m| format!("{}\\l", escape(&format!("{} {}({})", m.return_type, m.name, params(&m.params))))).collect();
+            out.push_str(&format!("  {:?} [label=\"{{interface {}|{}}}\", style=dashed];\n", name, escape(name), methods));
+        }
+        for (name, outline) in &self.classes {
+            for (field, held) in &outline.holds {
+                if self.classes.contains_key(held) || interfaces.contains_key(held) {
+                    out.push_str(&format!("  {:?} -> {:?} [label={:?}, arrowhead=diamond];\n", name, held, field));
+                }
+            }
+            for (interface_name, interface) in &interfaces {
+                if interface.methods.iter().all(|m| outline.method_names.contains(&m.name)) {
+                    out.push_str(&format!("  {:?} -> {:?} [style=dashed, arrowhead=empty];\n", name, interface_name));
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn params(params: &[Variable]) -> String {
+    params.iter().map(Variable::param).collect::<Vec<_>>().join(", ")
+}
+
+// Characters with a meaning in record labels
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Item;
+    use crate::parser::parse_module;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_class_and_import_graphs() {
+        let tokens = tokenize("interface Shape { double area(); }\nclass Point { int x; int y; }\nclass Circle { Point center; double r; double area() { return self.r * self.r; } }");
+        let module = parse_module(&tokens, "main.z");
+        let mut graph = ProjectGraph::default();
+        let class_names: HashMap<String, String> = [("Point", "Point"), ("Circle", "Circle")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut interfaces = HashMap::new();
+        for item in &module.items {
+            match item {
+                Item::Class(class) => graph.add_class(class, &class_names),
+                Item::Interface(interface) => {
+                    interfaces.insert(interface.full_name(), interface.clone());
+                }
+                _ => {}
+            }
+        }
+        let dot = graph.classes_dot(&interfaces);
+        assert!(dot.contains("  \"Circle\" [label=\"{Circle|Point center\\ldouble r\\l|double area()\\l}\"];"), "{}", dot);
+        assert!(dot.contains("  \"Circle\" -> \"Point\" [label=\"center\", arrowhead=diamond];"));
+        assert!(dot.contains("  \"Circle\" -> \"Shape\" [style=dashed, arrowhead=empty];"));
+        assert!(!dot.contains("\"Point\" -> \"Shape\""));
+
+        graph.add_import("main.z", "geo.z");
+        graph.add_import("geo.z", "c:math.h");
+        let dot = graph.imports_dot();
+        assert!(dot.contains("  \"c:math.h\" [label=\"<math.h>\", shape=ellipse, color=grey];"));
+        assert!(dot.contains("  \"main.z\" -> \"geo.z\";"));
+    }
+}
@@ -0,0 +1,165 @@
+// src/ice.rs
+//
+// Internal compiler errors. A pass that panics has hit a bug in the
+// compiler rather than in the program, and a Rust panic message with a
+// backtrace means nothing to someone writing Z. The pipeline catches the
+// panic and records an `Ice` on the session instead: the pass, the file it
+// was compiling and what the panic said. Compilation stops there, and the
+// driver prints `report` and exits with its internal-error status.
+//
+// `minimize` narrows the file down to the top-level items that still make
+// the same pass panic, dropping one item after another as long as the
+// panic stays. What is left gives the lines to look at, and is a small
+// file reproducing the bug for the report.
+
+use std::any::Any;
+
+use crate::compiler::{Compiler, Session};
+use crate::workspace::split;
+
+/// Most compilations `minimize` tries before settling for what it has;
+/// each drops one item
+const MINIMIZE_BUDGET: usize = 200;
+
+/// A pass that panicked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ice {
+    /// The pass, e.g. `lower`
+    pub pass: String,
+    /// The file it was compiling, which may be an import
+    pub file: String,
+    /// The source of that file
+    pub source: String,
+    /// What the panic said
+    pub message: String,
+    /// Where in the compiler it panicked, e.g. `src/passes.rs:120:9`, when
+    /// the embedder's panic hook recorded it
+    pub location: Option<String>,
+}
+
+/// The part of a file that reproduces an `Ice` on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reproduction {
+    pub text: String,
+    /// The lines of the file it was cut from, first and last, from 1
+    pub lines: (usize, usize),
+}
+
+impl Ice {
+    /// The panic `payload` of `pass` compiling `file`
+    pub fn new(pass: &str, file: &str, source: &str, payload: &(dyn Any + Send)) -> Ice {
+        Ice {
+            pass: pass.to_string(),
+            file: file.to_string(),
+            source: source.to_string(),
+            message: panic_message(payload),
+            location: None,
+        }
+    }
+
+    /// What the user is told, pointing at `reproduction` when there is one
+    pub fn report(&self, reproduction: Option<&Reproduction>) -> String {
+        let place = match reproduction {
+            Some(Reproduction { lines: (first, last), .. }) if first == last => format!("{}:{}", self.file, first),
+            Some(Reproduction { lines: (first, last), .. }) => format!("{}:{}-{}", self.file, first, last),
+            None => self.file.clone(),
+        };
+        let mut report = format!("error: internal compiler error: the `{}` pass panicked: {}\n  --> {}\n", self.pass, self.message, place);
+        report.push_str(&format!("note: this is a bug in the compiler (z-lang {}), not in the program\n", env!("CARGO_PKG_VERSION")));
+        if let Some(location) = &self.location {
+            report.push_str(&format!("note: it panicked at {}\n", location));
+        }
+        report
+    }
+}
+
+/// What a panic with `payload` said
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = payload.downcast_ref::<String>().cloned().or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()));
+    message.unwrap_or_else(|| "no message".to_string())
+}
+
+/// The fewest top-level items of the file `ice` is about that still make
+/// the same pass of `compiler` panic; none when the file alone doesn't
+pub fn minimize(compiler: &Compiler, ice: &Ice) -> Option<Reproduction> {
+    let (pieces, _) = split(&ice.source);
+    let fails = |kept: &[bool]| {
+        let text: String = pieces.iter().zip(kept).filter(|(_, keep)| **keep).map(|(piece, _)| *piece).collect();
+        let mut session = Session::default();
+        compiler.compile_in(&mut session, &ice.file, &text);
+        session.ice.is_some_and(|again| again.pass == ice.pass)
+    };
+    let mut kept = vec![true; pieces.len()];
+    if !fails(&kept) {
+        return None;
+    }
+    // From the end, as later items tend to use earlier ones
+    for i in (0..pieces.len()).rev().take(MINIMIZE_BUDGET - 1) {
+        kept[i] = false;
+        if !fails(&kept) {
+            kept[i] = true;
+        }
+    }
+
+    // The lines the kept pieces cover, leaving out blank ones around them
+    let mut lines = Vec::new();
+    let mut line = 1;
+    for (piece, keep) in pieces.iter().zip(&kept) {
+        for text in piece.split_inclusive('\n') {
+            if *keep && !text.trim().is_empty() {
+                lines.push(line);
+            }
+            line += 1;
+        }
+    }
+    let text = pieces.iter().zip(&kept).filter(|(_, keep)| **keep).map(|(piece, _)| *piece).collect();
+    Some(Reproduction { text, lines: (*lines.first()?, *lines.last()?) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Context, Pass};
+    use crate::tokenizer::Token;
+
+    // Trips over any function named `boom`
+    struct Fragile;
+
+    impl Pass for Fragile {
+        fn name(&self) -> &str {
+            "fragile"
+        }
+
+        fn run(&self, cx: &mut Context<'_>) {
+            if cx.tokens.iter().any(|t| matches!(t, Token::Identifier(name) if name == "boom")) {
+                panic!("cannot lower `boom`");
+            }
+        }
+    }
+
+    #[test]
+    fn test_panics_become_internal_errors() {
+        let compiler = Compiler::builder().pass_after("parse", Fragile).build();
+        let mut session = Session::default();
+        let source = "int one() { return 1; }\n\nint boom() {\n  return 2;\n}\nint main() { return one(); }\n";
+        let output = compiler.compile_in(&mut session, "main.z", source);
+        assert_eq!(output, "");
+        let ice = session.ice.clone().unwrap();
+        assert_eq!((ice.pass.as_str(), ice.file.as_str(), ice.message.as_str()), ("fragile", "main.z", "cannot lower `boom`"));
+        // Later passes didn't run
+        assert!(!session.timings.iter().any(|t| t.pass == "lower"));
+
+        let reproduction = minimize(&compiler, &ice).unwrap();
+        assert_eq!(reproduction.text, "\nint boom() {\n  return 2;\n}\n");
+        assert_eq!(reproduction.lines, (3, 5));
+        assert!(ice.report(Some(&reproduction)).starts_with("error: internal compiler error: the `fragile` pass panicked: cannot lower `boom`\n  --> main.z:3-5\nnote: this is a bug in the compiler (z-lang "));
+
+        // In an import, the import's file is the one reported
+        std::fs::write(std::env::temp_dir().join("tarnish_ice_geo.z"), "int boom() { return 0; }\n").unwrap();
+        let mut session = Session::default();
+        let main = format!("#import <{}>\nint main() {{ return 0; }}\n", std::env::temp_dir().join("tarnish_ice_geo.z").display());
+        compiler.compile_in(&mut session, "main.z", &main);
+        assert!(session.ice.unwrap().file.ends_with("tarnish_ice_geo.z"));
+        assert_eq!(session.import_depth, 0);
+    }
+}
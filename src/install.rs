@@ -0,0 +1,154 @@
+// src/install.rs
+//
+// `z-lang install` and the toolchain it installs. The compiler, the
+// standard library and a default configuration go into one directory per
+// user, `~/.tarnish` unless `TARNISH_HOME` or `--root` names another:
+//
+//     ~/.tarnish/
+//         bin/z-lang
+//         lib/std/*.z
+//         config.toml
+//
+// An installed compiler finds the rest from where its executable is, not
+// from the working directory, so it works from any directory once `bin/`
+// is on the `PATH`. The files in `lib/std/` are read instead of the ones
+// bundled into the compiler, and `config.toml` holds the settings of every
+// build of the user, e.g. the C compiler when `CC` is not set.
+//
+// A compiler that isn't installed, e.g. one run by cargo, has no layout and
+// uses what is bundled.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Manifest, FILE};
+use crate::stdlib;
+use crate::toolchain;
+
+/// The configuration `install` writes when there is none yet
+const DEFAULT_CONFIG: &str = "# Settings of the Tarnish toolchain for every build of this user
+
+[toolchain]
+# The C compiler when `CC` is not set, e.g. \"clang\" or \"ccache gcc\";
+# the first of gcc and clang on the PATH otherwise
+# cc = \"gcc\"
+";
+
+/// The directories of an installed toolchain
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    root: PathBuf,
+}
+
+impl Layout {
+    pub fn new(root: impl Into<PathBuf>) -> Layout {
+        Layout { root: root.into() }
+    }
+
+    /// `TARNISH_HOME`, or `.tarnish` in the user's home directory
+    pub fn user() -> Option<Layout> {
+        if let Some(home) = env::var_os("TARNISH_HOME").filter(|h| !h.is_empty()) {
+            return Some(Layout::new(home));
+        }
+        let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).filter(|h| !h.is_empty())?;
+        Some(Layout::new(Path::new(&home).join(".tarnish")))
+    }
+
+    /// The layout `executable` was installed into, if it was
+    pub fn of_executable(executable: &Path) -> Option<Layout> {
+        let bin = executable.parent()?;
+        let layout = Layout::new(bin.parent()?);
+        (bin.file_name()? == "bin" && layout.std_dir().is_dir()).then_some(layout)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.root.join("bin")
+    }
+
+    /// The directory holding `std/`, so an import path joins onto it
+    pub fn lib(&self) -> PathBuf {
+        self.root.join("lib")
+    }
+
+    pub fn std_dir(&self) -> PathBuf {
+        self.lib().join("std")
+    }
+
+    pub fn config(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    /// The user's configuration, empty when there is none
+    pub fn load_config(&self) -> Result<Manifest, String> {
+        let path = self.config();
+        match fs::read_to_string(&path) {
+            // Errors name the file they are in
+            Ok(text) => Manifest::parse(&text).map_err(|e| e.replacen(FILE, &path.display().to_string(), 1)),
+            Err(_) => Ok(Manifest::default()),
+        }
+    }
+
+    /// Copies `executable` and the standard library into the layout and
+    /// writes the default configuration unless there is one. Returns the
+    /// installed executable
+    pub fn install(&self, executable: &Path) -> Result<PathBuf, String> {
+        let error = |path: &Path, e: std::io::Error| format!("cannot write {}: {}", path.display(), e);
+        for dir in [self.bin(), self.std_dir()] {
+            fs::create_dir_all(&dir).map_err(|e| error(&dir, e))?;
+        }
+        let installed = self.bin().join(toolchain::executable("z-lang"));
+        // Installing the installed compiler again only updates the rest
+        if !same_file(executable, &installed) {
+            fs::copy(executable, &installed).map_err(|e| error(&installed, e))?;
+        }
+        for (path, source) in stdlib::BUNDLED {
+            let file = self.lib().join(path);
+            fs::write(&file, source).map_err(|e| error(&file, e))?;
+        }
+        let config = self.config();
+        if !config.exists() {
+            fs::write(&config, DEFAULT_CONFIG).map_err(|e| error(&config, e))?;
+        }
+        Ok(installed)
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_creates_the_layout() {
+        let root = env::temp_dir().join(format!("tarnish_install_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let layout = Layout::new(&root);
+        let executable = root.join("z-lang-build");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&executable, "binary").unwrap();
+
+        let installed = layout.install(&executable).unwrap();
+        assert_eq!(installed, root.join("bin").join(toolchain::executable("z-lang")));
+        assert_eq!(fs::read_to_string(&installed).unwrap(), "binary");
+        assert_eq!(fs::read_to_string(root.join("lib/std/math.z")).unwrap(), stdlib::source("std/math.z").unwrap());
+        assert_eq!(Layout::of_executable(&installed), Some(layout.clone()));
+        assert_eq!(Layout::of_executable(&executable), None);
+        assert_eq!(layout.load_config().unwrap().get("toolchain", "cc"), None);
+
+        // A configuration the user changed is kept
+        fs::write(layout.config(), "[toolchain]\ncc = \"clang\"").unwrap();
+        layout.install(&installed).unwrap();
+        assert_eq!(layout.load_config().unwrap().get("toolchain", "cc").and_then(|v| v.as_str()), Some("clang"));
+        fs::write(layout.config(), "[toolchain").unwrap();
+        assert_eq!(layout.load_config().unwrap_err(), format!("{}:1: expected `]` after the table name", layout.config().display()));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
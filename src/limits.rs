@@ -0,0 +1,113 @@
+// src/limits.rs
+//
+// Bounds on how much a compilation takes in, so a huge or runaway input is
+// reported rather than exhausting memory or the stack. Each `#import`
+// compiles the imported file nested inside the importer and splices its C
+// in, so without them a deep import chain overflows the stack and a large
+// one grows the output without end. The parser and the passes recurse into
+// brackets the same way, so brackets nested thousands deep would overflow
+// it too.
+//
+// The limits are set in `[limits]` of tarnish.toml:
+//
+//     [limits]
+//     max-file-size = 33554432
+//     max-import-depth = 32
+//     max-expansion-size = 268435456
+//     max-nesting-depth = 256
+//
+// Sizes are in bytes. An input over a limit is left out with an error
+// naming the limit, and compilation goes on with the rest.
+
+use crate::tokenizer::Token;
+
+/// How much one compilation may take in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest source file read, root or imported
+    pub max_file_size: u64,
+    /// How many imports deep a file may be; the root file is at 0
+    pub max_import_depth: usize,
+    /// Most C all imports together may splice into the output
+    pub max_expansion_size: u64,
+    /// How deep `(`, `[` and `{` may nest in a file
+    pub max_nesting_depth: usize,
+}
+
+impl Limits {
+    /// The keys of `[limits]`, as tarnish.toml writes them
+    pub const KEYS: &'static [&'static str] = &["max-file-size", "max-import-depth", "max-expansion-size", "max-nesting-depth"];
+
+    /// The error for a file of `size` bytes if it is over the limit
+    pub fn check_file_size(&self, path: &str, size: u64) -> Result<(), String> {
+        if size <= self.max_file_size {
+            return Ok(());
+        }
+        Err(format!("`{}` is {} bytes, more than `max-file-size` allows ({}); raise it in [limits] of tarnish.toml", path, size, self.max_file_size))
+    }
+
+    /// The error for importing `path` at `depth` if it is too deep
+    pub fn check_import_depth(&self, path: &str, depth: usize) -> Result<(), String> {
+        if depth <= self.max_import_depth {
+            return Ok(());
+        }
+        Err(format!("importing `{}` nests imports {} deep, more than `max-import-depth` allows ({}); raise it in [limits] of tarnish.toml", path, depth, self.max_import_depth))
+    }
+
+    /// The error for imports splicing in `size` bytes in all if it is too much
+    pub fn check_expansion_size(&self, path: &str, size: u64) -> Result<(), String> {
+        if size <= self.max_expansion_size {
+            return Ok(());
+        }
+        Err(format!("importing `{}` expands the imports to {} bytes, more than `max-expansion-size` allows ({}); raise it in [limits] of tarnish.toml", path, size, self.max_expansion_size))
+    }
+
+    /// The error for `tokens`, the file `path`, if its brackets nest too deep
+    pub fn check_nesting_depth(&self, path: &str, tokens: &[Token]) -> Result<(), String> {
+        let depth = nesting_depth(tokens);
+        if depth <= self.max_nesting_depth {
+            return Ok(());
+        }
+        Err(format!("brackets in `{}` nest {} deep, more than `max-nesting-depth` allows ({}); raise it in [limits] of tarnish.toml", path, depth, self.max_nesting_depth))
+    }
+}
+
+/// How deep the brackets of `tokens` nest at the deepest
+pub fn nesting_depth(tokens: &[Token]) -> usize {
+    let mut depth = 0usize;
+    let mut deepest = 0;
+    for token in tokens {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_file_size: 32 << 20, max_import_depth: 32, max_expansion_size: 256 << 20, max_nesting_depth: 256 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_limits_are_checked() {
+        let limits = Limits { max_file_size: 10, max_import_depth: 2, max_expansion_size: 100, max_nesting_depth: 2 };
+        assert_eq!(limits.check_file_size("a.z", 10), Ok(()));
+        assert_eq!(limits.check_file_size("a.z", 11), Err("`a.z` is 11 bytes, more than `max-file-size` allows (10); raise it in [limits] of tarnish.toml".to_string()));
+        assert!(limits.check_import_depth("a.z", 2).is_ok() && limits.check_import_depth("a.z", 3).is_err());
+        assert!(limits.check_expansion_size("a.z", 100).is_ok() && limits.check_expansion_size("a.z", 101).is_err());
+        assert!(limits.check_nesting_depth("a.z", &tokenize("int f() { return (1); } int a[2];")).is_ok());
+        assert_eq!(limits.check_nesting_depth("a.z", &tokenize("int f() { return g((1)); }")), Err("brackets in `a.z` nest 3 deep, more than `max-nesting-depth` allows (2); raise it in [limits] of tarnish.toml".to_string()));
+    }
+}
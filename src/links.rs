@@ -0,0 +1,173 @@
+// src/links.rs
+//
+// What a file needs at link time. `#link "sqlite3"` on a line of its own,
+// or `@link("sqlite3")`, asks for a library; the session collects them from
+// every file of the program and the driver passes `-lsqlite3` on, so a
+// module wrapping a C library brings its link requirements along. A value
+// starting with `-` is a linker flag and is passed as it is, split at
+// spaces (`#link "-framework Cocoa"`).
+//
+// `@link(pkg_config = "sdl2")` asks pkg-config instead: the driver runs
+// `pkg-config --cflags --libs sdl2` and passes what it prints to the C
+// compiler, include directories and defines as well as libraries.
+//
+// Directives are taken out right after `@cfg` is applied, so
+// `@cfg(os = "windows") #link "ws2_32"` links only where it is compiled.
+
+use std::env;
+use std::fmt;
+use std::process::Command;
+
+use crate::parser::parse_attribute;
+use crate::tokenizer::Token;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Link {
+    /// A library, or a linker flag when it starts with `-`
+    Library(String),
+    /// A package whose flags pkg-config gives
+    PkgConfig(String),
+}
+
+impl Link {
+    /// The linker arguments for a library or flag; none for a package
+    pub fn arguments(&self) -> Vec<String> {
+        match self {
+            Link::Library(flag) if flag.starts_with('-') => flag.split_whitespace().map(str::to_string).collect(),
+            Link::Library(library) => vec![format!("-l{}", library)],
+            Link::PkgConfig(_) => Vec::new(),
+        }
+    }
+}
+
+/// As written in a file, e.g. `#link "m"`
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Link::Library(library) => write!(f, "#link {:?}", library),
+            Link::PkgConfig(package) => write!(f, "@link(pkg_config = {:?})", package),
+        }
+    }
+}
+
+/// `tokens` without their `#link` and `@link` directives, what those ask
+/// for, and the errors
+pub fn collect(tokens: &[Token]) -> (Vec<Token>, Vec<Link>, Vec<String>) {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut links = Vec::new();
+    let mut errors = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let line_start = i == 0 || tokens[i - 1] == Token::Newline;
+        let directive = line_start && matches!(&tokens[i], Token::Symbol(s) if s == "#") && matches!(tokens.get(i + 1), Some(Token::Identifier(w)) if w == "link");
+        if directive {
+            let end = (i..tokens.len()).find(|&j| tokens[j] == Token::Newline).unwrap_or(tokens.len());
+            let args: Vec<&Token> = tokens[i + 2..end].iter().filter(|t| !matches!(t, Token::Comment(_) | Token::Eof)).collect();
+            match args.as_slice() {
+                [Token::StringLit(value)] => add(Link::Library(unquote(value)), &mut links, &mut errors),
+                _ => errors.push("`#link` takes a library name in quotes, e.g. `#link \"m\"`".to_string()),
+            }
+            i = end;
+            continue;
+        }
+        let Some((attribute, next)) = parse_attribute(tokens, i).filter(|(a, _)| a.name == "link") else {
+            out.push(tokens[i].clone());
+            i += 1;
+            continue;
+        };
+        let args: Vec<&Token> = attribute.args.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        for group in args.split(|t| matches!(t, Token::Symbol(s) if s == ",")) {
+            match group {
+                [Token::StringLit(value)] => add(Link::Library(unquote(value)), &mut links, &mut errors),
+                [Token::Identifier(key), Token::Symbol(eq), Token::StringLit(value)] if key == "pkg_config" && eq == "=" => {
+                    add(Link::PkgConfig(unquote(value)), &mut links, &mut errors)
+                }
+                _ => errors.push("`@link` takes library names in quotes or `pkg_config = \"package\"`, e.g. `@link(\"sqlite3\")`".to_string()),
+            }
+        }
+        out.extend(tokens[i..next].iter().filter(|t| **t == Token::Newline).cloned());
+        i = next;
+    }
+    (out, links, errors)
+}
+
+fn unquote(literal: &str) -> String {
+    literal.trim_matches('"').trim().to_string()
+}
+
+// Adds `link` to `links` unless it is there already
+fn add(link: Link, links: &mut Vec<Link>, errors: &mut Vec<String>) {
+    let (Link::Library(name) | Link::PkgConfig(name)) = &link;
+    if name.is_empty() {
+        errors.push("`#link` needs a library name".to_string());
+    } else if !links.contains(&link) {
+        links.push(link);
+    }
+}
+
+/// The C compiler flags pkg-config gives for `packages`: `--cflags`, and
+/// `--libs` too when `libs`. `PKG_CONFIG` names the program to run
+pub fn pkg_config(packages: &[String], libs: bool) -> Result<Vec<String>, String> {
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+    let program = env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string());
+    let mut command = Command::new(&program);
+    command.arg("--cflags");
+    if libs {
+        command.arg("--libs");
+    }
+    let output = command.args(packages).output().map_err(|e| format!("cannot run `{}` for {}: {}", program, packages.join(", "), e))?;
+    if !output.status.success() {
+        return Err(format!("{}: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(split_flags(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// pkg-config output split into flags; `\ ` is a space inside a flag
+fn split_flags(output: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut flag = String::new();
+    let mut chars = output.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => flag.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !flag.is_empty() {
+                    flags.push(std::mem::take(&mut flag));
+                }
+            }
+            c => flag.push(c),
+        }
+    }
+    if !flag.is_empty() {
+        flags.push(flag);
+    }
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_link_directives_are_collected() {
+        let (tokens, links, errors) = collect(&tokenize("#link \"m\"\n@link(\"sqlite3\", pkg_config = \"sdl2\") int open_db(void);\n#link \"-framework Cocoa\" // macOS\n#link \"m\"\nint main() { return 0; }"));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let library = |name: &str| Link::Library(name.to_string());
+        assert_eq!(links, vec![library("m"), library("sqlite3"), Link::PkgConfig("sdl2".to_string()), library("-framework Cocoa")]);
+        assert_eq!(detokenize(&tokens), "\nint open_db(void);\n\n\nint main() { return 0; }");
+        assert_eq!(library("-framework Cocoa").arguments(), vec!["-framework", "Cocoa"]);
+        assert_eq!(library("sqlite3").arguments(), vec!["-lsqlite3"]);
+        assert_eq!(links[2].to_string(), "@link(pkg_config = \"sdl2\")");
+
+        let (_, _, errors) = collect(&tokenize("#link m\n@link(sqlite3)\n@link(pkg = \"x\")"));
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn test_pkg_config_output_is_split() {
+        assert_eq!(split_flags("-I/usr/include/SDL2 -D_REENTRANT -I/opt/my\\ libs  -lSDL2\n"), vec!["-I/usr/include/SDL2", "-D_REENTRANT", "-I/opt/my libs", "-lSDL2"]);
+    }
+}
@@ -0,0 +1,216 @@
+// src/lint.rs
+//
+// `z-lang lint --native`: the C compiler (`-fsyntax-only -Wall -Wextra`) or
+// clang-tidy looks over the generated C for what the Z checks don't model
+// yet, and what it finds is reported where it is in the Z source.
+//
+// A finding is placed by the function it is in. The generated C keeps the
+// lines of a function's body as they are in Z, so the finding's line in the
+// function is its line in the Z function too, and its column is where the
+// same word is on that line. Findings in code the compiler generated,
+// e.g. a class's struct, keep their place in the C.
+
+use std::collections::HashMap;
+
+use crate::crash::Symbol;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::parser::function_definitions;
+use crate::tokenizer::{line_numbers, tokenize, Token};
+
+/// A warning or error of a C tool in the generated C
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The findings in `c_file` of the output of gcc, clang or clang-tidy,
+/// whose lines read `file:line:column: warning: message`. Notes are left
+/// out, like findings in other files
+pub fn parse(output: &str, c_file: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for line in output.lines() {
+        let Some(rest) = line.strip_prefix(c_file).and_then(|rest| rest.strip_prefix(':')) else { continue };
+        let mut parts = rest.splitn(3, ':');
+        let (Some(Ok(line)), Some(Ok(column)), Some(rest)) = (parts.next().map(str::parse), parts.next().map(str::parse), parts.next()) else { continue };
+        let rest = rest.trim_start();
+        let (severity, message) = if let Some(message) = rest.strip_prefix("warning:") {
+            (Severity::Warning, message)
+        } else if let Some(message) = rest.strip_prefix("error:").or_else(|| rest.strip_prefix("fatal error:")) {
+            (Severity::Error, message)
+        } else {
+            continue;
+        };
+        findings.push(Finding { severity, message: message.trim().to_string(), line, column });
+    }
+    findings
+}
+
+/// `findings` as diagnostics placed in the Z source. `c_file` names the
+/// generated C, `symbols` are the Z functions by C name, and `source`
+/// reads a Z file
+pub fn translate(findings: &[Finding], c_file: &str, c_code: &str, symbols: &HashMap<String, Symbol>, source: impl Fn(&str) -> Option<String>) -> Vec<Diagnostic> {
+    let tokens = tokenize(c_code);
+    let lines = line_numbers(&tokens);
+    // Name, first and last line of every function of the C
+    let functions: Vec<(&str, usize, usize)> = function_definitions(&tokens)
+        .iter()
+        .filter_map(|definition| Some((definition.name(&tokens), lines[definition.start], lines[definition.body_end(&tokens)?])))
+        .collect();
+    let c_lines: Vec<&str> = c_code.lines().collect();
+    let mut z_files: HashMap<String, Option<ZFile>> = HashMap::new();
+
+    let mut diagnostics = Vec::new();
+    for finding in findings {
+        let function = functions.iter().rev().find(|(_, first, last)| (*first..=*last).contains(&finding.line));
+        let symbol = function.and_then(|(c_name, first, _)| Some((symbols.get(*c_name)?, *first)));
+        let placed = symbol.and_then(|(symbol, first)| {
+            let file = z_files.entry(symbol.file.clone()).or_insert_with(|| {
+                let text = source(&symbol.file)?;
+                Some(ZFile { functions: definitions(&text), lines: text.lines().map(str::to_string).collect() })
+            });
+            let file = file.as_ref()?;
+            let line = file.functions.get(&symbol.name)? + (finding.line - first);
+            let c_line = c_lines.get(finding.line - 1).copied().unwrap_or("");
+            let column = column(c_line, finding.column, file.lines.get(line - 1).map_or("", String::as_str));
+            Some((format!("{}:{}:{}", symbol.file, line, column), format!("{} (in `{}`)", finding.message, symbol.name)))
+        });
+        let (location, message) = placed.unwrap_or_else(|| (format!("{}:{}:{}", c_file, finding.line, finding.column), format!("{} (in generated C)", finding.message)));
+        diagnostics.push(match finding.severity {
+            Severity::Error => Diagnostic::error(&location, message),
+            Severity::Warning => Diagnostic::warning(&location, message),
+        });
+    }
+    diagnostics
+}
+
+// A Z file findings are placed in
+struct ZFile {
+    lines: Vec<String>,
+    /// The line of each function, by Z name
+    functions: HashMap<String, usize>,
+}
+
+// The column in `z_line` of the word at `c_column` of `c_line`, or of the
+// first thing on the line
+fn column(c_line: &str, c_column: usize, z_line: &str) -> usize {
+    let word: String = c_line.chars().skip(c_column.saturating_sub(1)).take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+    match z_line.find(&word).filter(|_| !word.is_empty()) {
+        Some(at) => at + 1,
+        None => z_line.len() - z_line.trim_start().len() + 1,
+    }
+}
+
+// The line of every function defined in the Z source `text`, by its Z name
+// as crash reports write it, e.g. `geo::Point::sum` or `Vec::operator+`
+fn definitions(text: &str) -> HashMap<String, usize> {
+    let tokens = tokenize(text);
+    let lines = line_numbers(&tokens);
+    let is = |i: usize, s: &str| matches!(tokens.get(i), Some(Token::Symbol(t)) if t == s);
+    let identifier = |i: usize| match tokens.get(i) {
+        Some(Token::Identifier(name)) => Some(name.as_str()),
+        _ => None,
+    };
+    // Scopes with the names they add, and the brace depth they opened at
+    let mut scopes: Vec<(Vec<String>, usize)> = Vec::new();
+    let mut pending: Option<Vec<String>> = None;
+    let mut depth = 0;
+    let mut functions = HashMap::new();
+    for i in 0..tokens.len() {
+        match &tokens[i] {
+            Token::Identifier(keyword) if keyword == "namespace" => {
+                let mut path = Vec::new();
+                let mut j = i + 1;
+                while let Some(name) = identifier(j) {
+                    path.push(name.to_string());
+                    j += if is(j + 1, "::") { 2 } else { 1 };
+                }
+                // An inline namespace's members are named as the parent's
+                let inline = i > 0 && identifier(i - 1) == Some("inline");
+                pending = Some(if inline { Vec::new() } else { path });
+            }
+            Token::Identifier(keyword) if keyword == "class" => pending = identifier(i + 1).map(|name| vec![name.to_string()]),
+            Token::Symbol(s) if s == "{" => {
+                depth += 1;
+                if let Some(names) = pending.take() {
+                    scopes.push((names, depth));
+                }
+            }
+            Token::Symbol(s) if s == "}" => {
+                if scopes.last().is_some_and(|(_, d)| *d == depth) {
+                    scopes.pop();
+                }
+                depth = depth.saturating_sub(1);
+            }
+            Token::Symbol(s) if s == ";" => pending = None,
+            Token::Identifier(name) if !matches!(name.as_str(), "if" | "while" | "for" | "switch" | "return" | "sizeof") => {
+                let (name, open) = match (name.as_str(), tokens.get(i + 1)) {
+                    ("operator", Some(Token::Symbol(op))) if is(i + 2, "(") => (format!("operator{}", op), i + 2),
+                    _ if is(i + 1, "(") => (name.clone(), i + 1),
+                    _ => continue,
+                };
+                // A type before the name, and a body after the parameters
+                let typed = i > 0 && (identifier(i - 1).is_some() || is(i - 1, "*") || is(i - 1, "&"));
+                if typed && body_follows(&tokens, open) {
+                    let mut path: Vec<&str> = scopes.iter().flat_map(|(names, _)| names.iter().map(String::as_str)).collect();
+                    path.push(&name);
+                    functions.entry(path.join("::")).or_insert(lines[i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    functions
+}
+
+// Whether a `{` follows the parentheses opening at `open`
+fn body_follows(tokens: &[Token], open: usize) -> bool {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" => depth += 1,
+            Token::Symbol(s) if s == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    let next = tokens[i + 1..].iter().find(|t| !matches!(t, Token::Newline | Token::Comment(_)));
+                    return matches!(next, Some(Token::Symbol(s)) if s == "{");
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_findings_are_placed_in_the_z_source() {
+        let output = "target/main.c: In function 'geo_Point_sum':\ntarget/main.c:5:5: warning: unused variable 'unused' [-Wunused-variable]\n    5 | int unused = 3;\ntarget/main.c:1:1: note: declared here\ntarget/main.c:9:3: error: unknown type name 'point'\n";
+        let findings = parse(output, "target/main.c");
+        assert_eq!(findings, vec![
+            Finding { severity: Severity::Warning, message: "unused variable 'unused' [-Wunused-variable]".to_string(), line: 5, column: 5 },
+            Finding { severity: Severity::Error, message: "unknown type name 'point'".to_string(), line: 9, column: 3 },
+        ]);
+
+        let c_code = "\ntypedef struct { int x; } geo_Point;\nint geo_Point_sum(geo_Point self);\nint geo_Point_sum(geo_Point self) {\nint unused = 3;\nreturn self.x;\n}\n\npoint p;\n";
+        let z_code = "namespace geo {\n  class Point {\n    int x;\n\n    int sum() {\n      int unused = 3;\n      return self.x;\n    }\n  }\n}\n";
+        let symbols = HashMap::from([("geo_Point_sum".to_string(), Symbol { name: "geo::Point::sum".to_string(), file: "geo.z".to_string() })]);
+        let diagnostics = translate(&findings, "target/main.c", c_code, &symbols, |file| (file == "geo.z").then(|| z_code.to_string()));
+        assert_eq!(diagnostics[0].to_string(), "warning: unused variable 'unused' [-Wunused-variable] (in `geo::Point::sum`)\n  --> geo.z:6:11");
+        assert_eq!(diagnostics[1].to_string(), "error: unknown type name 'point' (in generated C)\n  --> target/main.c:9:3");
+    }
+
+    #[test]
+    fn test_definitions_are_named_like_crash_reports() {
+        let functions = definitions("int helper(int a) {\n  if (a) { return 1; }\n  return 0;\n}\nnamespace a::b {\n  class Vec {\n    Vec operator+(Vec o) {\n      return o;\n    }\n  }\n  inline namespace v1 {\n    int f()\n    {\n      return helper(1);\n    }\n  }\n}\n");
+        assert_eq!(functions.get("helper"), Some(&1));
+        assert_eq!(functions.get("a::b::Vec::operator+"), Some(&7));
+        assert_eq!(functions.get("a::b::f"), Some(&12));
+        assert_eq!(functions.len(), 3);
+    }
+}
@@ -0,0 +1,231 @@
+// src/lints.rs
+//
+// Named warnings and how loud each is. Every warning the compiler gives
+// belongs to a lint of `LINTS`, and each lint is at a level: allowed, and
+// not reported, warned about, which is the default, or denied, and
+// reported as an error. Levels are set at three scopes, the innermost
+// winning:
+//
+//     [lints]                    # tarnish.toml: the whole project
+//     shadowing = "deny"
+//
+//     @allow(unreachable);       // a statement at file scope: the file
+//
+//     @allow(unused)             // before a class, a function or an
+//     static int spare() { }     // `#import`: that item
+//
+// Within a scope the attribute written last wins. The standard library's
+// files ignore the project's levels.
+
+use std::collections::HashMap;
+
+use crate::ast::Attribute;
+use crate::diagnostics::Diagnostic;
+use crate::parser::{parse_attribute, parse_import};
+use crate::tokenizer::{detokenize, Token};
+
+/// Every lint, with what it warns about
+pub const LINTS: &[(&str, &str)] = &[
+    ("unused", "private functions, fields and imports nothing uses"),
+    ("unreachable", "code after a `return`, `break`, `continue` or `goto`"),
+    ("shadowing", "locals that shadow a parameter, a field or another local"),
+    ("incomplete_switch", "switches on an enum without a `default` that miss variants"),
+    ("format", "printf-style calls given more or fewer arguments than their format uses"),
+    ("recursive_inline", "inline functions that call themselves"),
+    ("missing_header", "`#import <c:...>` headers that can't be found to check calls against"),
+    ("unexported_inline", "`@inline` methods of rc classes a library's header leaves out"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// The level `@allow`, `@deny` or a `[lints]` value names
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The level of each lint; those not set are warned about
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Levels {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl Levels {
+    pub fn level(&self, lint: &str) -> Level {
+        self.levels.get(lint).copied().unwrap_or(Level::Warn)
+    }
+
+    pub fn set(&mut self, lint: &str, level: Level) -> Result<(), String> {
+        let lint = find(lint).ok_or_else(|| unknown(lint))?;
+        self.levels.insert(lint, level);
+        Ok(())
+    }
+
+    /// These levels overridden by the `@allow` and `@deny` of `attributes`,
+    /// those of an item inside their scope. Unknown lints are left to
+    /// `check`
+    pub fn within(&self, attributes: &[Attribute]) -> Levels {
+        let mut levels = self.clone();
+        for attribute in attributes {
+            let Some(level) = Level::parse(&attribute.name).filter(|level| *level != Level::Warn) else { continue };
+            for name in names(attribute) {
+                let _ = levels.set(&name, level);
+            }
+        }
+        levels
+    }
+}
+
+/// A warning of `lint` found inside items written with `attributes`,
+/// innermost last
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: &'static str,
+    pub message: String,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Warning {
+    pub fn new(lint: &'static str, message: impl Into<String>) -> Warning {
+        debug_assert!(find(lint).is_some(), "`{}` is not in `LINTS`", lint);
+        Warning { lint, message: message.into(), attributes: Vec::new() }
+    }
+
+    pub fn within(mut self, attributes: &[Attribute]) -> Warning {
+        self.attributes.extend(attributes.iter().cloned());
+        self
+    }
+}
+
+/// What `lint` at `level` reports in `file`: nothing when it is allowed
+pub fn diagnostic(file: &str, lint: &'static str, level: Level, message: impl Into<String>) -> Option<Diagnostic> {
+    match level {
+        Level::Allow => None,
+        Level::Warn => Some(Diagnostic::warning(file, message).with_lint(lint)),
+        Level::Deny => Some(Diagnostic::error(file, message).with_lint(lint)),
+    }
+}
+
+/// Checks an `@allow` or `@deny` names known lints
+pub fn check(attribute: &Attribute) -> Result<(), String> {
+    let names = names(attribute);
+    let words = attribute.args.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_) | Token::Symbol(_))).count();
+    if names.is_empty() || names.len() != words {
+        let name = &attribute.name;
+        return Err(format!("`@{}` takes the lints it applies to, e.g. `@{}(unused)`, not `{}`", name, name, detokenize(&attribute.args).trim()));
+    }
+    names.iter().find(|name| find(name).is_none()).map_or(Ok(()), |name| Err(unknown(name)))
+}
+
+/// The lints an `@allow` or `@deny` names
+fn names(attribute: &Attribute) -> Vec<String> {
+    attribute.args.iter().filter_map(|t| match t {
+        Token::Identifier(name) => Some(name.clone()),
+        _ => None,
+    }).collect()
+}
+
+fn find(lint: &str) -> Option<&'static str> {
+    LINTS.iter().map(|(name, _)| *name).find(|name| *name == lint)
+}
+
+fn unknown(lint: &str) -> String {
+    let names: Vec<String> = LINTS.iter().map(|(name, _)| format!("`{}`", name)).collect();
+    format!("unknown lint `{}`; the lints are {}", lint, names.join(", "))
+}
+
+/// Removes the `@allow(...);` and `@deny(...);` statements at file scope
+/// from `tokens` and returns their attributes, in order
+pub fn file_attributes(tokens: &mut Vec<Token>) -> Vec<Attribute> {
+    let mut attributes = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" || s == "(" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == "}" || s == ")" || s == "]" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let statement = match parse_attribute(tokens, i) {
+            Some((attribute, next)) if depth == 0 && Level::parse(&attribute.name).is_some() => {
+                let end = (next..tokens.len()).find(|&j| !matches!(tokens[j], Token::Newline | Token::Comment(_)));
+                end.filter(|&end| tokens[end] == Token::Symbol(";".to_string())).map(|end| (attribute, end))
+            }
+            _ => None,
+        };
+        match statement {
+            Some((attribute, end)) => {
+                attributes.push(attribute);
+                tokens.drain(i..=end);
+            }
+            None => i += 1,
+        }
+    }
+    attributes
+}
+
+/// The attributes written before each `#import`, by the path imported
+pub fn import_attributes(tokens: &[Token]) -> HashMap<String, Vec<Attribute>> {
+    let mut imports = HashMap::new();
+    let mut attributes = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some((attribute, next)) = parse_attribute(tokens, i) {
+            attributes.push(attribute);
+            i = next;
+            continue;
+        }
+        if let Some((import, next)) = parse_import(tokens, i) {
+            imports.insert(import.path, std::mem::take(&mut attributes));
+            i = next;
+            continue;
+        }
+        if !matches!(tokens[i], Token::Newline | Token::Comment(_)) {
+            attributes.clear();
+        }
+        i += 1;
+    }
+    imports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    fn attribute(text: &str) -> Attribute {
+        parse_attribute(&tokenize(text), 0).unwrap().0
+    }
+
+    #[test]
+    fn test_inner_levels_win() {
+        let mut project = Levels::default();
+        project.set("shadowing", Level::Deny).unwrap();
+        assert!(project.set("shadows", Level::Deny).unwrap_err().starts_with("unknown lint `shadows`; the lints are `unused`, "));
+
+        let mut tokens = tokenize("@allow(shadowing, unused);\nint f() { @deny(unused); }\n@allow(unused)\n#import <a.z>\n@deny(format);\n");
+        let file = project.within(&file_attributes(&mut tokens));
+        assert_eq!(detokenize(&tokens).trim(), detokenize(&tokenize("int f() { @deny(unused); }\n@allow(unused)\n#import <a.z>")).trim());
+        assert_eq!((file.level("shadowing"), file.level("unused"), file.level("format")), (Level::Allow, Level::Allow, Level::Deny));
+
+        let item = file.within(&import_attributes(&tokens)["a.z"]).within(&[attribute("@deny(unused)")]);
+        assert_eq!((item.level("unused"), item.level("unreachable")), (Level::Deny, Level::Warn));
+        assert!(diagnostic("a.z", "unused", Level::Allow, "x").is_none());
+        assert!(diagnostic("a.z", "unused", Level::Deny, "x").unwrap().is_error());
+
+        assert_eq!(check(&attribute("@allow(unused, format)")), Ok(()));
+        assert_eq!(check(&attribute("@deny(\"unused\")")), Err("`@deny` takes the lints it applies to, e.g. `@deny(unused)`, not `\"unused\"`".to_string()));
+        assert!(check(&attribute("@allow(dead_code)")).unwrap_err().starts_with("unknown lint `dead_code`"));
+    }
+}
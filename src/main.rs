@@ -1,23 +1,283 @@
-use z_lang::{compile, DEBUG};
+use z_lang::api::{c_header, exported, header_guard, interface, rust_bindings};
+use z_lang::cfg::Cfg;
+use z_lang::compdb::{self, Entry};
+use z_lang::diagnostics;
+use z_lang::ice::{self, Ice};
+use z_lang::install::Layout;
+use z_lang::links::{self, Link};
+use z_lang::lint;
+use z_lang::manifest::{editions, Manifest};
+use z_lang::pretty;
+use z_lang::profile::{Pgo, Profile};
+use z_lang::provenance::Provenance;
+use z_lang::stdlib;
+use z_lang::target_dir::{self, TargetDir};
+use z_lang::testing::{self, Outcome};
+use z_lang::timing::{measure, report, CountingAlloc};
+use z_lang::toolchain::{self, Kind, Toolchain};
+use z_lang::usage;
+use z_lang::{Compiler, Diagnostic, Edition, Options, Session, DEBUG};
 use std::fs;
 use std::env;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Mutex, PoisonError};
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Run before the program is compiled, see `run_build_script`
+const BUILD_SCRIPT: &str = "build.z";
+
+// Exit statuses, which scripts and build tools can rely on; 0 is success
+/// Errors in the program, its configuration or the command line
+const EXIT_ERRORS: i32 = 1;
+/// The compiler itself failed, see `report_ice`
+const EXIT_INTERNAL: i32 = 2;
+/// The C compiler, archiver or linter failed or could not be started
+const EXIT_TOOLCHAIN: i32 = 101;
+
+#[derive(PartialEq)]
+enum CrateType {
+    Bin,
+    /// `lib<name>.a` plus `<name>.h`
+    Staticlib,
+    /// `lib<name>.so` (`.dylib`, `.dll`) plus a `<name>.h` of its `@export`
+    /// functions
+    Cdylib,
+}
+
+/// What `lint --native` runs over the generated C
+enum Native {
+    /// The C compiler, `-fsyntax-only -Wall -Wextra`
+    Compiler,
+    ClangTidy,
+}
+
+/// Where in the compiler the last panic was, for reporting it
+static PANIC_LOCATION: Mutex<Option<String>> = Mutex::new(None);
 
 fn main() {
+    // A panic is a bug in the compiler, not in the program, and is reported
+    // as one instead of with Rust's message and backtrace. The passes'
+    // panics end their compilation, see `report_ice`; the rest end up here
+    std::panic::set_hook(Box::new(|info| {
+        *PANIC_LOCATION.lock().unwrap_or_else(PoisonError::into_inner) = info.location().map(|l| l.to_string());
+    }));
+    if let Err(payload) = std::panic::catch_unwind(build) {
+        eprintln!("error: internal compiler error: {}", ice::panic_message(payload.as_ref()));
+        eprintln!("note: this is a bug in the compiler ({}), not in the program", usage::version());
+        if let Some(location) = PANIC_LOCATION.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            eprintln!("note: it panicked at {}", location);
+        }
+        std::process::exit(EXIT_INTERNAL);
+    }
+}
+
+fn build() {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|a| a == "install") {
+        install(&args[2..]);
+        return;
+    }
     let mut gcc_args: Vec<String> = Vec::new();
+    let mut time_report = false;
+    let mut crate_type = CrateType::Bin;
+    let mut emit_rust_bindings = false;
+    let mut emit_interface = false;
+    let mut emit_callgraph = false;
+    let mut emit_graph = false;
+    let mut emit_compile_commands = false;
+    let mut bounds_check = false;
+    let mut overflow_checks = false;
+    let mut union_checks = false;
+    let mut panic_trace = false;
+    let mut crash_report = false;
+    let mut bundle = false;
+    let mut preserve_whitespace = false;
+    let mut keep_comments = false;
+    let mut strict = false;
+    let mut reproducible = false;
+    let mut ice_repro = false;
+    let mut edition = None;
+    let mut features: Vec<String> = Vec::new();
+    let mut no_default_features = false;
+    let mut target_dir = None;
+    let mut profile = None;
+    // `z-lang clean` removes the build directory
+    let clean = args.get(1).is_some_and(|a| a == "clean");
+    // `z-lang lint` checks the program without building it
+    let lint = args.get(1).is_some_and(|a| a == "lint");
+    // `z-lang test` builds and runs the tests instead, see `run_tests`
+    let test = args.get(1).is_some_and(|a| a == "test");
+    let mut native = None;
 
-    let source = fs::read_to_string("main.z");
-    let c_code = compile(source.unwrap().as_str());
-    if DEBUG {println!("{}", c_code)};
-
-    let mut path: String = "out".to_string();
     let mut main: String = "out".to_string();
-    for (i, arg) in args.iter().enumerate() {
-        if i == 0 {
+    let mut args_iter = args.iter().skip(if clean || lint || test { 2 } else { 1 });
+    while let Some(arg) = args_iter.next() {
+        if arg == "--help" {
+            print!("{}", usage::help());
+            return;
+        }
+
+        if arg == "--version" {
+            println!("{}", usage::version());
+            return;
+        }
+
+        if arg == "--print" {
+            print(args_iter.next().map(String::as_str).unwrap_or(""));
+            return;
+        }
+
+        // The rest is the C compiler's, as it is
+        if arg == "--" {
+            gcc_args.extend(args_iter.by_ref().cloned());
+            break;
+        }
+
+        if arg == "--time-report" {
+            time_report = true;
+            continue;
+        }
+
+        if arg == "--bounds-check" {
+            bounds_check = true;
+            continue;
+        }
+
+        if arg == "--overflow-checks" {
+            overflow_checks = true;
+            continue;
+        }
+
+        if arg == "--union-checks" {
+            union_checks = true;
+            continue;
+        }
+
+        if arg == "--panic-trace" {
+            panic_trace = true;
+            continue;
+        }
+
+        if arg == "--crash-report" {
+            crash_report = true;
+            continue;
+        }
+
+        if arg == "--bundle" {
+            bundle = true;
+            continue;
+        }
+
+        if arg == "--preserve-whitespace" {
+            preserve_whitespace = true;
+            continue;
+        }
+
+        if arg == "--keep-comments" {
+            keep_comments = true;
+            continue;
+        }
+
+        if arg == "--strict" {
+            strict = true;
+            continue;
+        }
+
+        if arg == "--reproducible" {
+            reproducible = true;
+            continue;
+        }
+
+        if arg == "--ice-repro" {
+            ice_repro = true;
+            continue;
+        }
+
+        if lint && arg.starts_with("--native") {
+            native = match arg.as_str() {
+                "--native" => Some(Native::Compiler),
+                "--native=clang-tidy" => Some(Native::ClangTidy),
+                _ => {
+                    eprintln!("error: unknown native linter `{}`, expected `--native` or `--native=clang-tidy`", arg);
+                    std::process::exit(EXIT_ERRORS);
+                }
+            };
+            continue;
+        }
+
+        if arg == "--edition" {
+            let name = args_iter.next().map(String::as_str).unwrap_or("");
+            edition = match Edition::parse(name) {
+                Some(e) => Some(e),
+                None => {
+                    eprintln!("error: unknown edition {:?}, expected {}", name, editions());
+                    std::process::exit(EXIT_ERRORS);
+                }
+            };
+            continue;
+        }
+
+        if arg == "--features" {
+            let list = args_iter.next().map(String::as_str).unwrap_or("");
+            features.extend(list.split([',', ' ']).filter(|f| !f.is_empty()).map(str::to_string));
             continue;
         }
 
+        if arg == "--no-default-features" {
+            no_default_features = true;
+            continue;
+        }
+
+        if arg == "--target-dir" {
+            target_dir = args_iter.next().cloned();
+            continue;
+        }
+
+        if arg == "--profile" {
+            profile = args_iter.next().cloned();
+            continue;
+        }
+
+        if arg == "--crate-type" {
+            crate_type = match args_iter.next().map(String::as_str) {
+                Some("bin") => CrateType::Bin,
+                Some("staticlib") => CrateType::Staticlib,
+                Some("cdylib") => CrateType::Cdylib,
+                other => {
+                    eprintln!("error: unknown crate type {:?}, expected `bin`, `staticlib` or `cdylib`", other.unwrap_or(""));
+                    std::process::exit(EXIT_ERRORS);
+                }
+            };
+            continue;
+        }
+
+        if arg == "--emit" {
+            match args_iter.next().map(String::as_str) {
+                Some("rust-bindings") => emit_rust_bindings = true,
+                Some("interface") => emit_interface = true,
+                Some("callgraph") => emit_callgraph = true,
+                Some("graph") => emit_graph = true,
+                Some("compile-commands") => emit_compile_commands = true,
+                other => {
+                    eprintln!("error: unknown emit kind {:?}, expected `rust-bindings`, `interface`, `callgraph`, `graph` or `compile-commands`", other.unwrap_or(""));
+                    std::process::exit(EXIT_ERRORS);
+                }
+            }
+            continue;
+        }
+
+        if arg.starts_with("--") {
+            if arg.starts_with("--native") {
+                eprintln!("error: `{}` goes with `z-lang lint`", arg);
+            } else {
+                eprintln!("error: {}", usage::unknown_flag(arg));
+            }
+            std::process::exit(EXIT_ERRORS);
+        }
+
         if arg.ends_with(".z") {
             if arg == "main.z" {
                 main = arg.clone();
@@ -28,22 +288,681 @@ fn main() {
             continue;
         }
 
-        if arg == "-o" {
-            path = args[i + 1].clone();
+        gcc_args.push(arg.to_string());
+    }
+
+    let manifest = match Manifest::load(Path::new(".")) {
+        Ok(manifest) => manifest.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    let edition = match edition.map(Ok).unwrap_or_else(|| manifest.edition()) {
+        Ok(edition) => edition,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    let target = match target_dir.map(Ok).unwrap_or_else(|| manifest.target_dir().map(|d| d.unwrap_or(target_dir::DEFAULT).to_string())) {
+        Ok(dir) => TargetDir::new(dir),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    if clean {
+        match target.clean() {
+            Ok(true) => println!("removed {}", target.root().display()),
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(EXIT_ERRORS);
+            }
+        }
+        return;
+    }
+
+    let features = match manifest.features(&features, no_default_features) {
+        Ok(features) => features,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
         }
+    };
 
-        gcc_args.push(arg.to_string());
+    let limits = match manifest.limits() {
+        Ok(limits) => limits,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let lints = match manifest.lints() {
+        Ok(lints) => lints,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let packages = match manifest.pkg_config() {
+        Ok(packages) => packages,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let profile = match profile.map(|name| Profile::named(&name, &manifest)).transpose() {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    if let Some(profile) = &profile {
+        // Before the flags on the command line, which override them
+        gcc_args.splice(0..0, profile.c_flags());
+        bounds_check |= profile.bounds_check;
+        overflow_checks |= profile.overflow_checks;
+        union_checks |= profile.union_checks;
+        panic_trace |= profile.panic_trace;
+        crash_report |= profile.crash_report;
+    }
+
+    // An installed compiler's standard library and the user's settings
+    let layout = env::current_exe().ok().and_then(|exe| Layout::of_executable(&exe));
+    let config = match layout.as_ref().map(Layout::load_config).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let cfg = Cfg { features: features.enabled, ..Cfg::host() };
+    let mut options = Options { entry_point: crate_type == CrateType::Bin || test, bounds_check, overflow_checks, union_checks, panic_trace, crash_report, preserve_whitespace, keep_comments, edition, cfg, std_modules: features.std_modules, generated: None, std_dir: layout.as_ref().map(Layout::lib), limits, test, lints, strict };
+    let toolchain = Toolchain::detect(config.get("toolchain", "cc").and_then(|cc| cc.as_str()));
+    if let Some(profile) = profile.as_ref().filter(|p| p.pgo) {
+        let problem = if crate_type != CrateType::Bin {
+            Some("builds programs, not libraries".to_string())
+        } else if toolchain.kind == Kind::Msvc {
+            Some(format!("needs gcc or clang, not `{}`", toolchain.name()))
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            eprintln!("error: profile `{}` uses profile-guided optimization, which {}", profile.name, problem);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+    if Path::new(BUILD_SCRIPT).exists() {
+        options.generated = Some(run_build_script(&options, &target, &toolchain));
+    }
+    let generated = options.generated.clone();
+    let mut session = Session::default();
+    if let Err(e) = fs::metadata("main.z").map_or(Ok(()), |m| limits.check_file_size("main.z", m.len())) {
+        eprintln!("error: {}", e);
+        std::process::exit(EXIT_ERRORS);
+    }
+    let source = match fs::read_to_string("main.z") {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: cannot read main.z: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+    let compiler = Compiler::builder().options(options.clone()).build();
+    let c_code = compiler.compile_in(&mut session, "main.z", &source);
+    if DEBUG {println!("{}", c_code)};
+
+    for diagnostic in &session.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if let Some(ice) = session.ice.take() {
+        report_ice(ice, &compiler, ice_repro.then(|| target.path("ice-repro.z")));
     }
+    // `lint --native` sums up what the C tools find as well
+    if !(lint && native.is_some()) || session.has_errors() {
+        summarize(&session.diagnostics);
+    }
+    if session.has_errors() {
+        std::process::exit(EXIT_ERRORS);
+    }
+    // `#import <c:...>` headers of the project itself
+    if session.local_headers {
+        gcc_args.push("-I.".to_string());
+    }
+    let c_code = Provenance::of(&session, &options, reproducible).banner() + &c_code;
+
+    // The manifest's packages go with the ones files ask for, into interfaces too
+    for package in packages {
+        session.link(Link::PkgConfig(package));
+    }
+    let packages: Vec<String> = session.links.iter().filter_map(|link| match link {
+        Link::PkgConfig(package) => Some(package.clone()),
+        Link::Library(_) => None,
+    }).collect();
+    let pkg_config = |libs: bool| match links::pkg_config(&packages, libs) {
+        Ok(flags) => flags,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    let name = main.trim_end_matches(".z").to_string();
+    if lint {
+        let Some(native) = native else { return };
+        if let Err(e) = target.create() {
+            eprintln!("error: cannot create {}: {}", target.root().display(), e);
+            std::process::exit(EXIT_ERRORS);
+        }
+        let c_file = format!("{}.c", name);
+        write_file(target.scratch(&c_file), &c_code);
+        let c_path = target.scratch(&c_file).display().to_string();
+        // Include directories and defines of the build
+        let mut flags: Vec<String> = gcc_args.iter().filter(|a| a.starts_with("-I") || a.starts_with("-D") || a.starts_with("-std")).cloned().collect();
+        flags.extend(pkg_config(false));
+        let (mut command, program, hint) = match native {
+            Native::Compiler if toolchain.kind == Kind::Msvc => {
+                eprintln!("error: `lint --native` needs gcc or clang, not `{}`; try `--native=clang-tidy`", toolchain.name());
+                std::process::exit(EXIT_ERRORS);
+            }
+            Native::Compiler => {
+                let mut args = vec![c_path.clone(), "-fsyntax-only".to_string(), "-Wall".to_string(), "-Wextra".to_string()];
+                args.extend(flags);
+                (toolchain.command(&args), toolchain.name().to_string(), "install gcc, clang or MSVC, or name a C compiler with `CC`")
+            }
+            Native::ClangTidy => {
+                let program = env::var("CLANG_TIDY").unwrap_or_else(|_| "clang-tidy".to_string());
+                let mut command = Command::new(&program);
+                command.arg(&c_path).arg("--").args(&flags);
+                (command, program, "install clang-tidy, or name the program with `CLANG_TIDY`")
+            }
+        };
+        let output = command.output();
+        let _ = fs::remove_file(&c_path);
+        let output = output.unwrap_or_else(|e| {
+            eprintln!("error: cannot run `{}`: {}; {}", program, e, hint);
+            std::process::exit(EXIT_TOOLCHAIN);
+        });
+        let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        let std_dir = layout.as_ref().map(Layout::lib);
+        let source = |file: &str| stdlib::load(file, std_dir.as_deref()).or_else(|| fs::read_to_string(file).ok()).or_else(|| fs::read_to_string(generated.as_ref()?.join(file)).ok());
+        let diagnostics = lint::translate(&lint::parse(&text, &c_path), &c_path, &c_code, &session.symbols, source);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        summarize(session.diagnostics.iter().chain(&diagnostics));
+        if diagnostics.iter().any(|d| d.is_error()) {
+            std::process::exit(EXIT_ERRORS);
+        }
+        return;
+    }
+    if test {
+        let mut flags = gcc_args.clone();
+        flags.extend(pkg_config(true));
+        run_tests(&c_code, &session, &options, &toolchain, &target, &flags);
+        return;
+    }
+    if emit_rust_bindings {
+        let link = if crate_type == CrateType::Staticlib { Some(name.as_str()) } else { None };
+        write_file(name.clone() + ".rs", rust_bindings(&session.api, link));
+    }
+    if emit_interface {
+        write_file(name.clone() + ".zi", interface(&session.api, &session.known_classes, &session.links));
+    }
+    if emit_callgraph {
+        write_file("callgraph.dot", session.call_graph.to_dot());
+    }
+    if emit_graph {
+        write_file("imports.dot", session.graph.imports_dot());
+        write_file("classes.dot", session.graph.classes_dot(&session.interfaces));
+    }
+
+    if bundle {
+        let file = gcc_args.iter().skip_while(|a| *a != "-o").nth(1).cloned().unwrap_or(format!("{}.c", name));
+        // `-I dir` and `-Idir`, then the files of `build.z`
+        let mut include_dirs: Vec<PathBuf> = gcc_args.iter().zip(gcc_args.iter().skip(1)).filter(|(a, _)| *a == "-I").map(|(_, dir)| PathBuf::from(dir)).collect();
+        include_dirs.extend(gcc_args.iter().filter_map(|a| a.strip_prefix("-I")).filter(|dir| !dir.is_empty()).map(PathBuf::from));
+        include_dirs.extend(generated);
+        let bundled = z_lang::bundle::bundle(&c_code, Path::new("."), &include_dirs);
+        for header in &bundled.external {
+            eprintln!("warning: the bundle includes {}, which is not part of the C library; it is needed where the bundle is built", header);
+        }
+        let mut link: Vec<String> = session.links.iter().flat_map(Link::arguments).collect();
+        link.extend(packages.iter().map(|package| format!("$(pkg-config --cflags --libs {})", package)));
+        link.push("-lm".to_string());
+        let command = match crate_type {
+            CrateType::Bin => format!("cc {} {} -o {}", file, link.join(" "), name),
+            CrateType::Staticlib => format!("cc -c {}", file),
+            CrateType::Cdylib => format!("cc -shared -fPIC {} {} -o {}", file, link.join(" "), toolchain.shared_library(&name)),
+        };
+        let preamble = format!("/* {}: {} and everything it imports, bundled by the Tarnish compiler.\n * Build it with a C compiler alone: {} */\n\n", file, main, command);
+        write_file(&file, preamble + &bundled.c_code);
+        println!("bundled {}", file);
+        return;
+    }
+
+    if let Err(e) = target.create() {
+        eprintln!("error: cannot create {}: {}", target.root().display(), e);
+        std::process::exit(EXIT_ERRORS);
+    }
+    let c_file = format!("{}.c", name);
+    write_file(target.scratch(&c_file), c_code);
+
+    if crate_type == CrateType::Staticlib {
+        let header = format!("{}.h", name);
+        write_file(&header, c_header(&header_guard(&header), &session.api));
+
+        let object = toolchain.object(&name);
+        // Include directories and defines; the libraries are linked by the library's users
+        gcc_args.extend(pkg_config(false));
+        gcc_args.extend(["-c".to_string(), target.scratch(&c_file).display().to_string(), "-o".to_string(), target.scratch(&object).display().to_string()]);
+        if emit_compile_commands {
+            write_compile_commands(&toolchain, &gcc_args, &target, &[&c_file, &object]);
+        }
+        let (gcc_output, gcc_timing) = measure("<native>", toolchain.name(), || run(toolchain.command(&gcc_args), toolchain.name()));
+        session.timings.push(gcc_timing);
+        let (ar_output, ar_timing) = measure("<native>", "ar", || run(toolchain.archive(&toolchain.static_library(&name), &target.scratch(&object)), "ar"));
+        session.timings.push(ar_timing);
+        let _ = target.finish(&c_file);
+        let _ = target.finish(&object);
 
-    gcc_args.push(main.clone() + ".c");
+        if time_report {
+            eprint!("{}", report(&session.timings));
+        }
+        eprint!("{}{}", String::from_utf8_lossy(&gcc_output.stderr), String::from_utf8_lossy(&ar_output.stderr));
+        if !gcc_output.status.success() || !ar_output.status.success() {
+            std::process::exit(EXIT_TOOLCHAIN);
+        }
+        return;
+    }
+
+    if crate_type == CrateType::Cdylib {
+        if session.exports.is_empty() {
+            eprintln!("warning: the library exports nothing; mark the functions it exports with `@export`");
+        }
+        let header = format!("{}.h", name);
+        write_file(&header, c_header(&header_guard(&header), &exported(&session.api, &session.exports)));
+        // Only `@export` functions are visible outside the library
+        gcc_args.extend(["-shared", "-fPIC", "-fvisibility=hidden"].map(str::to_string));
+        if !gcc_args.iter().any(|a| a == "-o") {
+            gcc_args.extend(["-o".to_string(), toolchain.shared_library(&name)]);
+        }
+    }
+
+    // The names of an instrumented program's recordings come from its C
+    // file and binary, so PGO builds use the final names from the start
+    let pgo = profile.as_ref().and_then(|p| p.pgo(&target.path("pgo").join(&p.name)));
+    if let Some(pgo) = &pgo {
+        gcc_args.extend(pgo_flags(pgo, &toolchain));
+        let _ = target.finish(&c_file);
+    }
+    let c_path = if pgo.is_some() { target.path(&c_file) } else { target.scratch(&c_file) };
+
+    // Before any `-l` flags, so libraries resolve symbols the program uses
+    gcc_args.insert(0, c_path.display().to_string());
+    // What the program's files ask for with `#link`
+    gcc_args.extend(session.links.iter().flat_map(Link::arguments));
+    gcc_args.extend(pkg_config(true));
+    // std/math.z needs libm; linking it unused is harmless
+    gcc_args.push("-lm".to_string());
+    // Without `-o` the binary goes to the build directory instead of `a.out`
+    let binary = (!gcc_args.iter().any(|a| a == "-o")).then(|| toolchain::executable(&name));
+    if let Some(binary) = &binary {
+        let path = if pgo.is_some() { target.path(binary) } else { target.scratch(binary) };
+        gcc_args.extend(["-o".to_string(), path.display().to_string()]);
+    }
 
     println!("{:?}", gcc_args);
+    if emit_compile_commands {
+        let files: Vec<&str> = [c_file.as_str()].into_iter().chain(binary.as_deref()).collect();
+        write_compile_commands(&toolchain, &gcc_args, &target, &files);
+    }
 
-    let _ = fs::write(main + ".c", c_code);
-    let gcc_output = Command::new("gcc").args(gcc_args).output().expect("Failed to execute command");
+    let (gcc_output, gcc_timing) = measure("<native>", toolchain.name(), || run(toolchain.command(&gcc_args), toolchain.name()));
+    session.timings.push(gcc_timing);
+    if pgo.is_none() {
+        let _ = target.finish(&c_file);
+        if let Some(binary) = &binary {
+            let _ = target.finish(binary);
+        }
+    }
+    // Debugger pretty-printers go next to a program with debug info
+    let debug_info = gcc_args.iter().any(|a| a.starts_with("-g") || a == "/Zi");
+    if debug_info && gcc_output.status.success() && !session.printers.is_empty() {
+        let program = match &binary {
+            Some(binary) => target.path(binary),
+            None => PathBuf::from(gcc_args.iter().skip_while(|a| *a != "-o").nth(1).cloned().unwrap_or_default()),
+        };
+        let file_name = program.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        write_file(format!("{}-gdb.py", program.display()), pretty::gdb_script(&file_name, &session.printers));
+        write_file(format!("{}.lldb.py", program.display()), pretty::lldb_script(&file_name, &session.printers));
+    }
+    match (&pgo, &profile) {
+        (Some(Pgo::Generate(_)), Some(profile)) if gcc_output.status.success() => {
+            eprintln!("note: the program records a profile when it runs; run it on typical input, then build again with `--profile {}` to optimize for it", profile.name);
+        }
+        (Some(Pgo::Use(dir)), _) => eprintln!("note: optimized with the profile recorded in {}", dir.display()),
+        _ => {}
+    }
+
+    if time_report {
+        eprint!("{}", report(&session.timings));
+    }
+
+    eprint!("{}", String::from_utf8_lossy(&gcc_output.stderr));
     let stdout = String::from_utf8_lossy(&gcc_output.stdout);
-    if stdout == "".to_string() {
-        return;
+    if !stdout.is_empty() {
+        println!("GCC:\n{}", stdout);
+    }
+    if !gcc_output.status.success() {
+        std::process::exit(EXIT_TOOLCHAIN);
+    }
+}
+
+// Reports the panic of a pass with the lines of its file that reproduce
+// it, written to `repro` if given, and exits
+fn report_ice(mut ice: Ice, compiler: &Compiler, repro: Option<PathBuf>) -> ! {
+    ice.location = PANIC_LOCATION.lock().unwrap_or_else(PoisonError::into_inner).take();
+    let reproduction = ice::minimize(compiler, &ice);
+    eprint!("{}", ice.report(reproduction.as_ref()));
+    match (&reproduction, repro) {
+        (Some(reproduction), Some(repro)) => match repro.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&repro, &reproduction.text)) {
+            Ok(()) => eprintln!("note: wrote those lines to {}; please attach it to a bug report", repro.display()),
+            Err(e) => eprintln!("warning: cannot write {}: {}", repro.display(), e),
+        },
+        (Some(_), None) => eprintln!("note: `--ice-repro` writes those lines to a file to attach to a bug report"),
+        (None, _) => {}
+    }
+    std::process::exit(EXIT_INTERNAL);
+}
+
+// Writes a file of the build; one that can't be written is an error
+fn write_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) {
+    if let Err(e) = fs::write(path.as_ref(), contents) {
+        eprintln!("error: cannot write {}: {}", path.as_ref().display(), e);
+        std::process::exit(EXIT_ERRORS);
+    }
+}
+
+// Prints how many errors and warnings the build reported, if any
+fn summarize<'a>(diagnostics: impl IntoIterator<Item = &'a Diagnostic>) {
+    if let Some(summary) = diagnostics::summary(diagnostics) {
+        eprintln!("{}", summary);
+    }
+}
+
+// `z-lang install [--root <dir>]`: copies this compiler and the standard
+// library into the user's toolchain directory
+fn install(args: &[String]) {
+    let mut root = None;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        if arg == "--root" {
+            root = args_iter.next().map(Layout::new);
+            continue;
+        }
+        eprintln!("error: unexpected argument `{}` to `install`, expected `--root <dir>`", arg);
+        std::process::exit(EXIT_ERRORS);
+    }
+    let Some(layout) = root.or_else(Layout::user) else {
+        eprintln!("error: no home directory to install into; set `TARNISH_HOME` or pass `--root <dir>`");
+        std::process::exit(EXIT_ERRORS);
+    };
+    let installed = env::current_exe().map_err(|e| e.to_string()).and_then(|exe| layout.install(&exe));
+    match installed {
+        Ok(installed) => println!("installed {}", installed.display()),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    if !env::split_paths(&path).any(|dir| dir == layout.bin()) {
+        eprintln!("note: add {} to the PATH to run `z-lang` from any directory", layout.bin().display());
+    }
+}
+
+// `--print <what>`, a line per answer for scripts to read
+fn print(what: &str) {
+    let layout = env::current_exe().ok().and_then(|exe| Layout::of_executable(&exe));
+    match what {
+        // The compiler builds for the machine it runs on
+        "target-list" => println!("{}", Cfg::host().target()),
+        "stdlib-path" => match &layout {
+            Some(layout) => println!("{}", layout.std_dir().display()),
+            None => {
+                eprintln!("error: this compiler is not installed and uses the standard library built into it; `z-lang install` installs one");
+                std::process::exit(EXIT_ERRORS);
+            }
+        },
+        "cc" => {
+            let config = match layout.as_ref().map(Layout::load_config).transpose() {
+                Ok(config) => config.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(EXIT_ERRORS);
+                }
+            };
+            println!("{}", Toolchain::detect(config.get("toolchain", "cc").and_then(|cc| cc.as_str())).cc.join(" "));
+        }
+        _ => {
+            eprintln!("error: unknown `--print` {:?}, expected `target-list`, `stdlib-path` or `cc`", what);
+            std::process::exit(EXIT_ERRORS);
+        }
+    }
+}
+
+// Writes `compile_commands.json` for compiling `files[0]`, the C file, with
+// `args`, naming the files of the finished build instead of scratch files
+fn write_compile_commands(toolchain: &Toolchain, args: &[String], target: &TargetDir, files: &[&str]) {
+    let finished = |arg: &String| match files.iter().find(|file| target.scratch(file).display().to_string() == *arg) {
+        Some(file) => target.path(file).display().to_string(),
+        None => arg.clone(),
+    };
+    let directory = env::current_dir().unwrap_or_default();
+    let entry = Entry {
+        file: directory.join(target.path(files[0])),
+        arguments: toolchain.arguments(args).iter().map(finished).collect(),
+        output: args.iter().skip_while(|a| *a != "-o").nth(1).map(|output| PathBuf::from(finished(output))),
+        directory,
+    };
+    write_file("compile_commands.json", compdb::to_json(&[entry]));
+}
+
+// The flags of a step of profile-guided optimization. Recordings go to an
+// absolute path, as the program may run from anywhere; clang's are merged
+// into the `default.profdata` it reads before they are used
+fn pgo_flags(pgo: &Pgo, toolchain: &Toolchain) -> Vec<String> {
+    let fail = |message: String| -> ! {
+        eprintln!("error: {}", message);
+        std::process::exit(EXIT_ERRORS);
+    };
+    let tool_failed = |message: String| -> ! {
+        eprintln!("error: {}", message);
+        std::process::exit(EXIT_TOOLCHAIN);
+    };
+    let (Pgo::Generate(dir) | Pgo::Use(dir)) = pgo;
+    if let Err(e) = fs::create_dir_all(dir) {
+        fail(format!("cannot create {}: {}", dir.display(), e));
+    }
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+    match pgo {
+        Pgo::Generate(_) => vec![format!("-fprofile-generate={}", dir.display())],
+        Pgo::Use(_) if toolchain.is_clang() => {
+            let program = env::var("LLVM_PROFDATA").unwrap_or_else(|_| "llvm-profdata".to_string());
+            let recordings = fs::read_dir(&dir).into_iter().flatten().flatten().map(|e| e.path()).filter(|p| p.extension().is_some_and(|ext| ext == "profraw"));
+            let mut command = Command::new(&program);
+            command.arg("merge").arg("-o").arg(dir.join("default.profdata")).args(recordings);
+            let output = command.output().unwrap_or_else(|e| tool_failed(format!("cannot run `{}`: {}; install LLVM's tools or name the program with `LLVM_PROFDATA`", program, e)));
+            if !output.status.success() {
+                tool_failed(format!("{}: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            vec![format!("-fprofile-use={}", dir.display())]
+        }
+        // Threads make gcc's counts slightly inconsistent; it evens them out
+        Pgo::Use(_) => vec![format!("-fprofile-use={}", dir.display()), "-fprofile-correction".to_string()],
+    }
+}
+
+// `z-lang test`: builds the program's `@test` functions and runs each, then
+// each example in the doc comments of its files as a program of its own.
+// `flags` are for the C compiler
+fn run_tests(c_code: &str, session: &Session, options: &Options, toolchain: &Toolchain, target: &TargetDir, flags: &[String]) {
+    if let Err(e) = target.create() {
+        eprintln!("error: cannot create {}: {}", target.root().display(), e);
+        std::process::exit(EXIT_ERRORS);
+    }
+    let mut results = Vec::new();
+    match build_test(c_code, &session.links, toolchain, target, flags) {
+        Ok(binary) => results.extend(session.tests.iter().map(|name| (name.clone(), run_test(&binary, name)))),
+        Err(why) => results.extend(session.tests.iter().map(|name| (name.clone(), Outcome::Failed(why.clone())))),
+    }
+
+    // The program's own files; the standard library's examples aren't its tests
+    let mut files: Vec<&String> = session.imported.iter().filter(|path| path.ends_with(".z") && stdlib::load(path, options.std_dir.as_deref()).is_none()).collect();
+    files.sort();
+    let root = "main.z".to_string();
+    files.insert(0, &root);
+    let compiler = Compiler::builder().options(options.clone()).build();
+    for file in files {
+        let Ok(source) = fs::read_to_string(options.locate(file)) else { continue };
+        for example in testing::examples(file, &source) {
+            let mut session = Session::default();
+            let c_code = compiler.compile_in(&mut session, file, &example.program(&source));
+            if let Some(ice) = session.ice.take() {
+                report_ice(ice, &compiler, None);
+            }
+            let outcome = if session.has_errors() {
+                let errors: Vec<String> = session.diagnostics.iter().filter(|d| d.is_error()).map(ToString::to_string).collect();
+                Outcome::Failed(format!("does not compile:\n{}", errors.join("\n")))
+            } else {
+                match build_test(&c_code, &session.links, toolchain, target, flags) {
+                    Ok(binary) => run_test(&binary, testing::EXAMPLE),
+                    Err(why) => Outcome::Failed(why),
+                }
+            };
+            results.push((example.name(), outcome));
+        }
+    }
+
+    print!("{}", testing::report(&results));
+    if results.iter().any(|(_, outcome)| *outcome != Outcome::Passed) {
+        std::process::exit(EXIT_ERRORS);
+    }
+}
+
+// Builds the C of a test build, or says why the C compiler couldn't
+fn build_test(c_code: &str, links: &[Link], toolchain: &Toolchain, target: &TargetDir, flags: &[String]) -> Result<PathBuf, String> {
+    let (c_file, binary) = ("tests.c".to_string(), toolchain::executable("tests"));
+    write_file(target.scratch(&c_file), c_code);
+    let mut args = vec![target.scratch(&c_file).display().to_string()];
+    args.extend(flags.iter().cloned());
+    args.extend(links.iter().flat_map(Link::arguments));
+    args.extend(["-lm".to_string(), "-o".to_string(), target.scratch(&binary).display().to_string()]);
+    let output = run(toolchain.command(&args), toolchain.name());
+    let _ = target.finish(&c_file);
+    if !output.status.success() {
+        return Err(format!("does not compile:\n{}", String::from_utf8_lossy(&output.stderr)));
+    }
+    target.finish(&binary).map_err(|e| format!("cannot build the tests: {}", e))
+}
+
+// Runs the test `name` of the test build `binary`
+fn run_test(binary: &Path, name: &str) -> Outcome {
+    let output = match Command::new(fs::canonicalize(binary).unwrap_or_else(|_| binary.to_path_buf())).arg(name).output() {
+        Ok(output) => output,
+        Err(e) => return Outcome::Failed(format!("cannot run the tests: {}", e)),
+    };
+    if output.status.success() {
+        return Outcome::Passed;
+    }
+    let how = output.status.code().map_or("was killed by a signal".to_string(), |code| format!("exited with status {}", code));
+    Outcome::Failed(format!("{}\n{}{}", how, String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)))
+}
+
+// Runs `command`, exiting when `program` can't be started
+fn run(mut command: Command, program: &str) -> Output {
+    match command.output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("error: cannot run `{}`: {}; install gcc, clang or MSVC, or name a C compiler with `CC`", program, e);
+            std::process::exit(EXIT_TOOLCHAIN);
+        }
+    }
+}
+
+// Compiles and runs `build.z` and returns the directory of the files it
+// generates. What the script prints is saved there as `generated.z`, and
+// it can write other files there itself; the program imports and embeds
+// them like its own files
+fn run_build_script(options: &Options, target: &TargetDir, toolchain: &Toolchain) -> PathBuf {
+    let fail = |message: String| -> ! {
+        eprintln!("error: {}", message);
+        std::process::exit(EXIT_ERRORS);
+    };
+    let out_dir = target.path("gen");
+    if let Err(e) = target.create().and_then(|_| fs::create_dir_all(&out_dir)) {
+        fail(format!("cannot create {}: {}", out_dir.display(), e));
+    }
+    let source = fs::read_to_string(BUILD_SCRIPT).unwrap_or_else(|e| fail(format!("cannot read {}: {}", BUILD_SCRIPT, e)));
+    let mut session = Session::default();
+    let script_options = Options { entry_point: true, ..options.clone() };
+    let compiler = Compiler::builder().options(script_options).build();
+    let c_code = compiler.compile_in(&mut session, BUILD_SCRIPT, &source);
+    for diagnostic in &session.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if let Some(ice) = session.ice.take() {
+        report_ice(ice, &compiler, None);
+    }
+    summarize(&session.diagnostics);
+    if session.has_errors() {
+        std::process::exit(EXIT_ERRORS);
+    }
+
+    let (c_file, binary) = ("build-script.c".to_string(), toolchain::executable("build-script"));
+    write_file(target.scratch(&c_file), c_code);
+    let mut args = vec![target.scratch(&c_file).display().to_string()];
+    if session.local_headers {
+        args.push("-I.".to_string());
+    }
+    args.extend(session.links.iter().flat_map(Link::arguments));
+    args.extend(["-lm".to_string(), "-o".to_string(), target.scratch(&binary).display().to_string()]);
+    let output = run(toolchain.command(&args), toolchain.name());
+    let _ = target.finish(&c_file);
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        eprintln!("error: cannot build {}", BUILD_SCRIPT);
+        std::process::exit(EXIT_TOOLCHAIN);
+    }
+    let binary = target.finish(&binary).unwrap_or_else(|e| fail(format!("cannot build {}: {}", BUILD_SCRIPT, e)));
+
+    // The script learns what it is building for from the environment
+    let cfg = &options.cfg;
+    let features: Vec<&str> = cfg.features.iter().map(String::as_str).collect();
+    let output = Command::new(fs::canonicalize(&binary).unwrap_or(binary))
+        .env("TARNISH_OUT_DIR", fs::canonicalize(&out_dir).unwrap_or(out_dir.clone()))
+        .env("TARNISH_OS", &cfg.os)
+        .env("TARNISH_FAMILY", &cfg.family)
+        .env("TARNISH_ARCH", &cfg.arch)
+        .env("TARNISH_FEATURES", features.join(","))
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap_or_else(|e| fail(format!("cannot run {}: {}", BUILD_SCRIPT, e)));
+    if !output.status.success() {
+        let code = output.status.code().map_or("a signal".to_string(), |c| format!("exit code {}", c));
+        fail(format!("{} failed with {}", BUILD_SCRIPT, code));
+    }
+    let generated = out_dir.join("generated.z");
+    if let Err(e) = fs::write(&generated, &output.stdout) {
+        fail(format!("cannot write {}: {}", generated.display(), e));
     }
-    println!("GCC:\n{}", stdout);    
+    out_dir
 }
@@ -0,0 +1,348 @@
+// src/manifest.rs
+//
+// `tarnish.toml`, the project manifest next to `main.z`. It is read with a
+// small subset of TOML: `[table]` headers, `key = value` pairs whose value
+// is a string, an integer, a boolean or an array of those, and `#` comments.
+//
+//     [package]
+//     name = "app"
+//     edition = "2024"
+//
+//     [build]
+//     target-dir = "out"
+//     pkg-config = ["sdl2"]
+//
+//     [limits]
+//     max-import-depth = 64
+//
+//     [lints]
+//     shadowing = "deny"
+//
+//     [features]
+//     default = ["gui"]
+//     gui = []
+//     server = ["std/net", "std/thread"]
+//     full = ["gui", "server"]
+//
+// A feature lists the features it turns on and the optional standard
+// library modules it provides.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::edition::Edition;
+use crate::limits::Limits;
+use crate::lints::{Level, Levels};
+use crate::stdlib;
+
+pub const FILE: &str = "tarnish.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// What the features of a build turn on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    pub enabled: BTreeSet<String>,
+    /// Optional standard library modules, e.g. `std/net.z`; `None` when the
+    /// manifest has no `[features]`, which provides them all
+    pub std_modules: Option<BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// Keys of each table; keys before the first header are in `""`
+    tables: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl Manifest {
+    /// Parses the text of a manifest; errors name the line
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        let mut table = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let error = |message: &str| format!("{}:{}: {}", FILE, n + 1, message);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header.strip_suffix(']').ok_or_else(|| error("expected `]` after the table name"))?.trim();
+                if name.is_empty() {
+                    return Err(error("empty table name"));
+                }
+                table = name.to_string();
+                manifest.tables.entry(table.clone()).or_default();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+            let key = key.trim().trim_matches('"');
+            let (value, rest) = parse_value(value.trim()).map_err(|e| error(&e))?;
+            if !rest.trim().is_empty() {
+                return Err(error(&format!("unexpected `{}` after the value of `{}`", rest.trim(), key)));
+            }
+            if manifest.tables.entry(table.clone()).or_default().insert(key.to_string(), value).is_some() {
+                return Err(error(&format!("`{}` is set twice", key)));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The manifest in `dir`, or `None` when there is none
+    pub fn load(dir: &Path) -> Result<Option<Manifest>, String> {
+        match fs::read_to_string(dir.join(FILE)) {
+            Ok(text) => Manifest::parse(&text).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn get(&self, table: &str, key: &str) -> Option<&Value> {
+        self.tables.get(table)?.get(key)
+    }
+
+    /// The keys of `table`, e.g. of `profile.release`
+    pub fn table(&self, name: &str) -> Option<&BTreeMap<String, Value>> {
+        self.tables.get(name)
+    }
+
+    pub fn table_names(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(String::as_str)
+    }
+
+    /// `package.edition`, the latest edition when it isn't set
+    pub fn edition(&self) -> Result<Edition, String> {
+        let Some(value) = self.get("package", "edition") else { return Ok(Edition::LATEST) };
+        let name = match value {
+            Value::String(s) => s.clone(),
+            Value::Integer(n) => n.to_string(),
+            _ => return Err(format!("{}: `edition` must be a string", FILE)),
+        };
+        Edition::parse(&name).ok_or_else(|| format!("{}: unknown edition `{}`, expected {}", FILE, name, editions()))
+    }
+
+    /// `build.target-dir`, the build directory
+    pub fn target_dir(&self) -> Result<Option<&str>, String> {
+        match self.get("build", "target-dir") {
+            None => Ok(None),
+            Some(value) => value.as_str().map(Some).ok_or_else(|| format!("{}: `target-dir` must be a string", FILE)),
+        }
+    }
+
+    /// `build.pkg-config`, packages whose pkg-config flags every build uses
+    pub fn pkg_config(&self) -> Result<Vec<String>, String> {
+        let error = || format!("{}: `pkg-config` must be an array of package names", FILE);
+        match self.get("build", "pkg-config") {
+            None => Ok(Vec::new()),
+            Some(Value::Array(items)) => items.iter().map(|item| item.as_str().map(str::to_string).ok_or_else(error)).collect(),
+            Some(_) => Err(error()),
+        }
+    }
+
+    /// `[limits]`, with the default of each limit it doesn't set
+    pub fn limits(&self) -> Result<Limits, String> {
+        let mut limits = Limits::default();
+        for (key, value) in self.tables.get("limits").into_iter().flatten() {
+            let Value::Integer(n) = value else { return Err(format!("{}: `{}` must be an integer", FILE, key)) };
+            let Ok(n) = u64::try_from(*n) else { return Err(format!("{}: `{}` must not be negative", FILE, key)) };
+            match key.as_str() {
+                "max-file-size" => limits.max_file_size = n,
+                "max-import-depth" => limits.max_import_depth = n as usize,
+                "max-expansion-size" => limits.max_expansion_size = n,
+                "max-nesting-depth" => limits.max_nesting_depth = n as usize,
+                _ => {
+                    let keys: Vec<String> = Limits::KEYS.iter().map(|k| format!("`{}`", k)).collect();
+                    return Err(format!("{}: unknown limit `{}`; the limits are {}", FILE, key, keys.join(", ")));
+                }
+            }
+        }
+        Ok(limits)
+    }
+
+    /// `[lints]`, the level of each lint set for the project
+    pub fn lints(&self) -> Result<Levels, String> {
+        let mut levels = Levels::default();
+        for (key, value) in self.tables.get("lints").into_iter().flatten() {
+            let level = value.as_str().and_then(Level::parse);
+            let Some(level) = level else { return Err(format!("{}: lint `{}` must be \"allow\", \"warn\" or \"deny\"", FILE, key)) };
+            levels.set(key, level).map_err(|e| format!("{}: {}", FILE, e))?;
+        }
+        Ok(levels)
+    }
+
+    /// Features declared in `[features]`, `default` excepted
+    pub fn feature_names(&self) -> Vec<&str> {
+        self.tables.get("features").map_or(Vec::new(), |t| t.keys().map(String::as_str).filter(|k| *k != "default").collect())
+    }
+
+    /// The features `requested` turns on, plus `default` unless
+    /// `no_default`, with everything they list in turn
+    pub fn features(&self, requested: &[String], no_default: bool) -> Result<Features, String> {
+        let Some(table) = self.tables.get("features") else {
+            return Ok(Features { enabled: requested.iter().cloned().collect(), std_modules: None });
+        };
+        let mut features = Features { enabled: BTreeSet::new(), std_modules: Some(BTreeSet::new()) };
+        let mut pending: Vec<String> = requested.to_vec();
+        if !no_default && table.contains_key("default") {
+            pending.push("default".to_string());
+        }
+        while let Some(name) = pending.pop() {
+            if let Some(module) = name.strip_prefix("std/") {
+                let path = format!("std/{}.z", module);
+                if !stdlib::OPTIONAL.contains(&path.as_str()) {
+                    let optional: Vec<String> = stdlib::OPTIONAL.iter().map(|p| format!("\"{}\"", p.trim_end_matches(".z"))).collect();
+                    return Err(format!("{}: `{}` is not an optional module; they are {}", FILE, name, optional.join(", ")));
+                }
+                features.std_modules.get_or_insert_with(BTreeSet::new).insert(path);
+                continue;
+            }
+            let Some(value) = table.get(&name) else {
+                return Err(format!("{}: unknown feature `{}`; the features are {}", FILE, name, self.feature_names().iter().map(|f| format!("`{}`", f)).collect::<Vec<_>>().join(", ")));
+            };
+            let Value::Array(items) = value else { return Err(format!("{}: feature `{}` must be an array", FILE, name)) };
+            if name != "default" && !features.enabled.insert(name.clone()) {
+                continue;
+            }
+            for item in items {
+                match item.as_str() {
+                    Some(s) => pending.push(s.to_string()),
+                    None => return Err(format!("{}: feature `{}` must list names", FILE, name)),
+                }
+            }
+        }
+        Ok(features)
+    }
+}
+
+/// The known editions as "`2024` or `2025`"
+pub fn editions() -> String {
+    let names: Vec<String> = Edition::ALL.iter().map(|e| format!("`{}`", e)).collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+// The line up to a `#` outside of strings
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted {
+            escaped = c == '\\';
+            quoted = c != '"';
+        } else if c == '"' {
+            quoted = true;
+        } else if c == '#' {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+// A value at the start of `text` and what follows it
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, e)| e) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(e @ ('"' | '\\')) => value.push(e),
+                    other => return Err(format!("unknown escape `\\{}`", other.map(String::from).unwrap_or_default())),
+                },
+                _ => value.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in array".to_string());
+            }
+        }
+    }
+    let end = text.find(|c: char| c == ',' || c == ']' || c.is_whitespace()).unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    match word {
+        "true" => Ok((Value::Bool(true), rest)),
+        "false" => Ok((Value::Bool(false), rest)),
+        _ => word.replace('_', "").parse().map(|n| (Value::Integer(n), rest)).map_err(|_| format!("expected a value, found `{}`", word)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_is_parsed() {
+        let manifest = Manifest::parse("# app\n[package]\nname = \"app # 1\"  # comment\nedition = \"2024\"\n\n[build]\nflags = [\"-O2\", \"-g\"]\njobs = 4\nlto = false\n").unwrap();
+        assert_eq!(manifest.get("package", "name"), Some(&Value::String("app # 1".to_string())));
+        assert_eq!(manifest.get("build", "flags"), Some(&Value::Array(vec![Value::String("-O2".to_string()), Value::String("-g".to_string())])));
+        assert_eq!(manifest.get("build", "jobs"), Some(&Value::Integer(4)));
+        assert_eq!(manifest.get("build", "lto"), Some(&Value::Bool(false)));
+        assert_eq!(manifest.edition(), Ok(Edition::E2024));
+        assert_eq!(Manifest::default().edition(), Ok(Edition::LATEST));
+        assert_eq!(Manifest::parse("[package]\nedition = \"2019\"").unwrap().edition(), Err("tarnish.toml: unknown edition `2019`, expected `2024` or `2025`".to_string()));
+        let build = Manifest::parse("[build]\ntarget-dir = \"out\"\npkg-config = [\"sdl2\", \"libpng\"]").unwrap();
+        assert_eq!(build.target_dir(), Ok(Some("out")));
+        assert_eq!(build.pkg_config(), Ok(vec!["sdl2".to_string(), "libpng".to_string()]));
+        assert_eq!(Manifest::parse("[package]\nname = app"), Err("tarnish.toml:2: expected a value, found `app`".to_string()));
+        let lints = Manifest::parse("[lints]\nshadowing = \"deny\"\nunused = \"allow\"").unwrap().lints().unwrap();
+        assert_eq!((lints.level("shadowing"), lints.level("unused"), lints.level("format")), (Level::Deny, Level::Allow, Level::Warn));
+        assert_eq!(Manifest::parse("[lints]\nunused = true").unwrap().lints(), Err("tarnish.toml: lint `unused` must be \"allow\", \"warn\" or \"deny\"".to_string()));
+        assert!(Manifest::parse("[lints]\nunusd = \"deny\"").unwrap().lints().unwrap_err().starts_with("tarnish.toml: unknown lint `unusd`; the lints are "));
+    }
+
+    #[test]
+    fn test_features_are_resolved() {
+        let manifest = Manifest::parse("[features]\ndefault = [\"gui\"]\ngui = []\nserver = [\"std/net\", \"logging\"]\nlogging = []\nfull = [\"gui\", \"server\"]").unwrap();
+        let features = manifest.features(&["server".to_string()], false).unwrap();
+        assert_eq!(features.enabled, ["gui", "logging", "server"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(features.std_modules, Some(["std/net.z".to_string()].into()));
+        let features = manifest.features(&[], true).unwrap();
+        assert!(features.enabled.is_empty() && features.std_modules == Some(BTreeSet::new()));
+        assert_eq!(manifest.features(&["fast".to_string()], false), Err("tarnish.toml: unknown feature `fast`; the features are `full`, `gui`, `logging`, `server`".to_string()));
+        assert_eq!(Manifest::default().features(&["x".to_string()], false).unwrap().std_modules, None);
+    }
+
+    #[test]
+    fn test_limits_are_read() {
+        let limits = Manifest::parse("[limits]\nmax-import-depth = 4").unwrap().limits().unwrap();
+        assert_eq!(limits, Limits { max_import_depth: 4, ..Limits::default() });
+        assert_eq!(Manifest::parse("[limits]\nmax-depth = 4").unwrap().limits(), Err("tarnish.toml: unknown limit `max-depth`; the limits are `max-file-size`, `max-import-depth`, `max-expansion-size`, `max-nesting-depth`".to_string()));
+        assert_eq!(Manifest::parse("[limits]\nmax-file-size = -1").unwrap().limits(), Err("tarnish.toml: `max-file-size` must not be negative".to_string()));
+    }
+}
@@ -0,0 +1,417 @@
+// src/namespaces.rs
+//
+// Named namespaces are open: every file that declares `namespace math`
+// adds to the same namespace. The session keeps the members of each one by
+// its full path (`math`, `math::linear`), so code inside a namespace can
+// name the members other files gave it without qualifying them, and two
+// files declaring the same member is reported as such instead of as a
+// redefinition by gcc. Anonymous namespaces stay private to their file and
+// aren't recorded.
+//
+// `export namespace math::linear as linalg;` and `export shapes::Circle;`
+// only add names: paths through them are rewritten to the paths they stand
+// for before anything looks at the module, so the rest of the compiler
+// never sees an alias.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::ast::{keeps_name, mangle, Function, Global, Interface, Item, Module, Namespace, OperatorOverload, Variable};
+use crate::parser::function_definitions;
+use crate::tokenizer::Token;
+use crate::visit::{walk_function_mut, walk_global_mut, walk_operator_mut, VisitMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberKind {
+    Class,
+    Interface,
+    Global,
+    Function,
+}
+
+impl fmt::Display for MemberKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MemberKind::Class => "class",
+            MemberKind::Interface => "interface",
+            MemberKind::Global => "global",
+            MemberKind::Function => "function",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub kind: MemberKind,
+    /// Name of the generated C symbol, e.g. `math_square`
+    pub c_name: String,
+    /// File that declares the member
+    pub file: String,
+}
+
+/// Members of every named namespace seen so far, by namespace path
+#[derive(Debug, Default)]
+pub struct NamespaceTable {
+    namespaces: BTreeMap<String, BTreeMap<String, Member>>,
+    /// Namespace paths exported under another path, by that path
+    aliases: BTreeMap<String, String>,
+    /// Paths of re-exported members, by the path they are exported as
+    reexports: BTreeMap<String, String>,
+}
+
+impl NamespaceTable {
+    /// Adds `name` to the namespace `path`, or returns the member that
+    /// already has the name
+    pub fn declare(&mut self, path: &str, name: &str, member: Member) -> Result<(), Member> {
+        let members = self.namespaces.entry(path.to_string()).or_default();
+        match members.get(name) {
+            Some(previous) => Err(previous.clone()),
+            None => {
+                members.insert(name.to_string(), member);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn lookup(&self, path: &str, name: &str) -> Option<&Member> {
+        self.namespaces.get(path)?.get(name)
+    }
+
+    /// Whether a namespace called `path` has members or is an alias
+    pub fn has_namespace(&self, path: &str) -> bool {
+        self.namespaces.contains_key(path) || self.aliases.contains_key(path)
+            || self.namespaces.keys().any(|p| p.strip_prefix(path).is_some_and(|rest| rest.starts_with("::")))
+    }
+
+    /// Makes `alias` another name of the namespace `target`; errors with
+    /// what `alias` already names
+    pub fn alias(&mut self, alias: &str, target: &str) -> Result<(), String> {
+        if let Some(previous) = self.aliases.get(alias) {
+            return if previous == target { Ok(()) } else { Err(format!("`{}` already names the namespace `{}`", alias, previous)) };
+        }
+        if self.has_namespace(alias) {
+            return Err(format!("`{}` is already a namespace", alias));
+        }
+        self.aliases.insert(alias.to_string(), target.to_string());
+        Ok(())
+    }
+
+    /// Exports the member at `target` as `name` of the namespace `path`
+    pub fn reexport(&mut self, path: &str, name: &str, target: &str, member: Member) -> Result<(), Member> {
+        self.declare(path, name, member)?;
+        self.reexports.insert(format!("{}::{}", path, name), target.to_string());
+        Ok(())
+    }
+
+    /// The path `path` stands for once aliases and re-exports are followed,
+    /// or `None` when it goes through neither
+    pub fn canonical(&self, path: &str) -> Option<String> {
+        let mut current = path.to_string();
+        // Bounded, since aliases can name each other
+        for _ in 0..16 {
+            let next = if let Some(target) = self.reexports.get(&current) {
+                target.clone()
+            } else {
+                let alias = self.aliases.keys().filter(|a| current.strip_prefix(a.as_str()).is_some_and(|rest| rest.starts_with("::"))).max_by_key(|a| a.len());
+                match alias {
+                    Some(alias) => format!("{}{}", self.aliases[alias], &current[alias.len()..]),
+                    None => break,
+                }
+            };
+            current = next;
+        }
+        (current != path).then_some(current)
+    }
+
+    /// C names of the members of the namespace `path` and those nested in it
+    pub fn c_names_under(&self, path: &str) -> Vec<&str> {
+        self.namespaces
+            .iter()
+            .filter(|(p, _)| *p == path || p.strip_prefix(path).is_some_and(|rest| rest.starts_with("::")))
+            .flat_map(|(_, members)| members.values().map(|m| m.c_name.as_str()))
+            .collect()
+    }
+
+    fn has_renames(&self) -> bool {
+        !self.aliases.is_empty() || !self.reexports.is_empty()
+    }
+
+    /// The member whose C symbol is `c_name`, in any namespace
+    pub fn by_c_name(&self, c_name: &str) -> Option<&Member> {
+        self.namespaces.values().flat_map(|members| members.values()).find(|m| m.c_name == c_name)
+    }
+
+    /// (namespace path, name, member) of every member, in the order of the paths
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String, &Member)> {
+        self.namespaces.iter().flat_map(|(path, members)| members.iter().map(move |(name, member)| (path, name, member)))
+    }
+
+    /// The members of the namespace `path`, by name
+    pub fn members(&self, path: &str) -> impl Iterator<Item = (&String, &Member)> {
+        self.namespaces.get(path).into_iter().flatten()
+    }
+}
+
+/// (namespace path, name, member) for each member of a named namespace
+/// declared in `module`, compiled from `file`. Only definitions count:
+/// prototypes, `extern` globals and `static` functions don't claim a name
+pub fn declared(module: &Module, file: &str) -> Vec<(String, String, Member)> {
+    let mut out = Vec::new();
+    declared_in(&module.items, None, file, &mut out);
+    out
+}
+
+fn declared_in(items: &[Item], namespace: Option<&Namespace>, file: &str, out: &mut Vec<(String, String, Member)>) {
+    for item in items {
+        if let Item::Namespace(namespace) = item {
+            declared_in(&namespace.items, Some(namespace), file, out);
+            continue;
+        }
+        let Some(path) = namespace.and_then(|n| n.path.as_deref()) else { continue };
+        let mut add = |name: &str, kind: MemberKind, c_name: String| {
+            out.push((path.to_string(), name.to_string(), Member { kind, c_name, file: file.to_string() }));
+        };
+        match item {
+            Item::Class(class) => add(&class.name, MemberKind::Class, class.full_name()),
+            Item::Interface(interface) => add(&interface.name, MemberKind::Interface, interface.full_name()),
+            Item::Global(global) if !global.type_.split(' ').any(|w| w == "extern") => add(&global.name, MemberKind::Global, global.full_name()),
+            Item::Raw(tokens) => {
+                for definition in function_definitions(tokens) {
+                    let is_static = definition.return_type.iter().any(|t| matches!(t, Token::Identifier(w) if w == "static"));
+                    if is_static || definition.body_end(tokens).is_none() {
+                        continue;
+                    }
+                    let name = definition.name(tokens);
+                    let c_name = if keeps_name(&definition.attributes) { name.to_string() } else { mangle(&namespace.and_then(|n| n.prefix.clone()), name) };
+                    add(name, MemberKind::Function, c_name);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Records the `export`s of `module`, compiled from `file`, and rewrites
+/// the paths through aliases and re-exports. Returns the errors
+pub fn resolve_exports(module: &mut Module, file: &str, table: &mut NamespaceTable) -> Vec<String> {
+    let mut errors = Vec::new();
+    exports_in(&module.items, None, file, table, &mut errors);
+    if table.has_renames() {
+        PathRewriter { table }.visit_module_mut(module);
+    }
+    errors
+}
+
+fn exports_in(items: &[Item], path: Option<&str>, file: &str, table: &mut NamespaceTable, errors: &mut Vec<String>) {
+    for item in items {
+        let export = match item {
+            Item::Namespace(namespace) => {
+                // Exports in anonymous namespaces would be file-private names; they stay at file scope
+                exports_in(&namespace.items, namespace.path.as_deref().or(path), file, table, errors);
+                continue;
+            }
+            Item::Export(export) => export,
+            _ => continue,
+        };
+        let target = table.canonical(&export.path).unwrap_or_else(|| export.path.clone());
+        let qualified = |name: &str| path.map_or(name.to_string(), |p| format!("{}::{}", p, name));
+        if export.namespace {
+            if !table.has_namespace(&target) {
+                errors.push(format!("cannot export `{}`: no namespace of that name has been declared", export.path));
+            } else if let Err(e) = table.alias(&qualified(&export.alias), &target) {
+                errors.push(format!("cannot export `{}` as `{}`: {}", export.path, qualified(&export.alias), e));
+            }
+            continue;
+        }
+        let Some(path) = path else {
+            errors.push(format!("cannot export `{}` outside of a namespace; export namespaces or put the export in one", export.path));
+            continue;
+        };
+        let member = target.rsplit_once("::").and_then(|(namespace, name)| table.lookup(namespace, name)).cloned();
+        let Some(member) = member else {
+            errors.push(format!("cannot export `{}`: it is not a class, interface, global or function of a namespace", export.path));
+            continue;
+        };
+        let kind = member.kind;
+        if let Err(previous) = table.reexport(path, &export.alias, &target, Member { file: file.to_string(), ..member }) {
+            errors.push(format!("{} `{}::{}` is already declared in {}", kind, path, export.alias, previous.file));
+        }
+    }
+}
+
+/// C names reachable through the `export`s of `module`, which an importer
+/// using them uses the exporting file for
+pub fn exported(module: &Module, table: &NamespaceTable) -> HashSet<String> {
+    let mut names = HashSet::new();
+    exported_in(&module.items, None, table, &mut names);
+    names
+}
+
+fn exported_in(items: &[Item], path: Option<&str>, table: &NamespaceTable, names: &mut HashSet<String>) {
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => exported_in(&namespace.items, namespace.path.as_deref().or(path), table, names),
+            Item::Export(export) => {
+                let alias = path.map_or(export.alias.clone(), |p| format!("{}::{}", p, export.alias));
+                if export.namespace {
+                    let target = table.aliases.get(&alias).cloned().unwrap_or(alias);
+                    names.extend(table.c_names_under(&target).into_iter().map(str::to_string));
+                } else if let Some((namespace, name)) = alias.rsplit_once("::") {
+                    names.extend(table.lookup(namespace, name).map(|m| m.c_name.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Rewrites `a::b` paths, in code and in types, to what they stand for
+struct PathRewriter<'a> {
+    table: &'a NamespaceTable,
+}
+
+impl PathRewriter<'_> {
+    fn tokens(&self, tokens: &mut Vec<Token>) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let after_access = i > 0 && matches!(&tokens[i - 1], Token::Symbol(s) if s == "::" || s == "." || s == "->");
+            if !matches!(tokens[i], Token::Identifier(_)) || after_access {
+                i += 1;
+                continue;
+            }
+            let mut segments = Vec::new();
+            let mut end = i;
+            while let Some(Token::Identifier(segment)) = tokens.get(end) {
+                segments.push(segment.as_str());
+                match tokens.get(end + 1) {
+                    Some(Token::Symbol(s)) if s == "::" && matches!(tokens.get(end + 2), Some(Token::Identifier(_))) => end += 2,
+                    _ => break,
+                }
+            }
+            let canonical = if segments.len() > 1 { self.table.canonical(&segments.join("::")) } else { None };
+            match canonical {
+                Some(path) => {
+                    let replacement: Vec<Token> = path_tokens(&path);
+                    let len = replacement.len();
+                    tokens.splice(i..=end, replacement);
+                    i += len;
+                }
+                None => i = end + 1,
+            }
+        }
+    }
+
+    // A type or signature kept as text, e.g. `const linalg::Vec*`
+    fn text(&self, text: &mut String) {
+        if !text.contains("::") {
+            return;
+        }
+        let mut out = String::new();
+        let mut word = String::new();
+        for c in text.chars().chain(std::iter::once(' ')) {
+            if c.is_alphanumeric() || c == '_' || c == ':' {
+                word.push(c);
+                continue;
+            }
+            out.push_str(&self.table.canonical(&word).unwrap_or(std::mem::take(&mut word)));
+            word.clear();
+            out.push(c);
+        }
+        out.pop();
+        *text = out;
+    }
+}
+
+fn path_tokens(path: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, segment) in path.split("::").enumerate() {
+        if i > 0 {
+            tokens.push(Token::Symbol("::".to_string()));
+        }
+        tokens.push(Token::Identifier(segment.to_string()));
+    }
+    tokens
+}
+
+impl VisitMut for PathRewriter<'_> {
+    fn visit_interface_mut(&mut self, interface: &mut Interface) {
+        for method in &mut interface.methods {
+            self.text(&mut method.return_type);
+            for param in &mut method.params {
+                self.text(&mut param.type_);
+            }
+        }
+    }
+
+    fn visit_variable_mut(&mut self, variable: &mut Variable) {
+        self.text(&mut variable.type_);
+    }
+
+    fn visit_global_mut(&mut self, global: &mut Global) {
+        self.text(&mut global.type_);
+        walk_global_mut(self, global);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        self.text(&mut function.return_type);
+        walk_function_mut(self, function);
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        self.text(&mut operator.return_type);
+        if operator.free {
+            self.text(&mut operator.class_name);
+        }
+        walk_operator_mut(self, operator);
+    }
+
+    fn visit_tokens_mut(&mut self, tokens: &mut Vec<Token>) {
+        self.tokens(tokens);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_namespaces_merge_by_path() {
+        let mut table = NamespaceTable::default();
+        let a = parse_module(&tokenize("namespace math { int square(int x) { return x * x; } int cube(int x); class V { int x; } }\nnamespace { int hidden = 1; }"), "a");
+        let b = parse_module(&tokenize("namespace math { int scale = 2; namespace linear { int dot(int a, int b) { return a * b; } } }"), "b");
+        for (path, name, member) in declared(&a, "a.z").into_iter().chain(declared(&b, "b.z")) {
+            table.declare(&path, &name, member).unwrap();
+        }
+        let names: Vec<&String> = table.members("math").map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["V", "scale", "square"]);
+        assert_eq!(table.lookup("math::linear", "dot").map(|m| m.c_name.as_str()), Some("math_linear_dot"));
+
+        let again = parse_module(&tokenize("namespace math { int square(int x) { return x; } }"), "c");
+        let (path, name, member) = declared(&again, "c.z").remove(0);
+        let previous = table.declare(&path, &name, member).unwrap_err();
+        assert_eq!((previous.kind, previous.file.as_str()), (MemberKind::Function, "a.z"));
+    }
+
+    #[test]
+    fn test_exports_rename_paths() {
+        let mut table = NamespaceTable::default();
+        let mut module = parse_module(&tokenize("namespace math { namespace linear { class Vec { int x; } int dot(Vec a, Vec b) { return a.x * b.x; } } int square(int x) { return x * x; } }\nexport namespace math::linear as linalg;\nnamespace api { export math::square as sq; export linalg::Vec; }\nint f(api::Vec v) { return linalg::dot(v, v) + api::sq(v.x); }"), "a");
+        for (path, name, member) in declared(&module, "a.z") {
+            table.declare(&path, &name, member).unwrap();
+        }
+        assert!(resolve_exports(&mut module, "a.z", &mut table).is_empty());
+        assert_eq!(table.canonical("linalg::Vec").as_deref(), Some("math::linear::Vec"));
+        assert_eq!(table.canonical("api::Vec").as_deref(), Some("math::linear::Vec"));
+        assert_eq!(table.canonical("math::square"), None);
+        let Some(Item::Raw(tokens)) = module.items.last() else { panic!("{:?}", module.items) };
+        assert!(crate::tokenizer::detokenize(tokens).contains("int f(math::linear::Vec v) { return math::linear::dot(v, v) + math::square(v.x); }"));
+
+        let mut again = parse_module(&tokenize("export namespace math as linalg;\nnamespace api { export math::cube; }"), "b");
+        assert_eq!(resolve_exports(&mut again, "b.z", &mut table), vec![
+            "cannot export `math` as `linalg`: `linalg` already names the namespace `math::linear`",
+            "cannot export `math::cube`: it is not a class, interface, global or function of a namespace",
+        ]);
+    }
+}
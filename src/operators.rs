@@ -0,0 +1,119 @@
+// src/operators.rs
+//
+// The overloadable operators. Parsing, lowering and code generation all go
+// through this table, so supporting a new operator is one new entry here.
+
+/// An overloadable operator and the suffix of its generated C function,
+/// e.g. `+` is `add` in `Vector_operator_add`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operator {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    /// Operands including `self`: 1 for `~v` or `v++`, 2 for `a + b`
+    pub arity: usize,
+}
+
+const fn op(symbol: &'static str, name: &'static str, arity: usize) -> Operator {
+    Operator { symbol, name, arity }
+}
+
+pub const OPERATORS: &[Operator] = &[
+    op("+", "add", 2),
+    op("-", "sub", 2),
+    op("*", "mul", 2),
+    op("/", "div", 2),
+    op("==", "eq", 2),
+    op("!=", "neq", 2),
+    op("<", "lt", 2),
+    op(">", "gt", 2),
+    op("<=", "le", 2),
+    op(">=", "ge", 2),
+    op("+=", "add_assign", 2),
+    op("-=", "sub_assign", 2),
+    op("*=", "mul_assign", 2),
+    op("/=", "div_assign", 2),
+    op("&", "bit_and", 2),
+    op("|", "bit_or", 2),
+    op("^", "bit_xor", 2),
+    op("<<", "shl", 2),
+    op(">>", "shr", 2),
+    op("&=", "bit_and_assign", 2),
+    op("|=", "bit_or_assign", 2),
+    op("^=", "bit_xor_assign", 2),
+    op("<<=", "shl_assign", 2),
+    op(">>=", "shr_assign", 2),
+    op("[]", "index", 2),
+    op("~", "bit_not", 1),
+    op("++", "increment", 1),
+    op("--", "decrement", 1),
+    // `operator bool()`, applied where a class value is tested for truth
+    op("bool", "bool", 1),
+];
+
+/// The operator written as `symbol` after the `operator` keyword
+pub fn find(symbol: &str) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|op| op.symbol == symbol)
+}
+
+/// `symbol` as an infix operator at a call site, e.g. `a << b`, or `[]`,
+/// whose right operand goes between its brackets
+pub fn binary(symbol: &str) -> Option<&'static Operator> {
+    find(symbol).filter(|op| op.arity == 2)
+}
+
+/// `v[i]`, which calls `operator[]` with `i` as its right operand
+pub fn index() -> &'static Operator {
+    find("[]").expect("`[]` is in `OPERATORS`")
+}
+
+/// `symbol` as a prefix operator at a call site, e.g. `~v` or `++v`
+pub fn prefix(symbol: &str) -> Option<&'static Operator> {
+    find(symbol).filter(|op| op.arity == 1 && op.symbol != "bool")
+}
+
+/// `symbol` as a postfix operator at a call site, i.e. `v++` or `v--`
+pub fn postfix(symbol: &str) -> Option<&'static Operator> {
+    find(symbol).filter(|op| op.symbol == "++" || op.symbol == "--")
+}
+
+/// How tightly a C binary operator binds, higher first: `*` is 10, `||` is 1.
+/// `None` for anything that isn't a binary operator, including assignments
+pub fn precedence(symbol: &str) -> Option<u8> {
+    Some(match symbol {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | ">" | "<=" | ">=" => 7,
+        "==" | "!=" => 6,
+        "&" => 5,
+        "^" => 4,
+        "|" => 3,
+        "&&" => 2,
+        "||" => 1,
+        _ => return None,
+    })
+}
+
+/// Comparisons, whose overloads return a truth value rather than a class value
+pub fn is_comparison(symbol: &str) -> bool {
+    precedence(symbol).is_some_and(|p| p == 6 || p == 7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_and_names_are_unique() {
+        for (i, a) in OPERATORS.iter().enumerate() {
+            for b in &OPERATORS[i + 1..] {
+                assert_ne!(a.symbol, b.symbol);
+                assert_ne!(a.name, b.name);
+            }
+        }
+        assert_eq!(binary("<<").map(|op| op.name), Some("shl"));
+        assert!(binary("~").is_none() && prefix("~").is_some() && postfix("~").is_none());
+        assert_eq!(index().name, "index");
+        assert!(precedence("*") > precedence("+") && precedence("+=").is_none() && is_comparison("<="));
+    }
+}
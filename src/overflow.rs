@@ -0,0 +1,306 @@
+// src/overflow.rs
+//
+// `--overflow-checks`: `+`, `-` and `*` on built-in signed integer variables
+// are rewritten into `__builtin_*_overflow` calls that abort with the source
+// location. Like slices this runs on the token stream before parsing, while
+// line numbers are still known. Only variables declared with a signed
+// integer type and integer literals are checked; unsigned arithmetic wraps
+// around by definition, and hashes and generators count on it. Anything the
+// rewrite can't type (calls, members, casts, pointer arithmetic) is left
+// alone.
+
+use std::collections::HashSet;
+
+use crate::parser::{code, parse_params, parse_variables};
+use crate::tokenizer::{generated, line_numbers, Token};
+
+const RUNTIME: &str = "#ifndef __Z_OVERFLOW_CHECK
+#define __Z_OVERFLOW_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline void __z_overflow(const char* op, const char* file, int line) {
+  fprintf(stderr, \"%s:%d: integer overflow in `%s`\\n\", file, line, op);
+  abort();
+}
+#define __z_checked(builtin, symbol, a, b, file, line) ({ __typeof__((a) + (b)) __z_result; if (builtin((a), (b), &__z_result)) __z_overflow(symbol, file, line); __z_result; })
+#endif
+";
+
+// Words of the signed integer types
+const INTEGER_TYPES: &[&str] = &[
+    "char", "short", "int", "long", "signed", "ssize_t", "ptrdiff_t", "intptr_t",
+    "int8_t", "int16_t", "int32_t", "int64_t",
+];
+
+/// Rewrites checked arithmetic inside function bodies; `file` is reported on overflow
+pub fn rewrite(tokens: &[Token], file: &str) -> Vec<Token> {
+    let integers = integer_variables(tokens);
+    let lines = line_numbers(tokens);
+    let rewriter = Rewriter { tokens, lines: &lines, integers: &integers, file };
+
+    let mut out: Vec<Token> = Vec::new();
+    let mut checked = false;
+    // Whether each open brace is (inside) a function body
+    let mut braces: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let in_function = braces.last() == Some(&true);
+        if in_function && starts_expression(previous_code(tokens, i)) {
+            if let Some((code, end)) = rewriter.compound_assignment(i).or_else(|| rewriter.arithmetic(i)) {
+                out.extend(code);
+                checked = true;
+                i = end;
+                continue;
+            }
+        }
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" => {
+                let body = in_function || matches!(previous_code(tokens, i), Some(Token::Symbol(p)) if p == ")");
+                braces.push(body);
+            }
+            Token::Symbol(s) if s == "}" => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+
+    if checked {
+        out.splice(0..0, generated(RUNTIME, 1));
+    }
+    out
+}
+
+// A parsed `+ - *` expression over integer operands
+enum Expr {
+    Leaf(Vec<Token>),
+    Binary { op: String, line: usize, left: Box<Expr>, right: Box<Expr> },
+}
+
+struct Rewriter<'a> {
+    tokens: &'a [Token],
+    lines: &'a [usize],
+    integers: &'a HashSet<String>,
+    file: &'a str,
+}
+
+impl Rewriter<'_> {
+    // `a + b * c` starting at `start`, when it ends where a lower-precedence
+    // operator or the end of the expression follows
+    fn arithmetic(&self, start: usize) -> Option<(Vec<Token>, usize)> {
+        let (expr, end) = self.sum(start)?;
+        if !matches!(expr, Expr::Binary { .. }) || !ends_expression(self.tokens.get(end)) || !self.has_variable(&expr) {
+            return None;
+        }
+        Some((self.emit(&expr), end))
+    }
+
+    // `x += e` -> `x = checked(x, e)`
+    fn compound_assignment(&self, start: usize) -> Option<(Vec<Token>, usize)> {
+        let Some(Token::Identifier(name)) = self.tokens.get(start) else { return None };
+        let Some(Token::Symbol(assign)) = self.tokens.get(start + 1) else { return None };
+        let op = match assign.as_str() {
+            "+=" => "+",
+            "-=" => "-",
+            "*=" => "*",
+            _ => return None,
+        };
+        if !self.integers.contains(name) {
+            return None;
+        }
+        let (value, end) = self.sum(start + 2)?;
+        if !ends_expression(self.tokens.get(end)) {
+            return None;
+        }
+        let expr = Expr::Binary {
+            op: op.to_string(),
+            line: self.lines[start + 1],
+            left: Box::new(Expr::Leaf(vec![Token::Identifier(name.clone())])),
+            right: Box::new(value),
+        };
+        let mut out = vec![Token::Identifier(name.clone()), Token::Symbol("=".to_string())];
+        out.extend(self.emit(&expr));
+        Some((out, end))
+    }
+
+    // sum := term (('+' | '-') term)*
+    fn sum(&self, start: usize) -> Option<(Expr, usize)> {
+        let (mut expr, mut i) = self.term(start)?;
+        while let Some(Token::Symbol(op)) = self.tokens.get(i) {
+            if op != "+" && op != "-" {
+                break;
+            }
+            let (right, next) = self.term(i + 1)?;
+            expr = Expr::Binary { op: op.clone(), line: self.lines[i], left: Box::new(expr), right: Box::new(right) };
+            i = next;
+        }
+        Some((expr, i))
+    }
+
+    // term := operand ('*' operand)*
+    fn term(&self, start: usize) -> Option<(Expr, usize)> {
+        let (mut expr, mut i) = self.operand(start)?;
+        while matches!(self.tokens.get(i), Some(Token::Symbol(op)) if op == "*") {
+            let (right, next) = self.operand(i + 1)?;
+            expr = Expr::Binary { op: "*".to_string(), line: self.lines[i], left: Box::new(expr), right: Box::new(right) };
+            i = next;
+        }
+        Some((expr, i))
+    }
+
+    // An integer variable, an integer literal or `( sum )`
+    fn operand(&self, i: usize) -> Option<(Expr, usize)> {
+        let after = self.tokens.get(i + 1);
+        let postfix = matches!(after, Some(Token::Symbol(s)) if matches!(s.as_str(), "(" | "[" | "." | "->" | "++" | "--"));
+        match self.tokens.get(i)? {
+            Token::Identifier(name) if self.integers.contains(name) && !postfix => {
+                Some((Expr::Leaf(vec![self.tokens[i].clone()]), i + 1))
+            }
+            Token::Number(n) if is_integer_literal(n) && !matches!(after, Some(Token::Identifier(_))) => {
+                Some((Expr::Leaf(vec![self.tokens[i].clone()]), i + 1))
+            }
+            Token::Symbol(s) if s == "(" => {
+                let (inner, close) = self.sum(i + 1)?;
+                if !matches!(self.tokens.get(close), Some(Token::Symbol(s)) if s == ")") {
+                    return None;
+                }
+                let mut tokens = vec![Token::Symbol("(".to_string())];
+                tokens.extend(self.emit(&inner));
+                tokens.push(Token::Symbol(")".to_string()));
+                Some((Expr::Leaf(tokens), close + 1))
+            }
+            _ => None,
+        }
+    }
+
+    // Literal-only arithmetic is folded and checked by the C compiler
+    fn has_variable(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Leaf(tokens) => tokens.iter().any(|t| matches!(t, Token::Identifier(name) if self.integers.contains(name))),
+            Expr::Binary { left, right, .. } => self.has_variable(left) || self.has_variable(right),
+        }
+    }
+
+    fn emit(&self, expr: &Expr) -> Vec<Token> {
+        match expr {
+            Expr::Leaf(tokens) => tokens.clone(),
+            Expr::Binary { op, line, left, right } => {
+                let builtin = match op.as_str() {
+                    "+" => "__builtin_add_overflow",
+                    "-" => "__builtin_sub_overflow",
+                    _ => "__builtin_mul_overflow",
+                };
+                let mut out = code(&format!("__z_checked({}, {:?},", builtin, op));
+                out.extend(self.emit(left));
+                out.push(Token::Symbol(",".to_string()));
+                out.extend(self.emit(right));
+                out.extend(code(&format!(", {:?}, {})", self.file, line)));
+                out
+            }
+        }
+    }
+}
+
+// Variables and parameters declared with a built-in integer type
+fn integer_variables(tokens: &[Token]) -> HashSet<String> {
+    let is_integer = |type_: &str| type_.split_whitespace().all(|word| INTEGER_TYPES.contains(&word) || word == "const");
+    let mut names: HashSet<String> = parse_variables(tokens)
+        .into_iter()
+        .filter(|v| is_integer(&v.type_))
+        .map(|v| v.name)
+        .collect();
+
+    // Parameter lists: `( ... )` followed by a body
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token, Token::Symbol(s) if s == "{") || !matches!(previous_code(tokens, i), Some(Token::Symbol(s)) if s == ")") {
+            continue;
+        }
+        let close = (0..i).rev().find(|&k| matches!(&tokens[k], Token::Symbol(s) if s == ")"));
+        let Some(close) = close else { continue };
+        let mut depth = 0;
+        let open = (0..=close).rev().find(|&k| {
+            match &tokens[k] {
+                Token::Symbol(s) if s == ")" => depth += 1,
+                Token::Symbol(s) if s == "(" => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        });
+        if let Some(open) = open {
+            names.extend(parse_params(&tokens[open + 1..close]).into_iter().filter(|p| is_integer(&p.type_)).map(|p| p.name));
+        }
+    }
+
+    // `unsigned int u` reads as an `int u`; such names aren't checked anywhere
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token, Token::Identifier(word) if word == "unsigned") {
+            continue;
+        }
+        let declared = tokens[i + 1..].iter().find(|t| !matches!(t, Token::Identifier(word) if INTEGER_TYPES.contains(&word.as_str()) || word == "const"));
+        if let Some(Token::Identifier(name)) = declared {
+            names.remove(name);
+        }
+    }
+    names
+}
+
+// A signed one: `1u` makes the arithmetic unsigned
+fn is_integer_literal(number: &str) -> bool {
+    !number.contains(['u', 'U']) && (number.starts_with("0x") || number.starts_with("0X") || !number.contains(['.', 'e', 'E']))
+}
+
+// Tokens after which a `+ - *` expression can start: lower-precedence
+// operators, openers and statement boundaries
+fn starts_expression(previous: Option<&Token>) -> bool {
+    match previous {
+        None => true,
+        Some(Token::Symbol(s)) => matches!(
+            s.as_str(),
+            "(" | "[" | "{" | "}" | ";" | "," | "=" | "?" | ":" | "&&" | "||" | "==" | "!=" | "<" | ">" | "<=" | ">="
+                | "<<" | ">>" | "|" | "^" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>="
+        ),
+        Some(Token::Identifier(word)) => word == "return",
+        _ => false,
+    }
+}
+
+// Tokens that may follow a whole `+ - *` expression
+fn ends_expression(next: Option<&Token>) -> bool {
+    match next {
+        None | Some(Token::Newline) | Some(Token::Comment(_)) => true,
+        Some(Token::Symbol(s)) => matches!(
+            s.as_str(),
+            ")" | "]" | "}" | ";" | "," | "?" | ":" | "&&" | "||" | "==" | "!=" | "<" | ">" | "<=" | ">="
+                | "<<" | ">>" | "&" | "|" | "^"
+        ),
+        _ => false,
+    }
+}
+
+fn previous_code(tokens: &[Token], i: usize) -> Option<&Token> {
+    tokens[..i].iter().rev().find(|t| !matches!(t, Token::Newline | Token::Comment(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_integer_arithmetic_is_checked() {
+        let tokens = tokenize("int g = 1 + 2;\nint f(int a, long b, int* p) {\n  int c = a + b * 2;\n  c += a;\n  p = p + 1;\n  return c - (a + 1) > 0 ? sizeof(int) * 2 : a;\n}");
+        let output = detokenize(&rewrite(&tokens, "main.z"));
+        assert!(output.contains("int g = 1 + 2;"));
+        assert!(output.contains("int c = __z_checked(__builtin_add_overflow, \"+\", a, __z_checked(__builtin_mul_overflow, \"*\", b, 2, \"main.z\", 3), \"main.z\", 3);"));
+        assert!(output.contains("c = __z_checked(__builtin_add_overflow, \"+\", c, a, \"main.z\", 4);"));
+        assert!(output.contains("p = p + 1;"));
+        assert!(output.contains("return __z_checked(__builtin_sub_overflow, \"-\", c, (__z_checked(__builtin_add_overflow, \"+\", a, 1, \"main.z\", 6)), \"main.z\", 6) > 0 ? sizeof(int) * 2 : a;"));
+
+        // Unsigned arithmetic wraps around
+        let output = detokenize(&rewrite(&tokenize("unsigned f(unsigned int u, size_t h, uint64_t x, int n) {\n  unsigned short s = 0;\n  s -= 1;\n  u -= 1;\n  h = h * 31 + x;\n  return n + 1u;\n}"), "main.z"));
+        assert!(output.contains("s -= 1;") && output.contains("u -= 1;") && output.contains("h = h * 31 + x;") && output.contains("return n + 1u;"));
+        assert!(!output.contains("__z_checked"));
+    }
+}
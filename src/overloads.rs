@@ -0,0 +1,232 @@
+// src/overloads.rs
+//
+// Choosing among the overloads of a binary operator. A class may overload
+// an operator once per type of right operand, e.g. `V operator*(V other)`
+// and `V operator*(float k)`. C has no overloading, so each of those gets a
+// C function named after that type, `V_operator_mul_V` and
+// `V_operator_mul_float`; an operator overloaded once keeps the plain
+// `V_operator_mul`.
+//
+// At a call site the type of the right operand picks the overload. One
+// taking exactly that type wins; failing that, one the operand reaches by a
+// single implicit conversion:
+//
+//   - an integer type to `float` or `double`, and `float` to `double`
+//   - any `T` to `const T`
+//
+// so `v * 2` finds `operator*(float)` without a cast. Conversions don't
+// chain: `int` doesn't reach `const float`. When more than one overload is
+// one conversion away, or the compiler can't tell the operand's type, the
+// call is an error naming the candidates rather than a guess. Z classes
+// don't inherit from each other, so there is no derived-to-base conversion.
+//
+// An operator overloaded once takes whatever C would pass to its parameter:
+// any arithmetic type for an arithmetic one, any pointer for `void*`. So
+// `v * 2` with only `operator*(V)` is an error here rather than in the C
+// compiler.
+
+use std::collections::HashMap;
+
+/// One overload of a binary operator
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overload {
+    /// Type of the right operand, mangled, e.g. `float` or `const geo_V`
+    pub right: String,
+    /// The C function, e.g. `V_operator_mul_float`
+    pub function: String,
+}
+
+/// Why no overload could be chosen; each names the types the overloads take
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unresolved {
+    /// The operand's type is unknown
+    Unknown { candidates: Vec<String> },
+    /// No overload takes the operand's type, even converted
+    NoMatch { argument: String, candidates: Vec<String> },
+    /// Several take it after one conversion each
+    Ambiguous { argument: String, candidates: Vec<String> },
+}
+
+// Integer types, which convert to either floating type
+const INTEGERS: &[&str] = &[
+    "char", "signed char", "unsigned char", "short", "unsigned short", "int", "unsigned", "unsigned int",
+    "long", "unsigned long", "long long", "unsigned long long", "size_t", "ptrdiff_t",
+    "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+];
+
+// Integer types from `int` up by conversion rank, as on LP64 targets
+const RANKS: &[&[&str]] = &[
+    &["int", "int32_t"],
+    &["unsigned", "unsigned int", "uint32_t"],
+    &["long", "ptrdiff_t", "int64_t"],
+    &["unsigned long", "size_t", "uint64_t"],
+    &["long long"],
+    &["unsigned long long"],
+];
+
+/// The overload of `overloads` to call with a right operand of type
+/// `argument`, if the compiler could tell it. An operator overloaded once
+/// takes an operand of unknown type, or any C would convert
+pub fn resolve<'o>(overloads: &'o [Overload], argument: Option<&str>) -> Result<&'o Overload, Unresolved> {
+    let candidates = || overloads.iter().map(|o| o.right.clone()).collect();
+    match (overloads, argument) {
+        ([only], Some(argument)) if !passes(argument, &only.right) => {
+            return Err(Unresolved::NoMatch { argument: argument.to_string(), candidates: candidates() });
+        }
+        ([only], _) => return Ok(only),
+        ([], argument) => return Err(Unresolved::NoMatch { argument: argument.unwrap_or_default().to_string(), candidates: Vec::new() }),
+        _ => {}
+    }
+    let Some(argument) = argument else {
+        return Err(Unresolved::Unknown { candidates: candidates() });
+    };
+    // Copying a `const T` gives a `T`, so that is no conversion either
+    let exact = overloads.iter().find(|o| o.right == argument)
+        .or_else(|| overloads.iter().find(|o| !argument.ends_with('*') && argument.strip_prefix("const ") == Some(&o.right)));
+    if let Some(exact) = exact {
+        return Ok(exact);
+    }
+    let converted: Vec<&Overload> = overloads.iter().filter(|o| converts(argument, &o.right)).collect();
+    match converted[..] {
+        [one] => Ok(one),
+        [] => Err(Unresolved::NoMatch { argument: argument.to_string(), candidates: candidates() }),
+        _ => Err(Unresolved::Ambiguous { argument: argument.to_string(), candidates: converted.iter().map(|o| o.right.clone()).collect() }),
+    }
+}
+
+/// Whether a value of type `from` becomes a `to` by one implicit conversion
+pub fn converts(from: &str, to: &str) -> bool {
+    if to.strip_prefix("const ") == Some(from) {
+        return true;
+    }
+    let floating = to == "float" || to == "double";
+    (INTEGERS.contains(&from) && floating) || (from == "float" && to == "double")
+}
+
+/// Whether C passes a value of type `from` to a parameter of type `to`
+/// without a cast
+pub fn passes(from: &str, to: &str) -> bool {
+    let bare = |t: &'_ str| t.strip_prefix("const ").unwrap_or(t).to_string();
+    let pointer = |t: &str| t.ends_with('*');
+    bare(from) == bare(to)
+        || converts(from, to)
+        || (arithmetic(from) && arithmetic(to))
+        || (pointer(from) && matches!(to, "void*" | "const void*"))
+}
+
+/// Whether `c_type` is an integer or floating type, `const` or not
+pub fn arithmetic(c_type: &str) -> bool {
+    let c_type = c_type.strip_prefix("const ").unwrap_or(c_type);
+    INTEGERS.contains(&c_type) || c_type == "float" || c_type == "double"
+}
+
+/// The type C converts the operands of arithmetic on `a` and `b` to, both
+/// arithmetic types, e.g. `double` of `int` and `double`
+pub fn common_type(a: &str, b: &str) -> String {
+    let a = a.strip_prefix("const ").unwrap_or(a);
+    let b = b.strip_prefix("const ").unwrap_or(b);
+    if let Some(floating) = ["double", "float"].into_iter().find(|f| a == *f || b == *f) {
+        return floating.to_string();
+    }
+    // Narrower than `int` is promoted to `int`
+    let rank = |t: &str| RANKS.iter().position(|rank| rank.contains(&t)).unwrap_or(0);
+    let promoted = |t: &str| if RANKS.iter().any(|rank| rank.contains(&t)) { t.to_string() } else { "int".to_string() };
+    if rank(b) > rank(a) { promoted(b) } else { promoted(a) }
+}
+
+/// The type of the number literal `number`, e.g. `double` of `2.5`
+pub fn literal_type(number: &str) -> &'static str {
+    let lower = number.to_ascii_lowercase();
+    if !lower.starts_with("0x") && (lower.contains('.') || lower.contains('e')) {
+        return if lower.ends_with('f') { "float" } else { "double" };
+    }
+    let suffix: String = lower.chars().rev().take_while(|c| *c == 'u' || *c == 'l').collect();
+    match (suffix.contains('u'), suffix.matches('l').count()) {
+        (false, 0) => "int",
+        (true, 0) => "unsigned int",
+        (false, 1) => "long",
+        (true, 1) => "unsigned long",
+        (false, _) => "long long",
+        (true, _) => "unsigned long long",
+    }
+}
+
+/// `written` with its class names mangled and its spacing normalized, e.g.
+/// `const geo_V` of `const V`
+pub fn c_type(written: &str, class_names: &HashMap<String, String>) -> String {
+    let words: Vec<String> = written.replace('*', " * ").split_whitespace().map(|word| class_names.get(word).cloned().unwrap_or_else(|| word.to_string())).collect();
+    words.join(" ").replace(" *", "*")
+}
+
+/// What the C function of an overload for `c_type` ends with, e.g.
+/// `const_char_ptr` for `const char*`
+pub fn suffix(c_type: &str) -> String {
+    c_type.replace('*', " ptr").split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+impl Unresolved {
+    /// The error for `operand`, the right operand of `symbol` on a value of
+    /// `class`
+    pub fn message(&self, symbol: &str, class: &str, operand: &str) -> String {
+        let list = |types: &[String]| {
+            let quoted: Vec<String> = types.iter().map(|t| format!("`{}`", t)).collect();
+            match quoted.split_last() {
+                Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+                _ => quoted.concat(),
+            }
+        };
+        match self {
+            Unresolved::Unknown { candidates } => format!(
+                "cannot tell which `operator{}` of `{}` to call: the type of `{}` is unknown; cast it to {}",
+                symbol, class, operand, list(candidates)
+            ),
+            Unresolved::NoMatch { candidates, .. } if candidates.is_empty() => format!("class `{}` has no `operator{}`", class, symbol),
+            Unresolved::NoMatch { argument, candidates } => format!(
+                "no `operator{}` of `{}` takes `{}` of type `{}`; {} {}",
+                symbol, class, operand, argument, if candidates.len() == 1 { "it takes" } else { "its overloads take" }, list(candidates)
+            ),
+            Unresolved::Ambiguous { argument, candidates } => format!(
+                "`operator{}` of `{}` is ambiguous for `{}` of type `{}`, which converts to {} alike; cast it to one of them",
+                symbol, class, operand, argument, list(candidates)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overloads(types: &[&str]) -> Vec<Overload> {
+        types.iter().map(|t| Overload { right: t.to_string(), function: format!("V_operator_mul_{}", suffix(t)) }).collect()
+    }
+
+    #[test]
+    fn test_one_conversion_picks_an_overload() {
+        let vf = overloads(&["V", "float"]);
+        assert_eq!(resolve(&vf, Some("int")).unwrap().function, "V_operator_mul_float");
+        assert_eq!(resolve(&vf, Some("float")).unwrap().function, "V_operator_mul_float");
+        assert_eq!(resolve(&vf, Some("const V")).unwrap().function, "V_operator_mul_V");
+        assert_eq!(resolve(&overloads(&["const V", "int"]), Some("V")).unwrap().function, "V_operator_mul_const_V");
+        // Exact beats converted
+        assert_eq!(resolve(&overloads(&["double", "int"]), Some("int")).unwrap().function, "V_operator_mul_int");
+        // No chains: int -> float -> const float
+        assert!(matches!(resolve(&overloads(&["V", "const float"]), Some("int")), Err(Unresolved::NoMatch { .. })));
+
+        let ambiguous = resolve(&overloads(&["float", "double"]), Some("int")).unwrap_err();
+        assert_eq!(ambiguous.message("*", "V", "2"), "`operator*` of `V` is ambiguous for `2` of type `int`, which converts to `float` or `double` alike; cast it to one of them");
+        assert!(matches!(resolve(&vf, None), Err(Unresolved::Unknown { .. })));
+        // Overloaded once, it takes what C converts, or an operand of unknown type
+        assert_eq!(resolve(&overloads(&["V"]), None).unwrap().function, "V_operator_mul_V");
+        assert_eq!(resolve(&overloads(&["long"]), Some("char")).unwrap().function, "V_operator_mul_long");
+        assert_eq!(resolve(&overloads(&["const void*"]), Some("V*")).unwrap().function, "V_operator_mul_const_void_ptr");
+        let mismatched = resolve(&overloads(&["V"]), Some("int")).unwrap_err();
+        assert_eq!(mismatched.message("*", "V", "2"), "no `operator*` of `V` takes `2` of type `int`; it takes `V`");
+        assert_eq!(resolve(&[], Some("double")).unwrap_err().message(">", "Circle", "2.0"), "class `Circle` has no `operator>`");
+
+        assert_eq!((common_type("int", "double"), common_type("char", "short"), common_type("const float", "long"), common_type("int", "size_t")), ("double".to_string(), "int".to_string(), "float".to_string(), "size_t".to_string()));
+        assert_eq!((literal_type("2"), literal_type("2.5"), literal_type("2.5f"), literal_type("0xff"), literal_type("2ul")), ("int", "double", "float", "int", "unsigned long"));
+        assert_eq!(c_type("const  V *", &HashMap::from([("V".to_string(), "geo_V".to_string())])), "const geo_V*");
+        assert_eq!(suffix("const char*"), "const_char_ptr");
+    }
+}
@@ -0,0 +1,136 @@
+// src/pack.rs
+//
+// `@derive(Pack)`: `Name_pack` writes a class's fields in order as
+// little-endian bytes with no padding, and `Name_unpack` reads them back.
+// Each field has a fixed width on the wire whatever the machine, so the
+// bytes can be used for file formats and network protocols. Fields of a
+// class type are packed with that class's own functions.
+
+use crate::ast::Class;
+
+/// Helpers shared by every packed class, emitted once
+pub const RUNTIME: &str = "#ifndef __Z_PACK
+#define __Z_PACK
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+static inline size_t __z_pack_int(unsigned char* out, unsigned long long value, size_t width) {
+  for (size_t i = 0; i < width; i++) out[i] = (unsigned char)(value >> (8 * i));
+  return width;
+}
+static inline unsigned long long __z_unpack_int(const unsigned char* in, size_t width) {
+  unsigned long long value = 0;
+  for (size_t i = 0; i < width; i++) value |= (unsigned long long)in[i] << (8 * i);
+  return value;
+}
+static inline size_t __z_pack_f32(unsigned char* out, float value) {
+  uint32_t bits;
+  memcpy(&bits, &value, 4);
+  return __z_pack_int(out, bits, 4);
+}
+static inline float __z_unpack_f32(const unsigned char* in) {
+  uint32_t bits = (uint32_t)__z_unpack_int(in, 4);
+  float value;
+  memcpy(&value, &bits, 4);
+  return value;
+}
+static inline size_t __z_pack_f64(unsigned char* out, double value) {
+  uint64_t bits;
+  memcpy(&bits, &value, 8);
+  return __z_pack_int(out, bits, 8);
+}
+static inline double __z_unpack_f64(const unsigned char* in) {
+  uint64_t bits = (uint64_t)__z_unpack_int(in, 8);
+  double value;
+  memcpy(&value, &bits, 8);
+  return value;
+}
+#endif
+";
+
+/// How a field is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wire {
+    /// An integer of this many bytes; signed ones are sign-extended back
+    /// by the cast to the field's type
+    Int(usize),
+    Bool,
+    F32,
+    F64,
+}
+
+/// The wire format of a field of C type `type_`, `None` for anything that
+/// isn't a number. `long` is 8 bytes even where C's is 4.
+pub fn wire(type_: &str) -> Option<Wire> {
+    let type_ = type_.split_whitespace().collect::<Vec<_>>().join(" ");
+    if type_.starts_with("enum ") {
+        return Some(Wire::Int(4));
+    }
+    let wire = match type_.as_str() {
+        "char" | "signed char" | "unsigned char" | "int8_t" | "uint8_t" => Wire::Int(1),
+        "short" | "short int" | "signed short" | "unsigned short" | "unsigned short int" | "int16_t" | "uint16_t" => Wire::Int(2),
+        "int" | "signed" | "signed int" | "unsigned" | "unsigned int" | "int32_t" | "uint32_t" => Wire::Int(4),
+        "long" | "long int" | "long long" | "long long int" | "unsigned long" | "unsigned long int" | "unsigned long long"
+        | "int64_t" | "uint64_t" | "size_t" | "ptrdiff_t" => Wire::Int(8),
+        "bool" | "_Bool" => Wire::Bool,
+        "float" => Wire::F32,
+        "double" => Wire::F64,
+        _ => return None,
+    };
+    Some(wire)
+}
+
+/// `size_t Name_pack(Name self, unsigned char* out)` and
+/// `size_t Name_unpack(Name* self, const unsigned char* in)`, which return
+/// the number of bytes written or read. Fields that aren't numbers are
+/// assumed to be classes deriving `Pack`; the attribute checker makes sure.
+pub fn functions(class: &Class, linkage: &str) -> String {
+    let name = class.full_name();
+    let mut pack = String::new();
+    let mut unpack = String::new();
+    for field in &class.variables {
+        let f = &field.name;
+        match wire(&field.type_) {
+            Some(Wire::Int(width)) => {
+                pack.push_str(&format!("n += __z_pack_int(out + n, (unsigned long long)self.{f}, {width}); "));
+                unpack.push_str(&format!("self->{f} = ({})__z_unpack_int(in + n, {width}); n += {width}; ", field.type_));
+            }
+            Some(Wire::Bool) => {
+                pack.push_str(&format!("n += __z_pack_int(out + n, self.{f} ? 1 : 0, 1); "));
+                unpack.push_str(&format!("self->{f} = __z_unpack_int(in + n, 1) != 0; n += 1; "));
+            }
+            Some(Wire::F32) => {
+                pack.push_str(&format!("n += __z_pack_f32(out + n, self.{f}); "));
+                unpack.push_str(&format!("self->{f} = __z_unpack_f32(in + n); n += 4; "));
+            }
+            Some(Wire::F64) => {
+                pack.push_str(&format!("n += __z_pack_f64(out + n, self.{f}); "));
+                unpack.push_str(&format!("self->{f} = __z_unpack_f64(in + n); n += 8; "));
+            }
+            None => {
+                pack.push_str(&format!("n += {}_pack(self.{f}, out + n); ", field.type_));
+                unpack.push_str(&format!("n += {}_unpack(&self->{f}, in + n); ", field.type_));
+            }
+        }
+    }
+
+    let mut out = String::from(RUNTIME);
+    out.push_str(&format!("{linkage}size_t {name}_pack({name} self, unsigned char* out){{ size_t n = 0; {pack}return n; }}\n"));
+    out.push_str(&format!("{linkage}size_t {name}_unpack({name}* self, const unsigned char* in){{ size_t n = 0; {unpack}return n; }}\n"));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire() {
+        assert_eq!(wire("unsigned  char"), Some(Wire::Int(1)));
+        assert_eq!(wire("long"), Some(Wire::Int(8)));
+        assert_eq!(wire("enum color"), Some(Wire::Int(4)));
+        assert_eq!(wire("double"), Some(Wire::F64));
+        assert_eq!(wire("char*"), None);
+        assert_eq!(wire("Point"), None);
+    }
+}
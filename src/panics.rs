@@ -0,0 +1,419 @@
+// src/panics.rs
+//
+// `panic(...)` and `try`/`catch`. A panic inside a `try` block jumps to its
+// `catch` with `longjmp`; anywhere else it prints `file:line: panic: ...`
+// and aborts. With `--panic-trace` every function records itself on entry,
+// so an uncaught panic also lists the functions it happened in. Like slices
+// this runs on the token stream before parsing, while lines are known.
+
+use crate::parser::{code, is_symbol, statement_end};
+use crate::tokenizer::{generated, line_numbers, Token};
+
+const RUNTIME: &str = "#ifndef __Z_PANIC
+#define __Z_PANIC
+#include <setjmp.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+typedef struct __z_try_frame { jmp_buf jump; struct __z_try_frame* prev; int depth; char message[256]; } __z_try_frame;
+typedef struct { const char* function; const char* file; int line; } __z_trace_entry;
+static _Thread_local __z_try_frame* __z_try_top = NULL;
+static _Thread_local __z_trace_entry __z_trace[256];
+static _Thread_local int __z_trace_depth = 0;
+static void __z_panic(const char* file, int line, const char* format, ...) __attribute__((noreturn, format(printf, 3, 4)));
+static void __z_panic(const char* file, int line, const char* format, ...) {
+  va_list args;
+  va_start(args, format);
+  __z_try_frame* frame = __z_try_top;
+  if (frame != NULL) {
+    vsnprintf(frame->message, sizeof(frame->message), format, args);
+    va_end(args);
+    __z_try_top = frame->prev;
+    __z_trace_depth = frame->depth;
+    longjmp(frame->jump, 1);
+  }
+  fflush(stdout);
+  fprintf(stderr, \"%s:%d: panic: \", file, line);
+  vfprintf(stderr, format, args);
+  va_end(args);
+  fputc('\\n', stderr);
+  for (int i = (__z_trace_depth < 256 ? __z_trace_depth : 256) - 1; i >= 0; i--) {
+    fprintf(stderr, \"  in %s (%s:%d)\\n\", __z_trace[i].function, __z_trace[i].file, __z_trace[i].line);
+  }
+  abort();
+}
+static inline int __z_trace_push(const char* function, const char* file, int line) {
+  if (__z_trace_depth < 256) {
+    __z_trace[__z_trace_depth].function = function;
+    __z_trace[__z_trace_depth].file = file;
+    __z_trace[__z_trace_depth].line = line;
+  }
+  return __z_trace_depth++;
+}
+static inline void __z_trace_pop(int* depth) {
+  __z_trace_depth = *depth;
+}
+#endif
+";
+
+/// Rewrites `panic` calls and `try`/`catch` blocks, reporting `file` and the
+/// line of each panic. With `trace` every function body is instrumented for
+/// the call trace. Returns the errors for jumps out of `try` blocks.
+pub fn rewrite(tokens: &[Token], file: &str, trace: bool) -> (Vec<Token>, Vec<String>) {
+    let lines = line_numbers(tokens);
+    let mut rewriter = Rewriter { tokens, lines: &lines, file, trace, tries: 0, used: false, errors: Vec::new() };
+    let mut out = rewriter.file_scope();
+    if rewriter.used {
+        out.splice(0..0, generated(RUNTIME, 1));
+    }
+    (out, rewriter.errors)
+}
+
+// What an open brace outside of function bodies belongs to
+enum Scope {
+    Class(String),
+    Other,
+}
+
+struct Rewriter<'a> {
+    tokens: &'a [Token],
+    lines: &'a [usize],
+    file: &'a str,
+    trace: bool,
+    // `try` blocks so far, for unique frame names
+    tries: usize,
+    used: bool,
+    errors: Vec<String>,
+}
+
+impl Rewriter<'_> {
+    // Copies everything outside of function bodies and rewrites the bodies
+    fn file_scope(&mut self) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut scopes: Vec<Scope> = Vec::new();
+        let mut i = 0;
+        while i < self.tokens.len() {
+            match &self.tokens[i] {
+                Token::Symbol(s) if s == "{" => {
+                    if let (Some(name), Some(close)) = (self.function_name(i, &scopes), matching_brace(self.tokens, i)) {
+                        out.push(self.tokens[i].clone());
+                        if self.trace {
+                            self.used = true;
+                            out.extend(code(&format!(
+                                "int __z_trace_frame __attribute__((cleanup(__z_trace_pop))) = __z_trace_push({:?}, {:?}, {});",
+                                name, self.file, self.lines[i]
+                            )));
+                        }
+                        out.extend(self.body(i + 1, close, &mut Vec::new()));
+                        out.push(self.tokens[close].clone());
+                        i = close + 1;
+                        continue;
+                    }
+                    let class = match previous_code(self.tokens, i) {
+                        Some((at, Token::Identifier(name))) if matches!(previous_code(self.tokens, at), Some((_, Token::Identifier(k))) if k == "class" || k == "union") => Scope::Class(name.clone()),
+                        _ => Scope::Other,
+                    };
+                    scopes.push(class);
+                }
+                Token::Symbol(s) if s == "}" => {
+                    scopes.pop();
+                }
+                _ => {}
+            }
+            out.push(self.tokens[i].clone());
+            i += 1;
+        }
+        out
+    }
+
+    // The name of the function whose body opens at `open`, as `Class::name`
+    // for methods, or `None` if the brace isn't a function body
+    fn function_name(&self, open: usize, scopes: &[Scope]) -> Option<String> {
+        if starts_with_hash(self.tokens, open) {
+            return None;
+        }
+        let (close, Token::Symbol(paren)) = previous_code(self.tokens, open)? else { return None };
+        if paren != ")" {
+            return None;
+        }
+        let params = matching_paren(self.tokens, close)?;
+        let name = match previous_code(self.tokens, params)? {
+            (at, Token::Symbol(op)) if matches!(previous_code(self.tokens, at), Some((_, Token::Identifier(k))) if k == "operator") => format!("operator{}", op),
+            (_, Token::Identifier(name)) if name != "operator" => name.clone(),
+            _ => return None,
+        };
+        match scopes.iter().rev().find_map(|s| match s {
+            Scope::Class(class) => Some(class),
+            Scope::Other => None,
+        }) {
+            Some(class) => Some(format!("{}::{}", class, name)),
+            None => Some(name),
+        }
+    }
+
+    // Rewrites `tokens[start..end]` inside a function body. `tries` are the
+    // frames of the enclosing `try` blocks, outermost first.
+    fn body(&mut self, start: usize, end: usize, tries: &mut Vec<usize>) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut i = start;
+        while i < end {
+            match &self.tokens[i] {
+                Token::Identifier(word) if word == "try" => {
+                    if let Some((rewritten, next)) = self.try_catch(i, end, tries) {
+                        out.extend(rewritten);
+                        i = next;
+                        continue;
+                    }
+                }
+                // The value is computed inside the `try`, then the frames of
+                // every enclosing `try` are left before returning it
+                Token::Identifier(word) if word == "return" && !tries.is_empty() => {
+                    let semicolon = statement_end(&self.tokens[..end], i).unwrap_or(end - 1);
+                    let leave = format!("__z_try_top = __z_try_{}.prev;", tries[0]);
+                    if next_code(self.tokens, i + 1, end) == Some(semicolon) {
+                        out.extend(code(&format!("{{ {} return; }}", leave)));
+                    } else {
+                        out.extend(code("{ __auto_type __z_result ="));
+                        out.extend(self.body(i + 1, semicolon, tries));
+                        out.extend(code(&format!("; {} return __z_result; }}", leave)));
+                    }
+                    i = semicolon + 1;
+                    continue;
+                }
+                Token::Identifier(word) if word == "panic" && self.is_panic_call(i) => {
+                    self.used = true;
+                    let empty = matches!(self.tokens.get(i + 2), Some(Token::Symbol(s)) if s == ")");
+                    let message = if empty { "\"explicit panic\"" } else { "" };
+                    out.extend(code(&format!("__z_panic({:?}, {}, {}", self.file, self.lines[i], message)));
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(self.tokens[i].clone());
+            i += 1;
+        }
+        out
+    }
+
+    // `try { ... } catch (e) { ... }` starting at `start`, and the index after it
+    fn try_catch(&mut self, start: usize, end: usize, tries: &mut Vec<usize>) -> Option<(Vec<Token>, usize)> {
+        let open = next_code(self.tokens, start + 1, end).filter(|&o| is_symbol(self.tokens.get(o), "{"))?;
+        let close = matching_brace(self.tokens, open).filter(|&c| c < end)?;
+        let Some(catch) = next_code(self.tokens, close + 1, end).filter(|&c| matches!(&self.tokens[c], Token::Identifier(w) if w == "catch")) else {
+            self.errors.push(format!("`try` on line {} needs a `catch` block after it", self.lines[start]));
+            return None;
+        };
+        let mut handler_open = next_code(self.tokens, catch + 1, end)?;
+        let mut binding = None;
+        if is_symbol(self.tokens.get(handler_open), "(") {
+            let name = next_code(self.tokens, handler_open + 1, end)?;
+            let paren = next_code(self.tokens, name + 1, end)?;
+            match (&self.tokens[name], &self.tokens[paren]) {
+                (Token::Identifier(name), Token::Symbol(s)) if s == ")" => binding = Some(name.clone()),
+                _ => {
+                    self.errors.push(format!("`catch` on line {} takes a single name for the message, as in `catch (e)`", self.lines[catch]));
+                    return None;
+                }
+            }
+            handler_open = next_code(self.tokens, paren + 1, end)?;
+        }
+        if !is_symbol(self.tokens.get(handler_open), "{") {
+            self.errors.push(format!("`catch` on line {} needs a block", self.lines[catch]));
+            return None;
+        }
+        let handler_close = matching_brace(self.tokens, handler_open).filter(|&c| c < end)?;
+
+        if let Some(jump) = escaping_jump(&self.tokens[open + 1..close]) {
+            self.errors.push(format!("`{}` can't leave the `try` block on line {}; only `return` can", jump, self.lines[start]));
+        }
+
+        self.used = true;
+        let frame = self.tries;
+        self.tries += 1;
+        let mut out = code(&format!(
+            "{{ __z_try_frame __z_try_{frame}; __z_try_{frame}.prev = __z_try_top; __z_try_{frame}.depth = __z_trace_depth; __z_try_top = &__z_try_{frame}; if (setjmp(__z_try_{frame}.jump) == 0) "
+        ));
+        tries.push(frame);
+        out.push(Token::Symbol("{".to_string()));
+        out.extend(self.body(open, close + 1, tries));
+        tries.pop();
+        out.extend(code(&format!("__z_try_top = __z_try_{frame}.prev; }} else {{")));
+        if let Some(name) = binding {
+            out.extend(code(&format!("const char* {} = __z_try_{}.message;", name, frame)));
+        }
+        out.extend(self.body(handler_open, handler_close + 1, tries));
+        out.extend(code("} }"));
+        Some((out, handler_close + 1))
+    }
+
+    // `panic(` as a call rather than a method or a declaration
+    fn is_panic_call(&self, i: usize) -> bool {
+        if !matches!(self.tokens.get(i + 1), Some(Token::Symbol(s)) if s == "(") {
+            return false;
+        }
+        match previous_code(self.tokens, i) {
+            Some((_, Token::Identifier(word))) => word == "return" || word == "else",
+            Some((_, Token::Symbol(s))) => s != "." && s != "->" && s != "*",
+            _ => true,
+        }
+    }
+}
+
+// The first `break`, `continue` or `goto` in a `try` body that would jump
+// out of it, i.e. isn't inside a loop (or, for `break`, a switch) of its own
+fn escaping_jump(tokens: &[Token]) -> Option<&'static str> {
+    // Loops and switches opened in the body: whether each is a loop, the
+    // brace depth of its body and whether that body is a single statement
+    let mut open: Vec<(bool, usize, bool)> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Identifier(word) if word == "for" || word == "while" || word == "switch" || word == "do" => {
+                let is_loop = word != "switch";
+                let mut body = i + 1;
+                if word != "do" {
+                    let Some(paren) = next_code(tokens, i + 1, tokens.len()).filter(|&p| is_symbol(tokens.get(p), "(")) else {
+                        i += 1;
+                        continue;
+                    };
+                    body = matching_paren_forward(tokens, paren).map_or(tokens.len(), |c| c + 1);
+                }
+                match next_code(tokens, body, tokens.len()) {
+                    // The `while (...);` ending a `do` loop
+                    Some(b) if is_symbol(tokens.get(b), ";") => {}
+                    Some(b) if is_symbol(tokens.get(b), "{") => open.push((is_loop, depth + 1, false)),
+                    Some(_) => open.push((is_loop, depth, true)),
+                    None => {}
+                }
+                i = body;
+                continue;
+            }
+            Token::Identifier(word) if word == "break" && open.is_empty() => return Some("break"),
+            Token::Identifier(word) if word == "continue" && !open.iter().any(|(is_loop, _, _)| *is_loop) => return Some("continue"),
+            Token::Identifier(word) if word == "goto" => return Some("goto"),
+            Token::Symbol(s) if s == "{" => depth += 1,
+            Token::Symbol(s) if s == "}" => {
+                while matches!(open.last(), Some((_, d, false)) if *d == depth) {
+                    open.pop();
+                }
+                depth -= 1;
+            }
+            Token::Symbol(s) if s == ";" => {
+                while matches!(open.last(), Some((_, d, true)) if *d == depth) {
+                    open.pop();
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn matching_brace(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "{" => depth += 1,
+            Token::Symbol(s) if s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Index of the `)` closing the `(` at `open`
+fn matching_paren_forward(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" => depth += 1,
+            Token::Symbol(s) if s == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Index of the `(` opening the `)` at `close`
+fn matching_paren(tokens: &[Token], close: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..=close).rev() {
+        match &tokens[i] {
+            Token::Symbol(s) if s == ")" => depth += 1,
+            Token::Symbol(s) if s == "(" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The last token before `i` that isn't a newline or comment, with its index
+fn previous_code(tokens: &[Token], i: usize) -> Option<(usize, &Token)> {
+    tokens[..i].iter().enumerate().rev().find(|(_, t)| !matches!(t, Token::Newline | Token::Comment(_)))
+}
+
+// Index of the first token in `start..end` that isn't a newline or comment
+fn next_code(tokens: &[Token], start: usize, end: usize) -> Option<usize> {
+    (start..end.min(tokens.len())).find(|&i| !matches!(tokens[i], Token::Newline | Token::Comment(_)))
+}
+
+// Whether the token at `i` is on a preprocessor line
+fn starts_with_hash(tokens: &[Token], i: usize) -> bool {
+    let line_start = tokens[..i].iter().rposition(|t| matches!(t, Token::Newline)).map_or(0, |p| p + 1);
+    matches!(tokens.get(line_start), Some(Token::Symbol(s)) if s == "#")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_panic_and_try() {
+        let tokens = tokenize("class C {\n  int get() { panic(); }\n}\nint f() {\n  try {\n    return g();\n  } catch (e) {\n    panic(\"%s\", e);\n  }\n}");
+        let (tokens, errors) = rewrite(&tokens, "main.z", true);
+        let output = detokenize(&tokens);
+        assert!(errors.is_empty());
+        assert!(output.contains("#ifndef __Z_PANIC"));
+        assert!(output.contains("__z_trace_push(\"C::get\", \"main.z\", 2);"));
+        assert!(output.contains("__z_panic(\"main.z\", 2, \"explicit panic\");"));
+        assert!(output.contains("if (setjmp(__z_try_0.jump) == 0)"));
+        assert!(output.contains("{ __auto_type __z_result = g(); __z_try_top = __z_try_0.prev; return __z_result; }"));
+        assert!(output.contains("const char *e = __z_try_0.message;"));
+        assert!(output.contains("__z_panic(\"main.z\", 8, \"%s\", e);"));
+    }
+
+    #[test]
+    fn test_jumps_out_of_try() {
+        let jump = |body: &str| escaping_jump(&tokenize(body));
+        assert_eq!(jump("for (;;) { if (x) break; continue; }"), None);
+        assert_eq!(jump("while (x) continue; do { break; } while (y);"), None);
+        assert_eq!(jump("switch (x) { case 1: break; }"), None);
+        assert_eq!(jump("switch (x) { case 1: continue; }"), Some("continue"));
+        assert_eq!(jump("for (;;) { } break;"), Some("break"));
+        assert_eq!(jump("goto done;"), Some("goto"));
+
+        let (_, errors) = rewrite(&tokenize("void f() {\n  while (1) {\n    try { break; } catch { }\n  }\n}"), "main.z", false);
+        assert_eq!(errors, vec!["`break` can't leave the `try` block on line 3; only `return` can"]);
+        let (_, errors) = rewrite(&tokenize("void f() {\n  try { }\n}"), "main.z", false);
+        assert_eq!(errors, vec!["`try` on line 2 needs a `catch` block after it"]);
+    }
+}
@@ -0,0 +1,1365 @@
+// src/parser.rs
+
+use crate::ast::{Attribute, Class, Export, Global, Import, Interface, InterfaceMethod, Item, Loop, LoopKind, Module, Namespace, OperatorOverload, Function, Stmt, Switch, Variable};
+use crate::operators;
+use crate::tokenizer::{detokenize, tokenize, Token};
+use crate::DEBUG;
+
+/// Builds the item tree for a token stream. Anything that is not a class,
+/// namespace or import is kept as raw C tokens. `file_tag` is the mangling
+/// prefix for anonymous namespaces and should be unique per source file.
+pub fn parse_module(tokens: &[Token], file_tag: &str) -> Module {
+    let mut state = ParseState {
+        file_tag: file_tag.to_string(),
+        segments: Vec::new(),
+        path: Vec::new(),
+        depth: 0,
+        private_depth: 0,
+    };
+    let (items, _) = parse_items(tokens, 0, &mut state);
+    Module { items }
+}
+
+struct ParseState {
+    file_tag: String,
+    // Mangling prefix segments of the enclosing namespaces
+    segments: Vec<String>,
+    // Names of the enclosing named, non-inline namespaces
+    path: Vec<String>,
+    // Namespace nesting depth; inline namespaces count but add no segment
+    depth: usize,
+    // How many enclosing namespaces are anonymous
+    private_depth: usize,
+}
+
+impl ParseState {
+    fn namespace(&self) -> Option<String> {
+        if self.segments.is_empty() { None } else { Some(self.segments.join("_")) }
+    }
+}
+
+fn parse_items(tokens: &[Token], start_index: usize, state: &mut ParseState) -> (Vec<Item>, usize) {
+    let mut items = Vec::new();
+    let mut raw: Vec<Token> = Vec::new();
+    let mut brace_level = 0;
+    let mut i = start_index;
+
+    while i < tokens.len() {
+        // Declarations are only recognized outside of function bodies
+        if brace_level == 0 {
+            if let Some((mut namespace, content_start)) = parse_namespace_declaration(tokens, i) {
+                flush_raw(&mut items, &mut raw);
+
+                let segment = if namespace.is_anonymous() {
+                    Some(state.file_tag.clone())
+                } else if namespace.inline {
+                    None
+                } else {
+                    Some(namespace.name.clone())
+                };
+                let pushed = segment.is_some();
+                let named = pushed && !namespace.is_anonymous();
+                if named {
+                    state.path.push(namespace.name.clone());
+                }
+                state.segments.extend(segment);
+                state.depth += 1;
+                state.private_depth += namespace.is_anonymous() as usize;
+                namespace.prefix = state.namespace();
+                namespace.path = (state.private_depth == 0 && !state.path.is_empty()).then(|| state.path.join("::"));
+
+                let (inner, next_i) = parse_items(tokens, content_start, state);
+
+                state.private_depth -= namespace.is_anonymous() as usize;
+                state.depth -= 1;
+                if pushed {
+                    state.segments.pop();
+                }
+                if named {
+                    state.path.pop();
+                }
+
+                namespace.items = inner;
+                items.push(Item::Namespace(namespace));
+                i = next_i;
+                continue;
+            }
+
+            if let Some((mut class, next_i)) = parse_class(tokens, i, state.namespace()) {
+                flush_raw(&mut items, &mut raw);
+                class.file_private = state.private_depth > 0;
+                items.push(Item::Class(class));
+                i = next_i;
+                continue;
+            }
+
+            if let Some((mut interface, next_i)) = parse_interface(tokens, i, state.namespace()) {
+                flush_raw(&mut items, &mut raw);
+                interface.file_private = state.private_depth > 0;
+                items.push(Item::Interface(interface));
+                i = next_i;
+                continue;
+            }
+
+            if at_statement_start(&raw) {
+                if let Some((operator, next_i)) = parse_free_operator(tokens, i, state.namespace()) {
+                    flush_raw(&mut items, &mut raw);
+                    items.push(Item::Operator(operator));
+                    i = next_i;
+                    continue;
+                }
+            }
+
+            if at_statement_start(&raw) {
+                if let Some((export, next_i)) = parse_export(tokens, i) {
+                    flush_raw(&mut items, &mut raw);
+                    items.push(Item::Export(export));
+                    i = next_i;
+                    continue;
+                }
+            }
+
+            if let Some((import, next_i)) = parse_import(tokens, i) {
+                flush_raw(&mut items, &mut raw);
+                items.push(Item::Import(import));
+                i = next_i;
+                continue;
+            }
+
+            // Top-level globals are plain C; only namespaced ones need mangling
+            if state.namespace().is_some() && at_statement_start(&raw) {
+                if let Some((globals, next_i)) = parse_global(tokens, i, state.namespace()) {
+                    flush_raw(&mut items, &mut raw);
+                    for mut global in globals {
+                        global.file_private = state.private_depth > 0;
+                        items.push(Item::Global(global));
+                    }
+                    i = next_i;
+                    continue;
+                }
+            }
+
+            // Closing brace of the enclosing namespace
+            if let Token::Symbol(s) = &tokens[i] {
+                if s == "}" && state.depth > 0 {
+                    flush_raw(&mut items, &mut raw);
+                    return (items, i + 1);
+                }
+            }
+        }
+
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" => brace_level += 1,
+            Token::Symbol(s) if s == "}" && brace_level > 0 => brace_level -= 1,
+            _ => {}
+        }
+        raw.push(tokens[i].clone());
+        i += 1;
+    }
+
+    flush_raw(&mut items, &mut raw);
+    (items, i)
+}
+
+fn flush_raw(items: &mut Vec<Item>, raw: &mut Vec<Token>) {
+    if !raw.is_empty() {
+        items.push(Item::Raw(std::mem::take(raw)));
+    }
+}
+
+// True when the raw tokens collected so far end a statement or a preprocessor line
+fn at_statement_start(raw: &[Token]) -> bool {
+    let mut significant = raw.iter().rev().filter(|t| !matches!(t, Token::Comment(_)));
+    match significant.next() {
+        None => true,
+        Some(Token::Symbol(s)) if s == ";" || s == "{" || s == "}" => true,
+        Some(Token::Newline) => {
+            // Start of the line we just finished
+            let line_start = raw[..raw.len() - 1]
+                .iter()
+                .rposition(|t| matches!(t, Token::Newline))
+                .map_or(0, |p| p + 1);
+            match raw[line_start..].iter().find(|t| !matches!(t, Token::Comment(_))) {
+                Some(Token::Symbol(s)) if s == "#" => true,
+                Some(Token::Newline) | None => at_statement_start(&raw[..line_start]),
+                // The line itself may end one, e.g. a function's closing `}`
+                _ => at_statement_start(&raw[..raw.len() - 1]),
+            }
+        }
+        _ => false,
+    }
+}
+
+/// `type name;` or `type name = init;` where the type may span several
+/// identifiers and `*`s, e.g. `const char* name = "z";`. The name may have
+/// array sizes, and one declaration may name several globals, each with its
+/// own `*`s: `int* first, second[3] = {1, 2, 3};`
+fn parse_global(tokens: &[Token], start_index: usize, namespace: Option<String>) -> Option<(Vec<Global>, usize)> {
+    let mut words: Vec<&str> = Vec::new();
+    let mut i = start_index;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Identifier(word) => words.push(word),
+            Token::Symbol(star) if star == "*" => words.push(star),
+            _ => break,
+        }
+        i += 1;
+    }
+
+    // Needs at least a type and a name, and the name must be an identifier
+    if words.len() < 2 || words.last() == Some(&"*") {
+        return None;
+    }
+    // typedefs and externs name C entities, `struct X;` is a forward declaration
+    if matches!(words[0], "typedef" | "extern" | "return")
+        || (words.len() == 2 && matches!(words[0], "struct" | "enum" | "union"))
+    {
+        return None;
+    }
+
+    let mut name = words.pop()?.to_string();
+    // The `*`s before the first name are its own, as they are in C
+    let mut pointers = words.iter().rev().take_while(|w| **w == "*").count();
+    let base = words[..words.len() - pointers].join(" ");
+    let mut globals = Vec::new();
+    loop {
+        let mut sizes_end = i;
+        while matches!(tokens.get(sizes_end), Some(Token::Symbol(s)) if s == "[") {
+            sizes_end = matching_close(tokens, sizes_end)? + 1;
+        }
+        let dimensions = array_dimensions(&tokens[i..sizes_end])?;
+        i = sizes_end;
+
+        let init = match tokens.get(i) {
+            Some(Token::Symbol(s)) if s == ";" || s == "," => None,
+            Some(Token::Symbol(s)) if s == "=" => {
+                let mut init = Vec::new();
+                let mut nesting = 0;
+                i += 1;
+                while let Some(token) = tokens.get(i) {
+                    match token {
+                        Token::Symbol(s) if (s == ";" || s == ",") && nesting == 0 => break,
+                        Token::Symbol(s) if s == "(" || s == "{" || s == "[" => nesting += 1,
+                        Token::Symbol(s) if s == ")" || s == "}" || s == "]" => nesting -= 1,
+                        _ => {}
+                    }
+                    init.push(token.clone());
+                    i += 1;
+                }
+                Some(init)
+            }
+            _ => return None,
+        };
+
+        let type_ = format!("{}{}{}", base, "*".repeat(pointers), dimensions).replace(" *", "*");
+        if DEBUG {println!("DEBUG: Found namespace global: {} {}", type_, name);}
+        globals.push(Global { namespace: namespace.clone(), type_, name, init, file_private: false });
+
+        match tokens.get(i) {
+            Some(Token::Symbol(s)) if s == "," => i = skip_trivia(tokens, i + 1),
+            _ => return Some((globals, i + 1)),
+        }
+        // The next declarator: `*`s and a name
+        pointers = 0;
+        while matches!(tokens.get(i), Some(Token::Symbol(s)) if s == "*") {
+            pointers += 1;
+            i = skip_trivia(tokens, i + 1);
+        }
+        let Some(Token::Identifier(next)) = tokens.get(i) else { return None };
+        name = next.clone();
+        i += 1;
+    }
+}
+
+fn parse_class(tokens: &[Token], start_index: usize, namespace: Option<String>) -> Option<(Class, usize)> {
+    // `@packed @align(8) rc class Name { ... }`
+    let mut attributes = Vec::new();
+    let mut start_index = start_index;
+    while let Some((attribute, next)) = parse_attribute(tokens, start_index) {
+        attributes.push(attribute);
+        start_index = skip_trivia(tokens, next);
+    }
+    let rc = matches!(tokens.get(start_index)?, Token::Identifier(word) if word == "rc");
+    let start_index = start_index + rc as usize;
+    let Token::Identifier(keyword) = tokens.get(start_index)? else { return None };
+    // There are no rc unions
+    let union = keyword == "union" && !rc;
+    if keyword != "class" && !union {
+        return None;
+    }
+    let Some(Token::Identifier(class_name)) = tokens.get(start_index + 1) else { return None };
+    match tokens.get(start_index + 2) {
+        Some(Token::Symbol(s)) if s == "{" => {}
+        _ => return None,
+    }
+    if DEBUG {println!("DEBUG: Class name: {} (namespace: {:?})", class_name, namespace);}
+
+    let mut j = start_index + 3;
+    let mut brace_level = 1;
+    let mut class_body_tokens: Vec<Token> = Vec::new();
+
+    while j < tokens.len() && brace_level > 0 {
+        match &tokens[j] {
+            Token::Symbol(s) if s == "{" => {
+                brace_level += 1;
+                class_body_tokens.push(tokens[j].clone());
+            }
+            Token::Symbol(s) if s == "}" => {
+                brace_level -= 1;
+                if brace_level > 0 {
+                    class_body_tokens.push(tokens[j].clone());
+                }
+            }
+            _ => class_body_tokens.push(tokens[j].clone()),
+        }
+        j += 1;
+    }
+
+    if DEBUG {println!("DEBUG: Class body extracted, {} tokens collected", class_body_tokens.len());}
+
+    // `union U { ... } u;` declares a variable: that's C, not a Z union
+    if union {
+        let next = (j..tokens.len()).find(|&k| !matches!(tokens[k], Token::Comment(_)));
+        match next.map(|k| &tokens[k]) {
+            Some(Token::Symbol(s)) if s == ";" => j = next? + 1,
+            Some(Token::Identifier(_)) => return None,
+            Some(Token::Symbol(s)) if s == "*" => return None,
+            _ => {}
+        }
+    }
+
+    // `Self` names the class inside its own body
+    for token in &mut class_body_tokens {
+        if matches!(token, Token::Identifier(word) if word == "Self") {
+            *token = Token::Identifier(class_name.clone());
+        }
+    }
+
+    let (functions, operators) = parse_functions_with_operators(&class_body_tokens, class_name.clone(), namespace.clone());
+    let (variables, unsupported_fields) = parse_fields(&class_body_tokens);
+    let class = Class {
+        name: class_name.clone(),
+        namespace,
+        variables,
+        functions,
+        operators,
+        file_private: false,
+        rc,
+        union,
+        checked: false,
+        rc_fields: Vec::new(),
+        attributes,
+        unsupported_fields,
+    };
+
+    if DEBUG {println!("DEBUG: Class {} parsed with {} functions, {} operators, and {} variables",
+        class.name, class.functions.len(), class.operators.len(), class.variables.len())};
+    Some((class, j))
+}
+
+/// `interface Name { Ret method(Params); ... }`
+fn parse_interface(tokens: &[Token], start_index: usize, namespace: Option<String>) -> Option<(Interface, usize)> {
+    if !matches!(tokens.get(start_index)?, Token::Identifier(word) if word == "interface") {
+        return None;
+    }
+    let Some(Token::Identifier(name)) = tokens.get(start_index + 1) else { return None };
+    let open = skip_trivia(tokens, start_index + 2);
+    if !matches!(tokens.get(open), Some(Token::Symbol(s)) if s == "{") {
+        return None;
+    }
+    let close = matching_close(tokens, open)?;
+    let body: Vec<Token> = tokens[open + 1..close].iter()
+        .filter(|t| !matches!(t, Token::Newline | Token::Comment(_)))
+        .map(|t| match t {
+            Token::Identifier(word) if word == "Self" => Token::Identifier(name.clone()),
+            _ => t.clone(),
+        })
+        .collect();
+
+    let mut methods = Vec::new();
+    for declaration in split_depth_zero(&body, ";") {
+        let Some((return_type, method, params_open)) = parse_function_head(&declaration, 0) else { continue };
+        let Some(params_close) = matching_close(&declaration, params_open) else { continue };
+        methods.push(InterfaceMethod { name: method, return_type, params: parse_params(&declaration[params_open + 1..params_close]) });
+    }
+    // `interface I { ... };` is as good as without the `;`
+    let mut next = close + 1;
+    if matches!(tokens.get(skip_trivia(tokens, next)), Some(Token::Symbol(s)) if s == ";") {
+        next = skip_trivia(tokens, next) + 1;
+    }
+    Some((Interface { name: name.clone(), namespace, methods, file_private: false }, next))
+}
+
+/// Structures the loops and switches in a statement list. Anything that
+/// doesn't parse as one is kept as plain tokens.
+pub(crate) fn parse_block(tokens: &[Token]) -> Vec<Stmt> {
+    let mut stmts = Vec::new();
+    let mut run: Vec<Token> = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let after_case = run.iter().rev().find(|t| !matches!(t, Token::Newline | Token::Comment(_)))
+            == Some(&Token::Identifier("case".to_string()));
+        let labeled = if after_case { None } else { parse_labeled_loop(tokens, i) };
+        if let Some((stmt, next_i)) = labeled.or_else(|| parse_control(tokens, i)) {
+            if !run.is_empty() {
+                stmts.push(Stmt::Tokens(std::mem::take(&mut run)));
+            }
+            stmts.push(stmt);
+            i = next_i;
+            continue;
+        }
+        run.push(tokens[i].clone());
+        i += 1;
+    }
+    if !run.is_empty() {
+        stmts.push(Stmt::Tokens(run));
+    }
+    stmts
+}
+
+// `label: while ...`, `label: do ...` or `label: for ...`
+fn parse_labeled_loop(tokens: &[Token], start_index: usize) -> Option<(Stmt, usize)> {
+    let Token::Identifier(label) = &tokens[start_index] else { return None };
+    if label == "default" {
+        return None;
+    }
+    let colon = skip_trivia(tokens, start_index + 1);
+    if tokens.get(colon) != Some(&Token::Symbol(":".to_string())) {
+        return None;
+    }
+    let keyword = skip_trivia(tokens, colon + 1);
+    if !matches!(tokens.get(keyword), Some(Token::Identifier(k)) if k == "while" || k == "do" || k == "for") {
+        return None;
+    }
+    match parse_control(tokens, keyword)? {
+        (Stmt::Loop(mut loop_), next_i) => {
+            loop_.label = Some(label.clone());
+            Some((Stmt::Loop(loop_), next_i))
+        }
+        _ => None,
+    }
+}
+
+// `while`, `do ... while`, `for` or `switch` starting at `start_index`
+fn parse_control(tokens: &[Token], start_index: usize) -> Option<(Stmt, usize)> {
+    let Token::Identifier(keyword) = &tokens[start_index] else { return None };
+    match keyword.as_str() {
+        "while" => {
+            let (condition, i) = parse_parenthesized(tokens, start_index + 1)?;
+            let (body, i) = parse_body(tokens, i)?;
+            Some((Stmt::Loop(Loop { label: None, kind: LoopKind::While { condition }, body }), i))
+        }
+        "do" => {
+            let (body, i) = parse_body(tokens, start_index + 1)?;
+            let i = skip_trivia(tokens, i);
+            if tokens.get(i) != Some(&Token::Identifier("while".to_string())) {
+                return None;
+            }
+            let (condition, i) = parse_parenthesized(tokens, i + 1)?;
+            let i = skip_trivia(tokens, i);
+            if tokens.get(i) != Some(&Token::Symbol(";".to_string())) {
+                return None;
+            }
+            Some((Stmt::Loop(Loop { label: None, kind: LoopKind::DoWhile { condition }, body }), i + 1))
+        }
+        "for" => {
+            let (header, i) = parse_parenthesized(tokens, start_index + 1)?;
+            let mut parts = split_depth_zero(&header, ";");
+            if parts.len() == 1 {
+                let mut parts = split_depth_zero(&header, ":");
+                if parts.len() != 2 {
+                    return None;
+                }
+                let iterable = parts.pop()?;
+                let binding = parts.pop()?;
+                let (body, i) = parse_body(tokens, i)?;
+                return Some((Stmt::Loop(Loop { label: None, kind: LoopKind::ForEach { binding, iterable }, body }), i));
+            }
+            if parts.len() != 3 {
+                return None;
+            }
+            let step = parts.pop()?;
+            let condition = parts.pop()?;
+            let init = parts.pop()?;
+            let (body, i) = parse_body(tokens, i)?;
+            Some((Stmt::Loop(Loop { label: None, kind: LoopKind::For { init, condition, step }, body }), i))
+        }
+        "switch" => {
+            let (scrutinee, i) = parse_parenthesized(tokens, start_index + 1)?;
+            let i = skip_trivia(tokens, i);
+            if tokens.get(i) != Some(&Token::Symbol("{".to_string())) {
+                return None;
+            }
+            let (body, i) = parse_body(tokens, i)?;
+            Some((Stmt::Switch(Switch { scrutinee, body }), i))
+        }
+        _ => None,
+    }
+}
+
+fn skip_trivia(tokens: &[Token], mut i: usize) -> usize {
+    while matches!(tokens.get(i), Some(Token::Newline | Token::Comment(_))) {
+        i += 1;
+    }
+    i
+}
+
+// `( ... )` after optional newlines: the inner tokens and the index after `)`
+fn parse_parenthesized(tokens: &[Token], start_index: usize) -> Option<(Vec<Token>, usize)> {
+    let open = skip_trivia(tokens, start_index);
+    if tokens.get(open) != Some(&Token::Symbol("(".to_string())) {
+        return None;
+    }
+    let close = matching_close(tokens, open)?;
+    Some((tokens[open + 1..close].to_vec(), close + 1))
+}
+
+/// Index of the bracket closing the one at `open`
+pub(crate) fn matching_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "{" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "}" || s == "]" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Index of the `;` ending the statement that starts at `start`, or `None`
+/// if a bracket enclosing `start` closes first
+pub(crate) fn statement_end(tokens: &[Token], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth = depth.checked_sub(1)?,
+            Token::Symbol(s) if s == ";" && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether `token` is the symbol `symbol`
+pub(crate) fn is_symbol(token: Option<&Token>, symbol: &str) -> bool {
+    matches!(token, Some(Token::Symbol(s)) if s == symbol)
+}
+
+/// The tokens of `text` without the trailing `Eof`, as the rewrites take them
+pub(crate) fn code(text: &str) -> Vec<Token> {
+    tokenize(text).into_iter().filter(|t| !matches!(t, Token::Eof)).collect()
+}
+
+// A braced block, a nested loop or a single statement ending in `;`
+fn parse_body(tokens: &[Token], start_index: usize) -> Option<(Vec<Stmt>, usize)> {
+    let i = skip_trivia(tokens, start_index);
+    match tokens.get(i)? {
+        Token::Symbol(s) if s == "{" => {
+            let close = matching_close(tokens, i)?;
+            Some((parse_block(&tokens[i + 1..close]), close + 1))
+        }
+        _ => {
+            let end = past_statement(tokens, i)?;
+            Some((parse_block(&tokens[i..end]), end))
+        }
+    }
+}
+
+// Index just past the single statement starting at `start_index`
+fn past_statement(tokens: &[Token], start_index: usize) -> Option<usize> {
+    let i = skip_trivia(tokens, start_index);
+    match tokens.get(i)? {
+        Token::Symbol(s) if s == "{" => Some(matching_close(tokens, i)? + 1),
+        Token::Identifier(keyword) if keyword == "if" => {
+            let (_, after_condition) = parse_parenthesized(tokens, i + 1)?;
+            let end = past_statement(tokens, after_condition)?;
+            let next = skip_trivia(tokens, end);
+            if tokens.get(next) == Some(&Token::Identifier("else".to_string())) {
+                past_statement(tokens, next + 1)
+            } else {
+                Some(end)
+            }
+        }
+        _ => {
+            if let Some((_, next_i)) = parse_control(tokens, i) {
+                return Some(next_i);
+            }
+            let mut depth = 0;
+            for (j, token) in tokens.iter().enumerate().skip(i) {
+                match token {
+                    Token::Symbol(s) if s == "(" || s == "{" || s == "[" => depth += 1,
+                    Token::Symbol(s) if s == ")" || s == "}" || s == "]" => depth -= 1,
+                    Token::Symbol(s) if s == ";" && depth == 0 => return Some(j + 1),
+                    _ => {}
+                }
+            }
+            None
+        }
+    }
+}
+
+// Splits on `separator` outside of any brackets
+fn split_depth_zero(tokens: &[Token], separator: &str) -> Vec<Vec<Token>> {
+    let mut parts = vec![Vec::new()];
+    let mut depth = 0;
+    for token in tokens {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "{" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "}" || s == "]" => depth -= 1,
+            Token::Symbol(s) if s == separator && depth == 0 => {
+                parts.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(part) = parts.last_mut() {
+            part.push(token.clone());
+        }
+    }
+    parts
+}
+
+/// A free function definition found in raw tokens
+#[derive(Debug, Clone)]
+pub(crate) struct FunctionDefinition {
+    /// Index of the first token of the return type
+    pub start: usize,
+    pub name_index: usize,
+    pub return_type: Vec<Token>,
+    /// Tokens between the parentheses
+    pub params: Vec<Token>,
+    /// `@name` attributes written before the return type
+    pub attributes: Vec<Attribute>,
+    /// Index of the `@` of the first attribute; `start` without any
+    pub attributes_start: usize,
+}
+
+impl FunctionDefinition {
+    pub fn name<'t>(&self, tokens: &'t [Token]) -> &'t str {
+        match &tokens[self.name_index] {
+            Token::Identifier(name) => name,
+            _ => "",
+        }
+    }
+
+    /// Index of the `{` opening the body
+    pub fn body_start(&self, tokens: &[Token]) -> Option<usize> {
+        let params_end = matching_close(tokens, self.name_index + 1)?;
+        Some(tokens.iter().skip(params_end).position(|t| *t == Token::Symbol("{".to_string()))? + params_end)
+    }
+
+    /// Index of the `}` closing the body
+    pub fn body_end(&self, tokens: &[Token]) -> Option<usize> {
+        matching_close(tokens, self.body_start(tokens)?)
+    }
+}
+
+/// Finds `type... name ( params ) {` at brace depth 0
+pub(crate) fn function_definitions(tokens: &[Token]) -> Vec<FunctionDefinition> {
+    let mut definitions = Vec::new();
+    let mut brace_level = 0;
+    let mut statement_start = true;
+    let mut attributes = Vec::new();
+    let mut attributes_start = None;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if brace_level == 0 && statement_start {
+            if let Some((attribute, next)) = parse_attribute(tokens, i) {
+                attributes.push(attribute);
+                attributes_start.get_or_insert(i);
+                i = next;
+                continue;
+            }
+        }
+        if brace_level == 0 && statement_start && matches!(&tokens[i], Token::Identifier(_)) {
+            statement_start = false;
+            if let Some(mut definition) = parse_function_definition(tokens, i) {
+                definition.attributes = std::mem::take(&mut attributes);
+                definition.attributes_start = attributes_start.unwrap_or(i);
+                definitions.push(definition);
+            }
+            attributes.clear();
+            attributes_start = None;
+        }
+
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" => brace_level += 1,
+            Token::Symbol(s) if s == "}" => {
+                brace_level -= 1;
+                statement_start = brace_level == 0;
+            }
+            Token::Symbol(s) if s == ";" && brace_level == 0 => statement_start = true,
+            Token::Newline if brace_level == 0 && is_preprocessor_line(tokens, i) => statement_start = true,
+            _ => {}
+        }
+        i += 1;
+    }
+    definitions
+}
+
+/// An `enum` declared at brace depth 0: its tag and/or typedef names and its
+/// enumerators in declaration order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EnumDefinition {
+    pub names: Vec<String>,
+    pub variants: Vec<String>,
+}
+
+/// Finds `enum Tag { ... }` and `typedef enum [Tag] { ... } Name;` at brace depth 0
+pub(crate) fn enum_definitions(tokens: &[Token]) -> Vec<EnumDefinition> {
+    let code: Vec<&Token> = tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    let mut definitions = Vec::new();
+    let mut brace_level = 0;
+    let mut i = 0;
+
+    while i < code.len() {
+        match code[i] {
+            Token::Symbol(s) if s == "{" => brace_level += 1,
+            Token::Symbol(s) if s == "}" => brace_level -= 1,
+            Token::Identifier(s) if s == "enum" && brace_level == 0 => {
+                let mut names = Vec::new();
+                let mut j = i + 1;
+                if let Some(Token::Identifier(tag)) = code.get(j) {
+                    names.push(tag.clone());
+                    j += 1;
+                }
+                if !matches!(code.get(j), Some(Token::Symbol(s)) if s == "{") {
+                    i = j;
+                    continue;
+                }
+
+                // Enumerators are the first name of each comma-separated entry
+                let mut variants = Vec::new();
+                let mut depth = 0;
+                let mut expect_name = true;
+                j += 1;
+                while j < code.len() {
+                    match code[j] {
+                        Token::Symbol(s) if s == "(" || s == "{" => depth += 1,
+                        Token::Symbol(s) if s == ")" => depth -= 1,
+                        Token::Symbol(s) if s == "}" => {
+                            if depth == 0 {
+                                break;
+                            }
+                            depth -= 1;
+                        }
+                        Token::Symbol(s) if s == "," && depth == 0 => expect_name = true,
+                        Token::Identifier(name) if expect_name => {
+                            variants.push(name.clone());
+                            expect_name = false;
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                }
+
+                let typedef = matches!(i.checked_sub(1).map(|k| code[k]), Some(Token::Identifier(s)) if s == "typedef");
+                if typedef {
+                    if let Some(Token::Identifier(name)) = code.get(j + 1) {
+                        names.push(name.clone());
+                    }
+                }
+                if !names.is_empty() {
+                    definitions.push(EnumDefinition { names, variants });
+                }
+                i = j + 1;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    definitions
+}
+
+// Whether the line ending at `newline` started with `#`
+fn is_preprocessor_line(tokens: &[Token], newline: usize) -> bool {
+    let line_start = tokens[..newline].iter().rposition(|t| matches!(t, Token::Newline)).map_or(0, |p| p + 1);
+    matches!(tokens[line_start..newline].first(), Some(Token::Symbol(s)) if s == "#")
+}
+
+fn parse_function_definition(tokens: &[Token], start_index: usize) -> Option<FunctionDefinition> {
+    let mut i = start_index;
+    // Return types may be qualified, e.g. `std::Result`
+    while matches!(tokens.get(i), Some(Token::Identifier(_))) || matches!(tokens.get(i), Some(Token::Symbol(s)) if s == "*" || s == "::") {
+        i += 1;
+    }
+    // Needs a return type and a name before the parameter list
+    if i < start_index + 2 || !matches!(tokens.get(i - 1), Some(Token::Identifier(_))) {
+        return None;
+    }
+    match tokens.get(i) {
+        Some(Token::Symbol(s)) if s == "(" => {}
+        _ => return None,
+    }
+    let name_index = i - 1;
+
+    let mut params = Vec::new();
+    let mut paren_level = 1;
+    i += 1;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Symbol(s) if s == "(" => paren_level += 1,
+            Token::Symbol(s) if s == ")" => {
+                paren_level -= 1;
+                if paren_level == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        params.push(token.clone());
+        i += 1;
+    }
+
+    let body = tokens.get(i + 1..)?.iter().find(|t| !matches!(t, Token::Newline | Token::Comment(_)));
+    if body != Some(&Token::Symbol("{".to_string())) {
+        return None;
+    }
+
+    Some(FunctionDefinition {
+        start: start_index,
+        name_index,
+        return_type: tokens[start_index..name_index].to_vec(),
+        params,
+        attributes: Vec::new(),
+        attributes_start: start_index,
+    })
+}
+
+/// `@name` or `@name(args)`, returning the attribute and the index after it
+pub(crate) fn parse_attribute(tokens: &[Token], start_index: usize) -> Option<(Attribute, usize)> {
+    let (Some(Token::Symbol(at)), Some(Token::Identifier(name))) = (tokens.get(start_index), tokens.get(start_index + 1)) else { return None };
+    if at != "@" {
+        return None;
+    }
+    let mut attribute = Attribute { name: name.clone(), args: Vec::new() };
+    let mut next = start_index + 2;
+    if matches!(tokens.get(next), Some(Token::Symbol(s)) if s == "(") {
+        let close = matching_close(tokens, next)?;
+        attribute.args = tokens[next + 1..close].to_vec();
+        next = close + 1;
+    }
+    Some((attribute, next))
+}
+
+/// `tokens` without their attributes, as they are emitted
+pub(crate) fn strip_attributes(tokens: &[Token]) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match parse_attribute(tokens, i) {
+            Some((_, next)) => i = next,
+            None => {
+                out.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// `# import < path >`, returning the path and the index after `>`
+pub(crate) fn parse_import(tokens: &[Token], start_index: usize) -> Option<(Import, usize)> {
+    match (tokens.get(start_index), tokens.get(start_index + 1), tokens.get(start_index + 2)) {
+        (Some(Token::Symbol(tag)), Some(Token::Identifier(import)), Some(Token::Symbol(left_angle)))
+            if tag == "#" && import == "import" && left_angle == "<" => {}
+        _ => return None,
+    }
+
+    let mut path = String::new();
+    let mut i = start_index + 3;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Symbol(right_angle) if right_angle == ">" => {
+                return Some((Import { path, expansion: None }, i + 1));
+            }
+            Token::Identifier(inside) | Token::Symbol(inside) | Token::Number(inside) => {
+                path.push_str(inside);
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `export [namespace] a::b [as c];`
+fn parse_export(tokens: &[Token], start_index: usize) -> Option<(Export, usize)> {
+    if !matches!(tokens.get(start_index)?, Token::Identifier(keyword) if keyword == "export") {
+        return None;
+    }
+    let mut i = start_index + 1;
+    let namespace = matches!(tokens.get(i), Some(Token::Identifier(keyword)) if keyword == "namespace");
+    if namespace {
+        i += 1;
+    }
+    let mut segments = Vec::new();
+    loop {
+        let Some(Token::Identifier(segment)) = tokens.get(i) else { return None };
+        segments.push(segment.clone());
+        i += 1;
+        match tokens.get(i) {
+            Some(Token::Symbol(s)) if s == "::" => i += 1,
+            _ => break,
+        }
+    }
+    let mut alias = segments.last()?.clone();
+    if matches!(tokens.get(i), Some(Token::Identifier(keyword)) if keyword == "as") {
+        let Some(Token::Identifier(name)) = tokens.get(i + 1) else { return None };
+        alias = name.clone();
+        i += 2;
+    }
+    match tokens.get(i) {
+        Some(Token::Symbol(s)) if s == ";" => Some((Export { path: segments.join("::"), alias, namespace }, i + 1)),
+        _ => None,
+    }
+}
+
+/// `namespace name {`, `inline namespace name {` or `namespace {`, returning
+/// the (still empty) namespace and the index of its first member
+pub(crate) fn parse_namespace_declaration(tokens: &[Token], start_index: usize) -> Option<(Namespace, usize)> {
+    if DEBUG {println!("DEBUG: Checking for namespace at token {}", start_index);}
+
+    let mut i = start_index;
+    let inline = matches!(&tokens[i], Token::Identifier(keyword) if keyword == "inline");
+    if inline {
+        i += 1;
+    }
+
+    match tokens.get(i) {
+        Some(Token::Identifier(keyword)) if keyword == "namespace" => i += 1,
+        _ => return None,
+    }
+
+    let name = match tokens.get(i) {
+        Some(Token::Identifier(namespace_name)) => {
+            i += 1;
+            namespace_name.clone()
+        }
+        _ => String::new(),
+    };
+
+    match tokens.get(i) {
+        Some(Token::Symbol(brace)) if brace == "{" => {
+            if DEBUG {println!("DEBUG: Found namespace: {:?} (inline: {})", name, inline);}
+            Some((Namespace { name, inline, prefix: None, path: None, items: Vec::new() }, i + 1))
+        }
+        _ => None,
+    }
+}
+
+fn parse_operator_overload(tokens: &[Token], start_index: usize, class_name: String, namespace: Option<String>) -> Option<(OperatorOverload, usize)> {
+    if DEBUG {println!("DEBUG: Checking for operator overload at token {}", start_index);}
+    
+    // Look for: return_type "operator" operator_symbol "(" params ")" "{" body "}"
+    // or the conversion `operator bool()`, which returns int
+    if start_index + 4 >= tokens.len() {
+        return None;
+    }
+    
+    let (return_type, op_symbol, open) = match (&tokens[start_index], &tokens[start_index + 1], &tokens[start_index + 2]) {
+        (Token::Identifier(keyword), Token::Identifier(op), _) if keyword == "operator" && op == "bool" => {
+            ("int".to_string(), op.clone(), start_index + 2)
+        }
+        // `operator[]`, whose brackets are two tokens
+        (Token::Identifier(return_type), Token::Identifier(keyword), Token::Symbol(open))
+            if keyword == "operator" && open == "[" && matches!(&tokens[start_index + 3], Token::Symbol(close) if close == "]") => {
+            (return_type.clone(), "[]".to_string(), start_index + 4)
+        }
+        (Token::Identifier(return_type), Token::Identifier(keyword), Token::Symbol(op) | Token::Identifier(op))
+            if keyword == "operator" && operators::find(op).is_some() => {
+            (return_type.clone(), op.clone(), start_index + 3)
+        }
+        _ => return None,
+    };
+    if matches!(&tokens[open], Token::Symbol(s) if s == "(") {
+        if DEBUG {println!("DEBUG: Found operator overload: {} operator{}", return_type, op_symbol);}
+        
+        // Parse parameters
+        let mut params = Vec::new();
+        let mut p = open + 1;
+        
+        // Parse parameters until )
+        while p < tokens.len() {
+            if let Token::Symbol(sym) = &tokens[p] {
+                if sym == ")" {
+                    p += 1; // move past )
+                    break;
+                }
+                if sym == "," {
+                    p += 1; // skip comma
+                    continue;
+                }
+            }
+            
+            // Try to parse type name pair
+            if let Token::Identifier(param_type) = &tokens[p] {
+                if p + 1 < tokens.len() {
+                    if let Token::Identifier(param_name) = &tokens[p + 1] {
+                        if DEBUG {println!("DEBUG: Found operator parameter: {} {}", param_type, param_name);}
+                        params.push(Variable {
+                            name: param_name.clone(),
+                            type_: param_type.clone(),
+                        });
+                        p += 2;
+                        continue;
+                    }
+                }
+            }
+            p += 1;
+        }
+        
+        // Find opening brace
+        while p < tokens.len() {
+            if let Token::Symbol(s) = &tokens[p] {
+                if s == "{" {
+                    break;
+                }
+            }
+            p += 1;
+        }
+        
+        // Parse body
+        let mut body_tokens = Vec::new();
+        if p < tokens.len() {
+            if let Token::Symbol(s) = &tokens[p] {
+                if s == "{" {
+                    let mut brace_level = 1;
+                    let mut b = p + 1;
+                    while b < tokens.len() && brace_level > 0 {
+                        match &tokens[b] {
+                            Token::Symbol(s) if s == "{" => brace_level += 1,
+                            Token::Symbol(s) if s == "}" => brace_level -= 1,
+                            _ => {},
+                        }
+                        if brace_level > 0 {
+                            body_tokens.push(tokens[b].clone());
+                        }
+                        b += 1;
+                    }
+                    
+                    let operator_overload = OperatorOverload {
+                        class_name: class_name.clone(),
+                        namespace: namespace.clone(),
+                        operator: op_symbol.clone(),
+                        return_type: return_type.clone(),
+                        params,
+                        body_tokens,
+                        attributes: Vec::new(),
+                        free: false,
+                        overload: None,
+                    };
+                    
+                    return Some((operator_overload, b));
+                }
+            }
+        }
+    }
+    
+    None
+}
+
+/// `@attrs V operator+(V a, V b) { ... }` outside of a class. Its class is
+/// the left operand's, which name resolution looks up from `namespace`
+fn parse_free_operator(tokens: &[Token], start_index: usize, namespace: Option<String>) -> Option<(OperatorOverload, usize)> {
+    let mut attributes = Vec::new();
+    let mut start_index = start_index;
+    while let Some((attribute, next)) = parse_attribute(tokens, start_index) {
+        attributes.push(attribute);
+        start_index = skip_trivia(tokens, next);
+    }
+    // A qualified return type, `geo::Vec`, is known by its class name like parameters are
+    while let (Some(Token::Identifier(_)), Some(Token::Symbol(s))) = (tokens.get(start_index), tokens.get(start_index + 1)) {
+        if s != "::" {
+            break;
+        }
+        start_index += 2;
+    }
+    let (mut operator, next) = parse_operator_overload(tokens, start_index, String::new(), namespace)?;
+    operator.class_name = operator.params.first()?.type_.clone();
+    operator.attributes = attributes;
+    operator.free = true;
+    Some((operator, next))
+}
+
+fn parse_functions_with_operators(tokens: &[Token], class: String, namespace: Option<String>) -> (Vec<Function>, Vec<OperatorOverload>) {
+    if DEBUG {println!("DEBUG: Starting parse_functions_with_operators with {} tokens", tokens.len());}
+    let mut functions = Vec::new();
+    let mut operators = Vec::new();
+    // Attributes waiting for the method or operator they are written before
+    let mut attributes = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if DEBUG && i % 50 == 0 {println!("DEBUG: parse_functions_with_operators - checking token {} of {}", i, tokens.len());}
+
+        if let Some((attribute, next_i)) = parse_attribute(tokens, i) {
+            attributes.push(attribute);
+            i = next_i;
+            continue;
+        }
+        
+        // Try to parse operator overload first
+        if let Some((mut op_overload, next_i)) = parse_operator_overload(tokens, i, class.clone(), namespace.clone()) {
+            op_overload.attributes = std::mem::take(&mut attributes);
+            operators.push(op_overload);
+            i = next_i;
+            continue;
+        }
+        
+        // Then try to parse regular function
+        if let Some((ret_type, name, paren)) = parse_function_head(tokens, i) {
+            if DEBUG {println!("DEBUG: Found function: {} {}", ret_type, name);}
+
+            // parse params until )
+            let mut param_tokens = Vec::new();
+            let mut paren_level = 1;
+            let mut p = paren + 1;
+            while p < tokens.len() {
+                match &tokens[p] {
+                    Token::Symbol(s) if s == "(" => paren_level += 1,
+                    Token::Symbol(s) if s == ")" => {
+                        paren_level -= 1;
+                        if paren_level == 0 {
+                            p += 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                param_tokens.push(tokens[p].clone());
+                p += 1;
+            }
+            let params = parse_params(&param_tokens);
+
+            // Find opening brace
+            while p < tokens.len() {
+                if let Token::Symbol(s) = &tokens[p] {
+                    if s == "{" {
+                        break;
+                    }
+                }
+                p += 1;
+            }
+            if p >= tokens.len() {
+                break;
+            }
+
+            // Parse body
+            let mut body_tokens = Vec::new();
+            let mut brace_level = 1;
+            let mut b = p + 1;
+            while b < tokens.len() && brace_level > 0 {
+                match &tokens[b] {
+                    Token::Symbol(s) if s == "{" => brace_level += 1,
+                    Token::Symbol(s) if s == "}" => brace_level -= 1,
+                    _ => {},
+                }
+                if brace_level > 0 {
+                    body_tokens.push(tokens[b].clone());
+                }
+                b += 1;
+            }
+            i = b;
+
+            functions.push(Function {
+                class_name: class.clone(),
+                namespace: namespace.clone(),
+                name,
+                return_type: ret_type,
+                params,
+                body_tokens,
+                attributes: std::mem::take(&mut attributes),
+            });
+            continue;
+        }
+        if matches!(&tokens[i], Token::Symbol(s) if s == ";") {
+            attributes.clear();
+        }
+        i += 1;
+    }
+
+    if DEBUG {println!("DEBUG: parse_functions_with_operators completed, found {} functions and {} operators", functions.len(), operators.len());}
+    (functions, operators)
+}
+
+/// `type *... name (`, returning the return type, the name and the index of `(`
+fn parse_function_head(tokens: &[Token], start_index: usize) -> Option<(String, String, usize)> {
+    let mut i = start_index;
+    while matches!(tokens.get(i), Some(Token::Identifier(_))) || matches!(tokens.get(i), Some(Token::Symbol(s)) if s == "*") {
+        i += 1;
+    }
+    if i < start_index + 2 {
+        return None;
+    }
+    let Some(Token::Identifier(name)) = tokens.get(i - 1) else { return None };
+    match tokens.get(i) {
+        Some(Token::Symbol(s)) if s == "(" => Some((type_text(&tokens[start_index..i - 1]), name.clone(), i)),
+        _ => None,
+    }
+}
+
+/// Spells a type the way it is written in C, e.g. `const char**`
+pub(crate) fn type_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token {
+            Token::Identifier(word) => {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(word);
+            }
+            Token::Symbol(star) if star == "*" => text.push('*'),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Splits a parameter list into `type name` pairs. `()` and `(void)` are empty.
+/// A trailing `...` becomes a parameter of type `...`, see `Variable::is_variadic`.
+pub(crate) fn parse_params(tokens: &[Token]) -> Vec<Variable> {
+    tokens
+        .split(|t| matches!(t, Token::Symbol(s) if s == ","))
+        .filter_map(|param| {
+            let param: Vec<Token> = param.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned().collect();
+            if matches!(param.as_slice(), [Token::Symbol(s)] if s == "...") {
+                return Some(Variable { name: String::new(), type_: "...".to_string() });
+            }
+            let (Token::Identifier(name), type_tokens) = param.split_last()? else { return None };
+            if type_tokens.is_empty() {
+                return None;
+            }
+            Some(Variable { name: name.clone(), type_: type_text(type_tokens) })
+        })
+        .collect()
+}
+
+/// Field declarations of a class body, skipping method bodies
+// The `Type name;` fields of a class body, and the other field
+// declarations as written
+fn parse_fields(tokens: &[Token]) -> (Vec<Variable>, Vec<String>) {
+    let mut fields = Vec::new();
+    let mut unsupported = Vec::new();
+    let mut statement: Vec<Token> = Vec::new();
+    let mut brace_level = 0;
+
+    for token in tokens {
+        match token {
+            Token::Symbol(s) if s == "{" => brace_level += 1,
+            Token::Symbol(s) if s == "}" => {
+                brace_level -= 1;
+                if brace_level == 0 {
+                    statement.clear();
+                }
+            }
+            Token::Symbol(s) if s == ";" && brace_level == 0 => {
+                // `float data[9];` is a field of type `float[9]`
+                let bracket = statement.iter().position(|t| matches!(t, Token::Symbol(s) if s == "[")).unwrap_or(statement.len());
+                let (declarator, dimensions) = statement.split_at(bracket);
+                let dimensions = array_dimensions(dimensions);
+                let declaration = declarator.iter().all(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "*"));
+                match (declaration, declarator.split_last(), dimensions) {
+                    (true, Some((Token::Identifier(name), type_tokens)), Some(dimensions)) if !type_tokens.is_empty() => {
+                        fields.push(Variable { name: name.clone(), type_: type_text(type_tokens) + &dimensions });
+                    }
+                    // Method prototypes and attributes aren't fields
+                    _ if statement.is_empty() || statement.iter().any(|t| matches!(t, Token::Symbol(s) if s == "(" || s == "@")) => {}
+                    _ => unsupported.push(detokenize(&statement).trim().to_string()),
+                }
+                statement.clear();
+            }
+            Token::Newline | Token::Comment(_) => {}
+            _ if brace_level == 0 => statement.push(token.clone()),
+            _ => {}
+        }
+    }
+    (fields, unsupported)
+}
+
+// `[3][4]` as written after a field's name, or `None` if `tokens` aren't
+// all bracketed sizes; nothing is no dimensions
+fn array_dimensions(tokens: &[Token]) -> Option<String> {
+    let mut dimensions = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if !matches!(&tokens[i], Token::Symbol(s) if s == "[") {
+            return None;
+        }
+        let close = matching_close(tokens, i)?;
+        dimensions.push_str(&format!("[{}]", detokenize(&tokens[i + 1..close]).trim()));
+        i = close + 1;
+    }
+    Some(dimensions)
+}
+
+pub(crate) fn parse_variables(tokens: &[Token]) -> Vec<Variable> {
+    if DEBUG {println!("DEBUG: Starting parse_variables with {} tokens", tokens.len());}
+    let mut vars = Vec::new();
+    let mut i = 0;
+
+    while i + 2 < tokens.len() {
+        // `Vector e` or `Vector* e`
+        let pointer = matches!(&tokens[i + 1], Token::Symbol(s) if s == "*");
+        let name_index = if pointer { i + 2 } else { i + 1 };
+        if let (Token::Identifier(type_), Some(Token::Identifier(name)), Some(Token::Symbol(sym))) =
+            (&tokens[i], tokens.get(name_index), tokens.get(name_index + 1))
+        {
+            let type_ = if pointer { format!("{}*", type_) } else { type_.clone() };
+            if sym == ";" {
+                // Vector e;
+                if DEBUG {
+                    println!("DEBUG: Found variable: {} {}", type_, name);
+                }
+                vars.push(Variable {
+                    name: name.clone(),
+                    type_,
+                });
+                i = name_index + 2;
+                continue;
+            } else if sym == "=" {
+                // Vector e = ...;
+                if DEBUG {
+                    println!(
+                        "DEBUG: Found variable with assignment: {} {}",
+                        type_, name
+                    );
+                }
+                vars.push(Variable {
+                    name: name.clone(),
+                    type_,
+                });
+
+                // Skip to the semicolon after the assignment expression
+                let mut j = name_index + 2;
+                while j < tokens.len() {
+                    if let Token::Symbol(s) = &tokens[j] {
+                        if s == ";" {
+                            break;
+                        }
+                    }
+                    j += 1;
+                }
+                i = j + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+
+
+    if DEBUG {println!("DEBUG: parse_variables completed, found {} variables", vars.len());}
+    vars
+}
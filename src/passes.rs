@@ -0,0 +1,4293 @@
+// src/passes.rs
+//
+// The built-in compiler passes, in pipeline order:
+// lex -> lint-levels -> cfg -> links -> embed -> const-generics -> const-eval -> slices -> ranges -> string-match -> atomics -> panics -> overflow-checks -> union-checks -> parse -> resolve-imports -> tests -> resolve-names -> type-check -> rc -> lower -> temporaries -> unused -> call-graph -> arena -> entry-point -> codegen-plugins -> export-api -> emit
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{alignment, derives, export_linkage, flatten, has_attribute, keeps_name, mangle, Attribute, Class, ATTRIBUTES, CLASS_ATTRIBUTES, FREE_FUNCTION_ATTRIBUTES, SHARED_ATTRIBUTES, INLINE_LINKAGE, Function, Global, Import, Interface, Item, Loop, LoopKind, Module, Namespace, OperatorOverload, Stmt, Switch, Variable};
+use crate::compiler::{Context, Pass};
+use crate::parser::{enum_definitions, function_definitions, matching_close, parse_attribute, parse_module, parse_params, parse_variables, strip_attributes, type_text, EnumDefinition, FunctionDefinition};
+use crate::api;
+use crate::arena;
+use crate::atomics;
+use crate::operators;
+use crate::overflow;
+use crate::overloads::{self, Overload};
+use crate::pack;
+use crate::panics;
+use crate::pretty::Printer;
+use crate::provenance;
+use crate::ranges;
+use crate::rc::{self, RcTypes};
+use crate::reflect;
+use crate::registry::ClassInfo;
+use crate::scopes::{self, Symbol, SymbolTable};
+use crate::slices;
+use crate::cfg;
+use crate::cheader;
+use crate::const_generics;
+use crate::consteval;
+use crate::crash;
+use crate::dynamic;
+use crate::embed;
+use crate::edition::{self, Edition};
+use crate::flow;
+use crate::format;
+use crate::links;
+use crate::lints::{self, Levels, Warning};
+use crate::namespaces;
+use crate::stdlib;
+use crate::strict;
+use crate::string_match;
+use crate::suggest;
+use crate::temporaries::Materializer;
+use crate::testing;
+use crate::unions;
+use crate::unused;
+use crate::tokenizer::{c_comments, detokenize, detokenize_preserving, tokenize, without_line_markers, Token};
+use crate::visit::{walk_block, walk_block_mut, walk_class, walk_class_mut, walk_function, walk_function_mut, walk_global_mut, walk_loop, walk_loop_mut, walk_stmt_mut, walk_switch, walk_module_mut, walk_namespace, walk_namespace_mut, walk_operator, walk_operator_mut, walk_switch_mut, Visit, VisitMut};
+use crate::DEBUG;
+
+/// Source text -> tokens
+pub struct Lex;
+
+impl Pass for Lex {
+    fn name(&self) -> &str {
+        "lex"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if let Err(e) = cx.options().limits.check_file_size(&cx.path, cx.source.len() as u64) {
+            cx.error(e);
+            cx.tokens = vec![Token::Eof];
+            return;
+        }
+        cx.session.source_hashes.insert(cx.path.clone(), provenance::hash(&cx.source));
+        cx.tokens = match cx.session.lexed.get(&cx.path) {
+            Some(tokens) => tokens.clone(),
+            None => tokenize(&cx.source),
+        };
+        // Before anything recurses into the brackets
+        if let Err(e) = cx.options().limits.check_nesting_depth(&cx.path, &cx.tokens) {
+            cx.error(e);
+            cx.tokens = vec![Token::Eof];
+            return;
+        }
+        let edition = if stdlib::source(&cx.path).is_some() { Edition::LATEST } else { cx.options().edition };
+        edition.escape(&mut cx.tokens);
+        if DEBUG {println!("DEBUG: Tokenized source into {} tokens", cx.tokens.len());}
+    }
+}
+
+/// Takes the file's `@allow(...);` and `@deny(...);` statements into the
+/// levels of its lints, see `lints`
+pub struct LintLevels;
+
+impl Pass for LintLevels {
+    fn name(&self) -> &str {
+        "lint-levels"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        // The standard library isn't the project's to lint
+        if stdlib::source(&cx.path).is_some() {
+            cx.lints = Levels::default();
+        }
+        let attributes = lints::file_attributes(&mut cx.tokens);
+        for attribute in &attributes {
+            if let Err(e) = lints::check(attribute) {
+                cx.error(e);
+            }
+        }
+        cx.lints = cx.lints.within(&attributes);
+    }
+}
+
+/// Drops the code `@cfg` excludes from this build, see `cfg`
+pub struct CfgAttributes;
+
+impl Pass for CfgAttributes {
+    fn name(&self) -> &str {
+        "cfg"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = cfg::apply(&cx.tokens, &cx.options().cfg);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `#link` and `@link` -> `Session::links`, see `links`
+pub struct LinkDirectives;
+
+impl Pass for LinkDirectives {
+    fn name(&self) -> &str {
+        "links"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, links, errors) = links::collect(&cx.tokens);
+        cx.tokens = tokens;
+        for link in links {
+            cx.session.link(link);
+        }
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `#embed "file" as NAME` -> a byte array and its length, see `embed`
+pub struct EmbedFiles;
+
+impl Pass for EmbedFiles {
+    fn name(&self) -> &str {
+        "embed"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let options = cx.options();
+        let (tokens, errors) = embed::expand(&cx.tokens, |path| options.locate(path));
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// Class templates -> one class per argument list, see `const_generics`
+pub struct ConstGenerics;
+
+impl Pass for ConstGenerics {
+    fn name(&self) -> &str {
+        "const-generics"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = const_generics::instantiate(&cx.tokens);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// Array sizes and enumerator values -> literals, see `consteval`
+pub struct ConstEval;
+
+impl Pass for ConstEval {
+    fn name(&self) -> &str {
+        "const-eval"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = consteval::rewrite(&cx.tokens);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `T[] name` slices -> pointer + length structs, see `slices`
+pub struct Slices;
+
+impl Pass for Slices {
+    fn name(&self) -> &str {
+        "slices"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        cx.tokens = slices::rewrite(&cx.tokens, &cx.path, cx.options().bounds_check);
+    }
+}
+
+/// `a..b` ranges -> index loops and `range` values, see `ranges`
+pub struct Ranges;
+
+impl Pass for Ranges {
+    fn name(&self) -> &str {
+        "ranges"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        cx.tokens = ranges::rewrite(&cx.tokens);
+    }
+}
+
+/// `match` on strings -> `strcmp` chains, see `string_match`
+pub struct StringMatch;
+
+impl Pass for StringMatch {
+    fn name(&self) -> &str {
+        "string-match"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = string_match::rewrite(&cx.tokens);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `Atomic<T>` -> C11 `_Atomic` and `<stdatomic.h>` calls, see `atomics`
+pub struct Atomics;
+
+impl Pass for Atomics {
+    fn name(&self) -> &str {
+        "atomics"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = atomics::rewrite(&cx.tokens);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `panic` and `try`/`catch` -> setjmp/longjmp, see `panics`
+pub struct Panics;
+
+impl Pass for Panics {
+    fn name(&self) -> &str {
+        "panics"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let (tokens, errors) = panics::rewrite(&cx.tokens, &cx.path, cx.options().panic_trace);
+        cx.tokens = tokens;
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+/// `--overflow-checks`: checked integer arithmetic, see `overflow`
+pub struct OverflowChecks;
+
+impl Pass for OverflowChecks {
+    fn name(&self) -> &str {
+        "overflow-checks"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if cx.options().overflow_checks {
+            cx.tokens = overflow::rewrite(&cx.tokens, &cx.path);
+        }
+    }
+}
+
+/// `--union-checks`: checked reads of `@tagged` unions, see `unions`
+pub struct UnionChecks;
+
+impl Pass for UnionChecks {
+    fn name(&self) -> &str {
+        "union-checks"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if cx.options().union_checks {
+            cx.tokens = unions::rewrite(&cx.tokens, &cx.path);
+        }
+    }
+}
+
+/// Tokens -> item tree
+pub struct Parse;
+
+impl Pass for Parse {
+    fn name(&self) -> &str {
+        "parse"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if cx.options().test {
+            cx.tokens = testing::mark_lines(&cx.tokens, &cx.path);
+        }
+        // Every pass that reads lines has run
+        cx.tokens = without_line_markers(&cx.tokens);
+        cx.module = parse_module(&cx.tokens, &file_tag(&cx.path));
+        if cx.options().union_checks {
+            unions::mark_checked(&mut cx.module);
+        }
+        if cx.options().strict && stdlib::source(&cx.path).is_none() {
+            for error in strict::check(&cx.module) {
+                cx.error(error);
+            }
+        }
+    }
+}
+
+/// Mangling prefix for a file's anonymous namespaces, stable across runs
+pub(crate) fn file_tag(path: &str) -> String {
+    // FNV-1a
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in path.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    format!("_anon_{:08x}", hash)
+}
+
+/// Compiles every `#import`ed file and stores the generated C on the import node
+pub struct ResolveImports;
+
+impl Pass for ResolveImports {
+    fn name(&self) -> &str {
+        "resolve-imports"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let mut module = std::mem::take(&mut cx.module);
+        let attributes = lints::import_attributes(&cx.tokens);
+        ImportExpander { cx, attributes }.visit_module_mut(&mut module);
+        cx.module = module;
+        if DEBUG {println!("DEBUG: After import processing, known classes: {:?}", cx.session.known_classes);}
+    }
+}
+
+// Why `path` can't be compiled as an imported Z file, if it can't
+fn not_z_source(path: &str) -> Option<String> {
+    match std::path::Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("z") => None,
+        Some("h" | "hh" | "hpp") => Some(format!("`{}` is a C header, not a Z file; import it as `#import <c:{}>` to check calls into it, or `#include \"{}\"` it", path, path, path)),
+        Some("c" | "cc" | "cpp") => Some(format!("`{}` is C source, not a Z file; name it on the command line to build it with the program", path)),
+        _ => Some(format!("`{}` is not a Z file; `#import` takes `.z` files, `.zi` interfaces and C headers as `<c:header.h>`", path)),
+    }
+}
+
+struct ImportExpander<'c, 'a> {
+    cx: &'c mut Context<'a>,
+    /// The attributes written before each import, by path
+    attributes: HashMap<String, Vec<Attribute>>,
+}
+
+impl VisitMut for ImportExpander<'_, '_> {
+    // Imports only appear as items, never inside code
+    fn visit_raw_mut(&mut self, _tokens: &mut Vec<Token>) {}
+
+    fn visit_import_mut(&mut self, import: &mut Import) {
+        if import.expansion.is_some() {
+            return;
+        }
+        self.cx.session.graph.add_import(&self.cx.path, &import.path);
+        let disabled = self.cx.options().std_modules.as_ref().is_some_and(|enabled| !enabled.contains(&import.path));
+        if disabled && stdlib::OPTIONAL.contains(&import.path.as_str()) {
+            let feature = import.path.trim_end_matches(".z");
+            self.cx.error(format!("`{}` is optional; enable it with a feature that lists \"{}\" in tarnish.toml", import.path, feature));
+            import.expansion = Some(String::new());
+            return;
+        }
+        if !self.cx.session.imported.insert(import.path.clone()) {
+            import.expansion = Some(String::new());
+            return;
+        }
+        let limits = self.cx.options().limits;
+        if let Err(e) = limits.check_import_depth(&import.path, self.cx.session.import_depth + 1) {
+            self.cx.error(e);
+            import.expansion = Some(String::new());
+            return;
+        }
+
+        // A precompiled module: declarations only, the implementation is linked in
+        if import.path.ends_with(".zi") {
+            let source = match std::fs::read_to_string(&import.path) {
+                Ok(source) => source,
+                Err(e) => {
+                    self.cx.error(format!("cannot read `{}`: {}", import.path, e));
+                    import.expansion = Some(String::new());
+                    return;
+                }
+            };
+            self.cx.session.source_hashes.insert(import.path.clone(), provenance::hash(&source));
+            let (classes, links, c_code) = api::parse_interface(&source);
+            for link in links {
+                self.cx.session.link(link);
+            }
+            for function in cheader::scan(&c_code) {
+                self.cx.session.c_functions.insert(function.name.clone(), function);
+            }
+            for (name, mangled) in &classes {
+                if self.cx.session.c_functions.contains_key(&format!("{}_operator_bool", mangled)) {
+                    self.cx.session.truthy_classes.insert(mangled.clone());
+                }
+                let prefix = format!("{}_", mangled);
+                let methods = self.cx.session.c_functions.keys()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .filter(|method| !method.starts_with("operator_"))
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                self.cx.session.methods.insert(mangled.clone(), methods.clone());
+                // Only names are known of the classes of an interface file
+                let namespace = mangled.strip_suffix(name.as_str()).and_then(|p| p.strip_suffix('_')).map(str::to_string);
+                self.cx.session.known_classes.insert(ClassInfo {
+                    name: name.clone(),
+                    c_name: mangled.clone(),
+                    namespace,
+                    interface: false,
+                    fields: Vec::new(),
+                    methods,
+                    operators: Vec::new(),
+                    file: import.path.clone(),
+                });
+            }
+            let mangled: HashSet<&String> = classes.iter().map(|(_, mangled)| mangled).collect();
+            for function in self.cx.session.c_functions.values() {
+                if mangled.contains(&function.return_type) {
+                    self.cx.session.method_returns.insert(function.name.clone(), function.return_type.clone());
+                }
+            }
+            import.expansion = Some(c_code);
+            return;
+        }
+
+        if let Some(header) = import.c_header().map(str::to_string) {
+            let header = header.as_str();
+            // A header of the project's own is included as one, from the
+            // working directory, wherever the C file is written
+            let local = std::path::Path::new(header).is_relative() && std::path::Path::new(header).is_file();
+            import.expansion = Some(if local { format!("#include \"{}\"\n", header) } else { format!("#include <{}>\n", header) });
+            self.cx.session.local_headers |= local;
+            match cheader::find(header).and_then(|path| std::fs::read_to_string(path).ok()) {
+                Some(source) => {
+                    for function in cheader::scan(&source) {
+                        self.cx.session.c_functions.insert(function.name.clone(), function);
+                    }
+                }
+                None => {
+                    let attributes = self.attributes.get(&import.path).map(Vec::as_slice).unwrap_or_default();
+                    self.cx.lint(Warning::new("missing_header", format!("C header `{}` not found; calls into it are not checked", header)).within(attributes));
+                }
+            }
+            return;
+        }
+
+        if let Some(problem) = not_z_source(&import.path) {
+            self.cx.error(problem);
+            import.expansion = Some(String::new());
+            return;
+        }
+        let file_content = match stdlib::load(&import.path, self.cx.options().std_dir.as_deref()) {
+            Some(source) => source,
+            None => match self.cx.session.sources.get(&import.path) {
+                Some(source) => source.clone(),
+                None => {
+                    let path = self.cx.options().locate(&import.path);
+                    // Refused before it is read, so a huge file never is
+                    let size = std::fs::metadata(&path).map_or(0, |m| m.len());
+                    if let Err(e) = limits.check_file_size(&import.path, size) {
+                        self.cx.error(e);
+                        import.expansion = Some(String::new());
+                        return;
+                    }
+                    match std::fs::read_to_string(path) {
+                        Ok(source) => source,
+                        Err(e) => {
+                            self.cx.error(format!("cannot read `{}`: {}", import.path, e));
+                            import.expansion = Some(String::new());
+                            return;
+                        }
+                    }
+                }
+            },
+        };
+        if file_content.contains('\0') {
+            self.cx.error(format!("`{}` holds binary data, not Z source", import.path));
+            import.expansion = Some(String::new());
+            return;
+        }
+
+        // Compile imported file with the current known classes context
+        let before = self.cx.session.expanded;
+        let expansion = self.cx.compile_import(&import.path, &file_content);
+        // The expansion holds those of the file's own imports
+        self.cx.session.expanded = before + expansion.len() as u64;
+        if let Err(e) = limits.check_expansion_size(&import.path, self.cx.session.expanded) {
+            self.cx.error(e);
+            self.cx.session.expanded = before;
+            import.expansion = Some(String::new());
+            return;
+        }
+        import.expansion = Some(expansion);
+    }
+}
+
+/// Registers the classes declared in this file under their mangled names.
+/// File-private classes stay out of the session so importers can't see them.
+pub struct ResolveNames;
+
+impl Pass for ResolveNames {
+    fn name(&self) -> &str {
+        "resolve-names"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        for (path, name, member) in namespaces::declared(&cx.module, &cx.path) {
+            let kind = member.kind;
+            match cx.session.namespaces.declare(&path, &name, member) {
+                Ok(()) => {}
+                Err(previous) if previous.file == cx.path => cx.error(format!("{} `{}::{}` is declared twice", kind, path, name)),
+                Err(previous) => cx.error(format!("{} `{}::{}` is already declared in {}", kind, path, name, previous.file)),
+            }
+        }
+        // Before anything reads a path, so aliases are never seen
+        for error in namespaces::resolve_exports(&mut cx.module, &cx.path, &mut cx.session.namespaces) {
+            cx.error(error);
+        }
+
+        let mut collector = ClassCollector::default();
+        collector.visit_module(&cx.module);
+        for class in &collector.classes {
+            if DEBUG {println!("DEBUG: Found class {} (full name: {})", class.name, class.full_name());}
+            cx.session.methods.insert(class.full_name(), class.functions.iter().map(|f| f.name.clone()).collect());
+            if class.operators.iter().any(|op| op.operator == "bool") {
+                cx.session.truthy_classes.insert(class.full_name());
+            }
+            if class.rc {
+                cx.session.rc_classes.insert(class.full_name(), class.variables.clone());
+            }
+            if has_attribute(&class.attributes, "reflect") {
+                cx.session.reflected.insert(class.full_name());
+            }
+            if derives(&class.attributes).iter().any(|d| d == "Pack") {
+                cx.session.packable.insert(class.full_name());
+            }
+            if class.file_private {
+                cx.local_classes.insert(class.name.clone(), class.full_name());
+            } else {
+                cx.session.known_classes.insert(ClassInfo::of_class(class, &cx.path));
+            }
+        }
+        for interface in &collector.interfaces {
+            let name = interface.full_name();
+            cx.session.methods.insert(name.clone(), interface.methods.iter().map(|m| m.name.clone()).collect());
+            if interface.file_private {
+                cx.local_classes.insert(interface.name.clone(), name.clone());
+            } else {
+                cx.session.known_classes.insert(ClassInfo::of_interface(interface, &cx.path));
+            }
+            cx.session.interfaces.insert(name, interface.clone());
+        }
+        // After registering them all, so a method can return a class declared later
+        let class_names = cx.class_names();
+        for class in &collector.classes {
+            cx.session.graph.add_class(class, &class_names);
+            let printer = Printer::of(class);
+            if !cx.session.printers.iter().any(|p| p.c_name == printer.c_name) {
+                cx.session.printers.push(printer);
+            }
+            for function in &class.functions {
+                if let Some(returned) = class_names.get(function.return_type.trim()) {
+                    cx.session.method_returns.insert(format!("{}_{}", class.full_name(), function.name), returned.clone());
+                }
+            }
+        }
+        for (name, returned) in &collector.functions {
+            cx.session.function_returns.insert(name.clone(), overloads::c_type(returned, &class_names));
+        }
+        for definition in collector.enums {
+            for name in definition.names {
+                cx.session.enums.insert(name, definition.variants.clone());
+            }
+        }
+
+        let mut resolver = OperatorResolver {
+            declared: collector.classes.iter().map(Class::full_name).collect(),
+            class_names: &class_names,
+            prefixes: Vec::new(),
+            truthy: Vec::new(),
+            errors: Vec::new(),
+        };
+        resolver.visit_module_mut(&mut cx.module);
+        cx.session.truthy_classes.extend(resolver.truthy);
+        for message in resolver.errors {
+            cx.error(message);
+        }
+
+        // Once free operators know their class, an operator overloaded more
+        // than once gets a C function per type of right operand
+        let mut binary = BinaryOperators { class_names: &class_names, found: Vec::new() };
+        binary.visit_module(&cx.module);
+        let mut overloaded = HashSet::new();
+        for (i, (function, right, operator)) in binary.found.iter().enumerate() {
+            if binary.found[..i].iter().any(|(f, r, _)| f == function && r == right) {
+                cx.error(format!("`operator{}` of `{}` is overloaded twice for `{}`", operator, function.split("_operator_").next().unwrap_or_default(), right));
+            }
+            let earlier = cx.session.operator_overloads.get(function).into_iter().flatten().map(|o| &o.right);
+            if earlier.chain(binary.found.iter().filter(|(f, _, _)| f == function).map(|(_, r, _)| r)).any(|r| r != right) {
+                overloaded.insert(function.clone());
+            }
+        }
+        let mut names = OverloadNames { class_names: &class_names, overloaded: &overloaded, overloads: Vec::new() };
+        names.visit_module_mut(&mut cx.module);
+        for (function, overload) in names.overloads {
+            let overloads = cx.session.operator_overloads.entry(function).or_default();
+            if !overloads.iter().any(|o| o.right == overload.right) {
+                overloads.push(overload);
+            }
+        }
+
+        // So `(a + b) * c` can be lowered
+        let mut operators = OperatorReturns { class_names: &class_names, returns: Vec::new() };
+        operators.visit_module(&cx.module);
+        cx.session.method_returns.extend(operators.returns);
+
+        let is_type = |name: &str| class_names.contains_key(name) || cx.session.enums.contains_key(name);
+        for suggestion in suggest::unresolved(&cx.tokens, &cx.session.namespaces, is_type) {
+            let name = suggestion.path.rsplit("::").next().unwrap_or_default();
+            let line = declared_source(cx, &suggestion.file).and_then(|text| suggest::declaration_line(&text, name));
+            let place = match line {
+                Some(line) => format!("{}:{}", suggestion.file, line),
+                None => suggestion.file.clone(),
+            };
+            let message = match (suggestion.path == suggestion.written, suggestion.needs_import) {
+                (true, _) => format!("`{}` is not declared here; it is declared in {}, add `#import <{}>`", suggestion.written, place, suggestion.file),
+                (false, true) => format!("`{}` is not declared; did you mean `{}` (declared in {}, add `#import <{}>`)?", suggestion.written, suggestion.path, place, suggestion.file),
+                (false, false) => format!("`{}` is not declared; did you mean `{}` (declared in {})?", suggestion.written, suggestion.path, place),
+            };
+            cx.error(message);
+        }
+    }
+}
+
+// The text of `file`, as imports read it
+fn declared_source(cx: &Context<'_>, file: &str) -> Option<String> {
+    stdlib::load(file, cx.options().std_dir.as_deref())
+        .or_else(|| cx.session.sources.get(file).cloned())
+        .or_else(|| std::fs::read_to_string(cx.options().locate(file)).ok())
+}
+
+/// (plain C name, mangled right operand type, symbol) of every binary
+/// operator, e.g. (`V_operator_mul`, `float`, `*`)
+struct BinaryOperators<'a> {
+    class_names: &'a HashMap<String, String>,
+    found: Vec<(String, String, String)>,
+}
+
+impl Visit for BinaryOperators<'_> {
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        if let (Some(_), Some(right)) = (operators::binary(&operator.operator), operator.right_type()) {
+            self.found.push((operator.full_name(), overloads::c_type(right, self.class_names), operator.operator.clone()));
+        }
+    }
+}
+
+/// Names the C function of each operator in `overloaded` after the type of
+/// its right operand, and lists every binary operator's overload by the
+/// plain name
+struct OverloadNames<'a> {
+    class_names: &'a HashMap<String, String>,
+    overloaded: &'a HashSet<String>,
+    overloads: Vec<(String, Overload)>,
+}
+
+impl VisitMut for OverloadNames<'_> {
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        let (Some(_), Some(right)) = (operators::binary(&operator.operator), operator.right_type()) else { return };
+        let right = overloads::c_type(right, self.class_names);
+        let function = operator.full_name();
+        if self.overloaded.contains(&function) {
+            operator.overload = Some(overloads::suffix(&right));
+        }
+        self.overloads.push((function, Overload { right, function: operator.full_name() }));
+    }
+}
+
+/// The C function of every operator returning a class value, with the class
+struct OperatorReturns<'a> {
+    class_names: &'a HashMap<String, String>,
+    returns: Vec<(String, String)>,
+}
+
+impl Visit for OperatorReturns<'_> {
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        if let Some(returned) = self.class_names.get(operator.return_type.trim()) {
+            self.returns.push((operator.full_name(), returned.clone()));
+        }
+    }
+}
+
+/// Points each free operator at the class of its left operand: one declared
+/// in an enclosing namespace, innermost first, or any class known by name
+struct OperatorResolver<'a> {
+    declared: HashSet<String>,
+    class_names: &'a HashMap<String, String>,
+    // Mangling prefixes of the enclosing namespaces
+    prefixes: Vec<Option<String>>,
+    // Classes given an `operator bool`
+    truthy: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl VisitMut for OperatorResolver<'_> {
+    fn visit_namespace_mut(&mut self, namespace: &mut Namespace) {
+        self.prefixes.push(namespace.prefix.clone());
+        walk_namespace_mut(self, namespace);
+        self.prefixes.pop();
+    }
+
+    fn visit_class_mut(&mut self, _class: &mut Class) {}
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        let name = operator.class_name.clone();
+        let enclosing = self.prefixes.iter().rev().flatten()
+            .find(|prefix| self.declared.contains(&mangle(&Some(prefix.to_string()), &name)));
+        if let Some(prefix) = enclosing {
+            operator.namespace = Some(prefix.clone());
+        } else if let Some(mangled) = self.class_names.get(&name) {
+            operator.namespace = None;
+            operator.class_name = mangled.clone();
+        } else {
+            self.errors.push(format!("the left operand of `operator{}` must be a class, not `{}`", operator.operator, name));
+            return;
+        }
+        if operator.operator == "bool" {
+            self.truthy.push(mangle(&operator.namespace, &operator.class_name));
+        }
+    }
+
+    fn visit_raw_mut(&mut self, _tokens: &mut Vec<Token>) {}
+}
+
+/// Gathers every class, interface and enum declared in a module, at any
+/// namespace depth
+#[derive(Default)]
+struct ClassCollector {
+    classes: Vec<Class>,
+    interfaces: Vec<Interface>,
+    enums: Vec<EnumDefinition>,
+    // (name, return type as written) of every free function
+    functions: Vec<(String, String)>,
+}
+
+impl Visit for ClassCollector {
+    fn visit_class(&mut self, class: &Class) {
+        self.classes.push(class.clone());
+    }
+
+    fn visit_interface(&mut self, interface: &Interface) {
+        self.interfaces.push(interface.clone());
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        self.enums.extend(enum_definitions(tokens));
+        for definition in function_definitions(tokens) {
+            let storage = |t: &&Token| matches!(t, Token::Identifier(word) if word == "static" || word == "inline" || word == "extern");
+            let returned: Vec<Token> = definition.return_type.iter().filter(|t| !storage(t)).cloned().collect();
+            self.functions.push((definition.name(tokens).to_string(), type_text(&returned)));
+        }
+    }
+}
+
+/// Records the declared type of every class-typed variable and parameter
+pub struct TypeCheck;
+
+impl Pass for TypeCheck {
+    fn name(&self) -> &str {
+        "type-check"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let class_names = cx.class_names();
+        let mut collector = VariableCollector {
+            class_names: &class_names,
+            variables: Vec::new(),
+            scalars: Vec::new(),
+        };
+        collector.visit_module(&cx.module);
+        cx.variables = collector.variables;
+        cx.scalars = collector.scalars;
+
+        let mut calls = CallChecker {
+            c_functions: &cx.session.c_functions,
+            class_names: &class_names,
+            declared: Vec::new(),
+            scope: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        };
+        calls.visit_module(&cx.module);
+        let mut control_flow = ControlFlowChecker {
+            variables: &cx.variables,
+            class_names: &class_names,
+            truthy_classes: &cx.session.truthy_classes,
+            methods: &cx.session.methods,
+            enums: &cx.session.enums,
+            symbols: SymbolTable::default(),
+            loops: 0,
+            switches: 0,
+            labels: Vec::new(),
+            scope: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        };
+        control_flow.visit_module(&cx.module);
+        let errors: Vec<String> = calls.errors.into_iter().chain(control_flow.errors).collect();
+        let warnings: Vec<Warning> = calls.warnings.into_iter().chain(control_flow.warnings).collect();
+        let mut attributes = AttributeChecker {
+            class_names: &class_names,
+            rc_classes: &cx.session.rc_classes,
+            packable: &cx.session.packable,
+            errors: Vec::new(),
+        };
+        attributes.visit_module(&cx.module);
+        let mut bodies = BodyChecker::default();
+        bodies.visit_module(&cx.module);
+        for error in errors.into_iter().chain(attributes.errors).chain(bodies.errors) {
+            cx.error(error);
+        }
+        for warning in warnings.into_iter().chain(bodies.warnings) {
+            cx.lint(warning);
+        }
+        if DEBUG {println!("DEBUG: Found {} class-typed variables", cx.variables.len());}
+    }
+}
+
+struct VariableCollector<'a> {
+    class_names: &'a HashMap<String, String>,
+    variables: Vec<Variable>,
+    scalars: Vec<Variable>,
+}
+
+impl VariableCollector<'_> {
+    fn add(&mut self, variable: Variable) {
+        // Pointers to classes are kept under their `T*` type, which checks
+        // that look up class values by type don't match
+        let pointee = variable.type_.strip_suffix('*').unwrap_or(&variable.type_);
+        if self.class_names.contains_key(pointee) {
+            self.variables.push(variable);
+        }
+    }
+
+    // A parameter or local; fields are only reached through `self`
+    fn add_local(&mut self, variable: Variable) {
+        let type_ = overloads::c_type(&variable.type_, self.class_names);
+        if overloads::arithmetic(&type_) || type_.ends_with('*') {
+            self.scalars.push(variable.clone());
+        }
+        self.add(variable);
+    }
+}
+
+impl Visit for VariableCollector<'_> {
+    fn visit_variable(&mut self, variable: &Variable) {
+        self.add(variable.clone());
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        // Parameters of free functions
+        for definition in function_definitions(tokens) {
+            for param in parse_params(&definition.params) {
+                self.add_local(param);
+            }
+        }
+        walk_block(self, tokens);
+    }
+
+    fn visit_loop(&mut self, loop_: &Loop) {
+        // The loop variable of `for (T x : items)`
+        if let LoopKind::ForEach { binding, .. } = &loop_.kind {
+            let declaration: Vec<Token> = binding.iter().cloned().chain([Token::Symbol(";".to_string())]).collect();
+            for variable in parse_variables(&declaration) {
+                self.add_local(variable);
+            }
+        }
+        walk_loop(self, loop_);
+    }
+
+    fn visit_tokens(&mut self, tokens: &[Token]) {
+        for variable in parse_variables(tokens) {
+            self.add_local(variable);
+        }
+    }
+}
+
+/// Reports attributes the compiler doesn't know or that don't apply where
+/// they are written, and checks the layout attributes of classes
+struct AttributeChecker<'a> {
+    class_names: &'a HashMap<String, String>,
+    rc_classes: &'a HashMap<String, Vec<Variable>>,
+    packable: &'a HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl AttributeChecker<'_> {
+    // `free`: the attributes of free functions, not methods or operators
+    fn check(&mut self, attributes: &[Attribute], free: bool) {
+        for attribute in attributes {
+            if !ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.errors.push(format!("unknown attribute `@{}`", attribute.name));
+            } else if SHARED_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.check_levels(attribute);
+            } else if CLASS_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.errors.push(format!("`@{}` applies to classes, not functions", attribute.name));
+            } else if !free && FREE_FUNCTION_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.errors.push(format!("`@{}` applies to free functions, not methods or operators", attribute.name));
+            }
+        }
+    }
+
+    // `@allow` and `@deny` name lints
+    fn check_levels(&mut self, attribute: &Attribute) {
+        if let Err(e) = lints::check(attribute) {
+            self.errors.push(e);
+        }
+    }
+
+    fn is_rc(&self, type_: &str) -> bool {
+        let type_ = type_.trim();
+        self.rc_classes.contains_key(self.class_names.get(type_).map_or(type_, String::as_str))
+    }
+
+    // `@derive(Pack)`: every field has to be a number or a class deriving it
+    fn check_pack(&mut self, class: &Class) {
+        if class.union || class.rc {
+            let kind = if class.union { "a union" } else { "an `rc class`" };
+            self.errors.push(format!("`{}` can't derive `Pack`; it is {}", class.name, kind));
+            return;
+        }
+        for field in &class.variables {
+            if pack::wire(&field.type_).is_some() {
+                continue;
+            }
+            let type_ = field.type_.trim();
+            let packable = self.class_names.get(type_).is_some_and(|mangled| self.packable.contains(mangled));
+            if !packable {
+                self.errors.push(format!(
+                    "field `{}` of class `{}` can't be packed: `{}` is not a number or a class that derives `Pack`",
+                    field.name, class.name, type_
+                ));
+            }
+        }
+        for field in &class.unsupported_fields {
+            self.errors.push(format!("class `{}` can't pack the field `{}`; only `Type name;` and `Type name[N];` fields are packed", class.name, field));
+        }
+    }
+}
+
+impl Visit for AttributeChecker<'_> {
+    fn visit_class(&mut self, class: &Class) {
+        for attribute in &class.attributes {
+            if !ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.errors.push(format!("unknown attribute `@{}`", attribute.name));
+            } else if SHARED_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.check_levels(attribute);
+            } else if !CLASS_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                self.errors.push(format!("`@{}` applies to functions, not classes", attribute.name));
+            } else if attribute.name == "tagged" && !class.union {
+                self.errors.push(format!("`@tagged` applies to unions, not classes like `{}`", class.name));
+            } else if attribute.name == "align" {
+                if let Err(message) = alignment(&attribute.args) {
+                    self.errors.push(format!("`@align` of class `{}` {}", class.name, message));
+                }
+            }
+        }
+        for derive in derives(&class.attributes) {
+            if derive == "Pack" {
+                self.check_pack(class);
+            } else {
+                self.errors.push(format!("`{}` can't be derived; `@derive` supports `Pack`", derive));
+            }
+        }
+        let layout = class.layout();
+        if layout.packed && layout.align.is_some() {
+            self.errors.push(format!("class `{}` can't be both `@packed` and `@align`; its Rust bindings couldn't express that layout", class.name));
+        }
+        // `@c_layout`: the struct is exactly the fields as written, in C terms
+        if has_attribute(&class.attributes, "c_layout") {
+            if class.rc {
+                self.errors.push(format!("`@c_layout` class `{}` can't be an `rc class`; its values are handles to a heap block", class.name));
+            }
+            for field in &class.variables {
+                if self.is_rc(&field.type_) {
+                    self.errors.push(format!("field `{}` of `@c_layout` class `{}` is an rc handle, not a C value", field.name, class.name));
+                }
+            }
+            for field in &class.unsupported_fields {
+                self.errors.push(format!("`@c_layout` class `{}` can't have the field `{}`; only `Type name;` and `Type name[N];` fields are laid out", class.name, field));
+            }
+        }
+        walk_class(self, class);
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        self.check(&function.attributes, false);
+    }
+
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        self.check(&operator.attributes, false);
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        let attributes: Vec<Attribute> = (0..tokens.len()).filter_map(|i| parse_attribute(tokens, i)).map(|(a, _)| a).collect();
+        self.check(&attributes, true);
+    }
+}
+
+/// Reports names declared twice in one block and locals that shadow a
+/// parameter, an outer local or a field, see `scopes`, and functions that
+/// can end without returning a value or have unreachable code, see `flow`
+#[derive(Default)]
+struct BodyChecker {
+    // Name and fields of the class whose methods are being checked
+    class: Option<(String, Vec<Variable>)>,
+    // Path of the namespace whose free functions are being checked, when
+    // they are mangled; a `main` in one isn't the program's
+    namespace: Option<String>,
+    scope: Vec<Attribute>,
+    errors: Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+impl BodyChecker {
+    fn check(&mut self, name: &str, return_type: &str, body: &[Token], params: &[Variable], attributes: &[Attribute]) {
+        let (function, fields) = match &self.class {
+            Some((class, fields)) => (format!("{}::{}", class, name), fields.as_slice()),
+            None => match &self.namespace {
+                Some(namespace) => (format!("{}::{}", namespace, name), &[][..]),
+                None => (name.to_string(), &[][..]),
+            },
+        };
+        let (errors, shadowing) = scopes::check_body(&function, body, params, fields);
+        self.errors.extend(errors);
+        let (errors, unreachable) = flow::check_body(&function, return_type, body);
+        self.errors.extend(errors);
+        let warnings = shadowing.into_iter().map(|w| Warning::new("shadowing", w))
+            .chain(unreachable.into_iter().map(|w| Warning::new("unreachable", w)));
+        self.warnings.extend(warnings.map(|w| w.within(&self.scope).within(attributes)));
+    }
+}
+
+impl Scoped for BodyChecker {
+    fn scope(&mut self) -> &mut Vec<Attribute> {
+        &mut self.scope
+    }
+}
+
+impl Visit for BodyChecker {
+    fn visit_class(&mut self, class: &Class) {
+        self.class = Some((class.name.clone(), class.variables.clone()));
+        self.within(&class.attributes, |v| walk_class(v, class));
+        self.class = None;
+    }
+
+    fn visit_namespace(&mut self, namespace: &Namespace) {
+        let outer = self.namespace.clone();
+        if let Some(prefix) = &namespace.prefix {
+            self.namespace = Some(namespace.path.clone().unwrap_or_else(|| prefix.clone()));
+        }
+        walk_namespace(self, namespace);
+        self.namespace = outer;
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        self.check(&function.name, &function.return_type, &function.body_tokens, &function.params, &function.attributes);
+    }
+
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        self.check(&format!("operator{}", operator.operator), &operator.return_type, &operator.body_tokens, &operator.params, &operator.attributes);
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        for definition in function_definitions(tokens) {
+            let Some(end) = definition.body_end(tokens) else { continue };
+            let Some(open) = (definition.name_index..end).find(|&i| tokens[i] == Token::Symbol("{".to_string())) else { continue };
+            self.check(definition.name(tokens), &detokenize(&definition.return_type), &tokens[open + 1..end], &parse_params(&definition.params), &definition.attributes);
+        }
+    }
+}
+
+/// A checker whose warnings take the lint levels of the items around the
+/// code they are about, see `lints`
+trait Scoped: Visit + Sized {
+    /// The attributes of the items around the code being visited,
+    /// outermost first
+    fn scope(&mut self) -> &mut Vec<Attribute>;
+
+    /// Runs `visit` inside an item written with `attributes`
+    fn within(&mut self, attributes: &[Attribute], visit: impl FnOnce(&mut Self)) {
+        let depth = self.scope().len();
+        self.scope().extend(attributes.iter().cloned());
+        visit(self);
+        self.scope().truncate(depth);
+    }
+
+    /// Walks a raw item a run at a time, each function inside its
+    /// attributes
+    fn walk_raw_scoped(&mut self, tokens: &[Token]) {
+        let mut from = 0;
+        for definition in function_definitions(tokens) {
+            let Some(end) = definition.body_end(tokens) else { continue };
+            if definition.attributes_start < from {
+                continue;
+            }
+            walk_block(self, &tokens[from..definition.attributes_start]);
+            self.within(&definition.attributes, |v| walk_block(v, &tokens[definition.attributes_start..=end]));
+            from = end + 1;
+        }
+        walk_block(self, &tokens[from..]);
+    }
+}
+
+/// Checks calls to functions declared by `#import <c:...>` headers against
+/// their prototypes, and printf-style calls against their format
+struct CallChecker<'a> {
+    c_functions: &'a HashMap<String, cheader::CFunction>,
+    class_names: &'a HashMap<String, String>,
+    // Every variable and parameter declared so far, of any type
+    declared: Vec<Variable>,
+    scope: Vec<Attribute>,
+    errors: Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Scoped for CallChecker<'_> {
+    fn scope(&mut self) -> &mut Vec<Attribute> {
+        &mut self.scope
+    }
+}
+
+impl Visit for CallChecker<'_> {
+    fn visit_class(&mut self, class: &Class) {
+        self.within(&class.attributes, |v| walk_class(v, class));
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        self.declared.extend(function.params.iter().cloned());
+        self.within(&function.attributes, |v| walk_function(v, function));
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        for definition in function_definitions(tokens) {
+            self.declared.extend(parse_params(&definition.params));
+        }
+        self.walk_raw_scoped(tokens);
+    }
+
+    fn visit_tokens(&mut self, tokens: &[Token]) {
+        self.declared.extend(parse_variables(tokens));
+        for i in 0..tokens.len() {
+            let Token::Identifier(name) = &tokens[i] else { continue };
+            if !matches!(tokens.get(i + 1), Some(Token::Symbol(s)) if s == "(") {
+                continue;
+            }
+            // Skip member calls and definitions or declarations of the same name
+            match i.checked_sub(1).map(|p| &tokens[p]) {
+                Some(Token::Symbol(s)) if s == "." || s == "->" || s == "::" => continue,
+                Some(Token::Identifier(word)) if !is_keyword(word) => continue,
+                _ => {}
+            }
+
+            if format::format_position(name).is_some() {
+                let arguments = call_arguments(tokens, i + 1);
+                let (errors, warnings) = format::check_call(name, &arguments, |arg| {
+                    format::argument_type(arg, &self.declared, self.class_names)
+                });
+                self.errors.extend(errors);
+                let scope = &self.scope;
+                self.warnings.extend(warnings.into_iter().map(|w| Warning::new("format", w).within(scope)));
+            }
+
+            let Some(function) = self.c_functions.get(name) else { continue };
+            let count = call_argument_count(tokens, i + 1);
+            if !function.accepts(count) {
+                let expected = if function.variadic {
+                    format!("at least {}", function.params.len())
+                } else {
+                    function.params.len().to_string()
+                };
+                self.errors.push(format!(
+                    "`{}` takes {} argument{} but {} {} given",
+                    name,
+                    expected,
+                    if function.params.len() == 1 { "" } else { "s" },
+                    count,
+                    if count == 1 { "was" } else { "were" }
+                ));
+            }
+        }
+    }
+}
+
+/// Checks loop conditions, that `break`/`continue` have something to leave,
+/// that for-each loops iterate over something iterable and that switches on
+/// an enum without `default` handle every enumerator
+struct ControlFlowChecker<'a> {
+    variables: &'a [Variable],
+    class_names: &'a HashMap<String, String>,
+    truthy_classes: &'a HashSet<String>,
+    methods: &'a HashMap<String, Vec<String>>,
+    enums: &'a HashMap<String, Vec<String>>,
+    // Names in scope where the code being visited is, see `scopes`
+    symbols: SymbolTable,
+    // Enclosing loops and switches of the code being visited
+    loops: usize,
+    switches: usize,
+    labels: Vec<String>,
+    scope: Vec<Attribute>,
+    errors: Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Scoped for ControlFlowChecker<'_> {
+    fn scope(&mut self) -> &mut Vec<Attribute> {
+        &mut self.scope
+    }
+}
+
+impl ControlFlowChecker<'_> {
+    fn check_condition(&mut self, keyword: &str, condition: &[Token]) {
+        let condition: Vec<&Token> = condition.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        match condition.as_slice() {
+            [] => self.errors.push(format!("`{}` loop has no condition", keyword)),
+            [Token::Identifier(name)] => self.check_truth_test(&format!("`{}` condition", keyword), name),
+            _ => {}
+        }
+    }
+
+    // for-each needs a class value with `begin()` and `end()`
+    fn check_iterable(&mut self, iterable: &[Token]) {
+        let code: Vec<&Token> = iterable.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        let variable = match code.as_slice() {
+            [Token::Identifier(name)] => find_class_variable(self.variables, self.class_names, name),
+            _ => None,
+        };
+        let Some(variable) = variable else {
+            self.errors.push(format!("for-each over `{}` needs a class value with `begin()` and `end()`", detokenize(iterable).trim()));
+            return;
+        };
+        let mangled = self.class_names.get(&variable.type_).unwrap_or(&variable.type_);
+        let methods = self.methods.get(mangled).map(Vec::as_slice).unwrap_or_default();
+        let missing: Vec<&str> = ["begin", "end"].into_iter().filter(|m| !methods.iter().any(|method| method == m)).collect();
+        if !missing.is_empty() {
+            self.errors.push(format!(
+                "`{}` can't be iterated with for-each: class `{}` has no {}",
+                variable.name,
+                variable.type_,
+                missing.iter().map(|m| format!("`{}()`", m)).collect::<Vec<_>>().join(" or ")
+            ));
+        }
+    }
+
+    fn check_exhaustive(&mut self, switch: &Switch) {
+        let scrutinee: Vec<&Token> = switch.scrutinee.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        let [Token::Identifier(name)] = scrutinee.as_slice() else { return };
+        let Some(variable) = self.symbols.lookup(name).map(Symbol::variable) else { return };
+        let Some(variants) = self.enums.get(&variable.type_) else { return };
+
+        let mut cases = Vec::new();
+        if case_labels(&switch.body, &mut cases) {
+            return;
+        }
+        let missing: Vec<String> = variants.iter().filter(|v| !cases.contains(v)).map(|v| format!("`{}`", v)).collect();
+        if !missing.is_empty() {
+            let message = format!("switch on `{}` of enum `{}` has no `default` and does not handle {}", name, variable.type_, missing.join(", "));
+            self.warnings.push(Warning::new("incomplete_switch", message).within(&self.scope));
+        }
+    }
+
+    // A class value used as a condition needs an `operator bool`
+    fn check_truth_test(&mut self, context: &str, name: &str) {
+        let Some(variable) = find_class_variable(self.variables, self.class_names, name) else { return };
+        let mangled = self.class_names.get(&variable.type_).unwrap_or(&variable.type_);
+        if !self.truthy_classes.contains(mangled) {
+            self.errors.push(format!(
+                "{} `{}` has class type `{}`, which can't be tested for truth",
+                context, name, variable.type_
+            ));
+        }
+    }
+}
+
+impl Visit for ControlFlowChecker<'_> {
+    fn visit_class(&mut self, class: &Class) {
+        self.within(&class.attributes, |v| walk_class(v, class));
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        let depth = self.symbols.enter(&function.params);
+        self.within(&function.attributes, |v| walk_function(v, function));
+        self.symbols.close(depth);
+    }
+
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        let depth = self.symbols.enter(&operator.params);
+        walk_operator(self, operator);
+        self.symbols.close(depth);
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        self.walk_raw_scoped(tokens);
+    }
+
+    fn visit_loop(&mut self, loop_: &Loop) {
+        let depth = self.symbols.depth();
+        self.symbols.push();
+        match &loop_.kind {
+            LoopKind::While { condition } => self.check_condition("while", condition),
+            LoopKind::DoWhile { condition } => self.check_condition("do-while", condition),
+            LoopKind::For { .. } => {}
+            LoopKind::ForEach { iterable, .. } => self.check_iterable(iterable),
+        }
+        self.loops += 1;
+        self.labels.extend(loop_.label.clone());
+        walk_loop(self, loop_);
+        if loop_.label.is_some() {
+            self.labels.pop();
+        }
+        self.loops -= 1;
+        self.symbols.close(depth);
+    }
+
+    fn visit_switch(&mut self, switch: &Switch) {
+        self.check_exhaustive(switch);
+        let depth = self.symbols.depth();
+        self.symbols.push();
+        self.switches += 1;
+        walk_switch(self, switch);
+        self.switches -= 1;
+        self.symbols.close(depth);
+    }
+
+    fn visit_tokens(&mut self, tokens: &[Token]) {
+        self.symbols.declare_in(tokens);
+        for (i, context) in truth_tests(tokens) {
+            if let Token::Identifier(name) = &tokens[i] {
+                self.check_truth_test(&context, name);
+            }
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            let Token::Identifier(word) = token else { continue };
+            if word != "break" && word != "continue" {
+                continue;
+            }
+            if let Some(Token::Identifier(label)) = tokens.get(i + 1) {
+                if !self.labels.contains(label) {
+                    self.errors.push(format!("`{} {}` has no enclosing loop labeled `{}`", word, label, label));
+                }
+            } else if word == "break" && self.loops + self.switches == 0 {
+                self.errors.push("`break` outside of a loop or switch".to_string());
+            } else if word == "continue" && self.loops == 0 {
+                self.errors.push("`continue` outside of a loop".to_string());
+            }
+        }
+    }
+}
+
+// Collects the `case NAME:` labels of a switch body, not counting nested
+// switches. Returns whether the body has a `default` label.
+fn case_labels(body: &[Stmt], cases: &mut Vec<String>) -> bool {
+    let mut has_default = false;
+    for stmt in body {
+        match stmt {
+            Stmt::Tokens(tokens) => {
+                let code: Vec<&Token> = tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+                for (i, token) in code.iter().enumerate() {
+                    match (token, code.get(i + 1), code.get(i + 2)) {
+                        (Token::Identifier(word), Some(Token::Identifier(label)), Some(Token::Symbol(colon)))
+                            if word == "case" && colon == ":" => cases.push(label.clone()),
+                        (Token::Identifier(word), Some(Token::Symbol(colon)), _) if word == "default" && colon == ":" => has_default = true,
+                        _ => {}
+                    }
+                }
+            }
+            Stmt::Loop(loop_) => has_default |= case_labels(&loop_.body, cases),
+            Stmt::Switch(_) => {}
+        }
+    }
+    has_default
+}
+
+// Number of comma-separated arguments in the call whose `(` is at `open`
+fn call_argument_count(tokens: &[Token], open: usize) -> usize {
+    call_arguments(tokens, open).len()
+}
+
+// The comma-separated arguments of the call whose `(` is at `open`
+fn call_arguments(tokens: &[Token], open: usize) -> Vec<&[Token]> {
+    let mut depth = 0;
+    let mut arguments = Vec::new();
+    let mut start = open + 1;
+    let mut empty = true;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    if !empty {
+                        arguments.push(&tokens[start..i]);
+                    }
+                    return arguments;
+                }
+            }
+            Token::Symbol(s) if s == "," && depth == 1 => {
+                arguments.push(&tokens[start..i]);
+                start = i + 1;
+                continue;
+            }
+            Token::Newline | Token::Comment(_) => continue,
+            _ => {}
+        }
+        empty = false;
+    }
+    if !empty {
+        arguments.push(&tokens[start..]);
+    }
+    arguments
+}
+
+/// Identifiers that are tested for truth on their own: `if (v)`,
+/// `while (v)`, operands of `&&`, `||` and `!`, and `v ? a : b`. Returns
+/// their indices with a description of where they appear.
+fn truth_tests(tokens: &[Token]) -> Vec<(usize, String)> {
+    let symbol = |i: Option<usize>| match i.and_then(|i| tokens.get(i)) {
+        Some(Token::Symbol(s)) => s.as_str(),
+        Some(Token::Identifier(s)) if s == "return" => ";",
+        None => ";",
+        _ => "",
+    };
+    let mut tests = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !matches!(token, Token::Identifier(word) if !is_keyword(word)) {
+            continue;
+        }
+        let before = symbol(i.checked_sub(1));
+        let after = symbol(Some(i + 1));
+        // Only a whole operand counts: `v.ok && x` tests `v.ok`, `a < v && x` tests `a < v`
+        if !matches!(before, "(" | "&&" | "||" | "!" | "=" | "," | ";" | "{" | "}" | "?" | ":")
+            || !matches!(after, ")" | "&&" | "||" | "?" | ";" | "," | ":")
+        {
+            continue;
+        }
+        let keyword = match i.checked_sub(2).map(|k| &tokens[k]) {
+            Some(Token::Identifier(word)) if before == "(" && after == ")" && (word == "if" || word == "while") => Some(word),
+            _ => None,
+        };
+        let context = if let Some(keyword) = keyword {
+            format!("`{}` condition", keyword)
+        } else if matches!(before, "&&" | "||" | "!") {
+            format!("`{}` operand", before)
+        } else if matches!(after, "&&" | "||") {
+            format!("`{}` operand", after)
+        } else if after == "?" {
+            "`?:` condition".to_string()
+        } else {
+            continue;
+        };
+        tests.push((i, context));
+    }
+    tests
+}
+
+/// Retains and releases for `rc class` values, see `rc`
+pub struct RefCounting;
+
+impl Pass for RefCounting {
+    fn name(&self) -> &str {
+        "rc"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if cx.session.rc_classes.is_empty() {
+            return;
+        }
+        let class_names = cx.class_names();
+        let types = RcTypes { class_names: &class_names, classes: &cx.session.rc_classes };
+        RcRewriter { types: &types }.visit_module_mut(&mut cx.module);
+    }
+}
+
+struct RcRewriter<'a> {
+    types: &'a RcTypes<'a>,
+}
+
+impl RcRewriter<'_> {
+    // `self` is an rc handle in methods of rc classes
+    fn receiver(&self, namespace: &Option<String>, class_name: &str) -> Option<String> {
+        let mangled = mangle(namespace, class_name);
+        self.types.classes.contains_key(&mangled).then_some(mangled)
+    }
+}
+
+impl VisitMut for RcRewriter<'_> {
+    fn visit_class_mut(&mut self, class: &mut Class) {
+        if class.rc {
+            class.rc_fields = class
+                .variables
+                .iter()
+                .filter_map(|field| Some((field.name.clone(), self.types.class_of(&field.type_)?)))
+                .collect();
+        }
+        walk_class_mut(self, class);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        let receiver = self.receiver(&function.namespace, &function.class_name);
+        function.body_tokens = rc::rewrite_body(&function.body_tokens, &function.params, receiver.as_deref(), self.types);
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        let receiver = if operator.free { None } else { self.receiver(&operator.namespace, &operator.class_name) };
+        operator.body_tokens = rc::rewrite_body(&operator.body_tokens, &operator.params, receiver.as_deref(), self.types);
+    }
+
+    fn visit_raw_mut(&mut self, tokens: &mut Vec<Token>) {
+        // Bodies are rewritten last to first so earlier indices stay valid
+        for definition in function_definitions(tokens).into_iter().rev() {
+            let Some(open) = (definition.name_index..tokens.len()).find(|&k| matches!(&tokens[k], Token::Symbol(s) if s == "{")) else { continue };
+            let Some(close) = matching_close(tokens, open) else { continue };
+            let body = rc::rewrite_body(&tokens[open + 1..close], &parse_params(&definition.params), None, self.types);
+            tokens.splice(open + 1..close, body);
+        }
+    }
+}
+
+/// Rewrites method calls, operators and `::` paths into plain C
+pub struct Lower;
+
+impl Pass for Lower {
+    fn name(&self) -> &str {
+        "lower"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let class_names = cx.class_names();
+        let mut interfaces = cx.session.interfaces.clone();
+        interfaces.insert("any".to_string(), dynamic::any());
+        // The file's own classes, file-private ones too, over those imported
+        let mut collector = ClassCollector::default();
+        collector.visit_module(&cx.module);
+        let mut fields: HashMap<String, (String, Vec<Variable>)> = cx.session.known_classes.iter()
+            .filter(|info| !info.interface)
+            .map(|info| (info.c_name.clone(), (info.name.clone(), info.fields.clone())))
+            .collect();
+        fields.extend(collector.classes.iter().map(|class| (class.full_name(), (class.name.clone(), class.variables.clone()))));
+        // Free functions of Z and of imported C headers, by name
+        let mut function_returns = cx.session.function_returns.clone();
+        for function in cx.session.c_functions.values() {
+            function_returns.entry(function.name.clone()).or_insert_with(|| overloads::c_type(&function.return_type, &class_names));
+        }
+        let mut lowerer = Lowerer {
+            variables: &cx.variables,
+            scalars: &cx.scalars,
+            class_names: &class_names,
+            fields: &fields,
+            truthy_classes: &cx.session.truthy_classes,
+            method_returns: &cx.session.method_returns,
+            function_returns: &function_returns,
+            operator_overloads: &cx.session.operator_overloads,
+            reflected: &cx.session.reflected,
+            interfaces: &interfaces,
+            methods: &cx.session.methods,
+            namespaces: &cx.session.namespaces,
+            scope: Vec::new(),
+            symbols: SymbolTable::default(),
+            namespace_globals: Vec::new(),
+            labels: Vec::new(),
+            next_label: 0,
+            dynamic: dynamic::Uses::default(),
+            errors: Vec::new(),
+        };
+        lowerer.visit_module_mut(&mut cx.module);
+        cx.dynamic = lowerer.dynamic;
+        for message in lowerer.errors {
+            cx.error(message);
+        }
+    }
+}
+
+struct Lowerer<'a> {
+    variables: &'a [Variable],
+    scalars: &'a [Variable],
+    class_names: &'a HashMap<String, String>,
+    // Name and fields of every class, by mangled name
+    fields: &'a HashMap<String, (String, Vec<Variable>)>,
+    truthy_classes: &'a HashSet<String>,
+    method_returns: &'a HashMap<String, String>,
+    function_returns: &'a HashMap<String, String>,
+    operator_overloads: &'a HashMap<String, Vec<Overload>>,
+    reflected: &'a HashSet<String>,
+    // Every interface by mangled name, `any` included
+    interfaces: &'a HashMap<String, Interface>,
+    methods: &'a HashMap<String, Vec<String>>,
+    // Members of every named namespace, whichever file declared them
+    namespaces: &'a namespaces::NamespaceTable,
+    // Parameters of the function being lowered
+    scope: Vec<Variable>,
+    // Names in scope where the code being lowered is, as written, see
+    // `scopes`
+    symbols: SymbolTable,
+    // Globals, classes and functions of each enclosing namespace, innermost
+    // last: (name, mangled name).
+    // The first frame holds what anonymous namespaces expose to the whole file.
+    namespace_globals: Vec<Vec<(String, String)>>,
+    // Labeled loops around the code being lowered, innermost last
+    labels: Vec<LoopLabel>,
+    // Makes the generated C labels and loop variables unique within the file
+    next_label: usize,
+    // Vtables and type IDs the lowered code needs
+    dynamic: dynamic::Uses,
+    errors: Vec<String>,
+}
+
+struct LoopLabel {
+    name: String,
+    id: usize,
+    broken: bool,
+    continued: bool,
+}
+
+// `self` inside a method of `class_name`, so calls on it are lowered too
+fn receiver(class_name: &str) -> Variable {
+    Variable { name: "self".to_string(), type_: class_name.to_string() }
+}
+
+// The mangled class of a value or pointer of type `type_` whose fields
+// are known
+fn class_with_fields<'f>(fields: &'f HashMap<String, (String, Vec<Variable>)>, class_names: &HashMap<String, String>, type_: &str) -> Option<&'f str> {
+    let name = type_.split_whitespace().last()?.trim_end_matches('*');
+    let mangled = class_names.get(name).map_or(name, String::as_str);
+    let (key, (_, members)) = fields.get_key_value(mangled)?;
+    (!members.is_empty()).then_some(key.as_str())
+}
+
+// Targets of `break label;` and `continue label;`
+fn break_label(name: &str, id: usize) -> String {
+    format!("__z_break_{}_{}", name, id)
+}
+
+fn continue_label(name: &str, id: usize) -> String {
+    format!("__z_continue_{}_{}", name, id)
+}
+
+// Members of anonymous namespaces (and their nested anonymous namespaces)
+// are usable unqualified anywhere in the file
+fn anonymous_members(items: &[Item], members: &mut Vec<(String, String)>) {
+    for item in items {
+        if let Item::Namespace(namespace) = item {
+            if !namespace.is_anonymous() {
+                continue;
+            }
+            for member in &namespace.items {
+                match member {
+                    Item::Global(global) => members.push((global.name.clone(), global.full_name())),
+                    Item::Class(class) => members.push((class.name.clone(), class.full_name())),
+                    _ => {}
+                }
+            }
+            anonymous_members(&namespace.items, members);
+        }
+    }
+}
+
+impl Lowerer<'_> {
+    // The variables in scope here, one per name
+    fn variables_in_scope(&self) -> Vec<Variable> {
+        let mut names = HashSet::new();
+        self.symbols.visible().filter(|s| names.insert(s.name.clone())).map(|symbol| {
+            let mut variable = symbol.variable();
+            // `geo::V` of a class known by its own name
+            let class = variable.type_.trim_end_matches('*');
+            if !self.class_names.contains_key(class) {
+                if let Some(name) = class.rsplit([':', ' ']).next().filter(|name| self.class_names.contains_key(*name)) {
+                    variable.type_ = format!("{}{}", name, &variable.type_[class.len()..]);
+                }
+            }
+            variable
+        }).collect()
+    }
+
+    // `break outer;` -> `goto __z_break_outer_0;`
+    fn lower_labeled_jumps(&mut self, tokens: &mut [Token]) {
+        for i in 0..tokens.len() {
+            let Token::Identifier(keyword) = &tokens[i] else { continue };
+            let is_break = keyword == "break";
+            if !is_break && keyword != "continue" {
+                continue;
+            }
+            let Some(Token::Identifier(name)) = tokens.get(i + 1) else { continue };
+            let Some(label) = self.labels.iter_mut().rev().find(|l| &l.name == name) else { continue };
+
+            let target = if is_break {
+                label.broken = true;
+                break_label(&label.name, label.id)
+            } else {
+                label.continued = true;
+                continue_label(&label.name, label.id)
+            };
+            tokens[i] = Token::Identifier("goto".to_string());
+            tokens[i + 1] = Token::Identifier(target);
+        }
+    }
+
+    // `for (T x : items) body` ->
+    // `for (it = V_begin(items), end = V_end(items); it != end; it++) { T x = *it; body }`
+    fn lower_for_each(&mut self, loop_: &mut Loop, variables: &[Variable]) {
+        let LoopKind::ForEach { binding, iterable } = &loop_.kind else { return };
+        let Some(Token::Identifier(name)) = iterable.iter().find(|t| !matches!(t, Token::Newline | Token::Comment(_))) else { return };
+        let Some(variable) = find_class_variable(variables, self.class_names, name) else { return };
+        let class = self.class_names.get(&variable.type_).unwrap_or(&variable.type_);
+
+        let id = self.next_label;
+        self.next_label += 1;
+        let code = |code: String| -> Vec<Token> { tokenize(&code).into_iter().filter(|t| !matches!(t, Token::Eof)).collect() };
+        let init = code(format!(
+            "__typeof__({class}_begin({name})) __z_it_{id} = {class}_begin({name}), __z_end_{id} = {class}_end({name})"
+        ));
+        let condition = code(format!("__z_it_{id} != __z_end_{id}"));
+        let step = code(format!("__z_it_{id}++"));
+        let mut element = binding.clone();
+        element.extend(code(format!(" = *__z_it_{id};")));
+
+        loop_.body.insert(0, Stmt::Tokens(element));
+        loop_.kind = LoopKind::For { init, condition, step };
+    }
+
+    // `typeinfo(V)` -> `(&V__typeinfo)`
+    fn lower_typeinfo(&mut self, tokens: &mut Vec<Token>) {
+        for (start, end, path) in reflect::typeinfo_calls(tokens).into_iter().rev() {
+            let class = self.lookup(&path)
+                .or(self.class_names.get(&path).map(String::as_str))
+                .or(self.class_names.values().find(|mangled| **mangled == path).map(String::as_str))
+                .map(str::to_string);
+            let Some(class) = class else {
+                self.errors.push(format!("`typeinfo` needs a class, and `{}` is not one", path));
+                continue;
+            };
+            if !self.reflected.contains(&class) {
+                self.errors.push(format!("class `{}` has no type information; declare it `@reflect class`", path));
+                continue;
+            }
+            let table = tokenize(&format!("(&{})", reflect::table_name(&class)));
+            tokens.splice(start..end, table.into_iter().filter(|t| !matches!(t, Token::Eof)));
+        }
+    }
+
+    // `v` -> `V_operator_bool(v)` where a class value is tested for truth
+    fn lower_truth_tests(&self, tokens: &mut Vec<Token>, variables: &[Variable], whole_condition: bool) {
+        let mut positions: Vec<usize> = truth_tests(tokens).into_iter().map(|(i, _)| i).collect();
+        if whole_condition {
+            let mut code = tokens.iter().enumerate().filter(|(_, t)| !matches!(t, Token::Newline | Token::Comment(_)));
+            if let (Some((i, Token::Identifier(_))), None) = (code.next(), code.next()) {
+                positions.push(i);
+            }
+        }
+        for &i in positions.iter().rev() {
+            let Token::Identifier(name) = &tokens[i] else { continue };
+            let Some(variable) = find_class_variable(variables, self.class_names, name) else { continue };
+            let mangled = self.class_names.get(&variable.type_).unwrap_or(&variable.type_);
+            if !self.truthy_classes.contains(mangled) {
+                continue;
+            }
+            let call = tokenize(&format!("{}_operator_bool({})", mangled, name));
+            tokens.splice(i..=i, call.into_iter().filter(|t| !matches!(t, Token::Eof)));
+        }
+    }
+
+    // `Shape s = &c;` and `Shape(&c)` -> `(Shape){ &c, &Circle__Shape__vtable }`,
+    // and `s as? Circle` -> the `Circle*` in `s`, or NULL
+    fn lower_dynamic(&mut self, tokens: &mut Vec<Token>, variables: &[Variable]) {
+        let resolve = |name: &str| self.lookup(name).or(self.class_names.get(name).map(String::as_str)).map(str::to_string);
+        let interface_of = |name: &str| resolve(name).or(Some(name.to_string())).filter(|mangled| self.interfaces.contains_key(mangled));
+
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+        let (mut errors, mut vtables, mut type_ids) = (Vec::new(), Vec::new(), Vec::new());
+        for conversion in dynamic::conversions(tokens, &|name| interface_of(name).is_some()) {
+            let interface = interface_of(&conversion.interface).unwrap();
+            let operand = &conversion.operand;
+            let class = match operand.as_slice() {
+                [Token::Symbol(amp), Token::Identifier(name), rest @ ..] if amp == "&" && rest.first().is_none_or(|t| matches!(t, Token::Symbol(s) if s == "[")) => {
+                    find_class_variable(variables, self.class_names, name).map(|v| resolve(&v.type_).unwrap_or(v.type_.clone()))
+                }
+                [Token::Identifier(name)] => find_class_pointer(variables, self.class_names, name).cloned(),
+                _ => None,
+            };
+            // Other initializers may well be interface values already
+            let Some(class) = class.filter(|class| !self.interfaces.contains_key(class)) else {
+                if conversion.explicit {
+                    errors.push(format!(
+                        "`{}(...)` needs a pointer to a class value, e.g. `{}(&value)`, not `{}`",
+                        conversion.interface, conversion.interface, detokenize(operand).trim()
+                    ));
+                }
+                continue;
+            };
+            let methods = self.methods.get(&class).map(Vec::as_slice).unwrap_or_default();
+            let missing: Vec<&str> = self.interfaces[&interface].methods.iter()
+                .map(|m| m.name.as_str())
+                .filter(|m| !methods.iter().any(|method| method == m))
+                .collect();
+            if let Some(method) = missing.first() {
+                errors.push(format!("class `{}` can't be a `{}`: it has no method `{}`", class, conversion.interface, method));
+                continue;
+            }
+            let code = format!("({}){{ {}, &{} }}", interface, detokenize(operand).trim(), dynamic::vtable_name(&class, &interface));
+            edits.push((conversion.start, conversion.end, code));
+            vtables.push((class, interface));
+        }
+        for downcast in dynamic::downcasts(tokens) {
+            let path = downcast.class.replace("::", "_");
+            let class = resolve(&downcast.class)
+                .or(self.class_names.values().find(|mangled| **mangled == path).cloned())
+                .filter(|class| !self.interfaces.contains_key(class));
+            let Some(class) = class else {
+                errors.push(format!("`as?` needs a class, and `{}` is not one", downcast.class));
+                continue;
+            };
+            let operand = detokenize(&downcast.operand);
+            let operand = operand.trim();
+            let code = format!("(({operand}).vt->type == &{} ? ({class}*)({operand}).data : ({class}*)0)", dynamic::type_id_name(&class));
+            edits.push((downcast.start, downcast.end, code));
+            type_ids.push(class);
+        }
+        self.errors.extend(errors);
+        self.dynamic.vtables.extend(vtables);
+        self.dynamic.type_ids.extend(type_ids);
+        edits.sort_by_key(|&(start, _, _)| std::cmp::Reverse(start));
+        for (start, end, code) in edits {
+            tokens.splice(start..end, tokenize(&code).into_iter().filter(|t| !matches!(t, Token::Eof)));
+        }
+    }
+
+    // Classes named in a type, e.g. `Point` in `const Point*`, whether they
+    // are members of an enclosing namespace or known from anywhere else
+    // Reports `v.name` where `v` is of a class with no field or method
+    // `name`, following `a.pos.x` through fields of class type. A variable
+    // of the function decides the class; without one, any variable of the
+    // file of that name that has the member will do
+    fn check_fields(&mut self, tokens: &[Token]) {
+        let (fields, class_names) = (self.fields, self.class_names);
+        let class_of = |type_: &str| class_with_fields(fields, class_names, type_);
+        for i in 0..tokens.len() {
+            let Token::Identifier(name) = &tokens[i] else { continue };
+            if i > 0 && matches!(&tokens[i - 1], Token::Symbol(s) if s == "." || s == "->" || s == "::") {
+                continue;
+            }
+            let own: Vec<&Variable> = self.scope.iter().filter(|v| v.name == *name).collect();
+            let declared = if own.is_empty() { self.variables.iter().filter(|v| v.name == *name).collect() } else { own };
+            let mut classes: Vec<&str> = declared.iter().filter_map(|v| class_of(&v.type_)).collect();
+            let mut j = i + 1;
+            while let (false, Some(Token::Symbol(access)), Some(Token::Identifier(member))) = (classes.is_empty(), tokens.get(j), tokens.get(j + 1)) {
+                if access != "." && access != "->" {
+                    break;
+                }
+                // Members the compiler adds, e.g. a checked union's tag
+                if member.starts_with("__z_") {
+                    break;
+                }
+                // An `rc class` is reached through its handle, see `rc`
+                if member == "ptr" && matches!(tokens.get(j + 2), Some(Token::Symbol(s)) if s == "->") {
+                    j += 2;
+                    continue;
+                }
+                let has_method = classes.iter().any(|class| self.methods.get(*class).is_some_and(|methods| methods.contains(member)));
+                let types: Vec<&str> = classes.iter()
+                    .filter_map(|class| fields[*class].1.iter().find(|f| f.name == *member))
+                    .map(|field| field.type_.as_str())
+                    .collect();
+                if types.is_empty() && !has_method {
+                    let (class, fields) = &fields[classes[0]];
+                    let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+                    let listed: Vec<String> = names.iter().map(|n| format!("`{}`", n)).collect();
+                    let listed = match listed.split_last() {
+                        Some((only, [])) => format!("its only field is {}", only),
+                        Some((last, rest)) => format!("its fields are {} and {}", rest.join(", "), last),
+                        None => String::new(),
+                    };
+                    let message = match suggest::closest(member, names.iter().copied()) {
+                        Some(close) => format!("class `{}` has no field `{}`; did you mean `{}`? {}", class, member, close, listed),
+                        None => format!("class `{}` has no field `{}`; {}", class, member, listed),
+                    };
+                    if !self.errors.contains(&message) {
+                        self.errors.push(message);
+                    }
+                    break;
+                }
+                classes = types.into_iter().filter_map(&class_of).collect();
+                j += 2;
+            }
+        }
+    }
+
+    fn mangle_type(&self, type_: &str) -> String {
+        type_.split(' ')
+            .map(|word| {
+                let name = word.trim_end_matches('*');
+                match self.lookup(name).or(self.class_names.get(name).map(String::as_str)) {
+                    Some(mangled) => format!("{}{}", mangled, &word[name.len()..]),
+                    None => word.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn lookup(&self, name: &str) -> Option<&str> {
+        self.namespace_globals.iter().rev()
+            .flat_map(|globals| globals.iter())
+            .find(|(global, _)| global == name)
+            .map(|(_, mangled)| mangled.as_str())
+    }
+
+    // `geo::area` naming a `@no_mangle` function becomes `area`; other paths
+    // are joined into their mangled names later
+    fn unmangled_paths(&self, tokens: &mut Vec<Token>) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let after_access = i > 0 && matches!(&tokens[i - 1], Token::Symbol(s) if s == "::" || s == "." || s == "->");
+            if !matches!(tokens[i], Token::Identifier(_)) || after_access {
+                i += 1;
+                continue;
+            }
+            let mut segments = Vec::new();
+            let mut end = i;
+            while let Some(Token::Identifier(segment)) = tokens.get(end) {
+                segments.push(segment.clone());
+                match tokens.get(end + 1) {
+                    Some(Token::Symbol(s)) if s == "::" && matches!(tokens.get(end + 2), Some(Token::Identifier(_))) => end += 2,
+                    _ => break,
+                }
+            }
+            let kept = segments.split_last()
+                .filter(|(_, path)| !path.is_empty())
+                .and_then(|(name, path)| self.namespaces.lookup(&path.join("::"), name))
+                .filter(|member| member.kind == namespaces::MemberKind::Function && Some(member.c_name.as_str()) == segments.last().map(String::as_str));
+            if let Some(member) = kept {
+                tokens.splice(i..=end, [Token::Identifier(member.c_name.clone())]);
+            } else {
+                i = end;
+            }
+            i += 1;
+        }
+    }
+
+    // Unqualified uses of a namespace member from inside that namespace.
+    // A leading `::` opts out and names the file-scope (usually C) symbol.
+    fn mangle_namespace_globals(&self, tokens: &mut Vec<Token>) {
+        let mut file_scope = Vec::new();
+        for i in 0..tokens.len() {
+            let Token::Identifier(name) = &tokens[i] else { continue };
+            if self.scope.iter().any(|p| &p.name == name) {
+                continue;
+            }
+            if i > 0 && matches!(&tokens[i - 1], Token::Symbol(s) if s == "::") {
+                file_scope.push(i - 1);
+                continue;
+            }
+            let member_access = i > 0 && matches!(&tokens[i - 1], Token::Symbol(s) if s == "." || s == "->");
+            let qualifier = matches!(tokens.get(i + 1), Some(Token::Symbol(s)) if s == "::");
+            if member_access || qualifier {
+                continue;
+            }
+
+            if let Some(mangled) = self.lookup(name) {
+                tokens[i] = Token::Identifier(mangled.to_string());
+            }
+        }
+        for index in file_scope.into_iter().rev() {
+            tokens.remove(index);
+        }
+    }
+}
+
+impl VisitMut for Lowerer<'_> {
+    fn visit_module_mut(&mut self, module: &mut Module) {
+        let mut members = Vec::new();
+        anonymous_members(&module.items, &mut members);
+        self.namespace_globals.push(members);
+        walk_module_mut(self, module);
+        self.namespace_globals.pop();
+    }
+
+    fn visit_namespace_mut(&mut self, namespace: &mut Namespace) {
+        let mut members = Vec::new();
+        for item in &namespace.items {
+            match item {
+                Item::Global(global) => members.push((global.name.clone(), global.full_name())),
+                Item::Class(class) => members.push((class.name.clone(), class.full_name())),
+                // Free functions of named namespaces are mangled like globals;
+                // anonymous ones stay unmangled and are made static instead
+                Item::Raw(tokens) if !namespace.is_anonymous() => {
+                    if let Some(prefix) = &namespace.prefix {
+                        for definition in function_definitions(tokens) {
+                            let name = definition.name(tokens);
+                            let c_name = if keeps_name(&definition.attributes) { name.to_string() } else { format!("{}_{}", prefix, name) };
+                            members.push((name.to_string(), c_name));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // What other files and `export`s added to the same namespace
+        if let Some(path) = &namespace.path {
+            members.extend(self.namespaces.members(path).map(|(name, m)| (name.clone(), m.c_name.clone())));
+        }
+        self.namespace_globals.push(members);
+        walk_namespace_mut(self, namespace);
+        self.namespace_globals.pop();
+    }
+
+    fn visit_global_mut(&mut self, global: &mut Global) {
+        // `geo::Point[3]`: the sizes stay as they are
+        let (element, dimensions) = global.type_.split_at(global.type_.find('[').unwrap_or(global.type_.len()));
+        global.type_ = element.split(' ')
+            .map(|word| self.lookup(word).unwrap_or(word))
+            .collect::<Vec<_>>()
+            .join(" ") + dimensions;
+        walk_global_mut(self, global);
+    }
+
+    fn visit_interface_mut(&mut self, interface: &mut Interface) {
+        for method in &mut interface.methods {
+            method.return_type = self.mangle_type(&method.return_type);
+            for param in &mut method.params {
+                param.type_ = self.mangle_type(&param.type_);
+            }
+        }
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        let written: Vec<Variable> = function.params.iter().cloned().chain([receiver(&function.class_name)]).collect();
+        let depth = self.symbols.enter(&written);
+        // Class names in the signature are mangled like a global's type
+        function.return_type = self.mangle_type(&function.return_type);
+        for param in &mut function.params {
+            param.type_ = self.mangle_type(&param.type_);
+        }
+        self.scope = function.params.clone();
+        self.scope.push(receiver(&function.class_name));
+        walk_function_mut(self, function);
+        self.scope.clear();
+        self.symbols.close(depth);
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        let receiver = (!operator.free).then(|| receiver(&operator.class_name));
+        let written: Vec<Variable> = operator.params.iter().cloned().chain(receiver.clone()).collect();
+        let depth = self.symbols.enter(&written);
+        operator.return_type = self.mangle_type(&operator.return_type);
+        for param in &mut operator.params {
+            param.type_ = self.mangle_type(&param.type_);
+        }
+        self.scope = operator.params.clone();
+        self.scope.extend(receiver);
+        walk_operator_mut(self, operator);
+        self.scope.clear();
+        self.symbols.close(depth);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        let Stmt::Loop(loop_) = stmt else {
+            walk_stmt_mut(self, stmt);
+            return;
+        };
+        let Some(name) = loop_.label.take() else {
+            walk_stmt_mut(self, stmt);
+            return;
+        };
+
+        // `continue label` jumps to the end of the body, `break label` past the loop
+        self.labels.push(LoopLabel { name, id: self.next_label, broken: false, continued: false });
+        self.next_label += 1;
+        self.visit_loop_mut(loop_);
+        let label = self.labels.pop().unwrap();
+
+        if label.continued {
+            let code = format!("{}: ;", continue_label(&label.name, label.id));
+            loop_.body.push(Stmt::Tokens(tokenize(&code).into_iter().filter(|t| !matches!(t, Token::Eof)).collect()));
+        }
+        if label.broken {
+            let mut tokens = flatten(std::slice::from_ref(stmt));
+            let code = format!(" {}: ;", break_label(&label.name, label.id));
+            tokens.extend(tokenize(&code).into_iter().filter(|t| !matches!(t, Token::Eof)));
+            *stmt = Stmt::Tokens(tokens);
+        }
+    }
+
+    fn visit_loop_mut(&mut self, loop_: &mut Loop) {
+        let variables: Vec<Variable> = self.scope.iter().chain(self.variables).cloned().collect();
+        self.lower_for_each(loop_, &variables);
+        if let LoopKind::While { condition } | LoopKind::DoWhile { condition } | LoopKind::For { condition, .. } = &mut loop_.kind {
+            self.lower_truth_tests(condition, &variables, true);
+        }
+        let depth = self.symbols.depth();
+        self.symbols.push();
+        walk_loop_mut(self, loop_);
+        self.symbols.close(depth);
+    }
+
+    fn visit_switch_mut(&mut self, switch: &mut Switch) {
+        let depth = self.symbols.depth();
+        self.symbols.push();
+        walk_switch_mut(self, switch);
+        self.symbols.close(depth);
+    }
+
+    fn visit_tokens_mut(&mut self, tokens: &mut Vec<Token>) {
+        self.lower_typeinfo(tokens);
+        self.lower_labeled_jumps(tokens);
+        let variables: Vec<Variable> = self.scope.iter().chain(self.variables).cloned().collect();
+        self.lower_dynamic(tokens, &variables);
+        self.lower_truth_tests(tokens, &variables, false);
+        self.unmangled_paths(tokens);
+        self.check_fields(tokens);
+        // A statement at a time, each with the variables in scope where it is
+        let mut lowered = Vec::with_capacity(tokens.len());
+        for statement in statements(tokens) {
+            self.symbols.declare_in(statement);
+            let in_scope = self.variables_in_scope();
+            let mut lowering = Lowering {
+                in_scope: &in_scope,
+                variables: &variables,
+                scalars: self.scalars,
+                class_names: self.class_names,
+                returns: self.method_returns,
+                functions: self.function_returns,
+                overloads: self.operator_overloads,
+                errors: Vec::new(),
+            };
+            lowered.extend(parse_function_calls_with_operators(statement, &mut lowering));
+            self.errors.extend(lowering.errors);
+        }
+        *tokens = lowered;
+        self.mangle_namespace_globals(tokens);
+    }
+}
+
+/// Evaluates the operands of operator calls left to right, see `temporaries`
+pub struct Temporaries;
+
+impl Pass for Temporaries {
+    fn name(&self) -> &str {
+        "temporaries"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let mut binary = HashMap::new();
+        for (plain, overloads) in &cx.session.operator_overloads {
+            let class = plain.rsplit_once("_operator_").map_or("", |(class, _)| class);
+            for overload in overloads {
+                binary.insert(overload.function.clone(), [class.to_string(), overload.right.clone()]);
+            }
+        }
+        let rc = cx.session.rc_classes.keys().cloned().collect();
+        let mut rewriter = TemporaryRewriter { materializer: Materializer::new(&binary, &rc) };
+        rewriter.visit_module_mut(&mut cx.module);
+    }
+}
+
+/// Runs the materializer over function bodies, leaving globals and the
+/// headers of loops and switches alone
+struct TemporaryRewriter<'a> {
+    materializer: Materializer<'a>,
+}
+
+impl VisitMut for TemporaryRewriter<'_> {
+    fn visit_raw_mut(&mut self, tokens: &mut Vec<Token>) {
+        self.materializer.start(0);
+        walk_block_mut(self, tokens);
+    }
+
+    fn visit_global_mut(&mut self, _global: &mut Global) {}
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        self.materializer.start(1);
+        walk_function_mut(self, function);
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        self.materializer.start(1);
+        walk_operator_mut(self, operator);
+    }
+
+    fn visit_loop_mut(&mut self, loop_: &mut Loop) {
+        for stmt in &mut loop_.body {
+            self.visit_stmt_mut(stmt);
+        }
+    }
+
+    fn visit_switch_mut(&mut self, switch: &mut Switch) {
+        for stmt in &mut switch.body {
+            self.visit_stmt_mut(stmt);
+        }
+    }
+
+    fn visit_tokens_mut(&mut self, tokens: &mut Vec<Token>) {
+        *tokens = self.materializer.rewrite(tokens);
+    }
+}
+
+/// Warns about private functions, fields and imports nothing uses, see `unused`
+pub struct Unused;
+
+impl Pass for Unused {
+    fn name(&self) -> &str {
+        "unused"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let references = unused::references(&cx.module);
+        cx.session.field_reads.extend(unused::field_reads(&cx.module));
+        let mut provided = unused::provided(&cx.module);
+        provided.extend(namespaces::exported(&cx.module, &cx.session.namespaces));
+        cx.session.provides.insert(cx.path.clone(), provided);
+
+        // The standard library is checked by its own tests
+        if stdlib::source(&cx.path).is_none() {
+            for (name, attributes) in unused::private_functions(&cx.module, &references) {
+                cx.lint(Warning::new("unused", format!("function `{}` is never used", name)).within(&attributes));
+            }
+            // Reported by the root file, at this file's level
+            for (class, field, attributes) in unused::fields(&cx.module) {
+                let level = cx.lints.within(&attributes).level("unused");
+                cx.session.declared_fields.push((cx.path.clone(), class, field, level));
+            }
+        }
+        let attributes = lints::import_attributes(&cx.tokens);
+        for path in unused::imports(&cx.module) {
+            let Some(names) = cx.session.provides.get(&path) else { continue };
+            if names.is_empty() || names.iter().any(|name| references.contains_key(name)) {
+                continue;
+            }
+            let warning = Warning::new("unused", format!("nothing from `{}` is used; remove the import or write `@allow(unused)` before it", path));
+            cx.lint(warning.within(attributes.get(&path).map(Vec::as_slice).unwrap_or_default()));
+        }
+
+        // A library's fields may be read by code this compile doesn't see
+        if cx.is_root() && cx.options().entry_point {
+            for (path, class, field, level) in std::mem::take(&mut cx.session.declared_fields) {
+                if cx.session.field_reads.contains(&field) {
+                    continue;
+                }
+                let message = format!("field `{}` of `{}` is never read", field, class);
+                cx.session.diagnostics.extend(lints::diagnostic(&path, "unused", level, message));
+            }
+        }
+    }
+}
+
+/// Adds the file's functions to the session's call graph and warns about
+/// inline functions that are recursive, see `callgraph`
+pub struct Calls;
+
+impl Pass for Calls {
+    fn name(&self) -> &str {
+        "call-graph"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        cx.session.call_graph.add(&cx.path, &cx.module);
+        let mut warnings = Vec::new();
+        let graph = &cx.session.call_graph;
+        let attributes = function_attributes(&cx.module.items);
+        for group in graph.recursion() {
+            for function in group.iter().filter(|f| graph.is_inline(f) && graph.file(f) == Some(&cx.path)) {
+                let others: Vec<String> = group.iter().filter(|g| *g != function).map(|g| format!("`{}`", g)).collect();
+                let through = if others.is_empty() { String::new() } else { format!(" through {}", others.join(", ")) };
+                let message = format!("inline function `{}` calls itself{}, so its calls can't all be expanded in place", function, through);
+                warnings.push(Warning::new("recursive_inline", message).within(attributes.get(function).map(Vec::as_slice).unwrap_or_default()));
+            }
+        }
+        for warning in warnings {
+            cx.lint(warning);
+        }
+    }
+}
+
+// The attributes of every free function of `items`, by name
+fn function_attributes(items: &[Item]) -> HashMap<String, Vec<Attribute>> {
+    let mut attributes = HashMap::new();
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => attributes.extend(function_attributes(&namespace.items)),
+            Item::Raw(tokens) => {
+                for definition in function_definitions(tokens) {
+                    attributes.insert(definition.name(tokens).to_string(), definition.attributes);
+                }
+            }
+            _ => {}
+        }
+    }
+    attributes
+}
+
+/// `@arena` functions release the rc temporaries they create, see `arena`
+pub struct Arenas;
+
+impl Pass for Arenas {
+    fn name(&self) -> &str {
+        "arena"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let class_names = cx.class_names();
+        let types = RcTypes { class_names: &class_names, classes: &cx.session.rc_classes };
+        let mut returns = RcReturns { types: &types, temporaries: HashMap::new() };
+        returns.temporaries.extend(cx.session.rc_classes.keys().map(|class| (format!("{}_new", class), class.clone())));
+        returns.visit_module(&cx.module);
+
+        let mut arenas = ArenaRewriter { temporaries: &returns.temporaries, used: false };
+        arenas.visit_module_mut(&mut cx.module);
+        if arenas.used {
+            let runtime = tokenize(arena::RUNTIME).into_iter().filter(|t| !matches!(t, Token::Eof)).collect();
+            cx.module.items.insert(0, Item::Raw(runtime));
+        }
+    }
+}
+
+// C names of the functions returning an rc value, with the value's class
+struct RcReturns<'a> {
+    types: &'a RcTypes<'a>,
+    temporaries: HashMap<String, String>,
+}
+
+impl Visit for RcReturns<'_> {
+    fn visit_class(&mut self, class: &Class) {
+        let name = class.full_name();
+        for function in &class.functions {
+            if let Some(rc) = self.types.class_of(&function.return_type) {
+                self.temporaries.insert(format!("{}_{}", name, function.name), rc);
+            }
+        }
+        for operator in &class.operators {
+            self.visit_operator(operator);
+        }
+    }
+
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        if let Some(rc) = self.types.class_of(&operator.return_type) {
+            self.temporaries.insert(operator.full_name(), rc);
+        }
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        for definition in function_definitions(tokens) {
+            if let Some(rc) = self.types.class_of(&type_text(&definition.return_type)) {
+                self.temporaries.insert(definition.name(tokens).to_string(), rc);
+            }
+        }
+    }
+}
+
+struct ArenaRewriter<'a> {
+    temporaries: &'a HashMap<String, String>,
+    used: bool,
+}
+
+impl VisitMut for ArenaRewriter<'_> {
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        if has_attribute(&function.attributes, "arena") {
+            self.used = true;
+            function.body_tokens = arena::rewrite_body(&function.body_tokens, function.return_type == "void", self.temporaries);
+        }
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        if has_attribute(&operator.attributes, "arena") {
+            self.used = true;
+            operator.body_tokens = arena::rewrite_body(&operator.body_tokens, operator.return_type == "void", self.temporaries);
+        }
+    }
+
+    fn visit_raw_mut(&mut self, tokens: &mut Vec<Token>) {
+        for definition in function_definitions(tokens).into_iter().rev() {
+            if !has_attribute(&definition.attributes, "arena") {
+                continue;
+            }
+            let Some(open) = (definition.name_index..tokens.len()).find(|&k| matches!(&tokens[k], Token::Symbol(s) if s == "{")) else { continue };
+            let Some(close) = matching_close(tokens, open) else { continue };
+            self.used = true;
+            let returns_void = type_text(&definition.return_type) == "void";
+            let body = arena::rewrite_body(&tokens[open + 1..close], returns_void, self.temporaries);
+            tokens.splice(open + 1..close, body);
+        }
+    }
+}
+
+/// `@test` functions: left out of other builds and recorded for the runner
+/// of a test build, see `testing`
+pub struct Tests;
+
+impl Pass for Tests {
+    fn name(&self) -> &str {
+        "tests"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let test = cx.options().test;
+        let mut names = Vec::new();
+        let mut errors = Vec::new();
+        for item in &mut cx.module.items {
+            match item {
+                Item::Raw(tokens) => {
+                    let mut found = Vec::new();
+                    for definition in function_definitions(tokens).iter().rev() {
+                        if !has_attribute(&definition.attributes, "test") {
+                            continue;
+                        }
+                        let name = definition.name(tokens).to_string();
+                        if !test {
+                            if let Some(end) = definition.body_end(tokens) {
+                                tokens.drain(definition.attributes_start..=end);
+                            }
+                        } else if detokenize(&definition.return_type).trim() != "void" || main_params(&definition.params) != Some(false) {
+                            errors.push(format!("`@test` function `{}` must take no parameters and return `void`", name));
+                        } else {
+                            found.push(name);
+                        }
+                    }
+                    names.extend(found.into_iter().rev());
+                }
+                Item::Namespace(namespace) => nested_tests(&namespace.items, &mut errors),
+                _ => {}
+            }
+        }
+        cx.session.tests.extend(names);
+        for error in errors {
+            cx.error(error);
+        }
+    }
+}
+
+// Reports the `@test` functions of `items`, which are in a namespace; the
+// runner calls tests by their name as written
+fn nested_tests(items: &[Item], errors: &mut Vec<String>) {
+    for item in items {
+        match item {
+            Item::Raw(tokens) => {
+                for definition in function_definitions(tokens) {
+                    if has_attribute(&definition.attributes, "test") {
+                        errors.push(format!("`@test` function `{}` must be at file scope, not in a namespace", definition.name(tokens)));
+                    }
+                }
+            }
+            Item::Namespace(namespace) => nested_tests(&namespace.items, errors),
+            _ => {}
+        }
+    }
+}
+
+/// Checks the root file's `main` and wraps it in the C entry point. The
+/// user's `main` becomes `__z_main`; the generated `main` stores argc/argv
+/// for the standard library before calling it.
+pub struct EntryPoint;
+
+impl Pass for EntryPoint {
+    fn name(&self) -> &str {
+        "entry-point"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        if !cx.is_root() || !cx.options().entry_point {
+            return;
+        }
+
+        // main has to live at file scope, so only top-level raw code is searched
+        let mut found: Vec<(usize, FunctionDefinition)> = Vec::new();
+        for (index, item) in cx.module.items.iter().enumerate() {
+            if let Item::Raw(tokens) = item {
+                for definition in function_definitions(tokens) {
+                    if definition.name(tokens) == "main" {
+                        found.push((index, definition));
+                    }
+                }
+            }
+        }
+
+        // A test build runs the tests instead; the program's `main` stays
+        // out of the runner's way
+        if cx.options().test {
+            for (index, definition) in &found {
+                if let Item::Raw(tokens) = &mut cx.module.items[*index] {
+                    tokens[definition.name_index] = Token::Identifier("__z_main".to_string());
+                }
+            }
+            let runner = testing::runner(&cx.session.tests);
+            cx.module.push_c(&runner);
+            return;
+        }
+
+        let (index, definition) = match found.len() {
+            0 => {
+                cx.error("program has no `main` function");
+                return;
+            }
+            1 => found.remove(0),
+            _ => {
+                cx.error("`main` is defined more than once");
+                return;
+            }
+        };
+
+        let return_type = detokenize(&definition.return_type);
+        let returns_void = match return_type.as_str() {
+            "int" => false,
+            "void" => true,
+            _ => {
+                cx.error(format!("`main` must return `int` or `void`, found `{}`", return_type));
+                return;
+            }
+        };
+
+        let forward_args = match main_params(&definition.params) {
+            Some(forward_args) => forward_args,
+            None => {
+                cx.error(format!(
+                    "`main` must take no parameters or `(int argc, char** argv)`, found `({})`",
+                    detokenize(&definition.params)
+                ));
+                return;
+            }
+        };
+
+        if let Item::Raw(tokens) = &mut cx.module.items[index] {
+            tokens[definition.name_index] = Token::Identifier("__z_main".to_string());
+            // C returns 0 from a `main` that reaches the end of its body,
+            // but not from any other function
+            if let (false, Some(open), Some(close)) = (returns_void, definition.body_start(tokens), definition.body_end(tokens)) {
+                if flow::reaches_end("main", &tokens[open + 1..close]) {
+                    tokens.splice(close..close, tokenize(" return 0; ").into_iter().filter(|t| !matches!(t, Token::Eof)));
+                }
+            }
+        }
+
+        let args = if forward_args { "argc, argv" } else { "" };
+        let call = if returns_void {
+            format!("__z_main({}); return 0;", args)
+        } else {
+            format!("return __z_main({});", args)
+        };
+        cx.module.push_c(&format!(
+            "\nint __z_argc; char** __z_argv;\nint main(int argc, char** argv) {{ __z_argc = argc; __z_argv = argv; {} }}\n",
+            call
+        ));
+
+        // The wrapper refers to __z_main, which has to be declared first
+        if let Item::Raw(tokens) = &mut cx.module.items[index] {
+            let prototype = format!("{} __z_main({});\n", return_type, detokenize(&definition.params));
+            let declaration: Vec<Token> = tokenize(&prototype).into_iter().filter(|t| !matches!(t, Token::Eof)).collect();
+            tokens.splice(definition.start..definition.start, declaration);
+        }
+    }
+}
+
+// Some(true) for `(int argc, char** argv)`, Some(false) for `()` or `(void)`
+fn main_params(params: &[Token]) -> Option<bool> {
+    let params: Vec<&Token> = params.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    if params.is_empty() || params == [&Token::Identifier("void".to_string())] {
+        return Some(false);
+    }
+
+    let mut parts = params.split(|t| **t == Token::Symbol(",".to_string()));
+    let argc = parts.next()?;
+    let argv = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let argc_ok = argc.first() == Some(&&Token::Identifier("int".to_string())) && argc.len() <= 2;
+    let indirections = argv.iter().filter(|t| ***t == Token::Symbol("*".to_string()) || ***t == Token::Symbol("[".to_string())).count();
+    let argv_ok = argv.first() == Some(&&Token::Identifier("char".to_string())) && indirections == 2;
+    if argc_ok && argv_ok { Some(true) } else { None }
+}
+
+/// Item tree -> C source
+pub struct Emit;
+
+impl Pass for Emit {
+    fn name(&self) -> &str {
+        "emit"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        let export_linkage = export_linkage(&cx.options().cfg.os);
+        let mut emitter = Emitter {
+            tokens: Vec::new(),
+            private_depth: 0,
+            emitted: &mut cx.session.emitted,
+            interfaces: &mut cx.session.interfaces,
+            dynamic: &cx.dynamic,
+            conflicts: Vec::new(),
+            export_linkage,
+        };
+        // `any` may be named without converting anything to it
+        if !cx.dynamic.is_empty() || cx.tokens.iter().any(|t| matches!(t, Token::Identifier(word) if word == "any")) {
+            emitter.emit_dynamic_runtime();
+        }
+        emitter.visit_module(&cx.module);
+        let (tokens, conflicts) = (emitter.tokens, emitter.conflicts);
+        // A namespace member another file declared was reported by name
+        let declared_elsewhere = |name: &String| cx.session.namespaces.by_c_name(name).is_some_and(|m| m.file != cx.path);
+        let conflicts: Vec<String> = conflicts.into_iter().filter(|name| !declared_elsewhere(name)).collect();
+        for name in conflicts {
+            cx.error(format!("`{}` is defined differently by another file", name));
+        }
+        for (c_name, name) in crash::names(&cx.module) {
+            cx.session.symbols.entry(c_name).or_insert(crash::Symbol { name, file: cx.path.clone() });
+        }
+        let mut tokens = c_comments(&tokens, cx.options().keep_comments);
+        edition::unescape(&mut tokens);
+        cx.output = if cx.options().preserve_whitespace {
+            // Imported code was already printed from its own file
+            let mut sources = vec![cx.source.as_str()];
+            expansions(&cx.module.items, &mut sources);
+            detokenize_preserving(&tokens, &sources)
+        } else {
+            detokenize(&tokens)
+        };
+        // At the end of the program, where every function is defined
+        if cx.options().crash_report && cx.options().entry_point && cx.is_root() {
+            cx.output.push_str(&crash::runtime(&tokens, &cx.session.symbols));
+        }
+    }
+}
+
+// The code each import of `items` expanded to
+fn expansions<'m>(items: &'m [Item], out: &mut Vec<&'m str>) {
+    for item in items {
+        match item {
+            Item::Import(import) => out.extend(import.expansion.as_deref()),
+            Item::Namespace(namespace) => expansions(&namespace.items, out),
+            _ => {}
+        }
+    }
+}
+
+// Namespaces are flattened: their classes already carry mangled names
+struct Emitter<'a> {
+    tokens: Vec<Token>,
+    // Inside an anonymous namespace, where functions are made static
+    private_depth: usize,
+    emitted: &'a mut HashMap<String, String>,
+    // Updated with the lowered interfaces, which the vtables are built from
+    interfaces: &'a mut HashMap<String, Interface>,
+    dynamic: &'a dynamic::Uses,
+    // Names already emitted with a different definition
+    conflicts: Vec<String>,
+    // Written before `@export` functions
+    export_linkage: &'static str,
+}
+
+impl Emitter<'_> {
+    fn push_code(&mut self, code: &str) {
+        self.tokens.extend(tokenize(code).into_iter().filter(|t| !matches!(t, Token::Eof)));
+    }
+
+    // Records the definition of `name`; false if it was already emitted
+    fn register(&mut self, name: String, code: &str) -> bool {
+        match self.emitted.get(&name) {
+            Some(previous) if previous == code => false,
+            Some(_) => {
+                // Emitted anyway, so gcc points at both definitions
+                self.conflicts.push(name);
+                true
+            }
+            None => {
+                self.emitted.insert(name, code.to_string());
+                true
+            }
+        }
+    }
+}
+
+impl Emitter<'_> {
+    fn emit_dynamic_runtime(&mut self) {
+        let code = dynamic::runtime();
+        if self.register("any".to_string(), &code) {
+            self.push_code(&code);
+        }
+    }
+
+    // The type IDs and vtables whose class and interface are both emitted
+    // by now, so they come right after the later of the two
+    fn emit_dynamic(&mut self) {
+        let classes = self.dynamic.type_ids.iter().chain(self.dynamic.vtables.iter().map(|(class, _)| class));
+        for class in classes {
+            if self.emitted.contains_key(class) && !self.emitted.contains_key(&dynamic::type_id_name(class)) {
+                let code = dynamic::type_id(class);
+                self.register(dynamic::type_id_name(class), &code);
+                self.push_code(&code);
+            }
+        }
+        for (class, interface) in &self.dynamic.vtables {
+            let name = dynamic::vtable_name(class, interface);
+            if !self.emitted.contains_key(class) || !self.emitted.contains_key(interface) || self.emitted.contains_key(&name) {
+                continue;
+            }
+            let Some(definition) = self.interfaces.get(interface).cloned().or((interface == "any").then(dynamic::any)) else { continue };
+            let code = dynamic::vtable(class, &definition);
+            self.register(name, &code);
+            self.push_code(&code);
+        }
+    }
+}
+
+impl Visit for Emitter<'_> {
+    fn visit_class(&mut self, class: &Class) {
+        let code = class.to_string();
+        if self.register(class.full_name(), &code) {
+            self.push_code(&code);
+        }
+        self.emit_dynamic();
+    }
+
+    fn visit_interface(&mut self, interface: &Interface) {
+        self.emit_dynamic_runtime();
+        let code = interface.to_string();
+        if self.register(interface.full_name(), &code) {
+            self.push_code(&code);
+        }
+        self.interfaces.insert(interface.full_name(), interface.clone());
+        self.emit_dynamic();
+    }
+
+    fn visit_global(&mut self, global: &Global) {
+        let code = global.to_string();
+        if self.register(global.full_name(), &code) {
+            self.push_code(&code);
+        }
+    }
+
+    fn visit_import(&mut self, import: &Import) {
+        if let Some(code) = &import.expansion {
+            self.push_code(code);
+        }
+        self.emit_dynamic();
+    }
+
+    // Free operators; the ones in classes are emitted with their class
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        let linkage = if has_attribute(&operator.attributes, "inline") {
+            INLINE_LINKAGE
+        } else if self.private_depth > 0 {
+            "static "
+        } else {
+            ""
+        };
+        let code = format!("{}{}", linkage, operator);
+        if self.register(operator.full_name(), &code) {
+            self.push_code(&code);
+        }
+    }
+
+    fn visit_namespace(&mut self, namespace: &Namespace) {
+        let private = namespace.is_anonymous() as usize;
+        self.private_depth += private;
+        walk_namespace(self, namespace);
+        self.private_depth -= private;
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        let mut tokens = strip_attributes(&make_functions_exported(&make_functions_inline(tokens), self.export_linkage));
+        if self.private_depth > 0 {
+            tokens = make_functions_static(&tokens);
+        }
+        // Function definitions go through the registry like classes do
+        for definition in function_definitions(&tokens).iter().rev() {
+            let Some(end) = definition.body_end(&tokens) else { continue };
+            let code = detokenize(&tokens[definition.start..=end]);
+            if !self.register(definition.name(&tokens).to_string(), &code) {
+                tokens.drain(definition.start..=end);
+            }
+        }
+        self.tokens.extend(tokens);
+    }
+}
+
+/// Gives every `@inline` function definition in `tokens` `static inline`
+/// linkage, replacing a `static` already written
+fn make_functions_inline(tokens: &[Token]) -> Vec<Token> {
+    let mut out = tokens.to_vec();
+    for definition in function_definitions(tokens).iter().rev() {
+        if !has_attribute(&definition.attributes, "inline") {
+            continue;
+        }
+        if definition.return_type.first() == Some(&Token::Identifier("static".to_string())) {
+            out.remove(definition.start);
+        }
+        out.splice(definition.start..definition.start, tokenize(INLINE_LINKAGE).into_iter().filter(|t| !matches!(t, Token::Eof)));
+    }
+    out
+}
+
+/// Prefixes `linkage` to every `@export` function definition in `tokens`
+fn make_functions_exported(tokens: &[Token], linkage: &str) -> Vec<Token> {
+    let mut out = tokens.to_vec();
+    for definition in function_definitions(tokens).iter().rev() {
+        if has_attribute(&definition.attributes, "export") {
+            out.splice(definition.start..definition.start, tokenize(linkage).into_iter().filter(|t| !matches!(t, Token::Eof)));
+        }
+    }
+    out
+}
+
+/// Prefixes `static` to every top-level function definition in `tokens`
+fn make_functions_static(tokens: &[Token]) -> Vec<Token> {
+    let mut out = tokens.to_vec();
+    for definition in function_definitions(tokens).iter().rev() {
+        if definition.return_type.first() != Some(&Token::Identifier("static".to_string())) {
+            out.insert(definition.start, Token::Identifier("static".to_string()));
+        }
+    }
+    out
+}
+
+/// End of the operand starting at `start`: prefix operators, then a name,
+/// literal, parenthesized expression, cast or compound literal, then any member
+/// accesses, calls and subscripts. Stops before the next binary operator,
+/// `?`, `:`, `,`, `;` or closing bracket.
+fn operand_end(tokens: &[Token], start: usize) -> usize {
+    let symbol_at = |i: usize| match tokens.get(i) {
+        Some(Token::Symbol(s)) => Some(s.as_str()),
+        _ => None,
+    };
+    let closing = |open: usize| -> usize {
+        let mut depth = 0;
+        for (i, token) in tokens.iter().enumerate().skip(open) {
+            match token {
+                Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+                Token::Symbol(s) if s == ")" || s == "]" || s == "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        tokens.len()
+    };
+
+    let mut i = start;
+    while matches!(symbol_at(i), Some("-" | "+" | "!" | "~" | "*" | "&" | "++" | "--")) {
+        i += 1;
+    }
+    match tokens.get(i) {
+        Some(Token::Symbol(s)) if s == "(" => {
+            let close = closing(i);
+            // `(float)n`: a cast takes the operand after it
+            let type_name = tokens[i + 1..close.saturating_sub(1).max(i + 1)].iter()
+                .all(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "*"));
+            let operand = matches!(tokens.get(close), Some(Token::Identifier(_) | Token::Number(_) | Token::CharLit(_))) || symbol_at(close) == Some("(");
+            if close > i + 2 && type_name && operand {
+                return operand_end(tokens, close);
+            }
+            i = close;
+            // `(V){...}`
+            if symbol_at(i) == Some("{") {
+                i = closing(i);
+            }
+        }
+        Some(Token::Identifier(_) | Token::Number(_) | Token::StringLit(_) | Token::CharLit(_)) => i += 1,
+        _ => return (i + 1).min(tokens.len()),
+    }
+    loop {
+        match symbol_at(i) {
+            Some("." | "->" | "::") if matches!(tokens.get(i + 1), Some(Token::Identifier(_))) => i += 2,
+            Some("(" | "[") => i = closing(i),
+            Some("++" | "--") => i += 1,
+            _ => return i,
+        }
+    }
+}
+
+// End of the right operand of `symbol` starting at `start`, including every
+// operator that binds tighter, e.g. `b * c` in `a + b * c`
+fn right_operand_end(tokens: &[Token], start: usize, symbol: &str) -> usize {
+    let mut end = operand_end(tokens, start);
+    while let Some(Token::Symbol(next)) = tokens.get(end) {
+        // Assignments bind loosest: `a += b + c`
+        match (operators::precedence(next), operators::precedence(symbol).unwrap_or(0)) {
+            (Some(next), current) if next > current => end = operand_end(tokens, end + 1),
+            _ => break,
+        }
+    }
+    end
+}
+
+// C keywords that can be directly followed by an expression
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "return" | "case" | "else" | "do" | "sizeof" | "goto")
+}
+
+fn find_class_variable<'v>(variables: &'v [Variable], class_names: &HashMap<String, String>, name: &str) -> Option<&'v Variable> {
+    variables.iter().find(|v| v.name == name && class_names.contains_key(&v.type_))
+}
+
+// Mangled class of a variable declared as a pointer to a class, e.g. `V* p`
+fn find_class_pointer<'c>(variables: &[Variable], class_names: &'c HashMap<String, String>, name: &str) -> Option<&'c String> {
+    variables.iter()
+        .filter(|v| v.name == name)
+        .find_map(|v| class_names.get(v.type_.strip_suffix('*')?))
+}
+
+/// What lowering method calls and operators needs to know of the
+/// classes, and the operators it could choose no overload for
+struct Lowering<'a> {
+    /// Variables in scope, see `scopes`
+    in_scope: &'a [Variable],
+    /// Parameters and the file's class variables, for names not in scope
+    variables: &'a [Variable],
+    /// Variables of scalar types, see `Context::scalars`
+    scalars: &'a [Variable],
+    class_names: &'a HashMap<String, String>,
+    /// Mangled class each method and operator returns, by C name
+    returns: &'a HashMap<String, String>,
+    /// Mangled type each free function returns, by name
+    functions: &'a HashMap<String, String>,
+    /// Overloads of each binary operator, see `overloads`
+    overloads: &'a HashMap<String, Vec<Overload>>,
+    errors: Vec<String>,
+}
+
+impl<'a> Lowering<'a> {
+    // Where to look `name` up: the variable in scope by that name, or
+    // failing that any of the file's
+    fn variables(&self, name: &str) -> &'a [Variable] {
+        if self.in_scope.iter().any(|v| v.name == name) { self.in_scope } else { self.variables }
+    }
+}
+
+// `.method(params)` at `dot` -> `Class_method(receiver, params)`, with the
+// params lowered too. Calls chained on a method returning a class are
+// lowered with the call as their receiver. Returns the call and the index
+// after the last `)`.
+fn lower_method_call(tokens: &[Token], dot: usize, receiver: Vec<Token>, class: &str, lowering: &mut Lowering<'_>) -> Option<(Vec<Token>, usize)> {
+    let Some(Token::Identifier(method_name)) = tokens.get(dot + 1) else { return None };
+    if !matches!(tokens.get(dot + 2), Some(Token::Symbol(s)) if s == "(") {
+        return None;
+    }
+    let close = matching_close(tokens, dot + 2)?;
+    if DEBUG {println!("DEBUG: Found method call: {}.{}(", detokenize(&receiver), method_name);}
+
+    let function = format!("{}_{}", class, method_name);
+    let mut call = vec![Token::Identifier(function.clone()), Token::Symbol("(".to_string())];
+    call.extend(receiver);
+    if close > dot + 3 {
+        call.push(Token::Symbol(",".to_string()));
+        call.extend(parse_function_calls_with_operators(&tokens[dot + 3..close], lowering));
+    }
+    call.push(Token::Symbol(")".to_string()));
+
+    if let (Some(Token::Symbol(s)), Some(returned)) = (tokens.get(close + 1), lowering.returns.get(&function)) {
+        if s == "." {
+            if let Some(chained) = lower_method_call(tokens, close + 1, call.clone(), returned, lowering) {
+                return Some(chained);
+            }
+        }
+    }
+    Some((call, close + 1))
+}
+
+// `left op right op ...` where `left`, already lowered, is of class `class`
+// and `tokens[at]` is an overloaded binary operator: the nested operator
+// calls and the index after the last operand. Each call is a C function
+// call, so the result keeps the precedence the Z expression had wherever it
+// ends up. None when `tokens[at]` isn't a binary operator
+fn lower_operator_chain(tokens: &[Token], at: usize, left: Vec<Token>, class: &str, lowering: &mut Lowering<'_>) -> Option<(Vec<Token>, usize)> {
+    let Some(Token::Symbol(operator)) = tokens.get(at) else { return None };
+    let mut op = operators::binary(operator)?;
+    if at + 1 >= tokens.len() {
+        return None;
+    }
+    if DEBUG {println!("DEBUG: Found binary operator: {} {} ...", detokenize(&left), operator);}
+
+    // Transform: obj + other -> Class_operator_add(obj, other)
+    let mut call = left;
+    let mut end = at;
+    loop {
+        // The right operand may itself need lowering, e.g. `a < b.next()` or `a + b * c`
+        let right_end = right_operand_end(tokens, end + 1, op.symbol);
+        let right = parse_function_calls_with_operators(&tokens[end + 1..right_end], lowering);
+        let mut lowered = vec![
+            Token::Identifier(operator_function(class, op, &right, lowering)),
+            Token::Symbol("(".to_string()),
+        ];
+        lowered.extend(call);
+        lowered.push(Token::Symbol(",".to_string()));
+        lowered.extend(right);
+        lowered.push(Token::Symbol(")".to_string()));
+        call = lowered;
+        end = right_end;
+
+        // `a + b - c` is `(a + b) - c`, assuming `a + b` is of the same class
+        let next = match tokens.get(end) {
+            Some(Token::Symbol(next)) if !operators::is_comparison(op.symbol) => operators::binary(next),
+            _ => None,
+        };
+        match next {
+            Some(next) if operators::precedence(next.symbol).is_some() => op = next,
+            _ => break,
+        }
+    }
+    Some((call, end))
+}
+
+// The C function of `op` on a value of `class` with the lowered operand
+// `right`: the one overload for the type of `right` when the class has
+// several, see `overloads`. When none fits, or the class declares none, the
+// error is recorded and the plain name kept
+fn operator_function(class: &str, op: &operators::Operator, right: &[Token], lowering: &mut Lowering<'_>) -> String {
+    let function = format!("{}_operator_{}", class, op.name);
+    // A class of a compiled library's interface declares its operators in C
+    if !lowering.overloads.contains_key(&function) && lowering.functions.contains_key(&function) {
+        return function;
+    }
+    let candidates = lowering.overloads.get(&function).map_or(&[][..], Vec::as_slice);
+    let argument = type_of(right, lowering);
+    match overloads::resolve(candidates, argument.as_deref()) {
+        Ok(overload) => overload.function.clone(),
+        Err(unresolved) => {
+            lowering.errors.push(unresolved.message(op.symbol, class, detokenize(right).trim()));
+            function
+        }
+    }
+}
+
+// Mangled type of a lowered operand where it can be told: a class value, a
+// literal, a variable, a cast, e.g. `float` of `(float)n`, a call to a
+// function, or arithmetic on those, e.g. `double` of `(n + 0.5)`
+fn type_of(operand: &[Token], lowering: &Lowering<'_>) -> Option<String> {
+    let operand = without_parentheses(operand);
+    if let Some(class) = class_of(operand, lowering) {
+        return Some(class);
+    }
+    if let Some(arithmetic) = arithmetic_type(operand, lowering) {
+        return Some(arithmetic);
+    }
+    let variable = |name: &str| lowering.variables(name).iter().chain(lowering.scalars).find(|v| v.name == name);
+    match operand {
+        [Token::Number(number)] => Some(overloads::literal_type(number).to_string()),
+        [Token::Symbol(sign), Token::Number(number)] if sign == "-" || sign == "+" => Some(overloads::literal_type(number).to_string()),
+        [Token::Symbol(sign), rest @ ..] if sign == "-" || sign == "+" => type_of(rest, lowering).filter(|t| overloads::arithmetic(t)),
+        [Token::CharLit(_)] => Some("char".to_string()),
+        [Token::Identifier(name)] => variable(name).map(|v| overloads::c_type(&v.type_, lowering.class_names)),
+        [Token::Identifier(function), Token::Symbol(open), ..] if open == "(" && matching_close(operand, 1) == Some(operand.len() - 1) => {
+            lowering.functions.get(function).cloned()
+        }
+        [Token::Symbol(open), ..] if open == "(" => {
+            let close = matching_close(operand, 0)?;
+            let inner = &operand[1..close];
+            // `(float)n`: only type names and `*` in the parentheses
+            let is_type = inner.iter().all(|t| matches!(t, Token::Identifier(word) if variable(word).is_none()) || matches!(t, Token::Symbol(s) if s == "*"));
+            (is_type && !inner.is_empty()).then(|| overloads::c_type(detokenize(inner).trim(), lowering.class_names))
+        }
+        _ => None,
+    }
+}
+
+// Type of `a + b * 2` and the like: the type C converts its operands to when
+// each is of a known arithmetic type
+fn arithmetic_type(operand: &[Token], lowering: &Lowering<'_>) -> Option<String> {
+    let mut operands = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    for (i, token) in operand.iter().enumerate() {
+        let Token::Symbol(s) = token else { continue };
+        match s.as_str() {
+            "(" | "[" => depth += 1,
+            ")" | "]" => depth -= 1,
+            // Not a sign, which follows another operator
+            "+" | "-" | "*" | "/" | "%" if depth == 0 && i > start => {
+                operands.push(&operand[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if operands.is_empty() {
+        return None;
+    }
+    operands.push(&operand[start..]);
+    let types: Option<Vec<String>> = operands.iter().map(|o| type_of(o, lowering).filter(|t| overloads::arithmetic(t))).collect();
+    types?.into_iter().reduce(|a, b| overloads::common_type(&a, &b))
+}
+
+// Mangled class of a lowered operand: a class variable, a call to a method
+// or operator returning a class, or one of those in parentheses
+fn class_of(operand: &[Token], lowering: &Lowering<'_>) -> Option<String> {
+    let operand = without_parentheses(operand);
+    match operand {
+        [Token::Identifier(name)] => find_class_variable(lowering.variables(name), lowering.class_names, name).and_then(|v| lowering.class_names.get(&v.type_).cloned()),
+        [Token::Identifier(function), Token::Symbol(open), ..] if open == "(" && matching_close(operand, 1) == Some(operand.len() - 1) => lowering.returns.get(function).cloned(),
+        // `c ? a : b` is of the class of its branches
+        _ => {
+            let (question, colon) = conditional(operand)?;
+            class_of(&operand[question + 1..colon], lowering)
+        }
+    }
+}
+
+// `operand` without the parentheses around all of it, however many layers,
+// in one scan: `((a))` -> `a`, while `(a) + (b)` is left alone
+fn without_parentheses(operand: &[Token]) -> &[Token] {
+    let leading = operand.iter().take_while(|t| matches!(t, Token::Symbol(s) if s == "(")).count();
+    if leading == 0 {
+        return operand;
+    }
+    // Where each leading `(` is closed
+    let mut closes = vec![None; leading];
+    let mut open = Vec::new();
+    for (i, token) in operand.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => open.push(i),
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => {
+                if let Some(at) = open.pop().filter(|&at| at < leading) {
+                    closes[at] = Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    let layers = (0..leading).take_while(|&k| closes[k] == Some(operand.len() - 1 - k)).count();
+    &operand[layers..operand.len() - layers]
+}
+
+// The `?` and `:` of a conditional expression at the top level of `tokens`
+fn conditional(tokens: &[Token]) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut question = None;
+    let mut nested = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Symbol(s) = token else { continue };
+        match s.as_str() {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            "?" if depth == 0 && question.is_none() => question = Some(i),
+            "?" if depth == 0 => nested += 1,
+            ":" if depth == 0 && question.is_some() => {
+                if nested == 0 {
+                    return Some((question?, i));
+                }
+                nested -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// `operand` followed by whatever it takes part in: a method call on it if it
+// is a parenthesized class value, then an overloaded operator if its class
+// has one. The lowered tokens and the index after them
+fn lower_operand(tokens: &[Token], next: usize, operand: Vec<Token>, lowering: &mut Lowering<'_>) -> (Vec<Token>, usize) {
+    let Some(class) = class_of(&operand, lowering) else { return (operand, next) };
+    // `(a + b).length()`
+    if matches!(operand.first(), Some(Token::Symbol(s)) if s == "(") && matches!(tokens.get(next), Some(Token::Symbol(s)) if s == ".") {
+        if let Some((call, after)) = lower_method_call(tokens, next, operand.clone(), &class, lowering) {
+            return lower_operand(tokens, after, call, lowering);
+        }
+    }
+    match lower_operator_chain(tokens, next, operand.clone(), &class, lowering) {
+        Some(chain) => chain,
+        None => (operand, next),
+    }
+}
+
+// `tokens` split after each `;` and each brace of a block, outside of
+// parentheses and initializers, so each statement can be lowered with the
+// names in scope where it is
+fn statements(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    // Open parentheses, brackets and braces that aren't blocks
+    let mut depth = 0usize;
+    // Whether each open brace is a block
+    let mut braces = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Symbol(s) = token else { continue };
+        let ends = match s.as_str() {
+            "(" | "[" => {
+                depth += 1;
+                false
+            }
+            ")" | "]" => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            "{" => {
+                let block = depth == 0 && opens_block(&tokens[..i]);
+                braces.push(block);
+                depth += usize::from(!block);
+                block
+            }
+            "}" => {
+                let block = braces.pop().unwrap_or(depth == 0);
+                depth = depth.saturating_sub(usize::from(!block));
+                block
+            }
+            ";" => depth == 0,
+            _ => false,
+        };
+        if ends {
+            runs.push(&tokens[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < tokens.len() {
+        runs.push(&tokens[start..]);
+    }
+    runs
+}
+
+// Whether a `{` after `before` opens a block rather than an initializer or
+// compound literal: it follows a statement, a label, `else`, `do` or the `)`
+// of `if (...)` or of a function's parameters
+fn opens_block(before: &[Token]) -> bool {
+    let mut code = before.iter().rev().filter(|t| !matches!(t, Token::Newline | Token::Comment(_)));
+    match code.next() {
+        None => true,
+        Some(Token::Symbol(s)) if matches!(s.as_str(), ";" | "{" | "}" | ":") => true,
+        Some(Token::Identifier(word)) => word == "else" || word == "do",
+        Some(Token::Symbol(s)) if s == ")" => {
+            let mut depth = 1;
+            while depth > 0 {
+                match code.next() {
+                    Some(Token::Symbol(s)) if s == ")" => depth += 1,
+                    Some(Token::Symbol(s)) if s == "(" => depth -= 1,
+                    Some(_) => {}
+                    None => return false,
+                }
+            }
+            matches!(code.next(), Some(Token::Identifier(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Rewrites method calls and overloaded operators on class-typed variables
+/// into calls to the generated C functions, and `a::b` into `a_b`.
+fn parse_function_calls_with_operators(tokens: &[Token], lowering: &mut Lowering<'_>) -> Vec<Token> {
+    if DEBUG {println!("DEBUG: Starting parse_function_calls_with_operators with {} tokens and {} classes", tokens.len(), lowering.class_names.len());}
+    
+    let mut out_tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if DEBUG && i % 200 == 0 {
+            println!("DEBUG: parse_function_calls_with_operators - processing token {} of {}", i, tokens.len());
+        }
+
+        // Handle operator overloading
+        if let Token::Identifier(left_operand) = &tokens[i] {
+            if let Some(var) = find_class_variable(lowering.variables(left_operand), lowering.class_names, left_operand) {
+                let class_with_namespace = lowering.class_names.get(&var.type_).unwrap_or(&var.type_);
+                // Check for binary operators: obj + other, obj == other, etc.
+                let left = vec![Token::Identifier(left_operand.clone())];
+                if let Some((call, end)) = lower_operator_chain(tokens, i + 1, left, class_with_namespace, lowering) {
+                    out_tokens.extend(call);
+                    i = end; // Skip past the binary operation
+                    continue;
+                }
+
+                // `obj[i]` -> Class_operator_index(obj, i)
+                if let Some(close) = matches!(tokens.get(i + 1), Some(Token::Symbol(s)) if s == "[").then(|| matching_close(tokens, i + 1)).flatten() {
+                    let index = parse_function_calls_with_operators(&tokens[i + 2..close], lowering);
+                    let mut call = vec![
+                        Token::Identifier(operator_function(class_with_namespace, operators::index(), &index, lowering)),
+                        Token::Symbol("(".to_string()),
+                        Token::Identifier(left_operand.clone()),
+                        Token::Symbol(",".to_string()),
+                    ];
+                    call.extend(index);
+                    call.push(Token::Symbol(")".to_string()));
+                    let (call, next) = lower_operand(tokens, close + 1, call, lowering);
+                    out_tokens.extend(call);
+                    i = next;
+                    continue;
+                }
+
+                // Check for unary operators: obj++, obj--
+                if let Some(op) = tokens.get(i + 1).and_then(|t| match t {
+                    Token::Symbol(operator) if i + 2 < tokens.len() => operators::postfix(operator),
+                    _ => None,
+                }) {
+                    if DEBUG {println!("DEBUG: Found postfix unary operator: {}{}", left_operand, op.symbol);}
+
+                    // Transform: obj++ -> Class_operator_increment(obj)
+                    let call = vec![
+                        Token::Identifier(format!("{}_operator_{}", class_with_namespace, op.name)),
+                        Token::Symbol("(".to_string()),
+                        Token::Identifier(left_operand.clone()),
+                        Token::Symbol(")".to_string()),
+                    ];
+                    let (call, next) = lower_operand(tokens, i + 2, call, lowering);
+                    out_tokens.extend(call);
+                    i = next; // Skip past the unary operation
+                    continue;
+                }
+                
+                // Handle method calls: obj.method(params) -> Class_method(obj, params)
+                if matches!(tokens.get(i + 1), Some(Token::Symbol(dot)) if dot == ".") {
+                    let receiver = vec![Token::Identifier(left_operand.clone())];
+                    if let Some((call, next)) = lower_method_call(tokens, i + 1, receiver, class_with_namespace, lowering) {
+                        // `a.scaled(2) + b`
+                        let (call, next) = lower_operand(tokens, next, call, lowering);
+                        out_tokens.extend(call);
+                        i = next;
+                        continue;
+                    }
+                }
+            } else if let Some(class) = find_class_pointer(lowering.variables(left_operand), lowering.class_names, left_operand) {
+                // Through a pointer: p.method(params) or p->method(params) -> Class_method(*p, params)
+                if matches!(tokens.get(i + 1), Some(Token::Symbol(arrow)) if arrow == "." || arrow == "->") {
+                    let receiver = vec![Token::Symbol("*".to_string()), Token::Identifier(left_operand.clone())];
+                    if let Some((call, next)) = lower_method_call(tokens, i + 1, receiver, class, lowering) {
+                        let (call, next) = lower_operand(tokens, next, call, lowering);
+                        out_tokens.extend(call);
+                        i = next;
+                        continue;
+                    }
+                }
+            }
+        }
+        
+        // Handle prefix unary operators: ++obj, --obj, ~obj
+        if let Token::Symbol(operator) = &tokens[i] {
+            if let (Some(op), Some(Token::Identifier(operand))) = (operators::prefix(operator), tokens.get(i + 1)) {
+                if let Some(var) = find_class_variable(lowering.variables(operand), lowering.class_names, operand) {
+                    if DEBUG {println!("DEBUG: Found prefix unary operator: {}{}", operator, operand);}
+                    
+                    let class_with_namespace = lowering.class_names.get(&var.type_).unwrap_or(&var.type_);
+                    
+                    // Transform: ++obj -> Class_operator_increment(obj)
+                    let call = vec![
+                        Token::Identifier(format!("{}_operator_{}", class_with_namespace, op.name)),
+                        Token::Symbol("(".to_string()),
+                        Token::Identifier(operand.clone()),
+                        Token::Symbol(")".to_string()),
+                    ];
+                    // `~a + b`: the prefix operator binds tighter
+                    let (call, next) = lower_operand(tokens, i + 2, call, lowering);
+                    out_tokens.extend(call);
+                    i = next; // Skip past the prefix operation
+                    continue;
+                }
+            }
+        }
+
+        // A parenthesized expression, lowered on its own so a class value it
+        // evaluates to can be an operand: `(a + b) * c`. The parentheses of
+        // calls, casts and statements like `if (...)` are left to the loop
+        if matches!(&tokens[i], Token::Symbol(s) if s == "(") {
+            let called = match i.checked_sub(1).map(|p| &tokens[p]) {
+                Some(Token::Identifier(word)) => !is_keyword(word) || word == "sizeof",
+                Some(Token::Symbol(s)) => s == ")" || s == "]",
+                _ => false,
+            };
+            if let (false, Some(close)) = (called, matching_close(tokens, i)) {
+                // `((a))` is lowered once rather than once per layer, so deep
+                // nesting doesn't rescan and copy the inside at every level
+                let layers = (close + 1 - i - without_parentheses(&tokens[i..=close]).len()) / 2;
+                let mut group = vec![Token::Symbol("(".to_string()); layers];
+                group.extend(parse_function_calls_with_operators(&tokens[i + layers..=close - layers], lowering));
+                group.extend(vec![Token::Symbol(")".to_string()); layers]);
+                let (lowered, next) = lower_operand(tokens, close + 1, group, lowering);
+                out_tokens.extend(lowered);
+                i = next;
+                continue;
+            }
+        }
+        
+        // Handle namespace resolution: namespace::class, namespace::function or a::b::global
+        if let Token::Identifier(first_part) = &tokens[i] {
+            let mut path = first_part.clone();
+            let mut j = i;
+            while let (Some(Token::Symbol(scope_res)), Some(Token::Identifier(next_part))) = (tokens.get(j + 1), tokens.get(j + 2)) {
+                if scope_res != "::" {
+                    break;
+                }
+                path.push('_');
+                path.push_str(next_part);
+                j += 2;
+            }
+
+            // `return ::puts(s)` is a file-scope name, not a path starting at `return`
+            if j > i && !is_keyword(first_part) {
+                if DEBUG {println!("DEBUG: Found namespace resolution: {}", path);}
+
+                // Replace namespace::identifier with namespace_identifier
+                out_tokens.push(Token::Identifier(path));
+                i = j + 1; // Skip past the namespace resolution
+                continue;
+            }
+        }
+        
+        // Copy non-special tokens as is
+        out_tokens.push(tokens[i].clone());
+        i += 1;
+    }
+
+    if DEBUG {println!("DEBUG: parse_function_calls_with_operators completed, {} input tokens -> {} output tokens", 
+             tokens.len(), out_tokens.len())};
+    out_tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile;
+
+    fn compile_root(src: &str) -> (String, crate::Session) {
+        let mut session = crate::Session::default();
+        let output = crate::Compiler::new().compile_in(&mut session, "main.z", src);
+        (output, session)
+    }
+
+    #[test]
+    fn test_main_is_wrapped() {
+        let (output, session) = compile_root("int main(int argc, char** argv) { return argc; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("__z_main(int argc, char **argv) { return argc; }"));
+        assert!(output.contains("__z_argc = argc; __z_argv = argv; return __z_main(argc, argv); }"));
+    }
+
+    #[test]
+    fn test_test_functions_only_in_test_builds() {
+        let source = "int add(int a, int b) { return a + b; }\n@test void adds() { add(1, 2); }\n@test\nvoid adds_zero() { add(0, 0); }\nint main() { return add(1, 1); }";
+        let (output, session) = compile_root(source);
+        assert!(!session.has_errors());
+        assert!(!output.contains("adds"));
+        assert!(output.contains("return __z_main(); }"));
+
+        let mut session = crate::Session::default();
+        let compiler = crate::Compiler::builder().options(crate::Options { test: true, ..crate::Options::default() }).build();
+        let output = compiler.compile_in(&mut session, "main.z", source);
+        assert!(!session.has_errors());
+        assert_eq!(session.tests, vec!["adds", "adds_zero"]);
+        assert!(output.contains("int __z_main() { return add(1, 1); }"));
+        assert!(output.contains("if (strcmp(argv[1], \"adds_zero\") == 0) { adds_zero(); return 0; }"));
+
+        let mut session = crate::Session::default();
+        compiler.compile_in(&mut session, "main.z", "@test int counts(int n) { return n; }\nnamespace geo { @test void inside() { } }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`@test` function `counts` must take no parameters and return `void`",
+            "`@test` function `inside` must be at file scope, not in a namespace",
+        ]);
+    }
+
+    #[test]
+    fn test_void_main_returns_zero() {
+        let (output, session) = compile_root("#include <stdio.h>\nvoid main() { puts(\"hi\"); }");
+        assert!(!session.has_errors());
+        assert!(output.contains("__z_main(); return 0;"));
+    }
+
+    #[test]
+    fn test_missing_main_is_an_error() {
+        let (_, session) = compile_root("int helper() { return 1; }");
+        assert_eq!(session.diagnostics[0].message, "program has no `main` function");
+    }
+
+    #[test]
+    fn test_bad_main_signature_is_an_error() {
+        let (_, session) = compile_root("float main(int x) { return 1; }");
+        assert!(session.diagnostics[0].message.contains("must return `int` or `void`"));
+
+        let (_, session) = compile_root("int main(float x) { return 1; }");
+        assert!(session.diagnostics[0].message.contains("must take no parameters"));
+    }
+
+    #[test]
+    fn test_namespace_global_is_mangled() {
+        let output = compile("namespace cfg { int verbosity = 0; }\nint main() { return cfg::verbosity; }");
+        assert!(output.contains("int cfg_verbosity = 0;"));
+        assert!(output.contains("return cfg_verbosity;"));
+    }
+
+    #[test]
+    fn test_namespace_arrays_and_declarator_lists() {
+        let output = compile("namespace cfg { int arr[3] = {1, 2, 3}; int* first, second[2][2]; }\nint main() { return cfg::arr[0] + *cfg::first + cfg::second[1][1]; }");
+        assert!(output.contains("int cfg_arr[3] = { 1, 2, 3 };"), "{}", output);
+        assert!(output.contains("int *cfg_first;") && output.contains("int cfg_second[2][2];"), "{}", output);
+        assert!(output.contains("return cfg_arr[0] + *cfg_first + cfg_second[1][1];"), "{}", output);
+    }
+
+    #[test]
+    fn test_nested_namespace_global() {
+        let output = compile("namespace app { namespace cfg { const char* name; } }\nint main() { puts(app::cfg::name); }");
+        assert!(output.contains("const char *app_cfg_name;"));
+        assert!(output.contains("puts(app_cfg_name)"));
+    }
+
+    #[test]
+    fn test_anonymous_namespace_is_file_private() {
+        let output = compile("namespace {\nint counter = 0;\nint bump() { return ++counter; }\nclass Hidden { int x; int get() { return self.x; } }\n}\nint main() { Hidden h; h.get(); return counter; }");
+        assert!(output.contains("static int _anon_"));
+        assert!(output.contains("static int bump()"));
+        assert!(output.contains("static int _anon_") && output.contains("_Hidden_get(_anon_"));
+        assert!(!output.contains("return counter;"));
+    }
+
+    #[test]
+    fn test_anonymous_classes_not_exported_to_session() {
+        let mut session = crate::Session::default();
+        crate::Compiler::new().compile_in(&mut session, "lib.z", "namespace { class Hidden { int x; } }\nclass Shown { int y; }");
+        assert!(session.known_classes.contains("Shown"));
+        assert!(!session.known_classes.contains("Hidden"));
+    }
+
+    #[test]
+    fn test_inline_namespace_members_use_parent_prefix() {
+        let output = compile("namespace lib { inline namespace v2 { int version = 2; } }\nint main() { return lib::version; }");
+        assert!(output.contains("int lib_version = 2;"));
+        assert!(output.contains("return lib_version;"));
+    }
+
+    #[test]
+    fn test_unqualified_global_inside_namespace() {
+        let output = compile("namespace cfg {\nint verbosity = 1;\nint level() { return verbosity; }\n}");
+        assert!(output.contains("return cfg_verbosity;"));
+    }
+
+    #[test]
+    fn test_namespace_functions_are_mangled() {
+        let output = compile("namespace util {\nint twice(int x) { return x * 2; }\nint quad(int x) { return twice(twice(x)); }\n}\nint main() { return util::quad(1); }");
+        assert!(output.contains("int util_twice(int x)"));
+        assert!(output.contains("return util_twice(util_twice(x));"));
+        assert!(output.contains("return util_quad(1);"));
+    }
+
+    #[test]
+    fn test_pointer_members() {
+        let output = compile("class Buf { char** items; int n; char* first() { char* item = self.items[0]; return item; } }");
+        assert!(output.contains("typedef struct { char **items; int n; } Buf;"));
+        assert!(output.contains("char *Buf_first(Buf self)"));
+    }
+
+    #[test]
+    fn test_std_env_is_bundled() {
+        let (output, session) = compile_root("#import <std/env.z>\nint main() { std::env::Args args = std::env::args(); return std::env::var(\"HOME\") != 0; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("std_env_Args std_env_args()"));
+        assert!(output.contains("return std_env_var(\"HOME\") != 0;"));
+    }
+
+    #[test]
+    fn test_std_fs_returns_results() {
+        let (output, session) = compile_root("#import <std/fs.z>\nint main() { std::Result r = std::fs::read_to_string(\"a.txt\"); return r.is_err(); }");
+        assert!(!session.has_errors());
+        assert!(output.contains("std_Result std_fs_read_to_string(const char *path)"));
+        assert!(output.contains("return std_Result_is_err(r);"));
+    }
+
+    #[test]
+    fn test_file_is_imported_once() {
+        let (output, _) = compile_root("#import <std/result.z>\n#import <std/fs.z>\n#import <std/result.z>\nint main() { return 0; }");
+        assert_eq!(output.matches("std_Result std_ok(").count(), 1);
+    }
+
+    #[test]
+    fn test_file_scope_prefix_skips_mangling() {
+        let output = compile("#include <math.h>\nnamespace m {\ndouble sin(double x) { return ::sin(x); }\n}");
+        assert!(output.contains("double m_sin(double x) { return sin(x); }"));
+    }
+
+    #[test]
+    fn test_std_math_and_rand() {
+        let (output, session) = compile_root("#import <std/math.z>\n#import <std/rand.z>\nint main() { std::rand::Rng rng = std::rand::seeded(1); return std::math::max(std::math::PI, std::rand::unit(&rng)) > 3; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("double std_math_sin(double x) { return sin(x); }"));
+        assert!(output.contains("std_rand_Rng std_rand_shared ="));
+        assert!(output.contains("std_math_max(std_math_PI, std_rand_unit(&rng))"));
+    }
+
+    #[test]
+    fn test_std_thread() {
+        let (output, session) = compile_root("#import <std/thread.z>\nvoid work(void* arg) { }\nint main() { std::thread::Mutex m = std::thread::mutex(); std::thread::Thread t = std::thread::spawn(work, NULL); m.lock(); m.unlock(); return t.join(); }");
+        assert!(!session.has_errors());
+        assert!(output.contains("std_thread_Thread std_thread_spawn(void(*fn)(void *), void *arg)"));
+        assert!(output.contains("std_thread_Mutex_lock(m);"));
+        assert!(output.contains("return std_thread_Thread_join(t);"));
+    }
+
+    #[test]
+    fn test_std_net() {
+        let (output, session) = compile_root("#import <std/net.z>\nint main() { std::Result r = std::net::connect(\"localhost\", 80); std::net::Stream* s = r.value; long n = s->write(\"hi\", 2); s->close(); return n; }");
+        assert!(!session.has_errors());
+        // The C functions shadowed by the module's own are called with `::`
+        assert!(output.contains("if (connect(fd, a->ai_addr, (int)a->ai_addrlen) == 0)"));
+        assert!(output.contains("std_Result std_net_accept(std_net_Listener *listener)"));
+        assert!(output.contains("long n = std_net_Stream_write(*s, \"hi\", 2);"));
+    }
+
+    #[test]
+    fn test_std_process() {
+        let (output, session) = compile_root("#import <std/process.z>\nint main() { const char* args[] = {\"-l\", NULL}; std::Result r = std::process::run(\"ls\", args); std::process::Output* o = r.value; int status = o->status; std::process::release(o); return status; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("std_Result std_process_run(const char *cmd, const char **args)"));
+        assert!(output.contains("std_process_Output *output = calloc(1, sizeof(std_process_Output));"));
+        assert!(output.contains("std_process_release(o);"));
+    }
+
+    #[test]
+    fn test_std_time() {
+        let (output, session) = compile_root("#import <std/time.z>\nint main() { std::time::Instant start = std::time::now(); std::time::Duration step = std::time::millis(2); std::time::Duration d = step * 3 + std::time::micros(1); std::time::sleep(d); std::time::Duration taken = std::time::elapsed(start); return taken < d; }");
+        assert!(!session.has_errors());
+        // Class names in method and operator signatures are mangled inside the namespace
+        assert!(output.contains("std_time_Duration std_time_Instant_since(std_time_Instant self, std_time_Instant earlier)"));
+        assert!(output.contains("std_time_Duration std_time_Duration_operator_add(std_time_Duration self, std_time_Duration other)"));
+        assert!(output.contains("std_time_Duration __z_tmp0 = std_time_Duration_operator_mul(step, 3); std_time_Duration __z_tmp1 = std_time_micros(1); std_time_Duration d = std_time_Duration_operator_add(__z_tmp0, __z_tmp1);"));
+        assert!(output.contains("return std_time_Duration_operator_lt(taken, d);"));
+    }
+
+    #[test]
+    fn test_c_header_import_checks_calls() {
+        let header = std::env::temp_dir().join("tarnish_test_cheader.h");
+        std::fs::write(&header, "int add(int a, int b);\nint log_line(const char *fmt, ...);\n").unwrap();
+        let src = format!("#import <c:{}>\nint main() {{ log_line(\"%d\", add(1, 2)); return add(1); }}", header.display());
+        let (output, session) = compile_root(&src);
+        assert!(output.contains(&format!("#include <{}>", header.display())));
+        assert_eq!(session.diagnostics.len(), 1);
+        assert_eq!(session.diagnostics[0].message, "`add` takes 2 arguments but 1 was given");
+    }
+
+    #[test]
+    fn test_variadic_methods_and_format_checks() {
+        let class = "#include <stdio.h>\n#include <stdarg.h>\nclass Log { int level; void line(const char* fmt, ...) { va_list ap; va_start(ap, fmt); vprintf(fmt, ap); va_end(ap); } }\n";
+        let (output, session) = compile_root(&format!("{}int main() {{ Log log; log.line(\"%d\", 1); return 0; }}", class));
+        assert!(!session.has_errors());
+        assert!(output.contains("void Log_line(Log self, const char *fmt, ...);"));
+        let (_, session) = compile_root(&format!("{}int main() {{ double d = 0.5; printf(\"%d %s\\n\", d); return 0; }}", class));
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`printf` format `%d` expects an integer but argument 2 is a floating-point number",
+            "`printf` format uses 2 arguments but 1 was given",
+        ]);
+    }
+
+    #[test]
+    fn test_zi_import_registers_classes() {
+        let interface = std::env::temp_dir().join("tarnish_test_geo.zi");
+        std::fs::write(&interface, "#class Point geo_Point\ntypedef struct { int x; } geo_Point;\nint geo_Point_sum(geo_Point self);\n").unwrap();
+        let src = format!("#import <{}>\nint main() {{ geo::Point p; return p.sum(); }}", interface.display());
+        let (output, session) = compile_root(&src);
+        assert!(!session.has_errors());
+        assert!(output.contains("int geo_Point_sum(geo_Point self);"));
+        assert!(output.contains("geo_Point p; return geo_Point_sum(p);"));
+    }
+
+    #[test]
+    fn test_definitions_are_emitted_once() {
+        let dir = std::env::temp_dir().join("tarnish_test_registry");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("point.z"), "class Point { int x; int get() { return self.x; } }\nint origin() { return 0; }").unwrap();
+        std::fs::write(dir.join("other.z"), "class Point { long x; }").unwrap();
+        let lib = dir.join("point.z");
+        let alias = dir.join(".").join("point.z");
+        let src = format!("#import <{}>\n#import <{}>\nint main() {{ Point p; return p.get() + origin(); }}", lib.display(), alias.display());
+        let (output, session) = compile_root(&src);
+        assert!(!session.has_errors());
+        assert_eq!(output.matches("} Point;").count(), 1);
+        assert_eq!(output.matches("int origin()").count(), 1);
+
+        let src = format!("#import <{}>\n#import <{}>\nint main() {{ return 0; }}", lib.display(), dir.join("other.z").display());
+        let (_, session) = compile_root(&src);
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["`Point` is defined differently by another file"]);
+    }
+
+    #[test]
+    fn test_loops_are_lowered_per_header_part() {
+        let output = compile("class V { int x; V operator+(V o) { return (V){self.x + o.x}; } int lt(V o) { return self.x < o.x; } }\nint main() { V a; V b; V step;\nfor (V i = a; i.lt(b); i = i + step) { a = a + step; }\nwhile (a.lt(b)) a = a + step;\ndo { b = b + a; } while (b.lt(a));\nreturn 0; }");
+        assert!(output.contains("for (V i = a; V_lt(i, b); i = V_operator_add(i, step)) {"));
+        assert!(output.contains("while (V_lt(a, b)) { a = V_operator_add(a, step); }"));
+        assert!(output.contains("do { b = V_operator_add(b, a); } while (V_lt(b, a));"));
+    }
+
+    #[test]
+    fn test_class_valued_expressions_are_operands() {
+        let output = compile("class V { int x; V operator+(V o) { return self; } V operator~() { return self; } int operator<(V o) { return 0; } V twice() { return self; } }\nV operator*(V a, V b) { return a; }\nint main(int t) { V a; V b; V c;\nV d = (a + b) * c;\nV e = a.twice() + ~b;\nV f = (t ? a : b).twice();\nint g = (a < b) + 1;\nreturn 0; }");
+        assert!(output.contains("V __z_tmp0 = V_operator_add(a, b); V d = V_operator_mul(__z_tmp0, c);"));
+        assert!(output.contains("V __z_tmp1 = V_twice(a); V __z_tmp2 = V_operator_bit_not(b); V e = V_operator_add(__z_tmp1, __z_tmp2);"));
+        assert!(output.contains("V f = V_twice((t ? a : b));"));
+        assert!(output.contains("int g = (V_operator_lt(a, b)) + 1;"));
+    }
+
+    #[test]
+    fn test_break_and_continue_need_a_loop() {
+        let (_, session) = compile_root("int main() { int i = 0; switch (i) { case 0: break; }\nwhile (i < 3) { if (i) break; i++; continue; }\nbreak; continue; return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["`break` outside of a loop or switch", "`continue` outside of a loop"]);
+    }
+
+    #[test]
+    fn test_loop_conditions_are_checked() {
+        let (_, session) = compile_root("class V { int x; }\nint main() { V v; while (v) { } do { } while (); return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["`while` condition `v` has class type `V`, which can't be tested for truth", "`do-while` loop has no condition"]);
+    }
+
+    #[test]
+    fn test_labeled_loops_lower_to_goto() {
+        let (output, session) = compile_root("int main() { int n = 0;\nouter: for (int i = 0; i < 3; i++) { for (int j = 0; j < 3; j++) { if (j == 1) continue outer; if (i == 2) break outer; n++; } }\nreturn n; }");
+        assert!(!session.has_errors());
+        assert!(!output.contains("outer:"));
+        assert!(output.contains("if (j == 1) goto __z_continue_outer_0;"));
+        assert!(output.contains("if (i == 2) goto __z_break_outer_0;"));
+        assert!(output.contains("__z_continue_outer_0 :; }"));
+        assert!(output.contains("} __z_break_outer_0 :;"));
+    }
+
+    #[test]
+    fn test_unknown_loop_label() {
+        let (_, session) = compile_root("int main() { outer: while (1) { break inner; }\ncase_label: return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["`break inner` has no enclosing loop labeled `inner`"]);
+    }
+
+    #[test]
+    fn test_operators_in_conditions_and_ternaries() {
+        let output = compile("class V { int x; int operator<(V o) { return self.x < o.x; } V operator+(V o) { return (V){self.x + o.x}; } V next() { return self; } }\nV pick(V a, V b) {\nif (a < b) { return b; } else if (b < a.next()) { return a; }\nint n = a < b ? 1 : 0;\nV c = n > 0 ? a + b.next() : b;\nreturn a < (V){1} ? a : b;\n}");
+        assert!(output.contains("if (V_operator_lt(a, b)) { return b; } else if (V_operator_lt(b, V_next(a))) { return a; }"));
+        assert!(output.contains("int n = V_operator_lt(a, b) ? 1 : 0;"));
+        assert!(output.contains("? V_operator_add(a, V_next(b)) : b;"));
+        assert!(output.contains("return V_operator_lt(a, (V){ 1 }) ? a : b;"));
+    }
+
+    #[test]
+    fn test_operator_bool_in_conditions() {
+        let output = compile("class Opt { int has; operator bool() { return self.has; } }\nint f(Opt o, Opt q) {\nif (o) { return 1; }\nwhile (o && !q) { o.has = 0; }\nfor (; q;) { }\nreturn o || q ? 1 : 0;\n}");
+        assert!(output.contains("int Opt_operator_bool(Opt self) { return self.has; }"));
+        assert!(output.contains("if (Opt_operator_bool(o)) { return 1; }"));
+        assert!(output.contains("while (Opt_operator_bool(o) && !Opt_operator_bool(q))"));
+        assert!(output.contains("for (; Opt_operator_bool(q);)"));
+        assert!(output.contains("return Opt_operator_bool(o) || Opt_operator_bool(q) ? 1 : 0;"));
+    }
+
+    #[test]
+    fn test_free_operators() {
+        let (output, session) = compile_root("namespace geo {\nclass V { int x; }\nV operator-(V a, V b) { return (V){a.x - b.x}; }\n}\ngeo::V operator+(V a, V b) { return (geo::V){a.x + b.x}; }\noperator bool(V v) { return v.x; }\nint operator*(int a, int b) { return a; }\nint main() { geo::V a; geo::V b; geo::V c = a + b - a; return c ? 0 : 1; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["the left operand of `operator*` must be a class, not `int`"]);
+        assert!(output.contains("geo_V geo_V_operator_sub(geo_V a, geo_V b) { return (geo_V){ a.x - b.x }; }"));
+        assert!(output.contains("geo_V geo_V_operator_add(geo_V a, geo_V b)"));
+        assert!(output.contains("int geo_V_operator_bool(geo_V v) { return v.x; }"));
+        assert!(output.contains("geo_V __z_tmp0 = geo_V_operator_add(a, b); geo_V c = geo_V_operator_sub(__z_tmp0, a);"));
+        assert!(output.contains("return geo_V_operator_bool(c) ? 0 : 1;"));
+    }
+
+    #[test]
+    fn test_operator_overloads_by_right_operand() {
+        let (output, session) = compile_root("class V { float x; V operator*(V o) { return o; } V operator*(float k) { return self; } V operator+(V o) { return o; } }\nV operator-(V a, double d) { return a; }\nV operator-(V a, int n) { return a; }\nstatic float half() { return 0.5f; }\ndouble scale(int n) { return n; }\nint main() { V v; int n = 3; V w = v * 2; V u = v * v + v * (float)n; V t = v - 2.5 - n; V s = v * half() - scale(n); V r = v * (n * 2) - (n + 0.5); return 0; }");
+        assert!(session.diagnostics.iter().all(|d| !d.is_error()));
+        assert!(output.contains("V V_operator_mul_V(V self, V o)"));
+        assert!(output.contains("V V_operator_mul_float(V self, float k)"));
+        assert!(output.contains("V V_operator_sub_double(V a, double d)"));
+        // Overloaded once, an operator keeps its plain name
+        assert!(output.contains("V __z_tmp0 = V_operator_mul_V(v, v); V __z_tmp1 = V_operator_mul_float(v, (float)n); V u = V_operator_add(__z_tmp0, __z_tmp1);"));
+        assert!(output.contains("V w = V_operator_mul_float(v, 2);"));
+        assert!(output.contains("V __z_tmp2 = V_operator_sub_double(v, 2.5); V t = V_operator_sub_int(__z_tmp2, n);"));
+        // Typed by what the function returns, and by the arithmetic in parentheses
+        assert!(output.contains("V __z_tmp4 = V_operator_mul_float(v, __z_tmp3); double __z_tmp5 = scale(n); V s = V_operator_sub_double(__z_tmp4, __z_tmp5);"));
+        assert!(output.contains("V __z_tmp6 = V_operator_mul_float(v, (n * 2)); V r = V_operator_sub_double(__z_tmp6, (n + 0.5));"));
+
+        let (_, session) = compile_root("class V { float x; V operator*(float k) { return self; } V operator*(double k) { return self; } V operator*(double k) { return self; } }\nint main() { V v; char* s; V w = v * 2; V a = v * s; V b = v * v.x; return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`operator*` of `V` is overloaded twice for `double`",
+            "`operator*` of `V` is ambiguous for `2` of type `int`, which converts to `float` or `double` alike; cast it to one of them",
+            "no `operator*` of `V` takes `s` of type `char*`; its overloads take `float` or `double`",
+            "cannot tell which `operator*` of `V` to call: the type of `v.x` is unknown; cast it to `float` or `double`",
+        ]);
+
+        // Declared once, or not at all
+        let (_, session) = compile_root("class V { float x; V operator*(V o) { return o; } }\nclass Circle { float r; int operator<(double d) { return self.r < d; } }\nint main() { V v; Circle c; V w = v * v; V a = v * 2; V b = v * 2.5f; int small = c < 2.0; int big = c > 2.0; return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "no `operator*` of `V` takes `2` of type `int`; it takes `V`",
+            "no `operator*` of `V` takes `2.5f` of type `float`; it takes `V`",
+            "class `Circle` has no `operator>`",
+        ]);
+    }
+
+    #[test]
+    fn test_const_generic_classes() {
+        let (output, session) = compile_root("const int ROWS = 2;\nclass Matrix<const N: int, const M: int> { float data[N*M]; int size() { return N * M; } }\nint main() { Matrix<ROWS, 3> a; Matrix<3, 3> b; return a.size() + b.size(); }");
+        assert!(session.diagnostics.iter().all(|d| !d.is_error()));
+        assert!(output.contains("typedef struct { float data[6]; } Matrix_2_3;"));
+        assert!(output.contains("typedef struct { float data[9]; } Matrix_3_3;"));
+        assert!(output.contains("int Matrix_2_3_size(Matrix_2_3 self) { return 2 * 3; }"));
+        assert!(output.contains("return Matrix_2_3_size(a) + Matrix_3_3_size(b);"));
+    }
+
+    #[test]
+    fn test_typeinfo() {
+        let (output, session) = compile_root("@reflect class P { int x; char* name; int get() { return self.x; } }\nclass Q { int y; }\nint main() { const TypeInfo* t = typeinfo(P); typeinfo(Q); typeinfo(R); return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`typeinfo` needs a class, and `R` is not one",
+            "class `Q` has no type information; declare it `@reflect class`",
+        ]);
+        assert!(output.contains("static const FieldInfo P__fields[] = { { \"x\", \"int\", offsetof(P, x), sizeof(((P *)0)->x) },"));
+        assert!(output.contains("static const MethodInfo P__methods[] = { { \"get\", (void(*)(void)) P_get } };"));
+        assert!(output.contains("const TypeInfo P__typeinfo = { \"P\", sizeof(P), _Alignof(P), 2, P__fields, 1, P__methods };"));
+        assert!(output.contains("const TypeInfo *t = (&P__typeinfo);"));
+        assert!(!output.contains("Q__typeinfo"));
+    }
+
+    #[test]
+    fn test_derive_pack() {
+        let (output, session) = compile_root("@derive(Pack) class P { short x; double y; }\n@derive(Pack, Debug) class Q { P p; char* name; int n; }\n@derive(Pack) union U { int i; }\nint main() { return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "field `name` of class `Q` can't be packed: `char*` is not a number or a class that derives `Pack`",
+            "`Debug` can't be derived; `@derive` supports `Pack`",
+            "`U` can't derive `Pack`; it is a union",
+        ]);
+        assert!(output.contains("size_t P_pack(P self, unsigned char *out) { size_t n = 0; n += __z_pack_int(out + n, (unsigned long long)self.x, 2); n += __z_pack_f64(out + n, self.y); return n; }"));
+        assert!(output.contains("size_t P_unpack(P *self, const unsigned char *in) { size_t n = 0; self->x = (short)__z_unpack_int(in + n, 2); n += 2; self->y = __z_unpack_f64(in + n); n += 8; return n; }"));
+        assert!(output.contains("n += P_pack(self.p, out + n);"));
+        assert!(output.contains("n += P_unpack(&self->p, in + n);"));
+    }
+
+    #[test]
+    fn test_truth_test_without_operator_bool() {
+        let (_, session) = compile_root("class P { int x; }\nint main() { P p; P q; if (p) return 1; int a = p && q.x; return !q ? 0 : p.x; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`if` condition `p` has class type `P`, which can't be tested for truth",
+            "`&&` operand `p` has class type `P`, which can't be tested for truth",
+            "`!` operand `q` has class type `P`, which can't be tested for truth",
+        ]);
+    }
+
+    #[test]
+    fn test_switch_on_enum_is_checked_for_missing_cases() {
+        let (output, session) = compile_root("enum Color { RED, GREEN = 4, BLUE };\ntypedef enum { ON, OFF } Mode;\nint main() { enum Color c = RED; Mode m = ON;\nswitch (c) { case RED: return 1; case BLUE: break; }\nswitch (m) { case ON: break; default: break; }\nreturn 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["switch on `c` of enum `Color` has no `default` and does not handle `GREEN`"]);
+        assert!(!session.has_errors());
+        assert!(output.contains("switch (c) { case RED : return 1; case BLUE : break; }"));
+
+        // A parameter is checked; an `int c` elsewhere, or hiding it, isn't
+        let (_, session) = compile_root("enum Color { RED, GREEN, BLUE };\nint f(enum Color c) { switch (c) { case RED: return 1; } return 0; }\nint g(int c) { switch (c) { case 1: return 1; } return 0; }\nint h(enum Color c) { { int c = 2; switch (c) { case 2: return 1; } } return 0; }\nint main() { return f(RED) + g(1) + h(BLUE); }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.message.starts_with("switch")).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["switch on `c` of enum `Color` has no `default` and does not handle `GREEN`, `BLUE`"]);
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let output = compile("class Bits { int v; Bits operator&(Bits o) { return (Bits){self.v & o.v}; } Bits operator<<(int n) { return (Bits){self.v << n}; } void operator|=(Bits o) { self.v |= o.v; } Bits operator~() { return (Bits){~self.v}; } }\nint main() { Bits a; Bits b;\nBits c = a & b;\nBits d = a << 2;\na |= b;\nBits e = ~a;\nreturn 0; }");
+        assert!(output.contains("Bits Bits_operator_bit_and(Bits self, Bits o)"));
+        assert!(output.contains("Bits Bits_operator_bit_not(Bits self)"));
+        assert!(output.contains("Bits c = Bits_operator_bit_and(a, b);"));
+        assert!(output.contains("Bits d = Bits_operator_shl(a, 2);"));
+        assert!(output.contains("Bits_operator_bit_or_assign(a, b);"));
+        assert!(output.contains("Bits e = Bits_operator_bit_not(a);"));
+    }
+
+    #[test]
+    fn test_index_operator() {
+        let output = compile("class Row { int* cells; int operator[](int i) { return self.cells[i]; } }\nint main() { Row r; int k = 1;\nint x = r[k + 1];\nreturn r[r[0]] + x; }");
+        assert!(output.contains("int Row_operator_index(Row self, int i) { return self.cells[i]; }"), "{}", output);
+        assert!(output.contains("int x = Row_operator_index(r, k + 1);"), "{}", output);
+        assert!(output.contains("int __z_tmp0 = Row_operator_index(r, 0); return Row_operator_index(r, __z_tmp0) + x;"), "{}", output);
+    }
+
+    #[test]
+    fn test_for_each_uses_begin_and_end() {
+        let output = compile("class Range { int* first; int* last; int* begin() { return self.first; } int* end() { return self.last; } }\nint main() { Range r; int sum = 0;\nfor (int x : r) sum += x;\nreturn sum; }");
+        assert!(output.contains("for (__typeof__(Range_begin(r)) __z_it_0 = Range_begin(r), __z_end_0 = Range_end(r); __z_it_0 != __z_end_0; __z_it_0++) { int x = *__z_it_0; sum += x; }"));
+    }
+
+    #[test]
+    fn test_for_each_needs_an_iterable_class() {
+        let (_, session) = compile_root("class Bag { int* items; int* begin() { return self.items; } }\nint main() { Bag b; int n = 3;\nfor (int x : b) { }\nfor (int y : n) { }\nreturn 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`b` can't be iterated with for-each: class `Bag` has no `end()`",
+            "for-each over `n` needs a class value with `begin()` and `end()`",
+        ]);
+    }
+
+    #[test]
+    fn test_rc_class_is_reference_counted() {
+        let (output, session) = compile_root("rc class Node { int value; Node next; void drop() { } }\nNode pair(Node tail) { Node head = Node::new(); head.next = tail; return head; }\nint main() { return 0; }");
+        assert!(session.rc_classes.contains_key("Node"));
+        assert!(output.contains("typedef struct { Node__data *ptr; } Node;"));
+        assert!(output.contains("struct Node__data { int __z_refs; int value; Node next; };"));
+        assert!(output.contains("Node_drop(self); Node_release(self.ptr->next); free(self.ptr); }"));
+        assert!(output.contains("Node_retain(tail); Node head = Node_new(); Node_set(&head.ptr->next, Node_retain(tail)); { Node_release(tail); return head; }"));
+    }
+
+    #[test]
+    fn test_operator_chains_follow_precedence() {
+        let output = compile("class V { int x; V operator+(V o) { return o; } V operator*(V o) { return o; } int operator<(V o) { return 0; } void operator+=(V o) { } }\nint f(V a, V b, V c) { V d = a + b * c + a; a += b + c; return a + b < c; }");
+        assert!(output.contains("V __z_tmp0 = V_operator_mul(b, c); V __z_tmp1 = V_operator_add(a, __z_tmp0); V d = V_operator_add(__z_tmp1, a);"));
+        assert!(output.contains("{ V __z_tmp2 = V_operator_add(b, c); V_operator_add_assign(a, __z_tmp2); }"));
+        assert!(output.contains("{ V __z_tmp3 = V_operator_add(a, b); return V_operator_lt(__z_tmp3, c); }"));
+
+        // Nested parentheses are lowered once, not layer by layer
+        let output = compile("class V { int x; V operator+(V o) { return o; } }\nV f(V a, V b) { V c = (((a))) + ((b)); return ((((a + b)) + a)); }");
+        assert!(output.contains("V c = V_operator_add((((a))), ((b)));"), "{}", output);
+        assert!(output.contains("{ V __z_tmp0 = V_operator_add(a, b); return ((V_operator_add(__z_tmp0, a))); }"), "{}", output);
+    }
+
+    #[test]
+    fn test_operands_are_typed_by_the_variable_in_scope() {
+        let (output, session) = compile_root("class Circle { float r; int operator<(Circle o) { return self.r < o.r; } }\nint digit(char c) { return c < '0'; }\nint main() { Circle c; Circle d;\nint a = c < d;\n{ char c = 'x'; a += c < '0'; }\nfor (int c = 0; c < 2; c++) a += c < 1;\nreturn a + digit('1') + (c < d); }");
+        assert!(!session.has_errors(), "{:?}", session.diagnostics);
+        assert!(output.contains("int digit(char c) { return c < '0'; }"));
+        assert!(output.contains("int a = Circle_operator_lt(c, d);"));
+        assert!(output.contains("{ char c = 'x'; a += c < '0'; }"));
+        assert!(output.contains("a += c < 1;"));
+        assert!(output.contains("(Circle_operator_lt(c, d))"));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_reported() {
+        let (_, session) = compile_root("class Point {\n  int x;\n  int y;\n  int sum() { return self.x + self.y; }\n}\nclass Body { Point pos; int mass; }\nint f(Point p, Body* b) {\n  int s = p.sum() + p.x + b->pos.y + b->mass;\n  return p.speed + b->pos.xx + b->weight;\n}\nint g(int p) { return p; }\nint main() { return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "class `Point` has no field `speed`; its fields are `x` and `y`",
+            "class `Point` has no field `xx`; did you mean `x`? its fields are `x` and `y`",
+            "class `Body` has no field `weight`; its fields are `pos` and `mass`",
+        ]);
+    }
+
+    #[test]
+    fn test_arena_keeps_operator_temporaries() {
+        let (output, session) = compile_root("rc class Big { int v; Big operator+(Big o) { return o; } }\n@arena int total(Big a, Big b) { Big s = a + b + a; return s.v; }\n@fast int main() { return 0; }");
+        assert!(output.contains("typedef struct { __z_arena_entry *entries;"));
+        assert!(output.contains("Big s = Big_operator_add(__z_arena_temp(&__z_arena, Big, Big_release_ptr, Big_operator_add(a, b)), a);"));
+        assert!(!output.contains("@"));
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["unknown attribute `@fast`"]);
+    }
+
+    #[test]
+    fn test_inline_functions_are_static_inline() {
+        let (output, _) = compile_root("class P { int x; @inline int get_x() { return self.x; } }\nnamespace { @inline static int twice(int v) { return v * 2; } }\nint main() { P p; return twice(p.get_x()); }");
+        assert!(output.contains("static inline int P_get_x(P self)"));
+        assert!(output.contains("static inline int twice(int v)"));
+        assert!(!output.contains("static static"));
+    }
+
+    #[test]
+    fn test_methods_can_call_later_methods() {
+        let output = compile("class C { int n; int twice() { return self.once() * 2; } int once() { return self.n; } }");
+        let prototype = output.find("int C_once(C self);").unwrap();
+        let body = output.find("int C_twice(C self) {").unwrap();
+        assert!(prototype < body);
+        assert!(output.contains("return C_once(self) * 2;"));
+    }
+
+    #[test]
+    fn test_method_calls_through_pointers() {
+        let (output, session) = compile_root("class V { int x; int add(int k) { return self.x + k; } int get() { return self.x; } }\nint f(V* q) { return q ? q->get() : 0; }\nint main() { V a; V* p = &a; return p.add(a.get()) + f(p); }");
+        assert!(!session.has_errors());
+        assert!(output.contains("return q ? V_get(*q) : 0;"));
+        assert!(output.contains("return V_add(*p, V_get(a)) + f(p);"));
+    }
+
+    #[test]
+    fn test_chained_method_calls() {
+        let (output, session) = compile_root("class V { int x; Self scale(int k) { self.x = self.x * k; return self; } W wrap() { W w; w.v = self; return w; } int get() { return self.x; } }
+class W { V v; V inner() { return self.v; } }
+int main() { V a; V* p = &a; return a.scale(2).scale(3).get() + p->wrap().inner().get(); }");
+        assert!(!session.has_errors());
+        assert!(output.contains("V V_scale(V self, int k)"));
+        assert!(output.contains("return V_get(V_scale(V_scale(a, 2), 3)) + V_get(W_inner(V_wrap(*p)));"));
+    }
+
+    #[test]
+    fn test_layout_attributes() {
+        let (output, session) = compile_root("@packed class H { char kind; int length; }\n@align(16)\nclass B { int a; }\nchar h[sizeof(H)];\nint main() { return 0; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("typedef struct __attribute__((packed)) { char kind; int length; } H;"));
+        assert!(output.contains("typedef struct __attribute__((aligned(16))) { int a; } B;"));
+        assert!(output.contains("char h[5];"));
+
+        let (_, session) = compile_root("rc class N { int v; }\n@c_layout class M { int id; N owner; char name[16]; unsigned flags : 3; }\n@align(3) class A { int x; }\n@packed int f() { return 0; }\nint main() { return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "field `owner` of `@c_layout` class `M` is an rc handle, not a C value",
+            "`@c_layout` class `M` can't have the field `unsigned flags : 3`; only `Type name;` and `Type name[N];` fields are laid out",
+            "`@align` of class `A` must be a power of two, not 3",
+            "`@packed` applies to classes, not functions",
+        ]);
+    }
+
+    #[test]
+    fn test_unions() {
+        let (output, session) = compile_root("namespace num { union Bits { float f; unsigned int u; unsigned int raw() { return self.u; } }; }\nunion Raw { int a; } raw_value;\n@tagged class T { int x; }\nint main() { num::Bits b; b.f = 1.0; return b.raw(); }");
+        assert!(output.contains("typedef union num_Bits { float f; unsigned int u; } num_Bits;"));
+        assert!(output.contains("unsigned int num_Bits_raw(num_Bits self)"));
+        assert!(output.contains("return num_Bits_raw(b);"));
+        // A union declaring a variable is left as C
+        assert!(output.contains("union Raw { int a; } raw_value;"));
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["`@tagged` applies to unions, not classes like `T`"]);
+
+        let mut session = crate::Session::default();
+        let compiler = crate::Compiler::builder().options(crate::Options { union_checks: true, ..crate::Options::default() }).build();
+        let output = compiler.compile_in(&mut session, "main.z", "@tagged union V { int i; double d; }\nint main() { V v = { .d = 1 }; return v.i; }");
+        assert!(output.contains("typedef struct V { int __z_tag; union { int i; double d; }; } V;"));
+        assert!(output.contains("__z_union_read(&v, v.__z_tag, 1, \"V\""));
+    }
+
+    #[test]
+    fn test_runtime_checks_report_source_lines() {
+        // Slices, ranges and every check put C in front of the code they
+        // report lines of; none of it counts
+        let source = "@tagged union V { int i; double d; }\nint first(int[] xs) { return xs[0]; }\nint total(range r) { int t = 0; for (int i : r) { t += i; } return t; }\nint main() {\n  int n = 1;\n  n = n * 2;\n  V v = { .d = 1 };\n  if (n > 3) { panic(\"big\"); }\n  return v.i;\n}";
+        let mut session = crate::Session::default();
+        let options = crate::Options { bounds_check: true, overflow_checks: true, union_checks: true, ..crate::Options::default() };
+        let output = crate::Compiler::builder().options(options).build().compile_in(&mut session, "main.z", source);
+        assert!(!session.has_errors());
+        assert!(output.contains("xs.ptr[__z_bounds_check(0, xs.len, \"main.z\", 2)]"));
+        assert!(output.contains("n = __z_checked(__builtin_mul_overflow, \"*\", n, 2, \"main.z\", 6);"));
+        assert!(output.contains("__z_panic(\"main.z\", 8, \"big\");"));
+        assert!(output.contains("\"main.z\", 9))->i;"));
+        assert!(!output.contains("__z_line"));
+    }
+
+    #[test]
+    fn test_interfaces() {
+        let (output, session) = compile_root("interface Named { const char* name(); }\nclass Dog { int legs; const char* name() { return \"dog\"; } }\nint main() { Dog d; Named n = &d; Dog* back = n as? Dog; return back == 0; }");
+        assert!(!session.has_errors());
+        assert!(output.contains("typedef struct { void *data; const Named__vtable *vt; } Named;"));
+        assert!(output.contains("static const Named__vtable Dog__Named__vtable = { &Dog__type, Dog__Named_name };"));
+        assert!(output.contains("Named n = (Named){ &d, &Dog__Named__vtable };"));
+        assert!(output.contains("Dog *back = ((n).vt->type == &Dog__type ? (Dog *)(n).data : (Dog *)0);"));
+
+        let (_, session) = compile_root("interface Named { const char* name(); }\nclass Cat { int lives; }\nint main() { Cat c; int x = 0; Named n = &c; any a = any(x); int* p = a as? int; return 0; }");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "class `Cat` can't be a `Named`: it has no method `name`",
+            "`any(...)` needs a pointer to a class value, e.g. `any(&value)`, not `x`",
+            "`as?` needs a class, and `int` is not one",
+        ]);
+    }
+
+    #[test]
+    fn test_unused_symbols() {
+        let dir = std::env::temp_dir().join("tarnish_test_unused");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("shapes.z"), "class Pt { int x; int y; }\nPt origin() { Pt p = { 0, 0 }; return p; }").unwrap();
+        std::fs::write(dir.join("extra.z"), "int extra() { return 1; }").unwrap();
+        let src = format!(
+            "#import <{}>\n#import <{}>\n@allow(unused)\n#import <{}>\nstatic int helper() {{ return 2; }}\nint main() {{ return origin().x; }}",
+            dir.join("shapes.z").display(), dir.join("extra.z").display(), dir.join("./extra.z").display()
+        );
+        let (_, session) = compile_root(&src);
+        let warnings: Vec<String> = session.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].contains("function `helper` is never used"));
+        assert!(warnings[1].contains(&format!("nothing from `{}` is used", dir.join("extra.z").display())));
+        assert!(warnings[2].contains("field `y` of `Pt` is never read") && warnings[2].contains("shapes.z"));
+    }
+
+    #[test]
+    fn test_return_paths() {
+        let (_, session) = compile_root("class C { int n; int get() { if (self.n) return 1; } }
+int sign(int n) { if (n > 0) return 1; else if (n < 0) return -1; else panic(\"zero\"); }
+namespace app { int main() { } }
+int main() { return 0; exit(1); }");
+        let messages: Vec<String> = session.diagnostics.iter().map(|d| d.message.clone()).collect();
+        assert_eq!(messages, vec![
+            "`C::get` returns `int` but can reach the end of its body without a `return`",
+            "`app::main` returns `int` but can reach the end of its body without a `return`",
+            "code after the `return` in `main` is never run",
+        ]);
+
+        // The program's `main` gets C's `return 0;`
+        let (output, _) = compile_root("int main() { int x = 1; }");
+        assert!(output.contains("int __z_main() { int x = 1; return 0; }"), "{}", output);
+    }
+
+    #[test]
+    fn test_recursive_inline_functions() {
+        let (_, session) = compile_root("@inline int fact(int n) { return n ? n * fact(n - 1) : 1; }\n@inline int twice(int n) { return n * 2; }\nint main() { return fact(twice(2)); }");
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["inline function `fact` calls itself, so its calls can't all be expanded in place"]);
+        assert_eq!(session.call_graph.callees("main"), vec!["fact", "twice"]);
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let mut lints = crate::lints::Levels::default();
+        lints.set("shadowing", crate::lints::Level::Deny).unwrap();
+        let compiler = crate::Compiler::builder().options(crate::Options { lints, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        let source = "@allow(unreachable);\n@deny(shadows);\nint f(int n) { return n; n++; }\nint g(int n) { { int n = 2; } return n; }\n@allow(shadowing)\nint h(int n) { { int n = 2; } return n; }\nclass C { int x; @deny(format) void show() { printf(\"done\\n\", self.x); } }\n@allow(unused) @deny(unused) static int spare(void) { return 0; }\nint main() { C c = { 1 }; c.show(); return f(1) + g(1) + h(1) + c.x; }";
+        let output = compiler.compile_in(&mut session, "main.z", source);
+        assert!(!output.contains("shadows"));
+        let diagnostics: Vec<String> = session.diagnostics.iter().map(|d| d.to_string().lines().next().unwrap().to_string()).collect();
+        assert_eq!(diagnostics, vec![
+            "error: unknown lint `shadows`; the lints are `unused`, `unreachable`, `shadowing`, `incomplete_switch`, `format`, `recursive_inline`, `missing_header`, `unexported_inline`",
+            "error: `printf` is given 1 argument after its format but the format uses 0 [format]",
+            "error: local `n` in `g` shadows the parameter `n` [shadowing]",
+            "error: function `spare` is never used [unused]",
+        ]);
+    }
+
+    #[test]
+    fn test_strict_rejects_unrecognized_code() {
+        let source = "clas Point { int x; };\nclass P { int x; int y = 2; }\nnamespace geo { int area(int w, int h) { return w * h; } }\nint main() { P p = { 1 }; return p.x + geo::area(1, 2); }";
+        let (_, session) = compile_root(source);
+        assert!(!session.has_errors());
+
+        let compiler = crate::Compiler::builder().options(crate::Options { strict: true, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        compiler.compile_in(&mut session, "main.z", source);
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`clas Point { ... }` isn't a declaration, definition or directive the compiler recognizes, and would be passed to the C compiler as it is (`--strict`)",
+            "`int y = 2` in class `P` isn't a field the compiler recognizes, and would be left out of its struct (`--strict`)",
+        ]);
+    }
+
+    #[test]
+    fn test_untouched_lines_keep_their_whitespace() {
+        let compiler = crate::Compiler::builder().options(crate::Options { preserve_whitespace: true, keep_comments: true, ..crate::Options::default() }).build();
+        let output = compiler.compile("class V { int x; int get() { return self.x; } }\nint    table[2] = { 1,\n                   2 };  // aligned\nint main() {\n    V v = { 4 };\n    return   v.get() + table[1];\n}");
+        assert!(output.contains("\nint    table[2] = { 1,\n                   2 };  // aligned\n"));
+        assert!(output.contains("\n    V v = { 4 };\nreturn V_get(v) + table[1];\n"));
+    }
+
+    #[test]
+    fn test_older_edition_keeps_later_keywords_as_names() {
+        let compiler = crate::Compiler::builder().options(crate::Options { edition: crate::Edition::E2024, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        let output = compiler.compile_in(&mut session, "main.z", "class Parser { int rc; int match(int try) { return self.rc + try; } }
+int main() {
+    Parser p = { 1 };
+    int panic = p.match(2);
+    return panic;
+}");
+        assert!(!session.has_errors(), "{:?}", session.diagnostics);
+        assert!(output.contains("int Parser_match(Parser self, int try) { return self.rc + try; }"), "{}", output);
+        assert!(output.contains("int panic = Parser_match(p, 2);"), "{}", output);
+    }
+
+    #[test]
+    fn test_cfg_excluded_declarations_are_not_registered() {
+        let cfg = crate::cfg::Cfg { os: "linux".to_string(), family: "unix".to_string(), arch: "x86_64".to_string(), features: ["fast".to_string()].into() };
+        let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, cfg, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        let output = compiler.compile_in(&mut session, "lib.z", "@cfg(os = \"windows\")\nclass Handle { int h; }\nnamespace sys {\n  @cfg(os = \"windows\") int page = 4096;\n  @cfg(feature = \"fast\") int page = 16384;\n}\nint size() { return sys::page; }");
+        assert!(!session.has_errors(), "{:?}", session.diagnostics);
+        assert!(!session.known_classes.contains("Handle"));
+        assert!(!output.contains("Handle") && output.contains("int sys_page = 16384;"), "{}", output);
+        assert_eq!(session.namespaces.lookup("sys", "page").map(|m| m.c_name.as_str()), Some("sys_page"));
+    }
+
+    #[test]
+    fn test_optional_std_modules_need_a_feature() {
+        let std_modules = Some(["std/thread.z".to_string()].into());
+        let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, std_modules, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        compiler.compile_in(&mut session, "lib.z", "@allow(unused) #import <std/thread.z>\n#import <std/net.z>\n");
+        let errors: Vec<String> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.to_string()).collect();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`std/net.z` is optional; enable it with a feature that lists \"std/net\" in tarnish.toml"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_imports_stop_at_the_limits() {
+        let sources = |session: &mut crate::Session| {
+            session.sources.insert("a.z".to_string(), "#import <b.z>\n@allow(unused) int a() { return b(); }\n".to_string());
+            session.sources.insert("b.z".to_string(), "@allow(unused) #import <c.z>\nint b() { return 2; }\n".to_string());
+            session.sources.insert("c.z".to_string(), "int c() { return 3; }\n".to_string());
+        };
+        let compile = |limits: crate::limits::Limits| {
+            let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, limits, ..crate::Options::default() }).build();
+            let mut session = crate::Session::default();
+            sources(&mut session);
+            let c_code = compiler.compile_in(&mut session, "lib.z", "@allow(unused) #import <a.z>\n");
+            let errors: Vec<String> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.to_string()).collect();
+            (c_code, errors)
+        };
+        let (c_code, errors) = compile(crate::limits::Limits::default());
+        assert!(errors.is_empty() && c_code.contains("int c()"), "{:?}", errors);
+
+        let (c_code, errors) = compile(crate::limits::Limits { max_import_depth: 2, ..Default::default() });
+        assert_eq!(errors, vec!["error: importing `c.z` nests imports 3 deep, more than `max-import-depth` allows (2); raise it in [limits] of tarnish.toml\n  --> b.z"]);
+        assert!(c_code.contains("int b()") && !c_code.contains("int c()"), "{}", c_code);
+
+        let (_, errors) = compile(crate::limits::Limits { max_expansion_size: 40, ..Default::default() });
+        assert!(errors.len() == 1 && errors[0].contains("expands the imports to"), "{:?}", errors);
+        let (_, errors) = compile(crate::limits::Limits { max_file_size: 30, ..Default::default() });
+        assert_eq!(errors, vec!["error: `a.z` is 53 bytes, more than `max-file-size` allows (30); raise it in [limits] of tarnish.toml\n  --> a.z"]);
+
+        // Brackets thousands deep are an error, not a stack overflow
+        let nested = format!("int f() {{ return {}1{}; }}", "(".repeat(20000), ")".repeat(20000));
+        let mut session = crate::Session::default();
+        crate::Compiler::builder().options(crate::Options { entry_point: false, ..crate::Options::default() }).build().compile_in(&mut session, "main.z", &nested);
+        let messages: Vec<&str> = session.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["brackets in `main.z` nest 20001 deep, more than `max-nesting-depth` allows (256); raise it in [limits] of tarnish.toml"]);
+    }
+
+    #[test]
+    fn test_only_z_files_are_imported() {
+        let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        session.sources.insert("blob.z".to_string(), "int x;\0\u{1}".to_string());
+        let c_code = compiler.compile_in(&mut session, "lib.z", "#import <vec.h>\n#import <vec.c>\n#import <notes.txt>\n#import <blob.z>\n#import <missing.z>\nint f() { return 0; }\n");
+        let errors: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(errors[..4], [
+            "`vec.h` is a C header, not a Z file; import it as `#import <c:vec.h>` to check calls into it, or `#include \"vec.h\"` it",
+            "`vec.c` is C source, not a Z file; name it on the command line to build it with the program",
+            "`notes.txt` is not a Z file; `#import` takes `.z` files, `.zi` interfaces and C headers as `<c:header.h>`",
+            "`blob.z` holds binary data, not Z source",
+        ]);
+        assert!(errors[4].starts_with("cannot read `missing.z`: "), "{:?}", errors);
+        assert!(c_code.contains("int f()") && !c_code.contains("vec"), "{}", c_code);
+    }
+
+    #[test]
+    fn test_misspelled_paths_get_suggestions() {
+        let mut session = crate::Session::default();
+        session.sources.insert("math.z".to_string(), "namespace math {\n  class Vector { int x; }\n\n  int area(int w, int h) { return w * h; }\n}\n".to_string());
+        crate::Compiler::new().compile_in(&mut session, "main.z", "#import <math.z>\nenum Color { Red };\nint main() {\n  math::vector v;\n  int a = math::aera(1, 2) + math::area(3, 4);\n  std::fs::read_to_string(\"a.txt\");\n  return Color::Red + other::thing();\n}\n");
+        let messages: Vec<&str> = session.diagnostics.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`math::vector` is not declared; did you mean `math::Vector` (declared in math.z:2)?",
+            "`math::aera` is not declared; did you mean `math::area` (declared in math.z:4)?",
+            "`std::fs::read_to_string` is not declared here; it is declared in std/fs.z:16, add `#import <std/fs.z>`",
+        ]);
+    }
+
+    #[test]
+    fn test_link_directives_reach_the_session() {
+        let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        compiler.compile_in(&mut session, "lib.z", "#link \"sqlite3\"\n@link(\"z\", \"sqlite3\") int open_db(void);\n@cfg(os = \"nowhere\") #link \"missing\"\n");
+        assert!(!session.has_errors(), "{:?}", session.diagnostics);
+        assert_eq!(session.links, vec![crate::links::Link::Library("sqlite3".to_string()), crate::links::Link::Library("z".to_string())]);
+    }
+
+    #[test]
+    fn test_exported_functions_keep_their_names() {
+        let compiler = crate::Compiler::builder().options(crate::Options { entry_point: false, ..crate::Options::default() }).build();
+        let mut session = crate::Session::default();
+        let c_code = compiler.compile_in(&mut session, "lib.z", "namespace geo {\n@export int area(int w, int h) { return w * h; }\n@no_mangle int sq(int n) { return area(n, n); }\nint cube(int n) { return sq(n) * n; }\n}\nint total(int n) { return geo::sq(n) + geo::cube(n); }\n");
+        assert!(!session.has_errors(), "{:?}", session.diagnostics);
+        assert!(c_code.contains("__attribute__((visibility(\"default\"))) int area(int w, int h)"), "{}", c_code);
+        assert!(c_code.contains("int sq(int n) { return area(n, n); }"), "{}", c_code);
+        assert!(c_code.contains("int geo_cube(int n) { return sq(n) * n; }"), "{}", c_code);
+        assert!(c_code.contains("return sq(n) + geo_cube(n);"), "{}", c_code);
+        assert_eq!(session.exports, vec!["area".to_string()]);
+
+        let mut session = crate::Session::default();
+        compiler.compile_in(&mut session, "lib.z", "class Point {\n  int x;\n  @export int get() { return self.x; }\n}\n");
+        assert!(session.diagnostics.iter().any(|d| d.to_string().contains("`@export` applies to free functions, not methods or operators")), "{:?}", session.diagnostics);
+    }
+}
@@ -0,0 +1,68 @@
+// src/plugin.rs
+
+use crate::ast::Module;
+use crate::compiler::{Context, Pass, Session};
+
+/// Extension point for user code generation. A plugin sees each file's module
+/// after lowering and right before emission, and may add or change items,
+/// e.g. generate serializers or logging wrappers for every class.
+///
+/// Register plugins with `CompilerBuilder::plugin`; they run in registration order.
+pub trait CodegenPlugin {
+    fn name(&self) -> &str;
+
+    fn transform(&self, module: &mut Module, session: &Session);
+}
+
+/// Runs the registered codegen plugins
+pub(crate) struct RunPlugins;
+
+impl Pass for RunPlugins {
+    fn name(&self) -> &str {
+        "codegen-plugins"
+    }
+
+    fn run(&self, cx: &mut Context<'_>) {
+        for plugin in cx.plugins() {
+            plugin.transform(&mut cx.module, cx.session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Class;
+    use crate::visit::Visit;
+    use crate::Compiler;
+
+    struct SizeFunctions;
+
+    impl CodegenPlugin for SizeFunctions {
+        fn name(&self) -> &str {
+            "size-functions"
+        }
+
+        fn transform(&self, module: &mut Module, _session: &Session) {
+            struct Classes(Vec<String>);
+            impl Visit for Classes {
+                fn visit_class(&mut self, class: &Class) {
+                    self.0.push(class.full_name());
+                }
+            }
+
+            let mut classes = Classes(Vec::new());
+            classes.visit_module(module);
+            for class in classes.0 {
+                module.push_c(&format!("int {0}_size() {{ return sizeof({0}); }}\n", class));
+            }
+        }
+    }
+
+    #[test]
+    fn test_plugin_injects_functions() {
+        let compiler = Compiler::builder().plugin(SizeFunctions).build();
+        let output = compiler.compile("namespace geo { class Point { int x; } }");
+        assert!(output.contains("int geo_Point_size() { return sizeof(geo_Point); }"));
+    }
+}
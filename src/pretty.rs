@@ -0,0 +1,207 @@
+// src/pretty.rs
+//
+// Pretty-printers that show classes in a debugger the way they are written
+// in Z. Without them GDB and LLDB show the generated C: an `rc class` value
+// is a handle with a `ptr` to a block holding a reference count, and a
+// checked union hides its members behind a tag. With them a value shows
+// its fields by name, the internals left out, and a class with a
+// `char* to_string()` method is summed up by what that method returns.
+//
+// The driver writes them next to a program built with `-g`: GDB loads
+// `<program>-gdb.py` by itself when its auto-load path allows it, and LLDB
+// takes `command script import <program>.lldb.py`.
+
+use std::fmt::Write;
+
+use crate::ast::Class;
+
+/// What the debugger scripts know of a class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Printer {
+    /// Name of the C type, e.g. `geo_Point`
+    pub c_name: String,
+    /// Name of the class as written, e.g. `Point`
+    pub name: String,
+    pub rc: bool,
+    /// C function of a `to_string` method returning a C string
+    pub to_string: Option<String>,
+}
+
+impl Printer {
+    pub fn of(class: &Class) -> Printer {
+        let to_string = class.functions.iter().find(|f| {
+            let returns_string = f.return_type.contains("char") && f.return_type.trim_end().ends_with('*');
+            f.name == "to_string" && f.params.is_empty() && returns_string
+        });
+        Printer {
+            c_name: class.full_name(),
+            name: class.name.clone(),
+            rc: class.rc,
+            to_string: to_string.map(|f| format!("{}_{}", class.full_name(), f.name)),
+        }
+    }
+}
+
+/// A GDB script registering a printer for each of `printers`
+pub fn gdb_script(program: &str, printers: &[Printer]) -> String {
+    script(program, printers, GDB)
+}
+
+/// An LLDB script adding a summary and the fields for each of `printers`
+pub fn lldb_script(program: &str, printers: &[Printer]) -> String {
+    script(program, printers, LLDB)
+}
+
+fn script(program: &str, printers: &[Printer], body: &str) -> String {
+    let mut out = format!("# Debugger pretty-printers for the classes of `{}`, generated by the Tarnish compiler. Do not edit.\n\n", program);
+    out.push_str("# C type: (class name, rc class, to_string function)\nCLASSES = {\n");
+    for printer in printers {
+        let to_string = printer.to_string.as_ref().map_or("None".to_string(), |f| format!("{:?}", f));
+        let rc = if printer.rc { "True" } else { "False" };
+        writeln!(out, "    {:?}: ({:?}, {}, {}),", printer.c_name, printer.name, rc, to_string).unwrap();
+    }
+    out.push_str("}\n");
+    out.push_str(body);
+    out
+}
+
+// Fields whose names start with `__z_` are the compiler's: the reference
+// count of an rc block and the tag of a checked union
+const GDB: &str = r#"
+import gdb
+import gdb.printing
+
+
+def _fields(value):
+    for field in value.type.strip_typedefs().fields():
+        if not field.name:
+            # The union of a checked union
+            yield from _fields(value[field])
+        elif not field.name.startswith("__z_"):
+            yield field.name, value[field.name]
+
+
+class ClassPrinter:
+    def __init__(self, value, name, rc, to_string):
+        self.value = value
+        self.name = name
+        self.rc = rc
+        self.to_string_function = to_string
+
+    def _block(self):
+        pointer = self.value["ptr"]
+        return None if int(pointer) == 0 else pointer.dereference()
+
+    def to_string(self):
+        if self.rc and self._block() is None:
+            return self.name + " (null)"
+        if self.to_string_function and self.value.address is not None:
+            try:
+                call = "%s(*(%s*)%d)" % (self.to_string_function, self.value.type.unqualified().name, int(self.value.address))
+                return gdb.parse_and_eval(call).string()
+            except gdb.error:
+                pass
+        if self.rc:
+            return "%s (refs %d)" % (self.name, int(self._block()["__z_refs"]))
+        return self.name
+
+    def children(self):
+        value = self._block() if self.rc else self.value
+        if value is not None:
+            yield from _fields(value)
+
+
+def lookup(value):
+    entry = CLASSES.get(value.type.unqualified().name)
+    return ClassPrinter(value, *entry) if entry else None
+
+
+gdb.printing.register_pretty_printer(gdb.current_objfile(), lookup)
+"#;
+
+const LLDB: &str = r#"
+import lldb
+
+
+def _fields(value):
+    for i in range(value.GetNumChildren()):
+        child = value.GetChildAtIndex(i)
+        name = child.GetName()
+        if not name:
+            yield from _fields(child)
+        elif not name.startswith("__z_"):
+            yield child
+
+
+def _entry(value):
+    return CLASSES.get(value.GetType().GetUnqualifiedType().GetName())
+
+
+def _block(value):
+    pointer = value.GetChildMemberWithName("ptr")
+    return None if pointer.GetValueAsUnsigned() == 0 else pointer.Dereference()
+
+
+class ClassProvider:
+    def __init__(self, value, internal_dict):
+        self.value = value
+        self.children = []
+
+    def update(self):
+        value = _block(self.value) if _entry(self.value)[1] else self.value
+        self.children = list(_fields(value)) if value is not None else []
+        return False
+
+    def num_children(self):
+        return len(self.children)
+
+    def get_child_index(self, name):
+        names = [child.GetName() for child in self.children]
+        return names.index(name) if name in names else -1
+
+    def get_child_at_index(self, index):
+        return self.children[index] if 0 <= index < len(self.children) else None
+
+    def has_children(self):
+        return True
+
+
+def summary(value, internal_dict):
+    value = value.GetNonSyntheticValue()
+    name, rc, to_string = _entry(value)
+    if rc and _block(value) is None:
+        return name + " (null)"
+    address = value.GetLoadAddress()
+    if to_string and address != lldb.LLDB_INVALID_ADDRESS:
+        type_name = value.GetType().GetUnqualifiedType().GetName()
+        result = value.GetFrame().EvaluateExpression("%s(*(%s*)%d)" % (to_string, type_name, address))
+        if result.GetError().Success():
+            return result.GetSummary()
+    if rc:
+        return "%s (refs %d)" % (name, _block(value).GetChildMemberWithName("__z_refs").GetValueAsSigned())
+    return name
+
+
+def __lldb_init_module(debugger, internal_dict):
+    for c_name in CLASSES:
+        debugger.HandleCommand('type summary add -F %s.summary "%s"' % (__name__, c_name))
+        debugger.HandleCommand('type synthetic add -l %s.ClassProvider "%s"' % (__name__, c_name))
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_list_the_classes() {
+        let printers = vec![
+            Printer { c_name: "geo_Point".to_string(), name: "Point".to_string(), rc: false, to_string: Some("geo_Point_to_string".to_string()) },
+            Printer { c_name: "Node".to_string(), name: "Node".to_string(), rc: true, to_string: None },
+        ];
+        let gdb = gdb_script("prog", &printers);
+        assert!(gdb.starts_with("# Debugger pretty-printers for the classes of `prog`"));
+        assert!(gdb.contains("CLASSES = {\n    \"geo_Point\": (\"Point\", False, \"geo_Point_to_string\"),\n    \"Node\": (\"Node\", True, None),\n}\n"), "{}", gdb);
+        assert!(gdb.contains("gdb.printing.register_pretty_printer"));
+        assert!(lldb_script("prog", &printers).contains("def __lldb_init_module(debugger, internal_dict):"));
+    }
+}
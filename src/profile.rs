@@ -0,0 +1,226 @@
+// src/profile.rs
+//
+// Build profiles, chosen with `--profile <name>`. A profile sets the C
+// compiler's optimization, debug info and link-time optimization, and the
+// runtime checks of the Z code, so a debug build and a release build are
+// one flag apart instead of a list of them:
+//
+//     [profile.release-lto]
+//     inherits = "release"
+//     opt-level = 3
+//     lto = true
+//     flags = ["-march=native"]
+//
+// `dev`, `release`, `release-lto` and `release-pgo` are built in, and a
+// `[profile.<name>]` table in tarnish.toml changes one of them or, with
+// `inherits`, defines a new one. A build without `--profile` uses none, and
+// only the flags it is given.
+//
+// A profile with `pgo = true` is built in two steps. The first build is
+// instrumented, and running it records where the program spends its time
+// in the build directory; the next build with the profile optimizes for
+// what was recorded. `z-lang clean` throws the recording away.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Manifest, Value, FILE};
+
+/// The profiles there are without a manifest
+pub const BUILT_IN: &[&str] = &["dev", "release", "release-lto", "release-pgo"];
+
+/// The keys a `[profile.<name>]` table may have
+const KEYS: &[&str] = &["inherits", "opt-level", "debug", "lto", "pgo", "flags", "bounds-check", "overflow-checks", "union-checks", "panic-trace", "crash-report"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// `-O0` to `-O3`, `-Os` or `-Oz`
+    pub opt_level: Option<String>,
+    pub debug: bool,
+    pub lto: bool,
+    pub pgo: bool,
+    /// More flags for the C compiler
+    pub flags: Vec<String>,
+    pub bounds_check: bool,
+    pub overflow_checks: bool,
+    pub union_checks: bool,
+    pub panic_trace: bool,
+    pub crash_report: bool,
+}
+
+/// Which step of profile-guided optimization a build is
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pgo {
+    /// Builds the program with instrumentation that records its profile
+    /// into the directory
+    Generate(PathBuf),
+    /// Optimizes with the profile recorded in the directory
+    Use(PathBuf),
+}
+
+impl Profile {
+    /// The profile `name`: a built-in one, changed by its table in
+    /// `manifest`, or one the manifest defines
+    pub fn named(name: &str, manifest: &Manifest) -> Result<Profile, String> {
+        Profile::resolve(name, manifest, &mut Vec::new())
+    }
+
+    // `seen` holds the profiles inheriting from this one, to catch cycles
+    fn resolve(name: &str, manifest: &Manifest, seen: &mut Vec<String>) -> Result<Profile, String> {
+        if seen.iter().any(|s| s == name) {
+            return Err(format!("{}: profile `{}` inherits from itself", FILE, name));
+        }
+        seen.push(name.to_string());
+        let table = manifest.table(&format!("profile.{}", name));
+        let inherits = match table.and_then(|t| t.get("inherits")) {
+            Some(value) => Some(value.as_str().ok_or_else(|| format!("{}: `inherits` of profile `{}` must be a profile name", FILE, name))?),
+            None => None,
+        };
+        if table.is_none() && !BUILT_IN.contains(&name) {
+            return Err(format!("unknown profile `{}`; the profiles are {}", name, names(manifest).join(", ")));
+        }
+        let mut profile = match inherits.or(built_in_parent(name)) {
+            Some(parent) => Profile::resolve(parent, manifest, seen)?,
+            None => Profile::default(),
+        };
+        built_in(name, &mut profile);
+        profile.name = name.to_string();
+        for (key, value) in table.into_iter().flatten() {
+            profile.set(key, value)?;
+        }
+        Ok(profile)
+    }
+
+    fn set(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        let error = |expected: &str| format!("{}: `{}` of profile `{}` must be {}", FILE, key, self.name, expected);
+        let flag = |value: &Value| match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(error("`true` or `false`")),
+        };
+        match key {
+            "inherits" => {}
+            "opt-level" => {
+                let level = match value {
+                    Value::Integer(n @ 0..=3) => n.to_string(),
+                    Value::String(s) if s == "s" || s == "z" => s.clone(),
+                    _ => return Err(error("0, 1, 2, 3, \"s\" or \"z\"")),
+                };
+                self.opt_level = Some(format!("-O{}", level));
+            }
+            "debug" => self.debug = flag(value)?,
+            "lto" => self.lto = flag(value)?,
+            "pgo" => self.pgo = flag(value)?,
+            "bounds-check" => self.bounds_check = flag(value)?,
+            "overflow-checks" => self.overflow_checks = flag(value)?,
+            "union-checks" => self.union_checks = flag(value)?,
+            "panic-trace" => self.panic_trace = flag(value)?,
+            "crash-report" => self.crash_report = flag(value)?,
+            "flags" => {
+                let Value::Array(items) = value else { return Err(error("an array of flags")) };
+                self.flags = items.iter().map(|item| item.as_str().map(str::to_string).ok_or_else(|| error("an array of flags"))).collect::<Result<_, _>>()?;
+            }
+            _ => return Err(format!("{}: unknown key `{}` in profile `{}`; the keys are {}", FILE, key, self.name, KEYS.join(", "))),
+        }
+        Ok(())
+    }
+
+    /// The C compiler flags of the profile, PGO's excepted
+    pub fn c_flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self.opt_level.iter().cloned().collect();
+        if self.debug {
+            flags.push("-g".to_string());
+        }
+        if self.lto {
+            flags.push("-flto".to_string());
+        }
+        flags.extend(self.flags.iter().cloned());
+        flags
+    }
+
+    /// The PGO step of a build whose recordings go to `dir`: `Use` once a
+    /// run of the instrumented build has written to it
+    pub fn pgo(&self, dir: &Path) -> Option<Pgo> {
+        if !self.pgo {
+            return None;
+        }
+        let recorded = fs::read_dir(dir).is_ok_and(|entries| {
+            entries.flatten().any(|entry| entry.path().extension().is_some_and(|ext| ext == "gcda" || ext == "profraw"))
+        });
+        Some(if recorded { Pgo::Use(dir.to_path_buf()) } else { Pgo::Generate(dir.to_path_buf()) })
+    }
+}
+
+// The profile a built-in one starts from, so `release-lto` is `release`
+// with LTO, however the manifest changes `release`
+fn built_in_parent(name: &str) -> Option<&'static str> {
+    match name {
+        "release-lto" => Some("release"),
+        "release-pgo" => Some("release-lto"),
+        _ => None,
+    }
+}
+
+// What the built-in profile `name` sets on top of its parent
+fn built_in(name: &str, profile: &mut Profile) {
+    match name {
+        "dev" => {
+            profile.opt_level = Some("-O0".to_string());
+            profile.debug = true;
+            profile.bounds_check = true;
+            profile.overflow_checks = true;
+        }
+        "release" => profile.opt_level = Some("-O2".to_string()),
+        "release-lto" => profile.lto = true,
+        "release-pgo" => profile.pgo = true,
+        _ => {}
+    }
+}
+
+// The built-in profiles and the manifest's, quoted
+fn names(manifest: &Manifest) -> Vec<String> {
+    let mut names: Vec<&str> = BUILT_IN.to_vec();
+    for table in manifest.table_names() {
+        if let Some(name) = table.strip_prefix("profile.") {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.iter().map(|n| format!("`{}`", n)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_are_resolved() {
+        let manifest = Manifest::parse("[profile.release]\nopt-level = 3\n\n[profile.fast]\ninherits = \"release-lto\"\nflags = [\"-march=native\"]\nbounds-check = true").unwrap();
+        let fast = Profile::named("fast", &manifest).unwrap();
+        assert_eq!(fast.c_flags(), vec!["-O3", "-flto", "-march=native"]);
+        assert!(fast.bounds_check && !fast.overflow_checks && !fast.pgo);
+        let dev = Profile::named("dev", &manifest).unwrap();
+        assert_eq!(dev.c_flags(), vec!["-O0", "-g"]);
+        assert!(dev.bounds_check && dev.overflow_checks);
+
+        assert_eq!(Profile::named("small", &manifest), Err("unknown profile `small`; the profiles are `dev`, `release`, `release-lto`, `release-pgo`, `fast`".to_string()));
+        let cycle = Manifest::parse("[profile.a]\ninherits = \"b\"\n[profile.b]\ninherits = \"a\"").unwrap();
+        assert_eq!(Profile::named("a", &cycle), Err("tarnish.toml: profile `a` inherits from itself".to_string()));
+        let bad = Manifest::parse("[profile.dev]\nopt-level = 5\n[profile.x]\nlto = 1\nspeed = 3").unwrap();
+        assert_eq!(Profile::named("dev", &bad), Err("tarnish.toml: `opt-level` of profile `dev` must be 0, 1, 2, 3, \"s\" or \"z\"".to_string()));
+        assert!(Profile::named("x", &bad).unwrap_err().contains("`lto` of profile `x` must be `true` or `false`"));
+    }
+
+    #[test]
+    fn test_pgo_uses_a_recorded_profile() {
+        let dir = std::env::temp_dir().join(format!("tarnish_pgo_{}", std::process::id()));
+        let profile = Profile::named("release-pgo", &Manifest::default()).unwrap();
+        assert_eq!(profile.pgo(&dir), Some(Pgo::Generate(dir.clone())));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("#tmp#target#main.gcda"), "").unwrap();
+        assert_eq!(profile.pgo(&dir), Some(Pgo::Use(dir.clone())));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Profile::named("release", &Manifest::default()).unwrap().pgo(&dir), None);
+    }
+}
@@ -0,0 +1,132 @@
+// src/provenance.rs
+//
+// What generated a C file: the compiler's version, a hash of every Z file
+// and interface that went into it, and the options that change the C. The
+// driver writes it at the top of the generated C as a comment, one
+// `key: value` per line, so a build system can tell whether an output is
+// still current without compiling again:
+//
+//     /*
+//      * Generated by the Tarnish compiler (z-lang 0.1.0); do not edit.
+//      * options: edition=2025 bounds-check target=x86_64-linux
+//      * source: main.z fnv1a64:4b6d0c0e97d2f3a1
+//      * directory: /home/me/app
+//      * generated: 1791200000
+//      */
+//
+// `directory` and `generated` (seconds since the Unix epoch) differ from
+// one checkout and one run to the next; a reproducible banner leaves them
+// out, so the same sources and options give the same bytes anywhere.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::compiler::{Options, Session};
+
+/// What went into one compilation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// Version of the compiler, e.g. `0.1.0`
+    pub version: String,
+    /// FNV-1a hash of every file compiled, by path, in order of the paths
+    pub sources: Vec<(String, u64)>,
+    /// The options that change the generated C, e.g. `bounds-check`
+    pub options: Vec<String>,
+    /// The working directory; `None` when reproducible
+    pub directory: Option<PathBuf>,
+    /// When it was compiled, in seconds since the Unix epoch; `None` when
+    /// reproducible
+    pub generated: Option<u64>,
+}
+
+impl Provenance {
+    /// The provenance of what `session` compiled with `options`. A
+    /// reproducible one leaves out what differs between machines and runs
+    pub fn of(session: &Session, options: &Options, reproducible: bool) -> Provenance {
+        Provenance {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            sources: session.source_hashes.iter().map(|(path, hash)| (path.clone(), *hash)).collect(),
+            options: describe(options),
+            directory: if reproducible { None } else { std::env::current_dir().ok() },
+            generated: if reproducible { None } else { SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()) },
+        }
+    }
+
+    /// The comment heading the generated C
+    pub fn banner(&self) -> String {
+        let mut banner = format!("/*\n * Generated by the Tarnish compiler (z-lang {}); do not edit.\n", self.version);
+        let _ = writeln!(banner, " * options: {}", self.options.join(" "));
+        for (path, hash) in &self.sources {
+            let _ = writeln!(banner, " * source: {} fnv1a64:{:016x}", path, hash);
+        }
+        if let Some(directory) = &self.directory {
+            let _ = writeln!(banner, " * directory: {}", directory.display());
+        }
+        if let Some(generated) = self.generated {
+            let _ = writeln!(banner, " * generated: {}", generated);
+        }
+        banner.push_str(" */\n");
+        banner
+    }
+}
+
+/// FNV-1a, 64 bits, of `text`
+pub fn hash(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// The options that change the generated C, as the command line writes
+/// them; the rest go without saying
+pub fn describe(options: &Options) -> Vec<String> {
+    let mut described = vec![format!("edition={}", options.edition)];
+    if !options.entry_point {
+        described.push("no-entry-point".to_string());
+    }
+    let flags = [
+        (options.bounds_check, "bounds-check"),
+        (options.overflow_checks, "overflow-checks"),
+        (options.union_checks, "union-checks"),
+        (options.panic_trace, "panic-trace"),
+        (options.crash_report, "crash-report"),
+        (options.preserve_whitespace, "preserve-whitespace"),
+        (options.keep_comments, "keep-comments"),
+    ];
+    described.extend(flags.iter().filter(|(on, _)| *on).map(|(_, flag)| flag.to_string()));
+    described.push(format!("target={}", options.cfg.target()));
+    if !options.cfg.features.is_empty() {
+        described.push(format!("features={}", options.cfg.features.iter().cloned().collect::<Vec<_>>().join(",")));
+    }
+    if let Some(modules) = &options.std_modules {
+        described.push(format!("std-modules={}", modules.iter().cloned().collect::<Vec<_>>().join(",")));
+    }
+    described
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner_describes_the_compilation() {
+        let mut session = Session::default();
+        let options = Options { bounds_check: true, ..Options::default() };
+        crate::Compiler::builder().options(options.clone()).build().compile_in(&mut session, "main.z", "#import <std/result.z>\nint main() { return 0; }");
+        let provenance = Provenance::of(&session, &options, true);
+        assert_eq!(provenance.sources.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>(), vec!["main.z", "std/result.z"]);
+        assert_eq!(provenance.sources[0].1, hash("#import <std/result.z>\nint main() { return 0; }"));
+        assert_eq!(&provenance.options[..2], ["edition=2025", "bounds-check"]);
+
+        let banner = provenance.banner();
+        assert!(banner.starts_with("/*\n * Generated by the Tarnish compiler (z-lang "));
+        assert!(banner.contains(&format!(" * source: main.z fnv1a64:{:016x}\n", provenance.sources[0].1)));
+        assert!(!banner.contains("directory:") && !banner.contains("generated:"));
+        assert_eq!(banner, Provenance::of(&session, &options, true).banner());
+        assert!(Provenance::of(&session, &options, false).banner().contains(" * generated: "));
+    }
+}
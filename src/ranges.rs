@@ -0,0 +1,241 @@
+// src/ranges.rs
+//
+// Ranges: `a..b` runs from `a` up to (not including) `b`, `a..=b` up to and
+// including `b`. `for (int i : a..b)` becomes a plain index loop; anywhere
+// else a range is a `range` value, a struct of `start` and `end` (always
+// exclusive) that can be stored and passed around. Like slices this runs on
+// the token stream before parsing, after slices have taken the ranges
+// inside slice indexes.
+
+use std::collections::HashSet;
+
+use crate::parser::code;
+use crate::slices::range_operator;
+use crate::tokenizer::{generated, Token};
+
+const RUNTIME: &str = "#ifndef __Z_RANGE
+#define __Z_RANGE
+typedef struct { long start; long end; } __z_range;
+#endif
+";
+
+/// Rewrites range loops, range values and the `range` type
+pub fn rewrite(tokens: &[Token]) -> Vec<Token> {
+    let names = range_names(tokens);
+    if names.is_empty() && !tokens.iter().any(|t| matches!(t, Token::Symbol(s) if s == ".." || s == "..=")) {
+        return tokens.to_vec();
+    }
+    let mut out = rewrite_tokens(tokens, &names, &mut 0);
+    out.splice(0..0, generated(RUNTIME, 1));
+    out
+}
+
+fn rewrite_tokens(tokens: &[Token], names: &HashSet<String>, next_id: &mut usize) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Identifier(word) if word == "for" => {
+                if let Some((header, end)) = range_loop(tokens, i, names, next_id) {
+                    out.extend(header);
+                    i = end;
+                    continue;
+                }
+            }
+            Token::Identifier(word) if word == "range" && matches!(tokens.get(i + 1), Some(Token::Identifier(_))) => {
+                out.push(Token::Identifier("__z_range".to_string()));
+                i += 1;
+                continue;
+            }
+            Token::Symbol(op) if op == ".." || op == "..=" => {
+                let start = operand_start(&out);
+                let end = operand_end(tokens, i + 1);
+                let low: Vec<Token> = out.drain(start..).collect();
+                let high = rewrite_tokens(&tokens[i + 1..end], names, next_id);
+                out.extend(value(&low, op, &high));
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+// `for (T x : a..b)` or `for (T x : r)` with `r` a range -> the header of
+// an index loop over it. Returns the header and the index after its `)`.
+fn range_loop(tokens: &[Token], for_index: usize, names: &HashSet<String>, next_id: &mut usize) -> Option<(Vec<Token>, usize)> {
+    let open = next_code(tokens, for_index + 1)?;
+    if !matches!(&tokens[open], Token::Symbol(s) if s == "(") {
+        return None;
+    }
+    let close = matching_close(tokens, open)?;
+    let inner = &tokens[open + 1..close];
+    let colon = top_level(inner, ":")?;
+    if top_level(inner, ";").is_some() {
+        return None;
+    }
+    let binding = &inner[..colon];
+    let Some(Token::Identifier(name)) = binding.iter().rev().find(|t| !matches!(t, Token::Newline | Token::Comment(_))) else { return None };
+    let iterable: Vec<Token> = inner[colon + 1..].iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned().collect();
+
+    let (low, high) = match (range_operator(&iterable), iterable.as_slice()) {
+        (Some(op), _) => {
+            let low = if op == 0 { code("0") } else { iterable[..op].to_vec() };
+            let high = rewrite_tokens(&iterable[op + 1..], names, next_id);
+            (low, bound(&iterable[op], &high))
+        }
+        (None, [Token::Identifier(range)]) if names.contains(range) => (code(&format!("{}.start", range)), code(&format!("{}.end", range))),
+        _ => return None,
+    };
+
+    let id = *next_id;
+    *next_id += 1;
+    let mut header = code("for (");
+    header.extend(binding.iter().cloned());
+    header.push(Token::Symbol("=".to_string()));
+    header.extend(low);
+    header.extend(code(&format!(", __z_end_{} =", id)));
+    header.extend(high);
+    header.extend(code(&format!("; {name} < __z_end_{id}; {name}++)", name = name, id = id)));
+    Some((header, close + 1))
+}
+
+// `a..b` -> `((__z_range){ (a), (b) })`, with `a` defaulting to 0
+fn value(low: &[Token], op: &str, high: &[Token]) -> Vec<Token> {
+    let mut out = code("((__z_range){ (");
+    if low.is_empty() {
+        out.extend(code("0"));
+    } else {
+        out.extend(low.iter().cloned());
+    }
+    out.extend(code("),"));
+    out.extend(bound(&Token::Symbol(op.to_string()), high));
+    out.extend(code("})"));
+    out
+}
+
+// The exclusive end of a range: `(b)`, or `(b) + 1` after `..=`
+fn bound(op: &Token, high: &[Token]) -> Vec<Token> {
+    let mut out = code("(");
+    out.extend(high.iter().cloned());
+    out.push(Token::Symbol(")".to_string()));
+    if matches!(op, Token::Symbol(s) if s == "..=") {
+        out.extend(code("+ 1"));
+    }
+    out
+}
+
+// A range binds looser than every operator but assignment: its operands run
+// to the enclosing bracket, `,`, `;`, `=` or `return`
+fn operand_start(out: &[Token]) -> usize {
+    let mut depth = 0;
+    let mut start = out.len();
+    while start > 0 {
+        match &out[start - 1] {
+            Token::Symbol(s) if s == ")" || s == "]" => depth += 1,
+            Token::Symbol(s) if (s == "(" || s == "[") && depth > 0 => depth -= 1,
+            Token::Symbol(s) if depth == 0 && is_boundary(s) => break,
+            Token::Identifier(word) if depth == 0 && word == "return" => break,
+            Token::Newline if depth == 0 => break,
+            _ => {}
+        }
+        start -= 1;
+    }
+    start
+}
+
+fn operand_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0;
+    let mut end = start;
+    while end < tokens.len() {
+        match &tokens[end] {
+            Token::Symbol(s) if s == "(" || s == "[" => depth += 1,
+            Token::Symbol(s) if (s == ")" || s == "]") && depth > 0 => depth -= 1,
+            Token::Symbol(s) if depth == 0 && is_boundary(s) => break,
+            Token::Newline if depth == 0 => break,
+            _ => {}
+        }
+        end += 1;
+    }
+    end
+}
+
+fn is_boundary(symbol: &str) -> bool {
+    matches!(symbol, "(" | ")" | "[" | "]" | "{" | "}" | "," | ";" | "=" | "?" | ":")
+}
+
+// Names declared as `range name` anywhere in the file
+fn range_names(tokens: &[Token]) -> HashSet<String> {
+    tokens
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Token::Identifier(type_), Token::Identifier(name)] if type_ == "range" => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Index of `symbol` outside of any brackets in `tokens`
+fn top_level(tokens: &[Token], symbol: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            Token::Symbol(s) if s == symbol && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn next_code(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&i| !matches!(tokens[i], Token::Newline | Token::Comment(_)))
+}
+
+fn matching_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" => depth += 1,
+            Token::Symbol(s) if s == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    fn rewritten(source: &str) -> String {
+        detokenize(&rewrite(&tokenize(source)))
+    }
+
+    #[test]
+    fn test_range_loops() {
+        let output = rewritten("for (int i : 0..n) total += i;\nfor (long j : 1..=f(n, 2)) {}\nfor (int k : r) {}\nvoid g(range r);");
+        assert!(output.contains("for (int i = 0, __z_end_0 = (n); i < __z_end_0; i++) total += i;"));
+        assert!(output.contains("for (long j = 1, __z_end_1 = (f(n, 2)) + 1; j < __z_end_1; j++) { }"));
+        assert!(output.contains("for (int k = r.start, __z_end_2 = r.end; k < __z_end_2; k++) { }"));
+        assert!(output.contains("void g(__z_range r);"));
+    }
+
+    #[test]
+    fn test_range_values() {
+        let output = rewritten("range r = a + 1..b * 2;\nreturn sum(..=10, 3);");
+        assert!(output.contains("__z_range r = ((__z_range){ (a + 1), (b * 2) });"));
+        assert!(output.contains("return sum(((__z_range){ (0), (10) + 1 }), 3);"));
+        assert_eq!(rewritten("int range = 1;"), "int range = 1;");
+    }
+}
@@ -0,0 +1,476 @@
+// src/rc.rs
+//
+// Reference counting for `rc class` values. A value is a handle to a heap
+// block that starts with its reference count; `Node::new()` allocates one
+// with a count of 1. Every local and parameter of an rc class owns one
+// reference. This pass inserts the `retain`s and `release`s that keep it that way:
+//
+// * `Node b = a;` retains, `Node b = make();` takes over the call's reference
+// * `b = a;` and `b.next = a;` go through `Node_set`, which releases the old value
+// * a parameter is retained on entry, and every owned variable is released
+//   when its block ends or a `return`, `break` or `continue` leaves it
+// * `return b;` hands `b`'s reference to the caller
+//
+// `self` is borrowed: methods neither retain nor release it. `goto` out of
+// a block skips the releases.
+
+use std::collections::HashMap;
+
+use crate::ast::Variable;
+use crate::parser::{code, statement_end};
+use crate::tokenizer::Token;
+
+/// The rc classes visible to a file
+pub struct RcTypes<'a> {
+    /// Class name -> mangled C name, for every visible class
+    pub class_names: &'a HashMap<String, String>,
+    /// Fields of each rc class, by mangled name
+    pub classes: &'a HashMap<String, Vec<Variable>>,
+}
+
+impl RcTypes<'_> {
+    /// Mangled name of the rc class a variable of type `type_` holds
+    pub fn class_of(&self, type_: &str) -> Option<String> {
+        if type_.contains('*') {
+            return None;
+        }
+        let name = type_.split_whitespace().last()?;
+        // Lowered code already spells the mangled name
+        if self.classes.contains_key(name) {
+            return Some(name.to_string());
+        }
+        let mangled = self.class_names.get(name)?;
+        self.classes.contains_key(mangled).then(|| mangled.clone())
+    }
+}
+
+/// Inserts retains and releases into a function body (the tokens between
+/// its braces). `receiver` is the mangled class of `self` in a method of an
+/// rc class.
+pub fn rewrite_body(body: &[Token], params: &[Variable], receiver: Option<&str>, types: &RcTypes) -> Vec<Token> {
+    let mut rewriter = Rewriter { types, scopes: Vec::new(), receiver: receiver.map(str::to_string) };
+    let mut function = Scope { kind: ScopeKind::Function, label: None, owned: Vec::new(), terminated: false };
+    let mut out = Vec::new();
+    for param in params {
+        if let Some(class) = types.class_of(&param.type_) {
+            out.extend(code(&format!("{}_retain({});", class, param.name)));
+            function.owned.push((param.name.clone(), class));
+        }
+    }
+    rewriter.scopes.push(function);
+    out.extend(rewriter.block(body));
+    let function = rewriter.scopes.pop().expect("function scope");
+    if !function.terminated {
+        out.extend(releases(function.owned.iter().rev()));
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScopeKind {
+    Function,
+    Loop,
+    Switch,
+    Block,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    // `outer` for `outer: for (...) {`
+    label: Option<String>,
+    // (variable, mangled class), in declaration order
+    owned: Vec<(String, String)>,
+    // The last statement was a `return`, `break` or `continue`
+    terminated: bool,
+}
+
+struct Rewriter<'a> {
+    types: &'a RcTypes<'a>,
+    scopes: Vec<Scope>,
+    receiver: Option<String>,
+}
+
+impl Rewriter<'_> {
+    fn block(&mut self, tokens: &[Token]) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut parens = 0;
+        let mut i = 0;
+        while i < tokens.len() {
+            let previous = previous_code(tokens, i);
+            let candidate = !matches!(&tokens[i], Token::Newline | Token::Comment(_)) && !matches!(&tokens[i], Token::Symbol(s) if s == "}");
+            if parens == 0 && candidate && starts_statement(previous) {
+                // Only a statement written directly in the block can end it
+                let direct = !matches!(previous, Some(Token::Symbol(s)) if s == ")")
+                    && !matches!(previous, Some(Token::Identifier(w)) if w == "else" || w == "do");
+                if direct {
+                    self.top().terminated = false;
+                }
+                if let Some((code, end, terminates)) = self.statement(tokens, i) {
+                    out.extend(code);
+                    if terminates && direct {
+                        self.top().terminated = true;
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+
+            match &tokens[i] {
+                Token::Symbol(s) if s == "(" => parens += 1,
+                Token::Symbol(s) if s == ")" => parens -= 1,
+                Token::Symbol(s) if s == "{" => {
+                    let (kind, label) = scope_kind(tokens, i);
+                    self.scopes.push(Scope { kind, label, owned: Vec::new(), terminated: false });
+                }
+                Token::Symbol(s) if s == "}" && self.scopes.len() > 1 => {
+                    let scope = self.scopes.pop().expect("block scope");
+                    if !scope.terminated {
+                        out.extend(releases(scope.owned.iter().rev()));
+                    }
+                }
+                Token::Identifier(_) if !after_member_access(tokens, i) => {
+                    if let Some((code, end, _)) = self.chain(tokens, i) {
+                        out.extend(code);
+                        i = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            out.push(tokens[i].clone());
+            i += 1;
+        }
+        out
+    }
+
+    // A declaration, assignment or jump that needs retains or releases:
+    // (code, index after the statement, whether it leaves the block)
+    fn statement(&mut self, tokens: &[Token], start: usize) -> Option<(Vec<Token>, usize, bool)> {
+        let end = statement_end(tokens, start)?;
+        let statement = &tokens[start..end];
+        match statement.first()? {
+            Token::Identifier(word) if word == "return" => Some((self.return_(&statement[1..]), end + 1, true)),
+            Token::Identifier(word) if word == "break" || word == "continue" => {
+                let label = match statement.get(1) {
+                    Some(Token::Identifier(label)) => Some(label.as_str()),
+                    _ => None,
+                };
+                let released = self.released_by_jump(word == "continue", label);
+                if released.is_empty() {
+                    return None;
+                }
+                let mut out = code("{");
+                out.extend(released);
+                out.extend(statement.iter().cloned());
+                out.extend(code("; }"));
+                Some((out, end + 1, true))
+            }
+            _ => self.declaration(statement).or_else(|| self.assignment(statement)).map(|out| (out, end + 1, false)),
+        }
+    }
+
+    // `Node b = a;` -> `Node b = Node_retain(a);`, `Node b;` -> `Node b = {0};`
+    fn declaration(&mut self, statement: &[Token]) -> Option<Vec<Token>> {
+        let code_tokens: Vec<&Token> = statement.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        let i = code_tokens.iter().position(|t| matches!(t, Token::Symbol(s) if s == "=")).unwrap_or(code_tokens.len()).checked_sub(1)?;
+        let Token::Identifier(name) = code_tokens[i] else { return None };
+        let is_type = |t: &&Token| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "::");
+        if i == 0 || !code_tokens[..i].iter().all(is_type) {
+            return None;
+        }
+        let Some(Token::Identifier(type_name)) = code_tokens[..i].last() else { return None };
+        let class = self.types.class_of(type_name)?;
+        let type_tokens: Vec<Token> = code_tokens[..i].iter().map(|t| (*t).clone()).collect();
+
+        let value = match code_tokens.get(i + 1) {
+            None => code("{0}"),
+            Some(Token::Symbol(s)) if s == "=" => {
+                let init: Vec<Token> = code_tokens[i + 2..].iter().map(|t| (*t).clone()).collect();
+                if has_top_level_comma(&init) {
+                    return None;
+                }
+                self.owned_value(&init)
+            }
+            _ => return None,
+        };
+
+        let mut out = type_tokens;
+        out.push(Token::Identifier(name.clone()));
+        out.push(Token::Symbol("=".to_string()));
+        out.extend(value);
+        out.push(Token::Symbol(";".to_string()));
+        self.top().owned.push((name.clone(), class));
+        Some(out)
+    }
+
+    // `b = a;` -> `Node_set(&b, Node_retain(a));`, also for `x.field = a;`
+    fn assignment(&mut self, statement: &[Token]) -> Option<Vec<Token>> {
+        let start = statement.iter().position(|t| !matches!(t, Token::Newline | Token::Comment(_)))?;
+        let Token::Identifier(name) = &statement[start] else { return None };
+        let (target, end, class) = self.chain(statement, start)?;
+        let class = class?;
+        if !matches!(statement.get(end), Some(Token::Symbol(s)) if s == "=") {
+            return None;
+        }
+        // A bare name must be a reference this function owns
+        if end == start + 1 && self.owned(name).is_none() {
+            return None;
+        }
+        let mut out = statement[..start].to_vec();
+        out.extend(code(&format!("{}_set(&", class)));
+        out.extend(target);
+        out.push(Token::Symbol(",".to_string()));
+        out.extend(self.owned_value(&statement[end + 1..]));
+        out.extend(code(");"));
+        Some(out)
+    }
+
+    // `return e;`, releasing every owned variable once `e` is evaluated
+    fn return_(&mut self, value: &[Token]) -> Vec<Token> {
+        let value: Vec<Token> = value.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned().collect();
+        let mut owned: Vec<(String, String)> = self.scopes.iter().rev().flat_map(|s| s.owned.iter().rev().cloned()).collect();
+
+        // Returning an owned variable hands its reference to the caller
+        if let [Token::Identifier(name)] = value.as_slice() {
+            if let Some(position) = owned.iter().position(|(owned, _)| owned == name) {
+                owned.remove(position);
+                return wrap_return(releases(owned.iter()), code(&format!("return {};", name)));
+            }
+        }
+
+        if value.is_empty() {
+            return wrap_return(releases(owned.iter()), code("return;"));
+        }
+        let value = self.owned_value(&value);
+        if owned.is_empty() {
+            let mut out = code("return");
+            out.extend(value);
+            out.push(Token::Symbol(";".to_string()));
+            return out;
+        }
+        let mut out = code("{ __typeof__(");
+        out.extend(value.clone());
+        out.extend(code(") __z_ret ="));
+        out.extend(value);
+        out.push(Token::Symbol(";".to_string()));
+        out.extend(releases(owned.iter()));
+        out.extend(code("return __z_ret; }"));
+        out
+    }
+
+    // Releases for the blocks a `break` or `continue` leaves
+    fn released_by_jump(&self, continue_: bool, label: Option<&str>) -> Vec<Token> {
+        let mut out = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            out.extend(releases(scope.owned.iter().rev()));
+            let target = match label {
+                Some(label) => scope.label.as_deref() == Some(label),
+                None => scope.kind == ScopeKind::Loop || (!continue_ && scope.kind == ScopeKind::Switch),
+            };
+            if target || scope.kind == ScopeKind::Function {
+                break;
+            }
+        }
+        out
+    }
+
+    // An expression stored somewhere that owns a reference: names and
+    // fields are retained, calls already return one
+    fn owned_value(&self, value: &[Token]) -> Vec<Token> {
+        let start = value.iter().position(|t| !matches!(t, Token::Newline | Token::Comment(_)));
+        if let Some(start) = start {
+            if let Some((chain, end, Some(class))) = self.chain(value, start) {
+                if value[end..].iter().all(|t| matches!(t, Token::Newline | Token::Comment(_))) {
+                    let mut out = code(&format!("{}_retain(", class));
+                    out.extend(chain);
+                    out.push(Token::Symbol(")".to_string()));
+                    return out;
+                }
+            }
+        }
+        self.expression(value)
+    }
+
+    // Field accesses on rc values inside an expression
+    fn expression(&self, tokens: &[Token]) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            if !after_member_access(tokens, i) {
+                if let Some((chain, end, _)) = self.chain(tokens, i) {
+                    out.extend(chain);
+                    i = end;
+                    continue;
+                }
+            }
+            out.push(tokens[i].clone());
+            i += 1;
+        }
+        out
+    }
+
+    // `n.next.value` on an rc variable -> `n.ptr->next.ptr->value`:
+    // (code, index after it, rc class of the result)
+    fn chain(&self, tokens: &[Token], start: usize) -> Option<(Vec<Token>, usize, Option<String>)> {
+        let Some(Token::Identifier(name)) = tokens.get(start) else { return None };
+        let mut class = self.variable(name)?;
+        let mut out = vec![tokens[start].clone()];
+        let mut i = start + 1;
+        while let (Some(Token::Symbol(dot)), Some(Token::Identifier(field))) = (tokens.get(i), tokens.get(i + 1)) {
+            let Some(current) = &class else { break };
+            // Method calls are left for lowering
+            if dot != "." || matches!(tokens.get(i + 2), Some(Token::Symbol(s)) if s == "(") {
+                break;
+            }
+            let Some(declared) = self.types.classes[current].iter().find(|f| &f.name == field) else { break };
+            out.extend(code(".ptr->"));
+            out.push(Token::Identifier(field.clone()));
+            class = self.types.class_of(&declared.type_);
+            i += 2;
+        }
+        Some((out, i, class))
+    }
+
+    // The rc class of a variable in scope, `None` inside `Some` for no class
+    fn variable(&self, name: &str) -> Option<Option<String>> {
+        if let Some(class) = self.owned(name) {
+            return Some(Some(class));
+        }
+        match &self.receiver {
+            Some(class) if name == "self" => Some(Some(class.clone())),
+            _ => None,
+        }
+    }
+
+    fn owned(&self, name: &str) -> Option<String> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|s| s.owned.iter().rev())
+            .find(|(owned, _)| owned == name)
+            .map(|(_, class)| class.clone())
+    }
+
+    fn top(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("function scope")
+    }
+}
+
+// What kind of block the `{` at `open` starts, and its loop label
+fn scope_kind(tokens: &[Token], open: usize) -> (ScopeKind, Option<String>) {
+    let keyword = match previous_index(tokens, open) {
+        Some(close) if matches!(&tokens[close], Token::Symbol(s) if s == ")") => {
+            let mut depth = 0;
+            let opening = (0..=close).rev().find(|&k| {
+                match &tokens[k] {
+                    Token::Symbol(s) if s == ")" => depth += 1,
+                    Token::Symbol(s) if s == "(" => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+            opening.and_then(|k| previous_index(tokens, k))
+        }
+        other => other,
+    };
+    let Some(keyword) = keyword else { return (ScopeKind::Block, None) };
+    let kind = match &tokens[keyword] {
+        Token::Identifier(word) if word == "for" || word == "while" || word == "do" => ScopeKind::Loop,
+        Token::Identifier(word) if word == "switch" => ScopeKind::Switch,
+        _ => return (ScopeKind::Block, None),
+    };
+    let colon = previous_index(tokens, keyword);
+    let label = match colon.map(|c| (&tokens[c], previous_index(tokens, c).map(|l| &tokens[l]))) {
+        Some((Token::Symbol(colon), Some(Token::Identifier(label)))) if colon == ":" => Some(label.clone()),
+        _ => None,
+    };
+    (kind, label)
+}
+
+fn releases<'a>(owned: impl Iterator<Item = &'a (String, String)>) -> Vec<Token> {
+    owned.flat_map(|(name, class)| code(&format!("{}_release({});", class, name))).collect()
+}
+
+fn wrap_return(released: Vec<Token>, return_: Vec<Token>) -> Vec<Token> {
+    if released.is_empty() {
+        return return_;
+    }
+    let mut out = code("{");
+    out.extend(released);
+    out.extend(return_);
+    out.extend(code("}"));
+    out
+}
+
+fn has_top_level_comma(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    tokens.iter().any(|token| {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            Token::Symbol(s) if s == "," => return depth == 0,
+            _ => {}
+        }
+        false
+    })
+}
+
+fn starts_statement(previous: Option<&Token>) -> bool {
+    match previous {
+        None => true,
+        Some(Token::Symbol(s)) => matches!(s.as_str(), "{" | "}" | ";" | ":" | ")"),
+        Some(Token::Identifier(word)) => word == "else" || word == "do",
+        _ => false,
+    }
+}
+
+fn after_member_access(tokens: &[Token], i: usize) -> bool {
+    matches!(previous_code(tokens, i), Some(Token::Symbol(s)) if s == "." || s == "->" || s == "::")
+}
+
+fn previous_index(tokens: &[Token], i: usize) -> Option<usize> {
+    (0..i).rev().find(|&k| !matches!(tokens[k], Token::Newline | Token::Comment(_)))
+}
+
+fn previous_code(tokens: &[Token], i: usize) -> Option<&Token> {
+    previous_index(tokens, i).map(|k| &tokens[k])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::detokenize;
+
+    fn rewrite(body: &str, params: &[Variable]) -> String {
+        let class_names = HashMap::from([("Node".to_string(), "Node".to_string())]);
+        let classes = HashMap::from([(
+            "Node".to_string(),
+            vec![
+                Variable { name: "value".to_string(), type_: "int".to_string() },
+                Variable { name: "next".to_string(), type_: "Node".to_string() },
+            ],
+        )]);
+        let types = RcTypes { class_names: &class_names, classes: &classes };
+        detokenize(&rewrite_body(&code(body), params, None, &types))
+    }
+
+    #[test]
+    fn test_locals_are_released_at_scope_exits() {
+        let output = rewrite("Node a = Node_new();\nNode b = a;\nwhile (1) { Node c; if (x) break; }\nb.next = a;\nreturn b.value;", &[]);
+        assert!(output.contains("Node a = Node_new();"));
+        assert!(output.contains("Node b = Node_retain(a);"));
+        assert!(output.contains("{ Node c = { 0 }; if (x) { Node_release(c); break; } Node_release(c); }"));
+        assert!(output.contains("Node_set(&b.ptr->next, Node_retain(a));"));
+        assert!(output.contains("{ __typeof__(b.ptr->value) __z_ret = b.ptr->value; Node_release(b); Node_release(a); return __z_ret; }"));
+    }
+
+    #[test]
+    fn test_returned_variable_and_parameters() {
+        let param = Variable { name: "p".to_string(), type_: "Node".to_string() };
+        let output = rewrite("Node q = p.next;\np = q;\nreturn q;", &[param]);
+        assert!(output.starts_with("Node_retain(p);"));
+        assert!(output.contains("Node q = Node_retain(p.ptr->next);"));
+        assert!(output.contains("Node_set(&p, Node_retain(q));"));
+        assert!(output.contains("{ Node_release(p); return q; }"));
+    }
+}
@@ -0,0 +1,105 @@
+// src/reflect.rs
+//
+// Compile-time reflection: a `@reflect` class is emitted with a table of its
+// fields and methods, and `typeinfo(Name)` is a pointer to that table. The
+// tables are plain C data, so serializers and debug printers can be written
+// in Z by walking them.
+
+use crate::ast::Class;
+use crate::tokenizer::Token;
+
+/// The table types, emitted once before the first `@reflect` class
+pub const RUNTIME: &str = "#ifndef __Z_TYPEINFO
+#define __Z_TYPEINFO
+#include <stddef.h>
+typedef struct { const char* name; const char* type; size_t offset; size_t size; } FieldInfo;
+typedef struct { const char* name; void (*function)(void); } MethodInfo;
+typedef struct { const char* name; size_t size; size_t align; size_t field_count; const FieldInfo* fields; size_t method_count; const MethodInfo* methods; } TypeInfo;
+#endif
+";
+
+/// C name of a class's table, e.g. `geo_Vec__typeinfo`
+pub fn table_name(mangled: &str) -> String {
+    format!("{}__typeinfo", mangled)
+}
+
+/// The field and method tables of `class` and its `TypeInfo`. Fields of an
+/// rc class are described where they live, in the block its handle points to.
+/// Methods are cast to `void (*)(void)`; their `self` comes first.
+pub fn tables(class: &Class, linkage: &str) -> String {
+    let name = class.full_name();
+    let fields_of = if class.rc { format!("{}__data", name) } else { name.clone() };
+    let mut out = String::from(RUNTIME);
+
+    let fields = if class.variables.is_empty() {
+        "NULL".to_string()
+    } else {
+        let entries: Vec<String> = class.variables.iter()
+            .map(|field| format!(
+                "{{ \"{0}\", \"{1}\", offsetof({2}, {0}), sizeof((({2}*)0)->{0}) }}",
+                field.name, field.type_, fields_of
+            ))
+            .collect();
+        out.push_str(&format!("static const FieldInfo {}__fields[] = {{ {} }};\n", name, entries.join(", ")));
+        format!("{}__fields", name)
+    };
+
+    let methods = if class.functions.is_empty() {
+        "NULL".to_string()
+    } else {
+        let entries: Vec<String> = class.functions.iter()
+            .map(|function| format!("{{ \"{}\", (void (*)(void)){}_{} }}", function.name, name, function.name))
+            .collect();
+        out.push_str(&format!("static const MethodInfo {}__methods[] = {{ {} }};\n", name, entries.join(", ")));
+        format!("{}__methods", name)
+    };
+
+    out.push_str(&format!(
+        "{linkage}const TypeInfo {table} = {{ \"{plain}\", sizeof({name}), _Alignof({name}), {field_count}, {fields}, {method_count}, {methods} }};\n",
+        table = table_name(&name),
+        plain = class.name,
+        field_count = class.variables.len(),
+        method_count = class.functions.len(),
+    ));
+    out
+}
+
+/// Every `typeinfo(Name)` or `typeinfo(ns::Name)` in `tokens`: the index of
+/// `typeinfo`, the index after `)` and the class as a C path, e.g. `geo_Vec`
+pub fn typeinfo_calls(tokens: &[Token]) -> Vec<(usize, usize, String)> {
+    let mut calls = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if *token != Token::Identifier("typeinfo".to_string()) || tokens.get(i + 1) != Some(&Token::Symbol("(".to_string())) {
+            continue;
+        }
+        let mut path = Vec::new();
+        let mut j = i + 2;
+        while let Some(Token::Identifier(segment)) = tokens.get(j) {
+            path.push(segment.as_str());
+            match tokens.get(j + 1) {
+                Some(Token::Symbol(s)) if s == "::" => j += 2,
+                _ => {
+                    j += 1;
+                    break;
+                }
+            }
+        }
+        if !path.is_empty() && tokens.get(j) == Some(&Token::Symbol(")".to_string())) {
+            calls.push((i, j + 1, path.join("_")));
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_typeinfo_calls() {
+        let tokens = tokenize("print(typeinfo(V), typeinfo(geo::Vec)); typeinfo(1); typeinfo");
+        let calls: Vec<String> = typeinfo_calls(&tokens).into_iter().map(|(_, _, path)| path).collect();
+        assert_eq!(calls, vec!["V", "geo_Vec"]);
+    }
+}
@@ -0,0 +1,136 @@
+// src/registry.rs
+//
+// The classes and interfaces a compilation knows of, by the name they are
+// written with, and what tooling needs to know of each: its C name, its
+// namespace, fields, methods and operators, and the file declaring it.
+// Iteration is ordered by name, so anything generated from the registry,
+// e.g. the `#class` lines of an interface file, comes out the same on
+// every run.
+//
+// File-private classes are never in it; `Context::class_names` adds those
+// of the file being compiled.
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::HashMap;
+
+use crate::ast::{Class, Interface, Variable};
+
+/// What the registry knows of one class or interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassInfo {
+    /// As written, e.g. `Point`
+    pub name: String,
+    /// Name of the C struct, e.g. `geo_Point`
+    pub c_name: String,
+    /// Mangling prefix of the namespace, e.g. `geo`; `None` at file scope
+    pub namespace: Option<String>,
+    pub interface: bool,
+    pub fields: Vec<Variable>,
+    pub methods: Vec<String>,
+    /// The operators overloaded, e.g. `+` and `bool`
+    pub operators: Vec<String>,
+    /// The file declaring it, e.g. `geo.z`, or the `.zi` it was imported from
+    pub file: String,
+}
+
+impl ClassInfo {
+    pub fn of_class(class: &Class, file: &str) -> ClassInfo {
+        ClassInfo {
+            name: class.name.clone(),
+            c_name: class.full_name(),
+            namespace: class.namespace.clone(),
+            interface: false,
+            fields: class.variables.clone(),
+            methods: class.functions.iter().map(|f| f.name.clone()).collect(),
+            operators: class.operators.iter().map(|o| o.operator.clone()).collect(),
+            file: file.to_string(),
+        }
+    }
+
+    pub fn of_interface(interface: &Interface, file: &str) -> ClassInfo {
+        ClassInfo {
+            name: interface.name.clone(),
+            c_name: interface.full_name(),
+            namespace: interface.namespace.clone(),
+            interface: true,
+            fields: Vec::new(),
+            methods: interface.methods.iter().map(|m| m.name.clone()).collect(),
+            operators: Vec::new(),
+            file: file.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolRegistry {
+    classes: BTreeMap<String, ClassInfo>,
+}
+
+impl SymbolRegistry {
+    /// Registers `info` under its name, replacing a class of that name
+    pub fn insert(&mut self, info: ClassInfo) {
+        self.classes.insert(info.name.clone(), info);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassInfo> {
+        self.classes.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    /// The C name of the class `name`
+    pub fn c_name(&self, name: &str) -> Option<&str> {
+        self.get(name).map(|info| info.c_name.as_str())
+    }
+
+    /// Every class by name, in the order of the names
+    pub fn iter(&self) -> btree_map::Values<'_, String, ClassInfo> {
+        self.classes.values()
+    }
+
+    /// The C name of every class, by name, for lowering
+    pub fn c_names(&self) -> HashMap<String, String> {
+        self.iter().map(|info| (info.name.clone(), info.c_name.clone())).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a SymbolRegistry {
+    type Item = &'a ClassInfo;
+    type IntoIter = btree_map::Values<'a, String, ClassInfo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_registry_describes_the_classes() {
+        let mut session = crate::Session::default();
+        crate::Compiler::new().compile_in(&mut session, "geo.z", "namespace geo {\n  class Point {\n    int x;\n    int y;\n    int sum() { return self.x + self.y; }\n    Point operator+(Point o) { return o; }\n  }\n  interface Shape { double area(); }\n}\nclass Counter { int n; }\nnamespace { class Hidden { int h; } }\nint main() { return 0; }");
+        let names: Vec<&str> = session.known_classes.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, vec!["Counter", "Point", "Shape"]);
+
+        let point = session.known_classes.get("Point").unwrap();
+        assert_eq!(point.c_name, "geo_Point");
+        assert_eq!(point.namespace.as_deref(), Some("geo"));
+        assert_eq!(point.fields.iter().map(|f| format!("{} {}", f.type_, f.name)).collect::<Vec<_>>(), vec!["int x", "int y"]);
+        assert_eq!(point.methods, vec!["sum"]);
+        assert_eq!(point.operators, vec!["+"]);
+        assert_eq!(point.file, "geo.z");
+        assert!(session.known_classes.get("Shape").unwrap().interface);
+        assert_eq!(session.known_classes.c_name("Counter"), Some("Counter"));
+        assert!(!session.known_classes.contains("Hidden"));
+    }
+}
@@ -0,0 +1,399 @@
+// src/scopes.rs
+//
+// A scoped symbol table for function bodies, and the shadowing checks built
+// on it. Lowering looks variables up by name across the whole file, so a
+// local that reuses an outer name with another type, or a name declared
+// twice in one block, gets the wrong method calls and operators. Redeclaring
+// a name in the same scope is an error; a local hiding a parameter, a local
+// of an enclosing block or a field of the method's class is a warning.
+//
+// Passes that need the type of a name where it is used follow the table
+// through the code as they walk it, see `SymbolTable::declare_in`.
+
+use crate::ast::Variable;
+use crate::parser::matching_close;
+use crate::tokenizer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Field,
+    Parameter,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub type_: String,
+    pub kind: SymbolKind,
+}
+
+// Words of a declared type that lookups by type leave out
+const QUALIFIERS: &[&str] = &["const", "volatile", "static", "register", "extern", "enum", "struct"];
+
+impl Symbol {
+    /// The symbol as a variable whose type is written without qualifiers,
+    /// storage words or `enum`, e.g. `Color` of `const enum Color c`
+    pub fn variable(&self) -> Variable {
+        let words: Vec<&str> = self.type_.split_whitespace().skip_while(|word| QUALIFIERS.contains(word)).collect();
+        Variable { name: self.name.clone(), type_: words.join(" ") }
+    }
+}
+
+/// What declaring a name did
+#[derive(Debug, PartialEq)]
+pub enum Declared {
+    New,
+    /// Hides this symbol of an enclosing scope
+    Shadows(Symbol),
+    /// This symbol of the same scope already has the name
+    Redeclares(Symbol),
+}
+
+/// Names in nested scopes, innermost last
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    scopes: Vec<Vec<Symbol>>,
+}
+
+impl SymbolTable {
+    pub fn push(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn declare(&mut self, symbol: Symbol) -> Declared {
+        if self.scopes.is_empty() {
+            self.push();
+        }
+        let result = if let Some(previous) = self.scopes.last().unwrap().iter().find(|s| s.name == symbol.name) {
+            Declared::Redeclares(previous.clone())
+        } else if let Some(outer) = self.lookup(&symbol.name) {
+            Declared::Shadows(outer.clone())
+        } else {
+            Declared::New
+        };
+        self.scopes.last_mut().unwrap().push(symbol);
+        result
+    }
+
+    /// The innermost symbol called `name`
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.visible().find(|s| s.name == name)
+    }
+
+    /// Every symbol in scope, innermost first, so a name's first is the one
+    /// it refers to
+    pub fn visible(&self) -> impl Iterator<Item = &Symbol> {
+        self.scopes.iter().rev().flat_map(|scope| scope.iter().rev())
+    }
+
+    /// Opens the body of a function taking `params`. Returns the depth to
+    /// `close` the table back to where the body ends
+    pub fn enter(&mut self, params: &[Variable]) -> usize {
+        let depth = self.depth();
+        self.push();
+        for param in params.iter().filter(|p| !p.is_variadic()) {
+            self.declare(Symbol { name: param.name.clone(), type_: param.type_.clone(), kind: SymbolKind::Parameter });
+        }
+        depth
+    }
+
+    /// Closes the scopes opened since the table was `depth` deep
+    pub fn close(&mut self, depth: usize) {
+        self.scopes.truncate(depth);
+    }
+
+    /// Declares what the statements of `tokens` declare, a scope per
+    /// brace, for code walked a run of statements at a time as `visit`
+    /// does. A function defined at file scope declares its parameters in
+    /// its body
+    pub fn declare_in(&mut self, tokens: &[Token]) {
+        let mut checker = Checker { function: "", table: std::mem::take(self), errors: Vec::new(), warnings: Vec::new() };
+        checker.block(tokens);
+        *self = checker.table;
+    }
+}
+
+/// Checks the declarations in `body`, the tokens between the braces of
+/// `function`. `fields` are those of the class for a method. Returns the
+/// errors and the warnings.
+pub fn check_body(function: &str, body: &[Token], params: &[Variable], fields: &[Variable]) -> (Vec<String>, Vec<String>) {
+    let mut checker = Checker { function, table: SymbolTable::default(), errors: Vec::new(), warnings: Vec::new() };
+    checker.table.push();
+    for field in fields {
+        checker.table.declare(Symbol { name: field.name.clone(), type_: field.type_.clone(), kind: SymbolKind::Field });
+    }
+    // Parameters live in the body's outermost block, as in C
+    checker.table.push();
+    for param in params.iter().filter(|p| !p.is_variadic()) {
+        checker.declare(param.name.clone(), param.type_.clone(), SymbolKind::Parameter);
+    }
+    checker.block(body);
+    (checker.errors, checker.warnings)
+}
+
+struct Checker<'a> {
+    function: &'a str,
+    table: SymbolTable,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Checker<'_> {
+    fn declare(&mut self, name: String, type_: String, kind: SymbolKind) {
+        // Names the compiler generates are unique on purpose
+        if name.starts_with("__z_") {
+            return;
+        }
+        let function = self.function;
+        match self.table.declare(Symbol { name: name.clone(), type_, kind }) {
+            Declared::New => {}
+            Declared::Redeclares(previous) => self.errors.push(match previous.kind {
+                SymbolKind::Parameter => format!("`{}` in `{}` redeclares the parameter `{}`", name, function, name),
+                _ => format!("`{}` is declared twice in the same block of `{}`", name, function),
+            }),
+            Declared::Shadows(outer) => self.warnings.push(match outer.kind {
+                SymbolKind::Field => format!("local `{}` in `{}` shadows the field `{}`; the field is still `self.{}`", name, function, name, name),
+                SymbolKind::Parameter => format!("local `{}` in `{}` shadows the parameter `{}`", name, function, name),
+                SymbolKind::Local => format!("local `{}` in `{}` shadows a local of an enclosing block", name, function),
+            }),
+        }
+    }
+
+    // Walks a block's tokens, declaring what each statement declares
+    fn block(&mut self, tokens: &[Token]) {
+        // Scopes opened by `for (` whose loop hasn't ended, by the brace
+        // depth of the table when the loop body started
+        let mut loops: Vec<(usize, bool)> = Vec::new();
+        // The table at each open `#if`, which every branch starts from
+        let mut conditionals: Vec<SymbolTable> = Vec::new();
+        let mut at_start = true;
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                Token::Newline | Token::Comment(_) => {
+                    i += 1;
+                    continue;
+                }
+                Token::Symbol(s) if s == "#" => {
+                    match tokens.get(i + 1) {
+                        Some(Token::Identifier(d)) if d.starts_with("if") => conditionals.push(self.table.clone()),
+                        Some(Token::Identifier(d)) if d == "else" || d == "elif" => {
+                            if let Some(table) = conditionals.last() {
+                                self.table = table.clone();
+                            }
+                        }
+                        Some(Token::Identifier(d)) if d == "endif" => {
+                            conditionals.pop();
+                        }
+                        _ => {}
+                    }
+                    i = (i..tokens.len()).find(|&j| tokens[j] == Token::Newline).unwrap_or(tokens.len());
+                    continue;
+                }
+                Token::Symbol(s) if s == "{" => {
+                    let params = if self.table.depth() <= 1 { parameters(&tokens[..i]) } else { Vec::new() };
+                    self.table.push();
+                    for (name, type_) in params {
+                        self.declare(name, type_, SymbolKind::Parameter);
+                    }
+                    at_start = true;
+                }
+                Token::Symbol(s) if s == "}" => {
+                    self.table.pop();
+                    self.end_loops(&mut loops, true);
+                    at_start = true;
+                }
+                Token::Symbol(s) if s == ";" => {
+                    self.end_loops(&mut loops, false);
+                    at_start = true;
+                }
+                Token::Identifier(word) if word == "for" && matches!(tokens.get(i + 1), Some(Token::Symbol(s)) if s == "(") => {
+                    // The loop variable is scoped to the loop
+                    let Some(close) = matching_close(tokens, i + 1) else { break };
+                    self.table.push();
+                    if let Some((names, _)) = declaration(&tokens[i + 2..close]) {
+                        for (name, type_) in names {
+                            self.declare(name, type_, SymbolKind::Local);
+                        }
+                    }
+                    let braced = matches!(tokens[close + 1..].iter().find(|t| !matches!(t, Token::Newline | Token::Comment(_))), Some(Token::Symbol(s)) if s == "{");
+                    loops.push((self.table.depth(), braced));
+                    i = close + 1;
+                    at_start = true;
+                    continue;
+                }
+                Token::Symbol(s) if s == ":" => at_start = true,
+                _ if at_start => {
+                    at_start = false;
+                    if let Some((names, end)) = declaration(&tokens[i..]) {
+                        for (name, type_) in names {
+                            self.declare(name, type_, SymbolKind::Local);
+                        }
+                        i += end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    // Closes the `for` scopes whose body just ended: a braced body at its
+    // `}`, a single statement at its `;`
+    fn end_loops(&mut self, loops: &mut Vec<(usize, bool)>, brace: bool) {
+        while let Some(&(depth, braced)) = loops.last() {
+            if self.table.depth() != depth || braced != brace {
+                break;
+            }
+            loops.pop();
+            self.table.pop();
+        }
+    }
+}
+
+// The names and types of the parameters of `f(params)` that `tokens` end
+// with, the header of a function whose body follows
+fn parameters(tokens: &[Token]) -> Vec<(String, String)> {
+    let code: Vec<&Token> = tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    if !matches!(code.last(), Some(Token::Symbol(s)) if s == ")") {
+        return Vec::new();
+    }
+    let mut depth = 0;
+    for open in (0..code.len()).rev() {
+        match code[open] {
+            Token::Symbol(s) if s == ")" => depth += 1,
+            Token::Symbol(s) if s == "(" => {
+                depth -= 1;
+                if depth == 0 {
+                    if !matches!(open.checked_sub(1).map(|p| code[p]), Some(Token::Identifier(_))) {
+                        return Vec::new();
+                    }
+                    let params: Vec<Token> = code[open + 1..code.len() - 1].iter().map(|t| (*t).clone()).collect();
+                    return params.split(|t| matches!(t, Token::Symbol(s) if s == ","))
+                        .filter_map(|param| declaration(param)?.0.into_iter().next())
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    Vec::new()
+}
+
+const NOT_TYPES: &[&str] = &["return", "goto", "break", "continue", "case", "default", "else", "do", "typedef", "sizeof", "if", "while", "switch"];
+
+// `Type a = x, *b;` at the start of `tokens`: the names and their types, and
+// the index of the `;` ending it (or the end of `tokens`)
+fn declaration(tokens: &[Token]) -> Option<(Vec<(String, String)>, usize)> {
+    let code: Vec<(usize, &Token)> = tokens.iter().enumerate().filter(|(_, t)| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    let mut words: Vec<&str> = Vec::new();
+    let mut k = 0;
+    while let Some((_, token)) = code.get(k) {
+        match token {
+            Token::Identifier(word) => words.push(word),
+            Token::Symbol(s) if s == "*" || s == "::" => words.push(s),
+            _ => break,
+        }
+        k += 1;
+    }
+    // `:` ends the binding of `for (T x : items)`
+    match code.get(k) {
+        Some((_, Token::Symbol(s))) if matches!(s.as_str(), "=" | ";" | "," | "[" | ":") => {}
+        None => {}
+        _ => return None,
+    }
+    let name = *words.last()?;
+    let identifiers = words.iter().filter(|w| **w != "*" && **w != "::").count();
+    if identifiers < 2 || name == "*" || name == "::" || NOT_TYPES.contains(&words[0]) {
+        return None;
+    }
+    let base: Vec<&str> = words[..words.len() - 1].iter().copied().filter(|w| *w != "*").collect();
+    let base = base.join(" ").replace(" :: ", "::");
+    let pointer = |stars: &[&str]| format!("{}{}", base, "*".repeat(stars.iter().filter(|w| **w == "*").count()));
+    // `V cells[3]` is no `V`
+    let array = |at: usize| if matches!(code.get(at), Some((_, Token::Symbol(s))) if s == "[") { "[]" } else { "" };
+    let mut names = vec![(name.to_string(), pointer(&words[..words.len() - 1]) + array(k))];
+
+    // Later declarators, after each `,` at depth 0
+    let mut depth = 0;
+    let mut end = tokens.len();
+    let mut m = k;
+    while let Some(&(index, token)) = code.get(m) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "{" || s == "[" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "}" || s == "]" => depth -= 1,
+            Token::Symbol(s) if s == ";" && depth == 0 => {
+                end = index;
+                break;
+            }
+            Token::Symbol(s) if s == "," && depth == 0 => {
+                let mut stars = 0;
+                while matches!(code.get(m + 1), Some((_, Token::Symbol(s))) if s == "*") {
+                    stars += 1;
+                    m += 1;
+                }
+                if let Some((_, Token::Identifier(next))) = code.get(m + 1) {
+                    names.push((next.to_string(), format!("{}{}{}", base, "*".repeat(stars), array(m + 2))));
+                }
+            }
+            _ => {}
+        }
+        m += 1;
+    }
+    Some((names, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_symbol_table_scopes() {
+        let local = |name: &str| Symbol { name: name.to_string(), type_: "int".to_string(), kind: SymbolKind::Local };
+        let mut table = SymbolTable::default();
+        assert_eq!(table.declare(local("x")), Declared::New);
+        table.push();
+        assert_eq!(table.declare(local("x")), Declared::Shadows(local("x")));
+        assert_eq!(table.declare(local("x")), Declared::Redeclares(local("x")));
+        table.pop();
+        assert_eq!(table.lookup("x"), Some(&local("x")));
+
+        // Walked a run of statements at a time, across functions
+        let mut table = SymbolTable::default();
+        table.declare_in(&tokenize("Circle c;\nint f(const enum Color c, ...) {"));
+        assert_eq!(table.lookup("c").map(Symbol::variable), Some(Variable { name: "c".to_string(), type_: "Color".to_string() }));
+        table.declare_in(&tokenize("{ char c = 'a'; }\n}\nint g() { return c; }\nint h() { geo::V* v, w[2];"));
+        assert_eq!(table.lookup("c").map(|s| s.type_.as_str()), Some("Circle"));
+        let types: Vec<String> = ["v", "w"].iter().filter_map(|name| table.lookup(name)).map(|s| s.variable().type_).collect();
+        assert_eq!(types, vec!["geo::V*", "geo::V[]"]);
+        let depth = table.enter(&[Variable { name: "c".to_string(), type_: "int".to_string() }]);
+        assert_eq!(table.lookup("c").map(|s| s.kind), Some(SymbolKind::Parameter));
+        table.close(depth);
+        assert_eq!(table.lookup("c").map(|s| s.kind), Some(SymbolKind::Local));
+    }
+
+    #[test]
+    fn test_shadowing_and_redeclaration() {
+        let body = tokenize("#ifdef _WIN32\nlong p;\n#else\nshort p;\n#endif\nint total = 0, *q = &total;\nfor (int i = 0; i < n; i++) { int n = i; total += n; }\nfor (int i = 0; i < 2; i++) total++;\nif (total) { const char* total = \"x\"; }\nint q = 1;\nreturn total;");
+        let params = vec![Variable { name: "n".to_string(), type_: "int".to_string() }];
+        let fields = vec![Variable { name: "total".to_string(), type_: "long".to_string() }];
+        let (errors, warnings) = check_body("C::sum", &body, &params, &fields);
+        assert_eq!(errors, vec!["`q` is declared twice in the same block of `C::sum`"]);
+        assert_eq!(warnings, vec![
+            "local `total` in `C::sum` shadows the field `total`; the field is still `self.total`",
+            "local `n` in `C::sum` shadows the parameter `n`",
+            "local `total` in `C::sum` shadows a local of an enclosing block",
+        ]);
+    }
+}
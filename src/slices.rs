@@ -0,0 +1,297 @@
+// src/slices.rs
+//
+// Slices: `T[] name` is a pointer and a length, `name.ptr` and `name.len`.
+// They are rewritten on the token stream before parsing, while the line of
+// every index expression is still known for `--bounds-check`.
+
+use std::collections::HashSet;
+
+use crate::parser::{code, is_symbol};
+use crate::tokenizer::{detokenize, generated, line_numbers, tokenize, Token};
+
+const RUNTIME: &str = "#include <stddef.h>\n";
+
+const BOUNDS_CHECK_RUNTIME: &str = "#ifndef __Z_BOUNDS_CHECK
+#define __Z_BOUNDS_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline size_t __z_bounds_check(size_t index, size_t len, const char* file, int line) {
+  if (index >= len) {
+    fprintf(stderr, \"%s:%d: index %zu is out of bounds for a slice of length %zu\\n\", file, line, index, len);
+    abort();
+  }
+  return index;
+}
+static inline void __z_range_check(size_t lo, size_t hi, size_t len, const char* file, int line) {
+  if (lo > hi || hi > len) {
+    fprintf(stderr, \"%s:%d: range %zu..%zu is out of bounds for a slice of length %zu\\n\", file, line, lo, hi, len);
+    abort();
+  }
+}
+#endif
+";
+
+/// C name of the slice struct for an element type, e.g. `__z_slice_char_ptr`
+/// for `char*[]`
+pub fn struct_name(element: &str) -> String {
+    let words: Vec<String> = tokenize(element)
+        .iter()
+        .filter_map(|t| match t {
+            Token::Identifier(word) => Some(word.clone()),
+            Token::Symbol(s) if s == "*" => Some("ptr".to_string()),
+            _ => None,
+        })
+        .collect();
+    format!("__z_slice_{}", words.join("_"))
+}
+
+/// Rewrites slice declarations into their structs and every `slice[i]` into
+/// `slice.ptr[i]`; with `bounds_check` the index is checked and an access
+/// out of range aborts with `file:line`
+pub fn rewrite(tokens: &[Token], file: &str, bounds_check: bool) -> Vec<Token> {
+    let names = slice_names(tokens);
+    if names.is_empty() {
+        return tokens.to_vec();
+    }
+    let indexed = rewrite_indexes(tokens, &line_numbers(tokens), &names, file, bounds_check);
+    let mut out = rewrite_declarations(&indexed);
+    let mut runtime = RUNTIME.to_string();
+    if bounds_check {
+        runtime.push_str(BOUNDS_CHECK_RUNTIME);
+    }
+    out.splice(0..0, generated(&runtime, 1));
+    out
+}
+
+// Names declared as `T[] name` anywhere in the file
+fn slice_names(tokens: &[Token]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for i in 1..tokens.len() {
+        if let (Some(Token::Symbol(open)), Some(Token::Symbol(close)), Some(Token::Identifier(name))) = (tokens.get(i), tokens.get(i + 1), tokens.get(i + 2)) {
+            let after_type = matches!(&tokens[i - 1], Token::Identifier(_)) || is_symbol(tokens.get(i - 1), "*");
+            if open == "[" && close == "]" && after_type {
+                names.insert(name.clone());
+            }
+        }
+    }
+    names
+}
+
+// `name[i]`, `a.name[i]` or `a->name[i]` on a slice -> `name.ptr[i]`,
+// including slices indexed inside the index
+fn rewrite_indexes(tokens: &[Token], lines: &[usize], names: &HashSet<String>, file: &str, bounds_check: bool) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some(Token::Identifier(name)) = tokens.get(i) {
+            if names.contains(name) && is_symbol(tokens.get(i + 1), "[") {
+                if let Some(close) = matching_bracket(tokens, i + 1) {
+                    let mut slice: Vec<Token> = out[base_start(&out)..].to_vec();
+                    slice.push(Token::Identifier(name.clone()));
+                    let index = rewrite_indexes(&tokens[i + 2..close], &lines[i + 2..close], names, file, bounds_check);
+                    if let Some(range) = range_operator(&index) {
+                        out.truncate(base_start(&out));
+                        out.extend(subslice(&slice, &index, range, file, lines[i], bounds_check));
+                        i = close + 1;
+                        continue;
+                    }
+                    out.push(Token::Identifier(name.clone()));
+                    out.extend(code(".ptr["));
+                    if bounds_check {
+                        out.extend(code("__z_bounds_check("));
+                        out.extend(index);
+                        out.push(Token::Symbol(",".to_string()));
+                        out.extend(slice);
+                        out.extend(code(&format!(".len, {:?}, {})", file, lines[i])));
+                    } else {
+                        out.extend(index);
+                    }
+                    out.push(Token::Symbol("]".to_string()));
+                    i = close + 1;
+                    continue;
+                }
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+// `xs[a..b]` -> the slice of `xs` from `a` up to `b`. `a` defaults to 0,
+// `b` to `xs.len`, and `..=b` includes `b`. The bounds are evaluated once.
+fn subslice(slice: &[Token], index: &[Token], range: usize, file: &str, line: usize, bounds_check: bool) -> Vec<Token> {
+    let start = if index[..range].is_empty() { "0".to_string() } else { detokenize(&index[..range]) };
+    let end = match (&index[range], &index[range + 1..]) {
+        (_, []) => "__z_s.len".to_string(),
+        (Token::Symbol(op), end) if op == "..=" => format!("({}) + 1", detokenize(end)),
+        (_, end) => detokenize(end),
+    };
+    let slice = detokenize(slice);
+    let check = if bounds_check {
+        format!("__z_range_check(__z_lo, __z_hi, __z_s.len, {:?}, {}); ", file, line)
+    } else {
+        String::new()
+    };
+    code(&format!(
+        "({{ __typeof__({slice}) __z_s = {slice}; size_t __z_lo = {start}, __z_hi = {end}; {check}__z_s.ptr += __z_lo; __z_s.len = __z_hi - __z_lo; __z_s; }})"
+    ))
+}
+
+/// Index of the `..` or `..=` of a range at the top level of `tokens`
+pub fn range_operator(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            Token::Symbol(s) if (s == ".." || s == "..=") && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// `T [ ] name` -> `__z_slice_T name`, with the struct's typedef inserted
+// before the first top-level item that uses it
+fn rewrite_declarations(tokens: &[Token]) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::new();
+    let mut typedefs: Vec<(usize, String)> = Vec::new();
+    let mut defined: HashSet<String> = HashSet::new();
+    let mut depth = 0;
+    let mut item_start = 0;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        if let (true, true, Some(Token::Identifier(name))) = (is_symbol(tokens.get(i), "["), is_symbol(tokens.get(i + 1), "]"), tokens.get(i + 2)) {
+            let element_start = element_type_start(&out);
+            if element_start < out.len() {
+                let element: Vec<Token> = out.drain(element_start..).collect();
+                let element = element_text(&element);
+                let struct_name = struct_name(&element);
+                if defined.insert(struct_name.clone()) {
+                    typedefs.push((item_start, format!(
+                        "\n#ifndef {guard}\n#define {guard}\ntypedef struct {{ {element}* ptr; size_t len; }} {name};\n#endif\n",
+                        guard = struct_name.to_uppercase(),
+                        element = element,
+                        name = struct_name,
+                    )));
+                }
+                out.push(Token::Identifier(struct_name));
+                out.push(Token::Identifier(name.clone()));
+                i += 3;
+                continue;
+            }
+        }
+
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" => depth += 1,
+            Token::Symbol(s) if s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    item_start = out.len() + 1;
+                }
+            }
+            Token::Symbol(s) if s == ";" && depth == 0 => item_start = out.len() + 1,
+            Token::Newline if depth == 0 && starts_with_hash(&out) => item_start = out.len() + 1,
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+
+    let lines = line_numbers(&out);
+    for (index, typedef) in typedefs.into_iter().rev() {
+        let line = lines.get(index).or(lines.last()).copied().unwrap_or(1);
+        out.splice(index..index, generated(&typedef, line));
+    }
+    out
+}
+
+// Start of the element type ending the output so far: names, `*` and `::`,
+// without storage classes, which stay in front of the slice type
+fn element_type_start(out: &[Token]) -> usize {
+    let mut start = out.len();
+    while start > 0 {
+        match &out[start - 1] {
+            Token::Identifier(word) if matches!(word.as_str(), "static" | "extern" | "register" | "inline" | "return" | "typedef") => break,
+            Token::Identifier(_) => start -= 1,
+            Token::Symbol(s) if s == "*" || s == "::" => start -= 1,
+            _ => break,
+        }
+    }
+    start
+}
+
+// Start of `a.` or `a->` right before a member slice, if any
+fn base_start(out: &[Token]) -> usize {
+    match out {
+        [.., Token::Identifier(_), Token::Symbol(access)] if access == "." || access == "->" => out.len() - 2,
+        _ => out.len(),
+    }
+}
+
+fn element_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token {
+            Token::Identifier(word) => {
+                if text.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    text.push(' ');
+                }
+                text.push_str(word);
+            }
+            // `geo::Point` is `geo_Point` in C
+            Token::Symbol(s) if s == "::" => text.push('_'),
+            Token::Symbol(s) => text.push_str(s),
+            _ => {}
+        }
+    }
+    text
+}
+
+fn matching_bracket(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "[" || s == "(" => depth += 1,
+            Token::Symbol(s) if s == "]" || s == ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Whether the line being copied is a preprocessor line
+fn starts_with_hash(out: &[Token]) -> bool {
+    let line_start = out.iter().rposition(|t| matches!(t, Token::Newline)).map_or(0, |p| p + 1);
+    is_symbol(out.get(line_start), "#")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slices_become_structs() {
+        let tokens = tokenize("int sum(int[] xs) {\n  return xs[0] + xs[xs.len - 1];\n}");
+        let output = detokenize(&rewrite(&tokens, "main.z", false));
+        assert!(output.contains("typedef struct { int *ptr; size_t len; } __z_slice_int;"));
+        assert!(output.contains("int sum(__z_slice_int xs) {\nreturn xs.ptr[0] + xs.ptr[xs.len - 1];"));
+        assert_eq!(struct_name("const char*"), "__z_slice_const_char_ptr");
+    }
+
+    #[test]
+    fn test_bounds_checked_index_reports_line() {
+        let tokens = tokenize("#include <stdio.h>\nvoid f(char*[] names) {\n\n  puts(names[1]);\n}");
+        let output = detokenize(&rewrite(&tokens, "main.z", true));
+        assert!(output.contains("names.ptr[__z_bounds_check(1, names.len, \"main.z\", 4)]"));
+        // The typedef comes after the include, right before the function
+        assert!(output.find("#include <stdio.h>").unwrap() < output.find("typedef struct { char **ptr").unwrap());
+    }
+}
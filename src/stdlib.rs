@@ -0,0 +1,39 @@
+// src/stdlib.rs
+//
+// The standard library ships inside the compiler so `#import <std/...>`
+// works without any files next to the program. An installed compiler reads
+// the modules from its `lib/std/` instead, see src/install.rs.
+
+use std::fs;
+use std::path::Path;
+
+/// Modules that need a system library of their own. When the manifest has
+/// `[features]`, they are only provided if a feature enables them, e.g.
+/// `net = ["std/net"]`
+pub const OPTIONAL: &[&str] = &["std/net.z", "std/thread.z"];
+
+/// The bundled modules by import path
+pub const BUNDLED: &[(&str, &str)] = &[
+    ("std/env.z", include_str!("../std/env.z")),
+    ("std/fs.z", include_str!("../std/fs.z")),
+    ("std/math.z", include_str!("../std/math.z")),
+    ("std/net.z", include_str!("../std/net.z")),
+    ("std/process.z", include_str!("../std/process.z")),
+    ("std/rand.z", include_str!("../std/rand.z")),
+    ("std/result.z", include_str!("../std/result.z")),
+    ("std/thread.z", include_str!("../std/thread.z")),
+    ("std/time.z", include_str!("../std/time.z")),
+];
+
+/// Source of a bundled module, looked up by its import path
+pub fn source(path: &str) -> Option<&'static str> {
+    BUNDLED.iter().find(|(p, _)| *p == path).map(|(_, source)| *source)
+}
+
+/// Source of the module `path`: the installed copy in `installed`, the
+/// directory holding `std/`, when there is one, or else the bundled one
+pub fn load(path: &str, installed: Option<&Path>) -> Option<String> {
+    let bundled = source(path)?;
+    let copy = installed.and_then(|dir| fs::read_to_string(dir.join(path)).ok());
+    Some(copy.unwrap_or_else(|| bundled.to_string()))
+}
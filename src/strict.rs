@@ -0,0 +1,323 @@
+// src/strict.rs
+//
+// `--strict`. Code at file or namespace scope that isn't a class, an
+// interface, an import or another Z item is kept as plain C and handed to
+// the C compiler as it is, so a typo such as `clas Point { ... }` or
+// `#improt <geo.z>` only comes back as a gcc error about the generated C.
+// Under `--strict` every such statement has to be something the compiler
+// recognizes, or it is an error:
+//
+//   * a preprocessor line with a directive C or Z knows
+//   * a declaration: type words, then declarators, each an optional `*`s,
+//     a name, `(...)` and `[...]`, and an optional `= initializer`, ending
+//     with `;`, e.g. `static const char* names[] = { "a" };`
+//   * a function definition: such a declaration with a body for its `;`
+//   * a `struct`, `union` or `enum` body in the type, e.g. in a `typedef`
+//   * `extern "C" { ... }`, `_Static_assert(...)` and `asm(...)`
+//
+// A declaration needs a type before its name, so a call such as
+// `init();` at file scope isn't one, nor is a use of a macro that expands
+// to declarations. Initializers are only checked for two operands in a
+// row, e.g. a missing `;` in `int x = 5 int y;`. Fields of a class that
+// aren't `Type name;` or `Type name[N];`, which are left out of its
+// struct, are errors too. Function bodies are checked as in any build.
+
+use crate::ast::{Class, Module};
+use crate::parser::{matching_close, strip_attributes};
+use crate::tokenizer::{detokenize, Token};
+use crate::visit::{walk_class, Visit};
+
+/// Directives a preprocessor line may use
+const DIRECTIVES: &[&str] = &[
+    "include", "define", "undef", "if", "ifdef", "ifndef", "elif", "elifdef", "elifndef", "else", "endif",
+    "error", "warning", "pragma", "line", "import", "link", "embed",
+];
+
+/// Words that start a statement with no type before it
+const STATEMENTS: &[&str] = &["_Static_assert", "static_assert", "asm", "__asm__"];
+
+/// Operators written as words, which an operand may follow
+const WORD_OPERATORS: &[&str] = &["sizeof", "_Alignof", "alignof", "__alignof__"];
+
+/// The errors `--strict` reports in `module`, as parsed
+pub fn check(module: &Module) -> Vec<String> {
+    let mut checker = Checker::default();
+    checker.visit_module(module);
+    checker.errors
+}
+
+#[derive(Default)]
+struct Checker {
+    errors: Vec<String>,
+}
+
+impl Visit for Checker {
+    fn visit_class(&mut self, class: &Class) {
+        for field in &class.unsupported_fields {
+            self.errors.push(format!("`{}` in class `{}` isn't a field the compiler recognizes, and would be left out of its struct (`--strict`)", field, class.name));
+        }
+        walk_class(self, class);
+    }
+
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        for statement in unrecognized(tokens) {
+            self.errors.push(format!("`{}` isn't a declaration, definition or directive the compiler recognizes, and would be passed to the C compiler as it is (`--strict`)", statement));
+        }
+    }
+}
+
+/// The statements of `tokens`, plain C at file or namespace scope, that
+/// aren't any the compiler recognizes, as written
+pub fn unrecognized(tokens: &[Token]) -> Vec<String> {
+    let tokens = strip_attributes(tokens);
+    let mut found = Vec::new();
+    let mut statement: Vec<Token> = Vec::new();
+    // Open `extern "C" {` blocks
+    let mut linkage = 0;
+    let mut line_start = true;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        match token {
+            Token::Newline => line_start = true,
+            Token::Comment(_) | Token::Eof => {}
+            Token::Symbol(s) if s == "#" && line_start => {
+                let end = line_end(&tokens, i);
+                if !is_directive(&tokens[i + 1..end]) {
+                    found.push(text(&tokens[i..end]));
+                }
+                i = end;
+                continue;
+            }
+            Token::Symbol(s) if s == "}" && statement.is_empty() && linkage > 0 => {
+                linkage -= 1;
+                line_start = false;
+            }
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => {
+                let Some(close) = matching_close(&tokens, i) else {
+                    statement.extend(tokens[i..].iter().cloned());
+                    break;
+                };
+                let linkage_block = s == "{" && is_linkage(&statement);
+                let initializer = statement.iter().any(|t| matches!(t, Token::Symbol(s) if s == "="));
+                let body = s == "{" && !linkage_block && !initializer && !is_type_body(&statement);
+                if linkage_block {
+                    linkage += 1;
+                    statement.clear();
+                    i += 1;
+                    line_start = false;
+                    continue;
+                }
+                let definition = body && is_definition(&statement);
+                statement.extend(tokens[i..=close].iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).cloned());
+                i = close + 1;
+                line_start = false;
+                // A function's body ends its definition; any other is a
+                // body of something unknown, up to a `;` after it
+                if body {
+                    if !definition {
+                        if matches!(next_significant(&tokens, i), Some(j) if tokens[j] == Token::Symbol(";".to_string())) {
+                            i = next_significant(&tokens, i).unwrap_or(i) + 1;
+                        }
+                        found.push(text(&statement));
+                    }
+                    statement.clear();
+                }
+                continue;
+            }
+            Token::Symbol(s) if s == ";" => {
+                statement.push(token.clone());
+                if !is_declaration(&statement) {
+                    found.push(text(&statement));
+                }
+                statement.clear();
+            }
+            _ => statement.push(token.clone()),
+        }
+        if !matches!(token, Token::Newline | Token::Comment(_)) {
+            line_start = false;
+        }
+        i += 1;
+    }
+    if !statement.is_empty() {
+        found.push(text(&statement));
+    }
+    found
+}
+
+// Index of the newline ending the preprocessor line at `start`, past any
+// continued with `\`
+fn line_end(tokens: &[Token], start: usize) -> usize {
+    let mut i = start;
+    while i < tokens.len() {
+        if tokens[i] == Token::Newline && !matches!(i.checked_sub(1).map(|p| &tokens[p]), Some(Token::Symbol(s)) if s == "\\") {
+            return i;
+        }
+        i += 1;
+    }
+    tokens.len()
+}
+
+// `directive ...` after a line's `#`; a line marker `# 12 "file"` too
+fn is_directive(line: &[Token]) -> bool {
+    match line.iter().find(|t| !matches!(t, Token::Comment(_))) {
+        Some(Token::Identifier(name)) => DIRECTIVES.contains(&name.as_str()),
+        Some(Token::Number(_)) | Some(Token::Newline) | None => true,
+        _ => false,
+    }
+}
+
+fn next_significant(tokens: &[Token], from: usize) -> Option<usize> {
+    (from..tokens.len()).find(|&j| !matches!(tokens[j], Token::Newline | Token::Comment(_)))
+}
+
+// `extern "C"`, before its `{`
+fn is_linkage(statement: &[Token]) -> bool {
+    matches!(statement, [Token::Identifier(word), Token::StringLit(_)] if word == "extern")
+}
+
+// `struct`, `union` or `enum` and maybe a tag, before a `{`
+fn is_type_body(statement: &[Token]) -> bool {
+    let keyword = |token: &Token| matches!(token, Token::Identifier(word) if matches!(word.as_str(), "struct" | "union" | "enum"));
+    match statement {
+        [.., last] if keyword(last) => true,
+        [.., before, Token::Identifier(_)] => keyword(before),
+        _ => false,
+    }
+}
+
+// Whether `head`, before a `{`, declares a function the `{` is the body of
+fn is_definition(head: &[Token]) -> bool {
+    // The parameters come last, but for `__attribute__((...))`s
+    let mut end = head.len();
+    while let Some(open) = end.checked_sub(1).and_then(|close| group_start(head, close)) {
+        match open.checked_sub(1) {
+            Some(word) if head[word] == Token::Identifier("__attribute__".to_string()) => end = word,
+            _ => break,
+        }
+    }
+    matches!(head[..end].last(), Some(Token::Symbol(s)) if s == ")") && is_head(&head[..end], true)
+}
+
+// Index of the `(` the `)` at `close` closes
+fn group_start(tokens: &[Token], close: usize) -> Option<usize> {
+    if tokens[close] != Token::Symbol(")".to_string()) {
+        return None;
+    }
+    let mut depth = 0usize;
+    for i in (0..=close).rev() {
+        match &tokens[i] {
+            Token::Symbol(s) if s == ")" => depth += 1,
+            Token::Symbol(s) if s == "(" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Type words and declarators ending with `;`
+fn is_declaration(statement: &[Token]) -> bool {
+    match statement {
+        [Token::Symbol(s)] if s == ";" => true,
+        [Token::Identifier(word), ..] if STATEMENTS.contains(&word.as_str()) => true,
+        [head @ .., _] => is_head(head, false),
+        [] => false,
+    }
+}
+
+// Whether `head`, a declaration without its `;` or body, is type words
+// then declarators; `definition` when a body follows it
+fn is_head(head: &[Token], definition: bool) -> bool {
+    let Some(Token::Identifier(_)) = head.first() else { return false };
+    let operand = |t: &Token| matches!(t, Token::Number(_)) || matches!(t, Token::Identifier(w) if !WORD_OPERATORS.contains(&w.as_str()));
+    let mut names = 0;
+    let mut type_body = false;
+    let mut initializer = false;
+    let mut depth = 0usize;
+    let mut previous: Option<&Token> = None;
+    for (i, token) in head.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => {
+                // `(*name)`, a declarator of a pointer to a function or an array
+                if depth == 0 && !initializer && s == "(" && matches!(head.get(i + 1), Some(Token::Symbol(star)) if star == "*") {
+                    names += 1;
+                }
+                type_body |= depth == 0 && !initializer && s == "{";
+                depth += 1;
+            }
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth = depth.saturating_sub(1),
+            // Parameters, sizes, type bodies and parts of initializers
+            _ if depth > 0 => {}
+            Token::Symbol(s) if s == "," => initializer = false,
+            // Two operands in a row
+            _ if initializer => {
+                if previous.is_some_and(operand) && operand(token) {
+                    return false;
+                }
+            }
+            Token::Symbol(s) if s == "=" && !definition => initializer = true,
+            Token::Identifier(_) => names += 1,
+            // `geo::Point` is one name
+            Token::Symbol(s) if s == "::" && matches!(previous, Some(Token::Identifier(_))) => names -= 1,
+            Token::StringLit(_) if matches!(previous, Some(Token::Identifier(w)) if w == "extern") => {}
+            Token::Symbol(s) if s == "*" => {}
+            _ => return false,
+        }
+        previous = Some(token);
+    }
+    names >= 2 || (type_body && names >= 1)
+}
+
+// The statement as written, its first line and without what is inside its
+// braces
+fn text(tokens: &[Token]) -> String {
+    let text = match tokens.iter().position(|t| *t == Token::Symbol("{".to_string())) {
+        Some(open) => format!("{} {{ ... }}", detokenize(&tokens[..open]).trim()),
+        None => detokenize(tokens).trim().to_string(),
+    };
+    match text.split_once('\n') {
+        Some((first, _)) => format!("{} ...", first.trim_end()),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn test_only_recognized_statements_pass() {
+        let source = r#"#include <stdio.h>
+#define MAX(a, b) \
+    ((a) > (b) ? (a) : (b))
+#improt <geo.z>
+static const char* names[] = { "a", "b" };
+typedef struct { int x; } P;
+enum Color { RED, GREEN };
+int (*handler)(int);
+@inline static int twice(int n) __attribute__((unused)) { return n * 2; }
+extern "C" {
+void f(void);
+}
+_Static_assert(sizeof(int) == 4, "int");
+geo::Point origin(void) { return geo::make(0, 0); }
+static const unsigned long long big = 1ULL << 40, mask = 0xFFul;
+float half = 1.5f, tiny = 1e-3F;
+clas Point { int x; };
+init();
+int x = 5 int y;
+int z = sizeof x, w = MAX(1, 2)
+"#;
+        let expected: Vec<String> = ["#improt <geo.z>", "clas Point { ... }", "init();", "int x = 5 int y;", "int z = sizeof x, w = MAX(1, 2)"]
+            .iter().map(|s| text(&tokenize(s))).collect();
+        assert_eq!(unrecognized(&tokenize(source)), expected);
+        // Suffixes are part of their numbers
+        assert_eq!(detokenize(&tokenize("long n = 1ULL + 2lu;")), "long n = 1ULL + 2lu;");
+    }
+}
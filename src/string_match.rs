@@ -0,0 +1,353 @@
+// src/string_match.rs
+//
+// `match (s) { "add" => ..., "del" | "rm" => { ... }, _ => ... }` on a
+// `char*`. Each arm is a block or a statement up to its `,`; `_` is the
+// default arm. A match becomes a chain of `strcmp` tests; one with many
+// arms first switches on a hash of the string, so a lookup costs one hash
+// and a comparison or two. Rewritten on the token stream before parsing;
+// the arms are left for the later passes like any other code.
+
+use crate::parser::{code, is_symbol};
+use crate::tokenizer::{generated, Token};
+
+/// Arms (besides `_`) from which a match switches on a hash
+pub const HASH_ARMS: usize = 8;
+
+const RUNTIME: &str = "#include <string.h>\n";
+
+const HASH_RUNTIME: &str = "#ifndef __Z_STR_HASH
+#define __Z_STR_HASH
+static inline unsigned int __z_str_hash(const char* s) {
+  unsigned int h = 2166136261;
+  while (*s) { h ^= (unsigned char)*s++; h *= 16777619; }
+  return h;
+}
+#endif
+";
+
+// A `match` found in the token stream
+struct Match {
+    scrutinee: Vec<Token>,
+    /// Or why they don't parse
+    arms: Result<Vec<Arm>, String>,
+    /// Index after the closing `}`
+    end: usize,
+}
+
+struct Arm {
+    /// String literals as written, quotes included; empty for `_`
+    patterns: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Rewrites every string `match`, returning the new tokens and the errors
+/// found in malformed matches, which are left as they are
+pub fn rewrite(tokens: &[Token]) -> (Vec<Token>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut hashed = false;
+    let mut next_id = 0;
+    let out = rewrite_tokens(tokens, &mut errors, &mut hashed, &mut next_id);
+    if next_id == 0 {
+        return (out, errors);
+    }
+    let mut runtime = RUNTIME.to_string();
+    if hashed {
+        runtime.push_str(HASH_RUNTIME);
+    }
+    let mut with_runtime = generated(&runtime, 1);
+    with_runtime.extend(out);
+    (with_runtime, errors)
+}
+
+fn rewrite_tokens(tokens: &[Token], errors: &mut Vec<String>, hashed: &mut bool, next_id: &mut usize) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some(Match { scrutinee, arms, end }) = parse_match(tokens, i, &out) {
+            match arms {
+                Ok(arms) => {
+                    let id = *next_id;
+                    *next_id += 1;
+                    let arms: Vec<Arm> = arms
+                        .into_iter()
+                        .map(|arm| Arm { patterns: arm.patterns, body: rewrite_tokens(&arm.body, errors, hashed, next_id) })
+                        .collect();
+                    let hash = arms.iter().filter(|arm| !arm.patterns.is_empty()).count() >= HASH_ARMS;
+                    *hashed |= hash;
+                    out.extend(lower(&scrutinee, &arms, id, hash));
+                    i = end;
+                    continue;
+                }
+                Err(message) => errors.push(message),
+            }
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out
+}
+
+// `match (scrutinee) { arms }` starting at `start`, or `None` when this
+// isn't a string match at all, e.g. a function named `match`
+fn parse_match(tokens: &[Token], start: usize, before: &[Token]) -> Option<Match> {
+    if !matches!(&tokens[start], Token::Identifier(word) if word == "match") {
+        return None;
+    }
+    // A statement can't follow a type name, so `int match(...) {` is a function
+    if matches!(before.iter().rev().find(|t| !is_trivia(t)), Some(Token::Identifier(word)) if word != "else") {
+        return None;
+    }
+    let open = next_code(tokens, start + 1)?;
+    if !is_symbol(tokens.get(open), "(") {
+        return None;
+    }
+    let close = matching(tokens, open)?;
+    let brace = next_code(tokens, close + 1)?;
+    if !is_symbol(tokens.get(brace), "{") {
+        return None;
+    }
+    let end = matching(tokens, brace)?;
+    let body = &tokens[brace + 1..end];
+    if !top_level(body).any(|i| is_symbol(body.get(i), "=>")) {
+        return None;
+    }
+    Some(Match { scrutinee: tokens[open + 1..close].to_vec(), arms: parse_arms(body), end: end + 1 })
+}
+
+fn parse_arms(body: &[Token]) -> Result<Vec<Arm>, String> {
+    let mut arms: Vec<Arm> = Vec::new();
+    let mut i = 0;
+    while let Some(start) = next_code(body, i) {
+        if arms.last().is_some_and(|arm| arm.patterns.is_empty()) {
+            return Err("the `_` arm of a `match` must come last".to_string());
+        }
+        // Patterns up to `=>`
+        let arrow = (start..body.len()).find(|&k| is_symbol(body.get(k), "=>")).ok_or("`match` arm without `=>`")?;
+        let mut patterns = Vec::new();
+        let mut wildcard = false;
+        for (k, token) in body[start..arrow].iter().filter(|t| !is_trivia(t)).enumerate() {
+            match token {
+                _ if k % 2 == 1 && is_symbol(Some(token), "|") => {}
+                Token::StringLit(literal) if k % 2 == 0 => patterns.push(literal.clone()),
+                Token::Identifier(word) if k == 0 && word == "_" => wildcard = true,
+                _ => return Err("`match` arm patterns must be string literals separated by `|`, or `_`".to_string()),
+            }
+        }
+        if wildcard != patterns.is_empty() {
+            return Err("`match` arm patterns must be string literals separated by `|`, or `_`".to_string());
+        }
+        for pattern in &patterns {
+            if arms.iter().any(|arm| arm.patterns.contains(pattern)) {
+                return Err(format!("{} is matched by more than one arm", pattern));
+            }
+        }
+
+        // A block, or a statement up to `,` or `;`
+        let body_start = next_code(body, arrow + 1).ok_or("`match` arm without a body")?;
+        let (arm_body, next) = if is_symbol(body.get(body_start), "{") {
+            let close = matching(body, body_start).ok_or("unclosed `match` arm")?;
+            let after = next_code(body, close + 1).filter(|&k| is_symbol(body.get(k), ",")).map_or(close + 1, |k| k + 1);
+            (body[body_start..=close].to_vec(), after)
+        } else {
+            let end = top_level(&body[body_start..]).map(|k| body_start + k).find(|&k| is_symbol(body.get(k), ",") || is_symbol(body.get(k), ";"));
+            let end = end.unwrap_or(body.len());
+            let mut statement = code("{");
+            statement.extend(body[body_start..end].iter().cloned());
+            statement.extend(code("; }"));
+            (statement, end + 1)
+        };
+        arms.push(Arm { patterns, body: arm_body });
+        i = next;
+    }
+    Ok(arms)
+}
+
+// The `if` chain, or with `hash` the switch picking an arm and the `if`
+// chain over arm numbers
+fn lower(scrutinee: &[Token], arms: &[Arm], id: usize, hash: bool) -> Vec<Token> {
+    let subject = format!("__z_match_{}", id);
+    let mut out = code(&format!("{{ const char* {} = (", subject));
+    out.extend(scrutinee.iter().cloned());
+    out.extend(code(");"));
+
+    let test = |arm_index: usize, arm: &Arm| -> String {
+        if hash {
+            format!("__z_arm_{} == {}", id, arm_index)
+        } else {
+            arm.patterns.iter().map(|p| format!("strcmp({}, {}) == 0", subject, p)).collect::<Vec<_>>().join(" || ")
+        }
+    };
+    if hash {
+        out.extend(code(&hash_switch(&subject, arms, id)));
+    }
+    for (index, arm) in arms.iter().enumerate() {
+        let keyword = if index == 0 { "" } else { "else " };
+        if arm.patterns.is_empty() {
+            out.extend(code(if index == 0 { "" } else { "else" }));
+        } else {
+            out.extend(code(&format!("{}if ({})", keyword, test(index, arm))));
+        }
+        out.extend(arm.body.iter().cloned());
+    }
+    out.extend(code("}"));
+    out
+}
+
+// `int __z_arm_N = -1; switch (__z_str_hash(s)) { case h: ... }`, with the
+// patterns whose hashes collide compared in turn under the same `case`
+fn hash_switch(subject: &str, arms: &[Arm], id: usize) -> String {
+    let mut cases: Vec<(u32, Vec<(&str, usize)>)> = Vec::new();
+    for (index, arm) in arms.iter().enumerate() {
+        for pattern in &arm.patterns {
+            let hash = fnv1a(&unescape(&pattern[1..pattern.len() - 1]));
+            match cases.iter_mut().find(|(h, _)| *h == hash) {
+                Some((_, patterns)) => patterns.push((pattern, index)),
+                None => cases.push((hash, vec![(pattern, index)])),
+            }
+        }
+    }
+    let mut code = format!("int __z_arm_{} = -1; switch (__z_str_hash({})) {{", id, subject);
+    for (hash, patterns) in cases {
+        let tests: Vec<String> = patterns
+            .iter()
+            .map(|(pattern, index)| format!("if (strcmp({}, {}) == 0) __z_arm_{} = {};", subject, pattern, id, index))
+            .collect();
+        code.push_str(&format!(" case {}: {} break;", hash, tests.join(" else ")));
+    }
+    code.push_str(" }");
+    code
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(2166136261u32, |h, &b| (h ^ b as u32).wrapping_mul(16777619))
+}
+
+// The bytes a C string literal's contents stand for
+fn unescape(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 == bytes.len() {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+        let escape = bytes[i];
+        i += 1;
+        match escape {
+            b'n' => out.push(b'\n'),
+            b't' => out.push(b'\t'),
+            b'r' => out.push(b'\r'),
+            b'0'..=b'7' => {
+                let mut value = (escape - b'0') as u32;
+                for _ in 0..2 {
+                    match bytes.get(i) {
+                        Some(&d @ b'0'..=b'7') => {
+                            value = value * 8 + (d - b'0') as u32;
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                out.push(value as u8);
+            }
+            b'x' => {
+                let mut value = 0u32;
+                while let Some(digit) = bytes.get(i).and_then(|&d| (d as char).to_digit(16)) {
+                    value = value * 16 + digit;
+                    i += 1;
+                }
+                out.push(value as u8);
+            }
+            b'a' => out.push(7),
+            b'b' => out.push(8),
+            b'f' => out.push(12),
+            b'v' => out.push(11),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+// Indices of `tokens` outside of any brackets
+fn top_level(tokens: &[Token]) -> impl Iterator<Item = usize> + '_ {
+    let mut depth = 0i32;
+    tokens.iter().enumerate().filter_map(move |(i, token)| {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            _ if depth == 0 => return Some(i),
+            _ => {}
+        }
+        None
+    })
+}
+
+fn matching(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn next_code(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&i| !is_trivia(&tokens[i]))
+}
+
+fn is_trivia(token: &Token) -> bool {
+    matches!(token, Token::Newline | Token::Comment(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    fn rewritten(source: &str) -> (String, Vec<String>) {
+        let (tokens, errors) = rewrite(&tokenize(source));
+        (detokenize(&tokens), errors)
+    }
+
+    #[test]
+    fn test_match_becomes_strcmp_chain() {
+        let (output, errors) = rewritten("match (cmd) {\n  \"add\" | \"plus\" => total += n,\n  \"del\" => { total -= n; }\n  _ => usage();\n}");
+        assert!(errors.is_empty());
+        assert!(output.starts_with("#include <string.h>\n"));
+        assert!(output.contains("{ const char *__z_match_0 = (cmd); if (strcmp(__z_match_0, \"add\") == 0 || strcmp(__z_match_0, \"plus\") == 0) { total += n; } else if (strcmp(__z_match_0, \"del\") == 0) { total -= n; } else { usage(); } }"));
+    }
+
+    #[test]
+    fn test_many_arms_switch_on_a_hash() {
+        let arms: Vec<String> = (0..HASH_ARMS).map(|i| format!("\"w{}\" => n = {},", i, i)).collect();
+        let (output, _) = rewritten(&format!("match (word) {{ {} _ => n = -1; }}", arms.join(" ")));
+        assert!(output.contains("switch (__z_str_hash(__z_match_0)) { case 256325234 : if (strcmp(__z_match_0, \"w0\") == 0) __z_arm_0 = 0; break;"));
+        assert!(output.contains("} if (__z_arm_0 == 0) { n = 0; } else if (__z_arm_0 == 1)"));
+        assert!(output.contains("else if (__z_arm_0 == 7) { n = 7; } else { n = -1; } }"));
+        assert_eq!(fnv1a(b""), 2166136261);
+        assert_eq!(fnv1a(b"a"), 0xe40c292c);
+        assert_eq!(unescape("a\\n\\x41\\101\\\""), b"a\nAA\"");
+    }
+
+    #[test]
+    fn test_match_errors_and_lookalikes() {
+        let (output, errors) = rewritten("int match(int x) { return x; }\nmatch (s) { \"a\" => f(), x => g(), }");
+        assert!(output.starts_with("int match(int x) { return x; }"));
+        assert_eq!(errors, vec!["`match` arm patterns must be string literals separated by `|`, or `_`"]);
+        let (_, errors) = rewritten("match (s) { _ => f(), \"a\" => g(), }");
+        assert_eq!(errors, vec!["the `_` arm of a `match` must come last"]);
+        let (_, errors) = rewritten("match (s) { \"a\" => f(), \"a\" => g(), }");
+        assert_eq!(errors, vec!["\"a\" is matched by more than one arm"]);
+    }
+}
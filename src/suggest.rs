@@ -0,0 +1,207 @@
+// src/suggest.rs
+//
+// "Did you mean" for namespace paths. A path such as `math::aera` whose
+// namespace is known but whose member isn't, or whose namespace nobody
+// declared, is compared with every member of every namespace: one
+// differing only in case, or by a typo or two, is offered in its place.
+// The members of the standard library's modules are candidates too, so a
+// path into a module the file doesn't import says which `#import` brings
+// it in.
+//
+// Paths nothing comes close to are left alone; they may name what the
+// namespace table doesn't track, e.g. a `static` function, and the C
+// compiler reports them if not.
+
+use std::collections::HashSet;
+
+use crate::namespaces::{declared, NamespaceTable};
+use crate::parser::parse_module;
+use crate::stdlib;
+use crate::tokenizer::{line_numbers, tokenize, Token};
+
+/// What a path that names nothing may have meant
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// The path that was written, e.g. `math::aera`
+    pub written: String,
+    /// The path of the member, e.g. `math::area`
+    pub path: String,
+    /// The file declaring the member
+    pub file: String,
+    /// Whether the member is in a standard library module nothing imports
+    pub needs_import: bool,
+}
+
+/// The paths in `tokens` that name no member of `table`, with what each
+/// may have meant. `is_type` tells the classes and enums that aren't
+/// namespaces, e.g. `Color` of `Color::Red`
+pub fn unresolved(tokens: &[Token], table: &NamespaceTable, is_type: impl Fn(&str) -> bool) -> Vec<Suggestion> {
+    let local = defined_functions(tokens);
+    let mut candidates: Vec<(String, String, bool)> = table.iter().map(|(path, name, member)| (format!("{}::{}", path, name), member.file.clone(), false)).collect();
+    let mut std_members = None;
+    let mut suggestions = Vec::new();
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some(segments) = path_at(tokens, i) else {
+            i += 1;
+            continue;
+        };
+        i += segments.len() * 2 - 1;
+        // Through an alias or re-export, which is rewritten later
+        if table.canonical(&segments.join("::")).is_some() {
+            continue;
+        }
+        // The longest prefix that is a namespace
+        let known = (1..segments.len()).take_while(|&k| table.has_namespace(&segments[..k].join("::"))).last().unwrap_or(0);
+        if known == 0 && is_type(segments[0]) {
+            continue;
+        }
+        if known > 0 && table.lookup(&segments[..known].join("::"), segments[known]).is_some() {
+            continue;
+        }
+        if local.contains(segments[known]) || local.contains(segments[segments.len() - 1]) {
+            continue;
+        }
+
+        // The standard library's members are only read once a path needs them
+        let std_members = std_members.get_or_insert_with(|| {
+            let members = standard_members(table);
+            candidates.extend(members.iter().cloned());
+            members
+        });
+        let prefixes: Vec<String> = (known.max(1) + 1..=segments.len()).map(|j| segments[..j].join("::")).collect();
+        let exact = prefixes.iter().find_map(|written| std_members.iter().find(|(path, _, _)| path == written).map(|member| (written, member)));
+        let found = exact.or_else(|| {
+            prefixes.iter()
+                .filter_map(|written| {
+                    let path = closest(written, candidates.iter().map(|(path, _, _)| path.as_str()))?;
+                    Some((distance(&written.to_lowercase(), &path.to_lowercase()), written, path))
+                })
+                .min_by_key(|(distance, _, _)| *distance)
+                .and_then(|(_, written, path)| Some((written, candidates.iter().find(|(p, _, _)| p == path)?)))
+        });
+        if let Some((written, (path, file, needs_import))) = found {
+            let suggestion = Suggestion { written: written.clone(), path: path.clone(), file: file.clone(), needs_import: *needs_import };
+            if !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+    }
+    suggestions
+}
+
+/// The candidate closest to `written`: one equal but for case, or else
+/// the nearest within a typo per three characters of the last name
+pub fn closest<'a>(written: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let lower = written.to_lowercase();
+    let name = written.rsplit("::").next().unwrap_or(written);
+    let most = (name.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == written {
+            continue;
+        }
+        let d = if candidate.to_lowercase() == lower { 0 } else { distance(written, candidate) };
+        if d <= most && best.is_none_or(|(best, _)| d < best) {
+            best = Some((d, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+/// Edits that turn `a` into `b`: inserting, removing or changing a
+/// character, or swapping two next to each other
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1).min(row[j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The line of `text` declaring `name`: a function, class or global of
+/// that name after its type
+pub fn declaration_line(text: &str, name: &str) -> Option<usize> {
+    let tokens = tokenize(text);
+    let lines = line_numbers(&tokens);
+    (1..tokens.len().saturating_sub(1)).find(|&i| {
+        let typed = matches!(&tokens[i - 1], Token::Identifier(_)) || matches!(&tokens[i - 1], Token::Symbol(s) if s == "*");
+        let follows = matches!(&tokens[i + 1], Token::Symbol(s) if matches!(s.as_str(), "(" | "{" | ";" | "="));
+        matches!(&tokens[i], Token::Identifier(word) if word == name) && typed && follows
+    }).map(|i| lines[i])
+}
+
+// The segments of the path starting at `i`, e.g. `math`, `area`; none
+// unless the path starts there and has two segments at least
+fn path_at(tokens: &[Token], i: usize) -> Option<Vec<&str>> {
+    let is = |i: usize, s: &str| matches!(tokens.get(i), Some(Token::Symbol(t)) if t == s);
+    let identifier = |i: usize| match tokens.get(i) {
+        Some(Token::Identifier(name)) => Some(name.as_str()),
+        _ => None,
+    };
+    let first = identifier(i)?;
+    if i > 0 && (is(i - 1, "::") || is(i - 1, ".") || is(i - 1, "->")) {
+        return None;
+    }
+    // Declarations and exports name namespaces rather than use them
+    if i > 0 && matches!(identifier(i - 1), Some("namespace" | "export" | "using" | "as")) {
+        return None;
+    }
+    let mut segments = vec![first];
+    let mut j = i + 1;
+    while is(j, "::") {
+        segments.push(identifier(j + 1)?);
+        j += 2;
+    }
+    (segments.len() > 1).then_some(segments)
+}
+
+// Names of the functions `tokens` defines or declares, `static` ones too
+fn defined_functions(tokens: &[Token]) -> HashSet<&str> {
+    tokens.windows(3).filter_map(|window| match window {
+        [Token::Identifier(_), Token::Identifier(name), Token::Symbol(open)] if open == "(" => Some(name.as_str()),
+        _ => None,
+    }).collect()
+}
+
+// (path, file, whether it needs an import) of the members of the standard
+// library's modules that `table` doesn't have yet
+fn standard_members(table: &NamespaceTable) -> Vec<(String, String, bool)> {
+    let mut members = Vec::new();
+    for (file, source) in stdlib::BUNDLED {
+        let module = parse_module(&tokenize(source), "std");
+        for (path, name, _) in declared(&module, file) {
+            if table.lookup(&path, &name).is_none() {
+                members.push((format!("{}::{}", path, name), file.to_string(), true));
+            }
+        }
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_names_are_suggested() {
+        assert_eq!(distance("area", "aera"), 1);
+        assert_eq!(distance("Vectr", "Vector"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(closest("math::vector", ["math::Vector", "math::vectors"]), Some("math::Vector"));
+        assert_eq!(closest("math::aera", ["math::area", "math::arena"]), Some("math::area"));
+        assert_eq!(closest("math::sqrt", ["math::area"]), None);
+        assert_eq!(declaration_line("namespace math {\n  class Vector {\n    int x;\n  }\n  int area(int w) { return w; }\n}\n", "area"), Some(5));
+    }
+}
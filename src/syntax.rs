@@ -0,0 +1,159 @@
+// src/syntax.rs
+//
+// The front end as a supported API for tools (formatters, linters, editor
+// plugins): tokens with their spans, the item tree and the visitors over
+// it. Everything re-exported here follows the crate's semver; the passes,
+// the compiler driver and the helpers they share may change in any release.
+//
+// Spans are per token. Item tree nodes keep their tokens but no positions,
+// so a tool that needs one looks the node's tokens up in the spanned stream.
+
+pub use crate::ast::{
+    Attribute, Class, Function, Global, Import, Item, Loop, LoopKind, Module, Namespace, OperatorOverload, Stmt, Switch, Variable, ATTRIBUTES,
+};
+pub use crate::tokenizer::{detokenize, tokenize, tokenize_spanned, Span, Token};
+pub use crate::visit::{Visit, VisitMut};
+
+/// Parses a source file into its item tree. `file_tag` is the mangling
+/// prefix for anonymous namespaces and should be unique per file.
+pub fn parse(source: &str, file_tag: &str) -> Module {
+    parse_tokens(&tokenize(source), file_tag)
+}
+
+/// `parse` for an already tokenized file
+pub fn parse_tokens(tokens: &[Token], file_tag: &str) -> Module {
+    crate::parser::parse_module(tokens, file_tag)
+}
+
+/// A problem `parse_forgiving` found and worked around
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Parses source that may be incomplete or malformed, e.g. a file being
+/// edited or fuzzer input. Never panics: unterminated literals and
+/// comments are reported and kept, closing brackets that match nothing are
+/// dropped, and brackets still open at the end are closed there. Brackets
+/// nested deeper than `max-nesting-depth` allows by default end the file
+/// where they do.
+pub fn parse_forgiving(source: &str, file_tag: &str) -> (Module, Vec<SyntaxError>) {
+    let mut errors = Vec::new();
+    let mut tokens = Vec::new();
+    // Open brackets with the span they were opened at
+    let mut open: Vec<(&str, Span)> = Vec::new();
+    let max_depth = crate::limits::Limits::default().max_nesting_depth;
+
+    for (token, span) in tokenize_spanned(source) {
+        let mut error = |message: String| errors.push(SyntaxError { message, span });
+        match &token {
+            Token::StringLit(text) if !is_terminated(text, '"') => error("unterminated string literal".to_string()),
+            Token::CharLit(text) if !is_terminated(text, '\'') => error("unterminated character literal".to_string()),
+            Token::Comment(text) if text.starts_with("/*") && (text.len() < 4 || !text.ends_with("*/")) => {
+                error("unterminated block comment".to_string())
+            }
+            Token::Symbol(s) if matches!(s.as_str(), "(" | "[" | "{") && open.len() == max_depth => {
+                error(format!("brackets nest more than {} deep; the rest of the file is left out", max_depth));
+                for (close, _) in open.drain(..).rev() {
+                    tokens.push(Token::Symbol(close.to_string()));
+                }
+                tokens.push(Token::Eof);
+                break;
+            }
+            Token::Symbol(s) if matches!(s.as_str(), "(" | "[" | "{") => open.push((closing(s), span)),
+            Token::Symbol(s) if matches!(s.as_str(), ")" | "]" | "}") => {
+                if open.last().map(|(close, _)| *close) == Some(s.as_str()) {
+                    open.pop();
+                } else {
+                    error(format!("unmatched `{}`", s));
+                    continue;
+                }
+            }
+            Token::Eof => {
+                for (close, span) in open.drain(..).rev() {
+                    errors.push(SyntaxError { message: format!("missing `{}`", close), span });
+                    tokens.push(Token::Symbol(close.to_string()));
+                }
+            }
+            _ => {}
+        }
+        tokens.push(token);
+    }
+    (parse_tokens(&tokens, file_tag), errors)
+}
+
+fn closing(open: &str) -> &'static str {
+    match open {
+        "(" => ")",
+        "[" => "]",
+        _ => "}",
+    }
+}
+
+// Whether a literal read by the tokenizer ends with its closing quote
+fn is_terminated(text: &str, quote: char) -> bool {
+    let mut chars = text.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            c if c == quote => return chars.next().is_none(),
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_point_into_the_source() {
+        let source = "class P {\n  int x;\n}\nint  main() {}";
+        let tokens = tokenize_spanned(source);
+        for (token, span) in &tokens {
+            if let Token::Identifier(text) | Token::Symbol(text) = token {
+                assert_eq!(&source[span.start..span.end], text);
+            }
+        }
+        let (_, x) = tokens.iter().find(|(t, _)| *t == Token::Identifier("x".to_string())).unwrap();
+        assert_eq!((x.line, x.column), (2, 7));
+        let (_, main) = tokens.iter().find(|(t, _)| *t == Token::Identifier("main".to_string())).unwrap();
+        assert_eq!((main.line, main.column), (4, 6));
+        assert_eq!(tokens.last().unwrap(), &(Token::Eof, Span { start: source.len(), end: source.len(), line: 4, column: 15 }));
+    }
+
+    #[test]
+    fn test_parse_forgiving_repairs_brackets() {
+        let (module, errors) = parse_forgiving("class P { int x; int get() { return self.x; }\n) \"open", "main");
+        let Item::Class(class) = &module.items[0] else { panic!("expected a class") };
+        assert_eq!(class.functions[0].name, "get");
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["unmatched `)`", "unterminated string literal", "missing `}`"]);
+        assert_eq!((errors[0].span.line, errors[2].span.column), (2, 9));
+        let (_, errors) = parse_forgiving(&format!("int f() {{ {} }}", "{".repeat(20000)), "main");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "brackets nest more than 256 deep; the rest of the file is left out");
+        assert!(is_terminated("'\\''", '\'') && is_terminated("\"a\\\\\"", '"') && !is_terminated("\"a\\\"", '"'));
+    }
+
+    #[test]
+    fn test_parse_forgiving_takes_any_prefix() {
+        let source = "rc class Nœud { Nœud next; @inline int f(int[] xs) { for (int x : xs) { break; } return 'c'; } }\nnamespace { int g = \"é\"; } /* end */";
+        for (end, _) in source.char_indices() {
+            parse_forgiving(&source[..end], "main");
+            parse_forgiving(&source[end..], "main");
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let module = parse("class P { int x; int get() { return self.x; } }", "main");
+        let Item::Class(class) = &module.items[0] else { panic!("expected a class") };
+        assert_eq!(class.name, "P");
+        assert_eq!(class.functions[0].name, "get");
+    }
+}
@@ -0,0 +1,104 @@
+// src/target_dir.rs
+//
+// The build directory, `target/` unless `[build] target-dir` in
+// tarnish.toml or `--target-dir` names another. It holds the generated C,
+// the objects and binaries built without `-o`, so a project's own files
+// are only ever next to the things it builds on purpose.
+//
+// Each build writes under a name of its own (`target/main.4711.c`) and
+// renames the finished file to its final name (`target/main.c`). A rename
+// replaces a file at once, so builds running side by side never read or
+// link a file another one is halfway through writing, and the last one to
+// finish leaves its files behind.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT: &str = "target";
+
+/// Written into every build directory; `clean` only removes directories
+/// that have it
+const MARKER: &str = ".tarnish-build";
+
+#[derive(Debug, Clone)]
+pub struct TargetDir {
+    root: PathBuf,
+    /// Tells this build's unfinished files from those of other builds
+    id: u32,
+}
+
+impl TargetDir {
+    pub fn new(root: impl Into<PathBuf>) -> TargetDir {
+        TargetDir { root: root.into(), id: std::process::id() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Creates the directory and its marker
+    pub fn create(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        fs::write(self.root.join(MARKER), "This directory is created by the z-lang compiler; `z-lang clean` removes it\n")
+    }
+
+    /// `file` in the directory, e.g. `target/main.c`
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.root.join(file)
+    }
+
+    /// Where this build writes `file` until it is finished, e.g.
+    /// `target/main.4711.c`
+    pub fn scratch(&self, file: &str) -> PathBuf {
+        let name = match file.rsplit_once('.') {
+            Some((stem, extension)) => format!("{}.{}.{}", stem, self.id, extension),
+            None => format!("{}.{}", file, self.id),
+        };
+        self.root.join(name)
+    }
+
+    /// Moves the scratch file of `file` to its final name
+    pub fn finish(&self, file: &str) -> io::Result<PathBuf> {
+        let path = self.path(file);
+        fs::rename(self.scratch(file), &path)?;
+        Ok(path)
+    }
+
+    /// Removes the directory; false when there was none
+    pub fn clean(&self) -> Result<bool, String> {
+        if !self.root.exists() {
+            return Ok(false);
+        }
+        if !self.root.join(MARKER).exists() {
+            return Err(format!("{} was not created by z-lang; not removing it", self.root.display()));
+        }
+        fs::remove_dir_all(&self.root).map(|_| true).map_err(|e| format!("cannot remove {}: {}", self.root.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_files_are_finished_and_cleaned() {
+        let root = std::env::temp_dir().join(format!("tarnish_target_dir_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let target = TargetDir::new(&root);
+        assert_eq!(target.clean(), Ok(false));
+        target.create().unwrap();
+        let scratch = target.scratch("main.c");
+        assert_eq!(scratch, root.join(format!("main.{}.c", std::process::id())));
+        assert_eq!(target.scratch("prog"), root.join(format!("prog.{}", std::process::id())));
+        fs::write(&scratch, "int main() { return 0; }").unwrap();
+        assert_eq!(target.finish("main.c").unwrap(), root.join("main.c"));
+        assert!(!scratch.exists() && root.join("main.c").exists());
+        assert_eq!(target.clean(), Ok(true));
+        assert!(!root.exists());
+
+        fs::create_dir_all(&root).unwrap();
+        assert!(target.clean().unwrap_err().ends_with("was not created by z-lang; not removing it"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
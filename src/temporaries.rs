@@ -0,0 +1,327 @@
+// src/temporaries.rs
+//
+// When the operands of an overloaded operator are evaluated. Lowering
+// turns `a.twice() + b * c` into nested calls,
+// `V_operator_add(V_twice(a), V_operator_mul(b, c))`, and C evaluates the
+// arguments of a call in no particular order, so either operand could run
+// first. Z evaluates them left to right: each operand of an operator call
+// that calls something itself is materialized into a named temporary,
+// declared before the statement in the order the operands are written,
+// and the operator is handed the temporary:
+//
+//     V __z_tmp0 = V_twice(a);
+//     V __z_tmp1 = V_operator_mul(b, c);
+//     V d = V_operator_add(__z_tmp0, __z_tmp1);
+//
+// Each intermediate value is computed once, straight into its temporary,
+// and lives until the end of the statement; passing it to the operator is
+// the only copy made of it, as for any other class value. A statement that
+// isn't a declaration is wrapped in a block with its temporaries, so they
+// go out of scope with it and the body of an unbraced `if` stays one
+// statement. The declarators of a declaration and the operands of a `,`
+// are evaluated in order too, so the temporaries of each come after the
+// one before it: `V c = a + a, d = c + c;` becomes two declarations, and
+// `i++, c = c + c;` two statements.
+//
+// What C evaluates conditionally, or not at all, is left as it is:
+// statements with `&&`, `||`, `?:` or `sizeof` keep their nested calls, as
+// do the conditions of `if`, `switch` and loops. Values of `rc` classes
+// aren't materialized either; `@arena` looks after their temporaries. The
+// arguments of ordinary function calls keep C's unspecified order.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::operators::OPERATORS;
+use crate::parser::{code, is_symbol, matching_close, statement_end};
+use crate::tokenizer::Token;
+
+// Operands that aren't evaluated, or only sometimes
+const CONDITIONAL: &[&str] = &["&&", "||", "?", "sizeof", "_Alignof", "typeof", "__typeof__"];
+
+/// Rewrites the statements of function bodies, one token run at a time
+pub struct Materializer<'a> {
+    /// The operand types of every binary operator function, by C name: its
+    /// class, then the type of the right operand
+    binary: &'a HashMap<String, [String; 2]>,
+    /// Mangled names of the `rc` classes
+    rc: &'a HashSet<String>,
+    /// Open braces around the tokens; 0 at file scope, where nothing is
+    /// rewritten
+    depth: usize,
+    /// Temporaries declared so far in the body
+    next: usize,
+}
+
+impl<'a> Materializer<'a> {
+    pub fn new(binary: &'a HashMap<String, [String; 2]>, rc: &'a HashSet<String>) -> Materializer<'a> {
+        Materializer { binary, rc, depth: 0, next: 0 }
+    }
+
+    /// Starts on a function body, `depth` 1, or on code at file scope, 0
+    pub fn start(&mut self, depth: usize) {
+        self.depth = depth;
+        self.next = 0;
+    }
+
+    /// `tokens` with the operands of their operator calls materialized. A
+    /// run may open and close braces of the body it is part of
+    pub fn rewrite(&mut self, tokens: &[Token]) -> Vec<Token> {
+        let mut out = Vec::new();
+        // Whether a statement may start at the next token
+        let mut start = true;
+        let mut i = 0;
+        while i < tokens.len() {
+            match &tokens[i] {
+                Token::Symbol(s) if s == "{" => {
+                    self.depth += 1;
+                    start = true;
+                }
+                Token::Symbol(s) if s == "}" => {
+                    self.depth = self.depth.saturating_sub(1);
+                    start = true;
+                }
+                Token::Symbol(s) if s == ";" || s == ":" => start = true,
+                // The condition stays as it is
+                Token::Identifier(word) if matches!(word.as_str(), "if" | "switch" | "while" | "for") && is_symbol(tokens.get(i + 1), "(") => {
+                    if let Some(close) = matching_close(tokens, i + 1) {
+                        out.extend(tokens[i..=close].iter().cloned());
+                        i = close + 1;
+                        start = true;
+                        continue;
+                    }
+                }
+                Token::Identifier(word) if word == "else" || word == "do" => start = true,
+                Token::Identifier(word) if word == "case" => start = false,
+                Token::Newline | Token::Comment(_) | Token::Eof => {}
+                // `label:` and `default:`
+                Token::Identifier(_) if is_symbol(tokens.get(i + 1), ":") => {}
+                _ if start && self.depth > 0 => {
+                    if let Some(end) = statement_end(tokens, i) {
+                        out.extend(self.statement(&tokens[i..=end]));
+                        i = end + 1;
+                        continue;
+                    }
+                    start = false;
+                }
+                _ => start = false,
+            }
+            out.push(tokens[i].clone());
+            i += 1;
+        }
+        out
+    }
+
+    // `statement`, up to its `;`, preceded by its temporaries. The
+    // declarators of a declaration, and the operands of a `,` anywhere
+    // else, are evaluated in order, so each one's temporaries come after
+    // the one before it
+    fn statement(&mut self, statement: &[Token]) -> Vec<Token> {
+        let conditional = statement.iter().any(|t| matches!(t, Token::Symbol(s) | Token::Identifier(s) if CONDITIONAL.contains(&s.as_str())));
+        if conditional {
+            return statement.to_vec();
+        }
+        let declaration = is_declaration(statement);
+        let (semicolon, body) = statement.split_last().expect("a statement ends with `;`");
+        // `return a, b;` returns `b` once `a` is done
+        let (keyword, body) = match body.split_first() {
+            Some((word @ Token::Identifier(w), rest)) if w == "return" && !declaration => (Some(word), rest),
+            _ => (None, body),
+        };
+        let parts: Vec<(Vec<Token>, Vec<Token>)> = arguments(body).into_iter().map(|part| {
+            let mut temporaries = Vec::new();
+            let rewritten = self.materialize(part, &mut temporaries);
+            (temporaries, rewritten)
+        }).collect();
+        if parts.iter().all(|(temporaries, _)| temporaries.is_empty()) {
+            return statement.to_vec();
+        }
+
+        let mut out = Vec::new();
+        if declaration {
+            // A declarator with temporaries starts a declaration of its own
+            let base = base_type(body);
+            for (k, (temporaries, rewritten)) in parts.into_iter().enumerate() {
+                if k > 0 && temporaries.is_empty() {
+                    out.push(Token::Symbol(",".to_string()));
+                } else if k > 0 {
+                    out.push(semicolon.clone());
+                    out.extend(temporaries);
+                    out.extend(base.iter().cloned());
+                } else {
+                    out.extend(temporaries);
+                }
+                out.extend(rewritten);
+            }
+            out.push(semicolon.clone());
+            return out;
+        }
+        out.push(Token::Symbol("{".to_string()));
+        let last = parts.len() - 1;
+        for (k, (temporaries, rewritten)) in parts.into_iter().enumerate() {
+            out.extend(temporaries);
+            if k == last {
+                out.extend(keyword.cloned());
+            }
+            out.extend(rewritten);
+            out.push(semicolon.clone());
+        }
+        out.push(Token::Symbol("}".to_string()));
+        out
+    }
+
+    // `tokens` with every operand of an operator call that calls something
+    // replaced by a temporary, whose declaration goes to `temporaries`
+    fn materialize(&mut self, tokens: &[Token], temporaries: &mut Vec<Token>) -> Vec<Token> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let close = match &tokens[i] {
+                Token::Identifier(_) if is_symbol(tokens.get(i + 1), "(") => matching_close(tokens, i + 1),
+                _ => None,
+            };
+            let (Some(close), Token::Identifier(function)) = (close, &tokens[i]) else {
+                out.push(tokens[i].clone());
+                i += 1;
+                continue;
+            };
+            let types = self.operand_types(function);
+            out.extend(tokens[i..i + 2].iter().cloned());
+            for (k, argument) in arguments(&tokens[i + 2..close]).into_iter().enumerate() {
+                if k > 0 {
+                    out.push(Token::Symbol(",".to_string()));
+                }
+                let argument = self.materialize(argument, temporaries);
+                match types.as_ref().and_then(|types| types.get(k)) {
+                    Some(type_) if calls(&argument) && !self.rc.contains(type_.trim_start_matches("const ")) => {
+                        let name = format!("__z_tmp{}", self.next);
+                        self.next += 1;
+                        temporaries.extend(code(&format!("{} {} =", type_, name)));
+                        temporaries.extend(unparenthesized(&argument).iter().cloned());
+                        temporaries.push(Token::Symbol(";".to_string()));
+                        out.push(Token::Identifier(name));
+                    }
+                    _ => out.extend(argument),
+                }
+            }
+            out.push(tokens[close].clone());
+            i = close + 1;
+        }
+        out
+    }
+
+    // The types of the operands of `function` if it is an operator: its
+    // class, then the right operand's for a binary one
+    fn operand_types(&self, function: &str) -> Option<Vec<String>> {
+        if let Some(types) = self.binary.get(function) {
+            return Some(types.to_vec());
+        }
+        let (class, name) = function.rsplit_once("_operator_")?;
+        OPERATORS.iter().any(|op| op.arity == 1 && op.name == name).then(|| vec![class.to_string()])
+    }
+}
+
+// The arguments of a call, split at the commas between them; also the
+// declarators of a declaration and the operands of a `,`
+fn arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.iter().all(|t| matches!(t, Token::Newline | Token::Comment(_))) {
+        return Vec::new();
+    }
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut from = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol(s) if s == "(" || s == "[" || s == "{" => depth += 1,
+            Token::Symbol(s) if s == ")" || s == "]" || s == "}" => depth -= 1,
+            Token::Symbol(s) if s == "," && depth == 0 => {
+                arguments.push(&tokens[from..i]);
+                from = i + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(&tokens[from..]);
+    arguments
+}
+
+// The type words a declaration's later declarators share: `const V` of
+// `const V* p = ...`
+fn base_type(declaration: &[Token]) -> Vec<Token> {
+    let code: Vec<&Token> = declaration.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    let words = code.iter().take_while(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "*")).count();
+    let name = code[..words].iter().rposition(|t| matches!(t, Token::Identifier(_))).unwrap_or(0);
+    let stars = code[..name].iter().rev().take_while(|t| matches!(t, Token::Symbol(s) if s == "*")).count();
+    code[..name - stars].iter().map(|t| (*t).clone()).collect()
+}
+
+// Whether evaluating `tokens` calls a function
+fn calls(tokens: &[Token]) -> bool {
+    tokens.windows(2).any(|pair| matches!(pair, [Token::Identifier(_), Token::Symbol(open)] if open == "("))
+}
+
+// `tokens` without the parentheses around all of it, e.g. `(f(x))`
+fn unparenthesized(tokens: &[Token]) -> &[Token] {
+    match tokens {
+        [Token::Symbol(open), inner @ .., Token::Symbol(_)] if open == "(" && matching_close(tokens, 0) == Some(tokens.len() - 1) => unparenthesized(inner),
+        _ => tokens,
+    }
+}
+
+// `T x = ...;` or `const T* x = ...;`: type names, then the declared name
+fn is_declaration(statement: &[Token]) -> bool {
+    let code: Vec<&Token> = statement.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+    let words = code.iter().take_while(|t| matches!(t, Token::Identifier(_)) || matches!(t, Token::Symbol(s) if s == "*")).count();
+    let names = code[..words].iter().filter(|t| matches!(t, Token::Identifier(_))).count();
+    let keyword = matches!(code.first(), Some(Token::Identifier(word)) if word == "return" || word == "goto");
+    names >= 2 && !keyword && matches!(code.get(words), Some(Token::Symbol(s)) if matches!(s.as_str(), "=" | ";" | "[" | ","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::detokenize;
+
+    #[test]
+    fn test_operands_are_materialized_left_to_right() {
+        let binary = HashMap::from([
+            ("V_operator_add".to_string(), ["V".to_string(), "V".to_string()]),
+            ("V_operator_mul_float".to_string(), ["V".to_string(), "float".to_string()]),
+        ]);
+        let rc = HashSet::new();
+        let mut materializer = Materializer::new(&binary, &rc);
+        let mut rewrite = |text: &str| {
+            materializer.start(1);
+            detokenize(&materializer.rewrite(&code(text)))
+        };
+        assert_eq!(
+            rewrite("V d = V_operator_add((V_twice(a)), V_operator_mul_float(b, f(x)));"),
+            "V __z_tmp0 = V_twice(a); float __z_tmp1 = f(x); V __z_tmp2 = V_operator_mul_float(b, __z_tmp1); V d = V_operator_add(__z_tmp0, __z_tmp2);",
+        );
+        // A block keeps an unbraced body one statement
+        assert_eq!(
+            rewrite("if (V_operator_bool(V_operator_add(a, b))) d = V_operator_add(V_operator_increment(a), b); else return V_operator_add(a, b);"),
+            "if (V_operator_bool(V_operator_add(a, b))) { V __z_tmp0 = V_operator_increment(a); d = V_operator_add(__z_tmp0, b); } else return V_operator_add(a, b);",
+        );
+        // Conditionally evaluated operands stay nested
+        let conditional = "int n = ok && V_operator_bool(V_operator_add(V_twice(a), b));";
+        assert_eq!(rewrite(conditional), detokenize(&code(conditional)));
+        // Each declarator's temporaries come after the ones before it
+        assert_eq!(
+            rewrite("V c = V_operator_add(V_twice(a), a), *p = &c, d = V_operator_add(V_twice(c), c);"),
+            "V __z_tmp0 = V_twice(a); V c = V_operator_add(__z_tmp0, a), *p = &c; V __z_tmp1 = V_twice(c); V d = V_operator_add(__z_tmp1, c);",
+        );
+        // and the operands of a `,` after the ones before them
+        assert_eq!(
+            rewrite("i++, c = V_operator_add(V_twice(c), c);"),
+            "{ i++; V __z_tmp0 = V_twice(c); c = V_operator_add(__z_tmp0, c); }",
+        );
+        assert_eq!(
+            rewrite("return i++, V_operator_add(V_twice(c), c);"),
+            "{ i++; V __z_tmp0 = V_twice(c); return V_operator_add(__z_tmp0, c); }",
+        );
+        // Not at file scope
+        materializer.start(0);
+        let global = "V g = V_operator_add(V_twice(a), b);";
+        assert_eq!(detokenize(&materializer.rewrite(&code(global))), detokenize(&code(global)));
+    }
+}
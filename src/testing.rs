@@ -0,0 +1,172 @@
+// src/testing.rs
+//
+// `z-lang test`. Tests live next to the code they test, in two forms. A
+// `@test` function is a free function taking nothing and returning `void`:
+//
+//     @test void adds() { assert(add(2, 2) == 4); }
+//
+// It passes unless the program stops abnormally: a failed `assert`, an
+// uncaught `panic`, a crash, a non-zero `exit`. Builds other than test
+// builds leave `@test` functions out.
+//
+// Examples in doc comments are tests too: a block fenced with ``` in `///`
+// lines is the body of a function, compiled with the file it documents as a
+// program of its own, so examples can't interfere with each other and one
+// that doesn't compile fails alone. A block fenced as another language,
+// ```c or ```text, isn't Z and isn't run, nor is one fenced ```ignore.
+// Failures are reported at the line of the example's opening fence, and
+// those of a `@test` function at its own lines.
+//
+// The `main` of a test build runs the test its first argument names, and
+// lists them all without one; the driver runs the program once per test,
+// so a test that crashes takes no other down with it.
+
+use crate::ast::has_attribute;
+use crate::parser::function_definitions;
+use crate::tokenizer::{line_numbers, tokenize, Token};
+
+/// Name of the function an example's code becomes
+pub const EXAMPLE: &str = "__z_doc_example";
+
+/// A fenced block of Z in a doc comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+    pub file: String,
+    /// Line of the opening fence, from 1
+    pub line: usize,
+    pub code: String,
+}
+
+/// How a test went
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// Why, with what the program printed
+    Failed(String),
+}
+
+impl Example {
+    /// What the example is called in the report, e.g. `main.z:12 (doc example)`
+    pub fn name(&self) -> String {
+        format!("{}:{} (doc example)", self.file, self.line)
+    }
+
+    /// The program testing the example: `source`, the file it documents,
+    /// with the example as a `@test` function. A `#line` points what the C
+    /// compiler and `assert` say about it at the doc comment
+    pub fn program(&self, source: &str) -> String {
+        format!("{}\n@test void {}() {{\n#line {} \"{}\"\n{}\n}}\n", source, EXAMPLE, self.line + 1, self.file, self.code)
+    }
+}
+
+/// The examples in the doc comments of `source`, the contents of `file`
+pub fn examples(file: &str, source: &str) -> Vec<Example> {
+    let mut examples = Vec::new();
+    // The open fence: its line, whether it is Z, and the lines so far
+    let mut open: Option<(usize, bool, Vec<&str>)> = None;
+    for (index, line) in source.lines().enumerate() {
+        let doc = line.trim_start().strip_prefix("///").filter(|rest| !rest.starts_with('/'));
+        let Some(doc) = doc.map(|rest| rest.strip_prefix(' ').unwrap_or(rest)) else {
+            // A comment ending inside a fence has no example
+            open = None;
+            continue;
+        };
+        match (doc.trim_start().strip_prefix("```"), open.take()) {
+            (Some(info), None) => {
+                let info = info.trim();
+                open = Some((index + 1, info.is_empty() || info == "z", Vec::new()));
+            }
+            (Some(_), Some((line, z, code))) => {
+                if z {
+                    examples.push(Example { file: file.to_string(), line, code: code.join("\n") });
+                }
+            }
+            (None, Some((line, z, mut code))) => {
+                code.push(doc);
+                open = Some((line, z, code));
+            }
+            (None, None) => {}
+        }
+    }
+    examples
+}
+
+/// `tokens`, the file `file`, with a `#line` opening the body of each
+/// `@test` function, so what the C compiler and `assert` say about it
+/// points at the Z source, as for examples
+pub fn mark_lines(tokens: &[Token], file: &str) -> Vec<Token> {
+    let lines = line_numbers(tokens);
+    let mut out = tokens.to_vec();
+    // Last to first so earlier indices stay valid
+    for definition in function_definitions(tokens).iter().rev() {
+        if !has_attribute(&definition.attributes, "test") {
+            continue;
+        }
+        let Some(open) = definition.body_start(tokens) else { continue };
+        let directive = format!("\n#line {} \"{}\"\n", lines[open], file);
+        out.splice(open + 1..open + 1, tokenize(&directive).into_iter().filter(|t| !matches!(t, Token::Eof)));
+    }
+    out
+}
+
+/// The C `main` of a test build, running the test of `tests` named by its
+/// first argument
+pub fn runner(tests: &[String]) -> String {
+    let mut out = String::from("\n#include <stdio.h>\n#include <string.h>\nint __z_argc; char** __z_argv;\n");
+    out.push_str("int main(int argc, char** argv) {\n__z_argc = argc; __z_argv = argv;\nif (argc < 2) {");
+    for test in tests {
+        out.push_str(&format!(" puts(\"{}\");", test));
+    }
+    out.push_str(" return 0; }\n");
+    for test in tests {
+        out.push_str(&format!("if (strcmp(argv[1], \"{0}\") == 0) {{ {0}(); return 0; }}\n", test));
+    }
+    out.push_str("fprintf(stderr, \"no test named %s\\n\", argv[1]);\nreturn 2;\n}\n");
+    out
+}
+
+/// What `z-lang test` prints about `results`, a test's name and outcome each
+pub fn report(results: &[(String, Outcome)]) -> String {
+    let plural = if results.len() == 1 { "" } else { "s" };
+    let mut out = format!("running {} test{}\n", results.len(), plural);
+    for (name, outcome) in results {
+        let status = if *outcome == Outcome::Passed { "ok" } else { "FAILED" };
+        out.push_str(&format!("test {} ... {}\n", name, status));
+    }
+    let failed: Vec<(&String, &String)> = results.iter().filter_map(|(name, outcome)| match outcome {
+        Outcome::Failed(why) => Some((name, why)),
+        Outcome::Passed => None,
+    }).collect();
+    if !failed.is_empty() {
+        out.push_str("\nfailures:\n");
+        for (name, why) in &failed {
+            out.push_str(&format!("\n---- {} ----\n{}\n", name, why.trim_end()));
+        }
+    }
+    let result = if failed.is_empty() { "ok" } else { "FAILED" };
+    out.push_str(&format!("\ntest result: {}. {} passed; {} failed\n", result, results.len() - failed.len(), failed.len()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::detokenize;
+
+    #[test]
+    fn test_examples_come_from_doc_comments() {
+        let source = "/// Adds two numbers\n///\n/// ```\n/// int n = add(2, 2);\n///   assert(n == 4);\n/// ```\n/// ```c\n/// not Z\n/// ```\nint add(int a, int b) { return a + b; }\n// ```\n// not a doc comment\n// ```\n  ///```z\n  /// add(1, 1);\n  ///```\n/// ```\n/// unterminated\nint x;";
+        let examples = examples("lib.z", source);
+        assert_eq!(examples, vec![
+            Example { file: "lib.z".to_string(), line: 3, code: "int n = add(2, 2);\n  assert(n == 4);".to_string() },
+            Example { file: "lib.z".to_string(), line: 14, code: "add(1, 1);".to_string() },
+        ]);
+        assert_eq!(examples[1].name(), "lib.z:14 (doc example)");
+        let marked = mark_lines(&tokenize("int x;\n@test\nvoid adds()\n{ assert(1); }\nvoid f() { }"), "main.z");
+        assert_eq!(detokenize(&marked), detokenize(&tokenize("int x;\n@test\nvoid adds()\n{\n#line 4 \"main.z\"\n assert(1); }\nvoid f() { }")));
+        assert!(examples[1].program("int x;").ends_with("int x;\n@test void __z_doc_example() {\n#line 15 \"lib.z\"\nadd(1, 1);\n}\n"));
+
+        let report = report(&[("adds".to_string(), Outcome::Passed), ("lib.z:3 (doc example)".to_string(), Outcome::Failed("exited with status 134\n".to_string()))]);
+        assert_eq!(report, "running 2 tests\ntest adds ... ok\ntest lib.z:3 (doc example) ... FAILED\n\nfailures:\n\n---- lib.z:3 (doc example) ----\nexited with status 134\n\ntest result: FAILED. 1 passed; 1 failed\n");
+    }
+}
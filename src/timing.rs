@@ -0,0 +1,185 @@
+// src/timing.rs
+//
+// Per-pass instrumentation. Wall time is always recorded; memory numbers
+// come from `CountingAlloc`, which a binary has to install as its global
+// allocator (the z-lang driver does). Without it they read as zero.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Global allocator wrapper that counts allocated and live bytes
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    // Growing a buffer in place counts only the bytes it gained
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let moved = System.realloc(ptr, layout, new_size);
+        if !moved.is_null() {
+            if new_size > layout.size() {
+                ALLOCATED.fetch_add(new_size - layout.size(), Ordering::Relaxed);
+                LIVE.fetch_add(new_size - layout.size(), Ordering::Relaxed);
+            } else {
+                LIVE.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        moved
+    }
+}
+
+/// Total bytes allocated so far
+pub fn allocated_bytes() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Bytes currently allocated and not yet freed
+pub fn live_bytes() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    /// File the pass ran on
+    pub module: String,
+    pub pass: String,
+    pub duration: Duration,
+    /// Bytes allocated while the pass ran
+    pub allocated: usize,
+    /// Bytes live when the pass finished
+    pub live: usize,
+}
+
+/// Measures `f` as one run of `pass` over `module`
+pub fn measure<T>(module: &str, pass: &str, f: impl FnOnce() -> T) -> (T, PassTiming) {
+    let allocated_before = allocated_bytes();
+    let start = std::time::Instant::now();
+    let result = f();
+    let timing = PassTiming {
+        module: module.to_string(),
+        pass: pass.to_string(),
+        duration: start.elapsed(),
+        allocated: allocated_bytes().saturating_sub(allocated_before),
+        live: live_bytes(),
+    };
+    (result, timing)
+}
+
+/// Human-readable summary grouped by pass and by module, as printed by `--time-report`
+pub fn report(timings: &[PassTiming]) -> String {
+    let mut by_pass: Vec<(String, Duration, usize)> = Vec::new();
+    let mut by_module: BTreeMap<String, (Duration, usize)> = BTreeMap::new();
+    let mut total = Duration::ZERO;
+    let mut peak_live = 0;
+
+    for timing in timings {
+        // Keep passes in first-run order
+        match by_pass.iter_mut().find(|(name, _, _)| *name == timing.pass) {
+            Some(entry) => {
+                entry.1 += timing.duration;
+                entry.2 += timing.allocated;
+            }
+            None => by_pass.push((timing.pass.clone(), timing.duration, timing.allocated)),
+        }
+
+        let entry = by_module.entry(timing.module.clone()).or_default();
+        entry.0 += timing.duration;
+        entry.1 += timing.allocated;
+
+        total += timing.duration;
+        peak_live = peak_live.max(timing.live);
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "time report:");
+    let _ = writeln!(out, "  {:<20} {:>12} {:>7} {:>12}", "pass", "time", "%", "allocated");
+    for (pass, duration, allocated) in &by_pass {
+        let _ = writeln!(out, "  {:<20} {:>12} {:>6.1}% {:>12}", pass, format_duration(*duration), percent(*duration, total), format_bytes(*allocated));
+    }
+    let _ = writeln!(out, "  {:<20} {:>12} {:>7} {:>12}", "module", "time", "%", "allocated");
+    for (module, (duration, allocated)) in &by_module {
+        let _ = writeln!(out, "  {:<20} {:>12} {:>6.1}% {:>12}", module, format_duration(*duration), percent(*duration, total), format_bytes(*allocated));
+    }
+    let _ = writeln!(out, "  total {}, peak live memory {}", format_duration(total), format_bytes(peak_live));
+    out
+}
+
+fn percent(part: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        0.0
+    } else {
+        part.as_secs_f64() * 100.0 / total.as_secs_f64()
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1}KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(module: &str, pass: &str, ms: u64) -> PassTiming {
+        PassTiming {
+            module: module.to_string(),
+            pass: pass.to_string(),
+            duration: Duration::from_millis(ms),
+            allocated: 2048,
+            live: 0,
+        }
+    }
+
+    #[test]
+    fn test_report_groups_by_pass_and_module() {
+        let report = report(&[
+            timing("main.z", "lex", 1),
+            timing("lib.z", "lex", 3),
+            timing("main.z", "emit", 4),
+        ]);
+        let lex = report.lines().find(|l| l.trim_start().starts_with("lex")).unwrap();
+        assert!(lex.contains("4.000ms") && lex.contains("50.0%"));
+        assert!(report.contains("lib.z"));
+        assert!(report.contains("total 8.000ms"));
+    }
+
+    #[test]
+    fn test_realloc_counts_the_size_difference() {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let (allocated, live) = (allocated_bytes(), live_bytes());
+        unsafe {
+            let ptr = CountingAlloc.alloc(layout);
+            let ptr = CountingAlloc.realloc(ptr, layout, 256);
+            assert_eq!((allocated_bytes() - allocated, live_bytes() - live), (256, 256));
+            let ptr = CountingAlloc.realloc(ptr, Layout::from_size_align(256, 8).unwrap(), 32);
+            assert_eq!((allocated_bytes() - allocated, live_bytes() - live), (256, 32));
+            CountingAlloc.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+        }
+        assert_eq!(live_bytes(), live);
+    }
+}
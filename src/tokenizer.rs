@@ -1,33 +1,93 @@
 // src/tokenizer.rs
 
-#[derive(Debug, Clone, PartialEq)]
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Token {
     Identifier(String),
     Number(String),
     StringLit(String),
     CharLit(String),
     Symbol(String),   // operators and punctuators, multi-char if needed
-    Comment(String),  // keeps //... or /* ... */
+    Comment(String),  // keeps //... or /* ... */; empty, see `is_macro_space`
     Newline,
     Eof,
 }
 
+/// Where a token came from: a byte range of the source and the 1-based
+/// line and column (in bytes) of its first character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Multi-char operators and punctuators
+const OPERATORS: &[&str] = &[
+    ">>=", "<<=", "==", "!=", "<=", ">=", "->", "++", "--", "&&", "||", "+=", "-=", "*=",
+    "/=", "%=", "&=", "|=", "^=", "<<", ">>", "::", "=>", "...", "..=", ".."
+];
+
+/// Whether `token` is the space of `#define N (3)`, between the name of an
+/// object-like macro and a value starting with `(`, which unspaced would
+/// make it `#define N(3)`, a function-like macro. Spaces aren't tokens, so
+/// the lexer puts an empty comment there
+pub fn is_macro_space(token: &Token) -> bool {
+    matches!(token, Token::Comment(text) if text.is_empty())
+}
+
 pub fn tokenize(input: &str) -> Vec<Token> {
+    lex(input).0
+}
+
+/// `tokenize`, with the span of every token. `Eof` gets an empty span at
+/// the end of the input.
+pub fn tokenize_spanned(input: &str) -> Vec<(Token, Span)> {
+    let (tokens, ranges) = lex(input);
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut scanned = 0;
+    tokens
+        .into_iter()
+        .zip(ranges)
+        .map(|(token, (start, end))| {
+            for (offset, byte) in input.as_bytes()[scanned..start].iter().enumerate() {
+                if *byte == b'\n' {
+                    line += 1;
+                    line_start = scanned + offset + 1;
+                }
+            }
+            scanned = start;
+            (token, Span { start, end, line, column: start - line_start + 1 })
+        })
+        .collect()
+}
+
+// Tokens and the byte range each one was read from
+fn lex(input: &str) -> (Vec<Token>, Vec<(usize, usize)>) {
     let mut tokens = Vec::new();
+    let mut ranges = Vec::new();
+    let mut token_start = 0;
     let mut i = 0;
     let len = input.len();
     let s = input;
 
     // Operators / punctuators (put longest first)
-    let mut ops = vec![
-        ">>=", "<<=", "==", "!=", "<=", ">=", "->", "++", "--", "&&", "||", "+=", "-=", "*=",
-        "/=", "%=", "&=", "|=", "^=", "<<", ">>", "::", "=>"
-    ];
+    let mut ops = OPERATORS.to_vec();
     // single-char will be matched by fallback
-    ops.sort_by(|a, b| b.len().cmp(&a.len()));
+    ops.sort_by_key(|op| std::cmp::Reverse(op.len()));
 
     while i < len {
-        let ch = s.as_bytes()[i] as char;
+        // Every token is pushed just before moving on, so `i` is where the
+        // previous one ended
+        if ranges.len() < tokens.len() {
+            ranges.push((token_start, i));
+        }
+        token_start = i;
+        // `i` only ever stops on a char boundary
+        let Some(ch) = s[i..].chars().next() else { break };
 
         // Newline handling (preserve)
         if ch == '\n' {
@@ -38,7 +98,7 @@ pub fn tokenize(input: &str) -> Vec<Token> {
 
         // Skip other whitespace
         if ch.is_whitespace() {
-            i += 1;
+            i += ch.len_utf8();
             continue;
         }
 
@@ -56,16 +116,30 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 tokens.push(Token::Comment(comment.to_string()));
                 continue;
             } else if next == '*' {
-                // block comment
+                // block comment; they nest, but one whose nesting never
+                // closes ends at its first */ as in C
                 let start = i;
+                let bytes = s.as_bytes();
+                let mut depth = 1;
+                let mut first_close = None;
                 i += 2;
-                while i + 1 < len && !(s.as_bytes()[i] as char == '*' && s.as_bytes()[i + 1] as char == '/') {
-                    i += 1;
+                while i + 1 < len && depth > 0 {
+                    if bytes[i] == b'/' && bytes[i + 1] == b'*' {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i] == b'*' && bytes[i + 1] == b'/' {
+                        depth -= 1;
+                        i += 2;
+                        first_close.get_or_insert(i);
+                    } else {
+                        i += 1;
+                    }
                 }
-                if i + 1 < len {
-                    i += 2; // consume */
+                // or the rest of an unterminated comment
+                if depth > 0 {
+                    i = first_close.unwrap_or(len);
                 }
-                let comment = &s[start..i.min(len)];
+                let comment = &s[start..i];
                 tokens.push(Token::Comment(comment.to_string()));
                 continue;
             }
@@ -80,7 +154,7 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 let c = s.as_bytes()[i] as char;
                 if c == '\\' {
                     // escape: include next char too
-                    i += 2;
+                    i += 1 + s[i + 1..].chars().next().map_or(0, char::len_utf8);
                     continue;
                 }
                 if c == quote {
@@ -89,7 +163,7 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 }
                 i += 1;
             }
-            let slice = &s[start..i.min(len)];
+            let slice = &s[start..i];
             if quote == '"' {
                 tokens.push(Token::StringLit(slice.to_string()));
             } else {
@@ -112,8 +186,8 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                 while i < len && ((s.as_bytes()[i] as char).is_ascii_digit()) {
                     i += 1;
                 }
-                // fraction
-                if i < len && (s.as_bytes()[i] as char) == '.' {
+                // fraction, but not the `..` of a range like `0..n`
+                if i < len && (s.as_bytes()[i] as char) == '.' && s.as_bytes().get(i + 1) != Some(&b'.') {
                     i += 1;
                     while i < len && ((s.as_bytes()[i] as char).is_ascii_digit()) {
                         i += 1;
@@ -136,6 +210,13 @@ pub fn tokenize(input: &str) -> Vec<Token> {
                     }
                 }
             }
+            // `u`, `l`, `ll` and `f` suffixes, in any case and order, when
+            // no other letters follow them
+            let suffixes = if s[start..i].starts_with("0x") || s[start..i].starts_with("0X") { "uUlL" } else { "uUlLfF" };
+            let suffix = s[i..].bytes().take_while(|b| suffixes.contains(*b as char)).count();
+            if !s[i + suffix..].starts_with(|c: char| c == '_' || c.is_alphanumeric()) {
+                i += suffix;
+            }
             tokens.push(Token::Number(s[start..i.min(len)].to_string()));
             continue;
         }
@@ -143,11 +224,10 @@ pub fn tokenize(input: &str) -> Vec<Token> {
         // Identifier or keyword-like token
         if ch == '_' || ch.is_alphabetic() {
             let start = i;
-            i += 1;
-            while i < len {
-                let c = s.as_bytes()[i] as char;
+            i += ch.len_utf8();
+            while let Some(c) = s[i..].chars().next() {
                 if c == '_' || c.is_alphanumeric() {
-                    i += 1;
+                    i += c.len_utf8();
                 } else {
                     break;
                 }
@@ -156,10 +236,20 @@ pub fn tokenize(input: &str) -> Vec<Token> {
             continue;
         }
 
+        // `#define N (3)`, the `(` spaced from the macro's name
+        let n = tokens.len();
+        let macro_value = ch == '(' && n >= 3 && ranges.last().is_some_and(|&(_, end)| end < token_start)
+            && matches!(&tokens[n - 3..], [Token::Symbol(hash), Token::Identifier(define), Token::Identifier(_)] if hash == "#" && define == "define")
+            && (n == 3 || tokens[n - 4] == Token::Newline);
+        if macro_value {
+            tokens.push(Token::Comment(String::new()));
+            ranges.push((token_start, token_start));
+        }
+
         // Operators / multi-char symbols (longest-first)
         let mut matched_op = None;
         for &op in &ops {
-            if i + op.len() <= len && &s[i..i + op.len()] == op {
+            if s.as_bytes()[i..].starts_with(op.as_bytes()) {
                 matched_op = Some(op);
                 break;
             }
@@ -172,29 +262,73 @@ pub fn tokenize(input: &str) -> Vec<Token> {
 
         // Single-char symbol/punctuator fallback
         tokens.push(Token::Symbol(ch.to_string()));
-        i += 1;
+        i += ch.len_utf8();
     }
 
+    if ranges.len() < tokens.len() {
+        ranges.push((token_start, len));
+    }
     tokens.push(Token::Eof);
-    tokens
+    ranges.push((len, len));
+    (tokens, ranges)
 }
 
+/// Prints tokens back as C source. Spacing depends on what a token does
+/// where it stands: the `*` of a dereference or a pointer declarator hugs
+/// what it applies to while a multiplication is spaced, `(int)x` is a cast,
+/// `a < b > c` compares but `Atomic<int>` names a type, and a `#` that
+/// starts a directive always begins a line of its own.
 pub fn detokenize(tokens: &[Token]) -> String {
+    detokenize_preserving(tokens, &[])
+}
+
+/// Like `detokenize`, but a line whose tokens are those of a line of one of
+/// `sources` is copied from it byte for byte, indentation, alignment and
+/// all. Only the lines the passes changed or generated are re-spaced
+pub fn detokenize_preserving(tokens: &[Token], sources: &[&str]) -> String {
+    let printer = Printer::new(tokens);
+    let mut written = written_lines(sources);
     let mut output = String::new();
-    let mut prev_token: Option<&Token> = None;
+    let mut prev: Option<usize> = None;
+    // Between the `<` and `>` of `#include <sys/types.h>`
+    let mut in_header_name = false;
 
-    for token in tokens {
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
         if matches!(token, Token::Eof) {
+            i += 1;
             continue; // skip EOF
         }
 
-        // Handle spacing
-        if let Some(prev) = prev_token {
-            if needs_space(prev, token) {
+        // A line as the user wrote it
+        if !written.is_empty() && (i == 0 || tokens[i - 1] == Token::Newline) {
+            let end = (i..tokens.len()).find(|&j| matches!(tokens[j], Token::Newline | Token::Eof)).unwrap_or(tokens.len());
+            if let Some(text) = written.get_mut(&tokens[i..end]).and_then(|texts| texts.next()) {
+                output.push_str(text);
+                prev = None;
+                i = end;
+                continue;
+            }
+        }
+
+        // Handle spacing; a directive keeps to a line of its own, and so
+        // does a `//` comment
+        let after_line_comment = *token != Token::Newline && prev.is_some_and(|p| matches!(&tokens[p], Token::Comment(c) if c.starts_with("//")));
+        if (printer.starts_directive(i) || after_line_comment) && !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        } else if let Some(p) = prev {
+            if !in_header_name && printer.space(p, i) {
                 output.push(' ');
             }
         }
 
+        match (token, printer.roles[i]) {
+            (_, Role::Header) => in_header_name = !in_header_name,
+            (Token::Newline, _) => in_header_name = false,
+            _ => {}
+        }
+
         match token {
             Token::Identifier(s)
             | Token::Number(s)
@@ -210,82 +344,568 @@ pub fn detokenize(tokens: &[Token]) -> String {
             Token::Eof => {} // already skipped
         }
 
-        prev_token = Some(token);
+        prev = Some(i);
+        i += 1;
     }
 
     output
 }
 
-fn needs_space(prev: &Token, current: &Token) -> bool {
-    use Token::*;
-    match (prev, current) {
-        // Never space around newlines or comments
-        (Newline, _) | (_, Newline) | (Comment(_), _) => false,
-
-        // Symbols that should never have spaces around them
-        (Symbol(a), Symbol(b)) => {
-            match (a.as_str(), b.as_str()) {
-                // No space around parentheses, brackets, member access
-                ("(", _) | (_, ")") | ("[", _) | (_, "]") => false,
-                (".", _) | (_, ".") => false,
-                ("->", _) | (_, "->") => false,
-                // No space after opening angle bracket or before closing
-                ("<", _) | (_, ">") => false,
-                // No space around semicolons and commas
-                (";", _) | (_, ";") | (",", _) | (_, ",") => false,
-                // Space around most other operators
-                _ => true,
+/// Texts of the lines written with the same tokens: each is copied once,
+/// in order, and the last one again after that
+struct Occurrences<'a> {
+    texts: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Occurrences<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let text = self.texts.get(self.next).or(self.texts.last()).copied();
+        self.next += 1;
+        text
+    }
+}
+
+// The lines of `sources` by their tokens
+fn written_lines<'a>(sources: &[&'a str]) -> HashMap<Vec<Token>, Occurrences<'a>> {
+    let mut lines: HashMap<Vec<Token>, Occurrences<'a>> = HashMap::new();
+    for source in sources {
+        let mut line: Vec<Token> = Vec::new();
+        let mut start = 0;
+        for (token, span) in tokenize_spanned(source) {
+            match token {
+                Token::Newline | Token::Eof => {
+                    if !line.is_empty() {
+                        lines.entry(std::mem::take(&mut line)).or_insert(Occurrences { texts: Vec::new(), next: 0 }).texts.push(&source[start..span.start]);
+                    }
+                    start = span.end;
+                }
+                token => line.push(token),
             }
         }
+    }
+    lines
+}
 
-        // Identifier followed by symbol
-        (Identifier(_), Symbol(s)) => {
-            match s.as_str() {
-                // No space before these symbols
-                "(" | "[" | "." | "->" | ";" | "," | ">" => false,
-                _ => true,
+/// Comments as the generated C has them. Kept, a nested block comment
+/// becomes a single one by breaking up its inner `/*` and `*/`; otherwise
+/// every comment is dropped, along with a line it had to itself
+pub fn c_comments(tokens: &[Token], keep: bool) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut drop_newline = false;
+    for token in tokens {
+        match token {
+            token if is_macro_space(token) => out.push(token.clone()),
+            Token::Comment(text) if keep => out.push(Token::Comment(flatten_comment(text))),
+            Token::Comment(_) => drop_newline = matches!(out.last(), None | Some(Token::Newline)),
+            Token::Newline if drop_newline => drop_newline = false,
+            token => {
+                drop_newline = false;
+                out.push(token.clone());
             }
         }
+    }
+    out
+}
 
-        // Symbol followed by identifier
-        (Symbol(s), Identifier(_)) => {
-            match s.as_str() {
-                // No space after these symbols
-                "(" | "[" | "." | "->" | "!" | "~" | "*" | "&" | "+" | "-" | "<" => false,
-                _ => true,
+fn flatten_comment(text: &str) -> String {
+    match text.strip_prefix("/*").and_then(|t| t.strip_suffix("*/")) {
+        Some(inner) if inner.contains("/*") || inner.contains("*/") => format!("/*{}*/", inner.replace("/*", "/ *").replace("*/", "* /")),
+        _ => text.to_string(),
+    }
+}
+
+/// What a symbol does where it stands, as far as spacing goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Other,
+    /// Prefix `*`, `&`, `+`, `-`, `++` or `--`
+    Unary,
+    /// Spaced on both sides
+    Binary,
+    /// `*` of a pointer type
+    Declarator,
+    Postfix,
+    /// `)` of a cast
+    CastClose,
+    /// `<` and `>` of `Name<T>`
+    Template,
+    /// `<` and `>` around an `#include`d header name
+    Header,
+}
+
+const DIRECTIVES: &[&str] = &["include", "define", "undef", "if", "ifdef", "ifndef", "elif", "else", "endif", "pragma", "error", "warning", "line"];
+
+// Words that never end an operand
+const KEYWORDS: &[&str] = &[
+    "return", "case", "default", "sizeof", "_Alignof", "alignof", "else", "do", "goto", "typedef", "if", "while", "for", "switch",
+    "static", "extern", "inline", "register", "struct", "union", "enum",
+];
+
+// Words that always name (part of) a type
+const TYPE_WORDS: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool", "bool", "const", "volatile", "restrict",
+    "_Atomic", "FILE", "va_list", "jmp_buf",
+];
+
+// Keywords spaced from their `(`, which isn't a call's
+const CONTROL: &[&str] = &["if", "while", "for", "switch", "return"];
+
+struct Printer<'a> {
+    tokens: &'a [Token],
+    /// Names `typedef`ed among the tokens
+    typedefs: HashSet<&'a str>,
+    /// Whether each token is on a preprocessor directive line
+    directive: Vec<bool>,
+    /// Where looking back stops on a directive line: at the `#`, or past
+    /// the directive's name and a `#define`'s macro and parameters
+    floor: Vec<usize>,
+    roles: Vec<Role>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        let n = tokens.len();
+        let mut printer = Printer { tokens, typedefs: typedefs(tokens), directive: vec![false; n], floor: vec![0; n], roles: vec![Role::Other; n] };
+        let mut i = 0;
+        while i < n {
+            if !printer.starts_directive(i) {
+                i += 1;
+                continue;
+            }
+            // A directive runs to the end of its line, `\` continuing it
+            let mut end = i;
+            while end < n && !(tokens[end] == Token::Newline && printer.symbol(end.saturating_sub(1)) != Some("\\")) {
+                end += 1;
+            }
+            let mut body = i + 2;
+            if printer.word(i + 1) == Some("define") {
+                body = i + 3;
+                if printer.symbol(body) == Some("(") {
+                    body = (body..end).find(|&j| printer.symbol(j) == Some(")")).map_or(end, |j| j + 1);
+                }
+            }
+            for j in i..end {
+                printer.directive[j] = true;
+                printer.floor[j] = if j < body { i } else { body };
             }
+            i = end;
         }
+        printer.assign_roles();
+        printer
+    }
 
-        // Symbol followed by number
-        (Symbol(s), Number(_)) => {
-            match s.as_str() {
-                // No space after these symbols when followed by numbers
-                "(" | "[" | "." | "->" | "!" | "~" | "*" | "&" | "+" | "-" | "<" => false,
-                _ => true,
+    fn word(&self, i: usize) -> Option<&'a str> {
+        match self.tokens.get(i) {
+            Some(Token::Identifier(w)) => Some(w),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self, i: usize) -> Option<&'a str> {
+        match self.tokens.get(i) {
+            Some(Token::Symbol(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn starts_directive(&self, i: usize) -> bool {
+        self.symbol(i) == Some("#") && self.word(i + 1).is_some_and(|w| DIRECTIVES.contains(&w)) && !(i > 0 && self.directive[i - 1])
+    }
+
+    // Tokens of the code around `i`: directive lines and the code between
+    // them are read apart
+    fn related(&self, i: usize, j: usize) -> bool {
+        !matches!(self.tokens[j], Token::Newline | Token::Comment(_) | Token::Eof) && self.directive[j] == self.directive[i]
+    }
+
+    fn prev(&self, i: usize) -> Option<usize> {
+        (self.floor[i]..i).rev().find(|&j| self.related(i, j))
+    }
+
+    fn next(&self, i: usize) -> Option<usize> {
+        (i + 1..self.tokens.len()).take_while(|&j| !self.directive[i] || self.directive[j]).find(|&j| self.related(i, j))
+    }
+
+    fn assign_roles(&mut self) {
+        for i in 0..self.tokens.len() {
+            // The `>` of a template or a header name is marked with its `<`
+            if self.roles[i] != Role::Other {
+                continue;
+            }
+            let Some(symbol) = self.symbol(i) else { continue };
+            let before = self.prev(i);
+            let after_operand = before.is_some_and(|b| self.ends_operand(b));
+            self.roles[i] = match symbol {
+                "*" => match before {
+                    Some(b) if self.symbol(b) == Some("*") && self.roles[b] == Role::Declarator => Role::Declarator,
+                    Some(b) if self.is_type(b) => Role::Declarator,
+                    _ if after_operand => Role::Binary,
+                    _ => Role::Unary,
+                },
+                "&" | "+" | "-" if after_operand => Role::Binary,
+                "&" | "+" | "-" => Role::Unary,
+                "++" | "--" if after_operand => Role::Postfix,
+                "++" | "--" => Role::Unary,
+                ")" if self.is_cast(i) => Role::CastClose,
+                "<" => {
+                    let include = self.directive[i] && i >= 2 && self.word(i - 1) == Some("include") && self.symbol(i - 2) == Some("#");
+                    let (role, marks) = match include {
+                        true => (Role::Header, (i..self.tokens.len()).take_while(|&j| self.tokens[j] != Token::Newline).find(|&j| self.symbol(j) == Some(">")).into_iter().collect()),
+                        false => (Role::Template, self.template(i).unwrap_or_default()),
+                    };
+                    for &j in &marks {
+                        self.roles[j] = role;
+                    }
+                    if marks.is_empty() {
+                        Role::Binary
+                    } else {
+                        role
+                    }
+                }
+                ">" => Role::Binary,
+                _ => Role::Other,
+            };
+        }
+    }
+
+    // Whether the token at `i` can end an operand, so that an operator
+    // after it is binary
+    fn ends_operand(&self, i: usize) -> bool {
+        match &self.tokens[i] {
+            Token::Number(_) | Token::StringLit(_) | Token::CharLit(_) => true,
+            Token::Identifier(w) => !KEYWORDS.contains(&w.as_str()) && !self.is_type(i),
+            Token::Symbol(s) => match s.as_str() {
+                ")" => self.roles[i] != Role::CastClose,
+                "]" => true,
+                "++" | "--" => self.roles[i] == Role::Postfix,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Whether the word at `i` names a type: a C type word, a typedef, a
+    // struct tag, or the `Name` of `Name *p` where only a declaration fits
+    fn is_type(&self, i: usize) -> bool {
+        let Some(word) = self.word(i) else { return false };
+        if TYPE_WORDS.contains(&word) || word.ends_with("_t") || self.typedefs.contains(word) {
+            return true;
+        }
+        if KEYWORDS.contains(&word) {
+            return false;
+        }
+        let before = self.prev(i);
+        if matches!(before.and_then(|b| self.word(b)), Some("struct" | "union" | "enum")) {
+            return true;
+        }
+        let mut last = i;
+        while let Some(star) = self.next(last).filter(|&j| self.symbol(j) == Some("*")) {
+            last = star;
+        }
+        if last == i {
+            return false;
+        }
+        let after = self.next(last);
+        let opens_list = before.is_some_and(|b| matches!(self.symbol(b), Some("(" | ",")));
+        match after.map(|a| &self.tokens[a]) {
+            // `(Name *)` and `f(int, Name *)`: no expression ends in `*`
+            Some(Token::Symbol(s)) if s == ")" => opens_list,
+            Some(Token::Identifier(_)) => {
+                let declarator_end = matches!(after.and_then(|a| self.next(a)).and_then(|j| self.symbol(j)), Some("=" | ";" | "," | ")" | "[" | "("));
+                let declaration_start = match before {
+                    None => true,
+                    Some(b) => match self.symbol(b) {
+                        Some(";" | "{" | "}") => true,
+                        Some("(" | ",") => self.in_parameters(b),
+                        _ => matches!(self.word(b), Some("static" | "extern" | "inline" | "register")),
+                    },
+                };
+                declarator_end && declaration_start
+            }
+            _ => false,
+        }
+    }
+
+    // Whether the `(` or `,` at `i` is in a parameter list or a `for` header
+    fn in_parameters(&self, i: usize) -> bool {
+        let mut depth = 0;
+        let mut open = None;
+        for j in (self.floor[i]..=i).rev() {
+            match self.symbol(j) {
+                Some(")") => depth += 1,
+                Some("(") if depth == 0 => {
+                    open = Some(j);
+                    break;
+                }
+                Some("(") => depth -= 1,
+                Some(";" | "{" | "}") => return false,
+                _ => {}
+            }
+        }
+        let Some(name) = open.and_then(|o| self.prev(o)) else { return false };
+        match self.word(name) {
+            Some("for") => true,
+            Some(w) if !KEYWORDS.contains(&w) => match self.prev(name) {
+                Some(p) if self.symbol(p) == Some("*") => self.roles[p] == Role::Declarator,
+                Some(p) => self.word(p).is_some_and(|w| !matches!(w, "return" | "case" | "else" | "do" | "goto")),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Whether the `)` at `close` ends a cast: a type in parentheses that
+    // aren't a call's or `sizeof`'s, with an operand after them
+    fn is_cast(&self, close: usize) -> bool {
+        let mut depth = 0;
+        let Some(open) = (self.floor[close]..close).rev().find(|&j| {
+            match self.symbol(j) {
+                Some(")") => depth += 1,
+                Some("(") if depth == 0 => return true,
+                Some("(") => depth -= 1,
+                _ => {}
+            }
+            false
+        }) else {
+            return false;
+        };
+        if let Some(before) = self.prev(open) {
+            let callee = self.word(before).is_some_and(|w| !KEYWORDS.contains(&w) || matches!(w, "sizeof" | "_Alignof" | "alignof"));
+            if callee || self.ends_operand(before) {
+                return false;
+            }
+        }
+        let inside: Vec<usize> = (open + 1..close).filter(|&j| self.related(close, j)).collect();
+        let words = inside.iter().take_while(|&&j| self.word(j).is_some()).count();
+        if words == 0 || inside[words..].iter().any(|&j| self.symbol(j) != Some("*")) {
+            return false;
+        }
+        let pointer = words < inside.len();
+        let typed = inside[..words].iter().all(|&j| self.is_type(j) || matches!(self.word(j), Some("struct" | "union" | "enum")));
+        if !(pointer || typed) {
+            return false;
+        }
+        match self.next(close).map(|j| &self.tokens[j]) {
+            Some(Token::Identifier(_) | Token::Number(_) | Token::StringLit(_) | Token::CharLit(_)) => true,
+            Some(Token::Symbol(s)) => matches!(s.as_str(), "(" | "{" | "*" | "&" | "-" | "+" | "!" | "~" | "++" | "--"),
+            _ => false,
+        }
+    }
+
+    // The `<`s and `>`s of `Name<T, U*>` opening at `open`. The name must
+    // be capitalized or a typedef and the arguments types or numbers, so
+    // `a < b > c` stays two comparisons
+    fn template(&self, open: usize) -> Option<Vec<usize>> {
+        if self.directive[open] {
+            return None;
+        }
+        let name = self.word(self.prev(open)?)?;
+        if KEYWORDS.contains(&name) || !(name.starts_with(|c: char| c.is_ascii_uppercase()) || self.typedefs.contains(name)) {
+            return None;
+        }
+        let mut marks = vec![open];
+        let mut depth = 1;
+        let mut arguments = 0;
+        let mut j = open;
+        while depth > 0 {
+            j = self.next(j)?;
+            match &self.tokens[j] {
+                Token::Identifier(w) if self.is_type(j) || w.starts_with(|c: char| c.is_ascii_uppercase()) => arguments += 1,
+                Token::Number(_) => arguments += 1,
+                Token::Symbol(s) => match s.as_str() {
+                    "*" | "," | "::" => {}
+                    "<" => {
+                        depth += 1;
+                        marks.push(j);
+                    }
+                    ">" => {
+                        depth -= 1;
+                        marks.push(j);
+                    }
+                    ">>" if depth >= 2 => {
+                        depth -= 2;
+                        marks.push(j);
+                    }
+                    _ => return None,
+                },
+                _ => return None,
             }
         }
+        (arguments > 0).then_some(marks)
+    }
+
+    // Whether a space goes between the tokens at `a` and `b`, `a` printed
+    // right before `b`
+    fn space(&self, a: usize, b: usize) -> bool {
+        if is_macro_space(&self.tokens[a]) || is_macro_space(&self.tokens[b]) {
+            return is_macro_space(&self.tokens[a]);
+        }
+        // Tokens that would lex as others unspaced, like `- -x` or `1 .x`
+        self.spaced(a, b) || joins(&self.tokens[a], &self.tokens[b])
+    }
 
-        // Number followed by symbol
-        (Number(_), Symbol(s)) => {
-            match s.as_str() {
-                // No space before these symbols
-                "(" | "[" | "." | "->" | ";" | "," | ">" | ")" | "]" => false,
+    fn spaced(&self, a: usize, b: usize) -> bool {
+        use Token::*;
+        let (prev, current) = (&self.tokens[a], &self.tokens[b]);
+        // Never space around newlines; a block comment is spaced from
+        // what follows
+        if matches!(prev, Newline) || matches!(current, Newline) {
+            return false;
+        }
+        if let Comment(_) = prev {
+            return true;
+        }
+        // `#include` and stringizing `#x`
+        if self.symbol(a) == Some("#") {
+            return false;
+        }
+        // A directive's name, or a macro's, from what follows it
+        if self.directive[b] && self.floor[b] == b {
+            return true;
+        }
+        // The parameters of `#define F(x)`, even of an `if`
+        if self.directive[a] && a >= 2 && self.word(a - 1) == Some("define") && self.symbol(a - 2) == Some("#") && self.symbol(b) == Some("(") {
+            return false;
+        }
+        match (self.roles[a], self.roles[b]) {
+            (Role::Unary | Role::Declarator | Role::CastClose, _) | (_, Role::Postfix | Role::Template) => return false,
+            (Role::Template, _) if self.symbol(a) == Some("<") => return false,
+            (_, Role::Header) => return true,
+            (_, Role::Declarator) => return !matches!(self.symbol(a), Some("*" | "(")),
+            (Role::Binary, _) | (_, Role::Binary) => return true,
+            _ => {}
+        }
+        match (prev, current) {
+            (Symbol(x), Symbol(y)) => match (x.as_str(), y.as_str()) {
+                // No space inside parentheses and brackets, or between
+                // calls and subscripts
+                ("(" | "[", _) | (_, ")" | "]") => false,
+                (")" | "]", "(" | "[") => false,
+                // Member access
+                ("." | "->" | "::", _) | (_, "." | "->" | "::") => false,
+                ("!" | "~", _) => false,
+                (";", ";") | (_, ";" | ",") => false,
+                // Space around most other operators
+                _ => true,
+            },
+            (Identifier(word), Symbol(s)) => match s.as_str() {
+                "(" => CONTROL.contains(&word.as_str()),
+                "[" | "." | "->" | "::" | ";" | "," | ")" | "]" => false,
                 _ => true,
+            },
+            (Number(_), Symbol(s)) => !matches!(s.as_str(), "(" | "[" | "." | "->" | ";" | "," | ")" | "]"),
+            (Symbol(s), Identifier(_) | Number(_)) => !matches!(s.as_str(), "(" | "[" | "." | "->" | "::" | "!" | "~"),
+            (StringLit(_) | CharLit(_), Symbol(s)) => !matches!(s.as_str(), ")" | "]" | ";" | ","),
+            (Symbol(s), StringLit(_) | CharLit(_)) => !matches!(s.as_str(), "(" | "[" | "!"),
+            // Words and literals are always spaced
+            _ => true,
+        }
+    }
+}
+
+// Whether `a` printed right before `b` would lex as other tokens
+fn joins(a: &Token, b: &Token) -> bool {
+    fn text(token: &Token) -> Option<&str> {
+        match token {
+            Token::Identifier(s) | Token::Number(s) | Token::StringLit(s) | Token::CharLit(s) | Token::Comment(s) | Token::Symbol(s) => Some(s),
+            Token::Newline | Token::Eof => None,
+        }
+    }
+    let (Some(x), Some(y)) = (text(a), text(b)) else { return false };
+    // Words and numbers run on into each other, and into a `.` or the sign
+    // of an exponent; anything else only into operators and comments
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let (last, first) = (x.chars().last().unwrap_or(' '), y.chars().next().unwrap_or(' '));
+    let exponent = matches!(a, Token::Number(_)) && matches!(first, '+' | '-');
+    if !(exponent || word(last) && word(first)) {
+        return fuses(x, y);
+    }
+    !matches!(tokenize(&format!("{}{}", x, y)).as_slice(), [first, second, Token::Eof] if first == a && second == b)
+}
+
+// Whether the symbol `x` printed right before `y` would lex as another token
+fn fuses(x: &str, y: &str) -> bool {
+    let joined = format!("{}{}", x, y);
+    joined.starts_with("//") || joined.starts_with("/*") || OPERATORS.iter().any(|op| op.len() > x.len() && joined.starts_with(op))
+}
+
+// Names the `typedef`s among `tokens` declare
+fn typedefs(tokens: &[Token]) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    for i in 0..tokens.len() {
+        if !matches!(&tokens[i], Token::Identifier(w) if w == "typedef") {
+            continue;
+        }
+        let mut depth = 0;
+        let mut last = None;
+        for token in &tokens[i + 1..] {
+            match token {
+                Token::Symbol(s) if matches!(s.as_str(), "(" | "[" | "{") => depth += 1,
+                Token::Symbol(s) if matches!(s.as_str(), ")" | "]" | "}") => depth -= 1,
+                Token::Symbol(s) if s == ";" && depth == 0 => break,
+                Token::Identifier(w) if depth == 0 => last = Some(w.as_str()),
+                _ => {}
             }
         }
+        names.extend(last);
+    }
+    names
+}
 
-        // Always space between identifiers/numbers
-        (Identifier(_), Identifier(_)) => true,
-        (Identifier(_), Number(_)) => true,
-        (Number(_), Identifier(_)) => true,
-        (Number(_), Number(_)) => true,
+/// 1-based source line of every token. Lines of code the compiler put in
+/// don't count: after a `#__z_line N` marker, see `generated`, the next
+/// line is line `N` again
+pub(crate) fn line_numbers(tokens: &[Token]) -> Vec<usize> {
+    let mut line = 1;
+    let mut marked = None;
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let current = line;
+            match token {
+                Token::Newline => line = marked.take().unwrap_or(line + 1),
+                Token::Comment(text) | Token::StringLit(text) => line += text.matches('\n').count(),
+                Token::Number(number) if i >= 2 && is_line_marker(tokens, i - 2) => marked = number.parse().ok(),
+                _ => {}
+            }
+            current
+        })
+        .collect()
+}
 
-        // String/char literals always spaced
-        (StringLit(_), _) | (_, StringLit(_)) | (CharLit(_), _) | (_, CharLit(_)) => true,
+/// The tokens of `text`, C the compiler puts before the token on `line`,
+/// with a `#__z_line` marker after them so `line_numbers` goes on counting
+/// from `line`. Parsing removes the markers
+pub(crate) fn generated(text: &str, line: usize) -> Vec<Token> {
+    let mut tokens: Vec<Token> = tokenize(text).into_iter().filter(|t| !matches!(t, Token::Eof)).collect();
+    if tokens.last() != Some(&Token::Newline) {
+        tokens.push(Token::Newline);
+    }
+    tokens.extend([Token::Symbol("#".to_string()), Token::Identifier(LINE_MARKER.to_string()), Token::Number(line.to_string()), Token::Newline]);
+    tokens
+}
 
-        // Default
-        _ => true,
+/// `tokens` without the markers of `generated`
+pub(crate) fn without_line_markers(tokens: &[Token]) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_line_marker(tokens, i) {
+            i += 4;
+            continue;
+        }
+        out.push(tokens[i].clone());
+        i += 1;
     }
+    out
+}
+
+const LINE_MARKER: &str = "__z_line";
+
+fn is_line_marker(tokens: &[Token], i: usize) -> bool {
+    matches!(&tokens[i..], [Token::Symbol(hash), Token::Identifier(name), Token::Number(_), Token::Newline, ..] if hash == "#" && name == LINE_MARKER)
 }
 
 #[cfg(test)]
@@ -366,4 +986,68 @@ mod tests {
         let output = detokenize(&tokens);
         assert_eq!(output, "self.f = 1");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_include_path_with_directories() {
+        let tokens = tokenize("#include <sys/types.h>\nint x = a / b;");
+        assert_eq!(detokenize(&tokens), "#include <sys/types.h>\nint x = a / b;");
+    }
+
+    #[test]
+    fn test_nested_comments() {
+        let tokens = tokenize("/* a /* b */ c */ x = \"/* s */\"; // y */\n/* C: /* ends here */ int z;");
+        assert_eq!(tokens[0], Token::Comment("/* a /* b */ c */".to_string()));
+        assert_eq!(tokens[3], Token::StringLit("\"/* s */\"".to_string()));
+        assert_eq!(tokens[7], Token::Comment("/* C: /* ends here */".to_string()));
+        assert_eq!(tokens[8], Token::Identifier("int".to_string()));
+
+        assert_eq!(detokenize(&c_comments(&tokens, true)), "/* a / * b * / c */ x = \"/* s */\"; // y */\n/* C: / * ends here */ int z;");
+        assert_eq!(detokenize(&c_comments(&tokenize("// alone\nx = 1; // after\n/* a */ y = 2;"), false)), "x = 1;\ny = 2;");
+    }
+
+    #[test]
+    fn test_written_lines_are_copied() {
+        let source = "int    x = 1;   // one\n  if (x)\n    y = x*2;\n";
+        let tokens = tokenize("int x = 1; // one\nif (x)\ny = f(x);\n");
+        assert_eq!(detokenize_preserving(&tokens, &[source]), "int    x = 1;   // one\n  if (x)\ny = f(x);\n");
+    }
+
+    #[test]
+    fn test_directives_keep_to_their_own_line() {
+        let mut tokens = tokenize("int x = 1;");
+        tokens.pop();
+        tokens.extend(tokenize("# define NEG -1\n#if A < B\n#endif"));
+        assert_eq!(detokenize(&tokens), "int x = 1;\n#define NEG -1\n#if A < B\n#endif");
+    }
+
+    #[test]
+    fn test_macro_values_in_parentheses_stay_spaced() {
+        let source = "#define N (3)\n#define TWICE(x) ((x) * 2)\n#define if(x) x\nint a = (N);";
+        let tokens = tokenize(source);
+        assert!(is_macro_space(&tokens[3]) && tokens.iter().filter(|t| is_macro_space(t)).count() == 1);
+        assert_eq!(detokenize(&tokens), source);
+        assert_eq!(detokenize(&c_comments(&tokens, false)), source);
+    }
+
+    #[test]
+    fn test_unary_and_binary_operators() {
+        let tokens = tokenize("int f(char * * argv, Node * n) { Node * p = & n; int k = a * b & c; return * argv [k] - - k + n ++ * 2; }");
+        assert_eq!(detokenize(&tokens), "int f(char **argv, Node *n) { Node *p = &n; int k = a * b & c; return *argv[k] - -k + n++ * 2; }");
+    }
+
+    #[test]
+    fn test_casts() {
+        let tokens = tokenize("typedef struct { int x; } V;
+long n = (long) x * (int) sizeof (V) + (unsigned char) - y;
+V v = (V) { 1 }; char *s = (char *) p;");
+        assert_eq!(detokenize(&tokens), "typedef struct { int x; } V;
+long n = (long)x * (int)sizeof(V) + (unsigned char)-y;
+V v = (V){ 1 }; char *s = (char *)p;");
+    }
+
+    #[test]
+    fn test_comparisons_and_templates() {
+        let tokens = tokenize("bool b = a < b > c; Atomic < int > n; Map < Key, Vec < int >> m; if (i<n && j>0) {}");
+        assert_eq!(detokenize(&tokens), "bool b = a < b > c; Atomic<int> n; Map<Key, Vec<int>> m; if (i < n && j > 0) { }");
+    }
+}
@@ -0,0 +1,273 @@
+// src/toolchain.rs
+//
+// The C compiler the driver hands the generated C to. `CC` picks it when
+// set (e.g. `CC="ccache gcc"`), and then `[toolchain] cc` of the user's
+// config.toml; otherwise it is the first of gcc and clang on the `PATH`,
+// and on Windows, where gcc usually comes from MinGW, MSVC's `cl.exe` after
+// them.
+//
+// The driver speaks gcc: flags given on the command line and the ones it
+// adds itself (`-o`, `-c`, `-lm`, `-pthread`) are written the gcc way, and
+// are translated for `cl.exe` and `clang-cl` here. Arguments are always
+// passed to the compiler one by one, never through a shell, so paths with
+// spaces need no quoting.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// gcc and compilers that take its flags: clang, MinGW
+    Gnu,
+    /// `cl.exe` and `clang-cl`
+    Msvc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toolchain {
+    pub kind: Kind,
+    /// The compiler and the arguments that come with it, e.g. `ccache gcc`
+    pub cc: Vec<String>,
+}
+
+impl Toolchain {
+    /// The compiler named by `CC` or else `configured`, or the first one
+    /// found on the `PATH`. Falls back to `gcc`, which then fails to start
+    /// with a clear error
+    pub fn detect(configured: Option<&str>) -> Toolchain {
+        if let Some(cc) = env::var("CC").ok().filter(|cc| !cc.trim().is_empty()) {
+            return Toolchain::from_command(&cc);
+        }
+        if let Some(cc) = configured.filter(|cc| !cc.trim().is_empty()) {
+            return Toolchain::from_command(cc);
+        }
+        let candidates: &[&str] = if cfg!(windows) { &["gcc", "clang", "cl"] } else { &["gcc", "clang"] };
+        let path = env::var_os("PATH").unwrap_or_default();
+        let found = candidates.iter().find(|name| find_program(name, &path).is_some());
+        Toolchain::from_command(found.copied().unwrap_or("gcc"))
+    }
+
+    /// The toolchain running `command`, a program and its arguments
+    pub fn from_command(command: &str) -> Toolchain {
+        let cc = split_words(command);
+        // Either separator, so a Windows path is understood anywhere
+        let file = cc.first().map_or("", |p| p.rsplit(['/', '\\']).next().unwrap_or(p)).to_lowercase();
+        let program = file.strip_suffix(".exe").unwrap_or(&file);
+        let kind = if program == "cl" || program == "clang-cl" { Kind::Msvc } else { Kind::Gnu };
+        Toolchain { kind, cc }
+    }
+
+    pub fn name(&self) -> &str {
+        self.cc.first().map_or("cc", String::as_str)
+    }
+
+    /// Whether the compiler is clang, whose profiles for profile-guided
+    /// optimization need merging before they are used
+    pub fn is_clang(&self) -> bool {
+        self.name().rsplit(['/', '\\']).next().is_some_and(|file| file.to_lowercase().contains("clang"))
+    }
+
+    /// A command running the compiler with the gcc-style `args`
+    pub fn command(&self, args: &[String]) -> Command {
+        let arguments = self.arguments(args);
+        let mut command = Command::new(self.name());
+        command.args(&arguments[1..]);
+        command
+    }
+
+    /// The compiler and the arguments it is run with for the gcc-style
+    /// `args`
+    pub fn arguments(&self, args: &[String]) -> Vec<String> {
+        let mut arguments = self.cc.clone();
+        if arguments.is_empty() {
+            arguments.push(self.name().to_string());
+        }
+        match self.kind {
+            Kind::Gnu => arguments.extend(args.iter().cloned()),
+            Kind::Msvc => arguments.extend(msvc_args(args)),
+        }
+        arguments
+    }
+
+    /// A command collecting `object` into the static library `library`
+    pub fn archive(&self, library: &str, object: &Path) -> Command {
+        match self.kind {
+            Kind::Gnu => {
+                let mut command = Command::new(env::var("AR").unwrap_or_else(|_| "ar".to_string()));
+                command.arg("rcs").arg(library).arg(object);
+                command
+            }
+            Kind::Msvc => {
+                let mut command = Command::new("lib");
+                command.arg("/nologo").arg(format!("/OUT:{}", library)).arg(object);
+                command
+            }
+        }
+    }
+
+    /// File name of the static library `name`: `libname.a`, or `name.lib`
+    /// for MSVC
+    pub fn static_library(&self, name: &str) -> String {
+        match self.kind {
+            Kind::Gnu => format!("lib{}.a", name),
+            Kind::Msvc => format!("{}.lib", name),
+        }
+    }
+
+    /// File name of the shared library `name`: `libname.so`, `libname.dylib`
+    /// on macOS and `name.dll` on Windows
+    pub fn shared_library(&self, name: &str) -> String {
+        match (self.kind, env::consts::OS) {
+            (Kind::Msvc, _) | (_, "windows") => format!("{}.dll", name),
+            (_, "macos") => format!("lib{}.dylib", name),
+            _ => format!("lib{}.so", name),
+        }
+    }
+
+    /// File name of the object compiled for `name`
+    pub fn object(&self, name: &str) -> String {
+        match self.kind {
+            Kind::Gnu => format!("{}.o", name),
+            Kind::Msvc => format!("{}.obj", name),
+        }
+    }
+}
+
+/// File name of the program `name`, with `.exe` on Windows
+pub fn executable(name: &str) -> String {
+    format!("{}{}", name, env::consts::EXE_SUFFIX)
+}
+
+/// Where `name` is found on `path`, trying the extensions of `PATHEXT` on
+/// Windows
+pub fn find_program(name: &str, path: &OsString) -> Option<PathBuf> {
+    let extensions: Vec<String> = if cfg!(windows) {
+        env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.BAT;.CMD".to_string()).split(';').map(str::to_lowercase).collect()
+    } else {
+        vec![String::new()]
+    };
+    env::split_paths(path).flat_map(|dir| extensions.iter().map(move |ext| dir.join(format!("{}{}", name, ext)))).find(|p| p.is_file())
+}
+
+/// `command` split into words at spaces outside of double quotes
+pub fn split_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in command.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            c => {
+                word.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        words.push(word);
+    }
+    words
+}
+
+// gcc-style arguments written for cl.exe; linker options go after `/link`
+fn msvc_args(args: &[String]) -> Vec<String> {
+    let compile_only = args.iter().any(|a| a == "-c");
+    let mut out = vec!["/nologo".to_string(), "/std:clatest".to_string()];
+    let mut link = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // `-I dir` and `-Idir` alike
+        let mut value = |flag: &str| -> Option<String> {
+            let rest = arg.strip_prefix(flag)?;
+            if rest.is_empty() { args.next().cloned() } else { Some(rest.to_string()) }
+        };
+        if arg == "-c" {
+            out.push("/c".to_string());
+        } else if arg == "-shared" {
+            out.push("/LD".to_string());
+        } else if arg == "-fPIC" || arg.starts_with("-fvisibility=") {
+            // DLLs export only what `__declspec(dllexport)` marks
+        } else if let Some(file) = value("-o") {
+            out.push(format!("{}{}", if compile_only { "/Fo:" } else { "/Fe:" }, file));
+        } else if let Some(dir) = value("-I") {
+            out.push(format!("/I{}", dir));
+        } else if let Some(macro_) = value("-D") {
+            out.push(format!("/D{}", macro_));
+        } else if let Some(macro_) = value("-U") {
+            out.push(format!("/U{}", macro_));
+        } else if let Some(dir) = value("-L") {
+            link.push(format!("/LIBPATH:{}", dir));
+        } else if let Some(library) = value("-l") {
+            // The C runtime has the maths and threads gcc links separately
+            if library != "m" && library != "pthread" {
+                out.push(format!("{}.lib", library));
+            }
+        } else if arg == "-pthread" || arg.starts_with("-std=") {
+            // Threads need no flag, and `/std:clatest` is always given
+        } else if arg == "-O0" {
+            out.push("/Od".to_string());
+        } else if arg == "-Os" || arg == "-Oz" {
+            out.push("/O1".to_string());
+        } else if arg.starts_with("-O") {
+            out.push("/O2".to_string());
+        } else if arg == "-flto" {
+            out.push("/GL".to_string());
+        } else if arg == "-g" {
+            out.push("/Zi".to_string());
+        } else if arg == "-Wall" || arg == "-Wextra" {
+            out.push("/W4".to_string());
+        } else if arg == "-Werror" {
+            out.push("/WX".to_string());
+        } else {
+            out.push(arg.clone());
+        }
+    }
+    if !link.is_empty() {
+        out.push("/link".to_string());
+        out.extend(link);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_compilers_are_recognised() {
+        assert_eq!(Toolchain::from_command("gcc").kind, Kind::Gnu);
+        assert_eq!(Toolchain::from_command("x86_64-w64-mingw32-gcc").kind, Kind::Gnu);
+        let cl = Toolchain::from_command("\"C:\\Program Files\\MSVC\\bin\\cl.exe\" /MD");
+        assert_eq!(cl.kind, Kind::Msvc);
+        assert_eq!(cl.cc, strings(&["C:\\Program Files\\MSVC\\bin\\cl.exe", "/MD"]));
+        assert!(Toolchain::from_command("/usr/bin/clang-17").is_clang() && !cl.is_clang());
+        assert_eq!(split_words("ccache  gcc -m32 \"\""), strings(&["ccache", "gcc", "-m32", ""]));
+    }
+
+    #[test]
+    fn test_gcc_flags_are_translated_for_msvc() {
+        let args = strings(&["target/main.c", "-O2", "-g", "-I", "include", "-DDEBUG=1", "-lm", "-pthread", "-lws2_32", "-L", "C:\\libs dir", "-lgeo", "-o", "target/main.exe"]);
+        assert_eq!(msvc_args(&args), strings(&["/nologo", "/std:clatest", "target/main.c", "/O2", "/Zi", "/Iinclude", "/DDEBUG=1", "ws2_32.lib", "geo.lib", "/Fe:target/main.exe", "/link", "/LIBPATH:C:\\libs dir"]));
+        assert_eq!(msvc_args(&strings(&["-O3", "-flto"])), strings(&["/nologo", "/std:clatest", "/O2", "/GL"]));
+        let args = strings(&["-c", "target/geo.c", "-o", "target/geo.obj"]);
+        assert_eq!(msvc_args(&args), strings(&["/nologo", "/std:clatest", "/c", "target/geo.c", "/Fo:target/geo.obj"]));
+        let args = strings(&["target/geo.c", "-shared", "-fPIC", "-fvisibility=hidden", "-o", "geo.dll"]);
+        assert_eq!(msvc_args(&args), strings(&["/nologo", "/std:clatest", "target/geo.c", "/LD", "/Fe:geo.dll"]));
+    }
+}
@@ -0,0 +1,330 @@
+// src/unions.rs
+//
+// `--union-checks`: a `@tagged` union becomes a struct of a hidden tag and
+// the union, the tag recording which member was written last. Writing
+// `u.x = ...` (or taking `&u.x`) sets the tag; reading `u.x` while it holds
+// another member prints `file:line` and both members and aborts. Like the
+// other checks this runs on the token stream before parsing, while line
+// numbers are still known, and only sees accesses through variables and
+// parameters declared with the union's type (or a pointer to it) and `self`.
+
+use std::collections::HashMap;
+
+use crate::ast::{has_attribute, Class, Module, Variable};
+use crate::parser::{code, parse_attribute, parse_params, parse_variables};
+use crate::tokenizer::{generated, line_numbers, Token};
+use crate::visit::{walk_class_mut, VisitMut};
+
+const RUNTIME: &str = "#ifndef __Z_UNION_CHECK
+#define __Z_UNION_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline void* __z_union_write(void* u, int* tag, int member) {
+  *tag = member;
+  return u;
+}
+static inline void* __z_union_read(void* u, int tag, int member, const char* name, const char* const* members, const char* file, int line) {
+  if (tag != member) {
+    fprintf(stderr, \"%s:%d: read of `%s.%s` but it holds %s%s%s\\n\", file, line, name, members[member - 1], tag ? \"`\" : \"\", tag ? members[tag - 1] : \"no member\", tag ? \"`\" : \"\");
+    abort();
+  }
+  return u;
+}
+#endif
+";
+
+/// Marks the `@tagged` unions of `module` as checked, so they are emitted
+/// with their tag
+pub fn mark_checked(module: &mut Module) {
+    struct Marker;
+    impl VisitMut for Marker {
+        fn visit_class_mut(&mut self, class: &mut Class) {
+            class.checked = class.union && has_attribute(&class.attributes, "tagged");
+            walk_class_mut(self, class);
+        }
+    }
+    Marker.visit_module_mut(module);
+}
+
+// A `@tagged` union: its members in order (tag `n` is member `n - 1`) and
+// the tokens of its body, where `self` is one
+struct Tagged {
+    members: Vec<String>,
+    body: (usize, usize),
+}
+
+/// Rewrites member accesses and initializers of `@tagged` unions; `file` is
+/// reported when a read fails
+pub fn rewrite(tokens: &[Token], file: &str) -> Vec<Token> {
+    let unions = tagged_unions(tokens);
+    if unions.is_empty() {
+        return tokens.to_vec();
+    }
+    let globals = union_variables(parse_variables(tokens), &unions);
+    let mut variables = globals.clone();
+    let lines = line_numbers(tokens);
+
+    let mut out: Vec<Token> = Vec::new();
+    // Whether each open brace is (inside) a function body
+    let mut braces: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let in_function = braces.last() == Some(&true);
+        if let Some((code, end)) = initializer(tokens, i, &unions) {
+            out.extend(code);
+            // It ends after the initializer's `{`
+            braces.push(in_function);
+            i = end;
+            continue;
+        }
+        if in_function {
+            if let Some((code, end)) = access(tokens, i, &unions, &variables, file, lines[i]) {
+                out.extend(code);
+                i = end;
+                continue;
+            }
+        }
+        match &tokens[i] {
+            Token::Symbol(s) if s == "{" => {
+                let body = in_function || matches!(previous_code(tokens, i), Some(Token::Symbol(p)) if p == ")");
+                if body && !in_function {
+                    variables = globals.clone();
+                    variables.extend(union_variables(function_variables(tokens, i), &unions));
+                }
+                braces.push(body);
+            }
+            Token::Symbol(s) if s == "}" => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        out.push(tokens[i].clone());
+        i += 1;
+    }
+    out.splice(0..0, generated(RUNTIME, 1));
+    out
+}
+
+// `v.m` or `p->m` at `i` with `v` a tagged union (`p` a pointer to one) and
+// `m` one of its members -> the checked access, and the index after `m`
+fn access(tokens: &[Token], i: usize, unions: &HashMap<String, Tagged>, variables: &HashMap<String, (String, bool)>, file: &str, line: usize) -> Option<(Vec<Token>, usize)> {
+    let Token::Identifier(variable) = &tokens[i] else { return None };
+    if matches!(previous_code(tokens, i), Some(Token::Symbol(s)) if s == "." || s == "->") {
+        return None;
+    }
+    let (union, pointer) = match variables.get(variable) {
+        Some((union, pointer)) => (union.as_str(), *pointer),
+        None if variable == "self" => (unions.iter().find(|(_, u)| u.body.0 < i && i < u.body.1)?.0.as_str(), false),
+        None => return None,
+    };
+    let (Some(Token::Symbol(op)), Some(Token::Identifier(member))) = (tokens.get(i + 1), tokens.get(i + 2)) else { return None };
+    if op != if pointer { "->" } else { "." } {
+        return None;
+    }
+    let members = &unions.get(union)?.members;
+    let tag = members.iter().position(|m| m == member)? + 1;
+
+    let (address, tag_field) = if pointer {
+        (variable.clone(), format!("{}->__z_tag", variable))
+    } else {
+        (format!("&{}", variable), format!("{}.__z_tag", variable))
+    };
+    let assigned = matches!(next_code(tokens, i + 3), Some(Token::Symbol(s)) if s == "=");
+    // A unary `&`: the address is taken to write through it
+    let address_taken = match previous_code_index(tokens, i) {
+        Some(amp) if tokens[amp] == Token::Symbol("&".to_string()) => !is_operand_end(previous_code(tokens, amp)),
+        _ => false,
+    };
+    let checked = if assigned || address_taken {
+        format!("((__typeof__({address}))__z_union_write({address}, &{tag_field}, {tag}))->{member}")
+    } else {
+        let names: Vec<String> = members.iter().map(|m| format!("\"{}\"", m)).collect();
+        format!(
+            "((__typeof__({address}))__z_union_read({address}, {tag_field}, {tag}, \"{union}\", (const char* const[]){{ {names} }}, \"{file}\", {line}))->{member}",
+            names = names.join(", ")
+        )
+    };
+    Some((code(&checked), i + 3))
+}
+
+// `U name = { ... }` with `U` a tagged union: the tag of the member it
+// initializes goes first, as the tag is the struct's first field
+fn initializer(tokens: &[Token], i: usize, unions: &HashMap<String, Tagged>) -> Option<(Vec<Token>, usize)> {
+    let (Token::Identifier(type_), Some(Token::Identifier(_)), Some(Token::Symbol(assign))) = (&tokens[i], tokens.get(i + 1), tokens.get(i + 2)) else { return None };
+    let members = &unions.get(type_)?.members;
+    let open = next_code_index(tokens, i + 3)?;
+    if assign != "=" || !matches!(&tokens[open], Token::Symbol(s) if s == "{") {
+        return None;
+    }
+    let first = next_code_index(tokens, open + 1)?;
+    let mut out = tokens[i..=open].to_vec();
+    match (&tokens[first], tokens.get(first + 1)) {
+        // `{ .m = x }`
+        (Token::Symbol(dot), Some(Token::Identifier(member))) if dot == "." => {
+            let tag = members.iter().position(|m| m == member)? + 1;
+            out.extend(code(&format!(".__z_tag = {},", tag)));
+        }
+        // `{}` and `{ 0 }` hold no member
+        (Token::Symbol(close), _) if close == "}" => {}
+        (Token::Number(zero), _) if zero == "0" && matches!(next_code(tokens, first + 1), Some(Token::Symbol(s)) if s == "}") => {}
+        // `{ x }` initializes the first member
+        _ => out.extend(code(&format!(".__z_tag = 1, .{} =", members.first()?))),
+    }
+    Some((out, open + 1))
+}
+
+// The `@tagged union Name { ... }` declarations of the file
+fn tagged_unions(tokens: &[Token]) -> HashMap<String, Tagged> {
+    let mut unions = HashMap::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let mut tagged = false;
+        let mut k = i;
+        while let Some((attribute, next)) = parse_attribute(tokens, k) {
+            tagged |= attribute.name == "tagged";
+            k = next_code_index(tokens, next).unwrap_or(tokens.len());
+        }
+        let declaration = match (tokens.get(k), next_code_index(tokens, k + 1)) {
+            (Some(Token::Identifier(keyword)), Some(name)) if tagged && keyword == "union" => Some(name),
+            _ => None,
+        };
+        let Some(name_index) = declaration else {
+            i = k.max(i + 1);
+            continue;
+        };
+        let (Token::Identifier(name), Some(open)) = (&tokens[name_index], next_code_index(tokens, name_index + 1)) else {
+            i = name_index;
+            continue;
+        };
+        let Some(close) = matches!(&tokens[open], Token::Symbol(s) if s == "{").then(|| matching_brace(tokens, open)).flatten() else {
+            i = open;
+            continue;
+        };
+        let members = parse_variables(&fields(&tokens[open + 1..close])).into_iter().map(|v| v.name).collect();
+        unions.insert(name.clone(), Tagged { members, body: (open, close) });
+        i = close + 1;
+    }
+    unions
+}
+
+// The `Type name;` statements of a union body, without its methods
+fn fields(body: &[Token]) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut statement = Vec::new();
+    let mut k = 0;
+    while k < body.len() {
+        match &body[k] {
+            Token::Symbol(s) if s == "{" => {
+                k = matching_brace(body, k).unwrap_or(body.len());
+                statement.clear();
+            }
+            Token::Symbol(s) if s == ";" => {
+                statement.push(body[k].clone());
+                out.append(&mut statement);
+            }
+            Token::Newline | Token::Comment(_) => {}
+            token => statement.push(token.clone()),
+        }
+        k += 1;
+    }
+    out
+}
+
+// The parameters and locals of the function whose body opens at `open`
+fn function_variables(tokens: &[Token], open: usize) -> Vec<Variable> {
+    let mut variables = Vec::new();
+    let close = previous_code_index(tokens, open).unwrap_or(0);
+    let mut depth = 0;
+    let params = (0..=close).rev().find(|&k| {
+        match &tokens[k] {
+            Token::Symbol(s) if s == ")" => depth += 1,
+            Token::Symbol(s) if s == "(" => depth -= 1,
+            _ => {}
+        }
+        depth == 0
+    });
+    if let Some(params) = params {
+        variables.extend(parse_params(&tokens[params + 1..close]));
+    }
+    let end = matching_brace(tokens, open).unwrap_or(tokens.len());
+    variables.extend(parse_variables(&tokens[open..end]));
+    variables
+}
+
+// The variables declared as a tagged union or a pointer to one, the latest
+// declaration of a name winning: name -> (union, pointer)
+fn union_variables(declared: Vec<Variable>, unions: &HashMap<String, Tagged>) -> HashMap<String, (String, bool)> {
+    declared
+        .into_iter()
+        .filter_map(|v| {
+            let type_ = v.type_.trim();
+            let (union, pointer) = match type_.strip_suffix('*') {
+                Some(pointee) => (pointee.trim(), true),
+                None => (type_, false),
+            };
+            unions.contains_key(union).then(|| (v.name, (union.to_string(), pointer)))
+        })
+        .collect()
+}
+
+// Whether `token` ends an operand, making a following `&` binary
+fn is_operand_end(token: Option<&Token>) -> bool {
+    match token {
+        Some(Token::Identifier(word)) => word != "return",
+        Some(Token::Number(_)) => true,
+        Some(Token::Symbol(s)) => s == ")" || s == "]",
+        _ => false,
+    }
+}
+
+fn previous_code(tokens: &[Token], i: usize) -> Option<&Token> {
+    previous_code_index(tokens, i).map(|k| &tokens[k])
+}
+
+fn previous_code_index(tokens: &[Token], i: usize) -> Option<usize> {
+    (0..i).rev().find(|&k| !matches!(tokens[k], Token::Newline | Token::Comment(_)))
+}
+
+fn next_code(tokens: &[Token], start: usize) -> Option<&Token> {
+    next_code_index(tokens, start).map(|k| &tokens[k])
+}
+
+fn next_code_index(tokens: &[Token], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|&k| !matches!(tokens[k], Token::Newline | Token::Comment(_)))
+}
+
+fn matching_brace(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol(s) if s == "{" => depth += 1,
+            Token::Symbol(s) if s == "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{detokenize, tokenize};
+
+    #[test]
+    fn test_checked_accesses() {
+        let source = "@tagged union V { int i; double d; double get() { return self.d; } }\nvoid set(V* p) { p->d = 1.5; }\nint f(int v) { return v; }\nint main() {\n  V v = { 3 };\n  V w = { .d = 2 };\n  int* q = &v.i;\n  return v.i + f(w.i);\n}";
+        let output = detokenize(&rewrite(&tokenize(source), "main.z"));
+        assert!(output.contains("return ((__typeof__(&self)) __z_union_read(&self, self.__z_tag, 2, \"V\", (const char *const[]) { \"i\", \"d\" }, \"main.z\", 1))->d;"));
+        assert!(output.contains("((__typeof__(p)) __z_union_write(p, &p->__z_tag, 2))->d = 1.5;"));
+        assert!(output.contains("int f(int v) { return v; }"));
+        assert!(output.contains("V v = {.__z_tag = 1,.i = 3 };"));
+        assert!(output.contains("V w = {.__z_tag = 2,.d = 2 };"));
+        assert!(output.contains("int *q = &((__typeof__(&v)) __z_union_write(&v, &v.__z_tag, 1))->i;"));
+        assert!(output.contains("__z_union_read(&w, w.__z_tag, 1, \"V\", (const char *const[]) { \"i\", \"d\" }, \"main.z\", 8))->i"));
+    }
+}
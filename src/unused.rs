@@ -0,0 +1,205 @@
+// src/unused.rs
+//
+// Unused-symbol analysis, run on the lowered module so names are the C
+// names every file agrees on. Within a file it finds private functions
+// (`static` ones and those of anonymous namespaces) nothing calls and
+// imports none of whose names are used. Fields can be read from any file,
+// so the session collects the fields and the member names read by every
+// file, and the root file compares them once all have been seen. What is
+// found is reported under the `unused` lint, so `@allow(unused)` on a
+// function, a class or an `#import` silences it, see `lints`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{derives, has_attribute, Attribute, Item, Module};
+use crate::parser::{enum_definitions, function_definitions};
+use crate::tokenizer::{tokenize, Token};
+
+/// How many times each identifier appears in the module's code, imports
+/// left out
+pub fn references(module: &Module) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for tokens in code(&module.items) {
+        for token in tokens {
+            if let Token::Identifier(name) = token {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Member names read through `.` or `->`; a plain `x.f = ...` only writes
+pub fn field_reads(module: &Module) -> HashSet<String> {
+    let mut reads = HashSet::new();
+    for tokens in code(&module.items) {
+        let code: Vec<&Token> = tokens.iter().filter(|t| !matches!(t, Token::Newline | Token::Comment(_))).collect();
+        for i in 1..code.len() {
+            let (Token::Symbol(access), Token::Identifier(member)) = (code[i - 1], code[i]) else { continue };
+            let written = matches!(code.get(i + 1), Some(Token::Symbol(s)) if s == "=");
+            if (access == "." || access == "->") && !written {
+                reads.insert(member.clone());
+            }
+        }
+    }
+    reads
+}
+
+/// The C names a module defines, for telling whether an import of it is used
+pub fn provided(module: &Module) -> HashSet<String> {
+    let mut names = HashSet::new();
+    provided_by(&module.items, &mut names);
+    names
+}
+
+fn provided_by(items: &[Item], names: &mut HashSet<String>) {
+    for item in items {
+        match item {
+            Item::Class(class) => {
+                names.insert(class.full_name());
+            }
+            Item::Interface(interface) => {
+                names.insert(interface.full_name());
+            }
+            Item::Global(global) => {
+                names.insert(global.full_name());
+            }
+            Item::Namespace(namespace) => provided_by(&namespace.items, names),
+            Item::Raw(tokens) => {
+                names.extend(function_definitions(tokens).iter().map(|d| d.name(tokens).to_string()));
+                for definition in enum_definitions(tokens) {
+                    names.extend(definition.names);
+                    names.extend(definition.variants);
+                }
+                for i in 0..tokens.len() {
+                    let define = matches!(&tokens[i], Token::Symbol(s) if s == "#") && matches!(tokens.get(i + 1), Some(Token::Identifier(w)) if w == "define");
+                    if let (true, Some(Token::Identifier(name))) = (define, tokens.get(i + 2)) {
+                        names.insert(name.clone());
+                    }
+                }
+            }
+            Item::Import(_) | Item::Operator(_) | Item::Export(_) => {}
+        }
+    }
+}
+
+/// Private functions of the module that nothing outside their own body
+/// refers to, with their attributes
+pub fn private_functions(module: &Module, references: &HashMap<String, usize>) -> Vec<(String, Vec<Attribute>)> {
+    let mut unused = Vec::new();
+    private_functions_in(&module.items, false, references, &mut unused);
+    unused
+}
+
+fn private_functions_in(items: &[Item], private: bool, references: &HashMap<String, usize>, unused: &mut Vec<(String, Vec<Attribute>)>) {
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => private_functions_in(&namespace.items, private || namespace.is_anonymous(), references, unused),
+            Item::Raw(tokens) => {
+                for definition in function_definitions(tokens) {
+                    let is_static = definition.return_type.iter().any(|t| matches!(t, Token::Identifier(w) if w == "static"));
+                    if !(private || is_static) {
+                        continue;
+                    }
+                    let name = definition.name(tokens);
+                    // Runtimes the compiler adds define helpers a file may not need
+                    if name.starts_with("__z_") {
+                        continue;
+                    }
+                    let Some(end) = definition.body_end(tokens) else { continue };
+                    let own = tokens[definition.start..=end].iter().filter(|t| matches!(t, Token::Identifier(w) if w == name)).count();
+                    if references.get(name).copied().unwrap_or(0) <= own {
+                        unused.push((name.to_string(), definition.attributes.clone()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// (class, field, the class's attributes) for every field whose reads
+/// should be checked. Unions, `@reflect` and `@derive` classes read their
+/// fields in generated code
+pub fn fields(module: &Module) -> Vec<(String, String, Vec<Attribute>)> {
+    let mut fields = Vec::new();
+    fields_in(&module.items, &mut fields);
+    fields
+}
+
+fn fields_in(items: &[Item], fields: &mut Vec<(String, String, Vec<Attribute>)>) {
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => fields_in(&namespace.items, fields),
+            Item::Class(class) => {
+                let generated = has_attribute(&class.attributes, "reflect") || !derives(&class.attributes).is_empty();
+                if class.union || generated {
+                    continue;
+                }
+                fields.extend(class.variables.iter().map(|v| (class.name.clone(), v.name.clone(), class.attributes.clone())));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Paths of the module's `#import`s of Z files
+pub fn imports(module: &Module) -> Vec<String> {
+    let mut paths = Vec::new();
+    imports_in(&module.items, &mut paths);
+    paths
+}
+
+fn imports_in(items: &[Item], paths: &mut Vec<String>) {
+    for item in items {
+        match item {
+            Item::Namespace(namespace) => imports_in(&namespace.items, paths),
+            Item::Import(import) if import.c_header().is_none() && !import.path.ends_with(".zi") => paths.push(import.path.clone()),
+            _ => {}
+        }
+    }
+}
+
+/// The code of each item as tokens: raw items as they are, the others as
+/// they will be emitted
+pub(crate) fn code(items: &[Item]) -> Vec<Vec<Token>> {
+    let mut out = Vec::new();
+    for item in items {
+        let text = match item {
+            Item::Raw(tokens) => {
+                out.push(tokens.clone());
+                continue;
+            }
+            Item::Namespace(namespace) => {
+                out.extend(code(&namespace.items));
+                continue;
+            }
+            Item::Import(_) | Item::Export(_) => continue,
+            Item::Class(class) => class.to_string(),
+            Item::Interface(interface) => interface.to_string(),
+            Item::Global(global) => global.to_string(),
+            Item::Operator(operator) => operator.to_string(),
+        };
+        out.push(tokenize(&text));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lints::{Level, Levels};
+    use crate::parser::parse_module;
+
+    #[test]
+    fn test_unused_symbols_are_found() {
+        let tokens = tokenize("class P { int x; int y; int z; int get() { return self.x; } }\n@allow(unused) class Q { int w; }\nstatic int helper(int n) { return n ? helper(n - 1) : 0; }\nstatic int used(void) { return 1; }\n@allow(unused) static int kept(void) { return 2; }\nnamespace { int hidden() { return 3; } }\nstatic void __z_helper(void) { }\nint main() { P p; p.y = 2; p.z += 1; return used(); }");
+        let module = parse_module(&tokens, "t");
+        let reported = |attributes: &[Attribute]| Levels::default().within(attributes).level("unused") != Level::Allow;
+        let functions: Vec<String> = private_functions(&module, &references(&module)).into_iter().filter(|(_, a)| reported(a)).map(|(f, _)| f).collect();
+        assert_eq!(functions, vec!["helper", "hidden"]);
+        let reads = field_reads(&module);
+        let unread: Vec<String> = fields(&module).into_iter().filter(|(_, f, a)| !reads.contains(f) && reported(a)).map(|(c, f, _)| format!("{}.{}", c, f)).collect();
+        assert_eq!(unread, vec!["P.y"]);
+    }
+}
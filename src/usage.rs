@@ -0,0 +1,103 @@
+// src/usage.rs
+//
+// What `z-lang --help` and `--version` print, and the flags the driver
+// knows. An argument starting with `--` that isn't one of them is an error
+// rather than something for the C compiler, so a misspelled flag doesn't
+// silently do nothing; the C compiler's own `--` flags go after a lone
+// `--`, which passes everything following it on as it is. Arguments with
+// one dash, e.g. `-O2` or `-o prog`, are the C compiler's.
+
+use crate::suggest;
+
+/// A flag of the driver
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Flag {
+    /// As written, e.g. `--edition`
+    pub name: &'static str,
+    /// What follows it, e.g. `<year>`; empty for a switch
+    pub value: &'static str,
+    pub help: &'static str,
+}
+
+const fn flag(name: &'static str, value: &'static str, help: &'static str) -> Flag {
+    Flag { name, value, help }
+}
+
+/// Every flag of a build, in the order `--help` lists them
+pub const FLAGS: &[Flag] = &[
+    flag("--help", "", "print this help and exit"),
+    flag("--version", "", "print the compiler's version and exit"),
+    flag("--print", "<what>", "print `target-list`, `stdlib-path` or `cc` and exit"),
+    flag("--edition", "<year>", "compile with that edition instead of the one in tarnish.toml"),
+    flag("--features", "<list>", "turn on those features of tarnish.toml as well as `default`"),
+    flag("--no-default-features", "", "leave the `default` features off"),
+    flag("--profile", "<name>", "build with a profile of tarnish.toml or a built-in one"),
+    flag("--target-dir", "<dir>", "put generated files in <dir> instead of target/"),
+    flag("--crate-type", "<type>", "`bin` (the default), `staticlib` or `cdylib`"),
+    flag("--emit", "<kind>", "also write `rust-bindings`, `interface`, `callgraph`, `graph` or `compile-commands`"),
+    flag("--bundle", "", "write the program as one C file instead of building it"),
+    flag("--bounds-check", "", "check slice indices and ranges at runtime"),
+    flag("--overflow-checks", "", "check integer `+`, `-` and `*` for overflow at runtime"),
+    flag("--union-checks", "", "check reads of `@tagged` unions at runtime"),
+    flag("--panic-trace", "", "print the call trace of an uncaught panic"),
+    flag("--crash-report", "", "print the Z functions on the stack when the program crashes"),
+    flag("--preserve-whitespace", "", "keep the source's spacing on lines no pass changed"),
+    flag("--keep-comments", "", "carry the source's comments into the generated C"),
+    flag("--strict", "", "make code the compiler doesn't recognize an error instead of passing it to C"),
+    flag("--reproducible", "", "leave the directory and time out of the generated C's banner"),
+    flag("--time-report", "", "print the time and memory each pass took"),
+    flag("--ice-repro", "", "on an internal compiler error, write the lines reproducing it to a file"),
+];
+
+/// `z-lang <version>`
+pub fn version() -> String {
+    format!("z-lang {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// What `--help` prints
+pub fn help() -> String {
+    let mut help = format!("{}\nCompiles main.z to C and builds it with the C compiler\n\n", version());
+    help.push_str("Usage:\n    z-lang [main.z] [flags] [C compiler arguments] [-- C compiler arguments]\n");
+    help.push_str("    z-lang lint [main.z] [--native | --native=clang-tidy] [flags]\n");
+    help.push_str("    z-lang test [main.z] [flags]\n");
+    help.push_str("    z-lang clean\n    z-lang install [--root <dir>]\n\nFlags:\n");
+    let width = FLAGS.iter().map(|f| usage(f).len()).max().unwrap_or(0);
+    for flag in FLAGS {
+        help.push_str(&format!("    {:width$}  {}\n", usage(flag), flag.help, width = width));
+    }
+    help.push_str("\nOther arguments, e.g. `-O2` or `-o prog`, and everything after `--` go to the C compiler\n");
+    help
+}
+
+/// The error for `arg`, a `--` argument that is no flag, with the flag
+/// it may have meant
+pub fn unknown_flag(arg: &str) -> String {
+    let name = arg.split('=').next().unwrap_or(arg);
+    let meant = suggest::closest(name, FLAGS.iter().map(|f| f.name)).map(|name| format!("; did you mean `{}`?", name));
+    meant.map_or_else(
+        || format!("unknown flag `{}`; see `z-lang --help`, and pass the C compiler's flags after `--`", arg),
+        |meant| format!("unknown flag `{}`{}", arg, meant),
+    )
+}
+
+// `--edition <year>`
+fn usage(flag: &Flag) -> String {
+    if flag.value.is_empty() { flag.name.to_string() } else { format!("{} {}", flag.name, flag.value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_lists_every_flag() {
+        let help = help();
+        assert!(help.starts_with(&format!("z-lang {}\n", env!("CARGO_PKG_VERSION"))));
+        for flag in FLAGS {
+            assert!(help.lines().any(|line| line.trim_start().starts_with(flag.name) && line.ends_with(flag.help)), "{}", flag.name);
+        }
+        assert_eq!(unknown_flag("--bounds-checks"), "unknown flag `--bounds-checks`; did you mean `--bounds-check`?");
+        assert_eq!(unknown_flag("--editon=2024"), "unknown flag `--editon=2024`; did you mean `--edition`?");
+        assert_eq!(unknown_flag("--coverage"), "unknown flag `--coverage`; see `z-lang --help`, and pass the C compiler's flags after `--`");
+    }
+}
@@ -0,0 +1,382 @@
+// src/visit.rs
+//
+// Read-only and mutable traversal of the AST. Every method has a default
+// that walks into the node's children, so an implementation only overrides
+// the nodes it cares about and calls the matching `walk_*` function when it
+// still wants to descend.
+//
+// Function bodies and raw items are stored as tokens; the walkers structure
+// their loops and switches on the fly, and the mutable walkers write the
+// result back as tokens.
+
+use crate::ast::{flatten, Class, Export, Function, Global, Import, Interface, Item, Loop, LoopKind, Module, Namespace, OperatorOverload, Stmt, Switch, Variable};
+use crate::parser::parse_block;
+use crate::tokenizer::Token;
+
+pub trait Visit {
+    fn visit_module(&mut self, module: &Module) {
+        walk_module(self, module);
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_namespace(&mut self, namespace: &Namespace) {
+        walk_namespace(self, namespace);
+    }
+
+    fn visit_class(&mut self, class: &Class) {
+        walk_class(self, class);
+    }
+
+    /// Interfaces have no code to walk into
+    fn visit_interface(&mut self, _interface: &Interface) {}
+
+    fn visit_variable(&mut self, _variable: &Variable) {}
+
+    fn visit_global(&mut self, global: &Global) {
+        walk_global(self, global);
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_operator(&mut self, operator: &OperatorOverload) {
+        walk_operator(self, operator);
+    }
+
+    fn visit_import(&mut self, _import: &Import) {}
+
+    fn visit_export(&mut self, _export: &Export) {}
+
+    /// Plain C at file or namespace scope, before it is split into statements
+    fn visit_raw(&mut self, tokens: &[Token]) {
+        walk_block(self, tokens);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_loop(&mut self, loop_: &Loop) {
+        walk_loop(self, loop_);
+    }
+
+    fn visit_switch(&mut self, switch: &Switch) {
+        walk_switch(self, switch);
+    }
+
+    /// Unstructured code: statements, loop headers and global initializers
+    fn visit_tokens(&mut self, _tokens: &[Token]) {}
+}
+
+pub fn walk_module<V: Visit + ?Sized>(v: &mut V, module: &Module) {
+    for item in &module.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_item<V: Visit + ?Sized>(v: &mut V, item: &Item) {
+    match item {
+        Item::Class(class) => v.visit_class(class),
+        Item::Interface(interface) => v.visit_interface(interface),
+        Item::Namespace(namespace) => v.visit_namespace(namespace),
+        Item::Global(global) => v.visit_global(global),
+        Item::Import(import) => v.visit_import(import),
+        Item::Export(export) => v.visit_export(export),
+        Item::Operator(operator) => v.visit_operator(operator),
+        Item::Raw(tokens) => v.visit_raw(tokens),
+    }
+}
+
+/// Structures `tokens` and visits each statement
+pub fn walk_block<V: Visit + ?Sized>(v: &mut V, tokens: &[Token]) {
+    for stmt in parse_block(tokens) {
+        v.visit_stmt(&stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visit + ?Sized>(v: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Tokens(tokens) => v.visit_tokens(tokens),
+        Stmt::Loop(loop_) => v.visit_loop(loop_),
+        Stmt::Switch(switch) => v.visit_switch(switch),
+    }
+}
+
+pub fn walk_loop<V: Visit + ?Sized>(v: &mut V, loop_: &Loop) {
+    match &loop_.kind {
+        LoopKind::While { condition } | LoopKind::DoWhile { condition } => v.visit_tokens(condition),
+        LoopKind::For { init, condition, step } => {
+            v.visit_tokens(init);
+            v.visit_tokens(condition);
+            v.visit_tokens(step);
+        }
+        LoopKind::ForEach { binding, iterable } => {
+            v.visit_tokens(binding);
+            v.visit_tokens(iterable);
+        }
+    }
+    for stmt in &loop_.body {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_switch<V: Visit + ?Sized>(v: &mut V, switch: &Switch) {
+    v.visit_tokens(&switch.scrutinee);
+    for stmt in &switch.body {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_namespace<V: Visit + ?Sized>(v: &mut V, namespace: &Namespace) {
+    for item in &namespace.items {
+        v.visit_item(item);
+    }
+}
+
+pub fn walk_global<V: Visit + ?Sized>(v: &mut V, global: &Global) {
+    if let Some(init) = &global.init {
+        v.visit_tokens(init);
+    }
+}
+
+pub fn walk_class<V: Visit + ?Sized>(v: &mut V, class: &Class) {
+    for variable in &class.variables {
+        v.visit_variable(variable);
+    }
+    for function in &class.functions {
+        v.visit_function(function);
+    }
+    for operator in &class.operators {
+        v.visit_operator(operator);
+    }
+}
+
+pub fn walk_function<V: Visit + ?Sized>(v: &mut V, function: &Function) {
+    for param in &function.params {
+        v.visit_variable(param);
+    }
+    walk_block(v, &function.body_tokens);
+}
+
+pub fn walk_operator<V: Visit + ?Sized>(v: &mut V, operator: &OperatorOverload) {
+    for param in &operator.params {
+        v.visit_variable(param);
+    }
+    walk_block(v, &operator.body_tokens);
+}
+
+pub trait VisitMut {
+    fn visit_module_mut(&mut self, module: &mut Module) {
+        walk_module_mut(self, module);
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item);
+    }
+
+    fn visit_namespace_mut(&mut self, namespace: &mut Namespace) {
+        walk_namespace_mut(self, namespace);
+    }
+
+    fn visit_class_mut(&mut self, class: &mut Class) {
+        walk_class_mut(self, class);
+    }
+
+    fn visit_interface_mut(&mut self, _interface: &mut Interface) {}
+
+    fn visit_variable_mut(&mut self, _variable: &mut Variable) {}
+
+    fn visit_global_mut(&mut self, global: &mut Global) {
+        walk_global_mut(self, global);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        walk_function_mut(self, function);
+    }
+
+    fn visit_operator_mut(&mut self, operator: &mut OperatorOverload) {
+        walk_operator_mut(self, operator);
+    }
+
+    fn visit_import_mut(&mut self, _import: &mut Import) {}
+
+    fn visit_export_mut(&mut self, _export: &mut Export) {}
+
+    /// Plain C at file or namespace scope, before it is split into statements
+    fn visit_raw_mut(&mut self, tokens: &mut Vec<Token>) {
+        walk_block_mut(self, tokens);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_loop_mut(&mut self, loop_: &mut Loop) {
+        walk_loop_mut(self, loop_);
+    }
+
+    fn visit_switch_mut(&mut self, switch: &mut Switch) {
+        walk_switch_mut(self, switch);
+    }
+
+    /// Unstructured code: statements, loop headers and global initializers
+    fn visit_tokens_mut(&mut self, _tokens: &mut Vec<Token>) {}
+}
+
+pub fn walk_module_mut<V: VisitMut + ?Sized>(v: &mut V, module: &mut Module) {
+    for item in &mut module.items {
+        v.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitMut + ?Sized>(v: &mut V, item: &mut Item) {
+    match item {
+        Item::Class(class) => v.visit_class_mut(class),
+        Item::Interface(interface) => v.visit_interface_mut(interface),
+        Item::Namespace(namespace) => v.visit_namespace_mut(namespace),
+        Item::Global(global) => v.visit_global_mut(global),
+        Item::Import(import) => v.visit_import_mut(import),
+        Item::Export(export) => v.visit_export_mut(export),
+        Item::Operator(operator) => v.visit_operator_mut(operator),
+        Item::Raw(tokens) => v.visit_raw_mut(tokens),
+    }
+}
+
+/// Structures `tokens`, visits each statement and writes the result back
+pub fn walk_block_mut<V: VisitMut + ?Sized>(v: &mut V, tokens: &mut Vec<Token>) {
+    let mut stmts = parse_block(tokens);
+    for stmt in &mut stmts {
+        v.visit_stmt_mut(stmt);
+    }
+    *tokens = flatten(&stmts);
+}
+
+pub fn walk_stmt_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Tokens(tokens) => v.visit_tokens_mut(tokens),
+        Stmt::Loop(loop_) => v.visit_loop_mut(loop_),
+        Stmt::Switch(switch) => v.visit_switch_mut(switch),
+    }
+}
+
+pub fn walk_loop_mut<V: VisitMut + ?Sized>(v: &mut V, loop_: &mut Loop) {
+    match &mut loop_.kind {
+        LoopKind::While { condition } | LoopKind::DoWhile { condition } => v.visit_tokens_mut(condition),
+        LoopKind::For { init, condition, step } => {
+            v.visit_tokens_mut(init);
+            v.visit_tokens_mut(condition);
+            v.visit_tokens_mut(step);
+        }
+        LoopKind::ForEach { binding, iterable } => {
+            v.visit_tokens_mut(binding);
+            v.visit_tokens_mut(iterable);
+        }
+    }
+    for stmt in &mut loop_.body {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_switch_mut<V: VisitMut + ?Sized>(v: &mut V, switch: &mut Switch) {
+    v.visit_tokens_mut(&mut switch.scrutinee);
+    for stmt in &mut switch.body {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_namespace_mut<V: VisitMut + ?Sized>(v: &mut V, namespace: &mut Namespace) {
+    for item in &mut namespace.items {
+        v.visit_item_mut(item);
+    }
+}
+
+pub fn walk_global_mut<V: VisitMut + ?Sized>(v: &mut V, global: &mut Global) {
+    if let Some(init) = &mut global.init {
+        v.visit_tokens_mut(init);
+    }
+}
+
+pub fn walk_class_mut<V: VisitMut + ?Sized>(v: &mut V, class: &mut Class) {
+    for variable in &mut class.variables {
+        v.visit_variable_mut(variable);
+    }
+    for function in &mut class.functions {
+        v.visit_function_mut(function);
+    }
+    for operator in &mut class.operators {
+        v.visit_operator_mut(operator);
+    }
+}
+
+pub fn walk_function_mut<V: VisitMut + ?Sized>(v: &mut V, function: &mut Function) {
+    for param in &mut function.params {
+        v.visit_variable_mut(param);
+    }
+    walk_block_mut(v, &mut function.body_tokens);
+}
+
+pub fn walk_operator_mut<V: VisitMut + ?Sized>(v: &mut V, operator: &mut OperatorOverload) {
+    for param in &mut operator.params {
+        v.visit_variable_mut(param);
+    }
+    walk_block_mut(v, &mut operator.body_tokens);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+    use crate::tokenizer::tokenize;
+
+    #[derive(Default)]
+    struct Names {
+        classes: Vec<String>,
+        functions: Vec<String>,
+    }
+
+    impl Visit for Names {
+        fn visit_class(&mut self, class: &Class) {
+            self.classes.push(class.full_name());
+            walk_class(self, class);
+        }
+
+        fn visit_function(&mut self, function: &Function) {
+            self.functions.push(function.name.clone());
+        }
+    }
+
+    #[test]
+    fn test_visit_nested_items() {
+        let module = parse_module(&tokenize(
+            "namespace math { class Vector { int x; int len() { return x; } } }\nclass Other { }",
+        ), "_anon");
+        let mut names = Names::default();
+        names.visit_module(&module);
+        assert_eq!(names.classes, vec!["math_Vector", "Other"]);
+        assert_eq!(names.functions, vec!["len"]);
+    }
+
+    struct Rename;
+
+    impl VisitMut for Rename {
+        fn visit_tokens_mut(&mut self, tokens: &mut Vec<Token>) {
+            for token in tokens.iter_mut() {
+                if *token == Token::Identifier("x".to_string()) {
+                    *token = Token::Identifier("y".to_string());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit_mut_rewrites_bodies() {
+        let mut module = parse_module(&tokenize("class A { int get() { return x; } }"), "_anon");
+        Rename.visit_module_mut(&mut module);
+        let Item::Class(class) = &module.items[0] else { panic!("expected class") };
+        assert!(class.functions[0].body_tokens.contains(&Token::Identifier("y".to_string())));
+    }
+}
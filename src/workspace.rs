@@ -0,0 +1,352 @@
+// src/workspace.rs
+//
+// Files open in an editor, kept lexed and parsed between edits: the
+// foundation of a language server. `apply_change` takes an edit as the
+// editor reports it and returns the file's diagnostics.
+//
+// A document is kept as chunks of whole lines, each ending where a
+// top-level item does (after a `}` or `;`, or a preprocessor line, at
+// bracket depth 0). An edit re-lexes and re-parses only the chunks it
+// touches, plus the ones after while the result is unfinished, e.g. an
+// opened `{` or `/*` that now runs on. The other chunks keep their tokens
+// and items. The passes after lexing still run on the whole file, with
+// the cached tokens, and imports of open files read their text rather
+// than what is on disk.
+
+use std::collections::BTreeMap;
+
+use crate::ast::{Item, Module};
+use crate::compiler::{Compiler, Options, Session};
+use crate::diagnostics::Diagnostic;
+use crate::passes::file_tag;
+use crate::syntax::{parse_forgiving, SyntaxError};
+use crate::tokenizer::{tokenize, tokenize_spanned, Token};
+
+/// A place in a document, both counted from 0 as editors do; `character`
+/// counts chars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, character: usize) -> Self {
+        Position { line, character }
+    }
+}
+
+/// The text from `start` up to `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn new(start: Position, end: Position) -> Self {
+        Range { start, end }
+    }
+}
+
+/// The open documents, by the path imports name them with, e.g. `geo.z`
+pub struct Workspace {
+    options: Options,
+    documents: BTreeMap<String, Document>,
+}
+
+impl Workspace {
+    /// Documents are compiled with `options`; a file without `main` wants
+    /// `entry_point` off
+    pub fn new(options: Options) -> Self {
+        Workspace { options, documents: BTreeMap::new() }
+    }
+
+    /// Opens `file` with `text`, replacing it if already open
+    pub fn open(&mut self, file: &str, text: &str) -> Vec<Diagnostic> {
+        let mut document = Document { chunks: Vec::new(), diagnostics: Vec::new() };
+        document.edit(Range::new(Position::new(0, 0), Position::new(0, 0)), text, &file_tag(file));
+        self.documents.insert(file.to_string(), document);
+        self.check(file)
+    }
+
+    /// Replaces `range` of `file` with `new_text`. A file not open yet is
+    /// opened empty first
+    pub fn apply_change(&mut self, file: &str, range: Range, new_text: &str) -> Vec<Diagnostic> {
+        let document = self.documents.entry(file.to_string()).or_insert_with(|| Document { chunks: Vec::new(), diagnostics: Vec::new() });
+        document.edit(range, new_text, &file_tag(file));
+        self.check(file)
+    }
+
+    pub fn close(&mut self, file: &str) {
+        self.documents.remove(file);
+    }
+
+    pub fn text(&self, file: &str) -> Option<String> {
+        self.documents.get(file).map(Document::text)
+    }
+
+    /// The tokens of `file`, ending with `Token::Eof` like `tokenize`'s
+    pub fn tokens(&self, file: &str) -> Option<Vec<Token>> {
+        self.documents.get(file).map(Document::tokens)
+    }
+
+    /// The item tree of `file`, as `syntax::parse_forgiving` reads it
+    pub fn module(&self, file: &str) -> Option<Module> {
+        self.documents.get(file).map(Document::module)
+    }
+
+    /// The diagnostics of `file` as of its last change
+    pub fn diagnostics(&self, file: &str) -> &[Diagnostic] {
+        self.documents.get(file).map_or(&[], |document| &document.diagnostics)
+    }
+
+    // Syntax errors of `file` if it has any, else what the compiler reports
+    fn check(&mut self, file: &str) -> Vec<Diagnostic> {
+        let document = &self.documents[file];
+        let mut diagnostics: Vec<Diagnostic> = document.syntax_errors().map(|(line, error)| {
+            Diagnostic::error(&format!("{}:{}:{}", file, line, error.span.column), &error.message)
+        }).collect();
+        if diagnostics.is_empty() {
+            let mut session = Session::default();
+            for (path, open) in &self.documents {
+                session.sources.insert(path.clone(), open.text());
+                session.lexed.insert(path.clone(), open.tokens());
+            }
+            let compiler = Compiler::builder().options(self.options.clone()).build();
+            let text = document.text();
+            compiler.compile_in(&mut session, file, &text);
+            diagnostics = session.diagnostics;
+            // The passes assume complete programs, and one may trip over a
+            // half-written one
+            if let Some(ice) = session.ice {
+                diagnostics.push(Diagnostic::error(&ice.file, ice.message));
+            }
+        }
+        self.documents.get_mut(file).unwrap().diagnostics = diagnostics.clone();
+        diagnostics
+    }
+}
+
+struct Document {
+    chunks: Vec<Chunk>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Whole lines of a document, lexed and parsed on their own
+struct Chunk {
+    text: String,
+    /// Tokens without the `Token::Eof`
+    tokens: Vec<Token>,
+    items: Vec<Item>,
+    errors: Vec<SyntaxError>,
+}
+
+impl Chunk {
+    fn new(text: &str, file_tag: &str) -> Chunk {
+        let mut tokens = tokenize(text);
+        tokens.pop();
+        let (module, errors) = parse_forgiving(text, file_tag);
+        Chunk { text: text.to_string(), tokens, items: module.items, errors }
+    }
+
+    fn lines(&self) -> usize {
+        self.text.matches('\n').count()
+    }
+}
+
+impl Document {
+    fn text(&self) -> String {
+        self.chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+    }
+
+    fn tokens(&self) -> Vec<Token> {
+        let mut tokens: Vec<Token> = self.chunks.iter().flat_map(|chunk| chunk.tokens.iter().cloned()).collect();
+        tokens.push(Token::Eof);
+        tokens
+    }
+
+    // The items of the chunks, with the raw C they split joined again as
+    // parsing the whole text leaves it
+    fn module(&self) -> Module {
+        let mut items: Vec<Item> = Vec::new();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            for item in &chunk.items {
+                match (items.last_mut(), item) {
+                    (Some(Item::Raw(raw)), Item::Raw(more)) => raw.extend(more.iter().cloned()),
+                    _ => items.push(item.clone()),
+                }
+                // Only the last chunk ends the file
+                if i + 1 < self.chunks.len() {
+                    if let Some(Item::Raw(raw)) = items.last_mut() {
+                        if raw.last() == Some(&Token::Eof) {
+                            raw.pop();
+                        }
+                    }
+                }
+            }
+        }
+        Module { items }
+    }
+
+    // The syntax errors of every chunk, with their line in the document
+    fn syntax_errors(&self) -> impl Iterator<Item = (usize, &SyntaxError)> {
+        let mut first = 0;
+        self.chunks.iter().flat_map(move |chunk| {
+            let line = first;
+            first += chunk.lines();
+            chunk.errors.iter().map(move |error| (line + error.span.line, error))
+        })
+    }
+
+    // Applies the edit, and returns the indices of the chunks it replaced
+    fn edit(&mut self, range: Range, new_text: &str, file_tag: &str) -> std::ops::Range<usize> {
+        // The chunks holding the start and end of the range; a position
+        // past the last line is in the last chunk
+        let last = self.chunks.len().saturating_sub(1);
+        let mut first_line = 0;
+        let (mut start, mut end, mut region_line) = (None, last, 0);
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let next_line = first_line + chunk.lines();
+            if start.is_none() && (range.start.line < next_line || i == last) {
+                start = Some(i);
+                region_line = first_line;
+            }
+            if range.end.line < next_line {
+                end = i;
+                break;
+            }
+            first_line = next_line;
+        }
+        let start = start.unwrap_or(0);
+        let mut end = end.max(start);
+
+        let mut region: String = self.chunks[start..(end + 1).min(self.chunks.len())].iter().map(|chunk| chunk.text.as_str()).collect();
+        let from = offset(&region, range.start.line - region_line, range.start.character);
+        let to = offset(&region, range.end.line.saturating_sub(region_line), range.end.character).max(from);
+        region.replace_range(from..to, new_text);
+
+        // Takes in the chunks after until the region ends where an item does
+        let mut pieces = split(&region);
+        while !pieces.1 && end + 1 < self.chunks.len() {
+            end += 1;
+            region.push_str(&self.chunks[end].text);
+            pieces = split(&region);
+        }
+        let chunks: Vec<Chunk> = pieces.0.iter().map(|text| Chunk::new(text, file_tag)).collect();
+        let replaced = start..start + chunks.len();
+        self.chunks.splice(start..(end + 1).min(self.chunks.len()), chunks);
+        replaced
+    }
+}
+
+// The byte offset in `text` of `character` on `line`, clamped to the line
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut at = 0;
+    for _ in 0..line {
+        match text[at..].find('\n') {
+            Some(newline) => at += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let line_end = text[at..].find('\n').map_or(text.len(), |newline| at + newline);
+    text[at..line_end].char_indices().nth(character).map_or(line_end, |(i, _)| at + i)
+}
+
+// `text` in chunks, cut at the line breaks that end a top-level item, and
+// whether the last chunk ends at one (or `text` is empty)
+pub(crate) fn split(text: &str) -> (Vec<&str>, bool) {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    // The closing brackets still expected, innermost last
+    let mut open: Vec<&str> = Vec::new();
+    // The last token other than a comment since the previous cut
+    let mut last: Option<Token> = None;
+    let mut previous: Option<Token> = None;
+    let mut line_start = true;
+    let mut directive = false;
+    for (token, span) in tokenize_spanned(text) {
+        match &token {
+            Token::Newline => {
+                let continued = matches!(&previous, Some(Token::Symbol(s)) if s == "\\");
+                let ends_item = directive || matches!(&last, Some(Token::Symbol(s)) if s == "}" || s == ";");
+                if open.is_empty() && !continued && ends_item {
+                    pieces.push(&text[start..span.end]);
+                    start = span.end;
+                    last = None;
+                }
+                if !continued {
+                    line_start = true;
+                    directive = false;
+                }
+            }
+            Token::Comment(_) => {}
+            Token::Eof => break,
+            _ => {
+                match &token {
+                    Token::Symbol(s) if s == "(" => open.push(")"),
+                    Token::Symbol(s) if s == "[" => open.push("]"),
+                    Token::Symbol(s) if s == "{" => open.push("}"),
+                    // One that matches nothing is left for the parser to report
+                    Token::Symbol(s) if open.last() == Some(&s.as_str()) => {
+                        open.pop();
+                    }
+                    _ => {}
+                }
+                if line_start {
+                    directive = matches!(&token, Token::Symbol(s) if s == "#");
+                    line_start = false;
+                }
+                last = Some(token.clone());
+            }
+        }
+        previous = Some(token);
+    }
+    let finished = start == text.len();
+    if !finished {
+        pieces.push(&text[start..]);
+    }
+    (pieces, finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    const SOURCE: &str = "#import <std/io.z>\n\n// Adds\nint add(int a, int b)\n{\n  return a + b;\n}\n\nint twice(int a) {\n  return add(a, a);\n}\nint main() {\n  return twice(2);\n}\n";
+
+    #[test]
+    fn test_edits_reparse_only_what_they_touch() {
+        let mut document = Document { chunks: Vec::new(), diagnostics: Vec::new() };
+        document.edit(Range::new(Position::new(0, 0), Position::new(0, 0)), SOURCE, "main");
+        let texts: Vec<&str> = document.chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, vec!["#import <std/io.z>\n", "\n// Adds\nint add(int a, int b)\n{\n  return a + b;\n}\n", "\nint twice(int a) {\n  return add(a, a);\n}\n", "int main() {\n  return twice(2);\n}\n"]);
+
+        // Inside `twice`
+        assert_eq!(document.edit(Range::new(Position::new(9, 16), Position::new(9, 17)), "b", "main"), 2..3);
+        // An open `{` takes in what follows until it is closed
+        assert_eq!(document.edit(Range::new(Position::new(5, 15), Position::new(5, 15)), " {", "main"), 1..2);
+        assert_eq!(document.chunks.len(), 2);
+        assert_eq!(document.edit(Range::new(Position::new(5, 15), Position::new(5, 17)), "", "main"), 1..4);
+        assert_eq!(document.chunks.len(), 4);
+
+        let text = SOURCE.replace("add(a, a)", "add(a, b)");
+        assert_eq!(document.text(), text);
+        assert_eq!(document.tokens(), tokenize(&text));
+        assert_eq!(format!("{:?}", document.module().items), format!("{:?}", parse(&text, "main").items));
+    }
+
+    #[test]
+    fn test_workspace_reports_diagnostics() {
+        let mut workspace = Workspace::new(Options { entry_point: false, ..Options::default() });
+        assert!(workspace.open("geo.z", "namespace geo {\n  int area(int w, int h) { return w * h; }\n}\n").is_empty());
+        assert!(workspace.open("main.z", "#import <geo.z>\nint main() {\n  return geo::area(2, 3);\n}\n").is_empty());
+
+        let diagnostics = workspace.apply_change("main.z", Range::new(Position::new(2, 24), Position::new(2, 24)), ")");
+        assert_eq!(diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>(), vec!["error: unmatched `)`\n  --> main.z:3:25"]);
+        assert_eq!(workspace.diagnostics("main.z").len(), 1);
+        assert!(workspace.apply_change("main.z", Range::new(Position::new(2, 24), Position::new(2, 25)), "").is_empty());
+        assert_eq!(workspace.text("main.z").unwrap(), "#import <geo.z>\nint main() {\n  return geo::area(2, 3);\n}\n");
+    }
+}
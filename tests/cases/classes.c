@@ -0,0 +1,24 @@
+#include <stdio.h>
+typedef struct { int x; int y; } vec;
+int vec_dot(vec self, vec o);
+vec vec_operator_add(vec self, vec o);
+int vec_dot(vec self, vec o) {
+return self.x * o.x + self.y * o.y;
+} vec vec_operator_add(vec self, vec o) {
+vec r;
+r.x = self.x + o.x;
+r.y = self.y + o.y;
+return r;
+}
+int __z_main();
+int __z_main() {
+vec a;
+a.x = 1;
+a.y = 2;
+vec __z_tmp0 = vec_operator_add(a, a); vec b = vec_operator_add(__z_tmp0, a);
+printf("%d %d %d\n", b.x, b.y, vec_dot(a, b));
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
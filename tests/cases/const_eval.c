@@ -0,0 +1,18 @@
+#include <stdio.h>
+
+#define W 4
+const int H = W * 2;
+
+enum perms { Read = 1, Write = 2, Exec = 4, All = 7, Next };
+
+int grid[8][5];
+
+int __z_main();
+int __z_main() {
+grid[H - 1][W] = All;
+printf("%d %d %d\n", grid[H - 1][W], Next, (int)(sizeof(grid) / sizeof(int)));
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
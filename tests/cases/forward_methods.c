@@ -0,0 +1,17 @@
+#include <stdio.h>
+typedef struct { int n; } counter;
+int counter_twice(counter self);
+int counter_once(counter self);
+counter counter_operator_add(counter self, counter o);
+int counter_twice(counter self) { return counter_once(self) * 2; } int counter_once(counter self) { return self.n + 1; } counter counter_operator_add(counter self, counter o) { counter r; r.n = counter_twice(self) + counter_once(o); return r; }
+int __z_main();
+int __z_main() {
+counter c;
+c.n = 1;
+counter d = counter_operator_add(c, c);
+printf("%d %d\n", counter_twice(c), d.n);
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
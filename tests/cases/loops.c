@@ -0,0 +1,26 @@
+#include <stdio.h>
+typedef struct { int *data; int len; } span;
+int *span_begin(span self);
+int *span_end(span self);
+int *span_begin(span self) { return self.data; } int *span_end(span self) { return self.data + self.len; }
+int __z_main();
+int __z_main() {
+int raw[4] = { 1, 2, 3, 4 };
+span s;
+s.data = raw;
+s.len = 4;
+int sum = 0;
+for (__typeof__(span_begin(s)) __z_it_0 = span_begin(s), __z_end_0 = span_end(s); __z_it_0 != __z_end_0; __z_it_0++) { int x = *__z_it_0; sum += x; }
+for (int i = 0; i < 3; i++) {
+for (int j = 0; j < 3; j++) {
+if (j == 2) goto __z_continue_outer_1;
+if (i == 2) goto __z_break_outer_1;
+sum += 10;
+}
+__z_continue_outer_1 :; } __z_break_outer_1 :;
+printf("%d\n", sum);
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
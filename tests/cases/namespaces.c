@@ -0,0 +1,24 @@
+#include <stdio.h>
+
+int cfg_verbosity = 1;
+
+void cfg_louder() { cfg_verbosity += 1; }
+
+
+static int _anon_4af2a65d_counter = 0;
+
+
+
+int lib_version = 2;
+
+
+int __z_main();
+int __z_main() {
+cfg_louder();
+_anon_4af2a65d_counter += lib_version;
+printf("%d %d\n", cfg_verbosity, _anon_4af2a65d_counter);
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
@@ -0,0 +1,54 @@
+#ifndef __Z_RANGE
+#define __Z_RANGE
+typedef struct { long start; long end; } __z_range;
+#endif
+#include <stddef.h>
+#ifndef __Z_BOUNDS_CHECK
+#define __Z_BOUNDS_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline size_t __z_bounds_check(size_t index, size_t len, const char *file, int line) {
+if (index >= len) {
+fprintf(stderr, "%s:%d: index %zu is out of bounds for a slice of length %zu\n", file, line, index, len);
+abort();
+}
+return index;
+}
+static inline void __z_range_check(size_t lo, size_t hi, size_t len, const char *file, int line) {
+if (lo > hi || hi > len) {
+fprintf(stderr, "%s:%d: range %zu..%zu is out of bounds for a slice of length %zu\n", file, line, lo, hi, len);
+abort();
+}
+}
+#endif
+#include <stdio.h>
+
+#ifndef __Z_SLICE_INT
+#define __Z_SLICE_INT
+typedef struct { int *ptr; size_t len; } __z_slice_int;
+#endif
+int sum(__z_slice_int xs) {
+int total = 0;
+for (size_t i = 0, __z_end_0 = (xs.len); i < __z_end_0; i++) { total += xs.ptr[__z_bounds_check(i, xs.len, "tests/cases/ranges.z", 5)]; }
+return total;
+}
+long span(__z_range r) {
+long count = 0;
+for (long i = r.start, __z_end_1 = r.end; i < __z_end_1; i++) { count++; }
+return count;
+}
+int __z_main();
+int __z_main() {
+int raw[5] = { 1, 2, 3, 4, 5 };
+__z_slice_int xs = { raw, 5 };
+printf("%d %d %d\n", sum(xs), sum(({ __typeof__(xs) __z_s = xs; size_t __z_lo = 1, __z_hi = 3; __z_range_check(__z_lo, __z_hi, __z_s.len, "tests/cases/ranges.z", 16); __z_s.ptr += __z_lo; __z_s.len = __z_hi - __z_lo; __z_s; })), sum(({ __typeof__(xs) __z_s = xs; size_t __z_lo = 0, __z_hi = (1) + 1; __z_range_check(__z_lo, __z_hi, __z_s.len, "tests/cases/ranges.z", 16); __z_s.ptr += __z_lo; __z_s.len = __z_hi - __z_lo; __z_s; })));
+printf("%d\n", sum(({ __typeof__(xs) __z_s = xs; size_t __z_lo = 3, __z_hi = __z_s.len; __z_range_check(__z_lo, __z_hi, __z_s.len, "tests/cases/ranges.z", 17); __z_s.ptr += __z_lo; __z_s.len = __z_hi - __z_lo; __z_s; })));
+__z_range r = ((__z_range){ (2), (4) + 1 });
+printf("%ld %ld %ld\n", span(r), span(((__z_range){ (0), (10) })), r.end);
+for (int i = 1, __z_end_2 = (3) + 1; i < __z_end_2; i++) { printf("%d ", i); }
+printf("\n");
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
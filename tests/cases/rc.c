@@ -0,0 +1,34 @@
+#include <stdio.h>
+#include <stdlib.h>
+typedef struct node__data node__data;
+typedef struct { node__data *ptr; } node;
+struct node__data { int __z_refs; int value; node next; };
+node node_new(void);
+node node_retain(node self);
+void node_release(node self);
+void node_set(node *slot, node value);
+void node_drop(node self);
+void node_drop(node self) { printf("drop %d\n", self.ptr->value); }
+node node_new(void) { node self = { calloc(1, sizeof(node__data)) }; if (!self.ptr) abort(); self.ptr->__z_refs = 1; return self; }
+node node_retain(node self) { if (self.ptr) self.ptr->__z_refs++; return self; }
+void node_release(node self) { if (!self.ptr || --self.ptr->__z_refs > 0) return; node_drop(self); node_release(self.ptr->next); free(self.ptr); }
+void node_set(node *slot, node value) { node old = *slot; *slot = value; node_release(old); }
+void node_release_ptr(void *ptr) { node_release((node){ ptr }); }
+
+node push(node list, int value) { node_retain(list);
+node head = node_new();
+head.ptr->value = value;
+node_set(&head.ptr->next, node_retain(list));
+{ node_release(list); return head; }
+}
+int __z_main();
+int __z_main() {
+node empty = node_new();
+node one = push(empty, 1);
+node two = push(one, 2);
+printf("%d\n", two.ptr->next.ptr->value);
+{ __typeof__(0) __z_ret = 0; node_release(two); node_release(one); node_release(empty); return __z_ret; }
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
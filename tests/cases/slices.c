@@ -0,0 +1,40 @@
+#include <stddef.h>
+#ifndef __Z_BOUNDS_CHECK
+#define __Z_BOUNDS_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline size_t __z_bounds_check(size_t index, size_t len, const char *file, int line) {
+if (index >= len) {
+fprintf(stderr, "%s:%d: index %zu is out of bounds for a slice of length %zu\n", file, line, index, len);
+abort();
+}
+return index;
+}
+static inline void __z_range_check(size_t lo, size_t hi, size_t len, const char *file, int line) {
+if (lo > hi || hi > len) {
+fprintf(stderr, "%s:%d: range %zu..%zu is out of bounds for a slice of length %zu\n", file, line, lo, hi, len);
+abort();
+}
+}
+#endif
+#include <stdio.h>
+
+#ifndef __Z_SLICE_INT
+#define __Z_SLICE_INT
+typedef struct { int *ptr; size_t len; } __z_slice_int;
+#endif
+int sum(__z_slice_int xs) {
+int total = 0;
+for (size_t i = 0; i < xs.len; i++) { total += xs.ptr[__z_bounds_check(i, xs.len, "tests/cases/slices.z", 5)]; }
+return total;
+}
+int __z_main();
+int __z_main() {
+int raw[3] = { 1, 2, 3 };
+__z_slice_int xs = { raw, 3 };
+printf("%d\n", sum(xs));
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
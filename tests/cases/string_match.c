@@ -0,0 +1,35 @@
+#include <string.h>
+#ifndef __Z_STR_HASH
+#define __Z_STR_HASH
+static inline unsigned int __z_str_hash(const char *s) {
+unsigned int h = 2166136261;
+while (*s) { h ^= (unsigned char)*s++; h *= 16777619; }
+return h;
+}
+#endif
+#ifndef __Z_RANGE
+#define __Z_RANGE
+typedef struct { long start; long end; } __z_range;
+#endif
+#include <stdio.h>
+int score(const char *word) {
+int n = 0;
+{ const char *__z_match_0 = (word); if (strcmp(__z_match_0, "one") == 0) { n = 1; } else if (strcmp(__z_match_0, "two") == 0 || strcmp(__z_match_0, "deux") == 0) { n = 2; } else if (strcmp(__z_match_0, "three") == 0) { n = 3; } else { n = -1; } }
+return n;
+}
+const char *kind(const char *word) {
+{ const char *__z_match_1 = (word); int __z_arm_1 = -1; switch (__z_str_hash(__z_match_1)) { case 3826002220 : if (strcmp(__z_match_1, "a") == 0) __z_arm_1 = 0; break; case 3758891744 : if (strcmp(__z_match_1, "e") == 0) __z_arm_1 = 1; break; case 3960223172 : if (strcmp(__z_match_1, "i") == 0) __z_arm_1 = 2; break; case 3926667934 : if (strcmp(__z_match_1, "o") == 0) __z_arm_1 = 3; break; case 4027333648 : if (strcmp(__z_match_1, "u") == 0) __z_arm_1 = 4; break; case 4228665076 : if (strcmp(__z_match_1, "y") == 0) __z_arm_1 = 5; break; case 4060888886 : if (strcmp(__z_match_1, "w") == 0) __z_arm_1 = 6; break; case 3876335077 : if (strcmp(__z_match_1, "\x62") == 0) __z_arm_1 = 7; break; case 2166136261 : if (strcmp(__z_match_1, "") == 0) __z_arm_1 = 8; break; } if (__z_arm_1 == 0) { return "vowel"; } else if (__z_arm_1 == 1) { return "vowel"; } else if (__z_arm_1 == 2) { return "vowel"; } else if (__z_arm_1 == 3) { return "vowel"; } else if (__z_arm_1 == 4) { return "vowel"; } else if (__z_arm_1 == 5) { return "sometimes"; } else if (__z_arm_1 == 6) { return "sometimes"; } else if (__z_arm_1 == 7) { return "b"; } else if (__z_arm_1 == 8) { return "empty"; } }
+return "consonant";
+}
+int __z_main();
+int __z_main() {
+const char *words[] = { "one", "deux", "three", "four" };
+for (int i = 0, __z_end_0 = (4); i < __z_end_0; i++) {
+printf("%s=%d ", words[i], score(words[i]));
+}
+printf("\n%s %s %s %s %s\n", kind("a"), kind("w"), kind("b"), kind(""), kind("z"));
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
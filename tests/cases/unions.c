@@ -0,0 +1,44 @@
+#ifndef __Z_UNION_CHECK
+#define __Z_UNION_CHECK
+#include <stdio.h>
+#include <stdlib.h>
+static inline void *__z_union_write(void *u, int *tag, int member) {
+*tag = member;
+return u;
+}
+static inline void *__z_union_read(void *u, int tag, int member, const char *name, const char *const *members, const char *file, int line) {
+if (tag != member) {
+fprintf(stderr, "%s:%d: read of `%s.%s` but it holds %s%s%s\n", file, line, name, members[member - 1], tag ? "`" : "", tag ? members[tag - 1] : "no member", tag ? "`" : "");
+abort();
+}
+return u;
+}
+#endif
+#include <stdio.h>
+
+
+typedef union num_Bits { float f; unsigned int u; } num_Bits;
+unsigned int num_Bits_raw(num_Bits self);
+unsigned int num_Bits_raw(num_Bits self) { return self.u; }
+
+
+typedef struct Value { int __z_tag; union { int i; double d; }; } Value;
+double Value_twice(Value self);
+double Value_twice(Value self) { return ((__typeof__(&self)) __z_union_read(&self, self.__z_tag, 2, "Value", (const char *const[]) { "i", "d" }, "tests/cases/unions.z", 11))->d * 2; }
+
+void set(Value *v, double d) { ((__typeof__(v)) __z_union_write(v, &v->__z_tag, 2))->d = d; }
+
+int __z_main();
+int __z_main() {
+num_Bits b;
+b.f = 1.0;
+printf("%x\n", num_Bits_raw(b));
+Value v = {.__z_tag = 1,.i = 3 };
+printf("%d\n", ((__typeof__(&v)) __z_union_read(&v, v.__z_tag, 1, "Value", (const char *const[]) { "i", "d" }, "tests/cases/unions.z", 21))->i);
+set(&v, 2.5);
+printf("%.1f\n", Value_twice(v));
+return 0;
+}
+
+int __z_argc; char **__z_argv;
+int main(int argc, char **argv) { __z_argc = argc; __z_argv = argv; return __z_main(); }
@@ -0,0 +1,11 @@
+// tests/common/mod.rs
+//
+// Helpers shared by the integration tests
+
+use std::process::Command;
+
+/// Whether gcc, which the driver builds with, can be run. Tests that
+/// execute compiled programs skip themselves without it.
+pub fn has_gcc() -> bool {
+    Command::new("gcc").arg("--version").output().is_ok_and(|o| o.status.success())
+}
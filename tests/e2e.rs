@@ -0,0 +1,575 @@
+// tests/e2e.rs
+//
+// End-to-end tests: each writes a small project to a scratch directory,
+// builds it with the `z-lang` binary and runs the result, checking what the
+// program prints and its exit status. They pass with a note on stderr when
+// gcc isn't installed.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+mod common;
+
+struct Run {
+    stdout: String,
+    stderr: String,
+    /// None when the program was killed by a signal (e.g. `abort()`)
+    code: Option<i32>,
+}
+
+// A fresh directory holding `files`
+fn project(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tarnish_e2e_{}", test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    dir
+}
+
+// Runs the compiler in `dir`, failing the test if it fails
+fn z_lang(dir: &PathBuf, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).args(args).current_dir(dir).output().unwrap();
+    assert!(output.status.success(), "z-lang {:?} failed:\n{}", args, String::from_utf8_lossy(&output.stderr));
+}
+
+// Builds `main.z` with `flags` and runs it with `args`; None without gcc
+fn build_and_run(test: &str, files: &[(&str, &str)], flags: &[&str], args: &[&str]) -> Option<Run> {
+    if !common::has_gcc() {
+        eprintln!("skipping {}: gcc not found", test);
+        return None;
+    }
+    let dir = project(test, files);
+    let mut compiler_args = vec!["main.z", "-o", "prog"];
+    compiler_args.extend(flags);
+    z_lang(&dir, &compiler_args);
+    let output = Command::new(dir.join("prog")).args(args).output().unwrap();
+    Some(Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code(),
+    })
+}
+
+#[test]
+fn test_exit_code_and_arguments() {
+    let main = "#include <stdio.h>\nint main(int argc, char** argv){\n  for (int i = 1; i < argc; i++) printf(\"%s\\n\", argv[i]);\n  return argc - 1;\n}";
+    let Some(run) = build_and_run("args", &[("main.z", main)], &[], &["a", "b c"]) else { return };
+    assert_eq!(run.stdout, "a\nb c\n");
+    assert_eq!(run.code, Some(2));
+
+    let Some(run) = build_and_run("void_main", &[("main.z", "void main(){ }")], &[], &[]) else { return };
+    assert_eq!(run.code, Some(0));
+
+    // Falling off the end of an `int main` still exits with 0
+    let main = "int answer(){ return 42; }\nint main(){ int x = answer(); if (x) { x++; } }";
+    let Some(run) = build_and_run("int_main_without_return", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_imports_and_operators() {
+    let vec = "class vec {\n  int x;\n  vec operator+(vec o){ vec r; r.x = self.x + o.x; return r; }\n  vec operator*(vec o){ vec r; r.x = self.x * o.x; return r; }\n}";
+    let main = "#import <vec.z>\nint main(){\n  vec a; a.x = 2;\n  vec b; b.x = 3;\n  vec c = a + b * b;\n  return c.x;\n}";
+    let Some(run) = build_and_run("imports", &[("vec.z", vec), ("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.code, Some(11));
+}
+
+#[test]
+fn test_local_c_headers() {
+    let header = "static inline int triple(int n) { return n * 3; }\n";
+    let main = "#import <c:mylib.h>\nint main(){ return triple(4); }";
+    let Some(run) = build_and_run("local_c_header", &[("mylib.h", header), ("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.code, Some(12), "{}", run.stderr);
+}
+
+#[test]
+fn test_namespaces_merge_across_files() {
+    let a = "namespace math {\n  int scale = 3;\n  int square(int x){ return x * x; }\n}";
+    let b = "#import <a.z>\nnamespace math {\n  int scaled(int x){ return scale * square(x); }\n}";
+    let main = "#import <b.z>\nint main(){ return math::scaled(2) + math::scale; }";
+    let Some(run) = build_and_run("namespaces", &[("a.z", a), ("b.z", b), ("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.code, Some(15));
+
+    let lib = "#import <a.z>\nexport namespace math as m;\nnamespace api { export math::square as sq; }";
+    let main = "#import <lib.z>\nint main(){ return api::sq(m::scale); }";
+    let Some(run) = build_and_run("exports", &[("a.z", a), ("lib.z", lib), ("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.code, Some(9));
+}
+
+#[test]
+fn test_rc_values_are_freed() {
+    let main = "#include <stdio.h>
+int live = 0;
+rc class big {
+  int value;
+  void drop(){ live -= 1; }
+  big operator+(big other){
+    big out = big::new();
+    live += 1;
+    out.value = self.value + other.value;
+    return out;
+  }
+}
+big make(int value){ big b = big::new(); live += 1; b.value = value; return b; }
+@arena int total(big a, big b, big c){
+  big sum = a + b + c;
+  return sum.value;
+}
+int main(){
+  big a = make(1);
+  big b = make(2);
+  big c = make(3);
+  printf(\"%d\\n\", total(a, b, c));
+  return live;
+}";
+    let Some(run) = build_and_run("rc", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "6\n");
+    // Only `a`, `b` and `c` are still alive when `main` returns
+    assert_eq!(run.code, Some(3));
+}
+
+#[test]
+fn test_threads_share_a_mutex() {
+    let main = "#include <stdio.h>
+#import <std/thread.z>
+int counter = 0;
+std::thread::Mutex lock;
+void work(void* arg){
+  for (int i = 0; i < *(int*)arg; i++) {
+    lock.lock();
+    counter++;
+    lock.unlock();
+  }
+}
+int main(){
+  lock = std::thread::mutex();
+  int n = 10000;
+  std::thread::Thread a = std::thread::spawn(work, &n);
+  std::thread::Thread b = std::thread::spawn(work, &n);
+  int failed = a.join() || b.join();
+  lock.destroy();
+  printf(\"%d\\n\", counter);
+  return failed;
+}";
+    let Some(run) = build_and_run("threads", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "20000\n");
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_atomics_count_across_threads() {
+    let main = "#include <stdio.h>
+#import <std/thread.z>
+Atomic<int> counter = 0;
+void work(void* arg){
+  for (int i = 0; i < 10000; i++) counter.fetch_add(1);
+}
+int main(){
+  std::thread::Thread a = std::thread::spawn(work, NULL);
+  std::thread::Thread b = std::thread::spawn(work, NULL);
+  int failed = a.join() || b.join();
+  int expected = 20000;
+  int swapped = counter.compare_exchange(&expected, 0);
+  printf(\"%d %d\\n\", swapped, counter.load());
+  return failed;
+}";
+    let Some(run) = build_and_run("atomics", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "1 0\n");
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_process_output_is_captured() {
+    let main = "#include <stdio.h>
+#import <std/process.z>
+int main(){
+  const char* args[] = {\"-c\", \"echo \\\"$0\\\"; echo oops >&2; exit 3\", \"it's\", NULL};
+  std::Result r = std::process::run(\"sh\", args);
+  if (r.is_err()) return 1;
+  std::process::Output* o = r.value;
+  printf(\"%s|%s|%d\\n\", o->out, o->err, o->status);
+  std::process::release(o);
+  return 0;
+}";
+    let Some(run) = build_and_run("process", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "it's\n|oops\n|3\n");
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_tcp_round_trip() {
+    let main = "#include <stdio.h>
+#import <std/net.z>
+int main(){
+  std::Result bound = std::net::bind(\"127.0.0.1\", 0);
+  if (bound.is_err()) return 1;
+  std::net::Listener* server = bound.value;
+  std::Result connected = std::net::connect(\"127.0.0.1\", server->port());
+  if (connected.is_err()) return 2;
+  std::net::Stream* client = connected.value;
+  client->write(\"ping\", 4);
+  std::Result accepted = std::net::accept(server);
+  if (accepted.is_err()) return 3;
+  std::net::Stream* peer = accepted.value;
+  char buffer[8] = {0};
+  long n = peer->read(buffer, 4);
+  printf(\"%ld %s\\n\", n, buffer);
+  peer->close();
+  client->close();
+  server->close();
+  return 0;
+}";
+    let Some(run) = build_and_run("net", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "4 ping\n");
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_panics_are_caught_or_traced() {
+    let main = "#include <stdio.h>
+int digit(char c){
+  if (c < '0' || c > '9') panic(\"not a digit: %c\", c);
+  return c - '0';
+}
+int parse(char c){
+  try {
+    return digit(c);
+  } catch (e) {
+    printf(\"%s\\n\", e);
+  }
+  return -1;
+}
+int main(){
+  printf(\"%d\\n\", parse('x'));
+  return digit('y');
+}";
+    let Some(run) = build_and_run("panic", &[("main.z", main)], &["--panic-trace"], &[]) else { return };
+    assert_eq!(run.stdout, "not a digit: x\n-1\n");
+    assert_eq!(run.code, None);
+    assert!(run.stderr.contains("main.z:3: panic: not a digit: y\n  in digit (main.z:2)\n  in main (main.z:14)\n"), "{}", run.stderr);
+}
+
+#[test]
+fn test_interface_values_share_an_array() {
+    let main = "#include <stdio.h>
+interface Shape {
+  double area();
+  const char* name();
+}
+class Circle {
+  double r;
+  double area() { return 3.0 * self.r * self.r; }
+  const char* name() { return \"circle\"; }
+}
+class Square {
+  double side;
+  double area() { return self.side * self.side; }
+  const char* name() { return \"square\"; }
+}
+int main(){
+  Circle c = { 2.0 };
+  Square s = { 3.0 };
+  Shape shapes[2] = { Shape(&c), Shape(&s) };
+  for (int i = 0; i < 2; i++) {
+    Shape shape = shapes[i];
+    Square* square = shape as? Square;
+    printf(\"%s %.1f %d\\n\", shape.name(), shape.area(), square != NULL);
+  }
+  any value = &c;
+  Circle* circle = value as? Circle;
+  return circle == &c ? 0 : 1;
+}";
+    let Some(run) = build_and_run("interfaces", &[("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "circle 12.0 0\nsquare 9.0 1\n");
+    assert_eq!(run.code, Some(0));
+}
+
+#[test]
+fn test_runtime_checks_abort() {
+    let slices = "int main(){\n  int raw[2] = {1, 2};\n  int[] xs = {raw, 2};\n  return xs[2];\n}";
+    let Some(run) = build_and_run("bounds", &[("main.z", slices)], &["--bounds-check"], &[]) else { return };
+    assert_eq!(run.code, None);
+    assert!(run.stderr.contains("main.z:4: index 2 is out of bounds for a slice of length 2"), "{}", run.stderr);
+
+    let overflow = "#include <limits.h>\nint main(){\n  int x = INT_MAX;\n  x += 1;\n  return 0;\n}";
+    let Some(run) = build_and_run("overflow", &[("main.z", overflow)], &["--overflow-checks"], &[]) else { return };
+    assert_eq!(run.code, None);
+    assert!(run.stderr.contains("main.z:4: integer overflow in `+`"), "{}", run.stderr);
+
+    // Unsigned arithmetic wraps around
+    let wraps = "int main(){\n  unsigned int u = 0;\n  u -= 1;\n  unsigned long h = u * u;\n  return u == 4294967295u && h == 1 ? 7 : 1;\n}";
+    let Some(run) = build_and_run("wraps", &[("main.z", wraps)], &["--overflow-checks"], &[]) else { return };
+    assert_eq!(run.code, Some(7), "{}", run.stderr);
+}
+
+#[test]
+fn test_static_library_with_interface() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_static_library_with_interface: gcc not found");
+        return;
+    }
+    let library = "namespace geo {\n  class Point {\n    int x;\n    int y;\n    int sum(){ return self.x + self.y; }\n  }\n  int origin_x(){ return 0; }\n}";
+    let lib_dir = project("staticlib", &[("main.z", library)]);
+    z_lang(&lib_dir, &["--crate-type", "staticlib", "--emit", "interface"]);
+
+    let program = "#import <out.zi>\nint main(){\n  geo::Point p;\n  p.x = 4;\n  p.y = 5;\n  return p.sum() + geo::origin_x();\n}";
+    let dir = project("staticlib_user", &[("main.z", program)]);
+    fs::copy(lib_dir.join("out.zi"), dir.join("out.zi")).unwrap();
+    fs::copy(lib_dir.join("libout.a"), dir.join("libout.a")).unwrap();
+    z_lang(&dir, &["main.z", "-o", "prog", "-L.", "-lout"]);
+    let status = Command::new(dir.join("prog")).status().unwrap();
+    assert_eq!(status.code(), Some(9));
+}
+
+// The library's file name differs on macOS and Windows
+#[cfg(target_os = "linux")]
+#[test]
+fn test_shared_library_exports_annotated_functions() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_shared_library_exports_annotated_functions: gcc not found");
+        return;
+    }
+    let library = "namespace geo {\n  @export int area(int w, int h){ return w * h; }\n  int twice(int n){ return n * 2; }\n  @no_mangle int doubled_area(int w, int h){ return twice(area(w, h)); }\n}\n@export int volume(int w, int h, int d){ return geo::doubled_area(w, h) * d / 2; }";
+    let program = "#include \"out.h\"\nint main(void) { return area(2, 3) + volume(1, 2, 3); }";
+    let dir = project("cdylib", &[("main.z", library), ("user.c", program)]);
+    z_lang(&dir, &["--crate-type", "cdylib"]);
+    let header = fs::read_to_string(dir.join("out.h")).unwrap();
+    assert!(header.contains("int area(int w, int h);") && header.contains("int volume(int w, int h, int d);"), "{}", header);
+    assert!(!header.contains("twice") && !header.contains("doubled_area"), "{}", header);
+
+    let output = Command::new("gcc").args(["user.c", "-L.", "-lout", "-Wl,-rpath,$ORIGIN", "-o", "user"]).current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let status = Command::new(dir.join("user")).status().unwrap();
+    assert_eq!(status.code(), Some(12));
+}
+
+#[test]
+fn test_call_graph_is_emitted() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_call_graph_is_emitted: gcc not found");
+        return;
+    }
+    let geo = "class Point {\n  int x;\n  int sum(){ return self.x + twice(self.x); }\n}\nint twice(int n){ return n * 2; }";
+    let main = "#import <geo.z>\nint depth(int n){ return n ? depth(n - 1) : 0; }\nint main(){\n  Point p;\n  p.x = 1;\n  return p.sum() + depth(3);\n}";
+    let dir = project("callgraph", &[("geo.z", geo), ("main.z", main)]);
+    z_lang(&dir, &["main.z", "-o", "prog", "--emit", "callgraph"]);
+    let dot = fs::read_to_string(dir.join("callgraph.dot")).unwrap();
+    assert!(dot.starts_with("digraph calls {"));
+    assert!(dot.contains("label=\"geo.z\";"), "{}", dot);
+    assert!(dot.contains("\"main\" -> \"Point_sum\";"), "{}", dot);
+    assert!(dot.contains("\"Point_sum\" -> \"twice\";"), "{}", dot);
+    assert!(dot.contains("\"depth\" [label=\"depth\", color=red];"), "{}", dot);
+}
+
+#[test]
+fn test_build_directory_and_clean() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_build_directory_and_clean: gcc not found");
+        return;
+    }
+    let dir = project("target_dir", &[("main.z", "int main(){ return 4; }"), ("tarnish.toml", "[build]\ntarget-dir = \"build\"\n")]);
+    z_lang(&dir, &["main.z"]);
+    assert!(dir.join("build/main.c").exists() && !dir.join("main.z.c").exists() && !dir.join("a.out").exists());
+    let status = Command::new(dir.join("build/main")).status().unwrap();
+    assert_eq!(status.code(), Some(4));
+    let leftovers: Vec<String> = fs::read_dir(dir.join("build")).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).filter(|n| n.matches('.').count() > 1).collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+    z_lang(&dir, &["clean"]);
+    assert!(!dir.join("build").exists() && dir.join("main.z").exists());
+}
+
+#[test]
+fn test_files_are_embedded() {
+    let main = "#include <stdio.h>\n#embed \"hello.txt\" as HELLO\nint main(){\n  fputs((const char*)HELLO, stdout);\n  return HELLO_LEN;\n}";
+    let Some(run) = build_and_run("embed", &[("hello.txt", "hi \"there\"?\n"), ("main.z", main)], &[], &[]) else { return };
+    assert_eq!(run.stdout, "hi \"there\"?\n");
+    assert_eq!(run.code, Some(12));
+}
+
+#[test]
+fn test_build_script_generates_sources() {
+    let build = "#include <stdio.h>\n#include <stdlib.h>\nint main(){\n  printf(\"int answer(){ return %d; }\\n\", 40 + 2);\n  char path[1024];\n  snprintf(path, sizeof path, \"%s/stamp.txt\", getenv(\"TARNISH_OUT_DIR\"));\n  FILE* f = fopen(path, \"w\");\n  fputs(getenv(\"TARNISH_FEATURES\"), f);\n  fclose(f);\n  return 0;\n}";
+    let main = "#include <stdio.h>\n#import <generated.z>\n#embed \"stamp.txt\" as STAMP\nint main(){\n  fputs((const char*)STAMP, stdout);\n  return answer();\n}";
+    let Some(run) = build_and_run("build_script", &[("build.z", build), ("main.z", main)], &["--features", "fast,small"], &[]) else { return };
+    assert_eq!(run.stdout, "fast,small");
+    assert_eq!(run.code, Some(42));
+}
+
+#[test]
+fn test_pgo_profile_builds_in_two_steps() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_pgo_profile_builds_in_two_steps: gcc not found");
+        return;
+    }
+    let main = "int collatz(int n){ int steps = 0; while (n != 1) { n = n % 2 ? 3 * n + 1 : n / 2; steps++; } return steps; }\nint main(){ return collatz(27) % 100; }";
+    let manifest = "[profile.release-pgo]\nopt-level = 3\nbounds-check = true";
+    let dir = project("pgo", &[("main.z", main), ("tarnish.toml", manifest)]);
+    let build = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).args(["--profile", "release-pgo"]).current_dir(&dir).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    assert!(build().contains("run it on typical input"));
+    // The instrumented program records its profile wherever it runs from
+    let status = Command::new(dir.join("target/out")).current_dir(std::env::temp_dir()).status().unwrap();
+    assert_eq!(status.code(), Some(11));
+    assert!(build().contains("optimized with the profile recorded in"));
+    assert_eq!(Command::new(dir.join("target/out")).status().unwrap().code(), Some(11));
+}
+
+#[test]
+fn test_debug_builds_get_pretty_printers() {
+    if !common::has_gcc() {
+        eprintln!("skipping test_debug_builds_get_pretty_printers: gcc not found");
+        return;
+    }
+    let main = "#include <stdio.h>\nclass Point {\n  int x;\n  const char* to_string(){ return self.x ? \"point\" : \"origin\"; }\n}\nint main(){\n  Point p;\n  p.x = 0;\n  puts(p.to_string());\n  return 0;\n}";
+    let dir = project("pretty_printers", &[("main.z", main)]);
+    z_lang(&dir, &["main.z", "-o", "prog"]);
+    assert!(!dir.join("prog-gdb.py").exists());
+    z_lang(&dir, &["main.z", "-o", "prog", "-g"]);
+    let gdb = fs::read_to_string(dir.join("prog-gdb.py")).unwrap();
+    assert!(gdb.contains("\"Point\": (\"Point\", False, \"Point_to_string\"),"), "{}", gdb);
+    assert!(fs::read_to_string(dir.join("prog.lldb.py")).unwrap().contains("type synthetic add"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_crashes_are_reported() {
+    let geo = "namespace geo {\n  class Grid {\n    int* cells;\n    int at(int i){ return self.cells[i]; }\n  }\n}";
+    let main = "#import <geo.z>\nint total(geo::Grid g){ return g.at(0) + g.at(1); }\nint main(){\n  geo::Grid g;\n  g.cells = 0;\n  return total(g);\n}";
+    let Some(run) = build_and_run("crash_report", &[("geo.z", geo), ("main.z", main)], &["--crash-report"], &[]) else { return };
+    assert_eq!(run.code, None);
+    assert!(run.stderr.starts_with("crash: segmentation fault (invalid memory access)\n  in geo::Grid::at (geo.z)\n  in total (main.z)\n  in main (main.z)\n"), "{}", run.stderr);
+}
+
+#[test]
+fn test_installed_compiler_uses_its_standard_library() {
+    if !common::has_gcc() {
+        eprintln!("skipping install: gcc not found");
+        return;
+    }
+    let root = project("install_root", &[]);
+    z_lang(&root, &["install", "--root", "."]);
+    fs::write(root.join("lib/std/time.z"), "int installed(){ return 7; }").unwrap();
+
+    // Run from another directory, where nothing of the toolchain is
+    let dir = project("install_project", &[("main.z", "#import <std/time.z>\nint main(){ return installed(); }")]);
+    let installed = root.join("bin").join(format!("z-lang{}", std::env::consts::EXE_SUFFIX));
+    let output = Command::new(&installed).args(["main.z", "-o", "prog"]).current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(dir.join("prog")).status().unwrap().code(), Some(7));
+}
+
+#[test]
+fn test_bundle_builds_without_the_project() {
+    if !common::has_gcc() {
+        eprintln!("skipping bundle: gcc not found");
+        return;
+    }
+    let main = "#include \"point.h\"\n#import <std/result.z>\nint main(){ point p; p.x = 5; return p.x; }";
+    let dir = project("bundle", &[("main.z", main), ("point.h", "#pragma once\ntypedef struct { int x; } point;\n")]);
+    z_lang(&dir, &["main.z", "--bundle", "-o", "app.c"]);
+
+    // Only the one file, where nothing of the project is
+    let elsewhere = project("bundle_elsewhere", &[("app.c", &fs::read_to_string(dir.join("app.c")).unwrap())]);
+    let status = Command::new("gcc").args(["app.c", "-o", "app"]).current_dir(&elsewhere).status().unwrap();
+    assert!(status.success());
+    assert_eq!(Command::new(elsewhere.join("app")).status().unwrap().code(), Some(5));
+}
+
+#[test]
+fn test_compile_commands_name_the_generated_c() {
+    if !common::has_gcc() {
+        eprintln!("skipping compile_commands: gcc not found");
+        return;
+    }
+    let dir = project("compile_commands", &[("main.z", "int main(){ return 0; }")]);
+    z_lang(&dir, &["main.z", "-O2", "-DLEVEL=\"high\"", "--emit", "compile-commands"]);
+    let database = fs::read_to_string(dir.join("compile_commands.json")).unwrap();
+    // The working directory, as the compiler sees it
+    let directory = dir.canonicalize().unwrap();
+    assert!(database.contains(&format!("\"file\": \"{}\",", directory.join("target").join("main.c").display())), "{}", database);
+    assert!(database.contains("\"target/main.c\", \"-O2\", \"-DLEVEL=\\\"high\\\"\", \"-lm\", \"-o\", \"target/main\"]"), "{}", database);
+    assert!(dir.join("target/main").exists());
+}
+
+#[test]
+fn test_native_lint_points_into_z_source() {
+    if !common::has_gcc() {
+        eprintln!("skipping native lint: gcc not found");
+        return;
+    }
+    let geo = "namespace geo {\n  class Point {\n    int x;\n\n    int sum() {\n      int unused = 3;\n      return self.x;\n    }\n  }\n}";
+    let main = "#import <geo.z>\nint main(){\n  geo::Point p;\n  p.x = 1;\n  return p.sum();\n}";
+    let dir = project("lint_native", &[("geo.z", geo), ("main.z", main)]);
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).args(["lint", "main.z", "--native"]).current_dir(&dir).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("warning: unused variable 'unused' [-Wunused-variable] (in `geo::Point::sum`)\n  --> geo.z:6:11"), "{}", stderr);
+    assert!(!dir.join("target/main").exists());
+}
+
+#[test]
+fn test_tests_and_doc_examples_run() {
+    if !common::has_gcc() {
+        eprintln!("skipping tests: gcc not found");
+        return;
+    }
+    let lib = "#include <assert.h>\n/// ```\n/// assert(twice(2) == 4);\n/// ```\nint twice(int n){ return n * 2; }\n\n/// ```\n/// assert(twice(3) == 7);\n/// ```\nint thrice(int n){ return n * 3; }\n@test void twice_of_zero(){ assert(twice(0) == 0); }";
+    let main = "#import <lib.z>\n@test void fails(){ assert(thrice(1) == 4); }\nint main(){ return thrice(0); }";
+    let dir = project("tests", &[("lib.z", lib), ("main.z", main)]);
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).arg("test").current_dir(&dir).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.starts_with("running 4 tests\ntest twice_of_zero ... ok\ntest fails ... FAILED\ntest lib.z:2 (doc example) ... ok\ntest lib.z:7 (doc example) ... FAILED\n"), "{}", stdout);
+    // The failed assertion points into the doc comment
+    assert!(stdout.contains("lib.z:8: __z_doc_example: Assertion `twice(3) == 7' failed."), "{}", stdout);
+    // and a `@test` function's at its line
+    assert!(stdout.contains("main.z:2: fails: Assertion `thrice(1) == 4' failed."), "{}", stdout);
+    assert!(stdout.ends_with("test result: FAILED. 2 passed; 2 failed\n"), "{}", stdout);
+
+    // Other builds leave the tests out
+    z_lang(&dir, &["main.z", "-o", "prog"]);
+    assert!(!fs::read_to_string(dir.join("target/main.c")).unwrap().contains("twice_of_zero"));
+}
+
+#[test]
+fn test_help_version_and_print() {
+    let dir = project("usage", &[]);
+    let run = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_z-lang")).args(args).current_dir(&dir).output().unwrap();
+    let version = run(&["--version"]);
+    assert_eq!(String::from_utf8_lossy(&version.stdout), format!("z-lang {}\n", env!("CARGO_PKG_VERSION")));
+    assert!(String::from_utf8_lossy(&run(&["--help"]).stdout).contains("--bounds-check "));
+    let targets = run(&["--print", "target-list"]);
+    assert_eq!(String::from_utf8_lossy(&targets.stdout), format!("{}-{}\n", std::env::consts::ARCH, std::env::consts::OS));
+
+    let misspelled = run(&["main.z", "--bounds-checks"]);
+    assert_eq!(misspelled.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&misspelled.stderr), "error: unknown flag `--bounds-checks`; did you mean `--bounds-check`?\n");
+}
+
+#[test]
+fn test_exit_status_contract() {
+    let dir = project("exit_status", &[("main.z", "int main() {\n  return 0;\n}\nclass P { int x; }\nint f(P p) { return p.y + p.z; }\n")]);
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).arg("main.z").current_dir(&dir).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.ends_with("\n2 errors, 1 warning emitted\n"), "{}", stderr);
+
+    // `false` stands in for a C compiler that fails
+    fs::write(dir.join("main.z"), "int main() { return 0; }").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).arg("main.z").env("CC", "false").current_dir(&dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(101), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A missing main.z is the user's error, not the compiler's
+    fs::remove_file(dir.join("main.z")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_z-lang")).arg("main.z").current_dir(&dir).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.starts_with("error: cannot read main.z: "), "{}", stderr);
+}
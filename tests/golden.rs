@@ -0,0 +1,161 @@
+// tests/golden.rs
+//
+// Snapshot tests for codegen. Every `tests/cases/<name>.z` is compiled and
+// the C it produces is compared with `<name>.c`. When `<name>.out` exists
+// the C is also built with gcc and run, and its stdout compared with that
+// file; without gcc the run is skipped.
+//
+//   cargo test --test golden                  check every case
+//   cargo test --test golden -- rc            only cases whose name contains `rc`
+//   cargo test --test golden -- --bless       rewrite the snapshots that differ
+//
+// A case can start with `// flags: --bounds-check --overflow-checks` to
+// compile with those options. To start checking a case's output, create an
+// empty `<name>.out` and bless it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use z_lang::{Compiler, Options, Session};
+
+mod common;
+
+const CASES: &str = "tests/cases";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|a| a == "--bless");
+    // libtest flags such as `--quiet` are passed here too
+    let filters: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(CASES)
+        .expect("tests/cases is missing")
+        .map(|entry| entry.expect("unreadable entry in tests/cases").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "z"))
+        .filter(|path| filters.is_empty() || filters.iter().any(|f| name(path).contains(f.as_str())))
+        .collect();
+    cases.sort();
+
+    let gcc = common::has_gcc();
+    let mut failed = Vec::new();
+    println!("\nrunning {} golden cases", cases.len());
+    for case in &cases {
+        match run_case(case, bless, gcc) {
+            Ok(note) => println!("case {} ... ok{}", name(case), note),
+            Err(message) => {
+                println!("case {} ... FAILED", name(case));
+                failed.push((name(case), message));
+            }
+        }
+    }
+
+    for (case, message) in &failed {
+        println!("\n---- {} ----\n{}", case, message);
+    }
+    let result = if failed.is_empty() { "ok" } else { "FAILED" };
+    println!("\ngolden result: {}. {} passed; {} failed\n", result, cases.len() - failed.len(), failed.len());
+    if !failed.is_empty() {
+        if !bless {
+            println!("run `cargo test --test golden -- --bless` to accept the new output");
+        }
+        std::process::exit(1);
+    }
+}
+
+fn name(case: &Path) -> String {
+    case.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+// Ok with a note for the summary line, or why the case failed
+fn run_case(case: &Path, bless: bool, gcc: bool) -> Result<String, String> {
+    let source = fs::read_to_string(case).map_err(|e| format!("can't read {}: {}", case.display(), e))?;
+    let options = parse_flags(&source)?;
+    let mut session = Session::default();
+    let path = case.to_string_lossy().replace('\\', "/");
+    let c_code = Compiler::builder().options(options).build().compile_in(&mut session, &path, &source);
+    if session.has_errors() {
+        let diagnostics: Vec<String> = session.diagnostics.iter().map(|d| d.to_string()).collect();
+        return Err(diagnostics.join("\n"));
+    }
+
+    let mut notes = Vec::new();
+    check_snapshot(&case.with_extension("c"), &c_code, bless, &mut notes)?;
+
+    let expected_out = case.with_extension("out");
+    if expected_out.exists() {
+        if !gcc {
+            return Ok(" (run skipped: gcc not found)".to_string());
+        }
+        let stdout = build_and_run(&name(case), &c_code)?;
+        check_snapshot(&expected_out, &stdout, bless, &mut notes)?;
+    }
+    Ok(notes.iter().map(|n| format!(" ({})", n)).collect())
+}
+
+fn parse_flags(source: &str) -> Result<Options, String> {
+    let mut options = Options::default();
+    let Some(flags) = source.lines().next().and_then(|line| line.strip_prefix("// flags:")) else {
+        return Ok(options);
+    };
+    for flag in flags.split_whitespace() {
+        match flag {
+            "--bounds-check" => options.bounds_check = true,
+            "--overflow-checks" => options.overflow_checks = true,
+            "--union-checks" => options.union_checks = true,
+            other => return Err(format!("unknown flag `{}` in `// flags:`", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn check_snapshot(snapshot: &Path, actual: &str, bless: bool, notes: &mut Vec<String>) -> Result<(), String> {
+    let expected = fs::read_to_string(snapshot).ok();
+    if expected.as_deref() == Some(actual) {
+        return Ok(());
+    }
+    if bless {
+        fs::write(snapshot, actual).map_err(|e| format!("can't write {}: {}", snapshot.display(), e))?;
+        notes.push(format!("blessed {}", snapshot.display()));
+        return Ok(());
+    }
+    match expected {
+        Some(expected) => Err(format!("{} differs:\n{}", snapshot.display(), diff(&expected, actual))),
+        None => Err(format!("{} is missing", snapshot.display())),
+    }
+}
+
+// The differing lines, `-` for the snapshot and `+` for the new output
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(i), actual.get(i));
+        if old == new {
+            continue;
+        }
+        if let Some(old) = old {
+            out.push_str(&format!("{:>4} - {}\n", i + 1, old));
+        }
+        if let Some(new) = new {
+            out.push_str(&format!("{:>4} + {}\n", i + 1, new));
+        }
+    }
+    out
+}
+
+fn build_and_run(case: &str, c_code: &str) -> Result<String, String> {
+    let dir = std::env::temp_dir().join("tarnish_golden");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let source = dir.join(format!("{}.c", case));
+    let binary = dir.join(case);
+    fs::write(&source, c_code).map_err(|e| e.to_string())?;
+
+    let gcc = Command::new("gcc").arg(&source).arg("-o").arg(&binary).arg("-lm").output().map_err(|e| e.to_string())?;
+    if !gcc.status.success() {
+        return Err(format!("gcc failed:\n{}", String::from_utf8_lossy(&gcc.stderr)));
+    }
+    let run = Command::new(&binary).output().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&run.stdout).into_owned())
+}
@@ -0,0 +1,73 @@
+// tests/lexer.rs
+//
+// Properties of the lexer over generated input: it never panics, the spans
+// of its tokens follow each other and cover everything but whitespace, and
+// printing the tokens back gives source that lexes to the same kinds of
+// tokens. Arbitrary strings test the first two; the third takes strings of
+// the characters Z is written with, which reach far more of the lexer and
+// the printer than random Unicode does. fuzz/ has a fuzz target for the
+// same properties.
+
+use proptest::prelude::*;
+use z_lang::syntax::{detokenize, tokenize, tokenize_spanned, Token};
+
+// Names, numbers, operators, quotes, comments and line breaks
+const Z_LIKE: &str = r#"[a-zA-Z0-9_ \n\t"'/*+\-<>=!&|.:;,(){}\[\]#\\@%^~?]{0,64}"#;
+
+// The kind of `token`, its text aside
+fn kind(token: &Token) -> &'static str {
+    match token {
+        Token::Identifier(_) => "identifier",
+        Token::Number(_) => "number",
+        Token::StringLit(_) => "string",
+        Token::CharLit(_) => "char",
+        Token::Symbol(_) => "symbol",
+        Token::Comment(_) => "comment",
+        Token::Newline => "newline",
+        Token::Eof => "eof",
+    }
+}
+
+// The kinds of the tokens of `source`; printing adds and drops line breaks
+fn kinds(source: &str) -> Vec<&'static str> {
+    tokenize(source).iter().filter(|t| !matches!(t, Token::Newline)).map(kind).collect()
+}
+
+fn check_spans(input: &str) -> Result<(), TestCaseError> {
+    let spanned = tokenize_spanned(input);
+    let mut at = 0;
+    for (token, span) in &spanned {
+        prop_assert!(span.start >= at && span.start <= span.end && span.end <= input.len(), "{:?} at {:?}", token, span);
+        prop_assert!(input[at..span.start].chars().all(|c| c.is_whitespace() && c != '\n'), "{:?} skipped before {:?}", &input[at..span.start], token);
+        prop_assert!(!matches!(token, Token::Eof) || span.start == input.len());
+        at = span.end;
+    }
+    prop_assert_eq!(at, input.len());
+    prop_assert!(matches!(spanned.last(), Some((Token::Eof, _))));
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn lexing_never_panics(input in any::<String>()) {
+        tokenize(&input);
+    }
+
+    #[test]
+    fn spans_follow_each_other_and_cover_the_input(input in any::<String>()) {
+        check_spans(&input)?;
+    }
+
+    #[test]
+    fn spans_cover_z_like_input(input in Z_LIKE) {
+        check_spans(&input)?;
+    }
+
+    #[test]
+    fn printed_tokens_lex_to_the_same_kinds(input in Z_LIKE) {
+        let printed = detokenize(&tokenize(&input));
+        prop_assert_eq!(kinds(&printed), kinds(&input), "printed as {:?}", printed);
+    }
+}